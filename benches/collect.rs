@@ -0,0 +1,21 @@
+//! Benchmarks collecting and serializing a single record from each of the
+//! synthetic cgroup fixtures in `tests/fixtures`
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use radvisor::collection::Bench;
+use std::path::Path;
+
+fn collect(c: &mut Criterion) {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for fixture in Bench::fixtures(&fixtures) {
+        let name = fixture
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut bench = Bench::new(&fixture).expect("could not initialize the fixture collector");
+        c.bench_function(&format!("collect {}", name), |b| b.iter(|| bench.sample()));
+    }
+}
+
+criterion_group!(benches, collect);
+criterion_main!(benches);
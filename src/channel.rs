@@ -0,0 +1,221 @@
+//! Contains a bounded channel used to send collection events from the polling
+//! thread to the collection thread. Unlike `std::sync::mpsc::channel`, the
+//! number of queued events is capped, and redundant events for the same target
+//! are coalesced before they take up space in the queue. Stop events are never
+//! dropped, since the provider already considers their targets stopped; start
+//! events that don't fit are handed back to be sent again on the next poll.
+
+use crate::shared::{CollectionEvent, Id};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Error that can occur when sending an event to the collection thread
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum SendError {
+    #[error("the receiving end of the event channel has been closed")]
+    Disconnected,
+    /// The queue was full, so a start event was deferred: either the one
+    /// being sent, or the oldest queued one (which made room for a stop
+    /// event). It has to be sent again later, or its target is never
+    /// collected.
+    #[error("the event queue is full (capacity {capacity}); a start event was deferred")]
+    Overflow {
+        capacity: usize,
+        deferred: Box<CollectionEvent>,
+    },
+}
+
+/// Snapshot of the counters kept by an event channel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QueueStats {
+    /// Number of events accepted into the queue
+    pub sent:      u64,
+    /// Number of events that were eliminated by coalescing them with an
+    /// already-queued event for the same target
+    pub coalesced: u64,
+    /// Number of start events that were deferred because the queue was full
+    pub deferred:  u64,
+    /// Number of events currently waiting in the queue
+    pub depth:     usize,
+}
+
+/// State shared between the sending and receiving halves of the channel
+struct Shared {
+    queue:     Mutex<VecDeque<CollectionEvent>>,
+    capacity:  usize,
+    closed:    AtomicBool,
+    sent:      AtomicU64,
+    coalesced: AtomicU64,
+    deferred:  AtomicU64,
+}
+
+/// Sending half of the event channel, owned by the polling thread
+pub struct EventSender {
+    shared: Arc<Shared>,
+}
+
+/// Receiving half of the event channel, owned by the collection thread
+pub struct EventReceiver {
+    shared: Arc<Shared>,
+}
+
+/// Creates a new bounded event channel that can hold at most `capacity`
+/// events at once
+#[must_use]
+pub fn bounded(capacity: usize) -> (EventSender, EventReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        closed: AtomicBool::new(false),
+        sent: AtomicU64::new(0),
+        coalesced: AtomicU64::new(0),
+        deferred: AtomicU64::new(0),
+    });
+
+    (
+        EventSender {
+            shared: Arc::clone(&shared),
+        },
+        EventReceiver { shared },
+    )
+}
+
+/// Gets the id of the target an event refers to
+const fn event_id(event: &CollectionEvent) -> &Id {
+    match event {
+        CollectionEvent::Stop(id) => id,
+        CollectionEvent::Start { target, .. } => &target.id,
+    }
+}
+
+/// Result of attempting to merge an incoming event with the queued events
+// Only ever returned by value to be immediately matched, so boxing the event
+// would just add an allocation
+#[allow(clippy::large_enum_variant)]
+enum Coalesce {
+    /// The event was absorbed; the inner value is the number of events
+    /// eliminated in the process
+    Absorbed(u64),
+    /// The event could not be merged, and needs to be appended to the queue
+    Append(CollectionEvent),
+}
+
+/// Attempts to merge the given event with the most recent queued event for the
+/// same target:
+///   - a Stop for a target whose Start is still queued cancels both out
+///   - a Start for a target whose Start is still queued replaces the old one
+///   - a Stop for a target whose Stop is still queued is redundant
+///
+/// A Start following a queued Stop has to be kept, since ordering matters.
+fn coalesce(queue: &mut VecDeque<CollectionEvent>, event: CollectionEvent) -> Coalesce {
+    let position = queue
+        .iter()
+        .rposition(|queued| event_id(queued) == event_id(&event));
+    match position {
+        None => Coalesce::Append(event),
+        Some(position) => match (&queue[position], event) {
+            (CollectionEvent::Start { .. }, CollectionEvent::Stop(_)) => {
+                queue.remove(position);
+                Coalesce::Absorbed(2)
+            },
+            (CollectionEvent::Start { .. }, start @ CollectionEvent::Start { .. }) => {
+                queue[position] = start;
+                Coalesce::Absorbed(1)
+            },
+            (CollectionEvent::Stop(_), CollectionEvent::Stop(_)) => Coalesce::Absorbed(1),
+            (CollectionEvent::Stop(_), start @ CollectionEvent::Start { .. }) => {
+                Coalesce::Append(start)
+            },
+        },
+    }
+}
+
+impl Shared {
+    fn stats(&self) -> QueueStats {
+        let depth = self.queue.lock().unwrap().len();
+        QueueStats {
+            sent: self.sent.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            deferred: self.deferred.load(Ordering::Relaxed),
+            depth,
+        }
+    }
+}
+
+impl EventSender {
+    /// Attempts to enqueue an event, coalescing it with any queued event for
+    /// the same target. If the queue is still full afterwards, a start event
+    /// is deferred (and counted) and returned in `SendError::Overflow`: for
+    /// a stop event, the oldest queued start event is removed to make room,
+    /// and if there is none, the stop event is queued beyond the capacity
+    /// (which is bounded by the number of started targets).
+    pub fn send(&self, event: CollectionEvent) -> Result<(), SendError> {
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return Err(SendError::Disconnected);
+        }
+
+        let mut queue = self.shared.queue.lock().unwrap();
+        match coalesce(&mut queue, event) {
+            Coalesce::Absorbed(count) => {
+                self.shared.coalesced.fetch_add(count, Ordering::Relaxed);
+                Ok(())
+            },
+            Coalesce::Append(event) => {
+                if queue.len() < self.shared.capacity {
+                    queue.push_back(event);
+                    self.shared.sent.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+
+                let deferred = match event {
+                    start @ CollectionEvent::Start { .. } => start,
+                    stop @ CollectionEvent::Stop(_) => {
+                        let oldest_start = queue
+                            .iter()
+                            .position(|queued| matches!(queued, CollectionEvent::Start { .. }))
+                            .and_then(|position| queue.remove(position));
+                        queue.push_back(stop);
+                        self.shared.sent.fetch_add(1, Ordering::Relaxed);
+                        match oldest_start {
+                            Some(start) => start,
+                            None => return Ok(()),
+                        }
+                    },
+                };
+
+                self.shared.deferred.fetch_add(1, Ordering::Relaxed);
+                Err(SendError::Overflow {
+                    capacity: self.shared.capacity,
+                    deferred: Box::new(deferred),
+                })
+            },
+        }
+    }
+
+    /// Gets a snapshot of the current channel counters
+    #[must_use]
+    pub fn stats(&self) -> QueueStats { self.shared.stats() }
+
+    /// Gets the maximum number of events that can be queued at once
+    #[must_use]
+    pub fn capacity(&self) -> usize { self.shared.capacity }
+}
+
+impl EventReceiver {
+    /// Removes all currently queued events, returning an iterator over them
+    /// in the order they were sent. Never blocks on the sender.
+    #[must_use]
+    pub fn try_iter(&self) -> std::vec::IntoIter<CollectionEvent> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.drain(..).collect::<Vec<_>>().into_iter()
+    }
+
+    /// Gets a snapshot of the current channel counters
+    #[must_use]
+    pub fn stats(&self) -> QueueStats { self.shared.stats() }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) { self.shared.closed.store(true, Ordering::SeqCst); }
+}
@@ -1,11 +1,21 @@
+use crate::collection::{BfqColumns, DiskUsagePolicy, EventLogTarget, LifecycleFormat,
+                        ServiceTimeColumns, ShipTarget, SinkType, SnapshotFormat, Subsystem,
+                        SyncPolicy, TargetLimitPolicy, TriggerRule};
+use crate::environment;
+use crate::export::ExportFormat;
 use crate::polling::providers::ProviderType;
+use crate::util::CpuList;
 use byte_unit::{Byte, ByteError};
-use clap::{Clap, ValueHint};
+use clap::{AppSettings, Clap, IntoApp, ValueHint};
+use std::convert::TryFrom;
 use std::error;
 use std::fmt;
+use std::iter;
+use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, UNIX_EPOCH};
 
 type ShellOptions = crate::shell::Options;
 
@@ -18,12 +28,16 @@ lazy_static::lazy_static! {
         .map(|s| s.split(':').collect::<Vec<&str>>().join(", "));
 }
 
-/// Parses and resolves defaults for all CLI arguments. Additionally, handles
-/// displaying help/version text if specified.
+/// Parses and resolves defaults for all CLI arguments, including options set
+/// using `RADVISOR_*` environment variables (see `crate::environment`).
+/// Additionally, handles displaying help/version text if specified.
 #[allow(clippy::must_use_candidate)]
 pub fn load() -> Opts {
-    // Parse command line arguments (let clap fold in defaults)
-    Opts::parse()
+    // Add options that are set using environment variables to the command
+    // line, then parse it (let clap fold in defaults)
+    let args =
+        environment::apply(std::env::args_os(), &Opts::into_app()).unwrap_or_else(|err| err.exit());
+    Opts::parse_from(args)
 }
 
 /// Auto-parsed CLI options for rAdvisor, generated via clap
@@ -44,6 +58,7 @@ pub struct Opts {
 }
 
 #[derive(Clap, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
     #[clap(
         version = VERSION.unwrap_or("unknown"),
@@ -51,6 +66,25 @@ pub enum Command {
         about = "Runs a collection thread that writes resource statistics to output CSV files"
     )]
     Run(RunCommand),
+    #[clap(
+        version = VERSION.unwrap_or("unknown"),
+        author = AUTHORS.as_deref().unwrap_or("contributors"),
+        about = "Converts, filters, and merges log files and ring files"
+    )]
+    Export(ExportCommand),
+    #[clap(
+        version = VERSION.unwrap_or("unknown"),
+        author = AUTHORS.as_deref().unwrap_or("contributors"),
+        about = "Collects a single record for each current target and prints them to stdout"
+    )]
+    Snapshot(SnapshotCommand),
+    #[clap(
+        version = VERSION.unwrap_or("unknown"),
+        author = AUTHORS.as_deref().unwrap_or("contributors"),
+        about = "Measures the latency and allocations of collecting records from cgroup directories",
+        setting = AppSettings::Hidden
+    )]
+    Bench(BenchCommand),
 }
 
 #[derive(Clap, Clone)]
@@ -60,7 +94,138 @@ pub struct RunCommand {
     pub provider: ProviderType,
 }
 
+#[derive(Clap, Clone)]
+pub struct SnapshotCommand {
+    /// Format to print the snapshot in: `table` for a table of the columns and
+    /// values of each target, or `json` for a JSON object with an array of
+    /// targets, each including its record keyed by column name
+    #[clap(
+        long = "format",
+        default_value = "table",
+        value_hint = ValueHint::Other
+    )]
+    pub format: SnapshotFormat,
+
+    #[clap(subcommand)]
+    /// Provider to use to get the current targets (such as containers/pods)
+    pub provider: ProviderType,
+}
+
+#[derive(Clap, Clone)]
+pub struct BenchCommand {
+    /// (optional) cgroup directories to collect records from, each of which
+    /// is collected as a cgroup v2 directory if it contains
+    /// `cgroup.controllers`. Defaults to the synthetic fixtures (see
+    /// `--fixtures`)
+    #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+    pub cgroups: Vec<PathBuf>,
+
+    /// Directory containing the synthetic cgroup fixtures, which are collected
+    /// if no cgroup directories are given
+    #[clap(
+        parse(from_os_str),
+        long = "fixtures",
+        default_value = "tests/fixtures",
+        value_hint = ValueHint::DirPath
+    )]
+    pub fixtures: PathBuf,
+
+    /// Number of samples that are measured for each cgroup directory
+    #[clap(long = "samples", default_value = "10000", value_hint = ValueHint::Other)]
+    pub samples: u64,
+
+    /// Number of samples taken before measuring, which aren't included in the
+    /// results (so that allocations made while warming up aren't counted)
+    #[clap(long = "warmup", default_value = "100", value_hint = ValueHint::Other)]
+    pub warmup: u64,
+
+    /// Collection interval used to report how many targets could be
+    /// collected within each interval
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "interval",
+        default_value = "50ms",
+        value_hint = ValueHint::Other
+    )]
+    pub interval: Duration,
+
+    /// Exit with a non-zero status code if any measured sample made a heap
+    /// allocation (which requires building with the `bench` feature, since
+    /// allocations aren't counted otherwise)
+    #[clap(long = "audit")]
+    pub audit: bool,
+}
+
+#[derive(Clap, Clone)]
+pub struct ExportCommand {
+    /// Log files or ring files to export. Each one is converted to a file
+    /// with the same name, ending in the extension of the format (`.log` for
+    /// `csv`, `.json` for `json`)
+    #[clap(parse(from_os_str), required = true, value_hint = ValueHint::FilePath)]
+    pub files: Vec<PathBuf>,
+
+    /// (optional) Directory to write exported files to, instead of next to
+    /// each original file
+    #[clap(
+        parse(from_os_str),
+        short = 'o',
+        long = "output",
+        conflicts_with = "merge",
+        value_hint = ValueHint::DirPath
+    )]
+    pub output: Option<PathBuf>,
+
+    /// Format to export to: `csv` for log files (or a plain CSV table when
+    /// merging), or `json` for a JSON object with the log file headers and an
+    /// array of records keyed by column name
+    #[clap(
+        long = "format",
+        default_value = "csv",
+        value_hint = ValueHint::Other
+    )]
+    pub format: ExportFormat,
+
+    /// (optional) Comma-separated list of the columns to export, in order.
+    /// Defaults to all columns
+    #[clap(
+        long = "columns",
+        use_delimiter = true,
+        value_hint = ValueHint::Other
+    )]
+    pub columns: Vec<String>,
+
+    /// (optional) Only exports records read at or after the given time, as a
+    /// nanosecond timestamp or an RFC 3339 date (such as
+    /// `2021-06-01T12:00:00Z`)
+    #[clap(
+        parse(try_from_str = parse_timestamp),
+        long = "since",
+        value_hint = ValueHint::Other
+    )]
+    pub since: Option<u128>,
+
+    /// (optional) Only exports records read before the given time, as a
+    /// nanosecond timestamp or an RFC 3339 date
+    #[clap(
+        parse(try_from_str = parse_timestamp),
+        long = "until",
+        value_hint = ValueHint::Other
+    )]
+    pub until: Option<u128>,
+
+    /// (optional) Merges all files into a single table at the given path,
+    /// keyed by the id of each record's target (in the `target.id` column)
+    /// and ordered by time
+    #[clap(
+        parse(from_os_str),
+        long = "merge",
+        value_hint = ValueHint::FilePath
+    )]
+    pub merge: Option<PathBuf>,
+}
+
 #[derive(Clap, Clone, Debug, PartialEq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct CollectionOptions {
     /// Collection interval between log entries
     #[clap(
@@ -74,6 +239,13 @@ pub struct CollectionOptions {
     )]
     pub interval: Duration,
 
+    /// Align collection ticks to multiples of the collection interval on the
+    /// wall clock (such as every 100ms on the 100ms mark), so that the records
+    /// of rAdvisor instances on different machines (with synchronized clocks)
+    /// line up
+    #[clap(long = "align", global = true)]
+    pub align: bool,
+
     /// Target directory to place log files in ({id}_{timestamp}.log)
     #[clap(
         parse(from_os_str),
@@ -95,6 +267,51 @@ pub struct CollectionOptions {
     )]
     pub flush_log: Option<PathBuf>,
 
+    /// Maximum interval between syncs of the flush log to disk. Flush events
+    /// are appended to the flush log as they occur, so at most this much
+    /// history is lost if rAdvisor crashes
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "flush-log-interval",
+        default_value = "1s",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub flush_log_interval: Duration,
+
+    /// (optional) Streams auxiliary events of the given kind (`flush`,
+    /// `lifecycle`, `error`, or `trigger`) to a CSV event log as they occur, in
+    /// the format `<kind>=<path>[,max-size=<size>][,keep=<count>]`. Each
+    /// event log is rotated independently once it reaches its max size,
+    /// keeping the given number of old event logs (1 by default)
+    #[clap(
+        long = "event-log",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub event_logs: Vec<EventLogTarget>,
+
+    /// (optional) Records the start and stop of every target (with its
+    /// provider, id, name, cgroup, and log file) in `events.log` in the log
+    /// directory, as `csv` or `jsonl`. Appends to the file if it already
+    /// exists
+    #[clap(
+        long = "lifecycle-log",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub lifecycle_log: Option<LifecycleFormat>,
+
+    /// (optional) Records how long it took for each target to be sampled once
+    /// it started (from when it was polled, to when its collector was
+    /// initialized, to when its first sample was collected) in
+    /// `start_latency.csv` in the log directory. Appends to the file if it
+    /// already exists
+    #[clap(long = "start-latency-log", global = true)]
+    pub start_latency_log: bool,
+
     /// Size (in bytes) of the heap-allocated buffer to use to write collection
     /// records in
     #[clap(
@@ -106,6 +323,514 @@ pub struct CollectionOptions {
         value_hint = ValueHint::Other
     )]
     pub buffer_size: Byte,
+
+    /// Whether to size the buffer of each log file based on the rate at which
+    /// records are written to it: buffers start small and are periodically
+    /// resized to hold about 30 seconds of records, up to `--buffer-size`.
+    /// Reduces the memory used on nodes with many mostly-idle targets
+    #[clap(long = "adaptive-buffer", global = true)]
+    pub adaptive_buffer: bool,
+
+    /// Destination of log files: `file` to write them to disk, `null` to
+    /// discard (but count) all bytes, measuring collection overhead without
+    /// any disk I/O, or `ring` to write fixed-size binary records to a
+    /// memory-mapped ring file per target (convert them to log files with
+    /// `radvisor export`), or `stream` (or `-`) to write the records of all
+    /// targets to stdout, prefixed with `target_id` and `provider` columns
+    #[clap(
+        long = "sink",
+        default_value = "file",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub sink: SinkType,
+
+    /// Number of records that each ring file holds when using the `ring`
+    /// sink. Once a ring file is full, the oldest records are overwritten
+    #[clap(
+        long = "ring-capacity",
+        default_value = "65536",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub ring_capacity: u64,
+
+    /// (optional) Maximum rate at which log files are written to disk (such
+    /// as `5MB/s`), to limit the disturbance of I/O-sensitive workloads.
+    /// Flushes that exceed the limit are delayed (without delaying
+    /// collection) and written out as soon as the limit allows
+    #[clap(
+        parse(try_from_str = parse_rate),
+        long = "io-rate-limit",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub io_rate_limit: Option<u64>,
+
+    /// When log files are synced to durable storage: `never` (leaving it up
+    /// to the kernel), `interval:<duration>` to sync with the first buffer
+    /// flush after the duration has passed since the previous sync, or
+    /// `every-flush`. Log files are also synced when they are closed (unless
+    /// `never`). Syncs are recorded in the flush log
+    #[clap(
+        long = "sync",
+        default_value = "never",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub sync: SyncPolicy,
+
+    /// Whether to include a CRC-32 checksum of each log file's body in the
+    /// footer written when the log file is closed cleanly, so that corrupted
+    /// log files can be detected when they are read
+    #[clap(long = "checksum", global = true)]
+    pub checksum: bool,
+
+    /// (optional) Size (in bytes) after which log files are rotated, closing
+    /// the current file and continuing collection in a new one
+    #[clap(
+        parse(try_from_str = parse_byte),
+        long = "rotate-size",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub rotate_size: Option<Byte>,
+
+    /// (optional) Duration after which log files are rotated, closing the
+    /// current file and continuing collection in a new one
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "rotate-interval",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub rotate_interval: Option<Duration>,
+
+    /// (optional) Maximum total size of the log files that this run wrote to
+    /// the log directory and the spool directory, if given (such as `10GB`,
+    /// including rotated log files but not files from previous runs), which is
+    /// checked every second. See `--disk-usage-policy` for what happens once it
+    /// is exceeded
+    #[clap(
+        parse(try_from_str = parse_byte),
+        long = "max-disk-usage",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub max_disk_usage: Option<Byte>,
+
+    /// What happens once the log directory exceeds `--max-disk-usage`: `stop`
+    /// to stop collection with an error, or `evict` to delete the oldest log
+    /// files from this run that are no longer being written (such as rotated
+    /// log files), stopping only if that isn't enough
+    #[clap(
+        long = "disk-usage-policy",
+        default_value = "stop",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub disk_usage_policy: DiskUsagePolicy,
+
+    /// (optional) Maximum number of targets that are collected at once, which
+    /// keeps a node that suddenly runs thousands of short-lived targets from
+    /// exhausting rAdvisor's memory and file descriptors. See
+    /// `--max-targets-policy` for what happens once it is reached
+    #[clap(long = "max-targets", global = true, value_hint = ValueHint::Other)]
+    pub max_targets: Option<NonZeroUsize>,
+
+    /// What happens when a target starts while `--max-targets` targets are
+    /// already collected: `deny` to hold back the new target (with a
+    /// warning) until another target stops, or `evict` to stop collecting the
+    /// target whose records haven't changed for the longest time to make room
+    /// for it once the new target's collector is initialized. Evicted targets
+    /// aren't collected again
+    #[clap(
+        long = "max-targets-policy",
+        default_value = "deny",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub max_targets_policy: TargetLimitPolicy,
+
+    /// (optional) Destination that finished log files are uploaded to once
+    /// their targets stop, they are rotated, or rAdvisor stops: either
+    /// `file://<directory>` or `http://<host>[:<port>]/<path>` (using a PUT
+    /// request per file). Failed uploads are retried, and shipped files are
+    /// recorded in `shipped.csv` in the log directory
+    #[clap(long = "ship-to", global = true, value_hint = ValueHint::Url)]
+    pub ship_to: Option<ShipTarget>,
+
+    /// (optional) Directory on fast local storage (such as a tmpfs) to write
+    /// log files to instead of the log directory. Finished log files (once
+    /// their targets stop, they are rotated, or rAdvisor stops) are moved to
+    /// the log directory in the background, so that writing to a slow log
+    /// directory (such as one on a network file system) doesn't delay
+    /// collection. Log files that are still open if rAdvisor is killed are
+    /// left in the spool directory
+    #[clap(
+        parse(from_os_str),
+        long = "spool-dir",
+        conflicts_with = "ship-to",
+        global = true,
+        value_hint = ValueHint::DirPath
+    )]
+    pub spool_dir: Option<PathBuf>,
+
+    /// Only print the targets that would be collected (along with their
+    /// cgroups, collectors, and log files) as they are discovered, without
+    /// writing any files. Useful to check filters and permissions before a
+    /// real run
+    #[clap(long = "dry-run", global = true)]
+    pub dry_run: bool,
+
+    /// Number of times to retry initializing the collector of a target that
+    /// couldn't be initialized (such as when its cgroup doesn't exist yet),
+    /// waiting 1s before the first retry and twice as long before each
+    /// subsequent one. 0 disables retries
+    #[clap(
+        long = "init-retries",
+        default_value = "5",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub init_retries: u32,
+
+    /// Number of consecutive failed writes to a log file (such as when the
+    /// disk is full) after which its targets are quarantined: collection for
+    /// them pauses while recovering the log file is retried with backoff (up
+    /// to every 30s), re-creating it with its header if it was deleted. 0
+    /// disables quarantining
+    #[clap(
+        long = "quarantine-after",
+        default_value = "3",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub quarantine_after: u32,
+
+    /// Only write records when any counter changed (by more than the sparse
+    /// epsilon) since the last written record, plus periodic heartbeat records
+    #[clap(long = "sparse", global = true)]
+    pub sparse: bool,
+
+    /// Minimum absolute change in any counter for a record to be written in
+    /// sparse mode
+    #[clap(
+        long = "sparse-epsilon",
+        default_value = "0",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub sparse_epsilon: u64,
+
+    /// Maximum interval between written records in sparse mode
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "sparse-heartbeat",
+        default_value = "10s",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub sparse_heartbeat: Duration,
+
+    /// (optional) Interval at which to write a single record summarizing the
+    /// samples collected since the last one, instead of writing every sample.
+    /// Integer counters and gauges (but not limits, which can be `max`,
+    /// settings, or the status) are replaced by their minimum, mean, and
+    /// maximum.
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "aggregate",
+        conflicts_with = "sparse",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub aggregate: Option<Duration>,
+
+    /// Checks that every collected value parses as the type declared for its
+    /// column, counting invalid values by column and reporting them when
+    /// collection stops
+    #[clap(long = "validate", global = true)]
+    pub validate: bool,
+
+    /// (optional) Address to serve an HTTP endpoint on that reports uptime,
+    /// active targets, per-target sample counts, the last collection error,
+    /// and buffer flush statistics as JSON
+    #[clap(
+        long = "status-addr",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub status_addr: Option<SocketAddr>,
+
+    /// (optional) Identifier of the node that rAdvisor runs on, which is
+    /// included in the header of each log file (as `NodeId`) and reported by
+    /// the status endpoint, so that logs collected on many nodes (such as by a
+    /// daemon set) can be told apart. Defaults to the name of the node from
+    /// the provider (for the kubernetes provider), or the hostname
+    #[clap(long = "node-id", global = true, value_hint = ValueHint::Other)]
+    pub node_id: Option<String>,
+
+    /// Prefix the name of each log file with the node id (see --node-id)
+    #[clap(long = "node-id-in-filename", global = true)]
+    pub node_id_in_filename: bool,
+
+    /// Name the log file of each target after its name and the start of its
+    /// id (such as `web-3f2a9c1e0b7d`) instead of its full id, lengthening
+    /// the part of the id used whenever active targets would share a name.
+    /// Short names are reported by the status endpoint and recorded in the
+    /// lifecycle event log
+    #[clap(long = "short-names", global = true)]
+    pub short_names: bool,
+
+    /// (optional) Path of a Unix socket that accepts commands (one per line)
+    /// to pause or resume collection of all targets or of a single target
+    /// (`pause [<id>]`, `resume [<id>]`), flush buffers (`flush`), or change
+    /// the collection interval (`interval <duration>`) while collection runs
+    #[clap(long = "control-socket", global = true, value_hint = ValueHint::FilePath)]
+    pub control_socket: Option<PathBuf>,
+
+    /// (optional) Maximum CPU usage of rAdvisor itself (as a percentage of a
+    /// single CPU, such as `2%`). The collection interval is lengthened
+    /// whenever the usage exceeds the budget, and shortened back (down to the
+    /// collection interval) once there is enough headroom
+    #[clap(
+        parse(try_from_str = parse_percentage),
+        long = "overhead-budget",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub overhead_budget: Option<f64>,
+
+    /// (optional) CPUs to pin the polling and collection threads to, in the
+    /// kernel's list format (such as `0-1,4`), so that they don't compete with
+    /// the workloads being measured on other CPUs. Recorded in the header of
+    /// each log file (as `Scheduling`)
+    #[clap(
+        parse(try_from_str = parse_cpu_list),
+        long = "pin-cpus",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub pin_cpus: Option<CpuList>,
+
+    /// (optional) Nice value (from -20 to 19) to run the polling and
+    /// collection threads with. Values below 0 require `CAP_SYS_NICE`
+    #[clap(
+        parse(try_from_str = parse_nice),
+        long = "nice",
+        allow_hyphen_values = true,
+        conflicts_with = "rt-priority",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub nice: Option<i32>,
+
+    /// (optional) Runs the polling and collection threads under the real-time
+    /// `SCHED_FIFO` policy with the given priority (from 1 to 99), so that
+    /// collection isn't delayed by the workloads being measured. Requires
+    /// `CAP_SYS_NICE`
+    #[clap(
+        parse(try_from_str = parse_rt_priority),
+        long = "rt-priority",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub rt_priority: Option<i32>,
+
+    /// (optional) Rule that switches a target to burst sampling whenever it
+    /// matches the target's latest sample, in the format `<column>
+    /// increases`, `<column> <op> <value>`, or `<column> <op> <percent>% of
+    /// <column>` (where `<op>` is `>`, `>=`, `<`, or `<=`). Can be given
+    /// multiple times
+    #[clap(
+        long = "trigger",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        conflicts_with = "overhead-budget",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub triggers: Vec<TriggerRule>,
+
+    /// Collection interval of targets while they are burst sampled
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "burst-interval",
+        default_value = "50ms",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub burst_interval: Duration,
+
+    /// Duration that burst sampling continues for after a trigger rule last
+    /// matched
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "burst-duration",
+        default_value = "10s",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub burst_duration: Duration,
+
+    /// Whether to include the cgroup v1 `blkio.bfq.*` columns: `auto` to only
+    /// include them if any block device uses the bfq I/O scheduler, `always`,
+    /// or `never`
+    #[clap(
+        long = "blkio-bfq",
+        default_value = "auto",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub bfq_columns: BfqColumns,
+
+    /// Which cgroup v1 columns break down the `blkio.service.time.*` totals
+    /// across block devices, to show I/O latency skew between devices:
+    /// `total` for none, `devices` for the total service time of each block
+    /// device, or `histogram` for the number of devices whose mean service
+    /// time per I/O falls in each latency bucket
+    #[clap(
+        long = "blkio-service-time",
+        default_value = "total",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub service_time_columns: ServiceTimeColumns,
+
+    /// (optional) Comma-separated list of the subsystems to collect, out of
+    /// `pids`, `cpu`, `cpuset`, `memory`, `hugetlb`, and `blkio` (the `io`
+    /// controller on cgroup v2). Files of other subsystems aren't read, and
+    /// their columns are left out of log files. Defaults to all subsystems
+    #[clap(
+        long = "collect",
+        use_delimiter = true,
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub collect: Vec<Subsystem>,
+
+    /// (optional) Comma-separated list of the keys to read from the cgroup v2
+    /// `cpu.stat` file, each of which is written to a `cpu.stat/<key>`
+    /// column. Keys that the kernel doesn't provide are left empty, so
+    /// fields added by newer kernels can be collected as soon as they exist.
+    /// Defaults to `usage_usec`, `system_usec`, `user_usec`, `nr_periods`,
+    /// `nr_throttled`, `throttled_usec`, `nr_bursts`, and `burst_usec`
+    #[clap(
+        long = "cpu-stat-keys",
+        use_delimiter = true,
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub cpu_stat_keys: Vec<String>,
+
+    /// Additionally record the CPU time that each cgroup v2 target used on
+    /// each CPU in a `cpu.usage.percpu` column (as cgroup v1 does), which
+    /// cgroup v2 doesn't report itself. The time is measured by a software
+    /// perf counter opened for the cgroup on each CPU in its cpuset, so it
+    /// counts from when collection of the target started, and needs access
+    /// to perf events (see `perf_event_paranoid`)
+    #[clap(long = "percpu", global = true)]
+    pub percpu: bool,
+
+    /// Skip writing the records of cgroup v1 targets whose files were all
+    /// empty or couldn't be read (as the cgroup v2 collector always does),
+    /// such as while their cgroups are being removed, instead of writing rows
+    /// of empty fields at the end of their log files
+    #[clap(long = "skip-empty-rows", global = true)]
+    pub skip_empty_rows: bool,
+
+    /// Additionally record the time since boot (including time spent
+    /// suspended) in a `read.boottime` column, and detect system suspends,
+    /// recording their duration in the `suspended` column of the first record
+    /// after each resume
+    #[clap(long = "boottime", global = true)]
+    pub boottime: bool,
+
+    /// Additionally record a timestamp from `CLOCK_MONOTONIC_RAW` in a
+    /// `read.monotonic` column, which (unlike the `read` column) isn't
+    /// affected by NTP adjustments or other wall-clock jumps, so it can be used
+    /// to compute the true interval between records
+    #[clap(long = "monotonic", global = true)]
+    pub monotonic: bool,
+
+    /// Additionally record the dispatch latency of each record (the time
+    /// between when its collection tick was scheduled and when collection
+    /// started) in a `latency` column
+    #[clap(long = "latency", global = true)]
+    pub latency: bool,
+
+    /// Additionally record the number of open file descriptors and sockets,
+    /// summed over the processes of each target, in `fds.open` and
+    /// `fds.sockets` columns. Since this is more expensive than reading
+    /// cgroup files, they are only counted every few collection ticks (see
+    /// --fds-divisor), with the columns left empty in between
+    #[clap(long = "fds", global = true)]
+    pub fds: bool,
+
+    /// Number of collection ticks between counts of open file descriptors
+    /// when using --fds
+    #[clap(
+        long = "fds-divisor",
+        default_value = "10",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub fds_divisor: NonZeroU32,
+
+    /// Additionally record the scheduler run-queue delay of each target, in
+    /// `sched.wait_time` (the nanoseconds that its threads spent runnable but
+    /// waiting for a CPU) and `sched.switches` (the number of times its
+    /// threads were switched onto a CPU) columns. Requires a kernel that
+    /// exposes per-thread scheduler statistics (`CONFIG_SCHED_INFO`)
+    #[clap(long = "sched", global = true)]
+    pub sched: bool,
+
+    /// Additionally record the number of TCP connections, listening TCP
+    /// sockets, and UDP sockets in the network namespace of each target's
+    /// init process, in `net.tcp.active`, `net.tcp.listen`, and
+    /// `net.udp.sockets` columns. Targets that share the host's network
+    /// namespace report the host's sockets. Since this is more expensive than
+    /// reading cgroup files, they are only counted every few collection ticks
+    /// (see --net-sockets-divisor), with the columns left empty in between
+    #[clap(long = "net-sockets", global = true)]
+    pub net_sockets: bool,
+
+    /// Number of collection ticks between counts of sockets when using
+    /// --net-sockets
+    #[clap(
+        long = "net-sockets-divisor",
+        default_value = "10",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub net_sockets_divisor: NonZeroU32,
+
+    /// Additionally record the utilization and memory of the GPUs assigned
+    /// to each target in `gpu.*` columns, using NVML. Requires rAdvisor to be
+    /// built with the `nvml` feature and the NVIDIA driver to be installed
+    #[clap(long = "gpu", global = true)]
+    pub gpu: bool,
+
+    /// Additionally record the number of instructions, cycles, cache misses,
+    /// and branch misses of each target (counted by the CPU's hardware
+    /// counters, using `perf_event_open` with cgroup attribution) in `perf.*`
+    /// columns. Requires rAdvisor to be built with the `perf` feature, access
+    /// to perf events (`CAP_PERFMON` or a `perf_event_paranoid` of at most 0),
+    /// and the `perf_event` controller on cgroup v1
+    #[clap(long = "perf", global = true)]
+    pub perf: bool,
+
+    /// Additionally record metrics derived from each target's current and
+    /// previous records, which saves a post-processing pass for common
+    /// analyses: `derived.cpu.millicores` (the thousandths of a CPU used on
+    /// average since the previous record), `derived.cpu.throttled.permille`
+    /// (the thousandths of the CFS periods since the previous record that
+    /// the target was throttled in), and `derived.memory.permille` (the
+    /// memory usage in thousandths of the memory limit, left empty if there is
+    /// no limit)
+    #[clap(long = "derived-columns", global = true)]
+    pub derived_columns: bool,
 }
 
 #[derive(Clap, Clone, Debug, PartialEq)]
@@ -121,6 +846,56 @@ pub struct PollingOptions {
         value_hint = ValueHint::Other
     )]
     pub interval: Duration,
+
+    /// Maximum number of target start/stop events that can be queued for the
+    /// collection thread before the start events of new targets are deferred
+    /// to the next poll
+    #[clap(
+        name = "event-queue",
+        long = "event-queue",
+        default_value = "1024",
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub event_queue: usize,
+
+    /// Whether to collect the root cgroup (along with host-wide CPU and
+    /// memory statistics from /proc) as an additional target named "host",
+    /// giving a host-level baseline alongside the per-target logs
+    #[clap(long = "collect-host", global = true)]
+    pub collect_host: bool,
+
+    /// (optional) Directory that the cgroup hierarchy is mounted at, which
+    /// the cgroups of all targets are relative to (such as
+    /// `/host/sys/fs/cgroup` when running in a container with the host's
+    /// cgroupfs mounted into it). If not given, it is detected from the
+    /// mounted cgroup hierarchies, preferring the one that exposes the most
+    /// of the hierarchy (outside of any cgroup namespace that rAdvisor runs
+    /// in), and falling back to /sys/fs/cgroup
+    #[clap(
+        parse(from_os_str),
+        long = "cgroup-root",
+        global = true,
+        value_hint = ValueHint::DirPath
+    )]
+    pub cgroup_root: Option<PathBuf>,
+
+    /// (optional) Additional provider to collect targets from alongside the
+    /// provider of the subcommand, given as its subcommand and options (such
+    /// as `systemd --unit '*.service'`). The targets of all providers are
+    /// collected by the same collection thread, with the provider of each
+    /// target recorded in its log file header. Polling and collection options
+    /// are shared by all providers, so only the provider's own options can be
+    /// given. Can be given multiple times
+    #[clap(
+        parse(try_from_str = parse_provider),
+        long = "also",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        global = true,
+        value_hint = ValueHint::Other
+    )]
+    pub also: Vec<ProviderType>,
 }
 
 #[derive(Debug, Clone)]
@@ -144,8 +919,121 @@ impl error::Error for ParseFailure {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> { None }
 }
 
-fn parse_duration(raw: &str) -> Result<Duration, humantime::DurationError> {
+pub(crate) fn parse_duration(raw: &str) -> Result<Duration, humantime::DurationError> {
     humantime::Duration::from_str(raw).map(Into::into)
 }
 
+/// Parses a positive percentage (with or without a trailing `%`) into a
+/// fraction
+fn parse_percentage(raw: &str) -> Result<f64, ParseFailure> {
+    let failure = || ParseFailure::new(String::from("percentage"), raw.to_owned());
+    let percentage = f64::from_str(raw.trim().trim_end_matches('%')).map_err(|_| failure())?;
+    match percentage.is_finite() && percentage > 0.0 {
+        true => Ok(percentage / 100.0),
+        false => Err(failure()),
+    }
+}
+
+/// Parses an additional provider, given as its subcommand and options
+fn parse_provider(raw: &str) -> Result<ProviderType, String> {
+    let words = split_words(raw).ok_or_else(|| format!("unterminated quote in '{}'", raw))?;
+    let command = RunCommand::try_parse_from(iter::once(String::from("run")).chain(words))
+        .map_err(|err| err.to_string())?;
+    match command.provider.polling().also.is_empty() {
+        true => Ok(command.provider),
+        false => Err(String::from(
+            "additional providers can't have additional providers of their own",
+        )),
+    }
+}
+
+/// Splits a command line into words like a shell would (without expanding
+/// anything), respecting single quotes, double quotes, and backslash escapes.
+/// Returns None if a quote isn't terminated.
+fn split_words(raw: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => current.push(c),
+                    }
+                }
+            },
+            '"' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => current.push(chars.next()?),
+                        c => current.push(c),
+                    }
+                }
+            },
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Parses a CPU list in the kernel's list format, such as `0-3,8`
+fn parse_cpu_list(raw: &str) -> Result<CpuList, ParseFailure> {
+    CpuList::parse(raw).ok_or_else(|| ParseFailure::new(String::from("CPU list"), raw.to_owned()))
+}
+
+/// Parses a nice value, from -20 to 19
+fn parse_nice(raw: &str) -> Result<i32, ParseFailure> {
+    match raw.trim().parse::<i32>() {
+        Ok(nice) if (-20..=19).contains(&nice) => Ok(nice),
+        _ => Err(ParseFailure::new(
+            String::from("nice value"),
+            raw.to_owned(),
+        )),
+    }
+}
+
+/// Parses a real-time scheduling priority, from 1 to 99
+fn parse_rt_priority(raw: &str) -> Result<i32, ParseFailure> {
+    match raw.trim().parse::<i32>() {
+        Ok(priority) if (1..=99).contains(&priority) => Ok(priority),
+        _ => Err(ParseFailure::new(
+            String::from("real-time priority"),
+            raw.to_owned(),
+        )),
+    }
+}
+
+/// Parses a nanosecond timestamp, given either as an integer or as an RFC 3339
+/// date
+fn parse_timestamp(raw: &str) -> Result<u128, ParseFailure> {
+    let failure = || ParseFailure::new(String::from("timestamp"), raw.to_owned());
+    match raw.parse::<u128>() {
+        Ok(timestamp) => Ok(timestamp),
+        Err(_) => humantime::parse_rfc3339_weak(raw)
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_nanos())
+            .ok_or_else(failure),
+    }
+}
+
 fn parse_byte(raw: &str) -> Result<Byte, ByteError> { Byte::from_str(raw) }
+
+/// Parses a positive byte rate (with or without a trailing `/s`), such as
+/// `5MB/s`, into bytes per second
+fn parse_rate(raw: &str) -> Result<u64, ParseFailure> {
+    let failure = || ParseFailure::new(String::from("rate"), raw.to_owned());
+    let bytes = Byte::from_str(raw.trim().trim_end_matches("/s")).map_err(|_| failure())?;
+    match u64::try_from(bytes.get_bytes()) {
+        Ok(rate) if rate > 0 => Ok(rate),
+        _ => Err(failure()),
+    }
+}
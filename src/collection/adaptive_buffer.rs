@@ -0,0 +1,80 @@
+//! Adapts the capacity of each log file's buffer to the rate at which records
+//! are written to it, so that nodes with many mostly-idle targets don't hold a
+//! full-size buffer for each one. Buffers start small, and are periodically
+//! resized to hold about 30 seconds of records (up to `--buffer-size`).
+
+use csv::ByteRecord;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// Smallest capacity that buffers are shrunk to
+const MIN_CAPACITY: usize = 64 * 1024;
+
+/// Minimum time between re-evaluations of a buffer's capacity
+const ADAPT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Span of records that each buffer is sized to hold, such that buffers are
+/// flushed about this often
+const BUFFERED_SPAN: Duration = Duration::from_secs(30);
+
+/// Tracks the rate at which records are written to a single log file, sizing
+/// its buffer accordingly
+pub struct AdaptiveBuffer {
+    max_capacity: usize,
+    capacity:     usize,
+    /// Number of bytes of records written since the last evaluation
+    produced:     u64,
+    evaluated_at: Instant,
+}
+
+impl AdaptiveBuffer {
+    /// Creates a tracker for a buffer that can grow up to the given capacity,
+    /// which starts out at the smallest capacity
+    #[must_use]
+    pub fn new(max_capacity: usize) -> Self {
+        Self {
+            max_capacity,
+            capacity: MIN_CAPACITY.min(max_capacity),
+            produced: 0,
+            evaluated_at: Instant::now(),
+        }
+    }
+
+    /// Gets the current capacity of the buffer
+    #[must_use]
+    pub const fn capacity(&self) -> usize { self.capacity }
+
+    /// Counts a record that was written to the buffer, including its
+    /// delimiters
+    pub fn record(&mut self, record: &ByteRecord) {
+        self.produced += (record.as_slice().len() + record.len()) as u64;
+    }
+
+    /// Re-evaluates the capacity of the buffer (at most once per adapt
+    /// interval) based on the rate at which records were written since the
+    /// last evaluation, rounded up to a power of two. Returns the new capacity
+    /// if it changed.
+    pub fn evaluate(&mut self) -> Option<usize> {
+        let elapsed = self.evaluated_at.elapsed();
+        if elapsed < ADAPT_INTERVAL {
+            return None;
+        }
+
+        let wanted = u128::from(self.produced) * BUFFERED_SPAN.as_nanos() / elapsed.as_nanos();
+        self.produced = 0;
+        self.evaluated_at = Instant::now();
+
+        let capacity = usize::try_from(wanted)
+            .ok()
+            .and_then(usize::checked_next_power_of_two)
+            .unwrap_or(usize::MAX)
+            .clamp(MIN_CAPACITY.min(self.max_capacity), self.max_capacity);
+        match capacity == self.capacity {
+            true => None,
+            false => {
+                self.capacity = capacity;
+                Some(capacity)
+            },
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use crate::formats::{Column, ColumnKind, ColumnType, Statistic, TableMetadata, Unit};
+use csv::ByteRecord;
+use std::time::{Duration, Instant};
+
+/// Name of the column appended to aggregated records with the number of
+/// samples that they summarize
+pub const SAMPLES_COLUMN: &str = "aggregate.samples";
+
+/// Conditions under which aggregated records are written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AggregatePolicy {
+    /// Interval at which the samples collected since the last aggregated
+    /// record are summarized into a new record
+    pub interval: Duration,
+}
+
+/// Determines which fields of the raw records written to a log file are
+/// summarized (replaced by their minimum, mean, and maximum) when aggregated.
+/// Only integer counters and gauges (and columns of an unknown kind) are
+/// summarized; the remaining columns (such as timestamps, limits, which are
+/// `max` when unlimited, settings, lists, vectors, and the target id) take
+/// the value of the last sample.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregateLayout {
+    summarized: Vec<bool>,
+}
+
+impl AggregateLayout {
+    /// Creates the layout for raw records with the given header row and
+    /// column types and schema (where columns that aren't in the table are
+    /// integers), never summarizing the given number of leading key fields
+    #[must_use]
+    pub fn new(header_row: &ByteRecord, table: &TableMetadata, key_fields: usize) -> Self {
+        let summarized = header_row
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let name = String::from_utf8_lossy(name);
+                let measured = table
+                    .schema
+                    .iter()
+                    .find(|schema| schema.name == name)
+                    .map_or(true, |schema| {
+                        matches!(
+                            schema.kind,
+                            ColumnKind::Counter | ColumnKind::Gauge | ColumnKind::Unknown
+                        )
+                    });
+                let integer = matches!(
+                    table.columns.get(name.as_ref()),
+                    None | Some(Column::Scalar {
+                        r#type: ColumnType::Int,
+                    })
+                );
+                i >= key_fields && integer && measured
+            })
+            .collect();
+        Self { summarized }
+    }
+
+    /// Rewrites the header row, column types, and schema of raw records into
+    /// those of aggregated records
+    pub fn apply(&self, header_row: &mut ByteRecord, table: &mut TableMetadata) {
+        let mut aggregated = ByteRecord::new();
+        let mut schema = Vec::with_capacity(table.schema.len());
+        for (name, &summarized) in header_row.iter().zip(&self.summarized) {
+            let name = String::from_utf8_lossy(name).into_owned();
+            let description = table.schema.iter().find(|schema| schema.name == name);
+            if !summarized {
+                aggregated.push_field(name.as_bytes());
+                schema.extend(description.cloned());
+                continue;
+            }
+
+            let column = table.columns.remove(&name);
+            for statistic in Statistic::all() {
+                let summary = format!("{}.{}", name, statistic.suffix());
+                aggregated.push_field(summary.as_bytes());
+                schema.extend(description.map(|schema| schema.summary(statistic)));
+                if let Some(column) = &column {
+                    table.columns.insert(summary, column.clone());
+                }
+            }
+        }
+
+        aggregated.push_field(SAMPLES_COLUMN.as_bytes());
+        let samples = Column::Scalar {
+            r#type: ColumnType::Int,
+        };
+        table.columns.insert(String::from(SAMPLES_COLUMN), samples);
+        table.schema = schema;
+        table.declare(SAMPLES_COLUMN, Unit::Count, ColumnKind::Gauge);
+        *header_row = aggregated;
+    }
+}
+
+/// Running summary of the values of a single field
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Summary {
+    min:   i128,
+    max:   i128,
+    sum:   i128,
+    count: i128,
+}
+
+impl Summary {
+    fn add(&mut self, value: i128) {
+        match self.count {
+            0 => {
+                self.min = value;
+                self.max = value;
+            },
+            _ => {
+                self.min = self.min.min(value);
+                self.max = self.max.max(value);
+            },
+        }
+        self.sum = self.sum.saturating_add(value);
+        self.count += 1;
+    }
+}
+
+/// Accumulates the raw records of a single target, summarizing them into a
+/// single record at each aggregation interval. This decouples the sampling
+/// interval from the rate at which records are written.
+pub struct Aggregator {
+    policy:     AggregatePolicy,
+    /// Most recent raw record, which provides the fields that aren't
+    /// summarized
+    last:       ByteRecord,
+    summaries:  Vec<Summary>,
+    samples:    u64,
+    started_at: Instant,
+}
+
+impl Aggregator {
+    #[must_use]
+    pub fn new(policy: AggregatePolicy) -> Self {
+        Self {
+            policy,
+            last: ByteRecord::new(),
+            summaries: Vec::new(),
+            samples: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Adds the raw record to the current interval. If the interval has
+    /// ended, the record is replaced with the aggregated record (which should
+    /// be written); otherwise returns false.
+    pub fn aggregate(&mut self, record: &mut ByteRecord, layout: &AggregateLayout) -> bool {
+        self.push(record, layout);
+        if !self.is_due() {
+            return false;
+        }
+
+        record.clear();
+        self.take(record, layout);
+        true
+    }
+
+    /// Adds a raw record to the current interval
+    fn push(&mut self, record: &ByteRecord, layout: &AggregateLayout) {
+        if self.samples == 0 {
+            self.started_at = Instant::now();
+        }
+
+        self.summaries.resize(record.len(), Summary::default());
+        let fields = record
+            .iter()
+            .zip(&layout.summarized)
+            .zip(&mut self.summaries);
+        for ((field, _), summary) in fields.filter(|((_, &summarized), _)| summarized) {
+            if let Some(value) = parse(field) {
+                summary.add(value);
+            }
+        }
+
+        self.last.clone_from(record);
+        self.samples += 1;
+    }
+
+    /// Whether the current interval has ended, such that the aggregated
+    /// record should be written
+    fn is_due(&self) -> bool {
+        self.samples > 0 && self.started_at.elapsed() >= self.policy.interval
+    }
+
+    /// Whether any samples were collected during the current interval
+    #[must_use]
+    pub const fn is_empty(&self) -> bool { self.samples == 0 }
+
+    /// Writes the aggregated record for the current interval into the given
+    /// (empty) record, and starts the next interval
+    pub fn take(&mut self, record: &mut ByteRecord, layout: &AggregateLayout) {
+        let mut itoa_buffer = itoa::Buffer::new();
+        let fields = self
+            .last
+            .iter()
+            .zip(&layout.summarized)
+            .zip(&self.summaries);
+        for ((field, &summarized), summary) in fields {
+            match (summarized, summary.count) {
+                (false, _) => record.push_field(field),
+                (true, 0) => {
+                    for _ in Statistic::all() {
+                        record.push_field(b"");
+                    }
+                },
+                (true, count) => {
+                    for statistic in Statistic::all() {
+                        let value = match statistic {
+                            Statistic::Min => summary.min,
+                            Statistic::Mean => summary.sum / count,
+                            Statistic::Max => summary.max,
+                        };
+                        record.push_field(itoa_buffer.format(value).as_bytes());
+                    }
+                },
+            }
+        }
+        record.push_field(itoa_buffer.format(self.samples).as_bytes());
+
+        self.summaries.clear();
+        self.samples = 0;
+    }
+}
+
+/// Parses a single integer value from a field
+fn parse(value: &[u8]) -> Option<i128> { std::str::from_utf8(value).ok()?.parse().ok() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const HEADER: [&str; 7] = [
+        "target.id",
+        "read",
+        "cpu.usage",
+        "memory.max",
+        "cpuset.cpus",
+        "pids.current",
+        "extra",
+    ];
+
+    /// Table of the header row, where `extra` has no description
+    fn table() -> TableMetadata {
+        let mut table = TableMetadata {
+            delimiter: String::from(","),
+            columns:   BTreeMap::new(),
+            schema:    Vec::new(),
+        };
+        table.columns.insert(String::from("read"), Column::Scalar {
+            r#type: ColumnType::Epoch19,
+        });
+        table
+            .columns
+            .insert(String::from("cpuset.cpus"), Column::Scalar {
+                r#type: ColumnType::List,
+            });
+        table.declare("target.id", Unit::Unitless, ColumnKind::Identifier);
+        table.declare("read", Unit::Nanoseconds, ColumnKind::Timestamp);
+        table.declare("cpu.usage", Unit::Nanoseconds, ColumnKind::Counter);
+        table.declare("memory.max", Unit::Bytes, ColumnKind::Limit);
+        table.declare("cpuset.cpus", Unit::Unitless, ColumnKind::Setting);
+        table.declare("pids.current", Unit::Count, ColumnKind::Gauge);
+        table
+    }
+
+    fn layout() -> AggregateLayout {
+        AggregateLayout::new(&ByteRecord::from(&HEADER[..]), &table(), 1)
+    }
+
+    /// Aggregator that never takes records on its own
+    fn aggregator() -> Aggregator {
+        Aggregator::new(AggregatePolicy {
+            interval: Duration::from_secs(3600),
+        })
+    }
+
+    fn take(aggregator: &mut Aggregator, layout: &AggregateLayout) -> Vec<String> {
+        let mut record = ByteRecord::new();
+        aggregator.take(&mut record, layout);
+        record
+            .iter()
+            .map(|field| String::from_utf8_lossy(field).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn summary_starts_from_first_value() {
+        let mut summary = Summary::default();
+        summary.add(-5);
+        assert_eq!(summary, Summary {
+            min:   -5,
+            max:   -5,
+            sum:   -5,
+            count: 1,
+        });
+
+        summary.add(3);
+        summary.add(10);
+        assert_eq!(summary, Summary {
+            min:   -5,
+            max:   10,
+            sum:   8,
+            count: 3,
+        });
+    }
+
+    #[test]
+    fn summary_sum_saturates() {
+        let mut summary = Summary::default();
+        summary.add(i128::MAX);
+        summary.add(1);
+        assert_eq!(summary.sum, i128::MAX);
+        assert_eq!(summary.count, 2);
+    }
+
+    #[test]
+    fn only_measured_integer_columns_are_summarized() {
+        assert_eq!(layout().summarized, vec![
+            false, false, true, false, false, true, true
+        ]);
+    }
+
+    #[test]
+    fn key_fields_are_never_summarized() {
+        let header = ByteRecord::from(vec!["cpu.usage", "pids.current"]);
+        let layout = AggregateLayout::new(&header, &table(), 1);
+        assert_eq!(layout.summarized, vec![false, true]);
+    }
+
+    #[test]
+    fn apply_replaces_summarized_columns() {
+        let mut header = ByteRecord::from(&HEADER[..]);
+        let mut table = table();
+        layout().apply(&mut header, &mut table);
+
+        let expected = [
+            "target.id",
+            "read",
+            "cpu.usage.min",
+            "cpu.usage.mean",
+            "cpu.usage.max",
+            "memory.max",
+            "cpuset.cpus",
+            "pids.current.min",
+            "pids.current.mean",
+            "pids.current.max",
+            "extra.min",
+            "extra.mean",
+            "extra.max",
+            SAMPLES_COLUMN,
+        ];
+        assert_eq!(header, ByteRecord::from(&expected[..]));
+
+        // Columns without a description (`extra`) stay undescribed
+        let described = table
+            .schema
+            .iter()
+            .map(|schema| (schema.name.as_str(), schema.statistic))
+            .collect::<Vec<_>>();
+        assert_eq!(described, vec![
+            ("target.id", None),
+            ("read", None),
+            ("cpu.usage.min", Some(Statistic::Min)),
+            ("cpu.usage.mean", Some(Statistic::Mean)),
+            ("cpu.usage.max", Some(Statistic::Max)),
+            ("memory.max", None),
+            ("cpuset.cpus", None),
+            ("pids.current.min", Some(Statistic::Min)),
+            ("pids.current.mean", Some(Statistic::Mean)),
+            ("pids.current.max", Some(Statistic::Max)),
+            (SAMPLES_COLUMN, None),
+        ]);
+        assert_eq!(
+            table.columns.get("read.min"),
+            None,
+            "timestamps aren't summarized"
+        );
+        assert!(table.columns.contains_key("cpuset.cpus"));
+        assert!(table.columns.contains_key(SAMPLES_COLUMN));
+    }
+
+    #[test]
+    fn take_merges_samples() {
+        let layout = layout();
+        let mut aggregator = aggregator();
+        for record in &[
+            ["a", "1", "100", "max", "0-3", "4", "-2"],
+            ["a", "2", "300", "max", "0-3", "8", "7"],
+            ["a", "3", "350", "1024", "0-1", "3", "1"],
+        ] {
+            aggregator.push(&ByteRecord::from(&record[..]), &layout);
+        }
+
+        assert_eq!(take(&mut aggregator, &layout), vec![
+            "a", "3", "100", "250", "350", "1024", "0-1", "3", "5", "8", "-2", "2", "7", "3"
+        ]);
+        assert!(aggregator.is_empty());
+    }
+
+    #[test]
+    fn take_leaves_fields_without_values_empty() {
+        let layout = layout();
+        let mut aggregator = aggregator();
+        aggregator.push(
+            &ByteRecord::from(vec!["a", "1", "", "max", "0-3", "4", ""]),
+            &layout,
+        );
+        aggregator.push(
+            &ByteRecord::from(vec!["a", "2", "", "max", "0-3", "", "x"]),
+            &layout,
+        );
+
+        // Fields that are empty in some samples are summarized from the rest
+        assert_eq!(take(&mut aggregator, &layout), vec![
+            "a", "2", "", "", "", "max", "0-3", "4", "4", "4", "", "", "", "2"
+        ]);
+    }
+
+    #[test]
+    fn take_without_samples() {
+        let layout = layout();
+        let mut aggregator = aggregator();
+        assert!(aggregator.is_empty());
+        assert_eq!(take(&mut aggregator, &layout), vec!["0"]);
+    }
+
+    #[test]
+    fn take_starts_the_next_interval() {
+        let layout = layout();
+        let mut aggregator = aggregator();
+        aggregator.push(
+            &ByteRecord::from(vec!["a", "1", "100", "max", "0-3", "4", "0"]),
+            &layout,
+        );
+        take(&mut aggregator, &layout);
+
+        aggregator.push(
+            &ByteRecord::from(vec!["a", "2", "200", "max", "0-3", "6", "0"]),
+            &layout,
+        );
+        assert_eq!(take(&mut aggregator, &layout), vec![
+            "a", "2", "200", "200", "200", "max", "0-3", "6", "6", "6", "0", "0", "0", "1"
+        ]);
+    }
+
+    #[test]
+    fn aggregate_waits_for_the_interval() {
+        let layout = layout();
+        let mut aggregator = aggregator();
+        let mut record = ByteRecord::from(vec!["a", "1", "100", "max", "0-3", "4", "0"]);
+        assert!(!aggregator.aggregate(&mut record, &layout));
+        assert_eq!(record.len(), HEADER.len());
+        assert!(!aggregator.is_empty());
+
+        let mut aggregator = Aggregator::new(AggregatePolicy {
+            interval: Duration::from_secs(0),
+        });
+        assert!(aggregator.aggregate(&mut record, &layout));
+        assert_eq!(&record[record.len() - 1], b"1");
+        assert!(aggregator.is_empty());
+    }
+}
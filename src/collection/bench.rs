@@ -0,0 +1,263 @@
+//! Contains the hidden `radvisor bench` command, which repeatedly collects and
+//! serializes records from cgroup directories (by default, the synthetic
+//! fixtures in `tests/fixtures`) and reports the latency and heap allocations
+//! (when built with the `bench` feature) of each sample. This catches
+//! performance regressions in the read/serialize path (which is meant to not
+//! allocate once it has warmed up), and helps size the collection interval for
+//! the hardware it's run on. The same samples are also measured by the
+//! criterion benchmarks in `benches/`.
+
+use crate::cli::BenchCommand;
+use crate::collection::buffers::WorkingBuffers;
+use crate::collection::collectors::{Collector, CollectorImpl, CollectorOptions};
+use crate::shared::CollectionMethod;
+use crate::util::{self, Allocations, CgroupDriver, CgroupPath, CgroupVersion};
+use csv::{Writer, WriterBuilder};
+use std::fs;
+use std::io::{self, Sink, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Error that can occur when running the benchmark
+#[derive(Debug, thiserror::Error)]
+pub enum BenchError {
+    #[error("no cgroup directories were given, and there are no fixtures in {}", .0.display())]
+    NoFixtures(PathBuf),
+    #[error("could not initialize the collector for {0}: {1}")]
+    Initialization(String, #[source] anyhow::Error),
+    #[error("could not serialize a record: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("could not print the results: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0} of the measured samples allocated, but the audit expects none to")]
+    Allocations(u64),
+    #[error("heap allocations are only counted when built with the `bench` feature")]
+    Uncounted,
+}
+
+/// Collector for a single cgroup directory whose samples are measured. Each
+/// sample collects a record and serializes it as CSV (to a writer that
+/// discards it), just as the collection thread does for each target.
+pub struct Bench {
+    name:      String,
+    collector: CollectorImpl,
+    buffers:   WorkingBuffers,
+    writer:    Writer<Sink>,
+}
+
+/// Latency and allocations of the measured samples of a single cgroup
+/// directory
+pub struct BenchReport {
+    pub name:               String,
+    pub collector:          &'static str,
+    pub columns:            usize,
+    pub samples:            u64,
+    pub mean:               Duration,
+    pub p50:                Duration,
+    pub p99:                Duration,
+    pub max:                Duration,
+    /// Total allocations made by all measured samples
+    pub allocations:        Allocations,
+    /// Number of measured samples that made at least one allocation
+    pub allocating_samples: u64,
+}
+
+impl Bench {
+    /// Initializes a collector for the cgroup directory, which is collected as
+    /// a cgroup v2 directory if it contains `cgroup.controllers` (and as a
+    /// cgroup v1 directory otherwise). Since every file is read from the
+    /// directory itself, cgroup v1 directories contain the files of every
+    /// subsystem (like the fixtures), and only the files of a single
+    /// subsystem are found in real cgroup v1 hierarchies.
+    pub fn new<P: AsRef<Path>>(cgroup: P) -> Result<Self, BenchError> {
+        let cgroup = cgroup.as_ref();
+        let name = cgroup.display().to_string();
+        let path = fs::canonicalize(cgroup)
+            .map_err(|err| BenchError::Initialization(name.clone(), err.into()))?;
+        let version = match path.join("cgroup.controllers").exists() {
+            true => CgroupVersion::V2,
+            false => CgroupVersion::V1,
+        };
+        let cgroup = CgroupPath {
+            path,
+            driver: CgroupDriver::Cgroupfs,
+            version,
+        };
+        let method = match version {
+            CgroupVersion::V1 => CollectionMethod::LinuxCgroupV1(cgroup),
+            CgroupVersion::V2 => CollectionMethod::LinuxCgroupV2(cgroup),
+        };
+        let options = CollectorOptions {
+            bfq: true,
+            ..CollectorOptions::default()
+        };
+
+        let mut collector = CollectorImpl::new(method, options);
+        collector
+            .init()
+            .map_err(|err| BenchError::Initialization(name.clone(), err))?;
+        let mut writer = WriterBuilder::new().from_writer(io::sink());
+        writer.write_byte_record(collector.header())?;
+        Ok(Self {
+            name,
+            collector,
+            buffers: WorkingBuffers::new(),
+            writer,
+        })
+    }
+
+    /// Gets the synthetic cgroup fixtures (a cgroup v1 and a cgroup v2
+    /// directory, as checked into the repository in `tests/fixtures`) in the
+    /// given directory
+    #[must_use]
+    pub fn fixtures(directory: &Path) -> Vec<PathBuf> {
+        let mut fixtures: Vec<PathBuf> = match fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        fixtures.sort();
+        fixtures
+    }
+
+    /// Runs the benchmark for each of the cgroup directories (or the fixtures,
+    /// if none were given), printing the results to stdout. If auditing,
+    /// fails if any measured sample allocated.
+    pub fn run(opts: &BenchCommand) -> Result<(), BenchError> {
+        if opts.audit && !util::counts_allocations() {
+            return Err(BenchError::Uncounted);
+        }
+        let cgroups = match opts.cgroups.is_empty() {
+            true => Self::fixtures(&opts.fixtures),
+            false => opts.cgroups.clone(),
+        };
+        if cgroups.is_empty() {
+            return Err(BenchError::NoFixtures(opts.fixtures.clone()));
+        }
+
+        let reports = cgroups
+            .iter()
+            .map(|cgroup| Ok(Self::new(cgroup)?.measure(opts.warmup, opts.samples)))
+            .collect::<Result<Vec<_>, BenchError>>()?;
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for report in &reports {
+            report.write(&mut out, opts.interval)?;
+        }
+
+        let allocating = reports.iter().map(|r| r.allocating_samples).sum::<u64>();
+        match opts.audit && allocating > 0 {
+            true => Err(BenchError::Allocations(allocating)),
+            false => Ok(()),
+        }
+    }
+
+    /// Collects a single record and serializes it
+    pub fn sample(&mut self) {
+        self.collector.collect(&mut self.buffers);
+        // Ignore errors: the writer discards everything
+        let _result = self.writer.write_byte_record(&self.buffers.record);
+        self.buffers.record.clear();
+        self.buffers.vanished = None;
+    }
+
+    /// Takes the given number of warm-up samples (which fill the working
+    /// buffers), and then measures the latency and allocations of each of the
+    /// given number of samples
+    pub fn measure(&mut self, warmup: u64, samples: u64) -> BenchReport {
+        for _ in 0..warmup {
+            self.sample();
+        }
+
+        let mut latencies: Vec<Duration> = Vec::with_capacity(util::remap(samples));
+        let mut allocating_samples = 0;
+        let start_allocations = util::allocations();
+        for _ in 0..samples {
+            let before = util::allocations();
+            let start = Instant::now();
+            self.sample();
+            latencies.push(start.elapsed());
+            if util::allocations().since(before).count > 0 {
+                allocating_samples += 1;
+            }
+        }
+        let allocations = util::allocations().since(start_allocations);
+
+        latencies.sort_unstable();
+        let total: Duration = latencies.iter().sum();
+        let percentile = |p: usize| -> Duration {
+            match latencies.len() {
+                0 => Duration::default(),
+                len => latencies[(len - 1) * p / 100],
+            }
+        };
+        BenchReport {
+            name: self.name.clone(),
+            collector: self.collector.get_type(),
+            columns: self.collector.header().len(),
+            samples,
+            mean: total.checked_div(util::remap(samples)).unwrap_or_default(),
+            p50: percentile(50),
+            p99: percentile(99),
+            max: latencies.last().copied().unwrap_or_default(),
+            allocations,
+            allocating_samples,
+        }
+    }
+}
+
+impl BenchReport {
+    /// Prints a block with the results, including the number of targets like
+    /// this one that could be collected within each collection interval (at
+    /// the 99th percentile latency)
+    pub fn write<W: Write>(&self, out: &mut W, interval: Duration) -> io::Result<()> {
+        #[allow(clippy::cast_precision_loss)]
+        let per_sample = |total: u64| total as f64 / self.samples.max(1) as f64;
+        let targets = match self.p99.as_nanos() {
+            0 => 0,
+            p99 => interval.as_nanos() / p99,
+        };
+        writeln!(
+            out,
+            "{} ({} collector, {} columns)",
+            self.name, self.collector, self.columns
+        )?;
+        writeln!(out, "  samples      {}", self.samples)?;
+        writeln!(
+            out,
+            "  latency      mean {}, p50 {}, p99 {}, max {}",
+            micros(self.mean),
+            micros(self.p50),
+            micros(self.p99),
+            micros(self.max)
+        )?;
+        match util::counts_allocations() {
+            true => writeln!(
+                out,
+                "  allocations  {:.2} per sample ({:.1} bytes), in {} samples",
+                per_sample(self.allocations.count),
+                per_sample(self.allocations.bytes),
+                self.allocating_samples
+            )?,
+            false => writeln!(
+                out,
+                "  allocations  not counted (build with `--features bench`)"
+            )?,
+        }
+        writeln!(
+            out,
+            "  capacity     {} targets per {} interval",
+            targets,
+            humantime::format_duration(interval)
+        )?;
+        writeln!(out)
+    }
+}
+
+/// Formats a duration in microseconds
+fn micros(duration: Duration) -> String { format!("{:.1}µs", duration.as_secs_f64() * 1e6) }
@@ -1,5 +1,9 @@
+use crate::collection::collectors::Subsystem;
 use crate::util::{AnonymousSlice, Buffer};
 use csv::ByteRecord;
+use std::fmt;
+use std::io;
+use std::mem;
 
 /// Length of the buffer for each row. Designed to be a reasonable upper limit
 /// to prevent expensive re-allocation
@@ -25,6 +29,14 @@ pub struct WorkingBuffers {
     pub buffer:      Buffer<WORKING_BUFFER_SIZE>,
     pub copy_buffer: Buffer<WORKING_BUFFER_SIZE>,
     pub slices:      [AnonymousSlice; SLICES_BUFFER_SIZE],
+    /// Error number of a failed read that indicated that the cgroup being
+    /// collected no longer exists, if there was one since it was last taken
+    pub vanished:    Option<i32>,
+    /// Subsystem whose files are currently being read, which failed reads
+    /// are counted against
+    pub subsystem:   Option<Subsystem>,
+    /// Failed reads since they were last taken
+    pub failed:      FailedReads,
 }
 
 impl Default for WorkingBuffers {
@@ -41,6 +53,81 @@ impl WorkingBuffers {
             slices:      [<AnonymousSlice>::default(); SLICES_BUFFER_SIZE],
             buffer:      Buffer::<WORKING_BUFFER_SIZE>::default(),
             copy_buffer: Buffer::<WORKING_BUFFER_SIZE>::default(),
+            vanished:    None,
+            subsystem:   None,
+            failed:      FailedReads::default(),
         }
     }
+
+    /// Counts failed reads against the given subsystem until another one is
+    /// set, returning the buffers so that they can be passed on
+    pub fn reading(&mut self, subsystem: Subsystem) -> &mut Self {
+        self.subsystem = Some(subsystem);
+        self
+    }
+
+    /// Takes the failed reads since they were last taken, clearing the
+    /// subsystem that they are counted against
+    pub fn take_failed(&mut self) -> FailedReads {
+        self.subsystem = None;
+        mem::take(&mut self.failed)
+    }
+
+    /// Records a failed read of a cgroup file, remembering its error number if
+    /// it indicates that the cgroup was removed. Open handles to the files of
+    /// a removed cgroup fail with `ENODEV`, while re-opening them fails with
+    /// `ENOENT`.
+    pub fn record_read_error(&mut self, err: &io::Error) {
+        if let Some(subsystem) = self.subsystem {
+            self.failed.record(subsystem);
+        }
+        if let Some(errno @ (libc::ENOENT | libc::ESRCH | libc::ENODEV)) = err.raw_os_error() {
+            self.vanished = Some(errno);
+        }
+    }
+}
+
+/// Number of failed reads of cgroup files, by subsystem
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FailedReads([u64; Subsystem::VARIANTS.len()]);
+
+impl FailedReads {
+    /// Counts a failed read of a file of the subsystem
+    pub fn record(&mut self, subsystem: Subsystem) { self.0[subsystem as usize] += 1; }
+
+    /// Adds the failed reads of the other counts to these ones
+    pub fn add(&mut self, other: &Self) {
+        for (count, other) in self.0.iter_mut().zip(&other.0) {
+            *count += other;
+        }
+    }
+
+    /// Gets the total number of failed reads across all subsystems
+    #[must_use]
+    pub fn total(&self) -> u64 { self.0.iter().sum() }
+
+    /// Iterates over the subsystems with any failed reads, along with their
+    /// number of failed reads
+    pub fn iter(&self) -> impl Iterator<Item = (Subsystem, u64)> + '_ {
+        Subsystem::VARIANTS
+            .iter()
+            .map(move |&subsystem| (subsystem, self.0[subsystem as usize]))
+            .filter(|&(_, count)| count > 0)
+    }
+}
+
+impl fmt::Display for FailedReads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.total() == 0 {
+            return write!(f, "none");
+        }
+
+        for (i, (subsystem, count)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", <&'static str>::from(subsystem), count)?;
+        }
+        Ok(())
+    }
 }
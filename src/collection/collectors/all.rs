@@ -1,12 +1,19 @@
 use crate::collection::buffers::WorkingBuffers;
-use crate::collection::collectors::{cgroup_v1, cgroup_v2, Collector, StatWriter};
-use crate::collection::perf_table::TableMetadata;
+use crate::collection::collectors::{cgroup_v1, cgroup_v2, host, hybrid, synthetic, Collector,
+                                    CollectorOptions};
+use crate::formats::TableMetadata;
 use crate::shared::CollectionMethod;
+use crate::util::CgroupPath;
 use anyhow::Error;
+use csv::ByteRecord;
 
 pub enum CollectorImpl {
     CgroupV1(cgroup_v1::Collector),
     CgroupV2(cgroup_v2::Collector),
+    /// Boxed since it holds both a cgroup v1 and a cgroup v2 collector
+    Hybrid(Box<hybrid::Collector>),
+    Host(host::Collector),
+    Synthetic(synthetic::Collector),
 }
 
 impl Collector for CollectorImpl {
@@ -14,6 +21,9 @@ impl Collector for CollectorImpl {
         match self {
             Self::CgroupV1(v1) => v1.metadata(),
             Self::CgroupV2(v2) => v2.metadata(),
+            Self::Hybrid(hybrid) => hybrid.metadata(),
+            Self::Host(host) => host.metadata(),
+            Self::Synthetic(synthetic) => synthetic.metadata(),
         }
     }
 
@@ -21,6 +31,9 @@ impl Collector for CollectorImpl {
         match self {
             Self::CgroupV1(v1) => v1.table_metadata(),
             Self::CgroupV2(v2) => v2.table_metadata(),
+            Self::Hybrid(hybrid) => hybrid.table_metadata(),
+            Self::Host(host) => host.table_metadata(),
+            Self::Synthetic(synthetic) => synthetic.table_metadata(),
         }
     }
 
@@ -28,6 +41,9 @@ impl Collector for CollectorImpl {
         match self {
             Self::CgroupV1(v1) => v1.get_type(),
             Self::CgroupV2(v2) => v2.get_type(),
+            Self::Hybrid(hybrid) => hybrid.get_type(),
+            Self::Host(host) => host.get_type(),
+            Self::Synthetic(synthetic) => synthetic.get_type(),
         }
     }
 
@@ -35,37 +51,84 @@ impl Collector for CollectorImpl {
         match self {
             Self::CgroupV1(v1) => v1.init(),
             Self::CgroupV2(v2) => v2.init(),
+            Self::Hybrid(hybrid) => hybrid.init(),
+            Self::Host(host) => host.init(),
+            Self::Synthetic(synthetic) => synthetic.init(),
         }
     }
 
-    fn write_header(&mut self, writer: &mut StatWriter) -> Result<(), csv::Error> {
+    fn header(&self) -> &ByteRecord {
         match self {
-            Self::CgroupV1(v1) => v1.write_header(writer),
-            Self::CgroupV2(v2) => v2.write_header(writer),
+            Self::CgroupV1(v1) => v1.header(),
+            Self::CgroupV2(v2) => v2.header(),
+            Self::Hybrid(hybrid) => hybrid.header(),
+            Self::Host(host) => host.header(),
+            Self::Synthetic(synthetic) => synthetic.header(),
         }
     }
 
-    fn collect(
-        &mut self,
-        writer: &mut StatWriter,
-        working_buffers: &mut WorkingBuffers,
-    ) -> Result<(), csv::Error> {
+    fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> bool {
         match self {
-            Self::CgroupV1(v1) => v1.collect(writer, working_buffers),
-            Self::CgroupV2(v2) => v2.collect(writer, working_buffers),
+            Self::CgroupV1(v1) => v1.collect(working_buffers),
+            Self::CgroupV2(v2) => v2.collect(working_buffers),
+            Self::Hybrid(hybrid) => hybrid.collect(working_buffers),
+            Self::Host(host) => host.collect(working_buffers),
+            Self::Synthetic(synthetic) => synthetic.collect(working_buffers),
+        }
+    }
+
+    fn demote_files(&mut self) {
+        match self {
+            Self::CgroupV1(v1) => v1.demote_files(),
+            Self::CgroupV2(v2) => v2.demote_files(),
+            Self::Hybrid(hybrid) => hybrid.demote_files(),
+            Self::Host(host) => host.demote_files(),
+            Self::Synthetic(synthetic) => synthetic.demote_files(),
+        }
+    }
+
+    fn promote_files(&mut self) -> bool {
+        match self {
+            Self::CgroupV1(v1) => v1.promote_files(),
+            Self::CgroupV2(v2) => v2.promote_files(),
+            Self::Hybrid(hybrid) => hybrid.promote_files(),
+            Self::Host(host) => host.promote_files(),
+            Self::Synthetic(synthetic) => synthetic.promote_files(),
         }
     }
 }
 
-impl From<CollectionMethod> for CollectorImpl {
-    fn from(method: CollectionMethod) -> Self {
+impl CollectorImpl {
+    /// Creates the collector for the given collection method
+    #[must_use]
+    pub fn new(method: CollectionMethod, options: CollectorOptions) -> Self {
         match method {
             CollectionMethod::LinuxCgroupV1(path) => {
-                Self::CgroupV1(cgroup_v1::Collector::new(path))
+                Self::CgroupV1(cgroup_v1::Collector::new(path, &options))
             },
             CollectionMethod::LinuxCgroupV2(path) => {
-                Self::CgroupV2(cgroup_v2::Collector::new(path))
+                Self::CgroupV2(cgroup_v2::Collector::new(path, options))
+            },
+            CollectionMethod::LinuxCgroupHybrid(path) => {
+                Self::Hybrid(Box::new(hybrid::Collector::new(path, &options)))
             },
+            CollectionMethod::Host(path) => {
+                let inner = CollectionMethod::for_cgroup(path);
+                Self::Host(host::Collector::new(Self::new(inner, options)))
+            },
+            CollectionMethod::Synthetic(seed) => Self::Synthetic(synthetic::Collector::new(seed)),
+        }
+    }
+
+    /// Gets the cgroup that statistics are collected from
+    #[must_use]
+    pub fn cgroup(&self) -> &CgroupPath {
+        match self {
+            Self::CgroupV1(v1) => v1.cgroup(),
+            Self::CgroupV2(v2) => v2.cgroup(),
+            Self::Hybrid(hybrid) => hybrid.cgroup(),
+            Self::Host(host) => host.cgroup(),
+            Self::Synthetic(synthetic) => synthetic.cgroup(),
         }
     }
 }
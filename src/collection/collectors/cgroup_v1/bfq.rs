@@ -0,0 +1,55 @@
+use crate::cli::ParseFailure;
+use std::fs;
+use std::str::FromStr;
+
+/// Directory containing the block devices on the system
+const SYS_BLOCK_ROOT: &str = "/sys/block";
+
+/// Whether the `blkio.bfq.*` columns are included in cgroup v1 log files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BfqColumns {
+    /// Included only if any block device uses the bfq I/O scheduler
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for BfqColumns {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(ParseFailure::new(String::from("bfq columns"), s.to_owned())),
+        }
+    }
+}
+
+impl BfqColumns {
+    /// Determines whether the bfq columns should be included, detecting the
+    /// active I/O schedulers if needed
+    #[must_use]
+    pub fn resolve(self) -> bool {
+        match self {
+            Self::Auto => bfq_active(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Determines whether any block device uses the bfq I/O scheduler. The active
+/// scheduler is the one in brackets in `/sys/block/<dev>/queue/scheduler`,
+/// such as `mq-deadline kyber [bfq] none`.
+fn bfq_active() -> bool {
+    if let Ok(devices) = fs::read_dir(SYS_BLOCK_ROOT) {
+        return devices.filter_map(Result::ok).any(|device| {
+            let scheduler = fs::read_to_string(device.path().join("queue/scheduler"));
+            matches!(scheduler, Ok(scheduler) if scheduler.split_whitespace().any(|s| s == "[bfq]"))
+        });
+    }
+
+    false
+}
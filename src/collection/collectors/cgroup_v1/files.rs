@@ -1,82 +1,234 @@
-use std::fs::File;
+use super::HUGE_PAGE_SIZES;
+use crate::collection::collectors::{Subsystem, Subsystems};
+use crate::collection::fd_budget::{CgroupFile, FileKind};
+use crate::util;
+use std::fs;
 use std::path::{Path, PathBuf};
 
-const CGROUP_V1_ROOT: &str = "/sys/fs/cgroup";
-
 /// File handles re-used for each target that read into the /proc VFS
 pub struct ProcFileHandles {
-    pub current_pids:                    Option<File>,
-    pub max_pids:                        Option<File>,
-    pub cpu_stat:                        Option<File>,
-    pub cpuacct_stat:                    Option<File>,
-    pub cpuacct_usage:                   Option<File>,
-    pub cpuacct_usage_sys:               Option<File>,
-    pub cpuacct_usage_user:              Option<File>,
-    pub cpuacct_usage_percpu:            Option<File>,
-    pub memory_usage_in_bytes:           Option<File>,
-    pub memory_max_usage_in_bytes:       Option<File>,
-    pub memory_limit_in_bytes:           Option<File>,
-    pub memory_soft_limit_in_bytes:      Option<File>,
-    pub memory_failcnt:                  Option<File>,
-    pub memory_stat:                     Option<File>,
-    pub blkio_io_service_bytes:          Option<File>,
-    pub blkio_io_serviced:               Option<File>,
-    pub blkio_io_service_time:           Option<File>,
-    pub blkio_io_queued:                 Option<File>,
-    pub blkio_io_wait_time:              Option<File>,
-    pub blkio_io_merged:                 Option<File>,
-    pub blkio_time:                      Option<File>,
-    pub blkio_sectors:                   Option<File>,
-    pub blkio_throttle_io_service_bytes: Option<File>,
-    pub blkio_throttle_io_serviced:      Option<File>,
-    pub blkio_bfq_io_service_bytes:      Option<File>,
-    pub blkio_bfq_io_serviced:           Option<File>,
+    pub current_pids:                    CgroupFile,
+    pub max_pids:                        CgroupFile,
+    pub cpu_stat:                        CgroupFile,
+    pub cpuacct_stat:                    CgroupFile,
+    pub cpuacct_usage:                   CgroupFile,
+    pub cpuacct_usage_sys:               CgroupFile,
+    pub cpuacct_usage_user:              CgroupFile,
+    pub cpuacct_usage_percpu:            CgroupFile,
+    pub cpuset_cpus:                     CgroupFile,
+    pub cpuset_mems:                     CgroupFile,
+    pub memory_usage_in_bytes:           CgroupFile,
+    pub memory_max_usage_in_bytes:       CgroupFile,
+    pub memory_limit_in_bytes:           CgroupFile,
+    pub memory_soft_limit_in_bytes:      CgroupFile,
+    pub memory_failcnt:                  CgroupFile,
+    pub memory_stat:                     CgroupFile,
+    pub memory_kmem_usage_in_bytes:      CgroupFile,
+    pub memory_kmem_max_usage_in_bytes:  CgroupFile,
+    pub memory_kmem_limit_in_bytes:      CgroupFile,
+    pub memory_kmem_failcnt:             CgroupFile,
+    pub memory_kmem_tcp_usage_in_bytes:  CgroupFile,
+    pub memory_kmem_tcp_max_usage:       CgroupFile,
+    pub memory_kmem_tcp_limit_in_bytes:  CgroupFile,
+    pub memory_kmem_tcp_failcnt:         CgroupFile,
+    /// Handles for each supported huge page size (see `HUGE_PAGE_SIZES`)
+    pub hugetlb:                         Vec<HugetlbFileHandles>,
+    pub blkio_io_service_bytes:          CgroupFile,
+    pub blkio_io_serviced:               CgroupFile,
+    pub blkio_io_service_time:           CgroupFile,
+    pub blkio_io_queued:                 CgroupFile,
+    pub blkio_io_wait_time:              CgroupFile,
+    pub blkio_io_merged:                 CgroupFile,
+    pub blkio_time:                      CgroupFile,
+    pub blkio_sectors:                   CgroupFile,
+    pub blkio_throttle_io_service_bytes: CgroupFile,
+    pub blkio_throttle_io_serviced:      CgroupFile,
+    pub blkio_bfq_io_service_bytes:      CgroupFile,
+    pub blkio_bfq_io_serviced:           CgroupFile,
+}
+
+/// File handles for the hugetlb subsystem files of a single huge page size
+pub struct HugetlbFileHandles {
+    pub usage_in_bytes:     CgroupFile,
+    pub max_usage_in_bytes: CgroupFile,
+    pub limit_in_bytes:     CgroupFile,
+    pub failcnt:            CgroupFile,
 }
 
 impl ProcFileHandles {
     /// Initializes all file handles to /proc files, utilizing them over the
-    /// entire timeline of the target monitoring. If a handle fails to
-    /// open, the struct field will be None
+    /// entire timeline of the target monitoring (unless the file descriptor
+    /// budget is under pressure, see `fd_budget`). If a handle fails to
+    /// open (or its subsystem or the bfq files aren't collected), the struct
+    /// field will be missing
     #[must_use]
-    pub fn new<C: AsRef<Path>>(cgroup: C) -> Self {
+    pub fn new<C: AsRef<Path>>(cgroup: C, bfq: bool, subsystems: Subsystems) -> Self {
+        let open_kind = |subsystem: &str, file: &str, kind: FileKind| match subsystems
+            .contains_v1_directory(subsystem)
+        {
+            true => open(&cgroup, subsystem, file, kind),
+            false => CgroupFile::Missing,
+        };
+        let o = |subsystem: &str, file: &str| open_kind(subsystem, file, FileKind::Statistic);
+        let s = |subsystem: &str, file: &str| open_kind(subsystem, file, FileKind::Setting);
+        let bfq_file = |file: &str| match bfq {
+            true => o("blkio", file),
+            false => CgroupFile::Missing,
+        };
+        let hugetlb = match subsystems.contains(Subsystem::Hugetlb) {
+            true => HUGE_PAGE_SIZES
+                .iter()
+                .map(|size| HugetlbFileHandles {
+                    usage_in_bytes:     h(&cgroup, size, "usage_in_bytes", FileKind::Statistic),
+                    max_usage_in_bytes: h(&cgroup, size, "max_usage_in_bytes", FileKind::Statistic),
+                    limit_in_bytes:     h(&cgroup, size, "limit_in_bytes", FileKind::Setting),
+                    failcnt:            h(&cgroup, size, "failcnt", FileKind::Statistic),
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+
         Self {
-            current_pids:                    o(&cgroup, "pids", "pids.current"),
-            max_pids:                        o(&cgroup, "pids", "pids.max"),
-            cpu_stat:                        o(&cgroup, "cpu", "cpu.stat"),
-            cpuacct_stat:                    o(&cgroup, "cpuacct", "cpuacct.stat"),
-            cpuacct_usage:                   o(&cgroup, "cpuacct", "cpuacct.usage"),
-            cpuacct_usage_sys:               o(&cgroup, "cpuacct", "cpuacct.usage_sys"),
-            cpuacct_usage_user:              o(&cgroup, "cpuacct", "cpuacct.usage_user"),
-            cpuacct_usage_percpu:            o(&cgroup, "cpuacct", "cpuacct.usage_percpu"),
-            memory_usage_in_bytes:           o(&cgroup, "memory", "memory.usage_in_bytes"),
-            memory_max_usage_in_bytes:       o(&cgroup, "memory", "memory.max_usage_in_bytes"),
-            memory_limit_in_bytes:           o(&cgroup, "memory", "memory.limit_in_bytes"),
-            memory_soft_limit_in_bytes:      o(&cgroup, "memory", "memory.soft_limit_in_bytes"),
-            memory_failcnt:                  o(&cgroup, "memory", "memory.failcnt"),
-            memory_stat:                     o(&cgroup, "memory", "memory.stat"),
-            blkio_io_service_bytes:          o(&cgroup, "blkio", "blkio.io_service_bytes"),
-            blkio_io_serviced:               o(&cgroup, "blkio", "blkio.io_serviced"),
-            blkio_io_service_time:           o(&cgroup, "blkio", "blkio.io_service_time"),
-            blkio_io_queued:                 o(&cgroup, "blkio", "blkio.io_queued"),
-            blkio_io_wait_time:              o(&cgroup, "blkio", "blkio.io_wait_time"),
-            blkio_io_merged:                 o(&cgroup, "blkio", "blkio.io_merged"),
-            blkio_time:                      o(&cgroup, "blkio", "blkio.time"),
-            blkio_sectors:                   o(&cgroup, "blkio", "blkio.sectors"),
-            blkio_throttle_io_service_bytes: o(&cgroup, "blkio", "blkio.throttle.io_service_bytes"),
-            blkio_throttle_io_serviced:      o(&cgroup, "blkio", "blkio.throttle.io_serviced"),
-            blkio_bfq_io_service_bytes:      o(&cgroup, "blkio", "blkio.bfq.io_service_bytes"),
-            blkio_bfq_io_serviced:           o(&cgroup, "blkio", "blkio.bfq.io_serviced"),
+            current_pids: o("pids", "pids.current"),
+            max_pids: s("pids", "pids.max"),
+            cpu_stat: o("cpu", "cpu.stat"),
+            cpuacct_stat: o("cpuacct", "cpuacct.stat"),
+            cpuacct_usage: o("cpuacct", "cpuacct.usage"),
+            cpuacct_usage_sys: o("cpuacct", "cpuacct.usage_sys"),
+            cpuacct_usage_user: o("cpuacct", "cpuacct.usage_user"),
+            cpuacct_usage_percpu: o("cpuacct", "cpuacct.usage_percpu"),
+            cpuset_cpus: s("cpuset", "cpuset.cpus"),
+            cpuset_mems: s("cpuset", "cpuset.mems"),
+            memory_usage_in_bytes: o("memory", "memory.usage_in_bytes"),
+            memory_max_usage_in_bytes: o("memory", "memory.max_usage_in_bytes"),
+            memory_limit_in_bytes: s("memory", "memory.limit_in_bytes"),
+            memory_soft_limit_in_bytes: s("memory", "memory.soft_limit_in_bytes"),
+            memory_failcnt: o("memory", "memory.failcnt"),
+            memory_stat: o("memory", "memory.stat"),
+            memory_kmem_usage_in_bytes: o("memory", "memory.kmem.usage_in_bytes"),
+            memory_kmem_max_usage_in_bytes: o("memory", "memory.kmem.max_usage_in_bytes"),
+            memory_kmem_limit_in_bytes: s("memory", "memory.kmem.limit_in_bytes"),
+            memory_kmem_failcnt: o("memory", "memory.kmem.failcnt"),
+            memory_kmem_tcp_usage_in_bytes: o("memory", "memory.kmem.tcp.usage_in_bytes"),
+            memory_kmem_tcp_max_usage: o("memory", "memory.kmem.tcp.max_usage_in_bytes"),
+            memory_kmem_tcp_limit_in_bytes: s("memory", "memory.kmem.tcp.limit_in_bytes"),
+            memory_kmem_tcp_failcnt: o("memory", "memory.kmem.tcp.failcnt"),
+            hugetlb,
+            blkio_io_service_bytes: o("blkio", "blkio.io_service_bytes"),
+            blkio_io_serviced: o("blkio", "blkio.io_serviced"),
+            blkio_io_service_time: o("blkio", "blkio.io_service_time"),
+            blkio_io_queued: o("blkio", "blkio.io_queued"),
+            blkio_io_wait_time: o("blkio", "blkio.io_wait_time"),
+            blkio_io_merged: o("blkio", "blkio.io_merged"),
+            blkio_time: o("blkio", "blkio.time"),
+            blkio_sectors: o("blkio", "blkio.sectors"),
+            blkio_throttle_io_service_bytes: o("blkio", "blkio.throttle.io_service_bytes"),
+            blkio_throttle_io_serviced: o("blkio", "blkio.throttle.io_serviced"),
+            blkio_bfq_io_service_bytes: bfq_file("blkio.bfq.io_service_bytes"),
+            blkio_bfq_io_serviced: bfq_file("blkio.bfq.io_serviced"),
         }
     }
+
+    /// Gets all file handles, such as to close the settings files that are
+    /// held open when the file descriptor budget is under pressure
+    pub fn files_mut(&mut self) -> Vec<&mut CgroupFile> {
+        let mut files = vec![
+            &mut self.current_pids,
+            &mut self.max_pids,
+            &mut self.cpu_stat,
+            &mut self.cpuacct_stat,
+            &mut self.cpuacct_usage,
+            &mut self.cpuacct_usage_sys,
+            &mut self.cpuacct_usage_user,
+            &mut self.cpuacct_usage_percpu,
+            &mut self.cpuset_cpus,
+            &mut self.cpuset_mems,
+            &mut self.memory_usage_in_bytes,
+            &mut self.memory_max_usage_in_bytes,
+            &mut self.memory_limit_in_bytes,
+            &mut self.memory_soft_limit_in_bytes,
+            &mut self.memory_failcnt,
+            &mut self.memory_stat,
+            &mut self.memory_kmem_usage_in_bytes,
+            &mut self.memory_kmem_max_usage_in_bytes,
+            &mut self.memory_kmem_limit_in_bytes,
+            &mut self.memory_kmem_failcnt,
+            &mut self.memory_kmem_tcp_usage_in_bytes,
+            &mut self.memory_kmem_tcp_max_usage,
+            &mut self.memory_kmem_tcp_limit_in_bytes,
+            &mut self.memory_kmem_tcp_failcnt,
+            &mut self.blkio_io_service_bytes,
+            &mut self.blkio_io_serviced,
+            &mut self.blkio_io_service_time,
+            &mut self.blkio_io_queued,
+            &mut self.blkio_io_wait_time,
+            &mut self.blkio_io_merged,
+            &mut self.blkio_time,
+            &mut self.blkio_sectors,
+            &mut self.blkio_throttle_io_service_bytes,
+            &mut self.blkio_throttle_io_serviced,
+            &mut self.blkio_bfq_io_service_bytes,
+            &mut self.blkio_bfq_io_serviced,
+        ];
+        for sizes in &mut self.hugetlb {
+            files.extend([
+                &mut sizes.usage_in_bytes,
+                &mut sizes.max_usage_in_bytes,
+                &mut sizes.limit_in_bytes,
+                &mut sizes.failcnt,
+            ]);
+        }
+        files
+    }
+}
+
+/// Whether the cgroup exists in the hierarchy of any collected subsystem (or
+/// no subsystems are collected), since it doesn't exist yet while its
+/// target is still starting
+#[must_use]
+pub fn exists<C: AsRef<Path>>(cgroup: C, subsystems: Subsystems) -> bool {
+    let mut collected = Subsystem::VARIANTS
+        .iter()
+        .filter(|&&subsystem| subsystems.contains(subsystem))
+        .peekable();
+    collected.peek().is_none()
+        || collected.any(|&subsystem| path(&cgroup, subsystem.into(), "").is_dir())
 }
 
 /// Opens a stats file in /proc for the cgroup corresponding to the given
 /// relative cgroup in the given subsystem
 #[must_use]
-fn o<C: AsRef<Path>>(cgroup: C, subsystem: &str, file: &str) -> Option<File> {
-    let mut path: PathBuf = PathBuf::from(CGROUP_V1_ROOT);
+fn open<C: AsRef<Path>>(cgroup: C, subsystem: &str, file: &str, kind: FileKind) -> CgroupFile {
+    CgroupFile::open(path(cgroup, subsystem, file), kind)
+}
+
+/// Reads the current (trimmed) contents of a single-line settings file for
+/// the cgroup corresponding to the given relative cgroup in the given
+/// subsystem
+#[must_use]
+pub fn read_setting<C: AsRef<Path>>(cgroup: C, subsystem: &str, file: &str) -> Option<String> {
+    fs::read_to_string(path(cgroup, subsystem, file))
+        .ok()
+        .map(|content| content.trim().to_owned())
+}
+
+fn path<C: AsRef<Path>>(cgroup: C, subsystem: &str, file: &str) -> PathBuf {
+    let mut path: PathBuf = util::cgroup_root().to_path_buf();
     path.push(subsystem);
+    // An absolute cgroup path (such as a fixture directory used by `radvisor
+    // bench`) replaces the subsystem hierarchy, so all files are read from it
     path.push(cgroup);
     path.push(file);
-    File::open(path).ok()
+    path
+}
+
+/// Opens a hugetlb subsystem file for the given huge page size
+#[must_use]
+fn h<C: AsRef<Path>>(cgroup: C, size: &str, file: &str, kind: FileKind) -> CgroupFile {
+    open(
+        cgroup,
+        "hugetlb",
+        &format!("hugetlb.{}.{}", size, file),
+        kind,
+    )
 }
@@ -0,0 +1,43 @@
+use std::fs;
+
+/// Directory containing a subdirectory for each huge page size supported by
+/// the kernel, such as `hugepages-2048kB`
+const HUGEPAGES_ROOT: &str = "/sys/kernel/mm/hugepages";
+
+/// Reads the huge page sizes supported by the kernel, in the format used by
+/// the hugetlb subsystem files (such as `2MB` in
+/// `hugetlb.2MB.usage_in_bytes`), from smallest to largest. Since these are
+/// determined by the kernel (and not by whether the hugetlb subsystem is
+/// mounted), the set of columns is stable across cgroups on the same host.
+pub fn page_sizes() -> Vec<String> {
+    let mut sizes: Vec<u64> = match fs::read_dir(HUGEPAGES_ROOT) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_prefix("hugepages-")?
+                    .strip_suffix("kB")?
+                    .parse()
+                    .ok()
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    sizes.sort_unstable();
+    sizes.into_iter().map(format_size).collect()
+}
+
+/// Formats a page size (in KiB) the same way the kernel names the hugetlb
+/// subsystem files
+fn format_size(kib: u64) -> String {
+    const KIB_PER_MIB: u64 = 1024;
+    const KIB_PER_GIB: u64 = 1024 * 1024;
+    if kib >= KIB_PER_GIB {
+        format!("{}GB", kib / KIB_PER_GIB)
+    } else if kib >= KIB_PER_MIB {
+        format!("{}MB", kib / KIB_PER_MIB)
+    } else {
+        format!("{}KB", kib)
+    }
+}
@@ -1,9 +1,18 @@
+mod bfq;
 mod files;
+mod hugetlb;
 mod read;
+mod service_time;
+mod throttle;
+
+pub use bfq::BfqColumns;
+pub use service_time::ServiceTimeColumns;
 
 use crate::collection::buffers::WorkingBuffers;
-use crate::collection::collectors::{Collector as CollectorTrait, StatWriter};
-use crate::collection::perf_table::{Column, ColumnType, TableMetadata};
+use crate::collection::collectors::{Collector as CollectorTrait, CollectorOptions, Subsystem,
+                                    Subsystems};
+use crate::collection::fd_budget::CgroupFile;
+use crate::formats::{Column, ColumnDeclaration, ColumnKind, ColumnType, TableMetadata, Unit};
 use crate::util::{self, CgroupDriver, CgroupPath};
 use anyhow::Error;
 use csv::ByteRecord;
@@ -12,23 +21,52 @@ use read::StatFileLayout;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use std::path::PathBuf;
+use throttle::ThrottleLimit;
 
 /// Implements `crate::collection::collector::Collector`
 /// for cgroup v1-sourced data
 pub struct Collector {
-    cgroup:        CgroupPath,
-    file_handles:  Option<ProcFileHandles>,
-    memory_layout: Option<StatFileLayout>,
+    cgroup:          CgroupPath,
+    /// Boxed since the handles of all cgroup v1 files are large
+    file_handles:    Option<Box<ProcFileHandles>>,
+    memory_layout:   Option<StatFileLayout>,
+    /// Whether the `blkio.bfq.*` columns are collected
+    bfq:             bool,
+    /// Which columns break down the `blkio.service.time.*` totals
+    service_time:    ServiceTimeColumns,
+    subsystems:      Subsystems,
+    /// Whether records are skipped when every file read was empty
+    skip_empty_rows: bool,
+    header:          ByteRecord,
+    /// Unit and kind of each column of the header
+    units:           Vec<(Unit, ColumnKind)>,
 }
 
 impl Collector {
-    pub const fn new(cgroup: CgroupPath) -> Self {
+    #[must_use]
+    pub fn new(cgroup: CgroupPath, options: &CollectorOptions) -> Self {
+        let bfq = options.bfq && options.subsystems.contains(Subsystem::Blkio);
+        let (names, units): (Vec<_>, _) =
+            get_columns(bfq, options.service_time, options.subsystems)
+                .into_iter()
+                .map(|(name, unit, kind)| (name, (unit, kind)))
+                .unzip();
         Self {
             cgroup,
             file_handles: None,
             memory_layout: None,
+            bfq,
+            service_time: options.service_time,
+            subsystems: options.subsystems,
+            skip_empty_rows: options.skip_empty_rows,
+            header: ByteRecord::from(names),
+            units,
         }
     }
+
+    /// Gets the cgroup that statistics are collected from
+    #[must_use]
+    pub const fn cgroup(&self) -> &CgroupPath { &self.cgroup }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize)]
@@ -36,8 +74,39 @@ impl Collector {
 struct Metadata<'a> {
     cgroup:        &'a PathBuf,
     cgroup_driver: &'a CgroupDriver,
+    /// CPUs that the target could be scheduled on when the log file was
+    /// opened (from `cpuset.cpus`), in the kernel's list format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpuset_cpus:   Option<String>,
+    /// NUMA nodes that the target could allocate memory on when the log file
+    /// was opened (from `cpuset.mems`), in the kernel's list format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpuset_mems:   Option<String>,
+    /// Contents of each limit file (see `LIMIT_FILES`) when the log file was
+    /// opened, by file name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    limits:        BTreeMap<&'static str, String>,
+    /// Block I/O throttling limits configured for the cgroup when the log file
+    /// was opened (from `blkio.throttle.*_device`), by device
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    throttle:      BTreeMap<String, BTreeMap<ThrottleLimit, u64>>,
 }
 
+/// Files (and their subsystems) containing limits that are configured for the
+/// cgroup, which rarely change and so are only read when the log file is
+/// opened
+const LIMIT_FILES: &[(&str, &str)] = &[
+    ("memory", "memory.limit_in_bytes"),
+    ("memory", "memory.soft_limit_in_bytes"),
+    ("memory", "memory.memsw.limit_in_bytes"),
+    ("cpu", "cpu.cfs_quota_us"),
+    ("cpu", "cpu.cfs_period_us"),
+    ("cpu", "cpu.shares"),
+    ("pids", "pids.max"),
+    ("blkio", "blkio.weight"),
+    ("blkio", "blkio.bfq.weight"),
+];
+
 /// Original entries in the memory.stat file that map to columns (in the same
 /// order) in the final output
 const MEMORY_STAT_ENTRIES: &[&[u8]] = &[
@@ -64,6 +133,16 @@ impl CollectorTrait for Collector {
         let metadata = Metadata {
             cgroup:        &self.cgroup.path,
             cgroup_driver: &self.cgroup.driver,
+            cpuset_cpus:   files::read_setting(&self.cgroup.path, "cpuset", "cpuset.cpus"),
+            cpuset_mems:   files::read_setting(&self.cgroup.path, "cpuset", "cpuset.mems"),
+            limits:        LIMIT_FILES
+                .iter()
+                .filter_map(|&(subsystem, file)| {
+                    let limit = files::read_setting(&self.cgroup.path, subsystem, file)?;
+                    Some((file, limit))
+                })
+                .collect(),
+            throttle:      throttle::read_limits(&self.cgroup.path),
         };
 
         serde_yaml::to_value(&metadata).ok()
@@ -77,40 +156,69 @@ impl CollectorTrait for Collector {
         });
         // Include metadata on the cpu.usage.percpu column,
         // which is a vector column that contains a space-delimited entry per CPU
-        columns.insert(String::from("cpu.usage.percpu"), Column::Vector {
-            r#type: ColumnType::Int,
-            count:  util::remap::<_, usize>(util::num_cores()),
-        });
-        TableMetadata {
-            delimiter: ",",
+        if self.subsystems.contains(Subsystem::Cpu) {
+            columns.insert(String::from("cpu.usage.percpu"), Column::Vector {
+                r#type: ColumnType::Int,
+                count:  util::remap::<_, usize>(util::num_cores()),
+            });
+        }
+        // Include metadata on the cpuset columns, which contain lists of CPUs
+        // and memory nodes
+        if self.subsystems.contains(Subsystem::Cpuset) {
+            for column in &["cpuset.cpus", "cpuset.mems"] {
+                columns.insert(String::from(*column), Column::Scalar {
+                    r#type: ColumnType::List,
+                });
+            }
+        }
+        let mut table = TableMetadata {
+            delimiter: String::from(","),
             columns,
+            schema: Vec::new(),
+        };
+        for (name, &(unit, kind)) in self.header.iter().zip(&self.units) {
+            table.declare(&String::from_utf8_lossy(name), unit, kind);
         }
+        table
     }
 
     fn get_type(&self) -> &'static str { "cgroup_v1" }
 
     fn init(&mut self) -> Result<(), Error> {
+        if !files::exists(&self.cgroup.path, self.subsystems) {
+            return Err(Error::msg(format!(
+                "cgroup {} does not exist",
+                self.cgroup.path.display()
+            )));
+        }
+
         // Open file handles to all of the /proc files in the cgroupfs
-        let handles = ProcFileHandles::new(&self.cgroup.path);
+        let handles = ProcFileHandles::new(&self.cgroup.path, self.bfq, self.subsystems);
 
         // Examine the layout of the memory stat file
         let memory_layout = read::StatFileLayout::new(&handles.memory_stat, MEMORY_STAT_ENTRIES);
 
-        self.file_handles = Some(handles);
+        self.file_handles = Some(Box::new(handles));
         self.memory_layout = Some(memory_layout);
 
         Ok(())
     }
 
-    fn write_header(&mut self, writer: &mut StatWriter) -> Result<(), csv::Error> {
-        writer.write_byte_record(&HEADER)
+    fn header(&self) -> &ByteRecord { &self.header }
+
+    fn demote_files(&mut self) {
+        if let Some(handles) = self.file_handles.as_mut() {
+            handles.files_mut().into_iter().for_each(CgroupFile::demote);
+        }
+    }
+
+    fn promote_files(&mut self) -> bool {
+        self.file_handles.as_mut().map_or(true, |handles| {
+            handles.files_mut().into_iter().all(CgroupFile::promote)
+        })
     }
 
-    fn collect(
-        &mut self,
-        writer: &mut StatWriter,
-        working_buffers: &mut WorkingBuffers,
-    ) -> Result<(), csv::Error> {
+    fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> bool {
         let file_handles = self
             .file_handles
             .as_ref()
@@ -120,89 +228,185 @@ impl CollectorTrait for Collector {
             .as_ref()
             .expect("memory layout not yet initialized during collect()");
 
+        let subsystems = self.subsystems;
         collect_read(working_buffers);
-        collect_pids(working_buffers, file_handles);
-        collect_cpu(working_buffers, file_handles);
-        collect_memory(working_buffers, file_handles, memory_layout);
-        collect_blkio(working_buffers, file_handles);
-
-        writer.write_byte_record(&working_buffers.record)?;
-        working_buffers.record.clear();
+        let pids_result = match subsystems.contains(Subsystem::Pids) {
+            true => collect_pids(working_buffers.reading(Subsystem::Pids), file_handles),
+            false => Err(read::Empty),
+        };
+        let cpu_result = match subsystems.contains(Subsystem::Cpu) {
+            true => collect_cpu(working_buffers.reading(Subsystem::Cpu), file_handles),
+            false => Err(read::Empty),
+        };
+        let cpuset_result = match subsystems.contains(Subsystem::Cpuset) {
+            true => collect_cpuset(working_buffers.reading(Subsystem::Cpuset), file_handles),
+            false => Err(read::Empty),
+        };
+        let memory_result = match subsystems.contains(Subsystem::Memory) {
+            true => collect_memory(
+                working_buffers.reading(Subsystem::Memory),
+                file_handles,
+                memory_layout,
+            ),
+            false => Err(read::Empty),
+        };
+        let hugetlb_result = match subsystems.contains(Subsystem::Hugetlb) {
+            true => collect_hugetlb(working_buffers.reading(Subsystem::Hugetlb), file_handles),
+            false => Err(read::Empty),
+        };
+        let blkio_result = match subsystems.contains(Subsystem::Blkio) {
+            true => collect_blkio(
+                working_buffers.reading(Subsystem::Blkio),
+                file_handles,
+                self.bfq,
+                self.service_time,
+            ),
+            false => Err(read::Empty),
+        };
 
-        Ok(())
+        // If enabled, skip writing the byte record if all of the cgroup file
+        // reads were empty (or skipped), such as while the cgroup is being
+        // removed
+        !self.skip_empty_rows
+            || read::all(&[
+                pids_result,
+                cpu_result,
+                cpuset_result,
+                memory_result,
+                hugetlb_result,
+                blkio_result,
+            ])
+            .is_ok()
     }
 }
 
 lazy_static::lazy_static! {
-    /// Static CSV header for the stats collector
-    static ref HEADER: ByteRecord = ByteRecord::from(get_headers());
+    /// Huge page sizes supported by the kernel, which each have their own
+    /// hugetlb columns
+    static ref HUGE_PAGE_SIZES: Vec<String> = hugetlb::page_sizes();
+
+    /// Block devices on the system, which each have their own service time
+    /// column if the per-device columns are enabled
+    static ref BLOCK_DEVICES: Vec<service_time::BlockDevice> = service_time::block_devices();
 }
 
-/// Creates the headers for the logfiles
-fn get_headers() -> Vec<String> {
-    let mut headers = (vec![
-        "read",
-        "pids.current",
-        "pids.max",
-        "cpu.usage.total",
-        "cpu.usage.system",
-        "cpu.usage.user",
-        "cpu.usage.percpu",
-        "cpu.stat.user",
-        "cpu.stat.system",
-        "cpu.throttling.periods",
-        "cpu.throttling.throttled.count",
-        "cpu.throttling.throttled.time",
-        "memory.usage.current",
-        "memory.usage.max",
-        "memory.limit.hard",
-        "memory.limit.soft",
-        "memory.failcnt",
-        "memory.hierarchical_limit.memory",
-        "memory.hierarchical_limit.memoryswap",
-        "memory.cache",
-        "memory.rss.all",
-        "memory.rss.huge",
-        "memory.mapped",
-        "memory.swap",
-        "memory.paged.in",
-        "memory.paged.out",
-        "memory.fault.total",
-        "memory.fault.major",
-        "memory.anon.inactive",
-        "memory.anon.active",
-        "memory.file.inactive",
-        "memory.file.active",
-        "memory.unevictable",
-        "blkio.time",
-        "blkio.sectors",
-    ])
-    .into_iter()
-    .map(String::from)
-    .collect::<Vec<_>>();
-
-    // Add in the IO 4-part headers
-    append_io_headers(&mut headers, "blkio.service.bytes");
-    append_io_headers(&mut headers, "blkio.service.ios");
-    append_io_headers(&mut headers, "blkio.service.time");
-    append_io_headers(&mut headers, "blkio.queued");
-    append_io_headers(&mut headers, "blkio.wait");
-    append_io_headers(&mut headers, "blkio.merged");
-    append_io_headers(&mut headers, "blkio.throttle.service.bytes");
-    append_io_headers(&mut headers, "blkio.throttle.service.ios");
-    append_io_headers(&mut headers, "blkio.bfq.service.bytes");
-    append_io_headers(&mut headers, "blkio.bfq.service.ios");
-
-    headers
+/// Gets the declarations of the collector's columns (see
+/// `ColumnDeclaration`), including the columns of the given subsystems, in
+/// the order of its records
+fn get_columns(
+    bfq: bool,
+    service_time: ServiceTimeColumns,
+    subsystems: Subsystems,
+) -> Vec<(String, Unit, ColumnKind)> {
+    use ColumnKind::{Counter, Gauge, Limit, Setting, Timestamp};
+    use Unit::{Bytes, Count, Cpus, Milliseconds, Nanoseconds, Nodes, Pages, Sectors, Ticks};
+
+    let mut columns = vec![(String::from("read"), Nanoseconds, Timestamp)];
+    let mut push_all = |subsystem: Subsystem, declared: &[ColumnDeclaration]| {
+        if subsystems.contains(subsystem) {
+            columns.extend(
+                declared
+                    .iter()
+                    .map(|&(name, unit, kind)| (String::from(name), unit, kind)),
+            );
+        }
+    };
+    push_all(Subsystem::Pids, &[
+        ("pids.current", Count, Gauge),
+        ("pids.max", Count, Limit),
+    ]);
+    push_all(Subsystem::Cpu, &[
+        ("cpu.usage.total", Nanoseconds, Counter),
+        ("cpu.usage.system", Nanoseconds, Counter),
+        ("cpu.usage.user", Nanoseconds, Counter),
+        ("cpu.usage.percpu", Nanoseconds, Counter),
+        ("cpu.stat.user", Ticks, Counter),
+        ("cpu.stat.system", Ticks, Counter),
+        ("cpu.throttling.periods", Count, Counter),
+        ("cpu.throttling.throttled.count", Count, Counter),
+        ("cpu.throttling.throttled.time", Nanoseconds, Counter),
+    ]);
+    push_all(Subsystem::Cpuset, &[
+        ("cpuset.cpus", Cpus, Setting),
+        ("cpuset.mems", Nodes, Setting),
+    ]);
+    push_all(Subsystem::Memory, &[
+        ("memory.usage.current", Bytes, Gauge),
+        ("memory.usage.max", Bytes, Gauge),
+        ("memory.limit.hard", Bytes, Limit),
+        ("memory.limit.soft", Bytes, Limit),
+        ("memory.failcnt", Count, Counter),
+        ("memory.hierarchical_limit.memory", Bytes, Limit),
+        ("memory.hierarchical_limit.memoryswap", Bytes, Limit),
+        ("memory.cache", Bytes, Gauge),
+        ("memory.rss.all", Bytes, Gauge),
+        ("memory.rss.huge", Bytes, Gauge),
+        ("memory.mapped", Bytes, Gauge),
+        ("memory.swap", Bytes, Gauge),
+        ("memory.paged.in", Pages, Counter),
+        ("memory.paged.out", Pages, Counter),
+        ("memory.fault.total", Count, Counter),
+        ("memory.fault.major", Count, Counter),
+        ("memory.anon.inactive", Bytes, Gauge),
+        ("memory.anon.active", Bytes, Gauge),
+        ("memory.file.inactive", Bytes, Gauge),
+        ("memory.file.active", Bytes, Gauge),
+        ("memory.unevictable", Bytes, Gauge),
+        ("memory.kmem.usage.current", Bytes, Gauge),
+        ("memory.kmem.usage.max", Bytes, Gauge),
+        ("memory.kmem.limit.hard", Bytes, Limit),
+        ("memory.kmem.failcnt", Count, Counter),
+        ("memory.kmem.tcp.usage.current", Bytes, Gauge),
+        ("memory.kmem.tcp.usage.max", Bytes, Gauge),
+        ("memory.kmem.tcp.limit.hard", Bytes, Limit),
+        ("memory.kmem.tcp.failcnt", Count, Counter),
+    ]);
+
+    // Add in the hugetlb columns for each huge page size
+    if subsystems.contains(Subsystem::Hugetlb) {
+        for size in HUGE_PAGE_SIZES.iter() {
+            columns.push((format!("hugetlb.{}.usage.current", size), Bytes, Gauge));
+            columns.push((format!("hugetlb.{}.usage.max", size), Bytes, Gauge));
+            columns.push((format!("hugetlb.{}.limit.hard", size), Bytes, Limit));
+            columns.push((format!("hugetlb.{}.failcnt", size), Count, Counter));
+        }
+    }
+
+    if !subsystems.contains(Subsystem::Blkio) {
+        return columns;
+    }
+
+    columns.push((String::from("blkio.time"), Milliseconds, Counter));
+    columns.push((String::from("blkio.sectors"), Sectors, Counter));
+
+    // Add in the IO 4-part columns
+    append_io_columns(&mut columns, ("blkio.service.bytes", Bytes, Counter));
+    append_io_columns(&mut columns, ("blkio.service.ios", Count, Counter));
+    append_io_columns(&mut columns, ("blkio.service.time", Nanoseconds, Counter));
+    append_io_columns(&mut columns, ("blkio.queued", Count, Gauge));
+    append_io_columns(&mut columns, ("blkio.wait", Nanoseconds, Counter));
+    append_io_columns(&mut columns, ("blkio.merged", Count, Counter));
+    append_io_columns(
+        &mut columns,
+        ("blkio.throttle.service.bytes", Bytes, Counter),
+    );
+    append_io_columns(&mut columns, ("blkio.throttle.service.ios", Count, Counter));
+    if bfq {
+        append_io_columns(&mut columns, ("blkio.bfq.service.bytes", Bytes, Counter));
+        append_io_columns(&mut columns, ("blkio.bfq.service.ios", Count, Counter));
+    }
+    service_time::append_columns(&mut columns, service_time, &BLOCK_DEVICES);
+
+    columns
 }
 
-/// Expands a single I/O prefix to the 4 headers that will end up in the logfile
-/// (read, write, sync, async)
-pub fn append_io_headers(headers: &mut Vec<String>, base: &'static str) {
-    headers.push(base.to_owned() + ".read");
-    headers.push(base.to_owned() + ".write");
-    headers.push(base.to_owned() + ".sync");
-    headers.push(base.to_owned() + ".async");
+/// Expands a single I/O prefix to the 4 columns that will end up in the
+/// logfile (read, write, sync, async), which share its unit and kind
+pub fn append_io_columns(columns: &mut Vec<(String, Unit, ColumnKind)>, base: ColumnDeclaration) {
+    let (name, unit, kind) = base;
+    for operation in &["read", "write", "sync", "async"] {
+        columns.push((format!("{}.{}", name, operation), unit, kind));
+    }
 }
 
 /// Collects the nanosecond unix timestamp read time
@@ -217,9 +421,14 @@ fn collect_read(buffers: &mut WorkingBuffers) {
 /// Collects all stats for the pids subsystem
 /// see <https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v1/pids.html>
 #[inline]
-fn collect_pids(buffers: &mut WorkingBuffers, handles: &ProcFileHandles) {
-    read::entry(&handles.current_pids, buffers);
-    read::entry(&handles.max_pids, buffers);
+fn collect_pids(
+    buffers: &mut WorkingBuffers,
+    handles: &ProcFileHandles,
+) -> Result<(), read::Empty> {
+    read::all(&[
+        read::entry(&handles.current_pids, buffers),
+        read::entry(&handles.max_pids, buffers),
+    ])
 }
 
 /// String offsets used for row headers for the cpuacct.stat file
@@ -234,13 +443,29 @@ const CPU_STAT_OFFSETS: [usize; 3] = [
 /// Collects all stats for the cpu and cpuacct subsystems
 /// see <https://access.redhat.com/documentation/en-us/red_hat_enterprise_linux/6/html/resource_management_guide/sec-cpuacct>
 #[inline]
-fn collect_cpu(buffers: &mut WorkingBuffers, handles: &ProcFileHandles) {
-    read::entry(&handles.cpuacct_usage, buffers);
-    read::entry(&handles.cpuacct_usage_sys, buffers);
-    read::entry(&handles.cpuacct_usage_user, buffers);
-    read::entry(&handles.cpuacct_usage_percpu, buffers);
-    read::stat_file(&handles.cpuacct_stat, &CPUACCT_STAT_OFFSETS, buffers);
-    read::stat_file(&handles.cpu_stat, &CPU_STAT_OFFSETS, buffers);
+fn collect_cpu(buffers: &mut WorkingBuffers, handles: &ProcFileHandles) -> Result<(), read::Empty> {
+    read::all(&[
+        read::entry(&handles.cpuacct_usage, buffers),
+        read::entry(&handles.cpuacct_usage_sys, buffers),
+        read::entry(&handles.cpuacct_usage_user, buffers),
+        read::entry(&handles.cpuacct_usage_percpu, buffers),
+        read::stat_file(&handles.cpuacct_stat, &CPUACCT_STAT_OFFSETS, buffers),
+        read::stat_file(&handles.cpu_stat, &CPU_STAT_OFFSETS, buffers),
+    ])
+}
+
+/// Collects the allowed CPUs and memory nodes from the cpuset subsystem, as
+/// lists of ranges (such as `0-3,8`)
+/// see <https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v1/cpusets.html>
+#[inline]
+fn collect_cpuset(
+    buffers: &mut WorkingBuffers,
+    handles: &ProcFileHandles,
+) -> Result<(), read::Empty> {
+    read::all(&[
+        read::entry(&handles.cpuset_cpus, buffers),
+        read::entry(&handles.cpuset_mems, buffers),
+    ])
 }
 
 /// Collects all stats for the memory subsystem
@@ -250,29 +475,84 @@ fn collect_memory(
     buffers: &mut WorkingBuffers,
     handles: &ProcFileHandles,
     layout: &read::StatFileLayout,
-) {
-    read::entry(&handles.memory_usage_in_bytes, buffers);
-    read::entry(&handles.memory_max_usage_in_bytes, buffers);
-    read::entry(&handles.memory_limit_in_bytes, buffers);
-    read::entry(&handles.memory_soft_limit_in_bytes, buffers);
-    read::entry(&handles.memory_failcnt, buffers);
-    read::with_layout(&handles.memory_stat, layout, buffers);
+) -> Result<(), read::Empty> {
+    read::all(&[
+        read::entry(&handles.memory_usage_in_bytes, buffers),
+        read::entry(&handles.memory_max_usage_in_bytes, buffers),
+        read::entry(&handles.memory_limit_in_bytes, buffers),
+        read::entry(&handles.memory_soft_limit_in_bytes, buffers),
+        read::entry(&handles.memory_failcnt, buffers),
+        read::with_layout(&handles.memory_stat, layout, buffers),
+        // Kernel memory accounting can be compiled out or disabled at boot
+        // (cgroup.memory=nokmem), in which case the files are either missing
+        // or fail to read, and the fields are left empty
+        read::entry(&handles.memory_kmem_usage_in_bytes, buffers),
+        read::entry(&handles.memory_kmem_max_usage_in_bytes, buffers),
+        read::entry(&handles.memory_kmem_limit_in_bytes, buffers),
+        read::entry(&handles.memory_kmem_failcnt, buffers),
+        read::entry(&handles.memory_kmem_tcp_usage_in_bytes, buffers),
+        read::entry(&handles.memory_kmem_tcp_max_usage, buffers),
+        read::entry(&handles.memory_kmem_tcp_limit_in_bytes, buffers),
+        read::entry(&handles.memory_kmem_tcp_failcnt, buffers),
+    ])
+}
+
+/// Collects all stats for the hugetlb subsystem, for each huge page size
+/// see <https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v1/hugetlb.html>
+#[inline]
+fn collect_hugetlb(
+    buffers: &mut WorkingBuffers,
+    handles: &ProcFileHandles,
+) -> Result<(), read::Empty> {
+    let mut result = Err(read::Empty);
+    for size in &handles.hugetlb {
+        result = read::all(&[
+            result,
+            read::entry(&size.usage_in_bytes, buffers),
+            read::entry(&size.max_usage_in_bytes, buffers),
+            read::entry(&size.limit_in_bytes, buffers),
+            read::entry(&size.failcnt, buffers),
+        ]);
+    }
+    result
 }
 
 /// Collects all stats for the blkio subsystem
 /// see <https://www.kernel.org/doc/Documentation/cgroup-v1/blkio-controller.txt>
 #[inline]
-fn collect_blkio(buffers: &mut WorkingBuffers, handles: &ProcFileHandles) {
-    read::simple_io(&handles.blkio_time, buffers);
-    read::simple_io(&handles.blkio_sectors, buffers);
-    read::io(&handles.blkio_io_service_bytes, buffers);
-    read::io(&handles.blkio_io_serviced, buffers);
-    read::io(&handles.blkio_io_service_time, buffers);
-    read::io(&handles.blkio_io_queued, buffers);
-    read::io(&handles.blkio_io_wait_time, buffers);
-    read::io(&handles.blkio_io_merged, buffers);
-    read::io(&handles.blkio_throttle_io_service_bytes, buffers);
-    read::io(&handles.blkio_throttle_io_serviced, buffers);
-    read::io(&handles.blkio_bfq_io_service_bytes, buffers);
-    read::io(&handles.blkio_bfq_io_serviced, buffers);
+fn collect_blkio(
+    buffers: &mut WorkingBuffers,
+    handles: &ProcFileHandles,
+    bfq: bool,
+    service_time: ServiceTimeColumns,
+) -> Result<(), read::Empty> {
+    let mut result = read::all(&[
+        read::simple_io(&handles.blkio_time, buffers),
+        read::simple_io(&handles.blkio_sectors, buffers),
+        read::io(&handles.blkio_io_service_bytes, buffers),
+        read::io(&handles.blkio_io_serviced, buffers),
+        read::io(&handles.blkio_io_service_time, buffers),
+        read::io(&handles.blkio_io_queued, buffers),
+        read::io(&handles.blkio_io_wait_time, buffers),
+        read::io(&handles.blkio_io_merged, buffers),
+        read::io(&handles.blkio_throttle_io_service_bytes, buffers),
+        read::io(&handles.blkio_throttle_io_serviced, buffers),
+    ]);
+    if bfq {
+        result = read::all(&[
+            result,
+            read::io(&handles.blkio_bfq_io_service_bytes, buffers),
+            read::io(&handles.blkio_bfq_io_serviced, buffers),
+        ]);
+    }
+    // The service time columns are read from the same files as the totals,
+    // so they don't need to be checked separately
+    service_time::collect(
+        buffers,
+        &handles.blkio_io_service_time,
+        &handles.blkio_io_serviced,
+        service_time,
+        &BLOCK_DEVICES,
+    );
+    result
 }
@@ -1,18 +1,33 @@
 use crate::collection::buffers::WorkingBuffers;
+use crate::collection::fd_budget::CgroupFile;
 use crate::util::{self, AnonymousSlice, BufferLike, LazyQuantity};
-use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 
 const EMPTY_BUFFER: &[u8] = &[];
 
+/// Returned when every field that was written from a file is empty (such as
+/// when the file couldn't be read because the cgroup is being removed)
+#[derive(Copy, Clone, PartialEq)]
+pub struct Empty;
+
+/// Combines the results of reading several files, which is only empty if
+/// every file was
+pub fn all(results: &[Result<(), Empty>]) -> Result<(), Empty> {
+    match results.iter().any(Result::is_ok) {
+        true => Ok(()),
+        false => Err(Empty),
+    }
+}
+
 /// Tries to read the given file handle, and directly write the contents as a
-/// field to the record
-pub fn entry(file: &Option<File>, buffers: &mut WorkingBuffers) {
+/// field to the record. If the field was empty, returns Err(Empty).
+pub fn entry(file: &CgroupFile, buffers: &mut WorkingBuffers) -> Result<(), Empty> {
     // Ignore errors: the buffer will just remain empty
     read_to_buffer(file, buffers);
 
     let trimmed = buffers.buffer.trim();
-    if util::content_len_raw(trimmed) == 0 {
+    let is_empty = util::content_len_raw(trimmed) == 0;
+    if is_empty {
         // Buffer ended up empty; prevent writing NUL bytes
         buffers.record.push_field(EMPTY_BUFFER);
     } else {
@@ -20,13 +35,22 @@ pub fn entry(file: &Option<File>, buffers: &mut WorkingBuffers) {
     }
 
     buffers.buffer.clear();
+
+    match is_empty {
+        true => Err(Empty),
+        false => Ok(()),
+    }
 }
 
 /// Parses every entry in a stats file, where each entry is a alphabetic key
 /// followed by a number, and then a newline. Attempts to parse offsets.len()
 /// entries from the file, using the precomputed offsets array to skip reading
-/// the alphabetic key.
-pub fn stat_file(file: &Option<File>, offsets: &[usize], buffers: &mut WorkingBuffers) {
+/// the alphabetic key. If no entries were parsed, returns Err(Empty).
+pub fn stat_file(
+    file: &CgroupFile,
+    offsets: &[usize],
+    buffers: &mut WorkingBuffers,
+) -> Result<(), Empty> {
     // Track whether we should keep parsing or if we should fill in the entries with
     // empty buffers
     let successful = read_to_buffer(file, buffers).is_some();
@@ -60,6 +84,11 @@ pub fn stat_file(file: &Option<File>, offsets: &[usize], buffers: &mut WorkingBu
     }
 
     buffers.buffer.clear();
+
+    match success_count {
+        0 => Err(Empty),
+        _ => Ok(()),
+    }
 }
 
 /// Used to store the results of an initial examination of the layout of a
@@ -87,20 +116,17 @@ impl StatFileLayout {
     /// Examines the layout of a stat file, to determine on which lines
     /// predetermined entries exist for faster processing during collection
     #[must_use]
-    pub fn new(file: &Option<File>, entries: &[&[u8]]) -> Self {
+    pub fn new(file: &CgroupFile, entries: &[&[u8]]) -> Self {
         let mut buffer: Vec<u8> = Vec::new();
-        let read_successful = match file {
-            None => false,
-            Some(file) => {
-                let mut file_mut = file;
-                let result = file_mut.read_to_end(&mut buffer);
-                // Ignore errors: if seeking fails, then the effect next time will be pushing
-                // empty buffers to the CSV rows, which lets the other monitoring
-                // continue
-                let _result = file_mut.seek(SeekFrom::Start(0));
-                result.is_ok()
-            },
-        };
+        let read_result = file.with(|mut file_mut| {
+            let result = file_mut.read_to_end(&mut buffer);
+            // Ignore errors: if seeking fails, then the effect next time will be pushing
+            // empty buffers to the CSV rows, which lets the other monitoring
+            // continue
+            let _result = file_mut.seek(SeekFrom::Start(0));
+            result
+        });
+        let read_successful = matches!(read_result, Some(Ok(_)));
         if read_successful {
             let mut lines_to_entries: Vec<Option<StatFileLine>> = Vec::new();
             let lines = util::ByteLines::new(&buffer);
@@ -150,8 +176,13 @@ fn find_index(source: &[&[u8]], target: &[u8]) -> Option<usize> {
 }
 
 /// Reads and parses a stat file, using a pre-examined layout to quickly read
-/// the desired entries from the file.
-pub fn with_layout(file: &Option<File>, layout: &StatFileLayout, buffers: &mut WorkingBuffers) {
+/// the desired entries from the file. If the file couldn't be read, returns
+/// Err(Empty).
+pub fn with_layout(
+    file: &CgroupFile,
+    layout: &StatFileLayout,
+    buffers: &mut WorkingBuffers,
+) -> Result<(), Empty> {
     let successful = read_to_buffer(file, buffers).is_some();
     if successful {
         let lines = util::ByteLines::new(&buffers.buffer.b);
@@ -181,6 +212,11 @@ pub fn with_layout(file: &Option<File>, layout: &StatFileLayout, buffers: &mut W
 
     clear_slice_buffer(buffers);
     buffers.buffer.clear();
+
+    match successful {
+        true => Ok(()),
+        false => Err(Empty),
+    }
 }
 
 /// Clears the slice buffer, resetting all values to their default
@@ -194,27 +230,27 @@ fn clear_slice_buffer(buffers: &mut WorkingBuffers) {
 /// Attempts to read the given file into the buffer, if it exists. If
 /// successful, returns Some with the length of the part of the file read. If
 /// the file handle wasn't given, or reading was unsuccessful, returns a None
-fn read_to_buffer(file: &Option<File>, buffers: &mut WorkingBuffers) -> Option<usize> {
-    match file {
-        None => None,
-        Some(f) => {
-            let mut file_mut = f;
-            let result = match file_mut.read(&mut buffers.buffer.b) {
-                Err(_) => None,
-                Ok(len) => {
-                    buffers.buffer.len += len;
-                    if len == 0 {
-                        None
-                    } else {
-                        Some(len)
-                    }
-                },
-            };
-            // Ignore errors: if seeking fails, then the effect next time will be pushing
-            // empty buffers to the CSV rows, which lets the other monitoring
-            // continue
-            let _result = file_mut.seek(SeekFrom::Start(0));
-            result
+fn read_to_buffer(file: &CgroupFile, buffers: &mut WorkingBuffers) -> Option<usize> {
+    let result = file.with(|mut file_mut| {
+        let result = file_mut.read(&mut buffers.buffer.b);
+        // Ignore errors: if seeking fails, then the effect next time will be pushing
+        // empty buffers to the CSV rows, which lets the other monitoring
+        // continue
+        let _result = file_mut.seek(SeekFrom::Start(0));
+        result
+    })?;
+    match result {
+        Err(err) => {
+            buffers.record_read_error(&err);
+            None
+        },
+        Ok(len) => {
+            buffers.buffer.len += len;
+            if len == 0 {
+                None
+            } else {
+                Some(len)
+            }
         },
     }
 }
@@ -246,12 +282,14 @@ struct IoQuantities<'a> {
 /// 11:0 Total 1073152
 /// Total 5345280
 /// ```
-pub fn io(file: &Option<File>, buffers: &mut WorkingBuffers) {
+/// If the file was empty, returns Err(Empty).
+pub fn io(file: &CgroupFile, buffers: &mut WorkingBuffers) -> Result<(), Empty> {
     // Ignore errors: the buffer will just remain empty
     read_to_buffer(file, buffers);
 
     let trimmed = buffers.buffer.trim();
-    if util::content_len_raw(trimmed) == 0 {
+    let is_empty = util::content_len_raw(trimmed) == 0;
+    if is_empty {
         // Buffer ended up empty; prevent writing NUL bytes
         buffers.record.push_field(EMPTY_BUFFER);
         buffers.record.push_field(EMPTY_BUFFER);
@@ -263,6 +301,11 @@ pub fn io(file: &Option<File>, buffers: &mut WorkingBuffers) {
     }
 
     buffers.buffer.clear();
+
+    match is_empty {
+        true => Err(Empty),
+        false => Ok(()),
+    }
 }
 
 /// Scans each line in the buffer and aggregates the trailing numbers
@@ -301,6 +344,41 @@ fn aggregate_lines<'a>(buffers: &'a mut WorkingBuffers) {
     async_total.write_to_record(&mut buffers.copy_buffer, &mut buffers.record);
 }
 
+/// Tries to read an IO file (see `io`) and gets the `Total` entry of each of
+/// the given devices (by device number, such as `8:0`), which is 0 for devices
+/// that aren't in the file. If the file couldn't be read, returns None.
+pub fn device_totals(
+    file: &CgroupFile,
+    buffers: &mut WorkingBuffers,
+    devices: &[&str],
+) -> Option<Vec<u64>> {
+    // Ignore errors: the buffer will just remain empty
+    read_to_buffer(file, buffers);
+
+    let totals = match util::content_len_raw(buffers.buffer.trim()) {
+        0 => None,
+        _ => {
+            let mut totals = vec![0; devices.len()];
+            for (line, _) in util::ByteLines::new(&buffers.buffer.b) {
+                if let Some(space) = util::find_char(line, 0, util::is_space) {
+                    let device = &line[..space];
+                    let total = parse_category(&line[(space + 1)..], b"Total")
+                        .and_then(|number| std::str::from_utf8(util::trim_raw(number)).ok())
+                        .and_then(|number| number.parse::<u64>().ok());
+                    let index = devices.iter().position(|d| d.as_bytes() == device);
+                    if let (Some(total), Some(index)) = (total, index) {
+                        totals[index] = total;
+                    }
+                }
+            }
+            Some(totals)
+        },
+    };
+
+    buffers.buffer.clear();
+    totals
+}
+
 /// Determines if the slice starts with the given category prefix,
 /// and if it does, parses the number at the end of the slice
 fn parse_category<'a>(slice: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
@@ -329,12 +407,14 @@ fn parse_category<'a>(slice: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
 /// 8:0 213264
 /// 11:0 0
 /// ```
-pub fn simple_io(file: &Option<File>, buffers: &mut WorkingBuffers) {
+/// If the file was empty, returns Err(Empty).
+pub fn simple_io(file: &CgroupFile, buffers: &mut WorkingBuffers) -> Result<(), Empty> {
     // Ignore errors: the buffer will just remain empty
     read_to_buffer(file, buffers);
 
     let trimmed = buffers.buffer.trim();
-    if util::content_len_raw(trimmed) == 0 {
+    let is_empty = util::content_len_raw(trimmed) == 0;
+    if is_empty {
         // Buffer ended up empty; prevent writing NUL bytes
         buffers.record.push_field(EMPTY_BUFFER);
     } else {
@@ -343,6 +423,11 @@ pub fn simple_io(file: &Option<File>, buffers: &mut WorkingBuffers) {
     }
 
     buffers.buffer.clear();
+
+    match is_empty {
+        true => Err(Empty),
+        false => Ok(()),
+    }
 }
 
 /// Scans each line in the buffer and aggregates the trailing numbers
@@ -0,0 +1,181 @@
+use super::read;
+use crate::cli::ParseFailure;
+use crate::collection::buffers::WorkingBuffers;
+use crate::collection::fd_budget::CgroupFile;
+use crate::formats::{ColumnKind, Unit};
+use std::fs;
+use std::str::FromStr;
+
+/// Directory containing the block devices on the system
+const SYS_BLOCK_ROOT: &str = "/sys/block";
+
+/// Name prefixes of virtual block devices (loop devices and RAM disks), which
+/// are left out of the per-device columns
+const VIRTUAL_DEVICE_PREFIXES: &[&str] = &["loop", "ram", "zram"];
+
+/// Buckets of the service time histogram, by the name of their column suffix
+/// and their (inclusive) upper bound on the mean service time per I/O, in
+/// nanoseconds. The last bucket is unbounded.
+const BUCKETS: &[(&str, u64)] = &[
+    ("le_100us", 100_000),
+    ("le_1ms", 1_000_000),
+    ("le_10ms", 10_000_000),
+    ("le_100ms", 100_000_000),
+    ("gt_100ms", u64::MAX),
+];
+
+/// Which columns break down `blkio.io_service_time` (in addition to the
+/// `blkio.service.time.*` totals across all devices)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServiceTimeColumns {
+    /// Only the totals across all devices
+    Total,
+    /// The total service time of each block device, in
+    /// `blkio.service.time.device.<name>` columns
+    Devices,
+    /// The number of block devices whose mean service time per I/O falls in
+    /// each bucket, in `blkio.service.time.hist.<bucket>` columns
+    Histogram,
+}
+
+impl Default for ServiceTimeColumns {
+    fn default() -> Self { Self::Total }
+}
+
+impl FromStr for ServiceTimeColumns {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "total" => Ok(Self::Total),
+            "devices" => Ok(Self::Devices),
+            "histogram" => Ok(Self::Histogram),
+            _ => Err(ParseFailure::new(
+                String::from("service time columns"),
+                s.to_owned(),
+            )),
+        }
+    }
+}
+
+/// Block device that has its own service time column
+pub struct BlockDevice {
+    /// Name of the device, such as `sda`
+    pub name:   String,
+    /// Device number in the form used by the blkio files, such as `8:0`
+    pub number: String,
+}
+
+/// Reads the (non-virtual) block devices on the system, sorted by name. Since
+/// these are determined by the host (and not by the I/O of each cgroup), the
+/// set of columns is stable across cgroups on the same host.
+pub fn block_devices() -> Vec<BlockDevice> {
+    let mut devices: Vec<BlockDevice> = match fs::read_dir(SYS_BLOCK_ROOT) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                if VIRTUAL_DEVICE_PREFIXES.iter().any(|p| name.starts_with(p)) {
+                    return None;
+                }
+
+                let number = fs::read_to_string(entry.path().join("dev")).ok()?;
+                Some(BlockDevice {
+                    name,
+                    number: number.trim().to_owned(),
+                })
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    devices.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+/// Adds the declarations of the columns that break down the service time
+pub fn append_columns(
+    columns: &mut Vec<(String, Unit, ColumnKind)>,
+    mode: ServiceTimeColumns,
+    devices: &[BlockDevice],
+) {
+    match mode {
+        ServiceTimeColumns::Total => {},
+        ServiceTimeColumns::Devices => columns.extend(devices.iter().map(|device| {
+            let name = format!("blkio.service.time.device.{}", device.name);
+            (name, Unit::Nanoseconds, ColumnKind::Counter)
+        })),
+        ServiceTimeColumns::Histogram => columns.extend(BUCKETS.iter().map(|(bucket, _)| {
+            let name = format!("blkio.service.time.hist.{}", bucket);
+            (name, Unit::Count, ColumnKind::Gauge)
+        })),
+    }
+}
+
+/// Collects the columns that break down the service time, from the
+/// `blkio.io_service_time` file (and `blkio.io_serviced` for the histogram).
+/// If the files couldn't be read, the columns are left empty.
+pub fn collect(
+    buffers: &mut WorkingBuffers,
+    service_time: &CgroupFile,
+    serviced: &CgroupFile,
+    mode: ServiceTimeColumns,
+    devices: &[BlockDevice],
+) {
+    // Only gather the device numbers if needed, so that nothing is allocated
+    // when the columns aren't enabled
+    let numbers = || {
+        devices
+            .iter()
+            .map(|d| d.number.as_str())
+            .collect::<Vec<_>>()
+    };
+    match mode {
+        ServiceTimeColumns::Total => {},
+        ServiceTimeColumns::Devices => match read::device_totals(service_time, buffers, &numbers())
+        {
+            Some(times) => push_all(buffers, &times),
+            None => push_empty(buffers, devices.len()),
+        },
+        ServiceTimeColumns::Histogram => {
+            let numbers = numbers();
+            let times = read::device_totals(service_time, buffers, &numbers);
+            let ios = read::device_totals(serviced, buffers, &numbers);
+            match times.zip(ios) {
+                Some((times, ios)) => push_all(buffers, &histogram(&times, &ios)),
+                None => push_empty(buffers, BUCKETS.len()),
+            }
+        },
+    }
+}
+
+/// Counts the devices whose mean service time per I/O falls in each bucket,
+/// skipping devices that the cgroup hasn't issued any I/O to
+fn histogram(times: &[u64], ios: &[u64]) -> Vec<u64> {
+    let mut counts = vec![0; BUCKETS.len()];
+    for (&time, &count) in times.iter().zip(ios).filter(|(_, &count)| count > 0) {
+        let mean = time / count;
+        if let Some(bucket) = BUCKETS.iter().position(|&(_, bound)| mean <= bound) {
+            counts[bucket] += 1;
+        }
+    }
+
+    counts
+}
+
+/// Adds each of the values as a field to the record
+fn push_all(buffers: &mut WorkingBuffers, values: &[u64]) {
+    let mut itoa_buffer = itoa::Buffer::new();
+    for &value in values {
+        buffers
+            .record
+            .push_field(itoa_buffer.format(value).as_bytes());
+    }
+}
+
+/// Adds the given number of empty fields to the record
+fn push_empty(buffers: &mut WorkingBuffers, count: usize) {
+    for _ in 0..count {
+        buffers.record.push_field(&[]);
+    }
+}
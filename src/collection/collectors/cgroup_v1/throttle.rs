@@ -0,0 +1,54 @@
+use crate::collection::collectors::cgroup_v1::files;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Files in the blkio subsystem that configure the throttling policy of the
+/// cgroup, with the name of the limit that each one sets
+const THROTTLE_FILES: &[(&str, ThrottleLimit)] = &[
+    ("blkio.throttle.read_bps_device", ThrottleLimit::ReadBps),
+    ("blkio.throttle.write_bps_device", ThrottleLimit::WriteBps),
+    ("blkio.throttle.read_iops_device", ThrottleLimit::ReadIops),
+    ("blkio.throttle.write_iops_device", ThrottleLimit::WriteIops),
+];
+
+/// Single throttling limit that can be configured for a device
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub enum ThrottleLimit {
+    /// Maximum bytes read per second
+    ReadBps,
+    /// Maximum bytes written per second
+    WriteBps,
+    /// Maximum read operations per second
+    ReadIops,
+    /// Maximum write operations per second
+    WriteIops,
+}
+
+/// Reads the throttling limits configured for the cgroup, by device (such as
+/// `8:0`). Devices without any limits are left out, so this is empty if the
+/// cgroup isn't throttled (or if the throttling policy isn't enabled).
+pub fn read_limits(cgroup: &Path) -> BTreeMap<String, BTreeMap<ThrottleLimit, u64>> {
+    let mut limits: BTreeMap<String, BTreeMap<ThrottleLimit, u64>> = BTreeMap::new();
+    for &(file, limit) in THROTTLE_FILES {
+        let contents = files::read_setting(cgroup, "blkio", file).unwrap_or_default();
+        // Lines look like `8:0 1048576`, with one line per throttled device
+        for (device, value) in contents.lines().filter_map(parse_line) {
+            limits
+                .entry(device.to_owned())
+                .or_default()
+                .insert(limit, value);
+        }
+    }
+
+    limits
+}
+
+/// Parses a single line of a throttling file into its device and limit
+fn parse_line(line: &str) -> Option<(&str, u64)> {
+    let mut fields = line.split_whitespace();
+    let device = fields.next()?;
+    let value = fields.next()?.parse().ok()?;
+    Some((device, value))
+}
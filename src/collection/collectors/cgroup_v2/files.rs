@@ -1,45 +1,103 @@
-use std::fs::File;
+use crate::collection::collectors::{Subsystem, Subsystems};
+use crate::collection::fd_budget::{CgroupFile, FileKind};
+use crate::util;
+use std::fs;
 use std::path::{Path, PathBuf};
 
-const CGROUP_V2_ROOT: &str = "/sys/fs/cgroup";
-
 /// File handles re-used for each target that read into the /proc VFS
 pub struct ProcFileHandles {
-    pub pids_current:   Option<File>,
-    pub pids_max:       Option<File>,
-    pub cpu_stat:       Option<File>,
-    pub memory_current: Option<File>,
-    pub memory_high:    Option<File>,
-    pub memory_max:     Option<File>,
-    pub memory_stat:    Option<File>,
-    pub io_stat:        Option<File>,
+    pub pids_current:        CgroupFile,
+    pub pids_max:            CgroupFile,
+    pub cpu_stat:            CgroupFile,
+    pub cpu_max:             CgroupFile,
+    pub cpu_max_burst:       CgroupFile,
+    pub cpu_weight:          CgroupFile,
+    pub memory_current:      CgroupFile,
+    pub memory_high:         CgroupFile,
+    pub memory_max:          CgroupFile,
+    pub memory_stat:         CgroupFile,
+    pub memory_events:       CgroupFile,
+    pub memory_swap_current: CgroupFile,
+    pub memory_swap_max:     CgroupFile,
+    pub io_stat:             CgroupFile,
 }
 
 impl ProcFileHandles {
     /// Initializes all file handles to /proc files, utilizing them over the
-    /// entire timeline of the target monitoring. If a handle fails to
-    /// open, the struct field will be None
+    /// entire timeline of the target monitoring (unless the file descriptor
+    /// budget is under pressure, see `fd_budget`). If a handle fails to
+    /// open (or its controller isn't collected), the struct field will be
+    /// missing
     #[must_use]
-    pub fn new<C: AsRef<Path>>(cgroup: C) -> Self {
+    pub fn new<C: AsRef<Path>>(cgroup: C, subsystems: Subsystems) -> Self {
+        let open_kind = |subsystem: Subsystem, file: &str, kind: FileKind| match subsystems
+            .contains(subsystem)
+        {
+            true => open(&cgroup, file, kind),
+            false => CgroupFile::Missing,
+        };
+        let o = |subsystem: Subsystem, file: &str| open_kind(subsystem, file, FileKind::Statistic);
+        let s = |subsystem: Subsystem, file: &str| open_kind(subsystem, file, FileKind::Setting);
+
         Self {
-            pids_current:   o(&cgroup, "pids.current"),
-            pids_max:       o(&cgroup, "pids.max"),
-            cpu_stat:       o(&cgroup, "cpu.stat"),
-            memory_current: o(&cgroup, "memory.current"),
-            memory_high:    o(&cgroup, "memory.high"),
-            memory_max:     o(&cgroup, "memory.max"),
-            memory_stat:    o(&cgroup, "memory.stat"),
-            io_stat:        o(&cgroup, "io.stat"),
+            pids_current:        o(Subsystem::Pids, "pids.current"),
+            pids_max:            s(Subsystem::Pids, "pids.max"),
+            cpu_stat:            o(Subsystem::Cpu, "cpu.stat"),
+            cpu_max:             s(Subsystem::Cpu, "cpu.max"),
+            cpu_max_burst:       s(Subsystem::Cpu, "cpu.max.burst"),
+            cpu_weight:          s(Subsystem::Cpu, "cpu.weight"),
+            memory_current:      o(Subsystem::Memory, "memory.current"),
+            memory_high:         s(Subsystem::Memory, "memory.high"),
+            memory_max:          s(Subsystem::Memory, "memory.max"),
+            memory_stat:         o(Subsystem::Memory, "memory.stat"),
+            memory_events:       o(Subsystem::Memory, "memory.events"),
+            memory_swap_current: o(Subsystem::Memory, "memory.swap.current"),
+            memory_swap_max:     s(Subsystem::Memory, "memory.swap.max"),
+            io_stat:             o(Subsystem::Blkio, "io.stat"),
         }
     }
+
+    /// Gets all file handles, such as to close the settings files that are
+    /// held open when the file descriptor budget is under pressure
+    pub fn files_mut(&mut self) -> Vec<&mut CgroupFile> {
+        vec![
+            &mut self.pids_current,
+            &mut self.pids_max,
+            &mut self.cpu_stat,
+            &mut self.cpu_max,
+            &mut self.cpu_max_burst,
+            &mut self.cpu_weight,
+            &mut self.memory_current,
+            &mut self.memory_high,
+            &mut self.memory_max,
+            &mut self.memory_stat,
+            &mut self.memory_events,
+            &mut self.memory_swap_current,
+            &mut self.memory_swap_max,
+            &mut self.io_stat,
+        ]
+    }
 }
 
 /// Opens a stats file in /proc for the cgroup corresponding to the given
 /// relative cgroup
 #[must_use]
-fn o<C: AsRef<Path>>(cgroup: C, file: &str) -> Option<File> {
-    let mut path: PathBuf = PathBuf::from(CGROUP_V2_ROOT);
+fn open<C: AsRef<Path>>(cgroup: C, file: &str, kind: FileKind) -> CgroupFile {
+    CgroupFile::open(path(cgroup, file), kind)
+}
+
+/// Reads the current (trimmed) contents of a single-line settings file for
+/// the cgroup corresponding to the given relative cgroup
+#[must_use]
+pub fn read_setting<C: AsRef<Path>>(cgroup: C, file: &str) -> Option<String> {
+    fs::read_to_string(path(cgroup, file))
+        .ok()
+        .map(|content| content.trim().to_owned())
+}
+
+fn path<C: AsRef<Path>>(cgroup: C, file: &str) -> PathBuf {
+    let mut path: PathBuf = util::cgroup_root().to_path_buf();
     path.push(cgroup);
     path.push(file);
-    File::open(path).ok()
+    path
 }
@@ -1,13 +1,17 @@
 mod files;
+mod percpu;
 mod read;
 
 use crate::collection::buffers::WorkingBuffers;
-use crate::collection::collectors::{Collector as CollectorTrait, StatWriter};
-use crate::collection::perf_table::{Column, ColumnType, TableMetadata};
+use crate::collection::collectors::{Collector as CollectorTrait, CollectorOptions, Subsystem,
+                                    Subsystems};
+use crate::collection::fd_budget::CgroupFile;
+use crate::formats::{Column, ColumnKind, ColumnType, TableMetadata, Unit};
 use crate::util::{self, CgroupDriver, CgroupPath};
 use anyhow::Error;
 use csv::ByteRecord;
 use files::ProcFileHandles;
+use percpu::PerCpuUsage;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use std::path::PathBuf;
@@ -15,17 +19,59 @@ use std::path::PathBuf;
 /// Implements `crate::collection::collector::Collector`
 /// for cgroup v2-sourced data
 pub struct Collector {
-    cgroup:       CgroupPath,
-    file_handles: Option<ProcFileHandles>,
+    cgroup:        CgroupPath,
+    /// Boxed to keep the collector small, like the cgroup v1 one
+    file_handles:  Option<Box<ProcFileHandles>>,
+    subsystems:    Subsystems,
+    cpu_stat_keys: read::FlatKeys,
+    memory_stat:   read::FlatKeys,
+    memory_events: read::FlatKeys,
+    /// Whether per-CPU usage is collected (see `percpu`)
+    percpu:        bool,
+    /// Per-CPU usage counters of the cgroup, once initialized
+    percpu_usage:  Option<PerCpuUsage>,
+    header:        ByteRecord,
+    /// Unit and kind of each column of the header
+    units:         Vec<(Unit, ColumnKind)>,
 }
 
 impl Collector {
-    pub const fn new(cgroup: CgroupPath) -> Self {
+    #[must_use]
+    pub fn new(cgroup: CgroupPath, options: CollectorOptions) -> Self {
+        let cpu_stat_keys = match options.cpu_stat_keys {
+            Some(keys) => keys.to_vec(),
+            None => DEFAULT_CPU_STAT_KEYS
+                .iter()
+                .map(|&key| key.to_owned())
+                .collect(),
+        };
+        let percpu = options.percpu && options.subsystems.contains(Subsystem::Cpu);
+        let (names, units): (Vec<_>, _) = get_columns(options.subsystems, &cpu_stat_keys, percpu)
+            .into_iter()
+            .map(|(name, unit, kind)| (name, (unit, kind)))
+            .unzip();
         Self {
             cgroup,
             file_handles: None,
+            subsystems: options.subsystems,
+            header: ByteRecord::from(names),
+            units,
+            cpu_stat_keys: read::FlatKeys::new(&cpu_stat_keys),
+            memory_stat: read::FlatKeys::new(
+                &MEMORY_STAT_KEYS
+                    .iter()
+                    .map(|&(key, ..)| key)
+                    .collect::<Vec<_>>(),
+            ),
+            memory_events: read::FlatKeys::new(&MEMORY_EVENTS_KEYS),
+            percpu,
+            percpu_usage: None,
         }
     }
+
+    /// Gets the cgroup that statistics are collected from
+    #[must_use]
+    pub const fn cgroup(&self) -> &CgroupPath { &self.cgroup }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize)]
@@ -33,13 +79,48 @@ impl Collector {
 struct Metadata<'a> {
     cgroup:        &'a PathBuf,
     cgroup_driver: &'a CgroupDriver,
+    /// CPUs that the target could be scheduled on when the log file was
+    /// opened (from `cpuset.cpus.effective`), in the kernel's list format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpuset_cpus:   Option<String>,
+    /// NUMA nodes that the target could allocate memory on when the log file
+    /// was opened (from `cpuset.mems.effective`), in the kernel's list format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpuset_mems:   Option<String>,
+    /// Contents of each limit file (see `LIMIT_FILES`) when the log file was
+    /// opened, by file name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    limits:        BTreeMap<&'static str, String>,
 }
 
+/// Files containing limits that are configured for the cgroup, which rarely
+/// change and so are only read when the log file is opened
+const LIMIT_FILES: &[&str] = &[
+    "memory.min",
+    "memory.low",
+    "memory.high",
+    "memory.max",
+    "memory.swap.max",
+    "cpu.max",
+    "cpu.max.burst",
+    "cpu.weight",
+    "pids.max",
+    "io.weight",
+    "io.bfq.weight",
+    "io.max",
+];
+
 impl CollectorTrait for Collector {
     fn metadata(&mut self) -> Option<serde_yaml::Value> {
         let metadata = Metadata {
             cgroup:        &self.cgroup.path,
             cgroup_driver: &self.cgroup.driver,
+            cpuset_cpus:   files::read_setting(&self.cgroup.path, "cpuset.cpus.effective"),
+            cpuset_mems:   files::read_setting(&self.cgroup.path, "cpuset.mems.effective"),
+            limits:        LIMIT_FILES
+                .iter()
+                .filter_map(|&file| Some((file, files::read_setting(&self.cgroup.path, file)?)))
+                .collect(),
         };
 
         serde_yaml::to_value(&metadata).ok()
@@ -51,102 +132,166 @@ impl CollectorTrait for Collector {
         columns.insert(String::from("read"), Column::Scalar {
             r#type: ColumnType::Epoch19,
         });
-        TableMetadata {
-            delimiter: ",",
+        // Include metadata on the cpu.usage.percpu column, which (as in cgroup
+        // v1) is a vector column that contains a space-delimited entry per CPU
+        if self.percpu {
+            columns.insert(String::from("cpu.usage.percpu"), Column::Vector {
+                r#type: ColumnType::Int,
+                count:  util::remap::<_, usize>(util::num_cores()),
+            });
+        }
+        let mut table = TableMetadata {
+            delimiter: String::from(","),
             columns,
+            schema: Vec::new(),
+        };
+        for (name, &(unit, kind)) in self.header.iter().zip(&self.units) {
+            table.declare(&String::from_utf8_lossy(name), unit, kind);
         }
+        table
     }
 
     fn get_type(&self) -> &'static str { "cgroup_v2" }
 
     fn init(&mut self) -> Result<(), Error> {
+        if !util::cgroup_root().join(&self.cgroup.path).is_dir() {
+            return Err(Error::msg(format!(
+                "cgroup {} does not exist",
+                self.cgroup.path.display()
+            )));
+        }
+
         // Open file handles to all of the /proc files in the cgroupfs
-        let handles = ProcFileHandles::new(&self.cgroup.path);
-        self.file_handles = Some(handles);
+        let handles = ProcFileHandles::new(&self.cgroup.path, self.subsystems);
+        self.file_handles = Some(Box::new(handles));
+        if self.percpu {
+            self.percpu_usage = Some(PerCpuUsage::new(&self.cgroup.path));
+        }
         Ok(())
     }
 
-    fn write_header(&mut self, writer: &mut StatWriter) -> Result<(), csv::Error> {
-        writer.write_byte_record(&HEADER)
+    fn header(&self) -> &ByteRecord { &self.header }
+
+    fn demote_files(&mut self) {
+        if let Some(handles) = self.file_handles.as_mut() {
+            handles.files_mut().into_iter().for_each(CgroupFile::demote);
+        }
+    }
+
+    fn promote_files(&mut self) -> bool {
+        self.file_handles.as_mut().map_or(true, |handles| {
+            handles.files_mut().into_iter().all(CgroupFile::promote)
+        })
     }
 
-    fn collect(
-        &mut self,
-        writer: &mut StatWriter,
-        working_buffers: &mut WorkingBuffers,
-    ) -> Result<(), csv::Error> {
+    fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> bool {
         let file_handles = self
             .file_handles
             .as_ref()
             .expect("file handles not yet initialized during collect()");
 
+        let subsystems = self.subsystems;
         collect_read(working_buffers);
-        let pids_result = collect_pids(working_buffers, file_handles);
-        let cpu_result = collect_cpu(working_buffers, file_handles);
-        let memory_result = collect_memory(working_buffers, file_handles);
-        let io_result = collect_io(working_buffers, file_handles);
+        let pids_result = match subsystems.contains(Subsystem::Pids) {
+            true => collect_pids(working_buffers.reading(Subsystem::Pids), file_handles),
+            false => Err(read::Empty),
+        };
+        let cpu_result = match subsystems.contains(Subsystem::Cpu) {
+            true => collect_cpu(
+                working_buffers.reading(Subsystem::Cpu),
+                file_handles,
+                &mut self.cpu_stat_keys,
+                self.percpu_usage.as_mut(),
+            ),
+            false => Err(read::Empty),
+        };
+        let memory_result = match subsystems.contains(Subsystem::Memory) {
+            true => collect_memory(
+                working_buffers.reading(Subsystem::Memory),
+                file_handles,
+                &mut self.memory_stat,
+                &mut self.memory_events,
+            ),
+            false => Err(read::Empty),
+        };
+        let io_result = match subsystems.contains(Subsystem::Blkio) {
+            true => collect_io(working_buffers.reading(Subsystem::Blkio), file_handles),
+            false => Err(read::Empty),
+        };
 
-        // If all of the cgroup file reads were empty,
+        // If all of the cgroup file reads were empty (or skipped),
         // skip writing the byte record.
-        if pids_result == Err(read::Empty)
+        !(pids_result == Err(read::Empty)
             && cpu_result == Err(read::Empty)
             && memory_result == Err(read::Empty)
-            && io_result == Err(read::Empty)
-        {
-            // Discard the working record
-            working_buffers.record.clear();
-        } else {
-            let result = writer.write_byte_record(&working_buffers.record);
-            working_buffers.record.clear();
-            result?;
-        }
-
-        Ok(())
+            && io_result == Err(read::Empty))
     }
 }
 
-lazy_static::lazy_static! {
-    /// Static CSV header for the stats collector
-    static ref HEADER: ByteRecord = ByteRecord::from(get_headers());
-}
+/// Gets the declarations of the collector's columns (see
+/// `ColumnDeclaration`), including the columns of the given controllers (and
+/// the given keys of the cpu.stat file, along with the per-CPU usage column if
+/// it is collected), in the order of its records
+fn get_columns(
+    subsystems: Subsystems,
+    cpu_stat_keys: &[String],
+    percpu: bool,
+) -> Vec<(String, Unit, ColumnKind)> {
+    use ColumnKind::{Counter, Gauge, Limit, Setting, Timestamp};
+    use Unit::{Bytes, Count, Microseconds, Nanoseconds, Unitless};
 
-/// Creates the headers for the logfiles
-#[allow(clippy::vec_init_then_push)]
-fn get_headers() -> Vec<String> {
-    let mut headers: Vec<String> = vec![];
-    // Add read headers
-    headers.push("read".into());
-    // Add pids headers
-    headers.push("pids.current".into());
-    headers.push("pids.max".into());
-    // Add cpu headers
-    for cpu_stat_key in CPU_STAT_KEYS {
-        headers.push(format!(
-            "cpu.stat/{}",
-            String::from_utf8(cpu_stat_key.to_vec()).unwrap()
-        ));
+    let mut columns = vec![(String::from("read"), Nanoseconds, Timestamp)];
+    let mut push = |name: &str, unit: Unit, kind: ColumnKind| {
+        columns.push((String::from(name), unit, kind));
+    };
+    if subsystems.contains(Subsystem::Pids) {
+        push("pids.current", Count, Gauge);
+        push("pids.max", Count, Limit);
     }
-    // Add memory headers
-    headers.push("memory.current".into());
-    headers.push("memory.high".into());
-    headers.push("memory.max".into());
-    for memory_stat_key in MEMORY_STAT_KEYS {
-        headers.push(format!(
-            "memory.stat/{}",
-            String::from_utf8(memory_stat_key.to_vec()).unwrap()
-        ));
+    if subsystems.contains(Subsystem::Cpu) {
+        for cpu_stat_key in cpu_stat_keys {
+            // The keys in cpu.stat are either durations (suffixed by their
+            // unit) or numbers of events
+            let unit = match cpu_stat_key.ends_with("_usec") {
+                true => Microseconds,
+                false => Count,
+            };
+            push(&format!("cpu.stat/{}", cpu_stat_key), unit, Counter);
+        }
+        push("cpu.max/quota", Microseconds, Limit);
+        push("cpu.max/period", Microseconds, Setting);
+        push("cpu.max.burst", Microseconds, Limit);
+        push("cpu.weight", Unitless, Setting);
+        if percpu {
+            push("cpu.usage.percpu", Nanoseconds, Counter);
+        }
+    }
+    if subsystems.contains(Subsystem::Memory) {
+        push("memory.current", Bytes, Gauge);
+        push("memory.high", Bytes, Limit);
+        push("memory.max", Bytes, Limit);
+        for (key, unit, kind) in MEMORY_STAT_KEYS {
+            push(&format!("memory.stat/{}", key_name(key)), unit, kind);
+        }
+        for key in MEMORY_EVENTS_KEYS {
+            push(&format!("memory.events/{}", key_name(key)), Count, Counter);
+        }
+        push("memory.swap.current", Bytes, Gauge);
+        push("memory.swap.max", Bytes, Limit);
     }
-    // Add io headers
-    for io_stat_key in IO_STAT_KEYS {
-        headers.push(format!(
-            "io.stat/{}",
-            String::from_utf8(io_stat_key.to_vec()).unwrap()
-        ));
+    if subsystems.contains(Subsystem::Blkio) {
+        for (key, &unit) in IO_STAT_KEYS.iter().zip(&IO_STAT_UNITS) {
+            push(&format!("io.stat/{}", key_name(key)), unit, Counter);
+        }
     }
 
-    headers
+    columns
 }
 
+/// Gets the name of a key read from a cgroup file, which the columns of its
+/// values are named after
+fn key_name(key: &[u8]) -> &str { std::str::from_utf8(key).unwrap() }
+
 /// Collects the nanosecond unix timestamp read time
 #[inline]
 fn collect_read(buffers: &mut WorkingBuffers) {
@@ -172,51 +317,81 @@ fn collect_pids(
     }
 }
 
-/// Keys to read from the cpu.stat file
-const CPU_STAT_KEYS: [&[u8]; 6] = [
-    b"usage_usec",
-    b"system_usec",
-    b"user_usec",
-    b"nr_periods",
-    b"nr_throttled",
-    b"throttled_usec",
+/// Keys read from the cpu.stat file unless others are configured using
+/// `--cpu-stat-keys`. The burst fields only exist on Linux 5.14 and later, so
+/// they are left empty on older kernels (as are any other missing keys).
+const DEFAULT_CPU_STAT_KEYS: &[&str] = &[
+    "usage_usec",
+    "system_usec",
+    "user_usec",
+    "nr_periods",
+    "nr_throttled",
+    "throttled_usec",
+    "nr_bursts",
+    "burst_usec",
 ];
-const CPU_STAT_DEFAULTS: [&[u8]; 6] = [b"0"; 6];
+
+/// Defaults for the quota and period in the cpu.max file, which are used when
+/// the cpu controller isn't enabled for the cgroup (leaving it unlimited)
+const CPU_MAX_DEFAULTS: [&[u8]; 2] = [b"max", b"100000"];
 
 /// Collects all stats for the cpu controller
 /// see <https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html#cpu>
 #[inline]
-fn collect_cpu(buffers: &mut WorkingBuffers, handles: &ProcFileHandles) -> Result<(), read::Empty> {
-    read::flat_keyed_file(
-        &handles.cpu_stat,
-        buffers,
-        &CPU_STAT_KEYS,
-        &CPU_STAT_DEFAULTS,
-    )
+fn collect_cpu(
+    buffers: &mut WorkingBuffers,
+    handles: &ProcFileHandles,
+    cpu_stat_keys: &mut read::FlatKeys,
+    percpu_usage: Option<&mut PerCpuUsage>,
+) -> Result<(), read::Empty> {
+    let cpu_stat = read::flat_keyed_file(&handles.cpu_stat, buffers, cpu_stat_keys);
+    // The quota is `max` if the cgroup is unlimited
+    let cpu_max = read::space_separated_file(&handles.cpu_max, buffers, &CPU_MAX_DEFAULTS);
+    // cpu.max.burst only exists on Linux 5.14 and later, so it is left empty
+    // on older kernels
+    let cpu_max_burst = read::single_value_file(&handles.cpu_max_burst, buffers, b"");
+    let cpu_weight = read::single_value_file(&handles.cpu_weight, buffers, b"100");
+    let percpu = match percpu_usage {
+        Some(percpu_usage) => percpu_usage.push_field(&mut buffers.record),
+        None => Err(read::Empty),
+    };
+    if cpu_stat == Err(read::Empty)
+        && cpu_max == Err(read::Empty)
+        && cpu_max_burst == Err(read::Empty)
+        && cpu_weight == Err(read::Empty)
+        && percpu == Err(read::Empty)
+    {
+        Err(read::Empty)
+    } else {
+        Ok(())
+    }
 }
 
-/// Keys to read from the memory.stat file
-const MEMORY_STAT_KEYS: [&[u8]; 18] = [
-    b"anon",
-    b"file",
-    b"kernel_stack",
-    b"pagetables",
-    b"percpu",
-    b"sock",
-    b"shmem",
-    b"file_mapped",
-    b"file_dirty",
-    b"file_writeback",
-    b"swapcached",
-    b"inactive_anon",
-    b"active_anon",
-    b"inactive_file",
-    b"active_file",
-    b"unevictable",
-    b"pgfault",
-    b"pgmajfault",
+/// Keys to read from the memory.stat file, along with the unit and kind of
+/// their values
+const MEMORY_STAT_KEYS: [(&[u8], Unit, ColumnKind); 18] = [
+    (b"anon", Unit::Bytes, ColumnKind::Gauge),
+    (b"file", Unit::Bytes, ColumnKind::Gauge),
+    (b"kernel_stack", Unit::Bytes, ColumnKind::Gauge),
+    (b"pagetables", Unit::Bytes, ColumnKind::Gauge),
+    (b"percpu", Unit::Bytes, ColumnKind::Gauge),
+    (b"sock", Unit::Bytes, ColumnKind::Gauge),
+    (b"shmem", Unit::Bytes, ColumnKind::Gauge),
+    (b"file_mapped", Unit::Bytes, ColumnKind::Gauge),
+    (b"file_dirty", Unit::Bytes, ColumnKind::Gauge),
+    (b"file_writeback", Unit::Bytes, ColumnKind::Gauge),
+    (b"swapcached", Unit::Bytes, ColumnKind::Gauge),
+    (b"inactive_anon", Unit::Bytes, ColumnKind::Gauge),
+    (b"active_anon", Unit::Bytes, ColumnKind::Gauge),
+    (b"inactive_file", Unit::Bytes, ColumnKind::Gauge),
+    (b"active_file", Unit::Bytes, ColumnKind::Gauge),
+    (b"unevictable", Unit::Bytes, ColumnKind::Gauge),
+    (b"pgfault", Unit::Count, ColumnKind::Counter),
+    (b"pgmajfault", Unit::Count, ColumnKind::Counter),
 ];
-const MEMORY_STAT_DEFAULTS: [&[u8]; 18] = [b"0"; 18];
+
+/// Keys to read from the memory.events file
+const MEMORY_EVENTS_KEYS: [&[u8]; 5] = [b"low", b"high", b"max", b"oom", b"oom_kill"];
 
 /// Collects all stats for the memory controller
 /// see <https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html#memory>
@@ -224,20 +399,25 @@ const MEMORY_STAT_DEFAULTS: [&[u8]; 18] = [b"0"; 18];
 fn collect_memory(
     buffers: &mut WorkingBuffers,
     handles: &ProcFileHandles,
+    stat_keys: &mut read::FlatKeys,
+    events_keys: &mut read::FlatKeys,
 ) -> Result<(), read::Empty> {
     let mem_current = read::single_value_file(&handles.memory_current, buffers, b"0");
     let mem_high = read::single_value_file(&handles.memory_high, buffers, b"max");
     let mem_max = read::single_value_file(&handles.memory_max, buffers, b"max");
-    let mem_stat = read::flat_keyed_file(
-        &handles.memory_stat,
-        buffers,
-        &MEMORY_STAT_KEYS,
-        &MEMORY_STAT_DEFAULTS,
-    );
+    let mem_stat = read::flat_keyed_file(&handles.memory_stat, buffers, stat_keys);
+    let mem_events = read::flat_keyed_file(&handles.memory_events, buffers, events_keys);
+    // The swap files are missing if swap accounting is disabled, in which
+    // case the defaults are written
+    let swap_current = read::single_value_file(&handles.memory_swap_current, buffers, b"0");
+    let swap_max = read::single_value_file(&handles.memory_swap_max, buffers, b"max");
     if mem_current == Err(read::Empty)
         && mem_high == Err(read::Empty)
         && mem_max == Err(read::Empty)
         && mem_stat == Err(read::Empty)
+        && mem_events == Err(read::Empty)
+        && swap_current == Err(read::Empty)
+        && swap_max == Err(read::Empty)
     {
         Err(read::Empty)
     } else {
@@ -248,6 +428,16 @@ fn collect_memory(
 /// Keys to read and get totals for from the io.stat file
 const IO_STAT_KEYS: [&[u8]; 6] = [b"rbytes", b"wbytes", b"rios", b"wios", b"dbytes", b"dios"];
 
+/// Unit of the values of each of `IO_STAT_KEYS` (which are all counters)
+const IO_STAT_UNITS: [Unit; 6] = [
+    Unit::Bytes,
+    Unit::Bytes,
+    Unit::Count,
+    Unit::Count,
+    Unit::Bytes,
+    Unit::Count,
+];
+
 /// Collects all stats for the io controller
 /// see <https://www.kernel.org/doc/html/latest/admin-guide/cgroup-v2.html#io>
 #[inline]
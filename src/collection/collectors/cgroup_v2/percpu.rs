@@ -0,0 +1,132 @@
+//! Measures the CPU time that a cgroup used on each CPU, which cgroup v2
+//! doesn't report in the cgroupfs (unlike `cpuacct.usage_percpu` in cgroup
+//! v1). A software `cpu-clock` counter is opened with `perf_event_open` for
+//! the cgroup on each CPU that it can run on (from `cpuset.cpus.effective`),
+//! which counts the nanoseconds that the cgroup's tasks ran on that CPU.
+//! Counters are counted against the file descriptor budget (see
+//! `fd_budget`), and are only opened while they fit within it.
+
+use crate::collection::collectors::cgroup_v2::{files, read};
+use crate::collection::fd_budget::{self, Budgeted};
+use crate::util::{self, CpuList, PerfEventAttr};
+use csv::ByteRecord;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// `PERF_COUNT_SW_CPU_CLOCK`, which counts nanoseconds on the CPU
+const CPU_CLOCK: u64 = 0;
+
+/// Minimum time between checks of the CPUs that the cgroup can run on, which
+/// opens counters on any CPUs that were added to its cpuset (or that
+/// counters couldn't be opened on before)
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Per-CPU usage counters of a single cgroup
+pub struct PerCpuUsage {
+    /// Path of the cgroup, relative to the cgroup root
+    cgroup:       PathBuf,
+    /// Number of entries in each field (one for each CPU on the system)
+    cores:        usize,
+    /// Counter opened on each CPU, by CPU
+    counters:     BTreeMap<usize, Budgeted<File>>,
+    refreshed_at: Option<Instant>,
+}
+
+impl PerCpuUsage {
+    #[must_use]
+    pub fn new(cgroup: &Path) -> Self {
+        Self {
+            cgroup:       cgroup.to_path_buf(),
+            cores:        util::remap::<_, usize>(util::num_cores()),
+            counters:     BTreeMap::new(),
+            refreshed_at: None,
+        }
+    }
+
+    /// Appends the `cpu.usage.percpu` field to the record, which contains
+    /// the nanoseconds that the cgroup ran on each CPU since its counters
+    /// were opened (separated by spaces, as in cgroup v1). CPUs that the
+    /// cgroup can't run on are 0. The field is left empty until any counter
+    /// could be opened.
+    pub fn push_field(&mut self, record: &mut ByteRecord) -> Result<(), read::Empty> {
+        let due = match self.refreshed_at {
+            Some(refreshed_at) => refreshed_at.elapsed() >= REFRESH_INTERVAL,
+            None => true,
+        };
+        if due {
+            self.open_counters();
+            self.refreshed_at = Some(Instant::now());
+        }
+
+        if self.counters.is_empty() {
+            record.push_field(b"");
+            return Err(read::Empty);
+        }
+
+        let mut field: Vec<u8> = Vec::with_capacity(self.cores * 12);
+        let mut itoa_buffer = itoa::Buffer::new();
+        let mut bytes = [0_u8; 8];
+        for cpu in 0..self.cores {
+            let usage = match self.counters.get_mut(&cpu) {
+                Some(counter) => match counter.read_exact(&mut bytes) {
+                    Ok(()) => u64::from_ne_bytes(bytes),
+                    Err(_) => 0,
+                },
+                None => 0,
+            };
+            if cpu > 0 {
+                field.push(b' ');
+            }
+            field.extend_from_slice(itoa_buffer.format(usage).as_bytes());
+        }
+        record.push_field(&field);
+        Ok(())
+    }
+
+    /// Opens a counter on each CPU that the cgroup can run on and that
+    /// doesn't have one yet. Counters on CPUs that were removed from its
+    /// cpuset are kept, since their counts are still needed. Nothing is
+    /// opened unless all of the missing counters fit within the file
+    /// descriptor budget.
+    fn open_counters(&mut self) {
+        let cpus = files::read_setting(&self.cgroup, "cpuset.cpus.effective")
+            .and_then(|cpus| CpuList::parse(&cpus))
+            .or_else(|| util::online_cpus().ok());
+        let cpus = match cpus {
+            Some(cpus) => cpus,
+            None => return,
+        };
+        let cores = self.cores;
+        let missing = cpus
+            .iter()
+            .filter(|&cpu| cpu < cores && !self.counters.contains_key(&cpu))
+            .count();
+        // Counters on each missing CPU, along with the cgroup's directory
+        if missing == 0 || !fd_budget::fits(missing as u64 + 1) {
+            return;
+        }
+        let directory = match File::open(util::cgroup_root().join(&self.cgroup)) {
+            Ok(directory) => Budgeted::new(directory),
+            Err(_) => return,
+        };
+
+        let attr = PerfEventAttr::new(util::PERF_TYPE_SOFTWARE, CPU_CLOCK);
+        let flags = util::PERF_FLAG_PID_CGROUP | util::PERF_FLAG_FD_CLOEXEC;
+        for cpu in cpus.iter().filter(|&cpu| cpu < cores) {
+            if self.counters.contains_key(&cpu) {
+                continue;
+            }
+            let counter = i32::try_from(cpu).ok().and_then(|index| {
+                util::perf_event_open(&attr, directory.as_raw_fd(), index, None, flags).ok()
+            });
+            if let Some(counter) = counter {
+                self.counters.insert(cpu, Budgeted::new(counter));
+            }
+        }
+    }
+}
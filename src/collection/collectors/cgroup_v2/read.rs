@@ -1,7 +1,8 @@
 use crate::collection::buffers::WorkingBuffers;
+use crate::collection::fd_budget::CgroupFile;
 use crate::util::{self, BufferLike, ByteLines, LazyQuantity};
-use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
 
 #[derive(Copy, Clone, PartialEq)]
 pub struct Empty;
@@ -10,7 +11,7 @@ pub struct Empty;
 /// and directly write the contents as a field to the next record.
 /// If the written field was empty, returns Err(Empty).
 pub fn single_value_file(
-    file: &Option<File>,
+    file: &CgroupFile,
     buffers: &mut WorkingBuffers,
     default: &'static [u8],
 ) -> Result<(), Empty> {
@@ -35,84 +36,143 @@ pub fn single_value_file(
     }
 }
 
+/// Tries to read the given file handle,
+/// splitting its (single-line) contents by spaces
+/// and writing each of the first K values as a field to the next record.
+/// Missing values are replaced with their defaults.
+/// If the file was empty, returns Err(Empty).
+pub fn space_separated_file<const K: usize>(
+    file: &CgroupFile,
+    buffers: &mut WorkingBuffers,
+    defaults: &[&'static [u8]; K],
+) -> Result<(), Empty> {
+    let content = match read_to_buffer(file, buffers) {
+        None => &[],
+        Some(_) => buffers.buffer.trim(),
+    };
+
+    let mut values = content
+        .split(|&c| util::is_space(c))
+        .filter(|value| !value.is_empty());
+    let mut all_empty = true;
+    for default in defaults {
+        match values.next() {
+            Some(value) => {
+                all_empty = false;
+                buffers.record.push_field(value);
+            },
+            None => buffers.record.push_field(default),
+        }
+    }
+
+    buffers.buffer.clear();
+
+    if all_empty {
+        Err(Empty)
+    } else {
+        Ok(())
+    }
+}
+
 /// Attempts to read the given file into the buffer, if it exists.
 /// If successful, returns Some with the length of the part of the file read.
 /// If the file handle wasn't given, or reading was unsuccessful, returns None.
-fn read_to_buffer(file: &Option<File>, buffers: &mut WorkingBuffers) -> Option<usize> {
-    match file {
-        None => None,
-        Some(f) => {
-            let mut file_mut = f;
-            let result = match file_mut.read(&mut buffers.buffer.b) {
-                Err(_) => None,
-                Ok(len) => {
-                    buffers.buffer.len = len;
-                    if len == 0 {
-                        None
-                    } else {
-                        Some(len)
-                    }
-                },
-            };
-            // Ignore errors: if seeking fails, then the effect next time will be pushing
-            // empty buffers to the CSV rows, which lets the other monitoring
-            // continue
-            let _result = file_mut.seek(SeekFrom::Start(0));
-            result
+fn read_to_buffer(file: &CgroupFile, buffers: &mut WorkingBuffers) -> Option<usize> {
+    let result = file.with(|mut file_mut| {
+        let result = file_mut.read(&mut buffers.buffer.b);
+        // Ignore errors: if seeking fails, then the effect next time will be pushing
+        // empty buffers to the CSV rows, which lets the other monitoring
+        // continue
+        let _result = file_mut.seek(SeekFrom::Start(0));
+        result
+    })?;
+    match result {
+        Err(err) => {
+            buffers.record_read_error(&err);
+            None
+        },
+        Ok(len) => {
+            buffers.buffer.len = len;
+            if len == 0 {
+                None
+            } else {
+                Some(len)
+            }
         },
     }
 }
 
+/// Keys to find in a flat keyed file, which are looked up by their position
+/// (slot) in the record so that the file can be parsed in a single pass
+pub struct FlatKeys {
+    /// Each key along with its slot, sorted by key
+    lookup: Vec<(Box<[u8]>, usize)>,
+    /// Location of each slot's value in the working buffer, if its key was
+    /// found during the current read
+    values: Vec<Option<Range<usize>>>,
+}
+
+impl FlatKeys {
+    #[must_use]
+    pub fn new<K: AsRef<[u8]>>(keys: &[K]) -> Self {
+        let mut lookup = keys
+            .iter()
+            .enumerate()
+            .map(|(slot, key)| (Box::from(key.as_ref()), slot))
+            .collect::<Vec<_>>();
+        lookup.sort();
+        Self {
+            lookup,
+            values: vec![None; keys.len()],
+        }
+    }
+}
+
 /// Tries to read the given file handle,
 /// attempting to find the given keys in the file's contents.
 /// The keys' values are written to the row buffer
-/// in the same order as the keys slice,
-/// and if a value does not exist, the cell is empty.
-/// If all of the written values were empty,
+/// in the same order as the keys were given,
+/// and if a value does not exist, the field is left empty.
+/// The keys can be decided at runtime,
+/// so keys that the kernel doesn't provide are tolerated.
+/// If all of the found values were empty,
 /// then Err(Empty) is returned.
-pub fn flat_keyed_file<const K: usize>(
-    file: &Option<File>,
+pub fn flat_keyed_file(
+    file: &CgroupFile,
     buffers: &mut WorkingBuffers,
-    keys: &[&'static [u8]; K],
-    defaults: &[&'static [u8]; K],
+    keys: &mut FlatKeys,
 ) -> Result<(), Empty> {
     // Ignore errors: the buffer will just remain empty
     // and all of the below processing will result in empty fields.
-    // It is important to always write K fields,
+    // It is important to always write a field for each key,
     // so we don't return early.
     let _result = read_to_buffer(file, buffers);
 
-    // Create K slices,
-    // each pointing to a location in the buffer
-    // where the statistic was found.
-    // After scanning each line,
-    // the slices will be consumed to add records.
-    let mut slices: [&[u8]; K] = [&[]; K];
-
-    let lines = ByteLines::new(&buffers.buffer.b);
-    for (line, _) in lines {
-        // Split the the buffer by the space in the middle
+    let FlatKeys { lookup, values } = keys;
+    values.fill(None);
+    for (line, start) in ByteLines::new(&buffers.buffer.b) {
+        // Split the line by the space in the middle
         // to obtain the key and value:
-        if let Some(space) = util::find_char(line, 0, util::is_space) {
-            let (key, value) = (&line[..space], &line[(space + 1)..]);
-            for (i, &target_key) in keys.iter().enumerate() {
-                if target_key == key {
-                    slices[i] = value;
-                    break;
-                }
-            }
+        let space = match util::find_char(line, 0, util::is_space) {
+            Some(space) => space,
+            None => continue,
+        };
+        let key = &line[..space];
+        let first = lookup.partition_point(|(k, _)| k.as_ref() < key);
+        for (_, slot) in lookup[first..]
+            .iter()
+            .take_while(|(k, _)| k.as_ref() == key)
+        {
+            values[*slot] = Some((start + space + 1)..(start + line.len()));
         }
     }
 
-    // Consume each of the slices
     let mut all_empty = true;
-    for (i, slice) in slices.iter().enumerate() {
-        all_empty = all_empty && slice.is_empty();
-        if slice.is_empty() {
-            buffers.record.push_field(defaults[i]);
-        } else {
-            buffers.record.push_field(slice);
-        }
+    let content = &buffers.buffer.b;
+    for value in values.iter() {
+        let value = value.clone().map_or(&[][..], |range| &content[range]);
+        all_empty = all_empty && value.is_empty();
+        buffers.record.push_field(value);
     }
 
     buffers.buffer.clear();
@@ -136,7 +196,7 @@ pub fn flat_keyed_file<const K: usize>(
 /// If all of the written values were 0,
 /// then Err(Empty) is returned.
 pub fn io_stat_file<const K: usize>(
-    file: &Option<File>,
+    file: &CgroupFile,
     buffers: &mut WorkingBuffers,
     keys: &[&'static [u8]; K],
 ) -> Result<(), Empty> {
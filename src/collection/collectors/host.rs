@@ -0,0 +1,204 @@
+use crate::collection::buffers::WorkingBuffers;
+use crate::collection::collectors::{Collector as CollectorTrait, CollectorImpl};
+use crate::collection::fd_budget::Budgeted;
+use crate::formats::{Column, ColumnDeclaration, ColumnKind, ColumnType, TableMetadata, Unit};
+use crate::util::{self, CgroupPath};
+use anyhow::Error;
+use csv::ByteRecord;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Columns with the time that all CPUs spent in each state (from the `cpu`
+/// line of `/proc/stat`, in the same order)
+const CPU_COLUMNS: &[ColumnDeclaration] = &[
+    ("host.cpu.user", Unit::Nanoseconds, ColumnKind::Counter),
+    ("host.cpu.nice", Unit::Nanoseconds, ColumnKind::Counter),
+    ("host.cpu.system", Unit::Nanoseconds, ColumnKind::Counter),
+    ("host.cpu.idle", Unit::Nanoseconds, ColumnKind::Counter),
+    ("host.cpu.iowait", Unit::Nanoseconds, ColumnKind::Counter),
+    ("host.cpu.irq", Unit::Nanoseconds, ColumnKind::Counter),
+    ("host.cpu.softirq", Unit::Nanoseconds, ColumnKind::Counter),
+    ("host.cpu.steal", Unit::Nanoseconds, ColumnKind::Counter),
+];
+
+/// Entries in `/proc/meminfo` and the columns they map to
+const MEMINFO_ENTRIES: &[(&str, ColumnDeclaration)] = &[
+    (
+        "MemTotal",
+        ("host.memory.total", Unit::Bytes, ColumnKind::Gauge),
+    ),
+    (
+        "MemFree",
+        ("host.memory.free", Unit::Bytes, ColumnKind::Gauge),
+    ),
+    (
+        "MemAvailable",
+        ("host.memory.available", Unit::Bytes, ColumnKind::Gauge),
+    ),
+    (
+        "Buffers",
+        ("host.memory.buffers", Unit::Bytes, ColumnKind::Gauge),
+    ),
+    (
+        "Cached",
+        ("host.memory.cached", Unit::Bytes, ColumnKind::Gauge),
+    ),
+    (
+        "SwapTotal",
+        ("host.swap.total", Unit::Bytes, ColumnKind::Gauge),
+    ),
+    (
+        "SwapFree",
+        ("host.swap.free", Unit::Bytes, ColumnKind::Gauge),
+    ),
+];
+
+const PROC_STAT_PATH: &str = "/proc/stat";
+const PROC_MEMINFO_PATH: &str = "/proc/meminfo";
+
+/// Collects the statistics of the whole host for the synthetic host target:
+/// those of the root cgroup (using the collector for the host's cgroup
+/// version), followed by `host.*` columns from `/proc/stat` and
+/// `/proc/meminfo`
+pub struct Collector {
+    inner:      Box<CollectorImpl>,
+    header:     ByteRecord,
+    stat:       Option<Budgeted<File>>,
+    meminfo:    Option<Budgeted<File>>,
+    /// Nanoseconds per clock tick in `/proc/stat`
+    tick_nanos: u64,
+    buffer:     Vec<u8>,
+}
+
+impl Collector {
+    #[must_use]
+    pub fn new(inner: CollectorImpl) -> Self {
+        let mut header = inner.header().clone();
+        for (name, ..) in host_columns() {
+            header.push_field(name.as_bytes());
+        }
+
+        Self {
+            inner: Box::new(inner),
+            header,
+            stat: None,
+            meminfo: None,
+            tick_nanos: 1_000_000_000 / util::clock_ticks_per_second().max(1),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Gets the cgroup that statistics are collected from
+    #[must_use]
+    pub fn cgroup(&self) -> &CgroupPath { self.inner.cgroup() }
+
+    /// Appends the `host.cpu.*` fields from the aggregate `cpu` line of
+    /// `/proc/stat`, converting clock ticks to nanoseconds
+    fn collect_stat(&mut self, buffers: &mut WorkingBuffers) {
+        let mut itoa_buffer = itoa::Buffer::new();
+        let mut pushed = 0;
+        if let Some(contents) = read(self.stat.as_deref(), &mut self.buffer) {
+            let line = contents.lines().find(|line| line.starts_with("cpu "));
+            let times = line
+                .into_iter()
+                .flat_map(|line| line.split_whitespace().skip(1));
+            for ticks in times.take(CPU_COLUMNS.len()) {
+                match ticks.parse::<u64>() {
+                    Ok(ticks) => {
+                        let nanos = ticks.saturating_mul(self.tick_nanos);
+                        buffers
+                            .record
+                            .push_field(itoa_buffer.format(nanos).as_bytes());
+                    },
+                    Err(_) => buffers.record.push_field(b""),
+                }
+                pushed += 1;
+            }
+        }
+
+        for _ in pushed..CPU_COLUMNS.len() {
+            buffers.record.push_field(b"");
+        }
+    }
+
+    /// Appends the `host.memory.*` and `host.swap.*` fields from
+    /// `/proc/meminfo`, converting kibibytes to bytes
+    fn collect_meminfo(&mut self, buffers: &mut WorkingBuffers) {
+        let contents = read(self.meminfo.as_deref(), &mut self.buffer).unwrap_or_default();
+        let mut itoa_buffer = itoa::Buffer::new();
+        for (entry, _) in MEMINFO_ENTRIES {
+            // Lines look like `MemTotal:       16318340 kB`
+            let kib = contents
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(key, _)| key == entry)
+                .and_then(|(_, value)| value.split_whitespace().next())
+                .and_then(|value| value.parse::<u64>().ok());
+            match kib {
+                Some(kib) => buffers
+                    .record
+                    .push_field(itoa_buffer.format(kib.saturating_mul(1024)).as_bytes()),
+                None => buffers.record.push_field(b""),
+            }
+        }
+    }
+}
+
+impl CollectorTrait for Collector {
+    fn metadata(&mut self) -> Option<serde_yaml::Value> { self.inner.metadata() }
+
+    fn table_metadata(&mut self) -> TableMetadata {
+        let mut table = self.inner.table_metadata();
+        for (name, unit, kind) in host_columns() {
+            table.columns.insert(String::from(name), Column::Scalar {
+                r#type: ColumnType::Int,
+            });
+            table.declare(name, unit, kind);
+        }
+        table
+    }
+
+    /// Uses the type of the root cgroup's collector, since the host columns
+    /// are only appended after its columns
+    fn get_type(&self) -> &'static str { self.inner.get_type() }
+
+    fn init(&mut self) -> Result<(), Error> {
+        self.inner.init()?;
+        self.stat = File::open(PROC_STAT_PATH).ok().map(Budgeted::new);
+        self.meminfo = File::open(PROC_MEMINFO_PATH).ok().map(Budgeted::new);
+        Ok(())
+    }
+
+    fn header(&self) -> &ByteRecord { &self.header }
+
+    fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> bool {
+        // The root cgroup may not have any statistics files on cgroup v2, but
+        // the host columns are always written
+        self.inner.collect(working_buffers);
+        self.collect_stat(working_buffers);
+        self.collect_meminfo(working_buffers);
+        true
+    }
+
+    fn demote_files(&mut self) { self.inner.demote_files(); }
+
+    fn promote_files(&mut self) -> bool { self.inner.promote_files() }
+}
+
+/// Gets the declarations of the columns appended to the root cgroup's columns
+fn host_columns() -> impl Iterator<Item = ColumnDeclaration> {
+    CPU_COLUMNS
+        .iter()
+        .copied()
+        .chain(MEMINFO_ENTRIES.iter().map(|&(_, column)| column))
+}
+
+/// Reads the entire file from the start into the buffer, returning its
+/// contents if successful
+fn read<'a>(file: Option<&File>, buffer: &'a mut Vec<u8>) -> Option<&'a str> {
+    let mut file = file?;
+    buffer.clear();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_end(buffer).ok()?;
+    std::str::from_utf8(buffer).ok()
+}
@@ -0,0 +1,134 @@
+use crate::collection::buffers::WorkingBuffers;
+use crate::collection::collectors::{cgroup_v1, cgroup_v2, Collector as CollectorTrait,
+                                    CollectorOptions, Subsystem, Subsystems};
+use crate::formats::TableMetadata;
+use crate::util::{self, CgroupPath};
+use anyhow::Error;
+use csv::ByteRecord;
+use serde_yaml::Value;
+use std::mem;
+
+/// Collects a cgroup v1 cgroup in the hybrid layout, where some controllers
+/// are only enabled on the cgroup v2 hierarchy (see
+/// `util::hybrid_controllers`). The statistics of those controllers are read
+/// from the same cgroup in the cgroup v2 hierarchy, and the statistics of the
+/// others from the cgroup v1 hierarchies, instead of forcing a single version
+/// for the whole target. Records contain the cgroup v1 columns followed by
+/// the cgroup v2 columns (without a second `read` column).
+pub struct Collector {
+    v1:      cgroup_v1::Collector,
+    v2:      cgroup_v2::Collector,
+    header:  ByteRecord,
+    /// Subsystems whose statistics are read from the cgroup v2 hierarchy
+    unified: Subsystems,
+    /// Record that the cgroup v2 collector writes to, whose fields are then
+    /// appended to the cgroup v1 collector's record
+    record:  ByteRecord,
+}
+
+impl Collector {
+    /// Creates the collector for the cgroup v1 cgroup, which must also exist
+    /// in the cgroup v2 hierarchy (see `CgroupPath::hybrid`)
+    #[must_use]
+    pub fn new(cgroup: CgroupPath, options: &CollectorOptions) -> Self {
+        let unified = cgroup.hybrid().unwrap_or_else(|| cgroup.clone());
+        let enabled = util::hybrid_controllers()
+            .iter()
+            .filter_map(|controller| controller.parse::<Subsystem>().ok())
+            .fold(Subsystems::NONE, Subsystems::with);
+        let subsystems = options.subsystems.intersection(enabled);
+
+        let v1 = cgroup_v1::Collector::new(cgroup, &CollectorOptions {
+            subsystems: options.subsystems.difference(subsystems),
+            ..options.clone()
+        });
+        let v2 = cgroup_v2::Collector::new(unified, CollectorOptions {
+            subsystems,
+            ..options.clone()
+        });
+        let mut header = v1.header().clone();
+        if !subsystems.is_empty() {
+            header.extend(v2.header().iter().skip(1));
+        }
+
+        Self {
+            v1,
+            v2,
+            header,
+            unified: subsystems,
+            record: ByteRecord::new(),
+        }
+    }
+
+    /// Gets the cgroup that statistics are collected from (in the cgroup v1
+    /// hierarchies)
+    #[must_use]
+    pub const fn cgroup(&self) -> &CgroupPath { self.v1.cgroup() }
+}
+
+impl CollectorTrait for Collector {
+    /// Gets the metadata of the cgroup v1 cgroup, with that of the cgroup v2
+    /// cgroup under `Unified`
+    fn metadata(&mut self) -> Option<Value> {
+        let mut metadata = self.v1.metadata()?;
+        if let (Value::Mapping(mapping), false) = (&mut metadata, self.unified.is_empty()) {
+            if let Some(unified) = self.v2.metadata() {
+                mapping.insert(Value::from("Unified"), unified);
+            }
+        }
+        Some(metadata)
+    }
+
+    /// Combines the metadata of both collectors' columns, leaving out the
+    /// cgroup v2 collector's `read` column (which isn't appended)
+    fn table_metadata(&mut self) -> TableMetadata {
+        let mut table = self.v1.table_metadata();
+        if self.unified.is_empty() {
+            return table;
+        }
+
+        let unified = self.v2.table_metadata();
+        for (name, column) in unified.columns {
+            table.columns.entry(name).or_insert(column);
+        }
+        table.schema.extend(unified.schema.into_iter().skip(1));
+        table
+    }
+
+    fn get_type(&self) -> &'static str { "cgroup_hybrid" }
+
+    fn init(&mut self) -> Result<(), Error> {
+        self.v1.init()?;
+        if !self.unified.is_empty() {
+            self.v2.init()?;
+        }
+        Ok(())
+    }
+
+    fn header(&self) -> &ByteRecord { &self.header }
+
+    fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> bool {
+        let collected = self.v1.collect(working_buffers);
+        if self.unified.is_empty() {
+            return collected;
+        }
+
+        // Let the cgroup v2 collector write to its own record, skipping its
+        // `read` field when appending it
+        mem::swap(&mut working_buffers.record, &mut self.record);
+        let unified = self.v2.collect(working_buffers);
+        mem::swap(&mut working_buffers.record, &mut self.record);
+        for field in self.record.iter().skip(1) {
+            working_buffers.record.push_field(field);
+        }
+        self.record.clear();
+        collected || unified
+    }
+
+    fn demote_files(&mut self) {
+        self.v1.demote_files();
+        self.v2.demote_files();
+    }
+
+    fn promote_files(&mut self) -> bool { self.v1.promote_files() && self.v2.promote_files() }
+}
@@ -1,63 +1,347 @@
 mod all;
 mod cgroup_v1;
 mod cgroup_v2;
+mod host;
+mod hybrid;
+mod subsystems;
+mod synthetic;
 
 use crate::cli;
-use crate::collection::buffers::WorkingBuffers;
+use crate::collection::adaptive_buffer::AdaptiveBuffer;
+use crate::collection::aggregate::{AggregateLayout, AggregatePolicy, Aggregator};
+use crate::collection::buffers::{FailedReads, WorkingBuffers};
+use crate::collection::derived::{DerivedColumns, DERIVED_COLUMNS};
+use crate::collection::event_log::EventLogs;
+use crate::collection::fd_budget::Budgeted;
+use crate::collection::fds::{self, FdCounter, FdCounts, FD_COLUMNS};
 use crate::collection::flush::{FlushLog, FlushLogger};
-use crate::collection::perf_table::TableMetadata;
-use crate::collection::system_info::SystemInfo;
-use crate::shared::CollectionTarget;
-use crate::util;
+#[cfg(feature = "nvml")]
+use crate::collection::gpu::{GpuMonitor, TargetGpus, GPU_COLUMNS};
+use crate::collection::lifecycle::LifecycleLog;
+use crate::collection::limit::{Activity, TargetLimit, TargetLimitPolicy};
+#[cfg(feature = "perf")]
+use crate::collection::perf::{TargetCounters, PERF_COLUMNS};
+use crate::collection::processor::{ColumnRegistry, RecordProcessor};
+use crate::collection::quarantine::WriteHealth;
+use crate::collection::ring::RingBuffer;
+use crate::collection::sched::{SchedCounter, SCHED_COLUMNS};
+use crate::collection::sink::{Sink, SinkType, StreamSink, SyncPolicy};
+use crate::collection::sockets::{self, SocketCounter, SocketCounts, SOCKET_COLUMNS};
+use crate::collection::sparse::{SparseFilter, SparsePolicy};
+use crate::collection::start_latency::StartLatency;
+use crate::collection::status::{StatusRegistry, TargetStatus};
+use crate::collection::summary::TargetSummary;
+use crate::collection::throttle::RateLimiter;
+use crate::collection::trigger::{BurstPolicy, BurstStart, TriggerState};
+use crate::collection::validate::{RecordValidator, ValidationReport};
+use crate::formats::{self, AggregateMetadata, Column, ColumnDeclaration, ColumnKind, ColumnSchema,
+                     ColumnType, GroupMember, LogFileFooter, LogFileHeader, SchedulingMetadata,
+                     SparseMetadata, SystemInfo, TableMetadata, Unit, SCHEMA_VERSION};
+use crate::shared::{CollectionTarget, Id};
+use crate::util::{self, CgroupPath};
 use anyhow::Error;
-use csv::WriterBuilder;
-use serde::Serialize;
-use std::fs::{self, File, OpenOptions};
+use csv::{ByteRecord, WriterBuilder};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::{self, OpenOptions};
 use std::io::{self, ErrorKind, Write};
-use std::path::Path;
+use std::mem;
+use std::num::NonZeroU32;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub use all::CollectorImpl;
+pub use cgroup_v1::{BfqColumns, ServiceTimeColumns};
+pub use subsystems::{Subsystem, Subsystems};
 
-pub type StatWriter = csv::Writer<FlushLogger<File>>;
+pub type StatWriter = csv::Writer<FlushLogger<Sink>>;
+
+/// Name of the column appended to each record that is empty unless the
+/// target's cgroup was found to be removed while collecting the record (in
+/// which case it is the error number of the failed read, such as 19 for
+/// `ENODEV`)
+const STATUS_COLUMN: &str = "status";
 
 pub trait Collector {
     fn metadata(&mut self) -> Option<serde_yaml::Value>;
     fn table_metadata(&mut self) -> TableMetadata;
     fn get_type(&self) -> &'static str;
     fn init(&mut self) -> Result<(), Error>;
-    fn write_header(&mut self, writer: &mut StatWriter) -> Result<(), csv::Error>;
-    fn collect(
-        &mut self,
-        writer: &mut StatWriter,
-        working_buffers: &mut WorkingBuffers,
-    ) -> Result<(), csv::Error>;
+    /// Gets the CSV header row with the names of each column
+    fn header(&self) -> &ByteRecord;
+    /// Collects the current statistics into the working record, returning
+    /// whether the record should be written
+    fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> bool;
+    /// Closes the settings files that are held open between reads, opening
+    /// them for each read instead, once the file descriptor budget is under
+    /// pressure (see `fd_budget`)
+    fn demote_files(&mut self) {}
+    /// Holds the files that are opened for each read open again, returning
+    /// false once the file descriptor budget has no room for more
+    fn promote_files(&mut self) -> bool { true }
+}
+
+/// Conditions under which a log file is closed and collection continues in a
+/// new log file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RotationPolicy {
+    /// Number of bytes after which the log file is rotated. Since this is
+    /// only checked once buffered records have been flushed to the file,
+    /// files can exceed this by up to the size of the buffer.
+    pub max_size: Option<u64>,
+    /// Duration after which the log file is rotated
+    pub max_age:  Option<Duration>,
+}
+
+impl RotationPolicy {
+    /// Whether any rotation conditions have been configured
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool { self.max_size.is_some() || self.max_age.is_some() }
+}
+
+/// Settings that determine which statistics collectors include
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CollectorOptions {
+    /// Whether the cgroup v1 `blkio.bfq.*` columns are collected
+    pub bfq:             bool,
+    /// Which cgroup v1 columns break down the `blkio.service.time.*` totals
+    pub service_time:    ServiceTimeColumns,
+    /// Subsystems whose files are read and whose columns are included
+    pub subsystems:      Subsystems,
+    /// Keys read from the cgroup v2 `cpu.stat` file, if they were configured
+    /// instead of the defaults
+    pub cpu_stat_keys:   Option<Arc<[String]>>,
+    /// Whether the cgroup v2 collector measures the usage of each CPU
+    pub percpu:          bool,
+    /// Whether the cgroup v1 collector skips records whose file reads were
+    /// all empty (which the cgroup v2 collector always does)
+    pub skip_empty_rows: bool,
+}
+
+impl CollectorOptions {
+    /// Resolves the collector settings from the collection options
+    #[must_use]
+    pub fn new(options: &cli::CollectionOptions) -> Self {
+        Self {
+            bfq:             options.bfq_columns.resolve(),
+            service_time:    options.service_time_columns,
+            subsystems:      Subsystems::from_list(&options.collect),
+            cpu_stat_keys:   match options.cpu_stat_keys.is_empty() {
+                true => None,
+                false => Some(Arc::from(options.cpu_stat_keys.as_slice())),
+            },
+            percpu:          options.percpu,
+            skip_empty_rows: options.skip_empty_rows,
+        }
+    }
 }
 
-/// Wraps a concrete implementation of Collector,
-/// handling setting up the log file as needed.
+/// Information about the current collector tick that is added to records
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickInfo {
+    /// Time the system spent suspended since the previous tick, if it was
+    /// suspended
+    pub suspended: Option<Duration>,
+    /// Time between when the tick was scheduled and when collection for it
+    /// started
+    pub latency:   Duration,
+    /// Base collection interval if any target is being burst sampled, in
+    /// which case the collection interval is shortened
+    pub burst:     Option<Duration>,
+}
+
+/// Settings shared by all log files
+#[allow(clippy::struct_excessive_bools)]
+pub struct LogSettings {
+    /// Directory to place log files in
+    pub location:         PathBuf,
+    /// Size of the buffer used to write records in (or its maximum size, if
+    /// it is adaptive)
+    pub buffer_capacity:  usize,
+    /// Whether the size of each buffer adapts to the rate at which records
+    /// are written to it
+    pub adaptive_buffer:  bool,
+    pub event_log:        Option<Arc<Mutex<FlushLog>>>,
+    /// Log of target start and stop events, if enabled
+    pub lifecycle:        Option<Mutex<LifecycleLog>>,
+    pub sparse:           Option<SparsePolicy>,
+    /// Summarizes the samples of each target into a record per interval
+    /// instead of writing every sample, if enabled
+    pub aggregate:        Option<AggregatePolicy>,
+    pub sink:             SinkType,
+    /// Total number of bytes discarded by null sinks
+    pub discarded:        Arc<AtomicU64>,
+    /// Statistics reported by the status endpoint, if it is enabled
+    pub status:           Option<Arc<StatusRegistry>>,
+    /// Identifier of the node that is included in each header, if known
+    pub node_id:          Option<String>,
+    /// Prefix of each log file's name (the node id), if enabled
+    pub file_prefix:      Option<String>,
+    pub collector:        CollectorOptions,
+    /// Whether the `read.boottime` and `suspended` columns are added to each
+    /// record
+    pub boottime:         bool,
+    /// Whether the `read.monotonic` column is added to each record
+    pub monotonic:        bool,
+    /// Whether the `latency` column is added to each record
+    pub latency:          bool,
+    /// Number of ticks between counts of each target's open file descriptors,
+    /// if the `fds.*` columns are added to each record
+    pub fds:              Option<NonZeroU32>,
+    /// Whether the `sched.*` columns are added to each record
+    pub sched:            bool,
+    /// Number of ticks between counts of each target's sockets, if the
+    /// `net.*` columns are added to each record
+    pub net_sockets:      Option<NonZeroU32>,
+    /// Whether the `derived.*` columns are added to each record
+    pub derived:          bool,
+    /// Event logs that auxiliary events are streamed to, if any are enabled
+    pub event_logs:       Option<Arc<EventLogs>>,
+    /// Hooks invoked with each record before it is written, in order
+    pub processors:       Vec<Arc<dyn RecordProcessor>>,
+    /// Rules that switch targets to burst sampling, if any were given
+    pub burst:            Option<BurstPolicy>,
+    /// Limits the rate at which all log files are written, if enabled
+    pub io_limiter:       Option<Arc<RateLimiter>>,
+    /// Counts collected values that don't parse as their column's type, if
+    /// validation is enabled
+    pub validation:       Option<Arc<ValidationReport>>,
+    /// When log files are synced to durable storage
+    pub sync:             SyncPolicy,
+    /// Whether the footer of each log file includes a checksum of its body
+    pub checksum:         bool,
+    /// Number of records that each ring file holds, if using the ring sink
+    pub ring_capacity:    u64,
+    /// Number of consecutive failed writes to a log file after which it is
+    /// quarantined, or 0 if log files are never quarantined
+    pub quarantine_after: u32,
+    /// Maximum number of targets that are collected at once, if limited
+    pub target_limit:     Option<TargetLimit>,
+    /// Scheduling settings that were applied to the collection thread, if
+    /// any were given
+    pub scheduling:       Option<SchedulingMetadata>,
+    /// Samples the host's GPUs, if the `gpu.*` columns are added to each
+    /// record
+    #[cfg(feature = "nvml")]
+    pub gpu:              Option<Arc<GpuMonitor>>,
+    /// Whether the `perf.*` columns are added to each record
+    #[cfg(feature = "perf")]
+    pub perf:             bool,
+}
+
+/// Single target that is collected into a log file
+struct Member {
+    collector:      CollectorImpl,
+    target:         CollectionTarget,
+    sparse:         Option<SparseFilter>,
+    status:         Option<Arc<TargetStatus>>,
+    /// Time the system spent suspended that hasn't been written to a record
+    /// yet
+    suspended:      Option<Duration>,
+    fds:            Option<FdCounter>,
+    sched:          Option<SchedCounter>,
+    sockets:        Option<SocketCounter>,
+    #[cfg(feature = "nvml")]
+    gpus:           Option<TargetGpus>,
+    #[cfg(feature = "perf")]
+    perf:           Option<TargetCounters>,
+    derived:        Option<DerivedColumns>,
+    /// Number of records written for the target, across all log files
+    rows:           u64,
+    aggregator:     Option<Aggregator>,
+    trigger:        Option<TriggerState>,
+    /// Error number of the failed read that found the target's cgroup
+    /// removed, if it was found removed during the current tick
+    vanished:       Option<i32>,
+    /// Whether collection of the target was paused via the control socket
+    paused:         bool,
+    /// When the target's collector was initialized
+    initialized_at: u128,
+    /// Whether a sample has been collected for the target yet
+    sampled:        bool,
+    /// Failed reads of the target's cgroup files
+    failed_reads:   FailedReads,
+    /// When the target's records last changed, if idle targets can be
+    /// evicted to stay within the target limit
+    activity:       Option<Activity>,
+}
+
+/// Owns a single log file, along with the collectors of each target that is
+/// written to it. Ungrouped targets each have their own log file, while all
+/// targets in a group share a single log file (with an extra `target.id`
+/// column).
 pub struct Handle {
-    pub collector: CollectorImpl,
-    pub writer:    StatWriter,
-    pub target:    CollectionTarget,
+    pub writer:        StatWriter,
+    /// Name of the group of targets that share the log file, if any
+    pub group:         Option<String>,
     /// `active` is used during difference resolution
     /// to mark inactive collectors for teardown/removal.
-    pub active:    bool,
+    pub active:        bool,
+    /// Path of the current log file
+    pub path:          String,
+    members:           BTreeMap<Id, Member>,
+    opened_at:         Instant,
+    written:           Arc<AtomicU64>,
+    /// Number of bytes written to the previous log files, before they were
+    /// rotated
+    rotated:           u64,
+    /// Number of times that the buffer was flushed, across all log files
+    flushes:           Arc<AtomicU64>,
+    /// Number of records written to the current log file
+    records:           u64,
+    /// Checksum of the current log file's body, if enabled
+    checksum:          Option<Arc<AtomicU32>>,
+    /// Device and inode numbers of the current log file, if written to one
+    file_id:           Option<(u64, u64)>,
+    /// Number of bytes of the log file held back by the I/O rate limit
+    backlog:           Arc<AtomicU64>,
+    /// Largest number of bytes that the buffer held when it was flushed
+    peak:              Arc<AtomicU64>,
+    /// Sizes the buffer, if it is adaptive
+    adaptive:          Option<AdaptiveBuffer>,
+    settings:          Arc<LogSettings>,
+    /// Columns of the records passed to each processor (which include the
+    /// columns appended by the processors before it)
+    processor_columns: Vec<ColumnRegistry>,
+    validator:         Option<RecordValidator>,
+    /// Ring file that records are written to instead of the CSV writer, if
+    /// using the ring sink
+    ring:              Option<RingBuffer>,
+    /// Which fields of raw records are summarized, if records are aggregated
+    aggregate:         Option<AggregateLayout>,
+    /// Columns of collected records that trigger rules are evaluated against,
+    /// if any were given
+    trigger_columns:   Option<ColumnRegistry>,
+    /// Burst sampling started since the last call to `take_bursts`
+    bursts:            Vec<BurstStart>,
+    /// Start latencies of the targets first sampled since the last call to
+    /// `take_started`
+    started:           Vec<StartLatency>,
+    /// Consecutive failed writes to the log file, and whether it is
+    /// quarantined because of them
+    health:            WriteHealth,
 }
 
-/// Bundles together all information stored in log file headers
-#[derive(Clone, Debug, PartialEq, Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct LogFileHeader<'a> {
-    version:            &'static str,
-    provider:           &'static str,
-    metadata:           &'a Option<serde_yaml::Value>,
-    perf_table:         &'a TableMetadata,
-    system:             SystemInfo,
-    collector_type:     &'static str,
-    collector_metadata: &'a Option<serde_yaml::Value>,
-    polled_at:          u128,
-    initialized_at:     u128,
+/// Log file that was just opened by `open_log`
+struct OpenLog {
+    writer:    StatWriter,
+    /// Number of bytes flushed to the file
+    written:   Arc<AtomicU64>,
+    /// Number of flushes to the file
+    flushes:   Arc<AtomicU64>,
+    /// Number of bytes held back by the I/O rate limit
+    backlog:   Arc<AtomicU64>,
+    /// Largest number of bytes flushed at once
+    peak:      Arc<AtomicU64>,
+    /// Checksum of the bytes flushed to the file, if enabled
+    checksum:  Option<Arc<AtomicU32>>,
+    /// Device and inode numbers of the file, if written to one
+    file_id:   Option<(u64, u64)>,
+    ring:      Option<RingBuffer>,
+    aggregate: Option<AggregateLayout>,
 }
 
 impl Handle {
@@ -66,75 +350,1282 @@ impl Handle {
     /// Then, opens up all required read and write file handles
     /// and writes the file header for the log file.
     pub fn new(
-        logs_location: &Path,
+        settings: &Arc<LogSettings>,
+        group: Option<String>,
         target: CollectionTarget,
         collector: CollectorImpl,
-        buffer_capacity: usize,
-        event_log: Option<Arc<Mutex<FlushLog>>>,
     ) -> Result<Self, Error> {
-        let mut collector = collector;
+        let mut members = BTreeMap::new();
+        let id = target.id.clone();
+        members.insert(
+            id,
+            Member::new(target, collector, settings, group.is_some()),
+        );
+
+        // Ring records only hold numbers, so they can't hold the `target.id`
+        // column of grouped log files
+        if let (SinkType::Ring, Some(group)) = (settings.sink, &group) {
+            return Err(Error::msg(format!(
+                "group {} can't be written to a ring file",
+                group
+            )));
+        }
 
         // Ensure directories exist before creating the collector
-        fs::create_dir_all(logs_location)?;
-        let path = construct_log_path(&target.id, logs_location)?;
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(true)
-            .open(path)?;
-
-        let collector_metadata = collector.metadata();
-        let perf_table = collector.table_metadata();
-        let header = LogFileHeader {
-            version:            cli::VERSION.unwrap_or("unknown"),
-            provider:           target.provider,
-            metadata:           &target.metadata,
-            system:             SystemInfo::get(),
-            collector_type:     collector.get_type(),
-            collector_metadata: &collector_metadata,
-            polled_at:          target.poll_time,
-            initialized_at:     util::nano_ts(),
-            perf_table:         &perf_table,
+        if matches!(settings.sink, SinkType::File | SinkType::Ring) {
+            fs::create_dir_all(&settings.location)?;
+        }
+        let name = match &group {
+            Some(group) => group,
+            None => members.values().next().unwrap().target.log_name(),
+        };
+        let path = construct_log_path(
+            name,
+            settings.file_prefix.as_deref(),
+            &settings.location,
+            settings.sink,
+        )?;
+        let adaptive = match settings.adaptive_buffer {
+            true => Some(AdaptiveBuffer::new(settings.buffer_capacity)),
+            false => None,
+        };
+        let capacity = adaptive
+            .as_ref()
+            .map_or(settings.buffer_capacity, AdaptiveBuffer::capacity);
+        // Let the collector initialize inner state before the log file is
+        // created, so that a target that can't be collected yet (and is
+        // retried later) doesn't leave a log file with only a header behind
+        for member in members.values_mut() {
+            member.collector.init()?;
+        }
+        let log = open_log(&path, group.as_deref(), &mut members, settings, capacity)?;
+        let processor_columns = match members.values().next() {
+            Some(first) if !settings.processors.is_empty() => {
+                processor_columns(&first.collector, group.is_some(), settings)
+            },
+            _ => Vec::new(),
+        };
+        let validator = match (&settings.validation, members.values_mut().next()) {
+            (Some(_), Some(first)) => {
+                let table_metadata = first.collector.table_metadata();
+                Some(RecordValidator::new(
+                    first.collector.header(),
+                    &table_metadata,
+                    usize::from(group.is_some()),
+                ))
+            },
+            _ => None,
+        };
+        let trigger_columns = match (&settings.burst, members.values().next()) {
+            (Some(_), Some(first)) => Some(ColumnRegistry::new(&header_row(
+                &first.collector,
+                group.is_some(),
+                settings,
+            ))),
+            _ => None,
+        };
+
+        Ok(Self {
+            writer: log.writer,
+            group,
+            active: true,
+            path,
+            members,
+            opened_at: Instant::now(),
+            written: log.written,
+            rotated: 0,
+            flushes: log.flushes,
+            records: 0,
+            checksum: log.checksum,
+            file_id: log.file_id,
+            backlog: log.backlog,
+            peak: log.peak,
+            adaptive,
+            settings: Arc::clone(settings),
+            processor_columns,
+            validator,
+            ring: log.ring,
+            aggregate: log.aggregate,
+            trigger_columns,
+            bursts: Vec::new(),
+            started: Vec::new(),
+            health: WriteHealth::new(settings.quarantine_after),
+        })
+    }
+
+    /// Adds another target to the group that shares the log file. Its
+    /// collector must produce the same columns as the existing ones.
+    pub fn add(&mut self, target: CollectionTarget, collector: CollectorImpl) -> Result<(), Error> {
+        if let Some(existing) = self.members.values().next() {
+            if existing.collector.get_type() != collector.get_type() {
+                return Err(Error::msg(format!(
+                    "collector type {} does not match the {} collector used by the group",
+                    collector.get_type(),
+                    existing.collector.get_type()
+                )));
+            }
+        }
+
+        let mut member = Member::new(target, collector, &self.settings, true);
+        member.collector.init()?;
+        self.members.insert(member.target.id.clone(), member);
+        Ok(())
+    }
+
+    /// Removes the target with the given id, returning its collection
+    /// statistics if it was written to the log file. If records are
+    /// aggregated, the samples collected since its last aggregated record are
+    /// written first.
+    pub fn remove(&mut self, id: &str) -> Option<TargetSummary> {
+        let mut member = self.members.remove(id)?;
+        // Write errors resurface when the buffer is flushed
+        let rows = member.rows;
+        let _ = write_aggregate(
+            &mut member,
+            self.aggregate.as_ref(),
+            &mut self.writer,
+            self.ring.as_mut(),
+        );
+        self.records += member.rows - rows;
+        Some(self.summarize(&member))
+    }
+
+    /// Whether no targets are written to the log file anymore
+    #[must_use]
+    pub fn is_empty(&self) -> bool { self.members.is_empty() }
+
+    /// Gets the collection statistics of each target written to the log file
+    #[must_use]
+    pub fn summaries(&self) -> Vec<(Id, TargetSummary)> {
+        self.members
+            .iter()
+            .map(|(id, member)| (id.clone(), self.summarize(member)))
+            .collect()
+    }
+
+    /// Updates the statistics of a target that was written to the log file
+    /// with the bytes and flushes of the log file, such as after it was
+    /// finished
+    pub fn update_summary(&self, summary: &mut TargetSummary) {
+        summary.bytes = self.rotated + self.written.load(Ordering::Relaxed);
+        summary.flushes = self.flushes.load(Ordering::Relaxed);
+    }
+
+    fn summarize(&self, member: &Member) -> TargetSummary {
+        let elapsed = util::nano_ts().saturating_sub(member.initialized_at);
+        let mut summary = TargetSummary {
+            rows:         member.rows,
+            bytes:        0,
+            elapsed:      Duration::from_nanos(u64::try_from(elapsed).unwrap_or(u64::MAX)),
+            failed_reads: member.failed_reads,
+            flushes:      0,
         };
+        self.update_summary(&mut summary);
+        summary
+    }
+
+    /// Writes the samples collected since the last aggregated record of each
+    /// target (used when collection stops)
+    pub fn write_aggregates(&mut self) -> Result<(), csv::Error> {
+        for member in self.members.values_mut() {
+            let rows = member.rows;
+            write_aggregate(
+                member,
+                self.aggregate.as_ref(),
+                &mut self.writer,
+                self.ring.as_mut(),
+            )?;
+            self.records += member.rows - rows;
+        }
+        Ok(())
+    }
 
-        // Write the YAML header to the file before initializing the CSV writer
-        let header_str = serde_yaml::to_string(&header)?;
-        writeln!(&file, "{}", header_str)?;
-        writeln!(&file, "---")?;
+    /// Gets the human-readable name of the target with the given id, if it is
+    /// written to the log file
+    #[must_use]
+    pub fn target_name(&self, id: &str) -> Option<&str> {
+        self.members
+            .get(id)
+            .map(|member| member.target.name.as_str())
+    }
 
-        // Initialize the CSV writer and then write the header row
-        let mut writer = WriterBuilder::new()
-            .buffer_capacity(buffer_capacity)
-            .from_writer(FlushLogger::new(file, target.id.clone(), event_log));
-        collector.write_header(&mut writer)?;
+    /// Gets the target with the given id (if it is written to the log file),
+    /// along with the cgroup that it is collected from
+    #[must_use]
+    pub fn target(&self, id: &str) -> Option<(&CollectionTarget, &CgroupPath)> {
+        self.members
+            .get(id)
+            .map(|member| (&member.target, member.collector.cgroup()))
+    }
 
-        // Let the collector initialize inner state
-        collector.init()?;
+    /// Gets the id, name, and number of written records of each target written
+    /// to the log file
+    pub fn rows(&self) -> impl Iterator<Item = (&str, &str, u64)> {
+        self.members
+            .iter()
+            .map(|(id, member)| (id.as_str(), member.target.name.as_str(), member.rows))
+    }
 
-        Ok(Self {
+    /// Gets when the records of each target last changed, for targets whose
+    /// activity is tracked
+    pub fn activity(&self) -> impl Iterator<Item = (&str, Instant)> {
+        self.members.iter().filter_map(|(id, member)| {
+            let activity = member.activity.as_ref()?;
+            Some((id.as_str(), activity.changed_at()))
+        })
+    }
+
+    /// Gets the number of bytes flushed to the current log file
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 { self.written.load(Ordering::Relaxed) }
+
+    /// Gets the largest number of bytes that the buffer held when it was
+    /// flushed, along with its current capacity
+    #[must_use]
+    pub fn buffer_usage(&self) -> (u64, usize) {
+        let capacity = self
+            .adaptive
+            .as_ref()
+            .map_or(self.settings.buffer_capacity, AdaptiveBuffer::capacity);
+        (self.peak.load(Ordering::Relaxed), capacity)
+    }
+
+    /// Whether any target written to the log file is being burst sampled
+    #[must_use]
+    pub fn is_bursting(&self) -> bool {
+        self.members
+            .values()
+            .any(|member| matches!(&member.trigger, Some(trigger) if trigger.is_bursting()))
+    }
+
+    /// Closes the settings files that the collectors of all targets hold open,
+    /// opening them for each read instead (see `fd_budget`)
+    pub fn demote_files(&mut self) {
+        for member in self.members.values_mut() {
+            member.collector.demote_files();
+        }
+    }
+
+    /// Holds the files that the collectors of all targets open for each read
+    /// open again, returning false once the file descriptor budget has no
+    /// room for more
+    pub fn promote_files(&mut self) -> bool {
+        self.members
+            .values_mut()
+            .all(|member| member.collector.promote_files())
+    }
+
+    /// Pauses or resumes collection of the target with the given id (or of
+    /// every target written to the log file), returning whether any target
+    /// was found
+    pub fn set_paused(&mut self, id: Option<&str>, paused: bool) -> bool {
+        let mut found = false;
+        for (_, member) in self
+            .members
+            .iter_mut()
+            .filter(|(member_id, _)| id.map_or(true, |id| id == member_id.as_str()))
+        {
+            member.paused = paused;
+            found = true;
+        }
+        found
+    }
+
+    /// Takes the burst sampling that was started (for targets written to the
+    /// log file) since the last call
+    pub fn take_bursts(&mut self) -> Vec<BurstStart> { mem::take(&mut self.bursts) }
+
+    /// Takes the start latencies of the targets (written to the log file)
+    /// whose first sample was collected since the last call
+    pub fn take_started(&mut self) -> Vec<StartLatency> { mem::take(&mut self.started) }
+
+    /// Takes the targets whose cgroups were found to be removed during the
+    /// last collection (along with the error number of the failed read),
+    /// whose final records have been written with the `status` column set.
+    /// They are left to be removed like stopped targets.
+    pub fn take_vanished(&mut self) -> Vec<(Id, i32)> {
+        self.members
+            .iter_mut()
+            .filter_map(|(id, member)| Some((id.clone(), member.vanished.take()?)))
+            .collect()
+    }
+
+    /// Gets the name that log files are created with: the group name, or the
+    /// target's log name for ungrouped targets (see
+    /// `CollectionTarget::log_name`)
+    fn log_name(&self) -> &str {
+        match &self.group {
+            Some(group) => group,
+            None => self
+                .members
+                .values()
+                .next()
+                .map_or("", |member| member.target.log_name()),
+        }
+    }
+
+    /// Collects the current statistics for all targets,
+    /// writing the CSV entries to the writer (unless they are
+    /// filtered out in sparse mode). If the system was suspended since the
+    /// last collection, the next record of each target is annotated with the
+    /// duration of the suspend.
+    pub fn collect(
+        &mut self,
+        working_buffers: &mut WorkingBuffers,
+        tick: TickInfo,
+    ) -> Result<(), csv::Error> {
+        // The members are taken out while they are collected, so that each
+        // one can be borrowed along with the rest of the handle
+        let mut members = mem::take(&mut self.members);
+        let mut result = Ok(());
+        for member in members.values_mut() {
+            if let Err(err) = self.collect_member(member, working_buffers, tick) {
+                result = Err(err);
+            }
+            working_buffers.record.clear();
+        }
+        self.members = members;
+
+        result?;
+        self.adapt_buffer()
+    }
+
+    /// Collects the current statistics for a single target, writing its
+    /// record unless it is filtered out
+    fn collect_member(
+        &mut self,
+        member: &mut Member,
+        working_buffers: &mut WorkingBuffers,
+        tick: TickInfo,
+    ) -> Result<(), csv::Error> {
+        if let Some(gap) = tick.suspended {
+            member.suspended = Some(member.suspended.unwrap_or_default() + gap);
+        }
+        if member.paused {
+            return Ok(());
+        }
+        if let (Some(trigger), Some(policy)) = (&member.trigger, &self.settings.burst) {
+            if !trigger.is_due(tick.burst, policy) {
+                return Ok(());
+            }
+        }
+
+        if self.group.is_some() {
+            working_buffers
+                .record
+                .push_field(member.target.id.as_bytes());
+        }
+
+        let collected = member.collector.collect(working_buffers);
+        member.vanished = working_buffers.vanished.take();
+        member.failed_reads.add(&working_buffers.take_failed());
+        let fd_counts = member.fds.as_mut().and_then(FdCounter::tick);
+        let socket_counts = member.sockets.as_mut().and_then(SocketCounter::tick);
+        if collected {
+            self.observe(member, &working_buffers.record);
+        }
+
+        // Always write the final record of a target whose cgroup was
+        // removed, even though most of its fields are empty
+        let write = member.vanished.is_some()
+            || (collected
+                && match &mut member.sparse {
+                    // Always write the first record after a suspend
+                    Some(filter) => {
+                        filter.should_write(&working_buffers.record) || member.suspended.is_some()
+                    },
+                    None => true,
+                });
+        if !write {
+            return Ok(());
+        }
+
+        member.push_appended_fields(
+            &self.settings,
+            tick,
+            (fd_counts, socket_counts),
+            &mut working_buffers.record,
+        );
+        self.write_member_record(member, &mut working_buffers.record)
+    }
+
+    /// Updates the per-target state that follows each collected record (such
+    /// as its activity and burst triggers), before the record is filtered
+    fn observe(&mut self, member: &mut Member, record: &ByteRecord) {
+        if let Some(sched) = &mut member.sched {
+            sched.sample();
+        }
+        if let Some(status) = &member.status {
+            status.sampled();
+        }
+        self.started.extend(member.first_sample());
+        if let Some(activity) = &mut member.activity {
+            activity.observe(record);
+        }
+        if let (Some(validator), Some(report)) = (&self.validator, &self.settings.validation) {
+            validator.validate(record, report);
+        }
+        if let (Some(trigger), Some(policy), Some(columns)) = (
+            &mut member.trigger,
+            &self.settings.burst,
+            &self.trigger_columns,
+        ) {
+            if let Some(rule) = trigger.evaluate(record, columns, policy) {
+                self.bursts.push(BurstStart {
+                    id:   member.target.id.clone(),
+                    rule: rule.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Passes a target's record through the processors (and the aggregator,
+    /// if enabled) and writes it unless any of them vetoes it
+    fn write_member_record(
+        &mut self,
+        member: &mut Member,
+        record: &mut ByteRecord,
+    ) -> Result<(), csv::Error> {
+        // Every processor runs (so that the record stays consistent with the
+        // header), but any one of them can veto the record
+        let mut keep = true;
+        for (processor, columns) in self.settings.processors.iter().zip(&self.processor_columns) {
+            keep &= processor.process(&member.target, columns, record);
+        }
+
+        // Aggregated records are only written once per interval
+        if let (true, Some(aggregator), Some(layout)) =
+            (keep, &mut member.aggregator, &self.aggregate)
+        {
+            keep = aggregator.aggregate(record, layout);
+        }
+        if !keep {
+            return Ok(());
+        }
+
+        let result = write_record(&mut self.writer, self.ring.as_mut(), record);
+        match &result {
+            Ok(()) => {
+                member.rows += 1;
+                self.records += 1;
+                self.health.succeeded();
+            },
+            Err(_) => self.health.failed(),
+        }
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.record(record);
+        }
+        result
+    }
+
+    /// Whether the log file is quarantined after too many consecutive failed
+    /// writes, in which case its targets shouldn't be collected until it is
+    /// recovered (see `recover`)
+    #[must_use]
+    pub const fn is_quarantined(&self) -> bool { self.health.is_quarantined() }
+
+    /// Gets the number of consecutive failed writes to the log file
+    #[must_use]
+    pub const fn write_failures(&self) -> u32 { self.health.failures() }
+
+    /// Attempts to recover the quarantined log file if an attempt is due,
+    /// returning how long it was quarantined for if it was recovered. The
+    /// buffered records are written out, which succeeds once the cause of the
+    /// failures was resolved (such as when disk space was freed). If the log
+    /// file was deleted instead, it is re-created with its header, and the
+    /// buffered records are lost.
+    pub fn recover(&mut self) -> Result<Option<Duration>, Error> {
+        if !self.health.attempt_due() {
+            return Ok(None);
+        }
+
+        if self.is_detached() {
+            self.recreate()?;
+        } else {
+            self.writer.flush()?;
+        }
+
+        Ok(Some(self.health.recovered()))
+    }
+
+    /// Re-creates the log file (with its header) if it was deleted or replaced
+    /// while it was open, which failed writes don't detect since writing to a
+    /// deleted file succeeds. This is checked at most once per second, and
+    /// the records written since the log file was deleted are lost. If it
+    /// can't be re-created, the log file is quarantined so that re-creating
+    /// it is retried (see `recover`). Returns whether the log file was
+    /// re-created.
+    pub fn reattach(&mut self) -> Result<bool, Error> {
+        if self.settings.sink != SinkType::File
+            || self.is_quarantined()
+            || !self.health.detached_check_due()
+            || !self.is_detached()
+        {
+            return Ok(false);
+        }
+
+        if let Err(err) = self.recreate() {
+            self.health.quarantine();
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    /// Whether the log file was deleted or replaced while it was open, which
+    /// is found by comparing the file at its path to the one that was opened
+    fn is_detached(&self) -> bool {
+        let (dev, ino) = match self.file_id {
+            Some(file_id) => file_id,
+            None => return false,
+        };
+        match fs::metadata(&self.path) {
+            Ok(current) => current.dev() != dev || current.ino() != ino,
+            Err(err) => err.kind() == ErrorKind::NotFound,
+        }
+    }
+
+    /// Re-creates the log file with its header, discarding the buffered
+    /// records. If this fails, all records are discarded until it succeeds.
+    fn recreate(&mut self) -> Result<(), Error> {
+        // Close the deleted file first (which only writes the buffered
+        // records to it), since it takes up space for as long as it is open
+        let discard = FlushLogger::new(
+            Sink::Null(Arc::new(AtomicU64::new(0))),
+            self.log_name().to_owned(),
+            None,
+            None,
+            None,
+            None,
+            SyncPolicy::Never,
+        );
+        drop(mem::replace(
+            &mut self.writer,
+            WriterBuilder::new().from_writer(discard),
+        ));
+        fs::create_dir_all(&self.settings.location)?;
+        let (_, capacity) = self.buffer_usage();
+        let log = match open_log(
+            &self.path,
+            self.group.as_deref(),
+            &mut self.members,
+            &self.settings,
+            capacity,
+        ) {
+            Ok(log) => log,
+            Err(err) => {
+                // Remove the partially written file, so that re-creating it is
+                // retried
+                let _ = fs::remove_file(&self.path);
+                return Err(err);
+            },
+        };
+        drop(self.replace_log(log));
+        Ok(())
+    }
+
+    /// Resizes the buffer if it is adaptive and the rate at which records are
+    /// written to it calls for a different size, which flushes it
+    fn adapt_buffer(&mut self) -> Result<(), csv::Error> {
+        match self.adaptive.as_mut().and_then(AdaptiveBuffer::evaluate) {
+            Some(capacity) => resize_writer(&mut self.writer, capacity),
+            None => Ok(()),
+        }
+    }
+
+    /// Determines whether the current log file has exceeded any of the limits
+    /// in the given rotation policy
+    #[must_use]
+    pub fn should_rotate(&self, policy: &RotationPolicy) -> bool {
+        // Streamed records have no log file to rotate, and quarantined log
+        // files are only rotated once they are recovered
+        if self.settings.sink == SinkType::Stream || self.is_quarantined() {
+            return false;
+        }
+
+        let written = self.written.load(Ordering::Relaxed);
+        let too_large = matches!(policy.max_size, Some(max) if written >= max);
+        let too_old = matches!(policy.max_age, Some(max) if self.opened_at.elapsed() >= max);
+        too_large || too_old
+    }
+
+    /// Writes out as much of the data held back by the I/O rate limit as the
+    /// limit currently allows. Should be called once per tick so that the
+    /// backlog keeps draining even while no new data is flushed.
+    pub fn pump(&mut self) -> io::Result<()> {
+        match self.backlog.load(Ordering::Relaxed) {
+            0 => Ok(()),
+            _ => {
+                let writer = &mut self.writer;
+                RateLimiter::pumping(|| writer.flush())
+            },
+        }
+    }
+
+    /// Closes the current log file, writing a footer that points to the next
+    /// log file, and continues collection in a new log file with the header
+    /// re-emitted. Since this happens in between collector ticks, no records
+    /// are lost during the switchover. Returns false if rotation had to be
+    /// deferred because the new log file would have the same name as the
+    /// current one.
+    pub fn rotate(&mut self) -> Result<bool, Error> {
+        let path = construct_log_path(
+            self.log_name(),
+            self.settings.file_prefix.as_deref(),
+            &self.settings.location,
+            self.settings.sink,
+        )?;
+        if path == self.path {
+            return Ok(false);
+        }
+
+        // Make sure all buffered records end up in the old log file
+        self.writer.flush()?;
+        let (_, capacity) = self.buffer_usage();
+        let log = open_log(
+            &path,
+            self.group.as_deref(),
+            &mut self.members,
+            &self.settings,
+            capacity,
+        )?;
+
+        let footer = self.footer(
+            Path::new(&path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(String::from),
+        );
+        let old_writer = self.replace_log(log);
+        self.path = path;
+
+        let mut sink = old_writer
+            .into_inner()
+            .map_err(|err| Error::msg(err.error().to_string()))?
+            .into_inner();
+        write_footer(&mut sink, &footer)?;
+        Ok(true)
+    }
+
+    /// Continues collection in the log file that was just opened, returning
+    /// the writer of the previous log file
+    fn replace_log(&mut self, log: OpenLog) -> StatWriter {
+        self.rotated += self.written.load(Ordering::Relaxed);
+        self.written = log.written;
+        log.flushes
+            .fetch_add(self.flushes.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.flushes = log.flushes;
+        self.records = 0;
+        self.checksum = log.checksum;
+        self.file_id = log.file_id;
+        self.backlog = log.backlog;
+        log.peak
+            .fetch_max(self.peak.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.peak = log.peak;
+        self.ring = log.ring;
+        self.opened_at = Instant::now();
+        // Make sure the new log file starts with a full record for each target
+        for member in self.members.values_mut() {
+            if let Some(filter) = &mut member.sparse {
+                filter.reset();
+            }
+        }
+
+        mem::replace(&mut self.writer, log.writer)
+    }
+
+    /// Finishes the current log file once collection for it is done, flushing
+    /// the buffered records and writing a footer with the number of records
+    /// and bytes in its body (and its checksum), so that log files that
+    /// weren't closed cleanly can be told apart from complete ones
+    pub fn finish(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        if self.settings.sink == SinkType::File {
+            let footer = self.footer(None);
+            let mut file = OpenOptions::new().append(true).open(&self.path)?;
+            write_footer(&mut file, &footer)?;
+        }
+        Ok(())
+    }
+
+    /// Creates the footer of the current log file, whose buffered records
+    /// must have been flushed beforehand
+    fn footer(&self, next_file: Option<String>) -> LogFileFooter {
+        LogFileFooter {
+            closed_at: util::nano_ts(),
+            next_file,
+            rows: Some(self.records),
+            bytes: Some(self.written.load(Ordering::Relaxed)),
+            crc32: self
+                .checksum
+                .as_ref()
+                .map(|checksum| checksum.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Member {
+    fn new(
+        target: CollectionTarget,
+        collector: CollectorImpl,
+        settings: &LogSettings,
+        grouped: bool,
+    ) -> Self {
+        // Ignore the target id column (if grouped) and the read column when
+        // comparing records
+        let key_fields = match grouped {
+            true => 2,
+            false => 1,
+        };
+        let status = settings
+            .status
+            .as_ref()
+            .map(|registry| registry.register(&target));
+        let fds = settings
+            .fds
+            .map(|divisor| FdCounter::new(collector.cgroup().clone(), divisor));
+        let sockets = settings
+            .net_sockets
+            .map(|divisor| SocketCounter::new(collector.cgroup().clone(), divisor));
+        let sched = match settings.sched {
+            true => Some(SchedCounter::new(collector.cgroup().clone())),
+            false => None,
+        };
+        #[cfg(feature = "nvml")]
+        let gpus = settings
+            .gpu
+            .as_ref()
+            .map(|_| TargetGpus::new(collector.cgroup().clone()));
+        #[cfg(feature = "perf")]
+        let perf = match settings.perf {
+            true => Some(TargetCounters::new(collector.cgroup().clone())),
+            false => None,
+        };
+        let derived = match settings.derived {
+            true => Some(DerivedColumns::new(collector.header(), grouped)),
+            false => None,
+        };
+        Self {
             collector,
-            writer,
             target,
-            active: true,
+            sparse: settings
+                .sparse
+                .map(|policy| SparseFilter::new(policy, key_fields)),
+            status,
+            suspended: None,
+            fds,
+            sched,
+            sockets,
+            #[cfg(feature = "nvml")]
+            gpus,
+            #[cfg(feature = "perf")]
+            perf,
+            derived,
+            rows: 0,
+            aggregator: settings.aggregate.map(Aggregator::new),
+            trigger: settings.burst.as_ref().map(|_| TriggerState::new()),
+            vanished: None,
+            paused: false,
+            initialized_at: util::nano_ts(),
+            sampled: false,
+            failed_reads: FailedReads::default(),
+            activity: settings
+                .target_limit
+                .filter(|limit| limit.policy == TargetLimitPolicy::Evict)
+                .map(|_| Activity::new(key_fields)),
+        }
+    }
+}
+
+impl Member {
+    /// Gets the target's start latency if this is its first sample
+    fn first_sample(&mut self) -> Option<StartLatency> {
+        if self.sampled {
+            return None;
+        }
+
+        self.sampled = true;
+        Some(StartLatency {
+            id:              self.target.id.clone(),
+            name:            self.target.name.clone(),
+            polled_at:       self.target.poll_time,
+            initialized_at:  self.initialized_at,
+            first_sample_at: util::nano_ts(),
+        })
+    }
+
+    /// Appends the fields of the columns returned by `appended_columns` to
+    /// the record, given the file descriptors and sockets counted during the
+    /// current tick (if any)
+    fn push_appended_fields(
+        &mut self,
+        settings: &LogSettings,
+        tick: TickInfo,
+        (fd_counts, socket_counts): (Option<FdCounts>, Option<SocketCounts>),
+        record: &mut ByteRecord,
+    ) {
+        let mut itoa_buffer = itoa::Buffer::new();
+        if settings.boottime {
+            push_boottime_fields(record, self.suspended.take());
+        }
+        if settings.monotonic {
+            record.push_field(itoa_buffer.format(util::monotonic_raw_ts()).as_bytes());
+        }
+        if settings.latency {
+            record.push_field(itoa_buffer.format(tick.latency.as_nanos()).as_bytes());
+        }
+        if self.fds.is_some() {
+            fds::push_fields(fd_counts, record);
+        }
+        if let Some(sched) = &self.sched {
+            sched.push_fields(record);
+        }
+        if self.sockets.is_some() {
+            sockets::push_fields(socket_counts, record);
+        }
+        #[cfg(feature = "nvml")]
+        if let (Some(gpus), Some(monitor)) = (&mut self.gpus, &settings.gpu) {
+            gpus.push_fields(monitor, record);
+        }
+        #[cfg(feature = "perf")]
+        if let Some(perf) = &mut self.perf {
+            perf.push_fields(record);
+        }
+        if let Some(derived) = &mut self.derived {
+            derived.push_fields(record);
+        }
+        match self.vanished {
+            Some(errno) => record.push_field(itoa_buffer.format(errno).as_bytes()),
+            None => record.push_field(b""),
+        }
+    }
+}
+
+/// Opens the log file at the given path, writing the YAML header and the CSV
+/// header row. When using the ring sink, this creates a ring file with the
+/// same header instead, and the CSV writer discards everything written to it.
+/// When using the stream sink, both headers are written to stdout as comments.
+/// Records are buffered in a buffer of the given capacity.
+#[allow(clippy::too_many_lines)]
+fn open_log(
+    path: &str,
+    group: Option<&str>,
+    members: &mut BTreeMap<Id, Member>,
+    settings: &LogSettings,
+    capacity: usize,
+) -> Result<OpenLog, Error> {
+    let mut group_members = BTreeMap::new();
+    if group.is_some() {
+        for (id, member) in members.iter_mut() {
+            group_members.insert(id.clone(), GroupMember {
+                name:               member.target.name.clone(),
+                metadata:           member.target.metadata.clone(),
+                collector_metadata: member.collector.metadata(),
+                polled_at:          member.target.poll_time,
+            });
+        }
+    }
+
+    // Groups share the columns (and collector type) of their first member
+    let first = members
+        .values_mut()
+        .next()
+        .ok_or_else(|| Error::msg("log file has no targets"))?;
+    let mut sink = match settings.sink {
+        SinkType::File => Sink::File(Budgeted::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(true)
+                .open(path)?,
+        )),
+        SinkType::Null => Sink::Null(Arc::clone(&settings.discarded)),
+        SinkType::Ring => Sink::Null(Arc::new(AtomicU64::new(0))),
+        SinkType::Stream => Sink::Stream(StreamSink::new(&first.target.id, first.target.provider)),
+    };
+    let mut perf_table = first.collector.table_metadata();
+    if group.is_some() {
+        perf_table.schema.insert(
+            0,
+            ColumnSchema::new("target.id", None, Unit::Unitless, ColumnKind::Identifier),
+        );
+    }
+    for (name, unit, kind) in appended_columns(settings) {
+        perf_table
+            .columns
+            .insert(String::from(name), Column::Scalar {
+                r#type: ColumnType::Int,
+            });
+        perf_table.declare(name, unit, kind);
+    }
+    let extra_columns = settings
+        .processors
+        .iter()
+        .flat_map(|processor| {
+            let columns = processor.columns(first.collector.get_type());
+            columns.into_iter().map(move |(name, column)| {
+                let (unit, kind) = processor.describe(&name);
+                (name, column, unit, kind)
+            })
         })
+        .collect::<Vec<_>>();
+    for (name, column, unit, kind) in &extra_columns {
+        perf_table.columns.insert(name.clone(), column.clone());
+        perf_table.declare(name, *unit, *kind);
+    }
+
+    let mut header_row = match (
+        group,
+        appended_columns(settings).is_empty(),
+        extra_columns.is_empty(),
+    ) {
+        (None, true, true) => first.collector.header().clone(),
+        _ => {
+            let mut header_row = header_row(&first.collector, group.is_some(), settings);
+            for (name, ..) in &extra_columns {
+                header_row.push_field(name.as_bytes());
+            }
+            header_row
+        },
+    };
+    let aggregate = settings.aggregate.map(|_| {
+        let key_fields = match group {
+            Some(_) => 2,
+            None => 1,
+        };
+        let layout = AggregateLayout::new(&header_row, &perf_table, key_fields);
+        layout.apply(&mut header_row, &mut perf_table);
+        layout
+    });
+
+    let header = LogFileHeader {
+        version: String::from(cli::VERSION.unwrap_or("unknown")),
+        schema_version: SCHEMA_VERSION,
+        node_id: settings.node_id.clone(),
+        provider: String::from(first.target.provider),
+        metadata: match group {
+            Some(_) => None,
+            None => first.target.metadata.clone(),
+        },
+        system: SystemInfo::get(),
+        collector_type: String::from(first.collector.get_type()),
+        collector_metadata: match group {
+            Some(_) => None,
+            None => first.collector.metadata(),
+        },
+        polled_at: first.target.poll_time,
+        initialized_at: util::nano_ts(),
+        monotonic_epoch: match settings.monotonic {
+            true => Some(util::nano_ts().saturating_sub(util::monotonic_raw_ts())),
+            false => None,
+        },
+        perf_table,
+        sparse: settings.sparse.map(|policy| SparseMetadata {
+            epsilon:   policy.epsilon,
+            heartbeat: policy.heartbeat.as_nanos(),
+        }),
+        aggregate: settings.aggregate.map(|policy| AggregateMetadata {
+            interval: policy.interval.as_nanos(),
+        }),
+        group: group.map(String::from),
+        members: group_members,
+        scheduling: settings.scheduling.clone(),
+    };
+
+    // Write the YAML header to the file before initializing the CSV writer
+    let header_str = serde_yaml::to_string(&header)?;
+    let prefix = format!("{}\n{}\n", header_str, formats::SECTION_SEPARATOR);
+    match &sink {
+        Sink::Stream(_) => write_stream_header(&prefix, &header_row)?,
+        _ => sink.write_all(prefix.as_bytes())?,
+    }
+
+    // Initialize the CSV writer and then write the header row
+    let id = group.unwrap_or(&first.target.id);
+    let file_id = sink.file_id();
+    let logger = FlushLogger::new(
+        sink,
+        id.to_owned(),
+        settings.event_log.clone(),
+        settings
+            .status
+            .as_ref()
+            .map(|registry| Arc::clone(&registry.flushes)),
+        settings.event_logs.clone(),
+        settings.io_limiter.clone(),
+        settings.sync,
+    );
+    let logger = match settings.checksum {
+        true => logger.with_checksum(),
+        false => logger,
+    };
+    let written = logger.written();
+    let flushes = logger.flushes();
+    let backlog = logger.backlog();
+    let peak = logger.peak();
+    let checksum = logger.checksum();
+    let mut writer = WriterBuilder::new()
+        .buffer_capacity(capacity)
+        .from_writer(logger);
+    if settings.sink != SinkType::Stream {
+        writer.write_byte_record(&header_row)?;
+    }
+
+    let ring = match settings.sink {
+        SinkType::Ring => Some(open_ring(
+            path,
+            prefix,
+            &header_row,
+            &header.perf_table,
+            settings,
+        )?),
+        SinkType::File | SinkType::Null | SinkType::Stream => None,
+    };
+
+    Ok(OpenLog {
+        writer,
+        written,
+        flushes,
+        backlog,
+        peak,
+        checksum,
+        file_id,
+        ring,
+        aggregate,
+    })
+}
+
+/// Creates the ring file at the given path, which starts with the same text
+/// as a log file (so that exporting it only has to append the decoded
+/// records)
+fn open_ring(
+    path: &str,
+    prefix: String,
+    header_row: &ByteRecord,
+    perf_table: &TableMetadata,
+    settings: &LogSettings,
+) -> Result<RingBuffer, Error> {
+    let mut metadata = csv::Writer::from_writer(prefix.into_bytes());
+    metadata.write_byte_record(header_row)?;
+    let metadata = metadata
+        .into_inner()
+        .map_err(|err| Error::msg(err.error().to_string()))?;
+    let columns = formats::layout(header_row, perf_table);
+    Ok(RingBuffer::create(
+        Path::new(path),
+        &metadata,
+        columns,
+        settings.ring_capacity,
+    )?)
+}
+
+/// Replaces the CSV writer with one that has a buffer of the given capacity,
+/// flushing the old buffer to the same destination
+fn resize_writer(writer: &mut StatWriter, capacity: usize) -> Result<(), csv::Error> {
+    // The old writer has to be moved out to get its destination back, so an
+    // unused writer takes its place in the meantime
+    let placeholder = WriterBuilder::new().from_writer(FlushLogger::new(
+        Sink::Null(Arc::default()),
+        String::new(),
+        None,
+        None,
+        None,
+        None,
+        SyncPolicy::Never,
+    ));
+    match mem::replace(writer, placeholder).into_inner() {
+        Ok(logger) => {
+            *writer = WriterBuilder::new()
+                .buffer_capacity(capacity)
+                .from_writer(logger);
+            Ok(())
+        },
+        Err(err) => {
+            let io_err = io::Error::new(err.error().kind(), err.error().to_string());
+            *writer = err.into_inner();
+            Err(io_err.into())
+        },
+    }
+}
+
+/// Writes the header of a log file to the stream as a block of comments,
+/// ending with the CSV header row (which starts with the `target_id` and
+/// `provider` columns that prefix each streamed record)
+fn write_stream_header(prefix: &str, header_row: &ByteRecord) -> Result<(), Error> {
+    let mut stream_header_row = ByteRecord::from(vec!["target_id", "provider"]);
+    stream_header_row.extend(header_row);
+    let mut header = csv::Writer::from_writer(prefix.as_bytes().to_vec());
+    header.write_byte_record(&stream_header_row)?;
+    let header = header
+        .into_inner()
+        .map_err(|err| Error::msg(err.error().to_string()))?;
+    StreamSink::write_comment(&String::from_utf8_lossy(&header))?;
+    Ok(())
+}
+
+/// Writes a single record to the ring file if using the ring sink, or to the
+/// CSV writer otherwise
+fn write_record(
+    writer: &mut StatWriter,
+    ring: Option<&mut RingBuffer>,
+    record: &ByteRecord,
+) -> Result<(), csv::Error> {
+    match ring {
+        Some(ring) => {
+            ring.push(record);
+            Ok(())
+        },
+        None => writer.write_byte_record(record),
     }
+}
+
+/// Writes the footer of a log file after its CSV body
+fn write_footer<W: Write>(sink: &mut W, footer: &LogFileFooter) -> Result<(), Error> {
+    // Serialized YAML documents start with a `---` marker, which doubles as
+    // the separator between the CSV body and the footer
+    let footer_str = serde_yaml::to_string(footer)?;
+    if !footer_str.starts_with(formats::SECTION_SEPARATOR) {
+        writeln!(sink, "{}", formats::SECTION_SEPARATOR)?;
+    }
+    writeln!(sink, "{}", footer_str)?;
+    Ok(())
+}
+
+/// Writes the samples collected since the last aggregated record of the
+/// target, if there are any
+fn write_aggregate(
+    member: &mut Member,
+    layout: Option<&AggregateLayout>,
+    writer: &mut StatWriter,
+    ring: Option<&mut RingBuffer>,
+) -> Result<(), csv::Error> {
+    if let (Some(aggregator), Some(layout)) = (&mut member.aggregator, layout) {
+        if !aggregator.is_empty() {
+            let mut record = ByteRecord::new();
+            aggregator.take(&mut record, layout);
+            write_record(writer, ring, &record)?;
+            member.rows += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Builds the CSV header row of a log file, excluding any columns appended by
+/// record processors
+fn header_row(collector: &CollectorImpl, grouped: bool, settings: &LogSettings) -> ByteRecord {
+    let mut header_row = ByteRecord::new();
+    if grouped {
+        header_row.push_field(b"target.id");
+    }
+    header_row.extend(collector.header());
+    for (name, ..) in appended_columns(settings) {
+        header_row.push_field(name.as_bytes());
+    }
+    header_row
+}
+
+/// Gets the declarations of the (integer) columns appended to each record with
+/// information about its collection tick, its target's file descriptors,
+/// scheduler statistics, sockets, GPUs, hardware counters, or derived metrics
+/// (if enabled), and its status
+fn appended_columns(settings: &LogSettings) -> Vec<ColumnDeclaration> {
+    let mut columns = Vec::new();
+    if settings.boottime {
+        columns.push(("read.boottime", Unit::Nanoseconds, ColumnKind::Timestamp));
+        columns.push(("suspended", Unit::Nanoseconds, ColumnKind::Gauge));
+    }
+    if settings.monotonic {
+        columns.push(("read.monotonic", Unit::Nanoseconds, ColumnKind::Timestamp));
+    }
+    if settings.latency {
+        columns.push(("latency", Unit::Nanoseconds, ColumnKind::Gauge));
+    }
+    if settings.fds.is_some() {
+        columns.extend(FD_COLUMNS);
+    }
+    if settings.sched {
+        columns.extend(SCHED_COLUMNS);
+    }
+    if settings.net_sockets.is_some() {
+        columns.extend(SOCKET_COLUMNS);
+    }
+    #[cfg(feature = "nvml")]
+    if settings.gpu.is_some() {
+        columns.extend(GPU_COLUMNS);
+    }
+    #[cfg(feature = "perf")]
+    if settings.perf {
+        columns.extend(PERF_COLUMNS);
+    }
+    if settings.derived {
+        columns.extend(DERIVED_COLUMNS);
+    }
+    columns.push((STATUS_COLUMN, Unit::Unitless, ColumnKind::Status));
+    columns
+}
+
+/// Builds the column registry passed to each record processor, each of which
+/// includes the columns appended by the processors before it
+fn processor_columns(
+    collector: &CollectorImpl,
+    grouped: bool,
+    settings: &LogSettings,
+) -> Vec<ColumnRegistry> {
+    let mut columns = ColumnRegistry::new(&header_row(collector, grouped, settings));
+    let mut registries = Vec::with_capacity(settings.processors.len());
+    for processor in &settings.processors {
+        registries.push(columns.clone());
+        for (name, _) in processor.columns(collector.get_type()) {
+            columns.push(name);
+        }
+    }
+    registries
+}
 
-    /// Collects the current statistics for the given target,
-    /// writing the CSV entries to the writer.
-    pub fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> Result<(), csv::Error> {
-        self.collector.collect(&mut self.writer, working_buffers)
+/// Appends the nanoseconds since boot (including time spent suspended) to the
+/// record, along with the nanoseconds that the system was suspended for
+/// before the record (left empty if it wasn't)
+fn push_boottime_fields(record: &mut ByteRecord, suspended: Option<Duration>) {
+    let mut itoa_buffer = itoa::Buffer::new();
+    record.push_field(itoa_buffer.format(util::boottime_ts()).as_bytes());
+    match suspended {
+        Some(gap) => record.push_field(itoa_buffer.format(gap.as_nanos()).as_bytes()),
+        None => record.push_field(b""),
     }
 }
 
-/// Constructs the log filepath for the given target id
-fn construct_log_path(id: &str, logs_location: &Path) -> Result<String, io::Error> {
-    // Construct filename
-    let filename = format!("{}_{}.log", id.to_string(), util::second_ts().to_string());
+/// Constructs the log filepath for the given target id (or group name) in the
+/// given directory, which ends in `.ring` instead of `.log` when using the
+/// ring sink
+pub fn construct_log_path(
+    id: &str,
+    prefix: Option<&str>,
+    logs_location: &Path,
+    sink: SinkType,
+) -> Result<String, io::Error> {
+    let extension = match sink {
+        SinkType::Ring => "ring",
+        SinkType::File | SinkType::Null | SinkType::Stream => "log",
+    };
+    // Construct filename, escaping ids that are paths (such as cgroups) and
+    // group names that are globs
+    let escape = |name: &str| {
+        name.trim_start_matches('/')
+            .replace('/', "-")
+            .replace(&['*', '?'][..], "_")
+    };
+    let escaped_id = match prefix {
+        Some(prefix) => format!("{}_{}", escape(prefix), escape(id)),
+        None => escape(id),
+    };
+    let filename = format!("{}_{}.{}", escaped_id, util::second_ts(), extension);
 
     // Join paths
-    let base = Path::new(logs_location);
     let filename_path = Path::new(&filename);
-    match base.join(filename_path).into_os_string().into_string() {
+    match logs_location
+        .join(filename_path)
+        .into_os_string()
+        .into_string()
+    {
         Ok(path) => Ok(path),
         Err(_) => Err(io::Error::new(
             ErrorKind::InvalidInput,
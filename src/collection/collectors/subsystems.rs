@@ -0,0 +1,112 @@
+use crate::cli::ParseFailure;
+use std::str::FromStr;
+use strum_macros::IntoStaticStr;
+
+/// Group of related statistics that can be collected (or skipped) as a whole.
+/// Named after the cgroup v1 subsystems; each one maps to the cgroup v2
+/// controller of the same name, except for `blkio` (the `io` controller).
+#[derive(IntoStaticStr, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum Subsystem {
+    Pids,
+    /// Includes the `cpuacct` subsystem on cgroup v1
+    Cpu,
+    /// Only collected on cgroup v1
+    Cpuset,
+    Memory,
+    /// Only collected on cgroup v1
+    Hugetlb,
+    Blkio,
+}
+
+impl FromStr for Subsystem {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pids" => Ok(Self::Pids),
+            "cpu" | "cpuacct" => Ok(Self::Cpu),
+            "cpuset" => Ok(Self::Cpuset),
+            "memory" => Ok(Self::Memory),
+            "hugetlb" => Ok(Self::Hugetlb),
+            "blkio" | "io" => Ok(Self::Blkio),
+            _ => Err(ParseFailure::new(String::from("subsystem"), s.to_owned())),
+        }
+    }
+}
+
+impl Subsystem {
+    /// Every subsystem, in order
+    pub const VARIANTS: [Self; 6] = [
+        Self::Pids,
+        Self::Cpu,
+        Self::Cpuset,
+        Self::Memory,
+        Self::Hugetlb,
+        Self::Blkio,
+    ];
+
+    const fn bit(self) -> u8 {
+        match self {
+            Self::Pids => 1,
+            Self::Cpu => 1 << 1,
+            Self::Cpuset => 1 << 2,
+            Self::Memory => 1 << 3,
+            Self::Hugetlb => 1 << 4,
+            Self::Blkio => 1 << 5,
+        }
+    }
+}
+
+/// Set of subsystems whose statistics are collected, which determines both
+/// the files that collectors open and the columns of their log files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Subsystems(u8);
+
+impl Subsystems {
+    /// Set containing every subsystem
+    pub const ALL: Self = Self(0b11_1111);
+    /// Set containing no subsystems
+    pub const NONE: Self = Self(0);
+
+    /// Creates the set of the given subsystems, where an empty list means
+    /// that all subsystems are collected
+    #[must_use]
+    pub fn from_list(subsystems: &[Subsystem]) -> Self {
+        match subsystems.is_empty() {
+            true => Self::ALL,
+            false => Self(subsystems.iter().fold(0, |bits, s| bits | s.bit())),
+        }
+    }
+
+    /// Whether the statistics of the subsystem are collected
+    #[must_use]
+    pub const fn contains(self, subsystem: Subsystem) -> bool { self.0 & subsystem.bit() != 0 }
+
+    /// Gets the set with the subsystem added to it
+    #[must_use]
+    pub const fn with(self, subsystem: Subsystem) -> Self { Self(self.0 | subsystem.bit()) }
+
+    /// Gets the subsystems that are in both sets
+    #[must_use]
+    pub const fn intersection(self, other: Self) -> Self { Self(self.0 & other.0) }
+
+    /// Gets the subsystems that are in this set but not in the other one
+    #[must_use]
+    pub const fn difference(self, other: Self) -> Self { Self(self.0 & !other.0) }
+
+    /// Whether no subsystems are collected
+    #[must_use]
+    pub const fn is_empty(self) -> bool { self.0 == 0 }
+
+    /// Whether the files in the given cgroup v1 subsystem directory (such as
+    /// `cpuacct`) are read
+    #[must_use]
+    pub fn contains_v1_directory(self, directory: &str) -> bool {
+        matches!(directory.parse(), Ok(subsystem) if self.contains(subsystem))
+    }
+}
+
+impl Default for Subsystems {
+    fn default() -> Self { Self::ALL }
+}
@@ -0,0 +1,195 @@
+use crate::collection::buffers::WorkingBuffers;
+use crate::collection::collectors::Collector as CollectorTrait;
+use crate::formats::{Column, ColumnDeclaration, ColumnKind, ColumnType, TableMetadata, Unit};
+use crate::util::{self, CgroupDriver, CgroupPath, CgroupVersion};
+use anyhow::Error;
+use csv::ByteRecord;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Columns of each synthetic record (after the `read` column), which use the
+/// names of the matching cgroup v1 columns
+const COLUMNS: &[ColumnDeclaration] = &[
+    ("pids.current", Unit::Count, ColumnKind::Gauge),
+    ("cpu.usage.total", Unit::Nanoseconds, ColumnKind::Counter),
+    ("cpu.usage.system", Unit::Nanoseconds, ColumnKind::Counter),
+    ("cpu.usage.user", Unit::Nanoseconds, ColumnKind::Counter),
+    ("memory.usage.current", Unit::Bytes, ColumnKind::Gauge),
+    ("memory.limit.hard", Unit::Bytes, ColumnKind::Limit),
+    ("blkio.service.bytes.read", Unit::Bytes, ColumnKind::Counter),
+    (
+        "blkio.service.bytes.write",
+        Unit::Bytes,
+        ColumnKind::Counter,
+    ),
+];
+
+/// Memory limit of each synthetic target, in bytes
+const MEMORY_LIMIT: u64 = 512 * 1024 * 1024;
+
+/// Memory usage that each synthetic target starts at, in bytes
+const MEMORY_START: u64 = 64 * 1024 * 1024;
+
+/// Largest change in memory usage between records, in bytes
+const MEMORY_STEP: u64 = 4 * 1024 * 1024;
+
+/// Largest number of bytes read or written between records
+const IO_STEP: u64 = 256 * 1024;
+
+/// Maximum number of processes of each synthetic target
+const MAX_PIDS: u64 = 16;
+
+/// Generates plausible statistics for a synthetic target without reading any
+/// files, so that rAdvisor can be run end-to-end (such as for demos or to
+/// test downstream tooling) on machines without containers. Each target's
+/// values follow a random walk that is determined by its seed: CPU usage
+/// advances by a varying share of the wall-clock time between records,
+/// memory usage wanders below a fixed limit, and I/O counters increase.
+pub struct Collector {
+    /// Non-existent cgroup that identifies the target, so that other
+    /// statistics that read its cgroup (such as `--fds`) find no processes
+    cgroup:   CgroupPath,
+    rng:      u64,
+    header:   ByteRecord,
+    last:     Option<Instant>,
+    /// Share of one CPU that the target currently uses, in thousandths
+    cpu_load: u64,
+    cpu_user: u64,
+    cpu_sys:  u64,
+    memory:   u64,
+    pids:     u64,
+    io_read:  u64,
+    io_write: u64,
+}
+
+impl Collector {
+    /// Creates a collector for a synthetic target, whose values are
+    /// determined by the seed
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        let mut header = ByteRecord::new();
+        header.push_field(b"read");
+        for (name, ..) in COLUMNS {
+            header.push_field(name.as_bytes());
+        }
+
+        Self {
+            cgroup: CgroupPath {
+                path:    PathBuf::from(format!("radvisor-synthetic/{}", seed)),
+                driver:  CgroupDriver::Cgroupfs,
+                version: CgroupVersion::V2,
+            },
+            // The state of xorshift can't be 0
+            rng: seed | 1,
+            header,
+            last: None,
+            cpu_load: 250,
+            cpu_user: 0,
+            cpu_sys: 0,
+            memory: MEMORY_START,
+            pids: 1,
+            io_read: 0,
+            io_write: 0,
+        }
+    }
+
+    /// Gets the (non-existent) cgroup that identifies the target
+    #[must_use]
+    pub const fn cgroup(&self) -> &CgroupPath { &self.cgroup }
+
+    /// Gets the next pseudo-random number in the sequence (using xorshift64)
+    fn next(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    /// Gets a pseudo-random number in `[0, bound)`
+    fn below(&mut self, bound: u64) -> u64 { self.next() % bound.max(1) }
+
+    /// Advances each value, based on the time elapsed since the last record
+    fn step(&mut self) {
+        let now = Instant::now();
+        let elapsed = self.last.map_or(0, |last| {
+            util::remap::<_, u64>(now.duration_since(last).as_nanos())
+        });
+        self.last = Some(now);
+
+        // Let the CPU load wander between 5% and 95% of a CPU
+        self.cpu_load = (self.cpu_load + self.below(101))
+            .saturating_sub(50)
+            .clamp(50, 950);
+        let used = elapsed / 1000 * self.cpu_load;
+        let system = used / 5;
+        self.cpu_sys += system;
+        self.cpu_user += used - system;
+
+        let delta = self.below(2 * MEMORY_STEP + 1);
+        self.memory = (self.memory + delta)
+            .saturating_sub(MEMORY_STEP)
+            .clamp(MEMORY_STEP, MEMORY_LIMIT);
+
+        if self.below(20) == 0 {
+            self.pids = 1 + self.below(MAX_PIDS);
+        }
+
+        self.io_read += self.below(IO_STEP);
+        self.io_write += self.below(IO_STEP / 2);
+    }
+}
+
+impl CollectorTrait for Collector {
+    /// The seed is already included in the target's metadata
+    fn metadata(&mut self) -> Option<serde_yaml::Value> { None }
+
+    fn table_metadata(&mut self) -> TableMetadata {
+        let mut columns: BTreeMap<String, Column> = BTreeMap::new();
+        columns.insert(String::from("read"), Column::Scalar {
+            r#type: ColumnType::Epoch19,
+        });
+        let mut table = TableMetadata {
+            delimiter: String::from(","),
+            columns,
+            schema: Vec::new(),
+        };
+        table.declare("read", Unit::Nanoseconds, ColumnKind::Timestamp);
+        for &(name, unit, kind) in COLUMNS {
+            table.declare(name, unit, kind);
+        }
+        table
+    }
+
+    fn get_type(&self) -> &'static str { "synthetic" }
+
+    fn init(&mut self) -> Result<(), Error> { Ok(()) }
+
+    fn header(&self) -> &ByteRecord { &self.header }
+
+    fn collect(&mut self, working_buffers: &mut WorkingBuffers) -> bool {
+        self.step();
+
+        let mut itoa_buffer = itoa::Buffer::new();
+        let values = [
+            self.pids,
+            self.cpu_user + self.cpu_sys,
+            self.cpu_sys,
+            self.cpu_user,
+            self.memory,
+            MEMORY_LIMIT,
+            self.io_read,
+            self.io_write,
+        ];
+        working_buffers
+            .record
+            .push_field(itoa_buffer.format(util::nano_ts()).as_bytes());
+        for value in values {
+            working_buffers
+                .record
+                .push_field(itoa_buffer.format(value).as_bytes());
+        }
+
+        true
+    }
+}
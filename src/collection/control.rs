@@ -0,0 +1,147 @@
+//! Unix socket that accepts commands to control collection while it runs,
+//! such as from experiment harnesses that need to coordinate collection
+//! windows. Each line sent to the socket is a single command, which is
+//! answered with a line containing either `ok` or `error: <reason>`:
+//!
+//! - `pause [<target id>]` - stops writing records for the target (or for all
+//!   targets) until it is resumed
+//! - `resume [<target id>]` - resumes writing records for the target (even if
+//!   all targets are paused), or for all targets
+//! - `flush` - flushes the buffers of all log files to their destinations
+//! - `interval <duration>` - changes the collection interval (such as `interval
+//!   100ms`)
+//!
+//! Commands are applied by the collection thread in between collection ticks,
+//! so they take effect by the next tick.
+
+use crate::collection::Control;
+use crate::shell::Shell;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+
+/// Bound control socket, whose file is removed once it is dropped
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) { let _ = fs::remove_file(&self.path); }
+}
+
+/// Binds the control socket to the given path, forwarding the commands that
+/// it receives to the collection thread on a background thread. A socket
+/// left behind at the path by a previous run is replaced.
+pub fn serve(
+    path: &Path,
+    control: Sender<Control>,
+    shell: Arc<Shell>,
+) -> io::Result<ControlSocket> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a file that isn't a socket already exists at the path",
+            ))
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+        Err(err) => return Err(err),
+    }
+
+    let listener = UnixListener::bind(path)?;
+    shell.status("Serving", format!("control socket at {}", path.display()));
+
+    thread::Builder::new()
+        .name(String::from("control"))
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        shell.warn(format!("Could not accept control connection: {}", err));
+                        continue;
+                    },
+                };
+
+                // Clients can keep their connection open to send commands over
+                // time, so each one is handled on its own thread
+                let control = control.clone();
+                let shell = Arc::clone(&shell);
+                let _ = thread::Builder::new()
+                    .name(String::from("control-conn"))
+                    .spawn(move || {
+                        if let Err(err) = handle_connection(stream, &control, &shell) {
+                            shell.verbose(|sh| {
+                                sh.warn(format!("Could not respond to control command: {}", err));
+                            });
+                        }
+                    });
+            }
+        })?;
+
+    Ok(ControlSocket {
+        path: path.to_owned(),
+    })
+}
+
+/// Reads commands from a single connection until it is closed, answering
+/// each one
+fn handle_connection(
+    stream: UnixStream,
+    control: &Sender<Control>,
+    shell: &Shell,
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let command = line.trim();
+        if command.is_empty() {
+            continue;
+        }
+
+        let result = parse(command).and_then(|message| {
+            control
+                .send(message)
+                .map_err(|_| String::from("collection has stopped"))
+        });
+        match result {
+            Ok(()) => {
+                shell.verbose(|sh| sh.info(format!("Received control command '{}'", command)));
+                writeln!(writer, "ok")?;
+            },
+            Err(reason) => writeln!(writer, "error: {}", reason)?,
+        }
+    }
+    Ok(())
+}
+
+/// Parses a single command into the control message sent to the collection
+/// thread
+fn parse(command: &str) -> Result<Control, String> {
+    let mut parts = command.split_whitespace();
+    let name = parts.next().unwrap_or_default();
+    let argument = parts.next().map(String::from);
+    if parts.next().is_some() {
+        return Err(format!("too many arguments for command '{}'", name));
+    }
+
+    match (name, argument) {
+        ("pause", target) => Ok(Control::Pause(target)),
+        ("resume", target) => Ok(Control::Resume(target)),
+        ("flush", None) => Ok(Control::Flush),
+        ("interval", Some(interval)) => match humantime::parse_duration(&interval) {
+            Ok(interval) if interval.as_nanos() > 0 => Ok(Control::SetInterval(interval)),
+            Ok(_) => Err(String::from("interval must be greater than zero")),
+            Err(err) => Err(format!("invalid interval '{}': {}", interval, err)),
+        },
+        ("interval", None) => Err(String::from("missing interval")),
+        ("flush", Some(_)) => Err(String::from("flush does not take an argument")),
+        (name, _) => Err(format!("unknown command '{}'", name)),
+    }
+}
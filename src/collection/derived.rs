@@ -0,0 +1,156 @@
+//! Computes the metrics that are most commonly derived from each target's
+//! statistics in post-processing (CPU utilization, the share of throttled CFS
+//! periods, and memory utilization against the limit) from its current and
+//! previous records, appending them to each record as `derived.*` columns.
+//! Since every column holds integers, they are given in thousandths.
+
+use crate::collection::processor::ColumnRegistry;
+use crate::formats::{ColumnDeclaration, ColumnKind, Unit};
+use csv::ByteRecord;
+
+/// The (integer) columns appended to each record
+pub const DERIVED_COLUMNS: &[ColumnDeclaration] = &[
+    (
+        "derived.cpu.millicores",
+        Unit::Millicores,
+        ColumnKind::Gauge,
+    ),
+    (
+        "derived.cpu.throttled.permille",
+        Unit::Permille,
+        ColumnKind::Gauge,
+    ),
+    ("derived.memory.permille", Unit::Permille, ColumnKind::Gauge),
+];
+
+/// Memory limits at least this large are treated as no limit, since cgroup v1
+/// reports the lack of a limit as the largest page-aligned `i64`
+const UNLIMITED_MEMORY: u64 = 1 << 62;
+
+/// Indices of the columns that the metrics are derived from, which differ
+/// between cgroup versions. Columns that the collector doesn't produce (such
+/// as when a controller isn't enabled) leave their metrics empty.
+struct Layout {
+    read:         Option<usize>,
+    /// Index of the CPU usage column, along with its unit in nanoseconds
+    cpu_usage:    Option<(usize, u64)>,
+    periods:      Option<usize>,
+    throttled:    Option<usize>,
+    memory_usage: Option<usize>,
+    memory_limit: Option<usize>,
+}
+
+/// Cumulative values of a single record, which metrics over the interval
+/// since the previous record are derived from
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    read:      u64,
+    /// CPU usage in nanoseconds
+    cpu_usage: Option<u64>,
+    periods:   Option<u64>,
+    throttled: Option<u64>,
+}
+
+/// Derives the metrics of a single target
+pub struct DerivedColumns {
+    layout:   Layout,
+    previous: Option<Sample>,
+}
+
+impl DerivedColumns {
+    /// Creates the derived metrics of a target whose collector produces
+    /// records with the given header (preceded by the `target.id` column if
+    /// its log file is grouped)
+    #[must_use]
+    pub fn new(header: &ByteRecord, grouped: bool) -> Self {
+        let columns = ColumnRegistry::new(header);
+        let offset = usize::from(grouped);
+        let index = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| columns.index_of(name))
+                .map(|index| index + offset)
+        };
+        let cpu_usage = match index(&["cpu.usage.total"]) {
+            Some(index) => Some((index, 1)),
+            None => index(&["cpu.stat/usage_usec"]).map(|index| (index, 1000)),
+        };
+        Self {
+            layout:   Layout {
+                read: index(&["read"]),
+                cpu_usage,
+                periods: index(&["cpu.throttling.periods", "cpu.stat/nr_periods"]),
+                throttled: index(&["cpu.throttling.throttled.count", "cpu.stat/nr_throttled"]),
+                memory_usage: index(&["memory.usage.current", "memory.current"]),
+                memory_limit: index(&["memory.limit.hard", "memory.max"]),
+            },
+            previous: None,
+        }
+    }
+
+    /// Appends the `derived.*` fields to the record, which starts with the
+    /// collector's fields. The CPU metrics are left empty for the first
+    /// record, and whenever a counter decreased since the previous one.
+    pub fn push_fields(&mut self, record: &mut ByteRecord) {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .and_then(atoi::atoi::<u64>)
+        };
+        let sample = field(self.layout.read).map(|read| Sample {
+            read,
+            cpu_usage: self
+                .layout
+                .cpu_usage
+                .and_then(|(index, unit)| field(Some(index))?.checked_mul(unit)),
+            periods: field(self.layout.periods),
+            throttled: field(self.layout.throttled),
+        });
+        let memory = match (
+            field(self.layout.memory_usage),
+            field(self.layout.memory_limit),
+        ) {
+            (Some(usage), Some(limit)) if limit > 0 && limit < UNLIMITED_MEMORY => {
+                Some(u128::from(usage) * 1000 / u128::from(limit))
+            },
+            _ => None,
+        };
+
+        let (millicores, throttled) = match (self.previous, sample) {
+            (Some(previous), Some(current)) => (
+                cpu_millicores(previous, current),
+                throttled_permille(previous, current),
+            ),
+            _ => (None, None),
+        };
+        self.previous = sample;
+
+        let mut itoa_buffer = itoa::Buffer::new();
+        for value in [millicores, throttled, memory] {
+            match value {
+                Some(value) => record.push_field(itoa_buffer.format(value).as_bytes()),
+                None => record.push_field(b""),
+            }
+        }
+    }
+}
+
+/// Gets the thousandths of a CPU that the target used on average between the
+/// samples
+fn cpu_millicores(previous: Sample, current: Sample) -> Option<u128> {
+    let elapsed = current.read.checked_sub(previous.read).filter(|&e| e > 0)?;
+    let used = current.cpu_usage?.checked_sub(previous.cpu_usage?)?;
+    Some(u128::from(used) * 1000 / u128::from(elapsed))
+}
+
+/// Gets the thousandths of the CFS periods between the samples that the
+/// target was throttled in, if any periods elapsed (which they only do while
+/// the target has a CPU quota and is runnable)
+fn throttled_permille(previous: Sample, current: Sample) -> Option<u128> {
+    let periods = current
+        .periods?
+        .checked_sub(previous.periods?)
+        .filter(|&p| p > 0)?;
+    let throttled = current.throttled?.checked_sub(previous.throttled?)?;
+    Some(u128::from(throttled) * 1000 / u128::from(periods))
+}
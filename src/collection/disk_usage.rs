@@ -0,0 +1,315 @@
+use crate::cli::ParseFailure;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Minimum time between measurements of the log directory's disk usage, since
+/// each one lists the entire directory
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happens once the log directory exceeds its maximum disk usage,
+/// controllable via a CLI flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiskUsagePolicy {
+    /// Collection stops with an error
+    Stop,
+    /// The oldest log files from this run that are no longer being written
+    /// (such as rotated log files) are deleted until the directory is within
+    /// the limit. Collection only stops if that isn't enough.
+    Evict,
+}
+
+impl FromStr for DiskUsagePolicy {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "stop" => Ok(Self::Stop),
+            "evict" => Ok(Self::Evict),
+            _ => Err(ParseFailure::new(
+                String::from("disk usage policy"),
+                s.to_owned(),
+            )),
+        }
+    }
+}
+
+/// Result of a disk usage check that found the log directories over their
+/// limit
+#[derive(Debug, Default, PartialEq)]
+pub struct QuotaCheck {
+    /// Maximum total size of the log directories
+    pub limit:    u64,
+    /// Total size of this run's log files after any evictions
+    pub usage:    u64,
+    /// Log files that were deleted to get back within the limit
+    pub evicted:  Vec<PathBuf>,
+    /// Whether the directories are still over the limit, such that collection
+    /// should stop
+    pub exceeded: bool,
+}
+
+/// Maximum disk usage of the log directories (the log directory, along with
+/// the spool directory if there is one), which is measured as the total size
+/// of the log files that this run wrote to them (including rotated log files,
+/// but not files from previous runs or other programs)
+pub struct DiskQuota {
+    limit:       u64,
+    policy:      DiskUsagePolicy,
+    directories: Vec<PathBuf>,
+    /// Names of the log files this run created, which are tracked by name
+    /// since spooled log files are moved to the log directory
+    created:     HashSet<OsString>,
+    checked_at:  Option<Instant>,
+    exceeded:    bool,
+}
+
+impl DiskQuota {
+    #[must_use]
+    pub fn new(limit: u64, policy: DiskUsagePolicy, directories: Vec<PathBuf>) -> Self {
+        Self {
+            limit,
+            policy,
+            directories,
+            created: HashSet::new(),
+            checked_at: None,
+            exceeded: false,
+        }
+    }
+
+    /// Whether the log directories exceeded the limit, such that collection
+    /// was stopped
+    #[must_use]
+    pub const fn is_exceeded(&self) -> bool { self.exceeded }
+
+    /// Directories whose disk usage is measured
+    #[must_use]
+    pub fn directories(&self) -> &[PathBuf] { &self.directories }
+
+    /// Records that this run created the log file, so that it counts towards
+    /// the disk usage (even once it is rotated or moved out of the spool
+    /// directory) and can be evicted once it is no longer being written
+    pub fn track(&mut self, path: &Path) {
+        if let Some(name) = path.file_name() {
+            if !self.created.contains(name) {
+                self.created.insert(name.to_owned());
+            }
+        }
+    }
+
+    /// Measures the disk usage of this run's log files (at most once per check
+    /// interval), evicting the ones that aren't open (determined by the given
+    /// predicate) if it is over the limit and the policy allows. Returns the
+    /// result if the log files were over the limit.
+    pub fn check<F>(&mut self, is_open: F) -> io::Result<Option<QuotaCheck>>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        if matches!(self.checked_at, Some(checked_at) if checked_at.elapsed() < CHECK_INTERVAL) {
+            return Ok(None);
+        }
+        self.checked_at = Some(Instant::now());
+
+        let mut files = Vec::new();
+        for directory in &self.directories {
+            match fs::read_dir(directory) {
+                Ok(entries) => files.extend(
+                    list_files(entries)
+                        .into_iter()
+                        .filter(|file| self.is_tracked(&file.path)),
+                ),
+                // Nothing has been written yet
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+                Err(err) => return Err(err),
+            }
+        }
+        let mut usage = files.iter().map(|file| file.size).sum::<u64>();
+        if usage <= self.limit {
+            return Ok(None);
+        }
+
+        let mut check = QuotaCheck {
+            limit: self.limit,
+            ..QuotaCheck::default()
+        };
+        if self.policy == DiskUsagePolicy::Evict {
+            let mut candidates = files
+                .into_iter()
+                .filter(|file| is_log_file(&file.path) && !is_open(&file.path))
+                .collect::<Vec<_>>();
+            candidates.sort_by_key(|file| file.modified);
+            for file in candidates {
+                if usage <= self.limit {
+                    break;
+                }
+
+                match fs::remove_file(&file.path) {
+                    Ok(()) => {
+                        if let Some(name) = file.path.file_name() {
+                            self.created.remove(name);
+                        }
+                        check.evicted.push(file.path);
+                    },
+                    // The file was moved out of the spool directory (or
+                    // removed by someone else) since the directory was
+                    // listed, so it is treated as already evicted
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+                    Err(err) => return Err(err),
+                }
+                usage = usage.saturating_sub(file.size);
+            }
+        }
+
+        check.usage = usage;
+        check.exceeded = usage > self.limit;
+        self.exceeded = check.exceeded;
+        Ok(Some(check))
+    }
+
+    /// Whether the file is one of the log files this run created
+    fn is_tracked(&self, path: &Path) -> bool {
+        path.file_name()
+            .map_or(false, |name| self.created.contains(name))
+    }
+}
+
+/// Regular file in one of the log directories
+struct DirectoryFile {
+    path:     PathBuf,
+    size:     u64,
+    modified: Option<SystemTime>,
+}
+
+/// Lists the regular files in a log directory, skipping any that
+/// disappeared while listing
+fn list_files(entries: fs::ReadDir) -> Vec<DirectoryFile> {
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            match metadata.is_file() {
+                true => Some(DirectoryFile {
+                    path:     entry.path(),
+                    size:     metadata.len(),
+                    modified: metadata.modified().ok(),
+                }),
+                false => None,
+            }
+        })
+        .collect()
+}
+
+/// Whether the file is a log file or ring file written by rAdvisor, which are
+/// named `<target>_<timestamp>.log` (or `.ring`). Other files in the log
+/// directory (such as the lifecycle log) are never evicted.
+fn is_log_file(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let stem = path.file_stem().and_then(|stem| stem.to_str());
+    match (extension, stem.and_then(|stem| stem.rsplit_once('_'))) {
+        (Some("log" | "ring"), Some((_, timestamp))) => {
+            !timestamp.is_empty() && timestamp.bytes().all(|b| b.is_ascii_digit())
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    /// Creates an empty scratch directory for the test
+    fn scratch(test: &str) -> PathBuf {
+        let directory =
+            env::temp_dir().join(format!("radvisor-disk-usage-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Writes a file of the given size, returning its path
+    fn write(directory: &Path, name: &str, size: usize) -> PathBuf {
+        let path = directory.join(name);
+        fs::write(&path, vec![b'x'; size]).unwrap();
+        path
+    }
+
+    #[test]
+    fn log_and_ring_files_are_log_files() {
+        assert!(is_log_file(Path::new("/logs/abc123_1600000000.log")));
+        assert!(is_log_file(Path::new("/logs/abc123_1600000000.ring")));
+        assert!(is_log_file(Path::new(
+            "prefix_system.slice-nginx.service_1600000000.log"
+        )));
+    }
+
+    #[test]
+    fn other_files_are_not_log_files() {
+        assert!(!is_log_file(Path::new("/logs/events.log")));
+        assert!(!is_log_file(Path::new("/logs/abc123_.log")));
+        assert!(!is_log_file(Path::new("/logs/abc123_16000a0000.log")));
+        assert!(!is_log_file(Path::new("/logs/abc123_1600000000.csv")));
+        assert!(!is_log_file(Path::new(
+            "/logs/abc123_1600000000.log.partial"
+        )));
+        assert!(!is_log_file(Path::new("/logs/abc123_1600000000")));
+    }
+
+    #[test]
+    fn only_tracked_files_count() {
+        let directory = scratch("tracked");
+        write(&directory, "previous_1500000000.log", 1000);
+        let current = write(&directory, "current_1600000000.log", 10);
+
+        let mut quota = DiskQuota::new(100, DiskUsagePolicy::Stop, vec![directory.clone()]);
+        quota.track(&current);
+        assert_eq!(quota.check(|_| false).unwrap(), None);
+        assert!(!quota.is_exceeded());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn stop_policy_exceeds_without_evicting() {
+        let directory = scratch("stop");
+        let current = write(&directory, "current_1600000000.log", 200);
+
+        let mut quota = DiskQuota::new(100, DiskUsagePolicy::Stop, vec![directory.clone()]);
+        quota.track(&current);
+        let check = quota.check(|_| false).unwrap().unwrap();
+        assert_eq!(check.usage, 200);
+        assert!(check.exceeded && check.evicted.is_empty());
+        assert!(quota.is_exceeded() && current.exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn evicts_only_closed_files_from_this_run() {
+        let directory = scratch("evict");
+        let previous = write(&directory, "previous_1500000000.log", 1000);
+        let rotated = write(&directory, "current_1600000000.log", 100);
+        let open = write(&directory, "current_1600000060.log", 50);
+
+        let mut quota = DiskQuota::new(100, DiskUsagePolicy::Evict, vec![directory.clone()]);
+        quota.track(&rotated);
+        quota.track(&open);
+        let check = quota.check(|path| path == open).unwrap().unwrap();
+        assert_eq!(check.evicted, vec![rotated.clone()]);
+        assert_eq!(check.usage, 50);
+        assert!(!check.exceeded && !quota.is_exceeded());
+        assert!(!rotated.exists() && open.exists() && previous.exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn missing_directories_are_empty() {
+        let directory =
+            env::temp_dir().join(format!("radvisor-disk-usage-missing-{}", process::id()));
+        let mut quota = DiskQuota::new(0, DiskUsagePolicy::Stop, vec![directory]);
+        assert_eq!(quota.check(|_| false).unwrap(), None);
+    }
+}
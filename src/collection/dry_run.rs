@@ -0,0 +1,184 @@
+//! Observe-only mode (`--dry-run`), which consumes the events of the polling
+//! thread like normal collection, but only prints what would be collected
+//! instead of opening any log files
+
+use crate::channel::EventReceiver;
+use crate::cli::CollectionOptions;
+use crate::collection::collectors::{self, Collector, CollectorImpl, CollectorOptions};
+use crate::collection::naming::ShortNames;
+use crate::collection::{file_prefix, Control};
+use crate::shared::{CollectionEvent, Id, IntervalWorkerContext};
+use crate::shell::Shell;
+use crate::timer::{Stoppable, Timer};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+
+/// Log file that would be written, along with the targets that would be
+/// written to it
+struct PlannedLog {
+    path:    String,
+    targets: usize,
+}
+
+/// Thread function that prints each target that would be collected (along
+/// with its cgroup, collector, and log file) as the polling thread discovers
+/// it, until collection is terminated
+pub fn run(
+    rx: &EventReceiver,
+    control: (Sender<Control>, Receiver<Control>),
+    context: IntervalWorkerContext,
+    options: &CollectionOptions,
+) {
+    context
+        .shell
+        .status("Beginning", "dry run; no log files will be written");
+    check_directory(&options.directory, &context.shell);
+
+    // Hand termination off to this thread, as in normal collection
+    let (timer, stop_handle) = Timer::new(context.interval, "collect");
+    let (control_tx, control_rx) = control;
+    let stop_handle_c = stop_handle.clone();
+    let mut term_rx = context.term_rx;
+    thread::Builder::new()
+        .name(String::from("collect-term"))
+        .spawn(move || {
+            term_rx.recv().unwrap();
+            let _ = control_tx.send(Control::Terminate);
+            stop_handle_c.stop();
+        })
+        .unwrap();
+
+    let mut logs: HashMap<String, PlannedLog> = HashMap::new();
+    let mut owners: HashMap<Id, String> = HashMap::new();
+    let mut names = options.short_names.then(ShortNames::default);
+    for _ in timer {
+        for event in rx.try_iter() {
+            handle_event(
+                event,
+                &mut logs,
+                &mut owners,
+                names.as_mut(),
+                options,
+                &context.shell,
+            );
+        }
+
+        let terminate = control_rx
+            .try_iter()
+            .any(|control| matches!(control, Control::Terminate));
+        if terminate {
+            break;
+        }
+    }
+
+    stop_handle.stop();
+}
+
+fn handle_event(
+    event: CollectionEvent,
+    logs: &mut HashMap<String, PlannedLog>,
+    owners: &mut HashMap<Id, String>,
+    names: Option<&mut ShortNames>,
+    options: &CollectionOptions,
+    shell: &Shell,
+) {
+    match event {
+        CollectionEvent::Start { mut target, method } => {
+            if let (Some(names), None) = (names, &target.file_name) {
+                target.file_name = Some(names.assign(&target));
+            }
+            let collector = CollectorImpl::new(method, CollectorOptions::default());
+            let name = target
+                .group
+                .clone()
+                .unwrap_or_else(|| target.log_name().to_owned());
+            if !logs.contains_key(&name) {
+                let path = match collectors::construct_log_path(
+                    &name,
+                    file_prefix(options),
+                    &options.directory,
+                    options.sink,
+                ) {
+                    Ok(path) => path,
+                    Err(err) => {
+                        shell.error(format!(
+                            "Could not determine log file for target {}: {}",
+                            target.id, err
+                        ));
+                        return;
+                    },
+                };
+                logs.insert(name.clone(), PlannedLog { path, targets: 0 });
+            }
+
+            let log = logs.get_mut(&name).unwrap();
+            log.targets += 1;
+            let cgroup = collector.cgroup();
+            shell.status(
+                "Would collect",
+                format!(
+                    "target '{}' ({}) from cgroup {} ({} collector, {} driver) into {}",
+                    target.name,
+                    target.id,
+                    Path::new("/").join(&cgroup.path).display(),
+                    collector.get_type(),
+                    cgroup.driver,
+                    log.path
+                ),
+            );
+            owners.insert(target.id, name);
+        },
+        CollectionEvent::Stop(id) => {
+            if let Some(names) = names {
+                names.release(&id);
+            }
+            if let Some(name) = owners.remove(&id) {
+                shell.status("Would stop", format!("collecting target {}", id));
+                let closed = match logs.get_mut(&name) {
+                    Some(log) => {
+                        log.targets -= 1;
+                        log.targets == 0
+                    },
+                    None => false,
+                };
+                if let (true, Some(log)) = (closed, logs.remove(&name)) {
+                    shell.status("Would close", log.path);
+                }
+            }
+        },
+    }
+}
+
+/// Warns if log files couldn't be created in the log directory (or the
+/// closest ancestor that exists, if it would be created), without creating
+/// anything
+fn check_directory(directory: &Path, shell: &Shell) {
+    let existing = directory.ancestors().find(|path| path.exists());
+    match matches!(existing, Some(path) if is_writable(path)) {
+        true => shell.status(
+            "Checked",
+            format!("log directory {} is writable", directory.display()),
+        ),
+        false => shell.warn(format!(
+            "Log files could not be written to {} since it is not writable",
+            directory.display()
+        )),
+    }
+}
+
+/// Determines whether the current process can create files in a directory
+fn is_writable(directory: &Path) -> bool {
+    // Relative log directories have an empty path as their last ancestor
+    let directory = match directory.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => directory,
+    };
+    match CString::new(directory.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) == 0 },
+        Err(_) => false,
+    }
+}
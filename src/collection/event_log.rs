@@ -0,0 +1,248 @@
+use crate::cli::ParseFailure;
+use crate::collection::flush::FlushLogger;
+use crate::collection::sink::SyncPolicy;
+use crate::util;
+use byte_unit::Byte;
+use csv::Writer;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use strum_macros::IntoStaticStr;
+
+/// Kind of auxiliary event that can be routed to an event log
+#[derive(IntoStaticStr, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[strum(serialize_all = "lowercase")]
+pub enum EventKind {
+    /// A log file buffer was flushed to its destination
+    Flush,
+    /// A target started or stopped, or a log file was rotated
+    Lifecycle,
+    /// Collection for a target failed
+    Error,
+    /// A trigger rule started burst sampling of a target
+    Trigger,
+}
+
+impl FromStr for EventKind {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flush" => Ok(Self::Flush),
+            "lifecycle" => Ok(Self::Lifecycle),
+            "error" => Ok(Self::Error),
+            "trigger" => Ok(Self::Trigger),
+            _ => Err(ParseFailure::new(String::from("event kind"), s.to_owned())),
+        }
+    }
+}
+
+/// Single `--event-log` destination, parsed from
+/// `<kind>=<path>[,max-size=<size>][,keep=<count>]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventLogTarget {
+    pub kind:     EventKind,
+    pub path:     PathBuf,
+    /// Number of bytes after which the event log is rotated
+    pub max_size: Option<u64>,
+    /// Number of rotated event logs to keep (as `<path>.1`, `<path>.2`, ...)
+    pub keep:     usize,
+}
+
+impl FromStr for EventLogTarget {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseFailure::new(String::from("event log"), s.to_owned());
+        let (kind, rest) = s.split_once('=').ok_or_else(invalid)?;
+        let mut parts = rest.split(',');
+        let path = parts
+            .next()
+            .filter(|path| !path.is_empty())
+            .ok_or_else(invalid)?;
+
+        let mut target = Self {
+            kind:     kind.parse()?,
+            path:     PathBuf::from(path),
+            max_size: None,
+            keep:     1,
+        };
+        for part in parts {
+            match part.split_once('=') {
+                Some(("max-size", size)) => {
+                    let size = Byte::from_str(size).map_err(|_| invalid())?;
+                    target.max_size = Some(u64::try_from(size.get_bytes()).unwrap_or(u64::MAX));
+                },
+                Some(("keep", keep)) => target.keep = keep.parse().map_err(|_| invalid())?,
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(target)
+    }
+}
+
+/// Single row of an event log
+#[derive(Debug, Serialize)]
+struct EventRecord<'a> {
+    timestamp: u128,
+    kind:      &'static str,
+    target:    &'a str,
+    message:   &'a str,
+}
+
+/// Event log file that events are streamed to as they occur, rotating it once
+/// it grows too large
+struct EventLog {
+    path:     PathBuf,
+    max_size: Option<u64>,
+    keep:     usize,
+    writer:   Writer<FlushLogger<File>>,
+    written:  Arc<AtomicU64>,
+}
+
+impl EventLog {
+    fn open(target: &EventLogTarget) -> IoResult<Self> {
+        let (writer, written) = open_writer(&target.path)?;
+        Ok(Self {
+            path: target.path.clone(),
+            max_size: target.max_size,
+            keep: target.keep,
+            writer,
+            written,
+        })
+    }
+
+    fn append(&mut self, record: &EventRecord<'_>) -> IoResult<()> {
+        self.writer.serialize(record)?;
+        // Events are infrequent, so write each one out immediately so that
+        // the event log is up-to-date even if rAdvisor is killed
+        self.writer.flush()?;
+        match self.max_size {
+            Some(max) if self.written.load(Ordering::Relaxed) >= max => self.rotate(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Shifts the current and all kept event logs up by one (discarding the
+    /// oldest), and continues in a new event log at the original path
+    fn rotate(&mut self) -> IoResult<()> {
+        if self.keep > 0 {
+            for n in (1..self.keep).rev() {
+                let from = numbered_path(&self.path, n);
+                if from.exists() {
+                    fs::rename(from, numbered_path(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, numbered_path(&self.path, 1))?;
+        }
+
+        let (writer, written) = open_writer(&self.path)?;
+        self.writer = writer;
+        self.written = written;
+        Ok(())
+    }
+}
+
+/// Opens a new CSV writer at the given path, truncating any existing file,
+/// along with the counter of bytes written to it
+fn open_writer(path: &Path) -> IoResult<(Writer<FlushLogger<File>>, Arc<AtomicU64>)> {
+    let file = File::create(path)?;
+    let logger = FlushLogger::new(
+        file,
+        String::new(),
+        None,
+        None,
+        None,
+        None,
+        SyncPolicy::Never,
+    );
+    let written = logger.written();
+    Ok((Writer::from_writer(logger), written))
+}
+
+fn numbered_path(path: &Path, n: usize) -> PathBuf {
+    let mut numbered = path.as_os_str().to_owned();
+    numbered.push(format!(".{}", n));
+    PathBuf::from(numbered)
+}
+
+/// Set of event logs, routing each kind of event to the logs configured for
+/// it. Kinds that are routed to the same path share a single event log.
+#[derive(Default)]
+pub struct EventLogs {
+    logs:   Vec<Mutex<EventLog>>,
+    routes: HashMap<EventKind, Vec<usize>>,
+}
+
+impl EventLogs {
+    /// Opens each of the configured event logs. Event logs that share a path
+    /// use the rotation settings of the first one given. Returns the event
+    /// logs that could be opened, along with the errors for the rest.
+    #[must_use]
+    pub fn open(targets: &[EventLogTarget]) -> (Self, Vec<(PathBuf, std::io::Error)>) {
+        let mut logs = Self::default();
+        let mut errors = Vec::new();
+        let mut by_path: HashMap<&Path, usize> = HashMap::new();
+        for target in targets {
+            let index = match by_path.get(target.path.as_path()) {
+                Some(&index) => index,
+                None => match EventLog::open(target) {
+                    Ok(log) => {
+                        logs.logs.push(Mutex::new(log));
+                        by_path.insert(&target.path, logs.logs.len() - 1);
+                        logs.logs.len() - 1
+                    },
+                    Err(err) => {
+                        errors.push((target.path.clone(), err));
+                        continue;
+                    },
+                },
+            };
+
+            let route = logs.routes.entry(target.kind).or_default();
+            if !route.contains(&index) {
+                route.push(index);
+            }
+        }
+
+        (logs, errors)
+    }
+
+    /// Whether any event log receives the given kind of event, in which case
+    /// the event's message needs to be constructed
+    #[must_use]
+    pub fn wants(&self, kind: EventKind) -> bool { self.routes.contains_key(&kind) }
+
+    /// Appends an event to each event log that receives its kind
+    pub fn record(&self, kind: EventKind, target: &str, message: &str) -> IoResult<()> {
+        if let Some(indices) = self.routes.get(&kind) {
+            let record = EventRecord {
+                timestamp: util::nano_ts(),
+                kind: kind.into(),
+                target,
+                message,
+            };
+            for &index in indices {
+                self.logs[index].lock().unwrap().append(&record)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flushes all buffered events to their event logs.
+    /// This should be called upon teardown of rAdvisor
+    pub fn flush(&self) -> IoResult<()> {
+        for log in &self.logs {
+            log.lock().unwrap().writer.flush()?;
+        }
+        Ok(())
+    }
+}
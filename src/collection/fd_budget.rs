@@ -0,0 +1,289 @@
+//! Keeps the file descriptors that collectors hold open within a budget.
+//! Each cgroup v1 target holds dozens of cgroup files open between reads (on
+//! top of its log file, and its perf counters on each CPU if enabled), so
+//! hundreds of targets can exhaust the open file limit. Every descriptor that
+//! collectors hold is counted: cgroup files, perf counters, log files, and
+//! the files of the host target and GPUs. Once most of the budget is used,
+//! the settings files of all targets (which are cheaper to open again for
+//! each read) are no longer held open, and once the whole budget is used,
+//! none of the cgroup files of new targets are, and no new counters are
+//! opened. Files are held open again once usage falls well below the
+//! threshold.
+
+#[cfg(feature = "nvml")]
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+/// Percentage of the open file limit that collectors can use, which leaves
+/// the rest for sockets, the other logs, and the standard streams
+const BUDGET_PERCENT: u64 = 75;
+
+/// Percentage of the budget after which the settings files of all targets
+/// are opened for each read
+const PRESSURE_PERCENT: u64 = 80;
+
+/// Percentage of the budget below which files that are opened for each read
+/// are held open again, which is well below `PRESSURE_PERCENT` so that
+/// holding them open doesn't raise the pressure again right away
+const PROMOTE_PERCENT: u64 = 60;
+
+/// Directory with an entry for each file descriptor of the process
+#[cfg(feature = "nvml")]
+const OWN_FDS_PATH: &str = "/proc/self/fd";
+
+/// File descriptors held open by all collectors
+static BUDGET: FdBudget = FdBudget {
+    open:     AtomicU64::new(0),
+    budget:   AtomicU64::new(0),
+    reported: AtomicU8::new(FdPressure::Normal as u8),
+    demoted:  AtomicBool::new(false),
+};
+
+struct FdBudget {
+    /// Number of file descriptors that are held open
+    open:     AtomicU64,
+    /// Maximum number of file descriptors that are held open, or 0 if
+    /// unlimited
+    budget:   AtomicU64,
+    /// Pressure that was last reported by `take_pressure_change`
+    reported: AtomicU8,
+    /// Whether any cgroup files were opened for each read because of the
+    /// pressure, which are held open again once there is room
+    demoted:  AtomicBool,
+}
+
+/// How close the file descriptors held open are to the budget
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum FdPressure {
+    /// All files of new targets are held open
+    Normal,
+    /// The settings files of all targets are opened for each read
+    High,
+    /// All cgroup files of new targets are opened for each read, and no new
+    /// counters are opened
+    Exhausted,
+}
+
+/// How a cgroup file is used, which decides whether it is held open under
+/// pressure
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    /// Statistics that change between reads (such as counters)
+    Statistic,
+    /// Settings that rarely change (such as limits)
+    Setting,
+}
+
+/// Single cgroup file of a collector
+pub enum CgroupFile {
+    /// Held open between reads, which only has to seek back to its start
+    /// after each read
+    Open(Budgeted<File>, PathBuf, FileKind),
+    /// Opened for each read and closed right after, which doesn't use a file
+    /// descriptor between reads
+    OnDemand(PathBuf, FileKind),
+    /// Not collected, or couldn't be opened
+    Missing,
+}
+
+/// File descriptor (such as a file or perf counter) that is counted against
+/// the budget until it is closed
+pub struct Budgeted<T>(T);
+
+/// File descriptors that are counted against the budget until this is
+/// dropped, for those held by libraries (such as NVML) rather than by
+/// collectors themselves
+#[cfg(feature = "nvml")]
+pub struct Reservation(u64);
+
+/// Sets the open file limit that the budget is a share of
+pub fn set_limit(limit: u64) {
+    BUDGET.budget.store(
+        limit.saturating_mul(BUDGET_PERCENT) / 100,
+        Ordering::Relaxed,
+    );
+}
+
+/// Gets the number of file descriptors that collectors hold open, along with
+/// the budget (if limited)
+#[must_use]
+pub fn usage() -> (u64, Option<u64>) {
+    let budget = match BUDGET.budget.load(Ordering::Relaxed) {
+        0 => None,
+        budget => Some(budget),
+    };
+    (BUDGET.open.load(Ordering::Relaxed), budget)
+}
+
+/// Gets how close the file descriptors held open are to the budget
+#[must_use]
+pub fn pressure() -> FdPressure {
+    match usage() {
+        (open, Some(budget)) if open >= budget => FdPressure::Exhausted,
+        (open, Some(budget)) if open >= budget.saturating_mul(PRESSURE_PERCENT) / 100 => {
+            FdPressure::High
+        },
+        _ => FdPressure::Normal,
+    }
+}
+
+/// Gets the current pressure if it changed since it was last taken. This is
+/// checked once per tick, so that the files of running targets can follow
+/// changes made by opening and closing any file descriptors.
+#[must_use]
+pub fn take_pressure_change() -> Option<FdPressure> {
+    let pressure = pressure();
+    let previous = BUDGET.reported.swap(pressure as u8, Ordering::Relaxed);
+    match previous == pressure as u8 {
+        true => None,
+        false => Some(pressure),
+    }
+}
+
+/// Whether the given number of file descriptors can be opened without
+/// exceeding the budget
+#[must_use]
+pub fn fits(count: u64) -> bool {
+    match usage() {
+        (open, Some(budget)) => open.saturating_add(count) <= budget,
+        (_, None) => true,
+    }
+}
+
+/// Whether files that were opened for each read because of the pressure
+/// should be held open again, which is the case once usage falls below
+/// `PROMOTE_PERCENT` of the budget
+#[must_use]
+pub fn promotion_due() -> bool { BUDGET.demoted.load(Ordering::Relaxed) && has_promotion_room() }
+
+/// Marks the start of holding files open again (see `promotion_due`). Any
+/// file that can't be held open for lack of room marks them as due again.
+pub fn start_promotion() { BUDGET.demoted.store(false, Ordering::Relaxed); }
+
+/// Whether usage is below `PROMOTE_PERCENT` of the budget
+fn has_promotion_room() -> bool {
+    match usage() {
+        (open, Some(budget)) => open < budget.saturating_mul(PROMOTE_PERCENT) / 100,
+        (_, None) => true,
+    }
+}
+
+/// Counts the file descriptors that the process has open, including those
+/// that aren't budgeted
+#[cfg(feature = "nvml")]
+pub fn count_own() -> io::Result<u64> {
+    // The directory itself is open while it is read
+    Ok((fs::read_dir(OWN_FDS_PATH)?.count() as u64).saturating_sub(1))
+}
+
+impl CgroupFile {
+    /// Opens the cgroup file at the given path, holding it open unless the
+    /// budget is under pressure. Files that don't exist are missing.
+    #[must_use]
+    pub fn open(path: PathBuf, kind: FileKind) -> Self {
+        let on_demand = match pressure() {
+            FdPressure::Normal => false,
+            FdPressure::High => kind == FileKind::Setting,
+            FdPressure::Exhausted => true,
+        };
+        if on_demand {
+            return match path.is_file() {
+                true => {
+                    BUDGET.demoted.store(true, Ordering::Relaxed);
+                    Self::OnDemand(path, kind)
+                },
+                false => Self::Missing,
+            };
+        }
+
+        match File::open(&path) {
+            Ok(file) => Self::Open(Budgeted::new(file), path, kind),
+            Err(_) => Self::Missing,
+        }
+    }
+
+    /// Closes the file if it is a settings file that is held open, opening it
+    /// for each read instead
+    pub fn demote(&mut self) {
+        if let Self::Open(_, path, FileKind::Setting) = self {
+            let path = mem::take(path);
+            *self = Self::OnDemand(path, FileKind::Setting);
+            BUDGET.demoted.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Holds the file open again if it is opened for each read and the budget
+    /// has room for it (see `promotion_due`). Returns false if it doesn't, in
+    /// which case it is held open once there is room.
+    pub fn promote(&mut self) -> bool {
+        let (path, kind) = match self {
+            Self::OnDemand(path, kind) => (path, *kind),
+            _ => return true,
+        };
+        if !has_promotion_room() {
+            BUDGET.demoted.store(true, Ordering::Relaxed);
+            return false;
+        }
+        if let Ok(file) = File::open(path.as_path()) {
+            *self = Self::Open(Budgeted::new(file), mem::take(path), kind);
+        }
+        true
+    }
+
+    /// Calls the function with the file, opening it for this call alone if
+    /// it isn't held open. Returns None if the file is missing.
+    pub fn with<R, F>(&self, f: F) -> Option<io::Result<R>>
+    where
+        F: FnOnce(&File) -> io::Result<R>,
+    {
+        match self {
+            Self::Open(file, ..) => Some(f(file)),
+            Self::OnDemand(path, _) => Some(File::open(path).and_then(|file| f(&file))),
+            Self::Missing => None,
+        }
+    }
+}
+
+impl<T> Budgeted<T> {
+    /// Counts the file descriptor against the budget until it is dropped
+    #[must_use]
+    pub fn new(inner: T) -> Self {
+        BUDGET.open.fetch_add(1, Ordering::Relaxed);
+        Self(inner)
+    }
+}
+
+impl<T> Deref for Budgeted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T { &self.0 }
+}
+
+impl<T> DerefMut for Budgeted<T> {
+    fn deref_mut(&mut self) -> &mut T { &mut self.0 }
+}
+
+impl<T> Drop for Budgeted<T> {
+    fn drop(&mut self) { BUDGET.open.fetch_sub(1, Ordering::Relaxed); }
+}
+
+#[cfg(feature = "nvml")]
+impl Reservation {
+    /// Counts the given number of file descriptors against the budget until
+    /// the reservation is dropped
+    #[must_use]
+    pub fn new(count: u64) -> Self {
+        BUDGET.open.fetch_add(count, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+#[cfg(feature = "nvml")]
+impl Drop for Reservation {
+    fn drop(&mut self) { BUDGET.open.fetch_sub(self.0, Ordering::Relaxed); }
+}
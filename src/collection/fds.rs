@@ -0,0 +1,97 @@
+//! Counts the file descriptors (and sockets) open by the processes in each
+//! target's cgroup and its descendants, appending them to each record as
+//! `fds.*` columns. Since this reads a directory per process, it is only done
+//! every few collector ticks, with the fields left empty in between.
+
+use crate::formats::{ColumnDeclaration, ColumnKind, Unit};
+use crate::util::CgroupPath;
+use csv::ByteRecord;
+use std::fs;
+use std::num::NonZeroU32;
+
+/// The (integer) columns appended to each record
+pub const FD_COLUMNS: &[ColumnDeclaration] = &[
+    ("fds.open", Unit::Count, ColumnKind::Gauge),
+    ("fds.sockets", Unit::Count, ColumnKind::Gauge),
+];
+
+/// Prefix of the link target of file descriptors that refer to sockets
+const SOCKET_PREFIX: &str = "socket:";
+
+/// Open file descriptors, summed over all processes in a cgroup
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FdCounts {
+    pub open:    u64,
+    pub sockets: u64,
+}
+
+/// Counts the file descriptors of a single target every `divisor` ticks
+pub struct FdCounter {
+    cgroup:    CgroupPath,
+    divisor:   NonZeroU32,
+    /// Number of ticks until the next count, where 0 means this tick
+    remaining: u32,
+}
+
+impl FdCounter {
+    #[must_use]
+    pub const fn new(cgroup: CgroupPath, divisor: NonZeroU32) -> Self {
+        Self {
+            cgroup,
+            divisor,
+            remaining: 0,
+        }
+    }
+
+    /// Advances to the next tick, counting the target's file descriptors if
+    /// they are due to be counted. Should be called once per tick, whether or
+    /// not the tick's record is written.
+    pub fn tick(&mut self) -> Option<FdCounts> {
+        match self.remaining {
+            0 => {
+                self.remaining = self.divisor.get() - 1;
+                Some(count(&self.cgroup))
+            },
+            _ => {
+                self.remaining -= 1;
+                None
+            },
+        }
+    }
+}
+
+/// Appends the `fds.*` fields to the record, which are empty if the file
+/// descriptors weren't counted this tick
+pub fn push_fields(counts: Option<FdCounts>, record: &mut ByteRecord) {
+    match counts {
+        Some(counts) => {
+            let mut itoa_buffer = itoa::Buffer::new();
+            record.push_field(itoa_buffer.format(counts.open).as_bytes());
+            record.push_field(itoa_buffer.format(counts.sockets).as_bytes());
+        },
+        None => {
+            for _ in FD_COLUMNS {
+                record.push_field(b"");
+            }
+        },
+    }
+}
+
+/// Counts the file descriptors open by all processes in the cgroup and its
+/// descendants. Processes that exit while they are being counted are skipped.
+fn count(cgroup: &CgroupPath) -> FdCounts {
+    let mut counts = FdCounts::default();
+    for pid in cgroup.processes() {
+        let entries = fs::read_dir(format!("/proc/{}/fd", pid));
+        for entry in entries.into_iter().flatten().flatten() {
+            counts.open += 1;
+            if let Ok(target) = fs::read_link(entry.path()) {
+                if target.to_string_lossy().starts_with(SOCKET_PREFIX) {
+                    counts.sockets += 1;
+                }
+            }
+        }
+    }
+
+    counts
+}
@@ -1,23 +1,36 @@
 #![allow(clippy::module_name_repetitions)]
 
-use crate::util::{self, Buffer};
+use crate::collection::event_log::{EventKind, EventLogs};
+use crate::collection::sink::{SyncData, SyncPolicy};
+use crate::collection::throttle::RateLimiter;
+use crate::util::{self, Buffer, Crc32};
 use csv::Writer;
 use serde::Serialize;
-use std::io::{Result as IoResult, Write};
+use std::fs::{self, File};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // Max length of a target ID
 // (Docker container or Kubernetes pod)
 const TARGET_ID_BUFFER_LENGTH: usize = 64;
 
-/// Stores metadata about a buffer flush event
+/// Stores metadata about a buffer flush event: either a write of a buffer to
+/// its destination, or a sync of the destination to durable storage
 #[derive(Debug, Serialize)]
 pub struct FlushEvent {
     timestamp: u128,
     target_id: Buffer<TARGET_ID_BUFFER_LENGTH>,
+    /// Number of bytes written, or made durable by a sync
     written:   usize,
     success:   bool,
+    /// `write` or `sync`
+    kind:      &'static str,
+    /// Time (in nanoseconds) that the write or sync took
+    duration:  u128,
 }
 
 impl FlushEvent {
@@ -25,7 +38,7 @@ impl FlushEvent {
     /// taking the current timestamp at the time of invocation
     /// and copying the given ID into the buffer
     #[must_use]
-    pub fn new<A: AsRef<str>>(result: &IoResult<usize>, id: A) -> Self {
+    pub fn new<A: AsRef<str>>(result: &IoResult<usize>, id: A, duration: Duration) -> Self {
         let success = result.is_ok();
         let written = *result.as_ref().unwrap_or(&0);
         Self {
@@ -33,68 +46,374 @@ impl FlushEvent {
             target_id: Buffer::from_str_truncate(id),
             written,
             success,
+            kind: "write",
+            duration: duration.as_nanos(),
+        }
+    }
+
+    /// Makes a new `FlushEvent` for a sync that made the given number of
+    /// bytes durable
+    #[must_use]
+    pub fn sync<A: AsRef<str>>(
+        result: &IoResult<()>,
+        id: A,
+        synced: usize,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            timestamp: util::nano_ts(),
+            target_id: Buffer::from_str_truncate(id),
+            written:   synced,
+            success:   result.is_ok(),
+            kind:      "sync",
+            duration:  duration.as_nanos(),
         }
     }
 }
 
-/// Stores a vec of flush events
+/// CSV file that flush events are appended to as they occur. Events are
+/// buffered in memory and periodically written out and synced to disk, so
+/// that at most one interval of events is lost if rAdvisor crashes.
 pub struct FlushLog {
-    pub path:   PathBuf,
-    pub events: Vec<FlushEvent>,
+    pub path:  PathBuf,
+    writer:    Writer<File>,
+    /// Handle to the same file as the writer, used to sync it to disk
+    file:      File,
+    interval:  Duration,
+    last_sync: Instant,
+    count:     u64,
+    /// Number of events that couldn't be appended, along with the most recent
+    /// error, which are reported on teardown
+    failed:    u64,
+    error:     Option<IoError>,
 }
 
 impl FlushLog {
-    #[must_use]
-    pub fn new<A: AsRef<Path>>(event_log_path: A, capacity: usize) -> Self {
-        Self {
-            path:   event_log_path.as_ref().to_path_buf(),
-            events: Vec::with_capacity(capacity),
+    /// Creates the flush log at the given path (truncating any existing
+    /// file, and creating its parent directories if needed), syncing it to
+    /// disk at most every `interval`
+    pub fn open<A: AsRef<Path>>(flush_log_path: A, interval: Duration) -> IoResult<Self> {
+        let path = flush_log_path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
+        let file = File::create(&path)?;
+        let writer = Writer::from_writer(file.try_clone()?);
+        Ok(Self {
+            path,
+            writer,
+            file,
+            interval,
+            last_sync: Instant::now(),
+            count: 0,
+            failed: 0,
+            error: None,
+        })
     }
 
-    /// Consumes the inner events, writing them to the file.
-    /// This should be called upon teardown of rAdvisor
-    pub fn write(&mut self) -> IoResult<usize> {
-        let mut writer = Writer::from_path(&self.path)?;
-        let event_count = self.events.len();
-        for event in self.events.drain(..) {
-            writer.serialize(event)?;
+    /// Appends a single event to the flush log, syncing all buffered events
+    /// to disk if the sync interval has elapsed. Failures are counted, so
+    /// that they can be reported even if the caller can't surface them.
+    pub fn append(&mut self, event: &FlushEvent) -> IoResult<()> {
+        let result = self.try_append(event);
+        if let Err(err) = &result {
+            self.failed += 1;
+            self.error = Some(IoError::new(err.kind(), err.to_string()));
         }
-        writer.flush()?;
-        Ok(event_count)
+        result
     }
+
+    fn try_append(&mut self, event: &FlushEvent) -> IoResult<()> {
+        self.writer.serialize(event)?;
+        self.count += 1;
+        if self.last_sync.elapsed() >= self.interval {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Writes out all buffered events and syncs them to disk
+    pub fn sync(&mut self) -> IoResult<()> {
+        self.last_sync = Instant::now();
+        self.writer.flush()?;
+        self.file.sync_data()
+    }
+
+    /// Gets the number of events appended to the flush log
+    #[must_use]
+    pub const fn count(&self) -> u64 { self.count }
+
+    /// Gets the number of events that couldn't be appended to the flush log,
+    /// along with the most recent error
+    #[must_use]
+    pub const fn failures(&self) -> (u64, Option<&IoError>) { (self.failed, self.error.as_ref()) }
+}
+
+/// Running totals of buffer flushes across all log files
+#[derive(Debug, Default)]
+pub struct FlushStats {
+    pub count:   AtomicU64,
+    pub failed:  AtomicU64,
+    /// Total number of bytes flushed
+    pub written: AtomicU64,
 }
 
 /// Sits between a buffered writer and some destination writer (such as a file),
 /// logging when the buffered writer flushes to its destination.
 /// This is useful to log when rAdvisor flushes its collection buffers to files,
-/// allowing it to note the time of these flushes
-pub struct FlushLogger<T: Write> {
-    log:    Option<Arc<Mutex<FlushLog>>>,
-    id:     String,
-    writer: T,
+/// allowing it to note the time of these flushes. If a rate limiter is given,
+/// writes that exceed the limit are held back and written out later.
+/// Depending on the sync policy, the destination is also synced to durable
+/// storage after flushes.
+pub struct FlushLogger<T: Write + SyncData> {
+    log:       Option<Arc<Mutex<FlushLog>>>,
+    stats:     Option<Arc<FlushStats>>,
+    events:    Option<Arc<EventLogs>>,
+    limiter:   Option<Arc<RateLimiter>>,
+    id:        String,
+    writer:    T,
+    written:   Arc<AtomicU64>,
+    /// Number of flushes to the destination writer
+    flushes:   Arc<AtomicU64>,
+    /// Largest number of bytes flushed at once
+    peak:      Arc<AtomicU64>,
+    /// Data held back by the rate limiter
+    pending:   Vec<u8>,
+    /// Error from writing out held-back data during a write, which already
+    /// accepted the data, so it's returned by the next flush instead
+    deferred:  Option<IoError>,
+    backlog:   Arc<AtomicU64>,
+    sync:      SyncPolicy,
+    synced_at: Instant,
+    /// Number of bytes written since the previous sync
+    unsynced:  usize,
+    /// CRC-32 checksum of all data written to the destination writer, if
+    /// enabled
+    checksum:  Option<Arc<AtomicU32>>,
 }
 
-impl<T: Write> FlushLogger<T> {
+impl<T: Write + SyncData> FlushLogger<T> {
     #[must_use]
-    pub fn new(writer: T, id: String, log: Option<Arc<Mutex<FlushLog>>>) -> Self {
-        Self { log, id, writer }
+    pub fn new(
+        writer: T,
+        id: String,
+        log: Option<Arc<Mutex<FlushLog>>>,
+        stats: Option<Arc<FlushStats>>,
+        events: Option<Arc<EventLogs>>,
+        limiter: Option<Arc<RateLimiter>>,
+        sync: SyncPolicy,
+    ) -> Self {
+        Self {
+            log,
+            stats,
+            events,
+            limiter,
+            id,
+            writer,
+            written: Arc::new(AtomicU64::new(0)),
+            flushes: Arc::new(AtomicU64::new(0)),
+            peak: Arc::new(AtomicU64::new(0)),
+            pending: Vec::new(),
+            deferred: None,
+            backlog: Arc::new(AtomicU64::new(0)),
+            sync,
+            synced_at: Instant::now(),
+            unsynced: 0,
+            checksum: None,
+        }
     }
-}
 
-impl<T: Write> Write for FlushLogger<T> {
-    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+    /// Keeps a CRC-32 checksum of all data written to the destination writer
+    #[must_use]
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = Some(Arc::new(AtomicU32::new(Crc32::new().value())));
+        self
+    }
+
+    /// Gets the shared CRC-32 checksum of all data that has been written to
+    /// the destination writer, if enabled
+    #[must_use]
+    pub fn checksum(&self) -> Option<Arc<AtomicU32>> { self.checksum.clone() }
+
+    /// Gets a shared counter of the total number of bytes that have been
+    /// flushed to the destination writer
+    #[must_use]
+    pub fn written(&self) -> Arc<AtomicU64> { Arc::clone(&self.written) }
+
+    /// Gets a shared counter of the number of times that the buffered writer
+    /// was flushed to the destination writer
+    #[must_use]
+    pub fn flushes(&self) -> Arc<AtomicU64> { Arc::clone(&self.flushes) }
+
+    /// Gets a shared counter of the largest number of bytes that the buffered
+    /// writer flushed at once, which is its peak fill in between flushes
+    #[must_use]
+    pub fn peak(&self) -> Arc<AtomicU64> { Arc::clone(&self.peak) }
+
+    /// Gets a shared counter of the number of bytes that are currently held
+    /// back by the rate limiter
+    #[must_use]
+    pub fn backlog(&self) -> Arc<AtomicU64> { Arc::clone(&self.backlog) }
+
+    /// Unwraps the logger, returning the destination writer. Any data held
+    /// back by the rate limiter must have been flushed beforehand.
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn into_inner(self) -> T { self.writer }
+
+    /// Writes out the data held back by the rate limiter: all of it if
+    /// forced, or else as much as the limit currently allows
+    fn drain(&mut self, force: bool) -> IoResult<()> {
+        match self.limiter.clone() {
+            Some(limiter) => self.drain_limited(&limiter, force),
+            None => Ok(()),
+        }
+    }
+
+    fn drain_limited(&mut self, limiter: &RateLimiter, force: bool) -> IoResult<()> {
+        let allowed = limiter.take(self.pending.len(), force);
+        let pending = mem::take(&mut self.pending);
+        let mut done = 0;
+        let result = loop {
+            if done >= allowed {
+                break Ok(());
+            }
+            match self.write_through(&pending[done..allowed]) {
+                Ok(0) => break Err(ErrorKind::WriteZero.into()),
+                Ok(written) => done += written,
+                Err(err) => break Err(err),
+            }
+        };
+
+        self.pending = pending;
+        self.pending.drain(..done);
+        self.backlog.fetch_sub(done as u64, Ordering::Relaxed);
+        limiter.release(done as u64);
+        result
+    }
+
+    /// Writes directly to the destination writer, logging the flush and
+    /// syncing afterwards if the sync policy says so
+    fn write_through(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let started = Instant::now();
         let result = self.writer.write(buf);
+        let duration = started.elapsed();
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        if let Ok(written) = result {
+            self.written.fetch_add(written as u64, Ordering::Relaxed);
+            self.unsynced += written;
+            if let Some(checksum) = &self.checksum {
+                let mut crc32 = Crc32::resume(checksum.load(Ordering::Relaxed));
+                crc32.update(&buf[..written]);
+                checksum.store(crc32.value(), Ordering::Relaxed);
+            }
+        }
+
+        if let Some(stats) = &self.stats {
+            stats.count.fetch_add(1, Ordering::Relaxed);
+            match &result {
+                Ok(written) => stats.written.fetch_add(*written as u64, Ordering::Relaxed),
+                Err(_) => stats.failed.fetch_add(1, Ordering::Relaxed),
+            };
+        }
+
         if let Some(log_lock) = &self.log {
-            // If logging is enabled, log the flush event
-            let event = FlushEvent::new(&result, &self.id);
+            // If logging is enabled, log the flush event. Errors can't be
+            // surfaced from here without failing the flush itself, so the
+            // flush log counts them to report them on teardown
+            let event = FlushEvent::new(&result, &self.id, duration);
             let mut log = log_lock.lock().unwrap();
-            log.events.push(event);
+            let _ = log.append(&event);
+        }
+
+        self.record_event(|| match &result {
+            Ok(written) => format!("wrote {} bytes", written),
+            Err(err) => format!("failed: {}", err),
+        });
+
+        // A failed sync is already logged, and the data was still written, so
+        // the write itself succeeded
+        if result.is_ok() && self.sync.is_due(self.synced_at) {
+            let _ = self.sync_data();
         }
         result
     }
 
+    /// Syncs the destination writer to durable storage, logging the sync
+    fn sync_data(&mut self) -> IoResult<()> {
+        let started = Instant::now();
+        let result = self.writer.sync_data();
+        let duration = started.elapsed();
+        let synced = mem::take(&mut self.unsynced);
+        self.synced_at = Instant::now();
+
+        if let Some(log_lock) = &self.log {
+            let event = FlushEvent::sync(&result, &self.id, synced, duration);
+            let mut log = log_lock.lock().unwrap();
+            let _ = log.append(&event);
+        }
+
+        self.record_event(|| match &result {
+            Ok(()) => format!(
+                "synced {} bytes in {}",
+                synced,
+                humantime::Duration::from(duration)
+            ),
+            Err(err) => format!("sync failed: {}", err),
+        });
+        result
+    }
+
+    /// Records a flush event in the event logs, if any of them want it
+    fn record_event(&self, message: impl FnOnce() -> String) {
+        if let Some(events) = &self.events {
+            if events.wants(EventKind::Flush) {
+                // Errors can't be surfaced from here without failing the
+                // flush itself, so they are ignored
+                let _ = events.record(EventKind::Flush, &self.id, &message());
+            }
+        }
+    }
+}
+
+impl<T: Write + SyncData> Write for FlushLogger<T> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.peak.fetch_max(buf.len() as u64, Ordering::Relaxed);
+        match self.limiter.clone() {
+            Some(limiter) => {
+                // Never block the collection thread: accept all data, and
+                // write out as much of it as the rate limit allows
+                limiter.hold(buf.len() as u64);
+                self.pending.extend_from_slice(buf);
+                self.backlog.fetch_add(buf.len() as u64, Ordering::Relaxed);
+                // The data is held back from here on, so it must not be
+                // written again by the caller retrying after an error
+                if let Err(err) = self.drain_limited(&limiter, false) {
+                    self.deferred = Some(err);
+                }
+                Ok(buf.len())
+            },
+            None => self.write_through(buf),
+        }
+    }
+
     // We don't need to track anything for flushes,
-    // since a flush to an upstream buffered reader causes a write itself
-    fn flush(&mut self) -> IoResult<()> { self.writer.flush() }
+    // since a flush to an upstream buffered reader causes a write itself.
+    // Held-back data is forced out (and synced, unless syncing is disabled),
+    // unless only pumping the backlog. Errors from writing out held-back data
+    // during earlier writes are returned here.
+    fn flush(&mut self) -> IoResult<()> {
+        let deferred = self.deferred.take();
+        match RateLimiter::is_pumping() {
+            true => self.drain(false)?,
+            false => {
+                self.drain(true)?;
+                self.writer.flush()?;
+                match (self.sync, self.unsynced) {
+                    (SyncPolicy::Never, _) | (_, 0) => {},
+                    _ => self.sync_data()?,
+                }
+            },
+        }
+        deferred.map_or(Ok(()), Err)
+    }
 }
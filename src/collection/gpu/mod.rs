@@ -0,0 +1,253 @@
+//! Collects the utilization and memory of the GPUs assigned to each target
+//! using NVML, appending them to each record as `gpu.*` columns. Only built
+//! with the `nvml` feature.
+//!
+//! GPUs are attributed to targets using their cgroup: on cgroup v1, by the
+//! NVIDIA character devices (`/dev/nvidia<minor>`) that the devices
+//! controller allows the cgroup to access, and otherwise by the
+//! `NVIDIA_VISIBLE_DEVICES` environment variable of the target's processes
+//! (which the NVIDIA container runtime and Kubernetes device plugin use to
+//! assign GPUs by index or UUID).
+
+mod nvml;
+
+pub use nvml::NvmlError;
+
+use crate::collection::fd_budget::{self, Reservation};
+use crate::collection::gpu::nvml::{Device, DeviceSample, Nvml};
+use crate::formats::{ColumnDeclaration, ColumnKind, Unit};
+use crate::util::{cgroup_root, CgroupPath, CgroupVersion};
+use csv::ByteRecord;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The (integer) columns appended to each record. Values are summed over all
+/// GPUs assigned to the target, so utilization can exceed 100%.
+pub const GPU_COLUMNS: &[ColumnDeclaration] = &[
+    ("gpu.count", Unit::Count, ColumnKind::Gauge),
+    ("gpu.utilization.gpu", Unit::Percent, ColumnKind::Gauge),
+    ("gpu.utilization.memory", Unit::Percent, ColumnKind::Gauge),
+    ("gpu.memory.used", Unit::Bytes, ColumnKind::Gauge),
+    ("gpu.memory.total", Unit::Bytes, ColumnKind::Gauge),
+];
+
+/// Major number of NVIDIA GPU character devices
+const NVIDIA_MAJOR: &str = "195";
+
+/// Environment variable that assigns GPUs to containers
+const VISIBLE_DEVICES_VAR: &[u8] = b"NVIDIA_VISIBLE_DEVICES=";
+
+/// Minimum time between attempts to attribute GPUs to a target, while it
+/// doesn't have any processes to inspect yet
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Samples all GPUs on the host once per collector tick, shared by all
+/// targets
+pub struct GpuMonitor {
+    nvml:    Nvml,
+    devices: Vec<Device>,
+    /// Most recent sample of each device, or None if it couldn't be sampled
+    samples: Mutex<Vec<Option<DeviceSample>>>,
+    /// File descriptors that NVML opened for the GPUs, which are counted
+    /// against the file descriptor budget
+    _fds:    Reservation,
+}
+
+impl GpuMonitor {
+    /// Loads NVML and enumerates the GPUs on the host, counting the file
+    /// descriptors that this opens against the file descriptor budget
+    pub fn new() -> Result<Self, NvmlError> {
+        let before = fd_budget::count_own();
+        let nvml = Nvml::load()?;
+        let devices = nvml.devices()?;
+        let opened = match (before, fd_budget::count_own()) {
+            (Ok(before), Ok(after)) => after.saturating_sub(before),
+            // Assume a device file for each GPU, along with the control file
+            _ => devices.len() as u64 + 1,
+        };
+        Ok(Self {
+            samples: Mutex::new(vec![None; devices.len()]),
+            nvml,
+            devices,
+            _fds: Reservation::new(opened),
+        })
+    }
+
+    /// Gets the number of GPUs on the host
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn device_count(&self) -> usize { self.devices.len() }
+
+    /// Samples all GPUs. Should be called once per tick, before any records
+    /// are collected.
+    pub fn refresh(&self) {
+        let mut samples = self.samples.lock().unwrap();
+        for (sample, device) in samples.iter_mut().zip(&self.devices) {
+            *sample = self.nvml.sample(device.handle).ok();
+        }
+    }
+
+    /// Determines which GPUs (by position in `devices`) are assigned to the
+    /// target in the given cgroup, returning None if that can't be determined
+    /// yet because the target doesn't have any processes
+    fn attribute(&self, cgroup: &CgroupPath) -> Option<Vec<usize>> {
+        if let CgroupVersion::V1 = cgroup.version {
+            if let Some(minors) = allowed_minors(cgroup) {
+                return Some(
+                    self.devices
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, device)| {
+                            matches!(device.minor, Some(minor) if minors.contains(&minor))
+                        })
+                        .map(|(i, _)| i)
+                        .collect(),
+                );
+            }
+        }
+
+        let visible = visible_devices(cgroup)?;
+        Some(match visible.as_str() {
+            "all" => (0..self.devices.len()).collect(),
+            _ => {
+                let assigned = visible.split(',').map(str::trim).collect::<Vec<_>>();
+                self.devices
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, device)| {
+                        assigned
+                            .iter()
+                            .any(|&id| id == device.uuid || id.parse::<u32>() == Ok(device.index))
+                    })
+                    .map(|(i, _)| i)
+                    .collect()
+            },
+        })
+    }
+}
+
+/// GPUs attributed to a single target
+pub struct TargetGpus {
+    cgroup:     CgroupPath,
+    /// Positions of the assigned GPUs in the monitor's devices, once known
+    devices:    Option<Vec<usize>>,
+    checked_at: Option<Instant>,
+}
+
+impl TargetGpus {
+    #[must_use]
+    pub const fn new(cgroup: CgroupPath) -> Self {
+        Self {
+            cgroup,
+            devices: None,
+            checked_at: None,
+        }
+    }
+
+    /// Appends the `gpu.*` fields to the record, using the monitor's most
+    /// recent samples. Fields are left empty until the target's GPUs are
+    /// known, or if any assigned GPU couldn't be sampled.
+    pub fn push_fields(&mut self, monitor: &GpuMonitor, record: &mut ByteRecord) {
+        let due = match self.checked_at {
+            Some(checked_at) => checked_at.elapsed() >= RETRY_INTERVAL,
+            None => true,
+        };
+        if self.devices.is_none() && due {
+            self.devices = monitor.attribute(&self.cgroup);
+            self.checked_at = Some(Instant::now());
+        }
+
+        let samples = monitor.samples.lock().unwrap();
+        let assigned = self.devices.as_ref().and_then(|devices| {
+            devices
+                .iter()
+                .map(|&i| samples.get(i).copied().flatten())
+                .collect::<Option<Vec<_>>>()
+                .map(|samples| (devices.len(), samples))
+        });
+        match assigned {
+            Some((count, samples)) => push_totals(count, &samples, record),
+            None => {
+                for _ in GPU_COLUMNS {
+                    record.push_field(b"");
+                }
+            },
+        }
+    }
+}
+
+/// Appends the `gpu.*` fields summed over the samples of a target's GPUs
+fn push_totals(count: usize, samples: &[DeviceSample], record: &mut ByteRecord) {
+    let mut itoa_buffer = itoa::Buffer::new();
+    record.push_field(itoa_buffer.format(count).as_bytes());
+    let utilization_gpu = samples
+        .iter()
+        .map(|s| u64::from(s.utilization_gpu))
+        .sum::<u64>();
+    record.push_field(itoa_buffer.format(utilization_gpu).as_bytes());
+    let utilization_memory = samples
+        .iter()
+        .map(|s| u64::from(s.utilization_memory))
+        .sum::<u64>();
+    record.push_field(itoa_buffer.format(utilization_memory).as_bytes());
+    let memory_used = samples.iter().map(|s| s.memory_used).sum::<u64>();
+    record.push_field(itoa_buffer.format(memory_used).as_bytes());
+    let memory_total = samples.iter().map(|s| s.memory_total).sum::<u64>();
+    record.push_field(itoa_buffer.format(memory_total).as_bytes());
+}
+
+/// Gets the minor numbers of the NVIDIA devices that the cgroup v1 devices
+/// controller allows access to, or None if access isn't restricted (or the
+/// controller isn't available)
+fn allowed_minors(cgroup: &CgroupPath) -> Option<Vec<u32>> {
+    let path = cgroup_root()
+        .join("devices")
+        .join(&cgroup.path)
+        .join("devices.list");
+    let list = fs::read_to_string(path).ok()?;
+    let mut minors = Vec::new();
+    for entry in list.lines() {
+        // Entries look like `c 195:0 rwm`, or `a *:* rwm` if unrestricted
+        let mut parts = entry.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("a"), _) => return None,
+            (Some("c"), Some(device)) => {
+                if let Some((NVIDIA_MAJOR, minor)) = device.split_once(':') {
+                    if let Ok(minor) = minor.parse() {
+                        minors.push(minor);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Some(minors)
+}
+
+/// Gets the value of `NVIDIA_VISIBLE_DEVICES` in the environment of the
+/// target's processes, including those in descendant cgroups (since the
+/// cgroups of pods have no processes of their own). The first process that
+/// has the variable set is used, skipping processes whose environment can't
+/// be read (such as those that exit in the meantime, or the pause container
+/// of a pod). If no process has it set, the target has no GPUs, and if no
+/// environment could be read at all, its GPUs are unknown.
+fn visible_devices(cgroup: &CgroupPath) -> Option<String> {
+    let mut any_read = false;
+    for pid in cgroup.processes() {
+        let environ = match fs::read(format!("/proc/{}/environ", pid)) {
+            Ok(environ) => environ,
+            Err(_) => continue,
+        };
+        any_read = true;
+        let visible = environ
+            .split(|&b| b == 0)
+            .find_map(|var| var.strip_prefix(VISIBLE_DEVICES_VAR));
+        if let Some(visible) = visible {
+            return Some(String::from_utf8_lossy(visible).trim().to_owned());
+        }
+    }
+
+    any_read.then(String::new)
+}
@@ -0,0 +1,260 @@
+//! Minimal bindings to the NVIDIA Management Library (NVML), which is loaded
+//! at runtime so that rAdvisor doesn't need to link against it (and still
+//! runs on hosts without NVIDIA drivers)
+
+use libc::{c_char, c_int, c_uint, c_void};
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::ptr;
+
+/// Shared library installed by the NVIDIA driver
+const LIBRARY: &str = "libnvidia-ml.so.1";
+
+/// Length of the buffer that device UUIDs are written to
+/// (`NVML_DEVICE_UUID_V2_BUFFER_SIZE`)
+const UUID_BUFFER_LENGTH: c_uint = 96;
+
+type NvmlReturn = c_int;
+type DeviceFn<T> = unsafe extern "C" fn(*mut c_void, *mut T) -> NvmlReturn;
+type InitFn = unsafe extern "C" fn() -> NvmlReturn;
+type GetCountFn = unsafe extern "C" fn(*mut c_uint) -> NvmlReturn;
+type GetHandleFn = unsafe extern "C" fn(c_uint, *mut *mut c_void) -> NvmlReturn;
+type GetUuidFn = unsafe extern "C" fn(*mut c_void, *mut c_char, c_uint) -> NvmlReturn;
+
+const NVML_SUCCESS: NvmlReturn = 0;
+
+#[repr(C)]
+#[derive(Default)]
+struct NvmlUtilization {
+    gpu:    c_uint,
+    memory: c_uint,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct NvmlMemory {
+    total: u64,
+    free:  u64,
+    used:  u64,
+}
+
+/// Error that can occur when loading or calling NVML
+#[derive(Debug, thiserror::Error)]
+pub enum NvmlError {
+    #[error("could not load NVML: {0}")]
+    Load(String),
+    #[error("libnvidia-ml.so.1 does not export {0}")]
+    MissingSymbol(&'static str),
+    #[error("{function} failed with NVML error code {code}")]
+    Call {
+        function: &'static str,
+        code:     NvmlReturn,
+    },
+}
+
+/// Opaque handle to a single GPU
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceHandle(*mut c_void);
+
+// NVML is thread-safe, and device handles stay valid until NVML is shut down
+unsafe impl Send for DeviceHandle {}
+unsafe impl Sync for DeviceHandle {}
+
+/// Single GPU found by NVML
+#[derive(Clone, Debug)]
+pub struct Device {
+    pub handle: DeviceHandle,
+    /// Index of the device, as used by `NVIDIA_VISIBLE_DEVICES`
+    pub index:  u32,
+    /// Globally unique id of the device, such as `GPU-8a7d...`
+    pub uuid:   String,
+    /// Minor number of the device's `/dev/nvidia<minor>` character device, if
+    /// it has one
+    pub minor:  Option<u32>,
+}
+
+/// Utilization and memory of a single GPU at a point in time
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeviceSample {
+    /// Percent of time over the past sample period that kernels were
+    /// executing on the GPU
+    pub utilization_gpu:    u32,
+    /// Percent of time over the past sample period that device memory was
+    /// being read or written
+    pub utilization_memory: u32,
+    /// Bytes of device memory in use
+    pub memory_used:        u64,
+    /// Total bytes of device memory
+    pub memory_total:       u64,
+}
+
+/// Loaded and initialized instance of NVML, which is shut down when dropped
+pub struct Nvml {
+    library:                 *mut c_void,
+    shutdown:                InitFn,
+    device_get_count:        GetCountFn,
+    device_get_handle:       GetHandleFn,
+    device_get_uuid:         GetUuidFn,
+    device_get_minor_number: DeviceFn<c_uint>,
+    device_get_utilization:  DeviceFn<NvmlUtilization>,
+    device_get_memory_info:  DeviceFn<NvmlMemory>,
+}
+
+// NVML is thread-safe, and the library handle is only used to unload it
+unsafe impl Send for Nvml {}
+unsafe impl Sync for Nvml {}
+
+/// Looks up a function in the loaded library, casting it to the given
+/// function pointer type
+macro_rules! symbol {
+    ($library:expr, $name:literal, $type:ty) => {{
+        let name = concat!($name, "\0");
+        let symbol = libc::dlsym($library, name.as_ptr().cast());
+        if symbol.is_null() {
+            libc::dlclose($library);
+            return Err(NvmlError::MissingSymbol($name));
+        }
+        mem::transmute::<*mut c_void, $type>(symbol)
+    }};
+}
+
+impl Nvml {
+    /// Loads and initializes NVML, failing if the NVIDIA driver isn't
+    /// installed or NVML can't communicate with it
+    pub fn load() -> Result<Self, NvmlError> {
+        let library_name = CString::new(LIBRARY).unwrap();
+        let library = unsafe { libc::dlopen(library_name.as_ptr(), libc::RTLD_NOW) };
+        if library.is_null() {
+            return Err(NvmlError::Load(last_dl_error()));
+        }
+
+        let (init, nvml) = unsafe {
+            let init = symbol!(library, "nvmlInit_v2", InitFn);
+            let nvml = Self {
+                library,
+                shutdown: symbol!(library, "nvmlShutdown", InitFn),
+                device_get_count: symbol!(library, "nvmlDeviceGetCount_v2", GetCountFn),
+                device_get_handle: symbol!(library, "nvmlDeviceGetHandleByIndex_v2", GetHandleFn),
+                device_get_uuid: symbol!(library, "nvmlDeviceGetUUID", GetUuidFn),
+                device_get_minor_number: symbol!(
+                    library,
+                    "nvmlDeviceGetMinorNumber",
+                    DeviceFn<c_uint>
+                ),
+                device_get_utilization: symbol!(
+                    library,
+                    "nvmlDeviceGetUtilizationRates",
+                    DeviceFn<NvmlUtilization>
+                ),
+                device_get_memory_info: symbol!(
+                    library,
+                    "nvmlDeviceGetMemoryInfo",
+                    DeviceFn<NvmlMemory>
+                ),
+            };
+            (init, nvml)
+        };
+
+        match unsafe { init() } {
+            NVML_SUCCESS => Ok(nvml),
+            code => {
+                // Only unload the library, since NVML wasn't initialized
+                unsafe {
+                    libc::dlclose(nvml.library);
+                }
+                mem::forget(nvml);
+                Err(NvmlError::Call {
+                    function: "nvmlInit_v2",
+                    code,
+                })
+            },
+        }
+    }
+
+    /// Gets all GPUs on the host, ordered by index
+    pub fn devices(&self) -> Result<Vec<Device>, NvmlError> {
+        let mut count: c_uint = 0;
+        check("nvmlDeviceGetCount_v2", unsafe {
+            (self.device_get_count)(ptr::addr_of_mut!(count))
+        })?;
+
+        let mut devices = Vec::new();
+        for index in 0..count {
+            let mut handle = ptr::null_mut();
+            check("nvmlDeviceGetHandleByIndex_v2", unsafe {
+                (self.device_get_handle)(index, ptr::addr_of_mut!(handle))
+            })?;
+
+            let mut uuid = [0 as c_char; UUID_BUFFER_LENGTH as usize];
+            check("nvmlDeviceGetUUID", unsafe {
+                (self.device_get_uuid)(handle, uuid.as_mut_ptr(), UUID_BUFFER_LENGTH)
+            })?;
+            let uuid = unsafe { CStr::from_ptr(uuid.as_ptr()) }
+                .to_string_lossy()
+                .into_owned();
+
+            // Devices such as MIG instances don't have a character device
+            let mut minor: c_uint = 0;
+            let code = unsafe { (self.device_get_minor_number)(handle, ptr::addr_of_mut!(minor)) };
+            let minor = match code {
+                NVML_SUCCESS => Some(minor),
+                _ => None,
+            };
+
+            devices.push(Device {
+                handle: DeviceHandle(handle),
+                index,
+                uuid,
+                minor,
+            });
+        }
+
+        Ok(devices)
+    }
+
+    /// Samples the current utilization and memory of a GPU
+    pub fn sample(&self, device: DeviceHandle) -> Result<DeviceSample, NvmlError> {
+        let mut utilization = NvmlUtilization::default();
+        check("nvmlDeviceGetUtilizationRates", unsafe {
+            (self.device_get_utilization)(device.0, ptr::addr_of_mut!(utilization))
+        })?;
+        let mut memory = NvmlMemory::default();
+        check("nvmlDeviceGetMemoryInfo", unsafe {
+            (self.device_get_memory_info)(device.0, ptr::addr_of_mut!(memory))
+        })?;
+
+        Ok(DeviceSample {
+            utilization_gpu:    utilization.gpu,
+            utilization_memory: utilization.memory,
+            memory_used:        memory.used,
+            memory_total:       memory.total,
+        })
+    }
+}
+
+impl Drop for Nvml {
+    fn drop(&mut self) {
+        unsafe {
+            (self.shutdown)();
+            libc::dlclose(self.library);
+        }
+    }
+}
+
+const fn check(function: &'static str, code: NvmlReturn) -> Result<(), NvmlError> {
+    match code {
+        NVML_SUCCESS => Ok(()),
+        code => Err(NvmlError::Call { function, code }),
+    }
+}
+
+/// Gets the message of the last error from the dynamic linker
+fn last_dl_error() -> String {
+    let error = unsafe { libc::dlerror() };
+    match error.is_null() {
+        true => String::from("unknown error"),
+        false => unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
@@ -0,0 +1,134 @@
+//! Lifecycle log (`events.log` in the log directory), which records when each
+//! target started and stopped being collected, so that the timeline of an
+//! experiment can be reconstructed without parsing log file names
+
+use crate::cli::ParseFailure;
+use crate::shared::CollectionTarget;
+use crate::util::{self, CgroupPath};
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Name of the lifecycle log file in the log directory
+pub const LIFECYCLE_LOG_NAME: &str = "events.log";
+
+/// Format that lifecycle events are written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifecycleFormat {
+    /// CSV with a header row
+    Csv,
+    /// One JSON object per line
+    Jsonl,
+}
+
+impl FromStr for LifecycleFormat {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => Err(ParseFailure::new(String::from("format"), s.to_owned())),
+        }
+    }
+}
+
+/// Kind of lifecycle event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifecycleEvent {
+    Start,
+    Stop,
+}
+
+impl LifecycleEvent {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+/// Single row of the lifecycle log
+#[derive(Debug, Serialize)]
+struct LifecycleRecord<'a> {
+    timestamp:      u128,
+    event:          &'static str,
+    provider:       &'a str,
+    id:             &'a str,
+    name:           &'a str,
+    group:          Option<&'a str>,
+    cgroup:         String,
+    cgroup_version: String,
+    log_file:       &'a str,
+}
+
+enum Writer {
+    Csv(Box<csv::Writer<File>>),
+    Jsonl(BufWriter<File>),
+}
+
+/// Log of target start and stop events, shared by all targets
+pub struct LifecycleLog {
+    pub path: PathBuf,
+    writer:   Writer,
+}
+
+impl LifecycleLog {
+    /// Opens the lifecycle log in the given directory, appending to it if it
+    /// already exists (such as from a previous run). The CSV header row is
+    /// only written to new (or empty) files.
+    pub fn open(directory: &Path, format: LifecycleFormat) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(LIFECYCLE_LOG_NAME);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let empty = file.metadata()?.len() == 0;
+        let writer = match format {
+            LifecycleFormat::Csv => Writer::Csv(Box::new(
+                csv::WriterBuilder::new()
+                    .has_headers(empty)
+                    .from_writer(file),
+            )),
+            LifecycleFormat::Jsonl => Writer::Jsonl(BufWriter::new(file)),
+        };
+        Ok(Self { path, writer })
+    }
+
+    /// Appends an event for the given target, which is collected from the
+    /// given cgroup into the given log file
+    pub fn record(
+        &mut self,
+        event: LifecycleEvent,
+        target: &CollectionTarget,
+        cgroup: &CgroupPath,
+        log_file: &str,
+    ) -> io::Result<()> {
+        let record = LifecycleRecord {
+            timestamp: util::nano_ts(),
+            event: event.name(),
+            provider: target.provider,
+            id: &target.id,
+            name: &target.name,
+            group: target.group.as_deref(),
+            cgroup: Path::new("/").join(&cgroup.path).display().to_string(),
+            cgroup_version: cgroup.version.to_string(),
+            log_file,
+        };
+
+        // Events are infrequent, so write each one out immediately so that
+        // the log is up-to-date even if rAdvisor is killed
+        match &mut self.writer {
+            Writer::Csv(writer) => {
+                writer.serialize(&record)?;
+                writer.flush()
+            },
+            Writer::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, &record)?;
+                writeln!(writer)?;
+                writer.flush()
+            },
+        }
+    }
+}
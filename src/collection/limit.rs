@@ -0,0 +1,120 @@
+use crate::cli::ParseFailure;
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
+use crate::util::Crc32;
+use csv::ByteRecord;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// What happens when a target starts while the maximum number of targets are
+/// already being collected, controllable via a CLI flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetLimitPolicy {
+    /// The new target isn't collected until another target stops
+    Deny,
+    /// Collection of the target that has been idle the longest (whose records
+    /// haven't changed for the longest time) stops to make room for the new
+    /// target. Evicted targets aren't collected again, since that would in
+    /// turn evict another target.
+    Evict,
+}
+
+impl FromStr for TargetLimitPolicy {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "deny" => Ok(Self::Deny),
+            "evict" => Ok(Self::Evict),
+            _ => Err(ParseFailure::new(
+                String::from("target limit policy"),
+                s.to_owned(),
+            )),
+        }
+    }
+}
+
+/// Maximum number of targets that are collected at once
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetLimit {
+    pub max:    usize,
+    pub policy: TargetLimitPolicy,
+}
+
+/// Tracks when the records of a single target last changed, so that the
+/// target that has been idle the longest can be evicted. Only a checksum of
+/// the last record is kept.
+pub struct Activity {
+    /// Number of leading fields (such as the read timestamp) that are
+    /// ignored when comparing records
+    key_fields:  usize,
+    fingerprint: Option<u32>,
+    changed_at:  Instant,
+}
+
+impl Activity {
+    #[must_use]
+    pub fn new(key_fields: usize) -> Self {
+        Self {
+            key_fields,
+            fingerprint: None,
+            changed_at: Instant::now(),
+        }
+    }
+
+    /// Compares a collected record to the previous one, updating when the
+    /// target was last active if any of its fields changed
+    pub fn observe(&mut self, record: &ByteRecord) {
+        let mut crc = Crc32::new();
+        for field in record.iter().skip(self.key_fields) {
+            crc.update(field);
+            // Separate the fields so that moving bytes between them counts
+            // as a change
+            crc.update(b",");
+        }
+
+        let fingerprint = Some(crc.value());
+        if self.fingerprint != fingerprint {
+            self.fingerprint = fingerprint;
+            self.changed_at = Instant::now();
+        }
+    }
+
+    /// Gets when the target's records last changed (or when collection of it
+    /// started, if they never did)
+    #[must_use]
+    pub const fn changed_at(&self) -> Instant { self.changed_at }
+}
+
+/// Start events of the targets that were denied because the target limit was
+/// reached, which are started in order as other targets stop
+#[derive(Default)]
+pub struct WaitingTargets {
+    pending: VecDeque<(CollectionTarget, CollectionMethod)>,
+}
+
+impl WaitingTargets {
+    /// Queues a denied target, replacing its previous start event if it was
+    /// already waiting
+    pub fn push(&mut self, target: CollectionTarget, method: CollectionMethod) {
+        self.remove(&target.id);
+        self.pending.push_back((target, method));
+    }
+
+    /// Stops waiting for room for the target, since it stopped
+    pub fn remove(&mut self, id: &str) { self.pending.retain(|(target, _)| target.id != id); }
+
+    /// Gets the number of targets waiting for room
+    #[must_use]
+    pub fn len(&self) -> usize { self.pending.len() }
+
+    /// Removes and returns the start events of (up to) the given number of
+    /// targets that waited the longest
+    pub fn take(&mut self, count: usize) -> Vec<CollectionEvent> {
+        let count = count.min(self.pending.len());
+        self.pending
+            .drain(..count)
+            .map(|(target, method)| CollectionEvent::Start { target, method })
+            .collect()
+    }
+}
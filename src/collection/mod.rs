@@ -1,46 +1,186 @@
+mod adaptive_buffer;
+mod aggregate;
+mod bench;
 mod buffers;
 mod collectors;
+mod control;
+mod derived;
+mod disk_usage;
+mod dry_run;
+mod event_log;
+mod fd_budget;
+mod fds;
 mod flush;
-mod perf_table;
+#[cfg(feature = "nvml")]
+mod gpu;
+mod lifecycle;
+mod limit;
+mod naming;
+mod overhead;
+mod overrun;
+#[cfg(feature = "perf")]
+mod perf;
+mod processor;
+mod quarantine;
+mod quirks;
+mod repeated;
+mod retry;
+mod ring;
+mod sched;
+mod ship;
+mod sink;
+mod snapshot;
+mod sockets;
+mod sparse;
+mod start_latency;
+mod stats;
+mod status;
+mod summary;
 mod system_info;
+mod throttle;
+mod topology;
+mod trigger;
+mod validate;
 
+pub use bench::{Bench, BenchError, BenchReport};
+pub use collectors::{BfqColumns, ServiceTimeColumns, Subsystem};
+pub use disk_usage::DiskUsagePolicy;
+pub use event_log::{EventKind, EventLogTarget};
+pub use lifecycle::LifecycleFormat;
+pub use limit::TargetLimitPolicy;
+pub use processor::{ColumnRegistry, RecordProcessor};
+pub use ship::ShipTarget;
+pub use sink::{SinkType, SyncPolicy};
+pub use snapshot::{Snapshot, SnapshotError, SnapshotFormat};
+pub use trigger::TriggerRule;
+
+use crate::channel::EventReceiver;
 use crate::cli::CollectionOptions;
+use crate::collection::aggregate::AggregatePolicy;
 use crate::collection::buffers::WorkingBuffers;
-use crate::collection::collectors::{CollectorImpl, Handle};
+use crate::collection::collectors::{CollectorImpl, CollectorOptions, Handle, LogSettings,
+                                    RotationPolicy, TickInfo};
+use crate::collection::control::ControlSocket;
+use crate::collection::disk_usage::DiskQuota;
+use crate::collection::event_log::EventLogs;
+use crate::collection::fd_budget::FdPressure;
 use crate::collection::flush::FlushLog;
-use crate::shared::{CollectionEvent, IntervalWorkerContext};
+use crate::collection::lifecycle::{LifecycleEvent, LifecycleLog};
+use crate::collection::limit::{TargetLimit, WaitingTargets};
+use crate::collection::naming::ShortNames;
+use crate::collection::overhead::OverheadTuner;
+use crate::collection::overrun::OverrunMonitor;
+use crate::collection::repeated::{io_error_kind, ErrorSource, Repeat};
+use crate::collection::retry::RetryQueue;
+use crate::collection::ship::Shipper;
+use crate::collection::sparse::SparsePolicy;
+use crate::collection::start_latency::{StartLatencies, StartLatencyLog};
+use crate::collection::stats::RuntimeStats;
+use crate::collection::status::StatusRegistry;
+use crate::collection::summary::TargetSummary;
+use crate::collection::throttle::RateLimiter;
+use crate::collection::trigger::BurstPolicy;
+use crate::collection::validate::ValidationReport;
+use crate::formats::SchedulingMetadata;
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget, Id, IntervalWorkerContext};
 use crate::shell::Shell;
-use crate::timer::{Stoppable, Timer};
+use crate::timer::{IntervalHandle, Stoppable, Stopper, Timer};
+use crate::util::{self, SuspendDetector, ThreadScheduling};
+use anyhow::Error;
+use byte_unit::Byte;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::io;
+use std::iter;
+use std::num::NonZeroUsize;
 use std::path::Path;
-use std::sync::mpsc::Receiver;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-/// Length of the buffer that contains buffer flush events
-const EVENT_BUFFER_LENGTH: usize = 8 * 1024;
-
-/// Synchronization status struct used to handle termination and buffer flushing
-struct CollectStatus {
-    terminating: bool,
-    collecting:  bool,
+/// Control message sent to the collection thread, which exclusively owns all
+/// collector handles
+#[derive(Clone, Debug, PartialEq)]
+pub enum Control {
+    /// Flushes the buffers of all log files to their destinations, without
+    /// stopping collection
+    Flush,
+    /// Prints runtime statistics about collection and each active target
+    Dump,
+    /// Stops collection and flushes all buffers
+    Terminate,
+    /// Stops writing records for the target with the given id (or for all
+    /// targets, including those started while paused) until it is resumed
+    Pause(Option<Id>),
+    /// Resumes writing records for the target with the given id (even if all
+    /// targets are paused), or for all targets
+    Resume(Option<Id>),
+    /// Changes the collection interval
+    SetInterval(Duration),
 }
 
-/// Mutex-protected map of target ids to collector handles
-type CollectorMap = Arc<Mutex<HashMap<String, RefCell<Handle>>>>;
+/// Collector handles by log name (the target id, or the group name for
+/// grouped targets), along with the log name that each target belongs to
+/// and the targets whose collectors are waiting to be initialized again
+#[derive(Default)]
+struct Collectors {
+    handles: HashMap<String, RefCell<Handle>>,
+    owners:  HashMap<Id, String>,
+    retries: RetryQueue,
+    /// Targets that are waiting for room under the target limit
+    waiting: WaitingTargets,
+    /// Whether collection of all targets is paused, in which case targets
+    /// are paused as they are started
+    paused:  bool,
+    /// Short names of the active targets, if log files are named after them
+    names:   Option<ShortNames>,
+}
 
 /// Thread function that collects all active targets and updates the active
-/// list, if possible
+/// list, if possible. Each record is passed through the given processors
+/// before it is written. The collector handles are owned by this thread alone;
+/// other threads interact with them by sending control messages, which are
+/// handled between ticks. Targets whose cgroups are found to be removed are
+/// sent to the polling thread through `vanished_tx`. Returns an exit code if
+/// collection stopped on its own because of an error (rather than being
+/// terminated).
 #[allow(clippy::too_many_lines)]
 pub fn run(
-    rx: &Receiver<CollectionEvent>,
+    rx: &EventReceiver,
+    vanished_tx: &Sender<Id>,
+    control: (Sender<Control>, Receiver<Control>),
     context: IntervalWorkerContext,
     options: &CollectionOptions,
-) {
-    let location = &options.directory;
-    let buffer_size = usize::try_from(options.buffer_size.get_bytes()).unwrap();
+    processors: Vec<Arc<dyn RecordProcessor>>,
+) -> Option<i32> {
+    if options.dry_run {
+        dry_run::run(rx, control, context, options);
+        return None;
+    }
+
+    // Apply the scheduling settings before starting the timer thread, so
+    // that it inherits them
+    let scheduling = schedule_thread(options, "collection", &context.shell);
+    configure_fd_budget(&context.shell);
+    let rotation = RotationPolicy {
+        max_size: options
+            .rotate_size
+            .map(|size| u64::try_from(size.get_bytes()).unwrap_or(u64::MAX)),
+        max_age:  options.rotate_interval,
+    };
+    let mut disk_quota = options.max_disk_usage.map(|limit| {
+        DiskQuota::new(
+            u64::try_from(limit.get_bytes()).unwrap_or(u64::MAX),
+            options.disk_usage_policy,
+            iter::once(&options.directory)
+                .chain(&options.spool_dir)
+                .cloned()
+                .collect(),
+        )
+    });
 
     context.shell.status(
         "Beginning",
@@ -50,165 +190,1254 @@ pub fn run(
         ),
     );
 
-    let (timer, stop_handle) = Timer::new(context.interval, "collect");
-    let collectors: CollectorMap = Arc::new(Mutex::new(HashMap::new()));
-
-    // If we are monitoring events, initialize the event log
-    let flush_log = options
-        .flush_log
-        .as_ref()
-        .map(|log_path| Arc::new(Mutex::new(FlushLog::new(log_path, EVENT_BUFFER_LENGTH))));
-
-    // Track when the collector is running and when SIGTERM/SIGINT are being handled
-    let status_mutex = Arc::new(Mutex::new(CollectStatus {
-        terminating: false,
-        collecting:  false,
-    }));
-
-    // Initialize the sigterm/sigint handler
-    let collectors_c = Arc::clone(&collectors);
-    let status_mutex_c = Arc::clone(&status_mutex);
-    let shell_c = Arc::clone(&context.shell);
-    let flush_log_c = flush_log.clone();
+    let (timer, stop_handle) = start_timer(options, context.interval);
+    let mut interval = CollectionInterval {
+        handle: timer.interval_handle(),
+        base:   context.interval,
+        burst:  false,
+        tuner:  options
+            .overhead_budget
+            .map(|budget| OverheadTuner::new(budget, context.interval)),
+    };
+    let mut collectors = Collectors {
+        retries: RetryQueue::new(options.init_retries),
+        names: options.short_names.then(ShortNames::default),
+        ..Collectors::default()
+    };
+    let (control_tx, control_rx) = control;
+    let _control_socket = open_control_socket(options, &control_tx, &context.shell);
+
+    let flush_log = open_flush_log(options, &context.shell);
+    warn_unavailable_columns(options, &context.shell);
+    let settings = Arc::new(LogSettings {
+        location: options
+            .spool_dir
+            .clone()
+            .unwrap_or_else(|| options.directory.clone()),
+        buffer_capacity: usize::try_from(options.buffer_size.get_bytes()).unwrap(),
+        adaptive_buffer: options.adaptive_buffer,
+        event_log: flush_log.clone(),
+        lifecycle: open_lifecycle_log(options, &context.shell),
+        sparse: match options.sparse {
+            true => Some(SparsePolicy {
+                epsilon:   options.sparse_epsilon,
+                heartbeat: options.sparse_heartbeat,
+            }),
+            false => None,
+        },
+        aggregate: options
+            .aggregate
+            .map(|interval| AggregatePolicy { interval }),
+        sink: options.sink,
+        discarded: Arc::new(AtomicU64::new(0)),
+        status: serve_status(options, &context.shell),
+        node_id: options.node_id.clone(),
+        file_prefix: file_prefix(options).map(str::to_owned),
+        collector: CollectorOptions::new(options),
+        boottime: options.boottime,
+        monotonic: options.monotonic,
+        latency: options.latency,
+        fds: options.fds.then(|| options.fds_divisor),
+        sched: sched_enabled(options, &context.shell),
+        net_sockets: options.net_sockets.then(|| options.net_sockets_divisor),
+        derived: options.derived_columns,
+        event_logs: open_event_logs(options, &context.shell),
+        processors,
+        burst: burst_policy(options),
+        io_limiter: options
+            .io_rate_limit
+            .map(|rate| Arc::new(RateLimiter::new(rate))),
+        validation: match options.validate {
+            true => Some(Arc::new(ValidationReport::new())),
+            false => None,
+        },
+        sync: options.sync,
+        checksum: options.checksum,
+        ring_capacity: options.ring_capacity,
+        quarantine_after: options.quarantine_after,
+        target_limit: options.max_targets.map(|max| TargetLimit {
+            max:    max.get(),
+            policy: options.max_targets_policy,
+        }),
+        scheduling,
+        #[cfg(feature = "nvml")]
+        gpu: gpu_monitor(options, &context.shell),
+        #[cfg(feature = "perf")]
+        perf: perf_enabled(options, &context.shell),
+    });
+    let blkio = settings.collector.subsystems.contains(Subsystem::Blkio);
+    if options.bfq_columns == BfqColumns::Auto && blkio && !settings.collector.bfq {
+        context.shell.verbose(|sh| {
+            sh.info("Omitting the blkio.bfq columns since no block device uses the bfq scheduler");
+        });
+    }
+
+    // If shipping is enabled, upload finished log files in the background
+    let shipper = start_shipper(options, &context.shell);
+
+    // Initialize the sigterm/sigint handler, which hands termination off to
+    // the collection thread and wakes it up if it is yielding to the sleep
     let stop_handle_c = stop_handle.clone();
     let mut term_rx = context.term_rx;
     thread::Builder::new()
         .name(String::from("collect-term"))
         .spawn(move || {
             term_rx.recv().unwrap();
-            let mut status = status_mutex_c.lock().unwrap();
-            match status.collecting {
-                true => {
-                    // Set terminating and let the collector thread flush the buffers
-                    // as it ends collection for the current tick
-                    status.terminating = true;
-                    shell_c.verbose(|sh| {
-                        sh.info(
-                            "Currently collecting: stopping & flushing buffers at the end of the \
-                             next collector tick",
-                        );
-                    });
-                },
-                false => {
-                    shell_c.verbose(|sh| {
-                        sh.info("Currently yielding: stopping & flushing buffers right now");
-                    });
-
-                    // The collection thread is yielding to the sleep; flush the buffers now
-                    let collectors = collectors_c.lock().unwrap();
-                    flush_buffers(&collectors, &shell_c, flush_log_c);
-                    stop_handle_c.stop();
-                },
-            }
+            // If the collection thread has already exited, then there is
+            // nothing left to stop
+            let _ = control_tx.send(Control::Terminate);
+            stop_handle_c.stop();
         })
         .unwrap();
 
     // Re-use working buffers
     let mut working_buffers = WorkingBuffers::new();
+    let mut suspend_detector = match options.boottime {
+        true => Some(SuspendDetector::new()),
+        false => None,
+    };
+    let mut overruns = OverrunMonitor::new();
+    let mut stats = RuntimeStats::new();
+    let mut start_latencies = StartLatencies::new(open_start_latency_log(options, &context.shell));
 
-    for _ in timer {
-        // Update status
-        let mut status = status_mutex.lock().unwrap();
-        if status.terminating {
-            // If already terminating, skip loop iteration
-            continue;
-        }
-        status.collecting = true;
-        // Drop the lock early
-        drop(status);
-
-        let mut collectors = collectors.lock().unwrap();
+    for tick in timer {
+        let tick_started = Instant::now();
 
-        // Check to see if update thread has sent any new start/stop events
-        let flush_log_ref = flush_log.clone();
-        for event in rx.try_iter() {
+        // Check to see if update thread has sent any new start/stop events,
+        // after starting the targets that were waiting for room and retrying
+        // the targets that previously failed to initialize
+        let admitted = admit_waiting(&mut collectors, &settings);
+        let retries = collectors.retries.take_due();
+        for event in admitted.into_iter().chain(retries).chain(rx.try_iter()) {
             handle_event(
                 event,
                 &mut collectors,
-                location,
-                buffer_size,
-                &flush_log_ref,
+                &settings,
                 &context.shell,
+                shipper.as_ref(),
+                &mut stats,
             );
         }
+        balance_fd_budget(&collectors, &context.shell);
 
-        // Loop over active target ids and run collection
-        for (id, c) in collectors.iter() {
+        // Detect whether the system was suspended since the last tick
+        let suspended = suspend_detector.as_mut().and_then(SuspendDetector::check);
+        if let Some(gap) = suspended {
+            let message = format!(
+                "after the system was suspended for {}",
+                humantime::Duration::from(gap)
+            );
+            log_event(&settings, &context.shell, EventKind::Lifecycle, "", || {
+                format!("resumed {}", message)
+            });
+            context.shell.status("Resumed", message);
+        }
+
+        // Sample the GPUs once for all targets
+        #[cfg(feature = "nvml")]
+        if let Some(monitor) = &settings.gpu {
+            monitor.refresh();
+        }
+
+        // Loop over active log files and run collection
+        let mut vanished = Vec::new();
+        for (id, c) in &collectors.handles {
             let mut collector = c.borrow_mut();
-            match collector.collect(&mut working_buffers) {
-                Ok(_) => (),
+            let tick_info = TickInfo {
+                suspended,
+                latency: tick.latency,
+                burst: interval.burst_base(),
+            };
+            collect_target(
+                id,
+                &mut collector,
+                &mut working_buffers,
+                tick_info,
+                &settings,
+                &context.shell,
+                &mut stats,
+            );
+
+            match collector.pump() {
+                Ok(()) => resolve_error(id, ErrorSource::Pump, &context.shell, &mut stats),
                 Err(err) => {
-                    context.shell.error(format!(
-                        "Could not run collector for target {}: {}",
-                        id, err
-                    ));
+                    let repeat = stats.record_error(id, ErrorSource::Pump, Some(err.kind()), &err);
+                    report_error(
+                        &settings,
+                        &context.shell,
+                        id,
+                        repeat,
+                        || format!("could not write held-back data: {}", err),
+                        || format!("Could not write held-back data for target {}: {}", id, err),
+                    );
                 },
-            };
-        }
+            }
 
-        // Update status
-        let mut status = status_mutex.lock().unwrap();
-        if status.terminating {
-            context.shell.verbose(|sh| {
-                sh.info(
-                    "Received termination flag from term handler thread; stopping and flushing \
-                     buffers now",
+            if rotation.is_enabled() && collector.should_rotate(&rotation) {
+                rotate(
+                    id,
+                    &mut collector,
+                    &settings,
+                    &context.shell,
+                    shipper.as_ref(),
+                    &mut stats,
                 );
+            }
+            vanished.extend(collector.take_vanished());
+            record_start_latencies(&mut start_latencies, &mut collector, &context.shell);
+        }
+        close_vanished(
+            vanished,
+            &mut collectors,
+            &settings,
+            &context.shell,
+            shipper.as_ref(),
+            &mut stats,
+            vanished_tx,
+        );
+
+        // Shorten the collection interval while any target is burst sampled
+        if let Some(policy) = &settings.burst {
+            let bursting = update_bursts(&collectors.handles, policy, &settings, &context.shell);
+            switch_interval(&mut interval, policy, bursting, &context.shell);
+        }
+
+        // Keep the CPU usage of rAdvisor within the overhead budget
+        if let Some(adjustment) = interval.tuner.as_mut().and_then(OverheadTuner::tick) {
+            interval.handle.set(adjustment.to);
+            let message = format!(
+                "collection interval from {} to {} (overhead of {:.2}% CPU)",
+                humantime::Duration::from(adjustment.from),
+                humantime::Duration::from(adjustment.to),
+                adjustment.usage * 100.0
+            );
+            log_event(&settings, &context.shell, EventKind::Lifecycle, "", || {
+                format!("adjusted {}", message)
             });
+            context.shell.status("Adjusting", message);
+        }
+
+        // Warn (at most every few seconds) if the I/O rate limit holds back
+        // more data than it can write out in reasonable time
+        if let Some(limiter) = &settings.io_limiter {
+            if let Some(backlog) = limiter.saturation() {
+                context.shell.warn(format!(
+                    "The I/O rate limit of {}/s is holding back {} of log data; buffered data \
+                     will keep growing unless the limit is raised",
+                    Byte::from_bytes(u128::from(limiter.rate())).get_appropriate_unit(false),
+                    Byte::from_bytes(u128::from(backlog)).get_appropriate_unit(true)
+                ));
+            }
+        }
+
+        // Warn (at most every few seconds) if collection can't keep up with the
+        // interval, in which case records are late or ticks are skipped
+        let current = interval.handle.get();
+        let duration = tick_started.elapsed();
+        stats.record_tick(duration);
+        if let Some(warning) = overruns.record(tick, duration, current) {
+            context.shell.warn(format!(
+                "{} collection ticks took longer than the {} interval (the longest took {}); {} \
+                 overruns in total",
+                warning.count,
+                humantime::Duration::from(current),
+                humantime::Duration::from(warning.longest),
+                warning.total
+            ));
+        }
 
-            // If termination signaled during collection, then the collection thread
-            // needs to tear down the buffers
-            let flush_log_ref = flush_log.map(|r| Arc::clone(&r));
-            flush_buffers(&collectors, &context.shell, flush_log_ref);
-            stop_handle.stop();
+        // Handle any control messages sent during the tick, and stop once the
+        // log directory exceeds its maximum disk usage
+        if handle_controls(
+            &control_rx,
+            &mut collectors,
+            &mut interval,
+            &settings,
+            &context.shell,
+            &stats,
+        ) || check_disk_usage(disk_quota.as_mut(), &collectors, &settings, &context.shell)
+        {
             break;
-        } else {
-            // If terminating, then don't signal the end of collecting. Otherwise,
-            // end collecting before yielding to the sleep
-            status.collecting = false;
         }
     }
+
+    // Stop the timer (if the loop ended due to a control message) and tear
+    // down the buffers
+    context.shell.verbose(|sh| {
+        sh.info("Received termination signal; stopping and flushing buffers now");
+    });
+    stop_handle.stop();
+    record_remaining_stops(&collectors, &settings, &context.shell);
+    flush_buffers(&collectors.handles, &settings, &context.shell, flush_log);
+
+    if let Some(shipper) = shipper {
+        ship_remaining(shipper, collectors, &context.shell);
+    }
+
+    context.shell.verbose(|sh| {
+        sh.info(format!("Collection tick overruns: {}", overruns.total()));
+    });
+    summarize_start_latencies(&mut start_latencies, options, &context.shell);
+
+    disk_quota.filter(DiskQuota::is_exceeded).map(|_| 1)
 }
 
-/// Flushes the buffers for the given collectors.
-/// This should only happen once (during teardown)
-fn flush_buffers(
+/// Reports the burst sampling that was started during the last tick,
+/// returning whether any target is still being burst sampled
+fn update_bursts(
     collectors: &HashMap<String, RefCell<Handle>>,
+    policy: &BurstPolicy,
+    settings: &LogSettings,
+    shell: &Shell,
+) -> bool {
+    let mut bursting = false;
+    for c in collectors.values() {
+        let mut collector = c.borrow_mut();
+        for start in collector.take_bursts() {
+            let message = format!(
+                "burst sampling every {} for at least {} after '{}' matched",
+                humantime::Duration::from(policy.interval),
+                humantime::Duration::from(policy.duration),
+                start.rule
+            );
+            log_event(settings, shell, EventKind::Trigger, &start.id, || {
+                format!("started {}", message)
+            });
+            shell.status("Triggered", format!("target {}: {}", start.id, message));
+        }
+        bursting |= collector.is_bursting();
+    }
+    bursting
+}
+
+/// Collection interval, which is shortened while any target is burst sampled
+/// and lengthened to keep rAdvisor within its overhead budget
+struct CollectionInterval {
+    handle: IntervalHandle,
+    /// Interval outside of burst sampling, which is the one changed on request
+    base:   Duration,
+    /// Whether any target is being burst sampled
+    burst:  bool,
+    tuner:  Option<OverheadTuner>,
+}
+
+impl CollectionInterval {
+    /// Changes the base interval on request, which the overhead tuner starts
+    /// over from. While any target is burst sampled, the new interval takes
+    /// effect once burst sampling ends.
+    fn set_base(&mut self, base: Duration) {
+        self.base = base;
+        if let Some(tuner) = &mut self.tuner {
+            tuner.reset(base);
+        }
+        if !self.burst {
+            self.handle.set(base);
+        }
+    }
+
+    /// Gets the base interval if any target is being burst sampled
+    const fn burst_base(&self) -> Option<Duration> {
+        match self.burst {
+            true => Some(self.base),
+            false => None,
+        }
+    }
+}
+
+/// Switches the collection interval when burst sampling starts or ends
+fn switch_interval(
+    interval: &mut CollectionInterval,
+    policy: &BurstPolicy,
+    bursting: bool,
+    shell: &Shell,
+) {
+    match (interval.burst, bursting) {
+        (false, true) => interval.handle.set(policy.interval),
+        (true, false) => {
+            interval.handle.set(interval.base);
+            shell.verbose(|sh| {
+                sh.info("Burst sampling ended; restoring the collection interval");
+            });
+        },
+        _ => {},
+    }
+    interval.burst = bursting;
+}
+
+/// Gets the scheduling settings of the polling and collection threads
+#[must_use]
+pub fn thread_scheduling(options: &CollectionOptions) -> ThreadScheduling {
+    ThreadScheduling {
+        cpus:        options.pin_cpus.clone(),
+        nice:        options.nice,
+        rt_priority: options.rt_priority,
+    }
+}
+
+/// Applies the scheduling settings (if any were given) to the calling thread,
+/// which is named in messages. Returns the settings if they were applied, to
+/// be recorded in the header of each log file.
+pub fn schedule_thread(
+    options: &CollectionOptions,
+    thread: &str,
+    shell: &Shell,
+) -> Option<SchedulingMetadata> {
+    let scheduling = thread_scheduling(options);
+    if scheduling.is_default() {
+        return None;
+    }
+
+    match scheduling.apply() {
+        Ok(()) => {
+            let metadata = SchedulingMetadata {
+                cpus:        scheduling.cpus.as_ref().map(ToString::to_string),
+                nice:        scheduling.nice,
+                rt_priority: scheduling.rt_priority,
+            };
+            shell.verbose(|sh| {
+                let mut settings = Vec::new();
+                if let Some(cpus) = &metadata.cpus {
+                    settings.push(format!("pinned to CPUs {}", cpus));
+                }
+                if let Some(nice) = metadata.nice {
+                    settings.push(format!("nice value of {}", nice));
+                }
+                if let Some(priority) = metadata.rt_priority {
+                    settings.push(format!("real-time priority of {}", priority));
+                }
+                sh.info(format!(
+                    "Scheduling the {} thread (and its timer): {}",
+                    thread,
+                    settings.join(", ")
+                ));
+            });
+            Some(metadata)
+        },
+        Err(err) => {
+            shell.warn(format!(
+                "Could not apply the scheduling settings to the {} thread, so they aren't \
+                 recorded in log file headers: {}",
+                thread, err
+            ));
+            None
+        },
+    }
+}
+
+/// Opens the lifecycle log in the log directory, if it is enabled
+fn open_lifecycle_log(options: &CollectionOptions, shell: &Shell) -> Option<Mutex<LifecycleLog>> {
+    let format = options.lifecycle_log?;
+    match LifecycleLog::open(&options.directory, format) {
+        Ok(log) => Some(Mutex::new(log)),
+        Err(err) => {
+            shell.error(format!(
+                "Could not open lifecycle log in {}: {}",
+                options.directory.display(),
+                err
+            ));
+            None
+        },
+    }
+}
+
+/// Opens the start latency log in the log directory, if it is enabled
+fn open_start_latency_log(options: &CollectionOptions, shell: &Shell) -> Option<StartLatencyLog> {
+    if !options.start_latency_log {
+        return None;
+    }
+
+    match StartLatencyLog::open(&options.directory) {
+        Ok(log) => Some(log),
+        Err(err) => {
+            shell.error(format!(
+                "Could not open start latency log in {}: {}",
+                options.directory.display(),
+                err
+            ));
+            None
+        },
+    }
+}
+
+/// Records the start latencies of the targets (written to the log file) whose
+/// first sample was just collected
+fn record_start_latencies(latencies: &mut StartLatencies, collector: &mut Handle, shell: &Shell) {
+    if let Err(err) = latencies.extend(collector.take_started()) {
+        shell.error(format!("Could not write to start latency log: {}", err));
+    }
+}
+
+/// Flushes the start latency log, and prints a summary of the start latencies
+/// of all targets (if the start latency log is enabled or the output is
+/// verbose)
+fn summarize_start_latencies(
+    latencies: &mut StartLatencies,
+    options: &CollectionOptions,
+    shell: &Shell,
+) {
+    match latencies.flush() {
+        Ok(Some(path)) => shell.verbose(|sh| {
+            sh.info(format!("Wrote start latencies to {}", path.display()));
+        }),
+        Ok(None) => {},
+        Err(err) => shell.error(format!("Could not flush start latency log: {}", err)),
+    }
+
+    if let Some(summary) = latencies.summary() {
+        match options.start_latency_log {
+            true => shell.status("Measured", summary),
+            false => shell.verbose(|sh| sh.status("Measured", summary)),
+        }
+    }
+}
+
+/// Binds the control socket, if enabled, which forwards the commands that it
+/// receives to the collection thread
+fn open_control_socket(
+    options: &CollectionOptions,
+    control_tx: &Sender<Control>,
     shell: &Arc<Shell>,
-    flush_log_option: Option<Arc<Mutex<FlushLog>>>,
+) -> Option<ControlSocket> {
+    let path = options.control_socket.as_ref()?;
+    match control::serve(path, control_tx.clone(), Arc::clone(shell)) {
+        Ok(socket) => Some(socket),
+        Err(err) => {
+            shell.error(format!(
+                "Could not serve control socket at {}: {}",
+                path.display(),
+                err
+            ));
+            None
+        },
+    }
+}
+
+/// Starts the timer that drives collection ticks, which is aligned to the
+/// wall clock if enabled
+fn start_timer(options: &CollectionOptions, interval: Duration) -> (Timer, Stopper) {
+    match options.align {
+        true => Timer::aligned(interval, "collect"),
+        false => Timer::new(interval, "collect"),
+    }
+}
+
+/// Creates the flush log that buffer flush events are streamed to, if
+/// enabled
+fn open_flush_log(options: &CollectionOptions, shell: &Shell) -> Option<Arc<Mutex<FlushLog>>> {
+    let log_path = options.flush_log.as_ref()?;
+    match FlushLog::open(log_path, options.flush_log_interval) {
+        Ok(flush_log) => Some(Arc::new(Mutex::new(flush_log))),
+        Err(err) => {
+            shell.error(format!(
+                "Could not open flush log {}: {}",
+                log_path.display(),
+                err
+            ));
+            None
+        },
+    }
+}
+
+/// Opens the configured event logs before collection starts, if any
+fn open_event_logs(options: &CollectionOptions, shell: &Shell) -> Option<Arc<EventLogs>> {
+    if options.event_logs.is_empty() {
+        return None;
+    }
+
+    let (event_logs, errors) = EventLogs::open(&options.event_logs);
+    for (path, err) in errors {
+        shell.error(format!(
+            "Could not open event log {}: {}",
+            path.display(),
+            err
+        ));
+    }
+    Some(Arc::new(event_logs))
+}
+
+/// Starts serving the status endpoint, if enabled
+fn serve_status(options: &CollectionOptions, shell: &Arc<Shell>) -> Option<Arc<StatusRegistry>> {
+    let addr = options.status_addr?;
+    let registry = Arc::new(StatusRegistry::new(
+        options.node_id.clone(),
+        options.max_targets.map(NonZeroUsize::get),
+    ));
+    match status::serve(addr, Arc::clone(&registry), Arc::clone(shell)) {
+        Ok(()) => Some(registry),
+        Err(err) => {
+            shell.error(format!(
+                "Could not serve collection status on {}: {}",
+                addr, err
+            ));
+            None
+        },
+    }
+}
+
+/// Loads NVML once for all targets if the `gpu.*` columns are enabled,
+/// warning if it couldn't be loaded
+#[cfg(feature = "nvml")]
+fn gpu_monitor(options: &CollectionOptions, shell: &Shell) -> Option<Arc<gpu::GpuMonitor>> {
+    if !options.gpu {
+        return None;
+    }
+
+    match gpu::GpuMonitor::new() {
+        Ok(monitor) => {
+            shell.verbose(|sh| {
+                sh.info(format!("Found {} GPUs using NVML", monitor.device_count()));
+            });
+            Some(Arc::new(monitor))
+        },
+        Err(err) => {
+            shell.warn(format!(
+                "Omitting the gpu columns since NVML could not be loaded: {}",
+                err
+            ));
+            None
+        },
+    }
+}
+
+/// Whether the `perf.*` columns are added to each record, warning if they
+/// were requested but hardware events can't be counted
+#[cfg(feature = "perf")]
+fn perf_enabled(options: &CollectionOptions, shell: &Shell) -> bool {
+    options.perf
+        && match perf::probe() {
+            Ok(()) => true,
+            Err(err) => {
+                shell.warn(format!(
+                    "Omitting the perf columns since hardware events could not be counted: {}",
+                    err
+                ));
+                false
+            },
+        }
+}
+
+/// Warns about requested columns that rAdvisor was built without support for
+/// (which does nothing if it was built with all of them)
+#[allow(unused_variables, clippy::missing_const_for_fn)]
+fn warn_unavailable_columns(options: &CollectionOptions, shell: &Shell) {
+    #[cfg(not(feature = "nvml"))]
+    if options.gpu {
+        shell.warn("Omitting the gpu columns since rAdvisor was built without the nvml feature");
+    }
+    #[cfg(not(feature = "perf"))]
+    if options.perf {
+        shell.warn("Omitting the perf columns since rAdvisor was built without the perf feature");
+    }
+}
+
+/// Prefix of each log file's name: the node id (which defaults to the node
+/// name from the provider or the hostname), if enabled
+fn file_prefix(options: &CollectionOptions) -> Option<&str> {
+    options
+        .node_id
+        .as_deref()
+        .filter(|_| options.node_id_in_filename)
+}
+
+/// Whether the `sched.*` columns are added to each record, warning if they
+/// were requested but the kernel doesn't support them
+fn sched_enabled(options: &CollectionOptions, shell: &Shell) -> bool {
+    if options.sched && !sched::is_supported() {
+        shell.warn(
+            "Omitting the sched columns since the kernel doesn't expose per-thread scheduler \
+             statistics",
+        );
+        return false;
+    }
+
+    options.sched
+}
+
+/// Gets the burst sampling policy, if any trigger rules were given
+fn burst_policy(options: &CollectionOptions) -> Option<BurstPolicy> {
+    match options.triggers.is_empty() {
+        true => None,
+        false => Some(BurstPolicy {
+            rules:    options.triggers.clone(),
+            interval: options.burst_interval,
+            duration: options.burst_duration,
+        }),
+    }
+}
+
+/// Runs a single collector, reporting any errors. Collectors whose log files
+/// are quarantined after too many consecutive failed writes aren't run until
+/// their log files are recovered.
+fn collect_target(
+    id: &str,
+    collector: &mut Handle,
+    working_buffers: &mut WorkingBuffers,
+    tick: TickInfo,
+    settings: &LogSettings,
+    shell: &Shell,
+    stats: &mut RuntimeStats,
 ) {
-    shell.status("Stopping", "collecting and flushing buffers");
+    if collector.is_quarantined() {
+        recover_log(id, collector, settings, shell);
+        return;
+    }
+
+    reattach_log(id, collector, settings, shell, stats);
+    if collector.is_quarantined() {
+        return;
+    }
+
+    match collector.collect(working_buffers, tick) {
+        Ok(()) => resolve_error(id, ErrorSource::Collect, shell, stats),
+        Err(err) => {
+            if let Some(status) = &settings.status {
+                status.record_error(id, &err);
+            }
+            let repeat = stats.record_error(id, ErrorSource::Collect, io_error_kind(&err), &err);
+            report_error(
+                settings,
+                shell,
+                id,
+                repeat,
+                || format!("could not run collector: {}", err),
+                || format!("Could not run collector for target {}: {}", id, err),
+            );
 
+            if collector.is_quarantined() {
+                let failures = collector.write_failures();
+                log_event(settings, shell, EventKind::Lifecycle, id, || {
+                    format!(
+                        "quarantined log file after {} consecutive failed writes",
+                        failures
+                    )
+                });
+                shell.warn(format!(
+                    "Quarantined target {} after {} consecutive failed writes to {}; collection \
+                     for it is paused until the log file can be written to again",
+                    id, failures, collector.path
+                ));
+            }
+        },
+    }
+}
+
+/// Reports an error that occurred for a target in the event log (with the
+/// given event message) and the shell (with the given message), unless the
+/// same error was reported recently. Errors that keep repeating are reported
+/// again every `REPEAT_INTERVAL`, along with the number of times they were
+/// suppressed.
+fn report_error<E, M>(
+    settings: &LogSettings,
+    shell: &Shell,
+    id: &str,
+    repeat: Repeat,
+    event: E,
+    message: M,
+) where
+    E: FnOnce() -> String,
+    M: FnOnce() -> String,
+{
+    let repeated = match repeat {
+        Repeat::First | Repeat::Summarize { suppressed: 0, .. } => String::new(),
+        Repeat::Summarize { suppressed, over } => format!(
+            " (repeated {} more times in the last {})",
+            suppressed,
+            humantime::Duration::from(Duration::from_secs(over.as_secs()))
+        ),
+        Repeat::Suppress => return,
+    };
+    log_event(settings, shell, EventKind::Error, id, || {
+        format!("{}{}", event(), repeated)
+    });
+    shell.error(format!("{}{}", message(), repeated));
+}
+
+/// Records that an operation succeeded for a target, printing the number of
+/// times its previous error was suppressed (if it was) since the error won't
+/// be summarized anymore
+fn resolve_error(id: &str, source: ErrorSource, shell: &Shell, stats: &mut RuntimeStats) {
+    if let Some(suppressed) = stats.resolve_error(id, source) {
+        shell.info(format!(
+            "The last error {} for target {} was repeated {} more times before it stopped",
+            source, id, suppressed
+        ));
+    }
+}
+
+/// Re-creates the log file of a single collector if it was deleted while it
+/// was open, quarantining it if that fails
+fn reattach_log(
+    id: &str,
+    collector: &mut Handle,
+    settings: &LogSettings,
+    shell: &Shell,
+    stats: &mut RuntimeStats,
+) {
+    match collector.reattach() {
+        Ok(true) => {
+            resolve_error(id, ErrorSource::Reattach, shell, stats);
+            log_event(settings, shell, EventKind::Lifecycle, id, || {
+                String::from("re-created deleted log file")
+            });
+            shell.warn(format!(
+                "Re-created log file {} for target {} since it was deleted; records written since \
+                 then were lost",
+                collector.path, id
+            ));
+        },
+        Ok(false) => {},
+        Err(err) => {
+            let repeat =
+                stats.record_error(id, ErrorSource::Reattach, io_error_kind(err.as_ref()), &err);
+            report_error(
+                settings,
+                shell,
+                id,
+                repeat,
+                || format!("could not re-create deleted log file: {}", err),
+                || {
+                    format!(
+                        "Could not re-create deleted log file {} for target {}: {}; collection \
+                         for it is paused until it can be re-created",
+                        collector.path, id, err
+                    )
+                },
+            );
+        },
+    }
+}
+
+/// Attempts to recover the quarantined log file of a single collector (if an
+/// attempt is due), resuming collection for it if successful
+fn recover_log(id: &str, collector: &mut Handle, settings: &LogSettings, shell: &Shell) {
+    match collector.recover() {
+        Ok(Some(quarantined)) => {
+            let duration = humantime::Duration::from(Duration::from_secs(quarantined.as_secs()));
+            log_event(settings, shell, EventKind::Lifecycle, id, || {
+                format!("recovered log file after {} in quarantine", duration)
+            });
+            shell.status(
+                "Recovered",
+                format!(
+                    "log file {} for target {} after {} in quarantine",
+                    collector.path, id, duration
+                ),
+            );
+        },
+        Ok(None) => (),
+        Err(err) => shell.verbose(|sh| {
+            sh.info(format!(
+                "Could not recover the log file of target {} yet: {}",
+                id, err
+            ));
+        }),
+    }
+}
+
+/// Rotates the log file of a single collector, shipping the previous log file
+/// if shipping is enabled
+fn rotate(
+    id: &str,
+    collector: &mut Handle,
+    settings: &LogSettings,
+    shell: &Shell,
+    shipper: Option<&Shipper>,
+    stats: &mut RuntimeStats,
+) {
+    let previous = collector.path.clone();
+    let rotated = collector.rotate();
+    if rotated.is_ok() {
+        resolve_error(id, ErrorSource::Rotate, shell, stats);
+    }
+    match rotated {
+        Ok(true) => {
+            if let Some(shipper) = shipper {
+                shipper.ship(previous);
+            }
+            log_event(settings, shell, EventKind::Lifecycle, id, || {
+                format!("rotated log file to {}", collector.path)
+            });
+            shell.verbose(|sh| {
+                sh.info(format!(
+                    "Rotated log file for target {} to {}",
+                    id, collector.path
+                ));
+            });
+        },
+        Ok(false) => (),
+        Err(err) => {
+            if let Some(status) = &settings.status {
+                status.record_error(id, &err);
+            }
+            let repeat =
+                stats.record_error(id, ErrorSource::Rotate, io_error_kind(err.as_ref()), &err);
+            report_error(
+                settings,
+                shell,
+                id,
+                repeat,
+                || format!("could not rotate log file: {}", err),
+                || format!("Could not rotate log file for target {}: {}", id, err),
+            );
+        },
+    }
+}
+
+/// Checks the disk usage of this run's log files in the log directory and
+/// spool directory (if they have a maximum), evicting log files if needed.
+/// Returns whether collection should stop since they are still over the limit.
+fn check_disk_usage(
+    quota: Option<&mut DiskQuota>,
+    collectors: &Collectors,
+    settings: &LogSettings,
+    shell: &Shell,
+) -> bool {
+    let is_open = |path: &Path| {
+        collectors
+            .handles
+            .values()
+            .any(|c| Path::new(&c.borrow().path) == path)
+    };
+    let quota = match quota {
+        Some(quota) => quota,
+        None => return false,
+    };
+    // Every open log file was created by this run, and rotated log files
+    // were tracked while they were still open
+    for handle in collectors.handles.values() {
+        quota.track(Path::new(&handle.borrow().path));
+    }
+    let directories = quota
+        .directories()
+        .iter()
+        .map(|directory| directory.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" and ");
+    let check = match quota.check(is_open) {
+        Ok(Some(check)) => check,
+        Ok(None) => return false,
+        Err(err) => {
+            shell.warn(format!(
+                "Could not check the disk usage of {}: {}",
+                directories, err
+            ));
+            return false;
+        },
+    };
+
+    for path in &check.evicted {
+        log_event(settings, shell, EventKind::Lifecycle, "", || {
+            format!("evicted log file {}", path.display())
+        });
+        shell.verbose(|sh| {
+            sh.info(format!(
+                "Evicted log file {} to stay within the maximum disk usage",
+                path.display()
+            ));
+        });
+    }
+
+    if check.exceeded {
+        let message = format!(
+            "the log files in {} use {}, exceeding the maximum disk usage of {}",
+            directories,
+            Byte::from_bytes(u128::from(check.usage)).get_appropriate_unit(true),
+            Byte::from_bytes(u128::from(check.limit)).get_appropriate_unit(true)
+        );
+        log_event(settings, shell, EventKind::Error, "", || {
+            format!("stopping collection since {}", message)
+        });
+        shell.error(format!("Stopping collection since {}", message));
+    }
+    check.exceeded
+}
+
+/// Starts shipping finished log files to the `--ship-to` destination, or
+/// moving them from the `--spool-dir` directory to the log directory, if
+/// either is given
+fn start_shipper(options: &CollectionOptions, shell: &Arc<Shell>) -> Option<Shipper> {
+    let (backend, manifest) = match (&options.ship_to, &options.spool_dir) {
+        (Some(target), _) => (
+            target.backend(),
+            Some(options.directory.join(ship::MANIFEST_NAME)),
+        ),
+        (None, Some(_)) => (ship::spool_backend(&options.directory), None),
+        (None, None) => return None,
+    };
+    if matches!(options.sink, SinkType::Null | SinkType::Stream) {
+        shell.warn("Not shipping log files since the null and stream sinks don't write any");
+        return None;
+    }
+
+    match Shipper::start(backend, manifest, Arc::clone(shell)) {
+        Ok(shipper) => Some(shipper),
+        Err(err) => {
+            shell.error(format!("Could not start shipping log files: {}", err));
+            None
+        },
+    }
+}
+
+/// Ships the log files that were still open once they are closed, waiting
+/// for all uploads to finish
+fn ship_remaining(shipper: Shipper, collectors: Collectors, shell: &Shell) {
+    let paths = collectors
+        .handles
+        .values()
+        .map(|c| c.borrow().path.clone())
+        .collect::<Vec<_>>();
+    drop(collectors);
+    if !paths.is_empty() {
+        shell.status("Shipping", "remaining log files");
+    }
+    for path in paths {
+        shipper.ship(path);
+    }
+    shipper.finish();
+}
+
+/// Handles all pending control messages, returning whether collection should
+/// stop
+fn handle_controls(
+    control_rx: &Receiver<Control>,
+    collectors: &mut Collectors,
+    interval: &mut CollectionInterval,
+    settings: &LogSettings,
+    shell: &Shell,
+    stats: &RuntimeStats,
+) -> bool {
+    let mut terminate = false;
+    for control in control_rx.try_iter() {
+        match control {
+            Control::Flush => {
+                shell.verbose(|sh| sh.info("Flushing buffers on request"));
+                flush_writers(&collectors.handles, settings, shell, "on request");
+            },
+            Control::Dump => {
+                let max_targets = settings.target_limit.map(|limit| limit.max);
+                stats.dump(
+                    &collectors.handles,
+                    max_targets,
+                    collectors.waiting.len(),
+                    shell,
+                );
+            },
+            Control::Terminate => terminate = true,
+            Control::Pause(target) => {
+                set_paused(collectors, target.as_deref(), true, settings, shell);
+            },
+            Control::Resume(target) => {
+                set_paused(collectors, target.as_deref(), false, settings, shell);
+            },
+            Control::SetInterval(base) => {
+                interval.set_base(base);
+                shell.status(
+                    "Adjusting",
+                    format!(
+                        "collection interval to {} on request",
+                        humantime::Duration::from(base)
+                    ),
+                );
+            },
+        }
+    }
+    terminate
+}
+
+/// Pauses or resumes collection of the target with the given id, or of all
+/// targets. Resuming all targets also resumes those paused individually,
+/// while resuming a single target while all targets are paused resumes just
+/// that target.
+fn set_paused(
+    collectors: &mut Collectors,
+    target: Option<&str>,
+    paused: bool,
+    settings: &LogSettings,
+    shell: &Shell,
+) {
+    let (status, event) = match paused {
+        true => ("Pausing", "paused"),
+        false => ("Resuming", "resumed"),
+    };
+    if target.is_none() {
+        collectors.paused = paused;
+    }
+
+    let found = match target {
+        Some(id) => collectors
+            .owners
+            .get(id)
+            .and_then(|name| collectors.handles.get(name))
+            .map_or(false, |handle| {
+                handle.borrow_mut().set_paused(Some(id), paused)
+            }),
+        None => {
+            for handle in collectors.handles.values() {
+                handle.borrow_mut().set_paused(None, paused);
+            }
+            true
+        },
+    };
+    match (found, target) {
+        (true, Some(id)) => {
+            log_event(settings, shell, EventKind::Lifecycle, id, || {
+                String::from(event)
+            });
+            shell.status(status, format!("collection of target {} on request", id));
+        },
+        (true, None) => {
+            log_event(settings, shell, EventKind::Lifecycle, "", || {
+                format!("{} all targets", event)
+            });
+            shell.status(status, "collection of all targets on request");
+        },
+        (false, _) => shell.warn(format!(
+            "Could not pause or resume target {}: it isn't being collected",
+            target.unwrap_or_default()
+        )),
+    }
+}
+
+/// Flushes the buffers of the given collectors to their log files
+fn flush_writers(
+    collectors: &HashMap<String, RefCell<Handle>>,
+    settings: &LogSettings,
+    shell: &Shell,
+    reason: &str,
+) {
     for (id, c) in collectors.iter() {
         let mut collector = c.borrow_mut();
         if let Err(err) = collector.writer.flush() {
+            log_event(settings, shell, EventKind::Error, id, || {
+                format!("could not flush buffer {}: {}", reason, err)
+            });
+            shell.warn(format!(
+                "Could not flush buffer {} for target {}: {}",
+                reason, id, err
+            ));
+        }
+    }
+}
+
+/// Finishes every log file on termination, flushing its buffer and writing
+/// its footer
+fn finish_logs(
+    collectors: &HashMap<String, RefCell<Handle>>,
+    settings: &LogSettings,
+    shell: &Shell,
+) {
+    for (id, c) in collectors {
+        if let Err(err) = c.borrow_mut().finish() {
+            log_event(settings, shell, EventKind::Error, id, || {
+                format!("could not finish log file on termination: {}", err)
+            });
+            shell.warn(format!(
+                "Could not finish log file on termination for target {}: {}",
+                id, err
+            ));
+        }
+    }
+}
+
+/// Writes the samples collected since the last aggregated record of every
+/// target, so that the end of each log file isn't lost on termination
+fn write_aggregates(
+    collectors: &HashMap<String, RefCell<Handle>>,
+    settings: &LogSettings,
+    shell: &Shell,
+) {
+    for (id, c) in collectors {
+        if let Err(err) = c.borrow_mut().write_aggregates() {
+            log_event(settings, shell, EventKind::Error, id, || {
+                format!("could not write aggregated records: {}", err)
+            });
             shell.warn(format!(
-                "Could not flush buffer on termination for target {}: {}",
+                "Could not write aggregated records for target {}: {}",
                 id, err
             ));
         }
     }
+}
 
-    // Write the event log if it's enabled
+/// Flushes the buffers for the given collectors.
+/// This should only happen once (during teardown)
+fn flush_buffers(
+    collectors: &HashMap<String, RefCell<Handle>>,
+    settings: &LogSettings,
+    shell: &Arc<Shell>,
+    flush_log_option: Option<Arc<Mutex<FlushLog>>>,
+) {
+    shell.status("Stopping", "collecting and flushing buffers");
+    if settings.aggregate.is_some() {
+        write_aggregates(collectors, settings, shell);
+    }
+    finish_logs(collectors, settings, shell);
+    for (name, handle) in collectors {
+        let handle = handle.borrow();
+        report_buffer_usage(name, &handle, shell);
+        for (id, summary) in handle.summaries() {
+            report_summary(&id, &summary, settings, shell);
+        }
+    }
+
+    if settings.sink == SinkType::Null {
+        let discarded = settings.discarded.load(Ordering::Relaxed);
+        shell.info(format!(
+            "Discarded {} written to the null sink",
+            Byte::from_bytes(u128::from(discarded)).get_appropriate_unit(true)
+        ));
+    }
+
+    if let Some(report) = &settings.validation {
+        report_validation(report, shell);
+    }
+
+    if let Some(event_logs) = &settings.event_logs {
+        if let Err(err) = event_logs.flush() {
+            shell.warn(format!("Could not flush event logs: {}", err));
+        }
+    }
+
+    // Sync the remaining events in the flush log if it's enabled
     if let Some(flush_log_lock) = flush_log_option {
         let mut flush_log = flush_log_lock.lock().unwrap();
-        let path_str = flush_log
-            .path
-            .clone()
-            .into_os_string()
-            .into_string()
-            .ok()
-            .unwrap_or_else(|| String::from("~"));
-        match flush_log.write() {
-            Ok(count) => shell.info(format!(
+        match flush_log.sync() {
+            Ok(()) => shell.info(format!(
                 "Wrote {} buffer flush events to {}",
-                count, path_str
+                flush_log.count(),
+                flush_log.path.display()
             )),
             Err(err) => shell.warn(format!(
                 "Could not write buffer flush events to {}: {}",
-                path_str, err
+                flush_log.path.display(),
+                err
             )),
         }
+        if let (failed @ 1.., Some(err)) = flush_log.failures() {
+            shell.warn(format!(
+                "Could not append {} buffer flush events to {}: {}",
+                failed,
+                flush_log.path.display(),
+                err
+            ));
+        }
+    }
+}
+
+/// Prints the number of collected values that didn't parse as their column's
+/// type, by column
+fn report_validation(report: &ValidationReport, shell: &Shell) {
+    let errors = report.errors();
+    if errors.is_empty() {
+        shell.info("All collected values matched the types of their columns");
+        return;
+    }
+
+    for (column, errors) in errors {
+        shell.warn(format!(
+            "{} values in column {} did not parse as {} (such as \"{}\")",
+            errors.count, column, errors.r#type, errors.example
+        ));
     }
 }
 
@@ -217,14 +1446,14 @@ fn flush_buffers(
 /// for newly monitored targets
 fn handle_event(
     event: CollectionEvent,
-    collectors: &mut HashMap<String, RefCell<Handle>>,
-    logs_location: &Path,
-    buffer_capacity: usize,
-    flush_log: &Option<Arc<Mutex<FlushLog>>>,
+    collectors: &mut Collectors,
+    settings: &Arc<LogSettings>,
     shell: &Shell,
+    shipper: Option<&Shipper>,
+    stats: &mut RuntimeStats,
 ) {
     match event {
-        CollectionEvent::Start { target, method } => {
+        CollectionEvent::Start { mut target, method } => {
             shell.verbose(|sh| {
                 sh.info(format!(
                     "Received start event for target '{}' from the collection thread",
@@ -232,42 +1461,504 @@ fn handle_event(
                 ));
             });
 
-            let collector: CollectorImpl = method.into();
+            let room = find_room(&target, collectors, settings);
+            if let Room::Full = room {
+                deny_target(target, method, collectors, settings, shell);
+                return;
+            }
+
+            // Keep the event around in case initialization has to be retried
+            let retry = match collectors.retries.is_enabled() {
+                true => Some((target.clone(), method.clone())),
+                false => None,
+            };
+            if let (Some(names), None) = (&mut collectors.names, &target.file_name) {
+                let short_name = names.assign(&target);
+                log_event(settings, shell, EventKind::Lifecycle, &target.id, || {
+                    format!("named {}", short_name)
+                });
+                target.file_name = Some(short_name);
+            }
+            let collector = CollectorImpl::new(method, settings.collector.clone());
             let id = target.id.clone();
-            let flush_log_c = flush_log.clone();
-            match Handle::new(
-                logs_location,
-                target,
-                collector,
-                buffer_capacity,
-                flush_log_c,
-            ) {
-                Ok(new_collector) => {
-                    collectors.insert(id, RefCell::new(new_collector));
+            // Streamed records already identify their target, so there is no
+            // log file for groups to share
+            let group = target
+                .group
+                .clone()
+                .filter(|_| settings.sink != SinkType::Stream);
+            let name = group.clone().unwrap_or_else(|| id.clone());
+            let result = match collectors.handles.get(&name) {
+                // Join the existing log file for the group
+                Some(handle) if group.is_some() => handle.borrow_mut().add(target, collector),
+                _ => Handle::new(settings, group, target, collector).map(|new_collector| {
+                    collectors
+                        .handles
+                        .insert(name.clone(), RefCell::new(new_collector));
+                }),
+            };
+
+            match result {
+                Ok(()) => {
+                    log_event(settings, shell, EventKind::Lifecycle, &id, || {
+                        let path = collectors
+                            .handles
+                            .get(&name)
+                            .map(|c| c.borrow().path.clone());
+                        format!("started; logging to {}", path.unwrap_or_default())
+                    });
+                    record_lifecycle(
+                        collectors,
+                        &name,
+                        &id,
+                        LifecycleEvent::Start,
+                        settings,
+                        shell,
+                    );
+                    collectors.retries.cancel(&id);
+                    if collectors.paused {
+                        if let Some(handle) = collectors.handles.get(&name) {
+                            handle.borrow_mut().set_paused(Some(&id), true);
+                        }
+                    }
+                    // Only evict once the new target's collector was
+                    // initialized, so that a target that can't be collected
+                    // doesn't displace one that can
+                    if let Room::Evict(evicted) = &room {
+                        evict_target(evicted, &id, collectors, settings, shell, shipper, stats);
+                    }
+                    collectors.owners.insert(id, name);
                 },
                 Err(err) => {
-                    // Back off until next iteration if the target is still running
-                    shell.error(format!(
-                        "Could not initialize collector for target id {}: {}",
-                        id, err
-                    ));
+                    if let Some(status) = &settings.status {
+                        status.record_error(&id, &err);
+                    }
+                    log_event(settings, shell, EventKind::Error, &id, || {
+                        format!("could not initialize collector: {}", err)
+                    });
+                    if let Some(names) = &mut collectors.names {
+                        names.release(&id);
+                    }
+                    report_init_failure(&id, &err, retry, &mut collectors.retries, shell);
                 },
             }
         },
         CollectionEvent::Stop(id) => {
-            shell.verbose(|sh| {
-                sh.info(format!(
-                    "Received stop event for target '{}' from the collection thread",
-                    collectors
-                        .get(&id)
-                        .map(|c| c.borrow().target.name.clone())
-                        .as_ref()
-                        .unwrap_or(&id)
-                ));
-            });
+            if let Some(name) = collectors.owners.get(&id) {
+                shell.verbose(|sh| {
+                    sh.info(format!(
+                        "Received stop event for target '{}' from the collection thread",
+                        collectors
+                            .handles
+                            .get(name)
+                            .and_then(|c| c.borrow().target_name(&id).map(String::from))
+                            .as_ref()
+                            .unwrap_or(&id)
+                    ));
+                });
+            }
 
-            let collector = collectors.remove(&id);
-            drop(collector);
+            stop_target(&id, collectors, settings, shell, shipper, stats);
+        },
+    }
+}
+
+/// Raises the open file limit as far as allowed (since each target holds
+/// many files open), budgeting a share of it for collectors
+fn configure_fd_budget(shell: &Shell) {
+    let limit = match util::raise_fd_limit() {
+        Ok((previous, current)) => {
+            if current.soft > previous.soft {
+                shell.verbose(|sh| {
+                    sh.info(format!(
+                        "Raised the open file limit from {} to {}",
+                        previous.soft, current.soft
+                    ));
+                });
+            }
+            Some(current.soft)
+        },
+        Err(err) => {
+            shell.warn(format!("Could not raise the open file limit: {}", err));
+            util::fd_limit().ok().map(|limit| limit.soft)
         },
+    };
+    if let Some(limit) = limit {
+        fd_budget::set_limit(limit);
+    }
+}
+
+/// Keeps the files held open by collectors within their file descriptor
+/// budget, checked once per tick: once the pressure rises, the settings files
+/// of all running targets are opened for each read instead of being held
+/// open, and once usage falls well below the threshold again, they are held
+/// open again
+fn balance_fd_budget(collectors: &Collectors, shell: &Shell) {
+    if let Some(pressure) = fd_budget::take_pressure_change() {
+        report_fd_pressure(pressure, shell);
+        if pressure != FdPressure::Normal {
+            for handle in collectors.handles.values() {
+                handle.borrow_mut().demote_files();
+            }
+        }
+    }
+
+    if fd_budget::promotion_due() {
+        fd_budget::start_promotion();
+        for handle in collectors.handles.values() {
+            if !handle.borrow_mut().promote_files() {
+                break;
+            }
+        }
+    }
+}
+
+/// Warns once the files held open by collectors get close to (or use up)
+/// their file descriptor budget, and reports when they are back within it
+fn report_fd_pressure(pressure: FdPressure, shell: &Shell) {
+    let (open, budget) = fd_budget::usage();
+    let budget = budget.unwrap_or_default();
+    match pressure {
+        FdPressure::Normal => shell.info(format!(
+            "Collectors hold {} of the {} file descriptors budgeted for them: the files of new \
+             targets are held open again, as are those of running targets while there is room",
+            open, budget
+        )),
+        FdPressure::High => shell.warn(format!(
+            "Collectors hold {} of the {} file descriptors budgeted for them: the settings files \
+             of all targets are opened for each read instead of being held open. Raise the open \
+             file limit (ulimit -n) to avoid this",
+            open, budget
+        )),
+        FdPressure::Exhausted => shell.warn(format!(
+            "Collectors hold {} of the {} file descriptors budgeted for them: the cgroup files of \
+             new targets are opened for each read instead of being held open (which is slower), \
+             and no new perf counters are opened. Raise the open file limit (ulimit -n) or limit \
+             the number of targets with --max-targets",
+            open, budget
+        )),
+    }
+}
+
+/// Whether a starting target fits within the target limit (`--max-targets`)
+enum Room {
+    /// The limit wasn't reached (or there is none)
+    Free,
+    /// The limit was reached, so the target that has been idle the longest
+    /// is evicted once the new target's collector is initialized
+    Evict(Eviction),
+    /// The limit was reached, so the new target waits for another target to
+    /// stop
+    Full,
+}
+
+/// Target that is evicted to make room for a new target
+struct Eviction {
+    id:   Id,
+    idle: Duration,
+}
+
+/// Finds room for a starting target if the target limit (`--max-targets`)
+/// was reached, depending on its policy
+fn find_room(target: &CollectionTarget, collectors: &Collectors, settings: &LogSettings) -> Room {
+    let limit = match settings.target_limit {
+        Some(limit) => limit,
+        None => return Room::Free,
+    };
+    if collectors.owners.len() < limit.max || collectors.owners.contains_key(&target.id) {
+        return Room::Free;
+    }
+
+    let idlest = match limit.policy {
+        TargetLimitPolicy::Deny => None,
+        TargetLimitPolicy::Evict => collectors
+            .handles
+            .values()
+            .flat_map(|handle| {
+                let handle = handle.borrow();
+                handle
+                    .activity()
+                    .map(|(id, changed_at)| (id.to_owned(), changed_at))
+                    .collect::<Vec<_>>()
+            })
+            .min_by_key(|(_, changed_at)| *changed_at),
+    };
+    match idlest {
+        Some((id, changed_at)) => Room::Evict(Eviction {
+            id,
+            idle: Duration::from_secs(changed_at.elapsed().as_secs()),
+        }),
+        None => Room::Full,
+    }
+}
+
+/// Stops collection of the target that has been idle the longest, now that
+/// the collector of the new target that it makes room for was initialized.
+/// The evicted target isn't collected again.
+fn evict_target(
+    evicted: &Eviction,
+    new_id: &str,
+    collectors: &mut Collectors,
+    settings: &LogSettings,
+    shell: &Shell,
+    shipper: Option<&Shipper>,
+    stats: &mut RuntimeStats,
+) {
+    shell.warn(format!(
+        "Reached the limit of {} targets: stopped collection of target {} (idle for {}) to \
+         collect target {}",
+        collectors.owners.len(),
+        evicted.id,
+        humantime::Duration::from(evicted.idle),
+        new_id
+    ));
+    log_event(settings, shell, EventKind::Lifecycle, &evicted.id, || {
+        format!("evicted for target {}", new_id)
+    });
+    if let Some(status) = &settings.status {
+        status.record_evicted();
+    }
+    stop_target(&evicted.id, collectors, settings, shell, shipper, stats);
+}
+
+/// Holds back a starting target since the target limit was reached, until
+/// another target stops
+fn deny_target(
+    target: CollectionTarget,
+    method: CollectionMethod,
+    collectors: &mut Collectors,
+    settings: &LogSettings,
+    shell: &Shell,
+) {
+    shell.warn(format!(
+        "Reached the limit of {} targets: target {} is collected once another target stops",
+        collectors.owners.len(),
+        target.id
+    ));
+    log_event(settings, shell, EventKind::Lifecycle, &target.id, || {
+        String::from("denied: reached the target limit; waiting for another target to stop")
+    });
+    if let Some(status) = &settings.status {
+        status.record_denied();
+    }
+    collectors.waiting.push(target, method);
+}
+
+/// Takes the start events of the targets that were denied by the target limit
+/// (`--max-targets`) for which there is room again
+fn admit_waiting(collectors: &mut Collectors, settings: &LogSettings) -> Vec<CollectionEvent> {
+    let limit = match settings.target_limit {
+        Some(limit) => limit,
+        None => return Vec::new(),
+    };
+    let admitted = collectors
+        .waiting
+        .take(limit.max.saturating_sub(collectors.owners.len()));
+    if let Some(status) = &settings.status {
+        status.set_waiting(collectors.waiting.len());
+    }
+    admitted
+}
+
+/// Tears down the collector of a stopped target, closing its log file once
+/// its last target has stopped. Targets that were already torn down (such as
+/// when their cgroups were removed before they were stopped) are ignored.
+fn stop_target(
+    id: &str,
+    collectors: &mut Collectors,
+    settings: &LogSettings,
+    shell: &Shell,
+    shipper: Option<&Shipper>,
+    stats: &mut RuntimeStats,
+) {
+    stats.forget(id);
+    collectors.retries.cancel(id);
+    collectors.waiting.remove(id);
+    if let Some(names) = &mut collectors.names {
+        names.release(id);
+    }
+    if let Some(name) = collectors.owners.remove(id) {
+        log_event(settings, shell, EventKind::Lifecycle, id, || {
+            String::from("stopped")
+        });
+        record_lifecycle(collectors, &name, id, LifecycleEvent::Stop, settings, shell);
+
+        // Close the log file once its last target has stopped
+        let (mut summary, empty) = match collectors.handles.get(&name) {
+            Some(handle) => {
+                let mut handle = handle.borrow_mut();
+                (handle.remove(id), handle.is_empty())
+            },
+            None => (None, false),
+        };
+        if empty {
+            stats.forget(&name);
+            let collector = collectors.handles.remove(&name);
+            if let Some(handle) = &collector {
+                // Finish the log file first so that the buffer's final fill
+                // is included
+                let mut handle = handle.borrow_mut();
+                if let Err(err) = handle.finish() {
+                    shell.warn(format!(
+                        "Could not finish log file for target {}: {}",
+                        id, err
+                    ));
+                }
+                report_buffer_usage(&name, &handle, shell);
+                // Include the records that were only flushed when the log
+                // file was finished
+                if let Some(summary) = &mut summary {
+                    handle.update_summary(summary);
+                }
+            }
+            let path = collector.as_ref().map(|c| c.borrow().path.clone());
+            drop(collector);
+            if let (Some(shipper), Some(path)) = (shipper, path) {
+                shipper.ship(path);
+            }
+        }
+        if let Some(summary) = summary {
+            report_summary(id, &summary, settings, shell);
+        }
+    }
+}
+
+/// Prints the collection statistics of a target that stopped, and appends
+/// them to the lifecycle event log
+fn report_summary(id: &str, summary: &TargetSummary, settings: &LogSettings, shell: &Shell) {
+    shell.info(format!("Collected target {}: {}", id, summary));
+    log_event(settings, shell, EventKind::Lifecycle, id, || {
+        format!("summary: {}", summary)
+    });
+}
+
+/// Reports the peak fill of a log file's buffer once it is closed
+fn report_buffer_usage(name: &str, handle: &Handle, shell: &Shell) {
+    let (peak, capacity) = handle.buffer_usage();
+    shell.verbose(|sh| {
+        sh.info(format!(
+            "Closing log file for {} with a peak buffer fill of {} (of {})",
+            name,
+            Byte::from_bytes(u128::from(peak)).get_appropriate_unit(true),
+            Byte::from_bytes(capacity as u128).get_appropriate_unit(true)
+        ));
+    });
+}
+
+/// Tears down the collectors of targets whose cgroups were found to be
+/// removed before their stop events arrived (after their final records were
+/// written), notifying the polling thread so that it can confirm that the
+/// provider reports them as stopped
+fn close_vanished(
+    vanished: Vec<(Id, i32)>,
+    collectors: &mut Collectors,
+    settings: &LogSettings,
+    shell: &Shell,
+    shipper: Option<&Shipper>,
+    stats: &mut RuntimeStats,
+    notify: &Sender<Id>,
+) {
+    for (id, errno) in vanished {
+        let err = io::Error::from_raw_os_error(errno);
+        shell.verbose(|sh| {
+            sh.info(format!(
+                "The cgroup of target {} was removed before it was stopped ({}); closing its \
+                 collector",
+                id, err
+            ));
+        });
+        log_event(settings, shell, EventKind::Lifecycle, &id, || {
+            format!("cgroup removed: {}", err)
+        });
+        stop_target(&id, collectors, settings, shell, shipper, stats);
+
+        // If the polling thread has already exited, then there is nothing
+        // left to confirm
+        let _ = notify.send(id);
+    }
+}
+
+/// Appends the start or stop of a target (written to the log file with the
+/// given name) to the lifecycle log, if it is enabled
+fn record_lifecycle(
+    collectors: &Collectors,
+    name: &str,
+    id: &str,
+    event: LifecycleEvent,
+    settings: &LogSettings,
+    shell: &Shell,
+) {
+    if let (Some(lifecycle), Some(handle)) = (&settings.lifecycle, collectors.handles.get(name)) {
+        let handle = handle.borrow();
+        if let Some((target, cgroup)) = handle.target(id) {
+            let mut lifecycle = lifecycle.lock().unwrap();
+            if let Err(err) = lifecycle.record(event, target, cgroup, &handle.path) {
+                shell.warn(format!(
+                    "Could not write to lifecycle log {}: {}",
+                    lifecycle.path.display(),
+                    err
+                ));
+            }
+        }
+    }
+}
+
+/// Records every target that is still active as stopped in the lifecycle log,
+/// if it is enabled (used when collection stops)
+fn record_remaining_stops(collectors: &Collectors, settings: &LogSettings, shell: &Shell) {
+    if settings.lifecycle.is_none() {
+        return;
+    }
+
+    for (id, name) in &collectors.owners {
+        record_lifecycle(collectors, name, id, LifecycleEvent::Stop, settings, shell);
+    }
+}
+
+/// Reports that a target's collector couldn't be initialized, scheduling
+/// another attempt if the target hasn't already been retried the maximum
+/// number of times
+fn report_init_failure(
+    id: &str,
+    err: &Error,
+    retry: Option<(CollectionTarget, CollectionMethod)>,
+    retries: &mut RetryQueue,
+    shell: &Shell,
+) {
+    let scheduled = retry.map(|(target, method)| retries.schedule(target, method));
+    match scheduled {
+        Some(Some(scheduled)) => shell.warn(format!(
+            "Could not initialize collector for target id {}: {}; retrying in {} (retry {} of {})",
+            id,
+            err,
+            humantime::Duration::from(scheduled.delay),
+            scheduled.attempt,
+            retries.max_retries()
+        )),
+        Some(None) => shell.error(format!(
+            "Could not initialize collector for target id {} after {} retries: {}",
+            id,
+            retries.max_retries(),
+            err
+        )),
+        None => shell.error(format!(
+            "Could not initialize collector for target id {}: {}",
+            id, err
+        )),
+    }
+}
+
+/// Records an event in each event log that receives its kind, only
+/// constructing the message if there is one
+fn log_event<F>(settings: &LogSettings, shell: &Shell, kind: EventKind, target: &str, message: F)
+where
+    F: FnOnce() -> String,
+{
+    if let Some(event_logs) = &settings.event_logs {
+        if event_logs.wants(kind) {
+            if let Err(err) = event_logs.record(kind, target, &message()) {
+                shell.warn(format!("Could not write to event log: {}", err));
+            }
+        }
     }
 }
@@ -0,0 +1,109 @@
+//! Assigns each target a short, human-friendly name made of its name and the
+//! start of its id (such as `web-3f2a9c1e0b7d` for a Docker container), which
+//! its log files are named after instead of its full id. Short names are
+//! unique among active targets: when two targets would get the same name,
+//! more of the later target's id is used, up to its full id, after which a
+//! counter is appended.
+
+use crate::shared::{CollectionTarget, Id};
+use std::collections::{HashMap, HashSet};
+
+/// Number of letters and digits of a target's id that its short name starts
+/// with (as with the short ids that Docker displays)
+const SHORT_ID_LENGTH: usize = 12;
+
+/// Number of additional letters and digits of the id used when a short name
+/// collides with that of another target
+const SHORT_ID_STEP: usize = 4;
+
+/// Maximum number of characters of the target's name in its short name
+const MAX_NAME_LENGTH: usize = 40;
+
+/// Short names of the active targets
+#[derive(Default)]
+pub struct ShortNames {
+    names: HashMap<Id, String>,
+    taken: HashSet<String>,
+}
+
+impl ShortNames {
+    /// Assigns the target a short name that no other active target has,
+    /// returning it. Targets that already have one keep it.
+    pub fn assign(&mut self, target: &CollectionTarget) -> String {
+        if let Some(name) = self.names.get(&target.id) {
+            return name.clone();
+        }
+
+        let id = short_id(&target.id);
+        let base = sanitize(&target.name);
+        let candidate = |length: usize| {
+            let id = &id[..length];
+            // Targets that are named after their id (such as cgroups and
+            // systemd units) are readable as they are
+            match (base.is_empty(), id.is_empty() || target.name == target.id) {
+                (true, true) => String::from("target"),
+                (false, true) => base.clone(),
+                (true, false) => id.to_owned(),
+                (false, false) => format!("{}-{}", base, id),
+            }
+        };
+
+        let mut length = SHORT_ID_LENGTH.min(id.len());
+        let mut name = candidate(length);
+        while self.taken.contains(&name) && length < id.len() {
+            length = (length + SHORT_ID_STEP).min(id.len());
+            name = candidate(length);
+        }
+        let mut counter = 2;
+        let unique = loop {
+            if !self.taken.contains(&name) {
+                break name;
+            }
+            name = format!("{}-{}", candidate(length), counter);
+            counter += 1;
+        };
+
+        self.taken.insert(unique.clone());
+        self.names.insert(target.id.clone(), unique.clone());
+        unique
+    }
+
+    /// Releases the short name of a target that stopped, so that it can be
+    /// given to another target
+    pub fn release(&mut self, id: &str) {
+        if let Some(name) = self.names.remove(id) {
+            self.taken.remove(&name);
+        }
+    }
+}
+
+/// Gets the letters and digits of the last component of the id (so that ids
+/// that are cgroup paths are shortened to their leaf), or of the whole id if
+/// its last component has none
+fn short_id(id: &str) -> String {
+    let alphanumeric =
+        |s: &str| -> String { s.chars().filter(char::is_ascii_alphanumeric).collect() };
+    let leaf = alphanumeric(id.rsplit('/').next().unwrap_or(id));
+    match leaf.is_empty() {
+        true => alphanumeric(id),
+        false => leaf,
+    }
+}
+
+/// Converts a target's name to one that is safe to use in file names,
+/// replacing runs of other characters than letters, digits, `.`, and `_`
+/// with a single `-`
+fn sanitize(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            sanitized.push(c);
+        } else if !sanitized.is_empty() && !sanitized.ends_with('-') {
+            sanitized.push('-');
+        }
+        if sanitized.len() >= MAX_NAME_LENGTH {
+            break;
+        }
+    }
+    sanitized.trim_end_matches('-').to_owned()
+}
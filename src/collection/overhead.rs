@@ -0,0 +1,90 @@
+use crate::util;
+use std::time::{Duration, Instant};
+
+/// Duration over which the CPU usage of rAdvisor is measured before the
+/// collection interval is adjusted
+const MEASUREMENT_WINDOW: Duration = Duration::from_secs(5);
+
+/// Maximum factor by which the collection interval can be lengthened
+const MAX_SCALE: u32 = 64;
+
+/// Adjustment to the collection interval made by the tuner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adjustment {
+    pub from:  Duration,
+    pub to:    Duration,
+    /// Measured CPU usage of rAdvisor (as a fraction of a single CPU) that
+    /// caused the adjustment
+    pub usage: f64,
+}
+
+/// Measures the CPU usage of rAdvisor itself, lengthening the collection
+/// interval whenever it exceeds the overhead budget, and shortening it (back
+/// to the configured interval at most) once there is enough headroom
+pub struct OverheadTuner {
+    /// Maximum CPU usage, as a fraction of a single CPU
+    budget:     f64,
+    base:       Duration,
+    current:    Duration,
+    window_cpu: Duration,
+    window_at:  Instant,
+}
+
+impl OverheadTuner {
+    #[must_use]
+    pub fn new(budget: f64, base: Duration) -> Self {
+        Self {
+            budget,
+            base,
+            current: base,
+            window_cpu: util::process_cpu_time(),
+            window_at: Instant::now(),
+        }
+    }
+
+    /// Starts over from a new base interval (once it is changed on request),
+    /// measuring the CPU usage from scratch
+    pub fn reset(&mut self, base: Duration) {
+        self.base = base;
+        self.current = base;
+        self.window_cpu = util::process_cpu_time();
+        self.window_at = Instant::now();
+    }
+
+    /// Measures the CPU usage once the current measurement window has ended,
+    /// returning the adjustment to the collection interval if one is needed
+    pub fn tick(&mut self) -> Option<Adjustment> {
+        let elapsed = self.window_at.elapsed();
+        if elapsed < MEASUREMENT_WINDOW {
+            return None;
+        }
+
+        let cpu = util::process_cpu_time();
+        let used = cpu.checked_sub(self.window_cpu).unwrap_or_default();
+        let usage = used.as_secs_f64() / elapsed.as_secs_f64();
+        self.window_cpu = cpu;
+        self.window_at = Instant::now();
+
+        let next = if usage > self.budget {
+            (self.current * 2).min(self.base * MAX_SCALE)
+        } else if usage < self.budget / 2.0 {
+            // Lengthening the interval roughly halves the usage, so only
+            // shorten it again once that would stay within the budget
+            (self.current / 2).max(self.base)
+        } else {
+            self.current
+        };
+
+        if next == self.current {
+            return None;
+        }
+
+        let adjustment = Adjustment {
+            from: self.current,
+            to: next,
+            usage,
+        };
+        self.current = next;
+        Some(adjustment)
+    }
+}
@@ -0,0 +1,71 @@
+use crate::timer::Tick;
+use std::time::{Duration, Instant};
+
+/// Minimum time between consecutive warnings about overruns
+const WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Overruns that happened since the previous warning
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverrunWarning {
+    /// Number of overruns since the previous warning
+    pub count:   u64,
+    /// Number of overruns since collection started
+    pub total:   u64,
+    /// Duration of the longest tick since the previous warning
+    pub longest: Duration,
+}
+
+/// Counts collection ticks whose work took longer than the collection
+/// interval (or that were skipped entirely because the previous tick was
+/// still running), rate-limiting the resulting warnings
+#[derive(Debug, Default)]
+pub struct OverrunMonitor {
+    total:        u64,
+    unreported:   u64,
+    longest:      Duration,
+    last_warning: Option<Instant>,
+}
+
+impl OverrunMonitor {
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Records the duration of a single tick, returning a warning if there
+    /// have been unreported overruns and the previous warning was long enough
+    /// ago
+    pub fn record(
+        &mut self,
+        tick: Tick,
+        duration: Duration,
+        interval: Duration,
+    ) -> Option<OverrunWarning> {
+        let overruns = tick.missed + u64::from(duration > interval);
+        if overruns > 0 {
+            self.total += overruns;
+            self.unreported += overruns;
+            self.longest = self.longest.max(duration);
+        }
+
+        let due = match self.last_warning {
+            Some(last_warning) => last_warning.elapsed() >= WARNING_INTERVAL,
+            None => true,
+        };
+        if self.unreported == 0 || !due {
+            return None;
+        }
+
+        let warning = OverrunWarning {
+            count:   self.unreported,
+            total:   self.total,
+            longest: self.longest,
+        };
+        self.unreported = 0;
+        self.longest = Duration::default();
+        self.last_warning = Some(Instant::now());
+        Some(warning)
+    }
+
+    /// Gets the number of overruns since collection started
+    #[must_use]
+    pub const fn total(&self) -> u64 { self.total }
+}
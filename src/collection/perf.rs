@@ -0,0 +1,204 @@
+//! Counts the hardware events (instructions, cycles, cache misses, and branch
+//! misses) of each target using `perf_event_open` with cgroup attribution,
+//! appending them to each record as `perf.*` columns. Only built with the
+//! `perf` feature.
+//!
+//! The kernel only counts the events of a cgroup per CPU, so a group of
+//! counters is opened on every online CPU for each target, and their counts
+//! are summed. If the kernel multiplexes the counters (when there are more
+//! events than the CPU has hardware counters), the counts are scaled up to
+//! estimate the full count.
+//!
+//! Each counter is counted against the file descriptor budget (see
+//! `fd_budget`), and the counters of a target are only opened if all of them
+//! fit within it.
+
+use crate::collection::fd_budget::{self, Budgeted};
+use crate::formats::{ColumnDeclaration, ColumnKind, Unit};
+use crate::util::{self, cgroup_root, CgroupPath, CgroupVersion, PerfEventAttr};
+use csv::ByteRecord;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+/// The (integer) columns appended to each record, with the number of events
+/// counted since the target's counters were opened
+pub const PERF_COLUMNS: &[ColumnDeclaration] = &[
+    ("perf.instructions", Unit::Count, ColumnKind::Counter),
+    ("perf.cycles", Unit::Count, ColumnKind::Counter),
+    ("perf.cache.misses", Unit::Count, ColumnKind::Counter),
+    ("perf.branch.misses", Unit::Count, ColumnKind::Counter),
+];
+
+/// Generic hardware events counted for each target (`PERF_COUNT_HW_*`), in
+/// the order of `PERF_COLUMNS`
+const EVENTS: [u64; 4] = [
+    1, // PERF_COUNT_HW_INSTRUCTIONS
+    0, // PERF_COUNT_HW_CPU_CYCLES
+    3, // PERF_COUNT_HW_CACHE_MISSES
+    5, // PERF_COUNT_HW_BRANCH_MISSES
+];
+
+/// Minimum time between attempts to open the counters of a target, while they
+/// can't be opened (such as before its cgroup exists in the `perf_event`
+/// hierarchy)
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Checks whether hardware events can be counted at all, by counting the
+/// calling thread's instructions. Fails if the CPU doesn't expose hardware
+/// counters (such as in many virtual machines), or if access to perf events is
+/// restricted (see `perf_event_paranoid`).
+pub fn probe() -> io::Result<()> { open_event(EVENTS[0], 0, -1, None, 0).map(drop) }
+
+/// Hardware event counters of a single target
+pub struct TargetCounters {
+    cgroup:    CgroupPath,
+    /// Group of counters opened on each online CPU, once opened
+    groups:    Option<Vec<CounterGroup>>,
+    opened_at: Option<Instant>,
+}
+
+/// Counters of every event on a single CPU, which are read together through
+/// the group's leader
+struct CounterGroup {
+    leader:   Budgeted<File>,
+    /// The other counters of the group, which are only kept open
+    _members: Vec<Budgeted<File>>,
+}
+
+impl TargetCounters {
+    #[must_use]
+    pub const fn new(cgroup: CgroupPath) -> Self {
+        Self {
+            cgroup,
+            groups: None,
+            opened_at: None,
+        }
+    }
+
+    /// Appends the `perf.*` fields to the record. Fields are left empty until
+    /// the target's counters could be opened, or if they couldn't be read.
+    pub fn push_fields(&mut self, record: &mut ByteRecord) {
+        let due = match self.opened_at {
+            Some(opened_at) => opened_at.elapsed() >= RETRY_INTERVAL,
+            None => true,
+        };
+        if self.groups.is_none() && due {
+            self.groups = open_groups(&self.cgroup).ok();
+            self.opened_at = Some(Instant::now());
+        }
+
+        match self
+            .groups
+            .as_mut()
+            .and_then(|groups| read_totals(groups).ok())
+        {
+            Some(totals) => {
+                let mut itoa_buffer = itoa::Buffer::new();
+                for total in totals {
+                    record.push_field(itoa_buffer.format(total).as_bytes());
+                }
+            },
+            None => {
+                for _ in PERF_COLUMNS {
+                    record.push_field(b"");
+                }
+            },
+        }
+    }
+}
+
+/// Opens a group of counters for the cgroup on every online CPU, unless they
+/// don't all fit within the file descriptor budget
+fn open_groups(cgroup: &CgroupPath) -> io::Result<Vec<CounterGroup>> {
+    let directory = match cgroup.version {
+        CgroupVersion::V1 => cgroup_root().join("perf_event").join(&cgroup.path),
+        CgroupVersion::V2 => cgroup_root().join(&cgroup.path),
+    };
+    let cpus = util::online_cpus()?;
+    // Counters of every event on each CPU, along with the cgroup's directory
+    let needed = (EVENTS.len() * cpus.iter().count() + 1) as u64;
+    if !fd_budget::fits(needed) {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "file descriptor budget exhausted",
+        ));
+    }
+    let cgroup_fd = Budgeted::new(File::open(directory)?);
+
+    let mut groups = Vec::new();
+    for cpu in cpus.iter() {
+        let cpu = i32::try_from(cpu).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let flags = util::PERF_FLAG_PID_CGROUP | util::PERF_FLAG_FD_CLOEXEC;
+        let leader = Budgeted::new(open_event(
+            EVENTS[0],
+            cgroup_fd.as_raw_fd(),
+            cpu,
+            None,
+            flags,
+        )?);
+        let members = EVENTS[1..]
+            .iter()
+            .map(|&event| {
+                open_event(event, cgroup_fd.as_raw_fd(), cpu, Some(&leader), flags)
+                    .map(Budgeted::new)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        groups.push(CounterGroup {
+            leader,
+            _members: members,
+        });
+    }
+
+    Ok(groups)
+}
+
+/// Opens a counter for a hardware event, as part of the leader's group if one
+/// is given
+fn open_event(
+    event: u64,
+    pid: RawFd,
+    cpu: i32,
+    leader: Option<&File>,
+    flags: libc::c_ulong,
+) -> io::Result<File> {
+    let attr = PerfEventAttr {
+        read_format: util::PERF_FORMAT_GROUP
+            | util::PERF_FORMAT_TOTAL_TIME_ENABLED
+            | util::PERF_FORMAT_TOTAL_TIME_RUNNING,
+        flags: util::PERF_ATTR_EXCLUDE_HV,
+        ..PerfEventAttr::new(util::PERF_TYPE_HARDWARE, event)
+    };
+    util::perf_event_open(&attr, pid, cpu, leader, flags)
+}
+
+/// Reads the counters of each group, summing the (scaled) count of each event
+/// over all CPUs
+fn read_totals(groups: &mut [CounterGroup]) -> io::Result<[u64; EVENTS.len()]> {
+    let mut totals = [0_u64; EVENTS.len()];
+    // The group is read as `nr`, `time_enabled`, `time_running`, and then the
+    // count of each event
+    let mut buffer = [0_u8; 8 * (3 + EVENTS.len())];
+    for group in groups {
+        group.leader.read_exact(&mut buffer)?;
+        let mut values = buffer.chunks_exact(8).map(|chunk| {
+            let mut bytes = [0_u8; 8];
+            bytes.copy_from_slice(chunk);
+            u64::from_ne_bytes(bytes)
+        });
+        let (_, enabled, running) = (values.next(), values.next(), values.next());
+        let (enabled, running) = (enabled.unwrap_or(0), running.unwrap_or(0));
+        // Events aren't counted on CPUs that the target never ran on
+        if running == 0 {
+            continue;
+        }
+        for (total, count) in totals.iter_mut().zip(values) {
+            let scaled = u128::from(count) * u128::from(enabled) / u128::from(running);
+            *total = total.saturating_add(u64::try_from(scaled).unwrap_or(u64::MAX));
+        }
+    }
+
+    Ok(totals)
+}
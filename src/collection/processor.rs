@@ -0,0 +1,119 @@
+//! Contains the `RecordProcessor` trait, which lets binaries that embed
+//! rAdvisor post-process each record before it is written, such as to compute
+//! derived metrics inline:
+//!
+//! ```no_run
+//! use csv::ByteRecord;
+//! use radvisor::collection::{ColumnRegistry, RecordProcessor};
+//! use radvisor::formats::{Column, ColumnType};
+//! use radvisor::shared::CollectionTarget;
+//!
+//! /// Appends the memory usage in MiB, and drops records without any usage
+//! struct MemoryMib;
+//!
+//! impl RecordProcessor for MemoryMib {
+//!     fn columns(&self, _collector_type: &str) -> Vec<(String, Column)> {
+//!         vec![(String::from("memory.usage.mib"), Column::Scalar {
+//!             r#type: ColumnType::Int,
+//!         })]
+//!     }
+//!
+//!     fn process(
+//!         &self,
+//!         _target: &CollectionTarget,
+//!         columns: &ColumnRegistry,
+//!         record: &mut ByteRecord,
+//!     ) -> bool {
+//!         let usage = columns
+//!             .get(record, "memory.usage.current")
+//!             .and_then(|field| std::str::from_utf8(field).ok())
+//!             .and_then(|field| field.parse::<u64>().ok())
+//!             .unwrap_or(0);
+//!         record.push_field((usage / 1024 / 1024).to_string().as_bytes());
+//!         usage > 0
+//!     }
+//! }
+//! ```
+//!
+//! Processors are registered on the daemon with `Daemon::add_processor`.
+
+use crate::formats::{Column, ColumnKind, Unit};
+use crate::shared::CollectionTarget;
+use csv::ByteRecord;
+use std::collections::HashMap;
+
+/// Hook that is invoked with each record right before it is written to a log
+/// file. Records skipped in sparse mode are never passed to processors.
+pub trait RecordProcessor: Send + Sync {
+    /// Gets the names and types of the columns that the processor appends to
+    /// every record of the given collector type (such as `cgroup_v1`). These
+    /// are added to the header of each log file.
+    fn columns(&self, collector_type: &str) -> Vec<(String, Column)>;
+
+    /// Gets the unit and kind of the values in one of the columns returned by
+    /// `columns`, which describe the column in the schema of each log file.
+    /// Both are unknown unless the processor declares them.
+    fn describe(&self, _column: &str) -> (Unit, ColumnKind) { (Unit::Unknown, ColumnKind::Unknown) }
+
+    /// Processes a completed record of the given target, appending exactly
+    /// one field for each of the columns returned by `columns`. Returns
+    /// whether the record should be written; if any processor vetoes a record,
+    /// it is dropped.
+    fn process(
+        &self,
+        target: &CollectionTarget,
+        columns: &ColumnRegistry,
+        record: &mut ByteRecord,
+    ) -> bool;
+}
+
+/// Names of the columns of the records passed to a processor, which lets
+/// processors look up fields by name. Includes the columns appended by any
+/// previous processors.
+#[derive(Clone, Debug, Default)]
+pub struct ColumnRegistry {
+    names:   Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl ColumnRegistry {
+    /// Creates a new registry from the CSV header row
+    #[must_use]
+    pub fn new(header: &ByteRecord) -> Self {
+        let mut registry = Self::default();
+        for name in header {
+            registry.push(String::from_utf8_lossy(name).into_owned());
+        }
+        registry
+    }
+
+    /// Adds a column to the end of the registry
+    pub fn push(&mut self, name: String) {
+        self.indices.entry(name.clone()).or_insert(self.names.len());
+        self.names.push(name);
+    }
+
+    /// Gets the index of the (first) column with the given name
+    #[must_use]
+    pub fn index_of(&self, name: &str) -> Option<usize> { self.indices.get(name).copied() }
+
+    /// Gets the field of the record in the column with the given name
+    #[must_use]
+    pub fn get<'r>(&self, record: &'r ByteRecord, name: &str) -> Option<&'r [u8]> {
+        self.index_of(name).and_then(|index| record.get(index))
+    }
+
+    /// Gets the names of all columns, in order
+    #[must_use]
+    pub fn names(&self) -> &[String] { &self.names }
+
+    /// Gets the number of columns
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn len(&self) -> usize { self.names.len() }
+
+    /// Whether there are no columns
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn is_empty(&self) -> bool { self.names.is_empty() }
+}
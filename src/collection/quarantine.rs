@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+/// Time waited before the first attempt at recovering a quarantined log file,
+/// which doubles after each failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest time waited between attempts at recovering a quarantined log file
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Minimum time between checks that the log file wasn't deleted, which
+/// failed writes can't detect since writing to a deleted file succeeds
+const DETACHED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks the consecutive failed writes to a log file. Once too many writes
+/// in a row have failed (such as when the disk is full), the log file is
+/// quarantined: collection for its targets pauses, and recovering the log
+/// file is attempted with exponential backoff instead of failing every tick.
+/// Log files that couldn't be re-created after being deleted are quarantined
+/// as well.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteHealth {
+    /// Number of consecutive failed writes that quarantine the log file, or 0
+    /// if it is never quarantined
+    max_failures: u32,
+    failures:     u32,
+    quarantine:   Option<Quarantine>,
+    checked_at:   Instant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Quarantine {
+    since:    Instant,
+    /// Number of failed attempts at recovering the log file
+    attempts: u32,
+    due:      Instant,
+}
+
+impl WriteHealth {
+    #[must_use]
+    pub fn new(max_failures: u32) -> Self {
+        Self {
+            max_failures,
+            failures: 0,
+            quarantine: None,
+            checked_at: Instant::now(),
+        }
+    }
+
+    /// Whether the log file is currently quarantined
+    #[must_use]
+    pub const fn is_quarantined(&self) -> bool { self.quarantine.is_some() }
+
+    /// Records a successful write
+    pub fn succeeded(&mut self) { self.failures = 0; }
+
+    /// Records a failed write, quarantining the log file if too many writes
+    /// in a row have failed
+    pub fn failed(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+        if self.max_failures == 0 || self.failures < self.max_failures {
+            return;
+        }
+
+        self.quarantine();
+    }
+
+    /// Quarantines the log file right away (unless it already is), such as
+    /// when it couldn't be re-created after being deleted
+    pub fn quarantine(&mut self) {
+        if self.is_quarantined() {
+            return;
+        }
+
+        let now = Instant::now();
+        self.quarantine = Some(Quarantine {
+            since:    now,
+            attempts: 0,
+            due:      now + INITIAL_BACKOFF,
+        });
+    }
+
+    /// Whether a check that the log file wasn't deleted is due. If so, the
+    /// next check is scheduled.
+    pub fn detached_check_due(&mut self) -> bool {
+        if self.checked_at.elapsed() < DETACHED_CHECK_INTERVAL {
+            return false;
+        }
+
+        self.checked_at = Instant::now();
+        true
+    }
+
+    /// Gets the number of consecutive failed writes
+    #[must_use]
+    pub const fn failures(&self) -> u32 { self.failures }
+
+    /// Whether an attempt at recovering the quarantined log file is due. If
+    /// so, the following attempt is scheduled (in case this one fails).
+    pub fn attempt_due(&mut self) -> bool {
+        match &mut self.quarantine {
+            Some(quarantine) if quarantine.due <= Instant::now() => {
+                quarantine.attempts = quarantine.attempts.saturating_add(1);
+                let backoff = INITIAL_BACKOFF
+                    .checked_mul(2_u32.saturating_pow(quarantine.attempts))
+                    .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+                quarantine.due = Instant::now() + backoff;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Lifts the quarantine once the log file was recovered, returning how
+    /// long it was quarantined for
+    pub fn recovered(&mut self) -> Duration {
+        self.failures = 0;
+        self.quarantine
+            .take()
+            .map_or_else(Duration::default, |quarantine| quarantine.since.elapsed())
+    }
+}
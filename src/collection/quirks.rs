@@ -0,0 +1,40 @@
+use crate::formats::Quirk;
+use crate::util::{self, CgroupVersion};
+
+/// Detects all applicable quirks for the running kernel, using a combination
+/// of the kernel release string and probing the mounted cgroup controllers
+#[must_use]
+pub fn detect(os_release: Option<&str>) -> Vec<Quirk> {
+    let mut quirks = Vec::new();
+    let kernel = os_release.and_then(parse_kernel_version);
+
+    match CgroupVersion::try_resolve() {
+        Some(CgroupVersion::V1) => {
+            if util::num_cores() > util::num_available_cores() {
+                quirks.push(Quirk::CpuacctPercpuIncludesOfflineCpus);
+            }
+
+            let blkio_root = util::cgroup_root().join("blkio");
+            if blkio_root.exists() && !blkio_root.join("blkio.io_service_bytes").exists() {
+                quirks.push(Quirk::BlkioCfqStatsUnavailable);
+            }
+        },
+        Some(CgroupVersion::V2) => {
+            if matches!(kernel, Some(version) if version < (5, 9)) {
+                quirks.push(Quirk::MemoryStatPercpuUnavailable);
+            }
+        },
+        None => {},
+    }
+
+    quirks
+}
+
+/// Parses the major and minor version out of a kernel release string, such as
+/// `5.4.0-91-generic`
+fn parse_kernel_version(release: &str) -> Option<(u32, u32)> {
+    let mut parts = release.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse::<u32>().ok()?;
+    let minor = parts.next()?.parse::<u32>().ok()?;
+    Some((major, minor))
+}
@@ -0,0 +1,141 @@
+use crate::shared::Id;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Interval at which an error that keeps occurring for the same target is
+/// printed again, along with the number of times that it was suppressed
+pub const REPEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Operation of the collection thread that an error occurred during, which
+/// are deduplicated separately for each target
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ErrorSource {
+    Collect,
+    Pump,
+    Rotate,
+    Reattach,
+}
+
+impl fmt::Display for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Collect => write!(f, "running the collector"),
+            Self::Pump => write!(f, "writing held-back data"),
+            Self::Rotate => write!(f, "rotating the log file"),
+            Self::Reattach => write!(f, "re-creating the deleted log file"),
+        }
+    }
+}
+
+/// Error that is deduplicated separately from others for the same target:
+/// errors during the same operation are only considered repeats if they have
+/// the same kind (for I/O errors), so that a new failure isn't suppressed
+/// behind an old one
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct ErrorKey {
+    source: ErrorSource,
+    kind:   Option<io::ErrorKind>,
+}
+
+/// Whether an error should be printed, as decided by `RepeatedErrors`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Repeat {
+    /// The error is the first of its kind (for the target), and should be
+    /// printed
+    First,
+    /// The error kept occurring since it was last printed, and should be
+    /// printed again with the number of times it was suppressed since
+    Summarize {
+        suppressed: u64,
+        over:       Duration,
+    },
+    /// The error was printed recently, and should be suppressed
+    Suppress,
+}
+
+/// Deduplicates the errors that occur for each target (and each operation and
+/// kind of error),
+/// so that a single broken target doesn't print the same error every tick.
+/// The first error is printed, and then repeated errors are suppressed and
+/// summarized every `REPEAT_INTERVAL` until the operation succeeds again.
+#[derive(Default)]
+pub struct RepeatedErrors {
+    errors: HashMap<Id, HashMap<ErrorKey, Repeats>>,
+}
+
+/// Repeats of an error since it was last printed
+struct Repeats {
+    printed_at: Instant,
+    suppressed: u64,
+}
+
+impl RepeatedErrors {
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Records an error that occurred for the target (or the group) with the
+    /// given id, along with its kind if it was an I/O error, returning
+    /// whether it should be printed
+    pub fn record(&mut self, id: &str, source: ErrorSource, kind: Option<io::ErrorKind>) -> Repeat {
+        let key = ErrorKey { source, kind };
+        let errors = self.errors.entry(id.to_owned()).or_default();
+        match errors.get_mut(&key) {
+            None => {
+                errors.insert(key, Repeats {
+                    printed_at: Instant::now(),
+                    suppressed: 0,
+                });
+                Repeat::First
+            },
+            Some(repeats) if repeats.printed_at.elapsed() >= REPEAT_INTERVAL => {
+                let over = repeats.printed_at.elapsed();
+                let suppressed = repeats.suppressed;
+                repeats.printed_at = Instant::now();
+                repeats.suppressed = 0;
+                Repeat::Summarize { suppressed, over }
+            },
+            Some(repeats) => {
+                repeats.suppressed += 1;
+                Repeat::Suppress
+            },
+        }
+    }
+
+    /// Records that the operation succeeded for the target, so that the next
+    /// error is printed right away. Returns the number of errors (of any
+    /// kind) that were suppressed since they were last printed, if any were.
+    pub fn resolve(&mut self, id: &str, source: ErrorSource) -> Option<u64> {
+        let errors = self.errors.get_mut(id)?;
+        let mut suppressed = 0;
+        errors.retain(|key, repeats| {
+            let resolved = key.source == source;
+            if resolved {
+                suppressed += repeats.suppressed;
+            }
+            !resolved
+        });
+        if errors.is_empty() {
+            self.errors.remove(id);
+        }
+        Some(suppressed).filter(|&suppressed| suppressed > 0)
+    }
+
+    /// Forgets the errors of a target once it has stopped
+    pub fn forget(&mut self, id: &str) { self.errors.remove(id); }
+}
+
+/// Gets the kind of the I/O error that caused an error, if there was one
+#[must_use]
+pub fn io_error_kind(err: &(dyn Error + 'static)) -> Option<io::ErrorKind> {
+    let mut cause = Some(err);
+    while let Some(err) = cause {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return Some(err.kind());
+        }
+        cause = err.source();
+    }
+    None
+}
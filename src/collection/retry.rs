@@ -0,0 +1,234 @@
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget, Id};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Time waited before the first retry of a target's initialization, which
+/// doubles after each subsequent failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Start event of a target whose collector couldn't be initialized, waiting
+/// to be retried
+struct PendingStart {
+    target: CollectionTarget,
+    method: CollectionMethod,
+    due:    Instant,
+}
+
+/// Retry that was scheduled after a failed initialization
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledRetry {
+    /// Number of the retry, starting at 1
+    pub attempt: u32,
+    pub delay:   Duration,
+}
+
+/// Queue of targets whose collectors couldn't be initialized (such as when
+/// their cgroup doesn't exist yet because they are still starting), which are
+/// retried with exponential backoff up to a maximum number of times
+#[derive(Default)]
+pub struct RetryQueue {
+    pending:     HashMap<Id, PendingStart>,
+    /// Number of failed retries of each target that is still being retried
+    retries:     HashMap<Id, u32>,
+    max_retries: u32,
+}
+
+impl RetryQueue {
+    #[must_use]
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    /// Whether failed initializations are retried at all
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool { self.max_retries > 0 }
+
+    /// Gets the maximum number of retries of each target
+    #[must_use]
+    pub const fn max_retries(&self) -> u32 { self.max_retries }
+
+    /// Schedules another attempt at initializing the target after its
+    /// initialization failed, returning None if it has already been retried
+    /// the maximum number of times
+    pub fn schedule(
+        &mut self,
+        target: CollectionTarget,
+        method: CollectionMethod,
+    ) -> Option<ScheduledRetry> {
+        let retries = self.retries.entry(target.id.clone()).or_insert(0);
+        if *retries >= self.max_retries {
+            self.retries.remove(&target.id);
+            return None;
+        }
+
+        let delay = INITIAL_BACKOFF * 2_u32.saturating_pow(*retries);
+        *retries += 1;
+        let attempt = *retries;
+        self.pending.insert(target.id.clone(), PendingStart {
+            target,
+            method,
+            due: Instant::now() + delay,
+        });
+        Some(ScheduledRetry { attempt, delay })
+    }
+
+    /// Removes and returns the start events of all targets that are due to be
+    /// retried
+    pub fn take_due(&mut self) -> Vec<CollectionEvent> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+
+        let now = Instant::now();
+        let due = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.due <= now)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        due.into_iter()
+            .filter_map(|id| self.pending.remove(&id))
+            .map(|pending| CollectionEvent::Start {
+                target: pending.target,
+                method: pending.method,
+            })
+            .collect()
+    }
+
+    /// Stops retrying the target, either because it was initialized or
+    /// because it stopped
+    pub fn cancel(&mut self, id: &str) {
+        self.pending.remove(id);
+        self.retries.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{CgroupDriver, CgroupPath, CgroupVersion};
+    use std::path::PathBuf;
+
+    fn target(id: &str) -> CollectionTarget {
+        CollectionTarget {
+            provider:  "test",
+            id:        String::from(id),
+            name:      String::from(id),
+            metadata:  None,
+            poll_time: 0,
+            group:     None,
+            file_name: None,
+        }
+    }
+
+    fn method() -> CollectionMethod {
+        CollectionMethod::LinuxCgroupV2(CgroupPath {
+            path:    PathBuf::from("test.slice"),
+            driver:  CgroupDriver::Systemd,
+            version: CgroupVersion::V2,
+        })
+    }
+
+    /// Makes the pending retry of the target due right away
+    fn make_due(queue: &mut RetryQueue, id: &str) {
+        queue.pending.get_mut(id).unwrap().due = Instant::now();
+    }
+
+    #[test]
+    fn backoff_doubles_until_max_retries() {
+        let mut queue = RetryQueue::new(3);
+        let delays = (0..4)
+            .map(|_| queue.schedule(target("a"), method()))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![
+            Some(ScheduledRetry {
+                attempt: 1,
+                delay:   Duration::from_secs(1),
+            }),
+            Some(ScheduledRetry {
+                attempt: 2,
+                delay:   Duration::from_secs(2),
+            }),
+            Some(ScheduledRetry {
+                attempt: 3,
+                delay:   Duration::from_secs(4),
+            }),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn retries_start_over_after_giving_up() {
+        let mut queue = RetryQueue::new(1);
+        assert!(queue.schedule(target("a"), method()).is_some());
+        assert_eq!(queue.schedule(target("a"), method()), None);
+        assert_eq!(
+            queue
+                .schedule(target("a"), method())
+                .map(|retry| retry.attempt),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn disabled_without_retries() {
+        let mut queue = RetryQueue::new(0);
+        assert!(!queue.is_enabled());
+        assert_eq!(queue.schedule(target("a"), method()), None);
+    }
+
+    #[test]
+    fn take_due_only_takes_due_targets() {
+        let mut queue = RetryQueue::new(5);
+        assert!(queue.take_due().is_empty());
+
+        queue.schedule(target("a"), method());
+        queue.schedule(target("b"), method());
+        assert!(queue.take_due().is_empty());
+
+        make_due(&mut queue, "b");
+        let due = queue.take_due();
+        assert_eq!(due.len(), 1);
+        assert!(matches!(&due[0], CollectionEvent::Start { target, .. } if target.id == "b"));
+
+        // Taken targets aren't taken again until they are scheduled again
+        assert!(queue.take_due().is_empty());
+        make_due(&mut queue, "a");
+        assert_eq!(queue.take_due().len(), 1);
+    }
+
+    #[test]
+    fn taking_keeps_the_backoff() {
+        let mut queue = RetryQueue::new(5);
+        queue.schedule(target("a"), method());
+        make_due(&mut queue, "a");
+        assert_eq!(queue.take_due().len(), 1);
+        assert_eq!(
+            queue
+                .schedule(target("a"), method())
+                .map(|retry| retry.delay),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn cancel_removes_pending_retry_and_resets_backoff() {
+        let mut queue = RetryQueue::new(5);
+        queue.schedule(target("a"), method());
+        queue.schedule(target("a"), method());
+        queue.cancel("a");
+
+        assert!(queue.pending.is_empty());
+        assert!(queue.retries.is_empty());
+        assert_eq!(
+            queue.schedule(target("a"), method()),
+            Some(ScheduledRetry {
+                attempt: 1,
+                delay:   INITIAL_BACKOFF,
+            })
+        );
+    }
+}
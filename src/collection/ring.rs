@@ -0,0 +1,109 @@
+use crate::formats::{self, Column, RingHeader};
+use crate::util::{self, MappedFile};
+use csv::ByteRecord;
+use std::convert::TryFrom;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::path::Path;
+
+/// Ring file that records are written to in ring mode, as fixed-size binary
+/// records in a pre-allocated memory mapping. Writing a record only encodes
+/// its fields into the mapping, without any system calls; the kernel writes
+/// the dirty pages back to disk on its own.
+pub struct RingBuffer {
+    map:           MappedFile,
+    header:        RingHeader,
+    columns:       Vec<Column>,
+    /// Slots of the record currently being encoded
+    slots:         Vec<i64>,
+    data_offset:   usize,
+    record_length: usize,
+}
+
+impl RingBuffer {
+    /// Creates a new ring file at the given path that can hold the given
+    /// number of records, using the given columns as the layout of each
+    /// record. The metadata (the text that a log file would start with) is
+    /// written after the binary header.
+    pub fn create(
+        path: &Path,
+        metadata: &[u8],
+        columns: Vec<Column>,
+        capacity: u64,
+    ) -> IoResult<Self> {
+        // Lists (such as `cpuset.cpus`) can hold any CPU of the host
+        let cpus = util::possible_cpus()
+            .ok()
+            .and_then(|cpus| cpus.iter().last())
+            .map_or(1, |last| last + 1);
+        let list_slots = formats::list_slots(cpus);
+        let slots = columns
+            .iter()
+            .map(|column| formats::slot_count(column, list_slots))
+            .sum::<usize>();
+        let header = RingHeader {
+            slots: slots as u64,
+            capacity,
+            written: 0,
+            metadata_length: metadata.len() as u64,
+            list_slots,
+        };
+        if slots == 0 || capacity == 0 {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "ring file must have at least one column and a non-zero capacity",
+            ));
+        }
+
+        let too_large = || IoError::new(ErrorKind::InvalidInput, "ring file is too large");
+        let data_offset = header.data_offset().ok_or_else(too_large)?;
+        let record_length = header.record_length().ok_or_else(too_large)?;
+        let mut map = MappedFile::create(path, header.file_length().ok_or_else(too_large)?)?;
+        let bytes = map.as_mut_slice();
+        header.write(bytes);
+        let metadata_start = formats::RING_HEADER_LENGTH;
+        bytes[metadata_start..metadata_start + metadata.len()].copy_from_slice(metadata);
+
+        Ok(Self {
+            map,
+            header,
+            columns,
+            slots: vec![formats::MISSING; slots],
+            data_offset,
+            record_length,
+        })
+    }
+
+    /// Encodes a record into the next position in the ring, overwriting the
+    /// oldest record if the ring is full. Fields beyond the ring's columns are
+    /// ignored, and missing fields are left empty.
+    pub fn push(&mut self, record: &ByteRecord) {
+        let mut offset = 0;
+        let mut fields = record.iter();
+        for column in &self.columns {
+            let count = formats::slot_count(column, self.header.list_slots);
+            let slots = &mut self.slots[offset..offset + count];
+            formats::encode(fields.next().unwrap_or_default(), column, slots);
+            offset += count;
+        }
+
+        // The position is below the capacity, so the record is within the
+        // file length that was checked on creation
+        let position = usize::try_from(self.header.written % self.header.capacity).unwrap_or(0);
+        let start = self.data_offset + position * self.record_length;
+        let bytes = self.map.as_mut_slice();
+        for (slot, chunk) in self
+            .slots
+            .iter()
+            .zip(bytes[start..start + self.record_length].chunks_exact_mut(formats::SLOT_LENGTH))
+        {
+            chunk.copy_from_slice(&slot.to_le_bytes());
+        }
+
+        // Only count the record once it has been written completely, so that
+        // readers never see a partial record
+        self.header.written += 1;
+        if let Some(written) = self.map.atomic_u64(RingHeader::WRITTEN_OFFSET) {
+            RingHeader::publish_written(written, self.header.written);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Measures the scheduler run-queue delay of each target's threads, which
+//! cgroup counters don't capture, appending it to each record as `sched.*`
+//! columns. Uses the per-thread scheduler statistics in
+//! `/proc/<pid>/task/<tid>/schedstat`, which are only available on kernels
+//! built with `CONFIG_SCHED_INFO`.
+
+use crate::formats::{ColumnDeclaration, ColumnKind, Unit};
+use crate::util::CgroupPath;
+use csv::ByteRecord;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The (integer) columns appended to each record
+pub const SCHED_COLUMNS: &[ColumnDeclaration] = &[
+    ("sched.wait_time", Unit::Nanoseconds, ColumnKind::Counter),
+    ("sched.switches", Unit::Count, ColumnKind::Counter),
+];
+
+/// File used to check whether the kernel exposes scheduler statistics
+const SUPPORT_CHECK_PATH: &str = "/proc/self/schedstat";
+
+/// Whether the kernel exposes per-thread scheduler statistics
+#[must_use]
+pub fn is_supported() -> bool { Path::new(SUPPORT_CHECK_PATH).exists() }
+
+/// Scheduler statistics of a single thread, or summed over many threads
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct SchedStats {
+    /// Nanoseconds spent runnable but waiting on a run queue
+    wait_time: u64,
+    /// Number of times the thread was switched onto a CPU
+    switches:  u64,
+}
+
+/// Tracks the scheduler statistics of a single target's threads. The totals
+/// are accumulated from the change in each thread's statistics between
+/// samples, so that they don't decrease when threads exit.
+pub struct SchedCounter {
+    cgroup:  CgroupPath,
+    threads: HashMap<u32, SchedStats>,
+    total:   SchedStats,
+}
+
+impl SchedCounter {
+    #[must_use]
+    pub fn new(cgroup: CgroupPath) -> Self {
+        Self {
+            cgroup,
+            threads: HashMap::new(),
+            total: SchedStats::default(),
+        }
+    }
+
+    /// Samples the statistics of every thread in the target's cgroup (and its
+    /// descendants), adding their change since the previous sample to the
+    /// totals. Threads that exit while they are being sampled are skipped.
+    pub fn sample(&mut self) {
+        let mut threads = HashMap::with_capacity(self.threads.len());
+        for pid in self.cgroup.processes() {
+            let tasks = fs::read_dir(format!("/proc/{}/task", pid));
+            for entry in tasks.into_iter().flatten().flatten() {
+                let tid = entry.file_name().to_str().and_then(|tid| tid.parse().ok());
+                let stats = fs::read_to_string(entry.path().join("schedstat"))
+                    .ok()
+                    .and_then(|contents| parse(&contents));
+                if let (Some(tid), Some(stats)) = (tid, stats) {
+                    let previous = self.threads.get(&tid).copied().unwrap_or_default();
+                    self.total.wait_time += stats.wait_time.saturating_sub(previous.wait_time);
+                    self.total.switches += stats.switches.saturating_sub(previous.switches);
+                    threads.insert(tid, stats);
+                }
+            }
+        }
+
+        self.threads = threads;
+    }
+
+    /// Appends the `sched.*` fields with the current totals to the record
+    pub fn push_fields(&self, record: &mut ByteRecord) {
+        let mut itoa_buffer = itoa::Buffer::new();
+        record.push_field(itoa_buffer.format(self.total.wait_time).as_bytes());
+        record.push_field(itoa_buffer.format(self.total.switches).as_bytes());
+    }
+}
+
+/// Parses the contents of a `schedstat` file, which contains the time spent
+/// on the CPU, the time spent waiting on a run queue, and the number of
+/// timeslices run (such as `8016384 1273504 21`)
+fn parse(contents: &str) -> Option<SchedStats> {
+    let mut fields = contents.split_whitespace().skip(1);
+    Some(SchedStats {
+        wait_time: fields.next()?.parse().ok()?,
+        switches:  fields.next()?.parse().ok()?,
+    })
+}
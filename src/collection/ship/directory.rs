@@ -0,0 +1,32 @@
+use crate::collection::ship::{Backend, ShipError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Copies files into a directory. Files are copied under a temporary name
+/// and then renamed, so that readers of the directory never see partial
+/// files.
+pub struct DirectoryBackend {
+    directory: PathBuf,
+}
+
+impl DirectoryBackend {
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_owned(),
+        }
+    }
+}
+
+impl Backend for DirectoryBackend {
+    fn upload(&self, file: &Path) -> Result<String, ShipError> {
+        let name = file.file_name().unwrap_or_default();
+        let destination = self.directory.join(name);
+        let mut partial = destination.clone().into_os_string();
+        partial.push(".partial");
+
+        fs::create_dir_all(&self.directory)?;
+        fs::copy(file, &partial)?;
+        fs::rename(&partial, &destination)?;
+        Ok(destination.display().to_string())
+    }
+}
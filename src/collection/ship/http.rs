@@ -0,0 +1,63 @@
+use crate::collection::ship::{Backend, ShipError};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+/// Time after which an upload is abandoned if the server stops responding
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Uploads files with a plain HTTP/1.1 PUT request to
+/// `http://<host>:<port>/<path>/<file name>`, which works with most object
+/// stores and web servers that accept uploads (or a TLS-terminating proxy)
+pub struct HttpBackend {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl HttpBackend {
+    pub fn new(host: &str, port: u16, path: &str) -> Self {
+        Self {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl Backend for HttpBackend {
+    fn upload(&self, file: &Path) -> Result<String, ShipError> {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        let request_path = format!("{}/{}", self.path, name);
+        let mut source = File::open(file)?;
+        let length = source.metadata()?.len();
+
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        write!(
+            stream,
+            "PUT {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: \
+             application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            request_path, self.host, self.port, length
+        )?;
+        io::copy(&mut source, &mut stream)?;
+        stream.flush()?;
+
+        // Only the status line matters: any 2xx status means the file was
+        // stored
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        let status_line = status_line.trim_end();
+        let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+        match status.starts_with('2') {
+            true => Ok(format!(
+                "http://{}:{}{}",
+                self.host, self.port, request_path
+            )),
+            false => Err(ShipError::Rejected(status_line.to_owned())),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Ships finished log files (once their targets stop, their log file is
+//! rotated, or rAdvisor stops) to a remote destination in the background,
+//! retrying failed uploads and recording each shipped file in a local
+//! manifest. Each kind of destination is a `Backend`. Log files written to a
+//! spool directory (see `--spool-dir`) are moved to the log directory the
+//! same way.
+
+mod directory;
+mod http;
+mod spool;
+
+use crate::cli::ParseFailure;
+use crate::shell::Shell;
+use crate::util;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Name of the manifest of shipped files, placed in the log directory
+pub const MANIFEST_NAME: &str = "shipped.csv";
+
+/// Number of times that each file is uploaded before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Time waited after the first failed upload of a file, which doubles after
+/// each subsequent failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Error that can occur when uploading a file
+#[derive(Debug, thiserror::Error)]
+pub enum ShipError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("server responded with {0}")]
+    Rejected(String),
+}
+
+/// Destination that log files are shipped to, parsed from a URL given via
+/// `--ship-to`
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShipTarget {
+    /// `file://<directory>`: copies files to a directory, such as one on a
+    /// network file system
+    Directory(PathBuf),
+    /// `http://<host>[:<port>]/<path>`: uploads files with a PUT request to
+    /// `<path>/<file name>`
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+    },
+}
+
+impl FromStr for ShipTarget {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseFailure::new(String::from("ship destination"), s.to_owned());
+        let (scheme, rest) = s.split_once("://").ok_or_else(invalid)?;
+        match scheme {
+            "file" if !rest.is_empty() => Ok(Self::Directory(PathBuf::from(rest))),
+            "http" => {
+                let (authority, path) = match rest.find('/') {
+                    Some(slash) => rest.split_at(slash),
+                    None => (rest, ""),
+                };
+                let (host, port) = match authority.rsplit_once(':') {
+                    Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+                    None => (authority, 80),
+                };
+                if host.is_empty() {
+                    return Err(invalid());
+                }
+
+                Ok(Self::Http {
+                    host: host.to_owned(),
+                    port,
+                    path: path.trim_end_matches('/').to_owned(),
+                })
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl ShipTarget {
+    /// Creates the backend that uploads files to the destination
+    #[must_use]
+    pub fn backend(&self) -> Box<dyn Backend> {
+        match self {
+            Self::Directory(directory) => Box::new(directory::DirectoryBackend::new(directory)),
+            Self::Http { host, port, path } => Box::new(http::HttpBackend::new(host, *port, path)),
+        }
+    }
+}
+
+/// Creates the backend that moves files from the spool directory into the log
+/// directory
+#[must_use]
+pub fn spool_backend(directory: &Path) -> Box<dyn Backend> {
+    Box::new(spool::SpoolBackend::new(directory))
+}
+
+/// Method of uploading files to a single kind of destination
+pub trait Backend: Send {
+    /// Uploads a single file, returning where it was uploaded to
+    fn upload(&self, file: &Path) -> Result<String, ShipError>;
+}
+
+/// Single row of the manifest of shipped files
+#[derive(Debug, Serialize)]
+struct ManifestRecord<'a> {
+    shipped_at:  u128,
+    file:        &'a str,
+    destination: &'a str,
+}
+
+/// Uploads files on a background thread, in the order they were queued
+pub struct Shipper {
+    tx:     Sender<PathBuf>,
+    thread: JoinHandle<()>,
+}
+
+impl Shipper {
+    /// Starts the background thread that uploads files using the backend,
+    /// appending each shipped file to the manifest at the given path (if any)
+    pub fn start(
+        backend: Box<dyn Backend>,
+        manifest: Option<PathBuf>,
+        shell: Arc<Shell>,
+    ) -> io::Result<Self> {
+        let (tx, rx) = mpsc::channel::<PathBuf>();
+        let thread = thread::Builder::new()
+            .name(String::from("ship"))
+            .spawn(move || {
+                for file in rx {
+                    match upload(backend.as_ref(), &file, &shell) {
+                        Ok(destination) => {
+                            shell.verbose(|sh| {
+                                sh.info(format!(
+                                    "Shipped log file {} to {}",
+                                    file.display(),
+                                    destination
+                                ));
+                            });
+                            let recorded = manifest
+                                .as_ref()
+                                .map(|manifest| (manifest, record(manifest, &file, &destination)));
+                            if let Some((manifest, Err(err))) = recorded {
+                                shell.warn(format!(
+                                    "Could not record shipped log file {} in {}: {}",
+                                    file.display(),
+                                    manifest.display(),
+                                    err
+                                ));
+                            }
+                        },
+                        Err(err) => shell.error(format!(
+                            "Could not ship log file {} after {} attempts: {}",
+                            file.display(),
+                            MAX_ATTEMPTS,
+                            err
+                        )),
+                    }
+                }
+            })?;
+        Ok(Self { tx, thread })
+    }
+
+    /// Queues a finished file to be shipped
+    pub fn ship<P: Into<PathBuf>>(&self, file: P) {
+        // The thread only exits once the sender is dropped
+        let _ = self.tx.send(file.into());
+    }
+
+    /// Waits until all queued files have been shipped (or have failed to be)
+    pub fn finish(self) {
+        drop(self.tx);
+        let _ = self.thread.join();
+    }
+}
+
+/// Uploads a single file, retrying with exponential backoff
+fn upload(backend: &dyn Backend, file: &Path, shell: &Shell) -> Result<String, ShipError> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match backend.upload(file) {
+            Ok(destination) => return Ok(destination),
+            Err(err) if attempt >= MAX_ATTEMPTS => return Err(err),
+            Err(err) => {
+                shell.verbose(|sh| {
+                    sh.warn(format!(
+                        "Could not ship log file {} (attempt {} of {}): {}",
+                        file.display(),
+                        attempt,
+                        MAX_ATTEMPTS,
+                        err
+                    ));
+                });
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            },
+        }
+    }
+}
+
+/// Appends a shipped file to the manifest, creating it (with a header row)
+/// if it doesn't exist yet
+fn record(manifest: &Path, file: &Path, destination: &str) -> Result<(), csv::Error> {
+    let exists = fs::metadata(manifest).is_ok();
+    let handle = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(manifest)?;
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(!exists)
+        .from_writer(handle);
+    writer.serialize(ManifestRecord {
+        shipped_at: util::nano_ts(),
+        file: &file.to_string_lossy(),
+        destination,
+    })?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use crate::collection::ship::{Backend, ShipError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Moves files from the spool directory into the log directory. Files are
+/// renamed if both directories are on the same file system, and are otherwise
+/// copied under a temporary name, renamed, and then removed from the spool
+/// directory, so that readers of the log directory never see partial files.
+pub struct SpoolBackend {
+    directory: PathBuf,
+}
+
+impl SpoolBackend {
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_owned(),
+        }
+    }
+}
+
+impl Backend for SpoolBackend {
+    fn upload(&self, file: &Path) -> Result<String, ShipError> {
+        let name = file.file_name().unwrap_or_default();
+        let destination = self.directory.join(name);
+        fs::create_dir_all(&self.directory)?;
+        match fs::rename(file, &destination) {
+            Ok(()) => {},
+            // The directories are on different file systems
+            Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+                let mut partial = destination.clone().into_os_string();
+                partial.push(".partial");
+                fs::copy(file, &partial)?;
+                fs::rename(&partial, &destination)?;
+                fs::remove_file(file)?;
+            },
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(destination.display().to_string())
+    }
+}
@@ -0,0 +1,213 @@
+use crate::cli::ParseFailure;
+use crate::collection::fd_budget::Budgeted;
+use std::fs::File;
+use std::io::{self, Result as IoResult, Write};
+use std::os::unix::fs::MetadataExt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Destination that log files are written to, controllable via a CLI flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SinkType {
+    /// Log files are written to disk
+    File,
+    /// All bytes are discarded (but counted), which is useful to measure the
+    /// overhead of collection without any I/O
+    Null,
+    /// Records are written as fixed-size binary records to a memory-mapped
+    /// ring file per log file, which can be converted to a log file with
+    /// `radvisor export`
+    Ring,
+    /// Records of all targets are written to stdout as a single stream, each
+    /// prefixed with the id and provider of its target, and with the header of
+    /// each log file written as a block of `#` comments when it is opened
+    Stream,
+}
+
+impl FromStr for SinkType {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "file" => Ok(Self::File),
+            "null" => Ok(Self::Null),
+            "ring" => Ok(Self::Ring),
+            "stream" | "-" => Ok(Self::Stream),
+            _ => Err(ParseFailure::new(String::from("sink"), s.to_owned())),
+        }
+    }
+}
+
+/// When log files are synced to durable storage (using `fsync`), controllable
+/// via a CLI flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncPolicy {
+    /// Log files are never synced explicitly, leaving it up to the kernel
+    Never,
+    /// Log files are synced with the first buffer flush after the given
+    /// duration has passed since the previous sync, and when they are closed
+    Interval(Duration),
+    /// Log files are synced after every buffer flush
+    EveryFlush,
+}
+
+impl SyncPolicy {
+    /// Whether a log file that was last synced at the given time should be
+    /// synced after the current buffer flush
+    #[must_use]
+    pub fn is_due(&self, synced_at: Instant) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Interval(interval) => synced_at.elapsed() >= *interval,
+            Self::EveryFlush => true,
+        }
+    }
+}
+
+impl FromStr for SyncPolicy {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let failure = || ParseFailure::new(String::from("sync policy"), s.to_owned());
+        match s.to_lowercase().as_str() {
+            "never" => Ok(Self::Never),
+            "every-flush" => Ok(Self::EveryFlush),
+            other => match other.strip_prefix("interval:") {
+                Some(interval) => humantime::Duration::from_str(interval)
+                    .map(|interval| Self::Interval(interval.into()))
+                    .map_err(|_| failure()),
+                None => Err(failure()),
+            },
+        }
+    }
+}
+
+/// Destination writer that can be synced to durable storage
+pub trait SyncData {
+    fn sync_data(&self) -> IoResult<()>;
+}
+
+impl SyncData for File {
+    fn sync_data(&self) -> IoResult<()> { Self::sync_data(self) }
+}
+
+/// Destination writer of a single log file
+pub enum Sink {
+    /// Log file on disk, which is counted against the file descriptor budget
+    File(Budgeted<File>),
+    /// Discards all bytes, adding their count to the shared counter
+    Null(Arc<AtomicU64>),
+    Stream(StreamSink),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            Self::Null(discarded) => {
+                discarded.fetch_add(buf.len() as u64, Ordering::Relaxed);
+                Ok(buf.len())
+            },
+            Self::Stream(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Null(_) => Ok(()),
+            Self::Stream(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Sink {
+    /// Gets the device and inode numbers of the log file being written to, if
+    /// any, which identify it even if it is deleted or replaced
+    #[must_use]
+    pub fn file_id(&self) -> Option<(u64, u64)> {
+        match self {
+            Self::File(file) => file
+                .metadata()
+                .ok()
+                .map(|metadata| (metadata.dev(), metadata.ino())),
+            Self::Null(_) | Self::Stream(_) => None,
+        }
+    }
+}
+
+impl SyncData for Sink {
+    fn sync_data(&self) -> IoResult<()> {
+        match self {
+            Self::File(file) => file.sync_data(),
+            Self::Null(_) | Self::Stream(_) => Ok(()),
+        }
+    }
+}
+
+/// Writes the lines of a single log file to stdout, which is shared by all log
+/// files when using the stream sink. Lines are only written once they are
+/// complete (each prefixed with the fields that identify the log file's
+/// target), so that the lines of different log files never interleave.
+pub struct StreamSink {
+    prefix:  Vec<u8>,
+    /// Bytes of the last line that haven't been written yet
+    pending: Vec<u8>,
+}
+
+impl StreamSink {
+    /// Creates a sink that prefixes each line with the given id and provider
+    /// fields (such as `2a8d2e1f,docker,`)
+    #[must_use]
+    pub fn new(target_id: &str, provider: &str) -> Self {
+        let mut prefix = csv::Writer::from_writer(Vec::new());
+        prefix
+            .write_record([target_id, provider])
+            .expect("Writing to a Vec can't fail");
+        let mut prefix = prefix.into_inner().unwrap_or_default();
+        prefix.pop();
+        prefix.push(b',');
+        Self {
+            prefix,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Writes the text to the stream as a block of `#` comments, without any
+    /// line prefix
+    pub fn write_comment(text: &str) -> IoResult<()> {
+        let mut block = String::with_capacity(text.len() + text.len() / 8);
+        for line in text.lines() {
+            match line.is_empty() {
+                true => block.push('#'),
+                false => {
+                    block.push_str("# ");
+                    block.push_str(line);
+                },
+            }
+            block.push('\n');
+        }
+        io::stdout().lock().write_all(block.as_bytes())
+    }
+}
+
+impl Write for StreamSink {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.pending.extend_from_slice(buf);
+        if let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') {
+            let lines = &self.pending[..=end];
+            let mut out = Vec::with_capacity(lines.len() + self.prefix.len() * 4);
+            for line in lines.split_inclusive(|&b| b == b'\n') {
+                out.extend_from_slice(&self.prefix);
+                out.extend_from_slice(line);
+            }
+            self.pending.drain(..=end);
+            io::stdout().lock().write_all(&out)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> { io::stdout().lock().flush() }
+}
@@ -0,0 +1,221 @@
+//! Contains the `radvisor snapshot` command, which polls the provider once and
+//! collects a single record for each of its current targets, printing them to
+//! stdout instead of writing log files. Useful for quick inspection and for
+//! low-frequency monitoring (such as from cron) without running the daemon.
+
+use crate::cli::{ParseFailure, RunCommand, SnapshotCommand};
+use crate::collection::buffers::WorkingBuffers;
+use crate::collection::collectors::{Collector, CollectorImpl, CollectorOptions};
+use crate::export;
+use crate::formats::{Column, ColumnType, TableMetadata};
+use crate::polling;
+use crate::polling::providers::{InitializationError, ProviderType};
+use crate::shared::{CollectionEvent, CollectionTarget};
+use crate::shell::Shell;
+use csv::ByteRecord;
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Format that snapshots are printed in, controllable via a CLI flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotFormat {
+    /// A table of the columns and values of each target's record
+    Table,
+    /// A single JSON object with an array of targets, each of which includes
+    /// its record as an object keyed by column name
+    Json,
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            _ => Err(ParseFailure::new(String::from("format"), s.to_owned())),
+        }
+    }
+}
+
+/// Error that can occur when taking or printing a snapshot
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("the {0} provider can't be used to take snapshots")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Initialization(#[from] InitializationError),
+    #[error("could not poll the provider: {0}")]
+    Poll(#[source] anyhow::Error),
+    #[error("could not print the snapshot: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not serialize the snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Single record collected for a target
+struct Sample {
+    target:    CollectionTarget,
+    collector: &'static str,
+    cgroup:    String,
+    table:     TableMetadata,
+    header:    ByteRecord,
+    record:    ByteRecord,
+}
+
+/// Records collected for each of the provider's targets at a single point in
+/// time
+pub struct Snapshot {
+    samples: Vec<Sample>,
+}
+
+impl Snapshot {
+    /// Initializes the provider, polls it once, and collects a single record
+    /// for each target that it returns. Targets whose collectors couldn't be
+    /// initialized are skipped with a warning.
+    pub fn take(opts: &SnapshotCommand, shell: &Arc<Shell>) -> Result<Self, SnapshotError> {
+        // The exec provider launches a command, which would be left running
+        if let ProviderType::Exec(_) = &opts.provider {
+            return Err(SnapshotError::Unsupported("exec"));
+        }
+
+        let run_opts = RunCommand {
+            provider: opts.provider.clone(),
+        };
+        polling::resolve_cgroup_root(opts.provider.polling(), shell);
+        let mut provider = opts.provider.get_impl();
+        provider.initialize(&run_opts, Arc::clone(shell))?;
+        let events = provider.poll().map_err(SnapshotError::Poll)?;
+
+        let options = CollectorOptions::new(opts.provider.collection());
+        let mut working_buffers = WorkingBuffers::new();
+        let samples = events
+            .into_iter()
+            .filter_map(|event| match event {
+                CollectionEvent::Start { target, method } => Some((target, method)),
+                CollectionEvent::Stop(_) => None,
+            })
+            .filter_map(|(target, method)| {
+                let mut collector = CollectorImpl::new(method, options.clone());
+                if let Err(err) = collector.init() {
+                    shell.warn(format!(
+                        "Could not initialize collector for target {}: {}",
+                        target.id, err
+                    ));
+                    return None;
+                }
+
+                collector.collect(&mut working_buffers);
+                let record = working_buffers.record.clone();
+                working_buffers.record.clear();
+                Some(Sample {
+                    collector: collector.get_type(),
+                    cgroup: Path::new("/")
+                        .join(&collector.cgroup().path)
+                        .display()
+                        .to_string(),
+                    table: collector.table_metadata(),
+                    header: collector.header().clone(),
+                    record,
+                    target,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        match samples.is_empty() {
+            true => shell.warn("The provider didn't return any targets to collect"),
+            false => shell.verbose(|sh| sh.info(format!("Collected {} targets", samples.len()))),
+        }
+        Ok(Self { samples })
+    }
+
+    /// Prints the snapshot in the given format
+    pub fn write<W: Write>(
+        &self,
+        format: SnapshotFormat,
+        out: &mut W,
+    ) -> Result<(), SnapshotError> {
+        match format {
+            SnapshotFormat::Table => self.write_table(out)?,
+            SnapshotFormat::Json => {
+                let targets = self.samples.iter().map(Sample::to_json).collect::<Vec<_>>();
+                let mut object = Map::new();
+                object.insert(String::from("Targets"), Value::Array(targets));
+                serde_json::to_writer(&mut *out, &object)?;
+                writeln!(out)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Prints a block for each target with its column names and values, where
+    /// empty values are printed as `-`
+    fn write_table<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (i, sample) in self.samples.iter().enumerate() {
+            if i != 0 {
+                writeln!(out)?;
+            }
+
+            writeln!(
+                out,
+                "{} ({}, {} collector, cgroup {})",
+                sample.target.name, sample.target.provider, sample.collector, sample.cgroup
+            )?;
+            let width = sample.header.iter().map(<[u8]>::len).max().unwrap_or(0);
+            for (column, field) in sample.header.iter().zip(&sample.record) {
+                let value = match field.is_empty() {
+                    true => String::from("-"),
+                    false => String::from_utf8_lossy(field).into_owned(),
+                };
+                writeln!(
+                    out,
+                    "  {:width$}  {}",
+                    String::from_utf8_lossy(column),
+                    value,
+                    width = width
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Sample {
+    /// Converts the sample to a JSON object, converting the fields of its
+    /// record according to the definitions of their columns (where columns
+    /// without a definition contain integers)
+    fn to_json(&self) -> Value {
+        let int = Column::Scalar {
+            r#type: ColumnType::Int,
+        };
+        let record = self
+            .header
+            .iter()
+            .zip(&self.record)
+            .map(|(column, field)| {
+                let column = String::from_utf8_lossy(column).into_owned();
+                let definition = self.table.columns.get(&column).unwrap_or(&int);
+                let value = export::to_json(field, Some(definition));
+                (column, value)
+            })
+            .collect::<Map<_, _>>();
+
+        let mut object = Map::new();
+        object.insert(String::from("Id"), Value::from(self.target.id.clone()));
+        object.insert(String::from("Name"), Value::from(self.target.name.clone()));
+        object.insert(String::from("Provider"), Value::from(self.target.provider));
+        object.insert(String::from("Collector"), Value::from(self.collector));
+        object.insert(String::from("Cgroup"), Value::from(self.cgroup.clone()));
+        object.insert(
+            String::from("Metadata"),
+            serde_json::to_value(&self.target.metadata).unwrap_or(Value::Null),
+        );
+        object.insert(String::from("Record"), Value::Object(record));
+        Value::Object(object)
+    }
+}
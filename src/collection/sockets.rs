@@ -0,0 +1,124 @@
+//! Counts the TCP and UDP sockets in the network namespace of each target's
+//! init process (the process with the lowest pid in its cgroup and the
+//! cgroup's descendants, such as a pod's pause container), appending them to
+//! each record as `net.*` columns. Since this parses the socket tables in
+//! `/proc/<pid>/net`, it is only done every few collector ticks, with the
+//! fields left empty in between.
+
+use crate::formats::{ColumnDeclaration, ColumnKind, Unit};
+use crate::util::CgroupPath;
+use csv::ByteRecord;
+use std::fs;
+use std::num::NonZeroU32;
+
+/// The (integer) columns appended to each record
+pub const SOCKET_COLUMNS: &[ColumnDeclaration] = &[
+    ("net.tcp.active", Unit::Count, ColumnKind::Gauge),
+    ("net.tcp.listen", Unit::Count, ColumnKind::Gauge),
+    ("net.udp.sockets", Unit::Count, ColumnKind::Gauge),
+];
+
+/// Socket tables of each protocol, relative to `/proc/<pid>/net`
+const TCP_TABLES: &[&str] = &["tcp", "tcp6"];
+const UDP_TABLES: &[&str] = &["udp", "udp6"];
+
+/// TCP socket states (in the `st` column of the socket tables)
+const TCP_CLOSE: &str = "07";
+const TCP_LISTEN: &str = "0A";
+
+/// Sockets in a network namespace
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SocketCounts {
+    /// TCP sockets that are connected, connecting, or closing
+    pub tcp_active: u64,
+    pub tcp_listen: u64,
+    pub udp:        u64,
+}
+
+/// Counts the sockets of a single target every `divisor` ticks
+pub struct SocketCounter {
+    cgroup:    CgroupPath,
+    divisor:   NonZeroU32,
+    /// Number of ticks until the next count, where 0 means this tick
+    remaining: u32,
+}
+
+impl SocketCounter {
+    #[must_use]
+    pub const fn new(cgroup: CgroupPath, divisor: NonZeroU32) -> Self {
+        Self {
+            cgroup,
+            divisor,
+            remaining: 0,
+        }
+    }
+
+    /// Advances to the next tick, counting the target's sockets if they are
+    /// due to be counted. Should be called once per tick, whether or not the
+    /// tick's record is written.
+    pub fn tick(&mut self) -> Option<SocketCounts> {
+        match self.remaining {
+            0 => {
+                self.remaining = self.divisor.get() - 1;
+                count(&self.cgroup)
+            },
+            _ => {
+                self.remaining -= 1;
+                None
+            },
+        }
+    }
+}
+
+/// Appends the `net.*` fields to the record, which are empty if the sockets
+/// weren't counted this tick
+pub fn push_fields(counts: Option<SocketCounts>, record: &mut ByteRecord) {
+    match counts {
+        Some(counts) => {
+            let mut itoa_buffer = itoa::Buffer::new();
+            record.push_field(itoa_buffer.format(counts.tcp_active).as_bytes());
+            record.push_field(itoa_buffer.format(counts.tcp_listen).as_bytes());
+            record.push_field(itoa_buffer.format(counts.udp).as_bytes());
+        },
+        None => {
+            for _ in SOCKET_COLUMNS {
+                record.push_field(b"");
+            }
+        },
+    }
+}
+
+/// Counts the sockets in the network namespace of the cgroup's init process,
+/// if it has any processes
+fn count(cgroup: &CgroupPath) -> Option<SocketCounts> {
+    let pid = cgroup.processes().into_iter().min()?;
+    let mut counts = SocketCounts::default();
+    for table in TCP_TABLES {
+        for_each_state(pid, table, |state| match state {
+            TCP_LISTEN => counts.tcp_listen += 1,
+            TCP_CLOSE => {},
+            _ => counts.tcp_active += 1,
+        });
+    }
+    for table in UDP_TABLES {
+        for_each_state(pid, table, |_| counts.udp += 1);
+    }
+
+    Some(counts)
+}
+
+/// Calls the function with the state of each socket in one of the process's
+/// socket tables, skipping tables that couldn't be read (such as when IPv6 is
+/// disabled)
+fn for_each_state<F: FnMut(&str)>(pid: u32, table: &str, mut f: F) {
+    let contents = fs::read_to_string(format!("/proc/{}/net/{}", pid, table)).unwrap_or_default();
+    // Lines look like `0: 0100007F:0CEA 00000000:0000 0A ...` (after a
+    // header line), where the fourth field is the state
+    for state in contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(3))
+    {
+        f(state);
+    }
+}
@@ -0,0 +1,95 @@
+use csv::ByteRecord;
+use std::time::{Duration, Instant};
+
+/// Conditions under which records are written in sparse mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SparsePolicy {
+    /// Minimum absolute change in any counter (since the last written record)
+    /// for a record to be written
+    pub epsilon:   u64,
+    /// Maximum duration between written records, after which a record is
+    /// written even if no counters changed
+    pub heartbeat: Duration,
+}
+
+/// Filters out records that are (nearly) identical to the last written record
+/// for a single target, so that idle targets only produce heartbeat records
+pub struct SparseFilter {
+    policy:     SparsePolicy,
+    /// Number of leading fields (such as the read timestamp) that are
+    /// ignored when comparing records
+    key_fields: usize,
+    last:       Option<ByteRecord>,
+    written_at: Instant,
+}
+
+impl SparseFilter {
+    #[must_use]
+    pub fn new(policy: SparsePolicy, key_fields: usize) -> Self {
+        Self {
+            policy,
+            key_fields,
+            last: None,
+            written_at: Instant::now(),
+        }
+    }
+
+    /// Determines whether the given record should be written, updating the
+    /// last written record if so
+    pub fn should_write(&mut self, record: &ByteRecord) -> bool {
+        let write = match &self.last {
+            None => true,
+            Some(last) => {
+                self.written_at.elapsed() >= self.policy.heartbeat
+                    || last.len() != record.len()
+                    || last
+                        .iter()
+                        .zip(record.iter())
+                        .skip(self.key_fields)
+                        .any(|(prev, curr)| changed(prev, curr, self.policy.epsilon))
+            },
+        };
+
+        if write {
+            self.last = Some(record.clone());
+            self.written_at = Instant::now();
+        }
+
+        write
+    }
+
+    /// Forgets the last written record, such that the next record is always
+    /// written (used when a new log file is opened)
+    pub fn reset(&mut self) { self.last = None; }
+}
+
+/// Determines whether a field changed by more than epsilon. Fields can
+/// contain space-separated lists of values (such as per-CPU usage), which are
+/// compared element-wise. Any non-numeric values (such as `max`) are only
+/// considered unchanged if they are identical.
+fn changed(prev: &[u8], curr: &[u8], epsilon: u64) -> bool {
+    if prev == curr {
+        return false;
+    }
+
+    let mut prev_values = prev.split(|&c| c == b' ');
+    let mut curr_values = curr.split(|&c| c == b' ');
+    loop {
+        match (prev_values.next(), curr_values.next()) {
+            (None, None) => return false,
+            (Some(prev_value), Some(curr_value)) => {
+                let unchanged = match (parse(prev_value), parse(curr_value)) {
+                    (Some(a), Some(b)) => a.max(b) - a.min(b) <= epsilon,
+                    _ => prev_value == curr_value,
+                };
+                if !unchanged {
+                    return true;
+                }
+            },
+            _ => return true,
+        }
+    }
+}
+
+/// Parses a single unsigned integer value from a field
+fn parse(value: &[u8]) -> Option<u64> { std::str::from_utf8(value).ok()?.parse().ok() }
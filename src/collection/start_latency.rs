@@ -0,0 +1,173 @@
+//! Measures how long it takes for each target to be collected once it starts:
+//! from when the provider polled it, to when its collector was initialized,
+//! to when its first sample was collected. This characterizes the detection
+//! latency of the polling subsystem (which is bounded by the polling
+//! interval), and the latencies can be written to `start_latency.csv` in the
+//! log directory for further analysis.
+
+use crate::shared::Id;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the start latency log in the log directory
+pub const START_LATENCY_LOG_NAME: &str = "start_latency.csv";
+
+/// Percentiles of each latency that are included in the summary
+const PERCENTILES: &[usize] = &[50, 99];
+
+/// Timestamps (in nanoseconds since the Unix epoch) of the start of a single
+/// target's collection
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartLatency {
+    pub id:              Id,
+    pub name:            String,
+    /// When the provider polled the target (`PolledAt` in the header)
+    pub polled_at:       u128,
+    /// When the target's collector was initialized (`InitializedAt` in the
+    /// header)
+    pub initialized_at:  u128,
+    /// When the target's first sample was collected
+    pub first_sample_at: u128,
+}
+
+impl StartLatency {
+    const fn poll_to_init(&self) -> u128 { self.initialized_at.saturating_sub(self.polled_at) }
+
+    const fn init_to_sample(&self) -> u128 {
+        self.first_sample_at.saturating_sub(self.initialized_at)
+    }
+
+    const fn poll_to_sample(&self) -> u128 { self.first_sample_at.saturating_sub(self.polled_at) }
+}
+
+/// Single row of the start latency log, with each latency in nanoseconds
+#[derive(Debug, Serialize)]
+struct StartLatencyRecord<'a> {
+    id:              &'a str,
+    name:            &'a str,
+    polled_at:       u128,
+    initialized_at:  u128,
+    first_sample_at: u128,
+    poll_to_init:    u128,
+    init_to_sample:  u128,
+    poll_to_sample:  u128,
+}
+
+/// Start latencies of all targets collected so far, which are summarized once
+/// collection stops
+#[derive(Default)]
+pub struct StartLatencies {
+    poll_to_init:   Vec<u128>,
+    init_to_sample: Vec<u128>,
+    poll_to_sample: Vec<u128>,
+    log:            Option<StartLatencyLog>,
+}
+
+/// Start latency log, shared by all targets
+pub struct StartLatencyLog {
+    pub path: PathBuf,
+    writer:   csv::Writer<File>,
+}
+
+impl StartLatencyLog {
+    /// Opens the start latency log in the given directory, appending to it if
+    /// it already exists (in which case the header row isn't written again)
+    pub fn open<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+        let path = directory.join(START_LATENCY_LOG_NAME);
+        let existing = path.exists() && fs::metadata(&path)?.len() > 0;
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let writer = csv::WriterBuilder::new()
+            .has_headers(!existing)
+            .from_writer(file);
+        Ok(Self { path, writer })
+    }
+}
+
+impl StartLatencies {
+    #[must_use]
+    pub fn new(log: Option<StartLatencyLog>) -> Self {
+        Self {
+            log,
+            ..Self::default()
+        }
+    }
+
+    /// Records the start latency of each of the targets, writing them to the
+    /// start latency log if it is enabled
+    pub fn extend(&mut self, latencies: Vec<StartLatency>) -> Result<(), csv::Error> {
+        for latency in latencies {
+            self.poll_to_init.push(latency.poll_to_init());
+            self.init_to_sample.push(latency.init_to_sample());
+            self.poll_to_sample.push(latency.poll_to_sample());
+            if let Some(log) = &mut self.log {
+                log.writer.serialize(StartLatencyRecord {
+                    id:              &latency.id,
+                    name:            &latency.name,
+                    polled_at:       latency.polled_at,
+                    initialized_at:  latency.initialized_at,
+                    first_sample_at: latency.first_sample_at,
+                    poll_to_init:    latency.poll_to_init(),
+                    init_to_sample:  latency.init_to_sample(),
+                    poll_to_sample:  latency.poll_to_sample(),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the start latency log (if it is enabled), returning its path
+    pub fn flush(&mut self) -> io::Result<Option<&Path>> {
+        match &mut self.log {
+            Some(log) => {
+                log.writer.flush()?;
+                Ok(Some(&log.path))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Summarizes the latencies of all targets collected so far, or returns
+    /// None if no targets have been sampled yet
+    #[must_use]
+    pub fn summary(&self) -> Option<String> {
+        if self.poll_to_sample.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "start latency of {} targets: poll to init {}; init to first sample {}; poll to first \
+             sample {}",
+            self.poll_to_sample.len(),
+            summarize(&self.poll_to_init),
+            summarize(&self.init_to_sample),
+            summarize(&self.poll_to_sample)
+        ))
+    }
+}
+
+/// Formats the percentiles and maximum of the latencies
+fn summarize(latencies: &[u128]) -> String {
+    let mut sorted = latencies.to_vec();
+    sorted.sort_unstable();
+    let duration = |nanos: u128| Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX));
+    let mut parts = PERCENTILES
+        .iter()
+        .map(|&p| {
+            format!(
+                "p{} {:?}",
+                p,
+                duration(sorted[(sorted.len() - 1) * p / 100])
+            )
+        })
+        .collect::<Vec<_>>();
+    if let Some(&max) = sorted.last() {
+        parts.push(format!("max {:?}", duration(max)));
+    }
+    parts.join(", ")
+}
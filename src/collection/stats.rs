@@ -0,0 +1,168 @@
+//! Keeps runtime statistics about the collection thread, which are printed
+//! on demand (when rAdvisor receives SIGUSR1) to get a snapshot of a long run
+//! without attaching a debugger.
+
+use crate::collection::collectors::Handle;
+use crate::collection::fd_budget;
+use crate::collection::repeated::{ErrorSource, Repeat, RepeatedErrors};
+use crate::shared::Id;
+use crate::shell::Shell;
+use byte_unit::Byte;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Number of recent collection ticks that latency percentiles are computed
+/// over
+const TICK_WINDOW: usize = 1024;
+
+/// Percentiles of the tick duration that are printed
+const PERCENTILES: &[usize] = &[50, 90, 99];
+
+/// Runtime statistics that aren't already kept by the collector handles
+pub struct RuntimeStats {
+    started_at:  Instant,
+    /// Durations of the most recent collection ticks, oldest first
+    ticks:       VecDeque<Duration>,
+    /// Most recent error of each active target
+    last_errors: HashMap<Id, String>,
+    /// Errors that were printed recently, which aren't printed again right
+    /// away if they repeat
+    repeated:    RepeatedErrors,
+}
+
+impl RuntimeStats {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            started_at:  Instant::now(),
+            ticks:       VecDeque::with_capacity(TICK_WINDOW),
+            last_errors: HashMap::new(),
+            repeated:    RepeatedErrors::new(),
+        }
+    }
+
+    /// Records the time it took to collect all targets during a single tick
+    pub fn record_tick(&mut self, duration: Duration) {
+        if self.ticks.len() == TICK_WINDOW {
+            self.ticks.pop_front();
+        }
+        self.ticks.push_back(duration);
+    }
+
+    /// Records an error that occurred while collecting or writing the target
+    /// (or the group) with the given id (along with its kind if it was an I/O
+    /// error), returning whether it should be reported (since the same error
+    /// might have been reported recently)
+    pub fn record_error<M: ToString>(
+        &mut self,
+        id: &str,
+        source: ErrorSource,
+        kind: Option<io::ErrorKind>,
+        message: &M,
+    ) -> Repeat {
+        self.last_errors.insert(id.to_owned(), message.to_string());
+        self.repeated.record(id, source, kind)
+    }
+
+    /// Records that an operation that previously failed for the target (or
+    /// the group) with the given id succeeded. Returns the number of errors
+    /// that were suppressed since the last one was printed, if any were.
+    pub fn resolve_error(&mut self, id: &str, source: ErrorSource) -> Option<u64> {
+        self.repeated.resolve(id, source)
+    }
+
+    /// Forgets the most recent error of a target once it has stopped
+    pub fn forget(&mut self, id: &str) {
+        self.last_errors.remove(id);
+        self.repeated.forget(id);
+    }
+
+    /// Prints the statistics of the collection thread and of each active
+    /// target
+    pub fn dump(
+        &self,
+        handles: &HashMap<String, RefCell<Handle>>,
+        max_targets: Option<usize>,
+        waiting: usize,
+        shell: &Shell,
+    ) {
+        let targets = handles
+            .values()
+            .map(|h| h.borrow().rows().count())
+            .sum::<usize>();
+        let limit = match (max_targets, waiting) {
+            (Some(max), 0) => format!(" (of at most {})", max),
+            (Some(max), waiting) => format!(" (of at most {}; {} more waiting)", max, waiting),
+            (None, _) => String::new(),
+        };
+        shell.status(
+            "Statistics",
+            format!(
+                "after {}: {} active targets{} in {} log files",
+                humantime::format_duration(truncate(self.started_at.elapsed())),
+                targets,
+                limit,
+                handles.len()
+            ),
+        );
+        let (open_files, budget) = fd_budget::usage();
+        shell.info(format!(
+            "File descriptors held open by collectors: {}{}",
+            open_files,
+            budget
+                .map(|budget| format!(" (of a budget of {})", budget))
+                .unwrap_or_default()
+        ));
+
+        let mut ticks = self.ticks.iter().copied().collect::<Vec<_>>();
+        ticks.sort_unstable();
+        if let Some(&max) = ticks.last() {
+            let percentiles = PERCENTILES
+                .iter()
+                .map(|&p| {
+                    let duration = ticks[(ticks.len() - 1) * p / 100];
+                    format!("p{} {:?}", p, duration)
+                })
+                .collect::<Vec<_>>();
+            shell.info(format!(
+                "Collection tick durations over the last {} ticks: {}, max {:?}",
+                ticks.len(),
+                percentiles.join(", "),
+                max
+            ));
+        }
+
+        let mut names = handles.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let handle = handles[name].borrow();
+            let bytes = Byte::from_bytes(u128::from(handle.bytes_written()));
+            let (peak, capacity) = handle.buffer_usage();
+            let log_error = self.last_errors.get(name.as_str());
+            for (id, target, rows) in handle.rows() {
+                let last_error = self.last_errors.get(id).or(log_error);
+                shell.info(format!(
+                    "Target '{}' ({}): {} rows written, {} flushed to {}; peak buffer fill of {} \
+                     (of {}); last error: {}",
+                    target,
+                    id,
+                    rows,
+                    bytes.get_appropriate_unit(true),
+                    handle.path,
+                    Byte::from_bytes(u128::from(peak)).get_appropriate_unit(true),
+                    Byte::from_bytes(capacity as u128).get_appropriate_unit(true),
+                    last_error.map_or("none", String::as_str)
+                ));
+            }
+        }
+    }
+}
+
+impl Default for RuntimeStats {
+    fn default() -> Self { Self::new() }
+}
+
+/// Truncates a duration to whole seconds, so that it is printed concisely
+const fn truncate(duration: Duration) -> Duration { Duration::from_secs(duration.as_secs()) }
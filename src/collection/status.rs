@@ -0,0 +1,283 @@
+use crate::collection::fd_budget;
+use crate::collection::flush::FlushStats;
+use crate::shared::{CollectionTarget, Id};
+use crate::shell::Shell;
+use crate::util;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Maximum size of a request that the status endpoint reads
+const MAX_REQUEST_LENGTH: usize = 8 * 1024;
+
+/// Time after which clients that don't send a full request are disconnected
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Live statistics about collection, reported by the status endpoint
+pub struct StatusRegistry {
+    started_at:  Instant,
+    node_id:     Option<String>,
+    /// Statistics for each target, which are removed once the target's
+    /// collector is dropped
+    targets:     Mutex<BTreeMap<Id, Weak<TargetStatus>>>,
+    last_error:  Mutex<Option<CollectionError>>,
+    /// Maximum number of targets that are collected at once, if limited
+    max_targets: Option<usize>,
+    /// Number of targets that weren't collected since the limit was reached
+    denied:      AtomicU64,
+    /// Number of denied targets that are waiting for other targets to stop
+    waiting:     AtomicU64,
+    /// Number of idle targets that stopped being collected to make room for
+    /// new targets
+    evicted:     AtomicU64,
+    /// Totals of buffer flushes across all log files
+    pub flushes: Arc<FlushStats>,
+}
+
+/// Live statistics about a single target
+pub struct TargetStatus {
+    name:        String,
+    group:       Option<String>,
+    /// Name that the target's log files are named after instead of its id
+    /// (such as its short name), if any
+    file_name:   Option<String>,
+    /// Number of records collected for the target (including those that were
+    /// skipped in sparse mode)
+    samples:     AtomicU64,
+    /// Nanosecond timestamp of the last collected record
+    last_sample: AtomicU64,
+}
+
+/// Most recent error that occurred during collection
+#[derive(Clone, Debug, Serialize)]
+struct CollectionError {
+    timestamp: u128,
+    target:    String,
+    message:   String,
+}
+
+/// Serialized response of the status endpoint
+#[derive(Debug, Serialize)]
+struct StatusReport<'a> {
+    node_id:         Option<&'a str>,
+    uptime:          f64,
+    active_targets:  usize,
+    max_targets:     Option<usize>,
+    denied_targets:  u64,
+    waiting_targets: u64,
+    evicted_targets: u64,
+    /// Number of file descriptors that collectors hold open, and how many
+    /// they can hold open at most (if limited)
+    open_files:      u64,
+    file_budget:     Option<u64>,
+    targets:         BTreeMap<Id, TargetReport>,
+    last_error:      Option<&'a CollectionError>,
+    flushes:         FlushReport,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetReport {
+    name:        String,
+    group:       Option<String>,
+    file_name:   Option<String>,
+    samples:     u64,
+    last_sample: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct FlushReport {
+    count:   u64,
+    failed:  u64,
+    written: u64,
+}
+
+impl StatusRegistry {
+    /// Creates an empty registry for the given node (if it is known), which
+    /// collects at most the given number of targets at once (if limited)
+    #[must_use]
+    pub fn new(node_id: Option<String>, max_targets: Option<usize>) -> Self {
+        Self {
+            started_at: Instant::now(),
+            node_id,
+            targets: Mutex::new(BTreeMap::new()),
+            last_error: Mutex::new(None),
+            max_targets,
+            denied: AtomicU64::new(0),
+            waiting: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
+            flushes: Arc::new(FlushStats::default()),
+        }
+    }
+
+    /// Registers a target, returning the statistics that its collector
+    /// should update. The target is reported until they are dropped.
+    pub fn register(&self, target: &CollectionTarget) -> Arc<TargetStatus> {
+        let status = Arc::new(TargetStatus {
+            name:        target.name.clone(),
+            group:       target.group.clone(),
+            file_name:   target.file_name.clone(),
+            samples:     AtomicU64::new(0),
+            last_sample: AtomicU64::new(0),
+        });
+
+        let mut targets = self.targets.lock().unwrap();
+        targets.retain(|_, target| target.strong_count() > 0);
+        targets.insert(target.id.clone(), Arc::downgrade(&status));
+        status
+    }
+
+    /// Records an error that occurred while collecting the given target
+    pub fn record_error<M: ToString>(&self, target: &str, message: &M) {
+        *self.last_error.lock().unwrap() = Some(CollectionError {
+            timestamp: util::nano_ts(),
+            target:    target.to_owned(),
+            message:   message.to_string(),
+        });
+    }
+
+    /// Records that a target wasn't collected because the target limit was
+    /// reached
+    pub fn record_denied(&self) { self.denied.fetch_add(1, Ordering::Relaxed); }
+
+    /// Sets the number of denied targets that are waiting for other targets
+    /// to stop
+    pub fn set_waiting(&self, count: usize) {
+        self.waiting
+            .store(u64::try_from(count).unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    /// Records that an idle target stopped being collected to make room for
+    /// a new target
+    pub fn record_evicted(&self) { self.evicted.fetch_add(1, Ordering::Relaxed); }
+
+    /// Serializes the current statistics to JSON
+    fn report(&self) -> Result<String, serde_json::Error> {
+        let targets: BTreeMap<Id, TargetReport> = self
+            .targets
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(id, target)| target.upgrade().map(|target| (id.clone(), target)))
+            .map(|(id, target)| {
+                let last_sample = target.last_sample.load(Ordering::Relaxed);
+                (id, TargetReport {
+                    name:        target.name.clone(),
+                    group:       target.group.clone(),
+                    file_name:   target.file_name.clone(),
+                    samples:     target.samples.load(Ordering::Relaxed),
+                    last_sample: match last_sample {
+                        0 => None,
+                        ts => Some(ts),
+                    },
+                })
+            })
+            .collect();
+
+        let last_error = self.last_error.lock().unwrap();
+        let (open_files, file_budget) = fd_budget::usage();
+        let report = StatusReport {
+            node_id: self.node_id.as_deref(),
+            uptime: self.started_at.elapsed().as_secs_f64(),
+            active_targets: targets.len(),
+            max_targets: self.max_targets,
+            denied_targets: self.denied.load(Ordering::Relaxed),
+            waiting_targets: self.waiting.load(Ordering::Relaxed),
+            evicted_targets: self.evicted.load(Ordering::Relaxed),
+            open_files,
+            file_budget,
+            targets,
+            last_error: last_error.as_ref(),
+            flushes: FlushReport {
+                count:   self.flushes.count.load(Ordering::Relaxed),
+                failed:  self.flushes.failed.load(Ordering::Relaxed),
+                written: self.flushes.written.load(Ordering::Relaxed),
+            },
+        };
+        serde_json::to_string(&report)
+    }
+}
+
+impl Default for StatusRegistry {
+    fn default() -> Self { Self::new(None, None) }
+}
+
+impl TargetStatus {
+    /// Records that a sample was collected for the target
+    pub fn sampled(&self) {
+        self.samples.fetch_add(1, Ordering::Relaxed);
+        #[allow(clippy::cast_possible_truncation)]
+        self.last_sample
+            .store(util::nano_ts() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Binds the status endpoint to the given address, serving the statistics in
+/// the registry as JSON on a background thread
+pub fn serve(addr: SocketAddr, registry: Arc<StatusRegistry>, shell: Arc<Shell>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    shell.status("Serving", format!("collection status on http://{}", addr));
+
+    thread::Builder::new()
+        .name(String::from("status"))
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| respond(stream, &registry));
+                if let Err(err) = result {
+                    shell.verbose(|sh| {
+                        sh.warn(format!("Could not respond to status request: {}", err));
+                    });
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// Handles a single HTTP request to the status endpoint
+fn respond(mut stream: TcpStream, registry: &StatusRegistry) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buf = [0_u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_LENGTH {
+        let read = stream.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    let request_line = request.split(|&c| c == b'\r' || c == b'\n').next();
+    let mut parts = request_line
+        .unwrap_or_default()
+        .split(|&c| c == b' ')
+        .filter(|part| !part.is_empty());
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(path)) if path == b"/" || path == b"/status" => {
+            match registry.report() {
+                Ok(report) => ("200 OK", report),
+                Err(err) => ("500 Internal Server Error", err.to_string()),
+            }
+        },
+        (Some(b"GET"), Some(_)) => ("404 Not Found", String::from("not found")),
+        _ => ("405 Method Not Allowed", String::from("method not allowed")),
+    };
+
+    let content_type = match status {
+        "200 OK" => "application/json",
+        _ => "text/plain",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,35 @@
+use crate::collection::buffers::FailedReads;
+use byte_unit::Byte;
+use std::fmt;
+use std::time::Duration;
+
+/// Collection statistics of a single target, reported when it stops so that
+/// each log file can be checked at a glance
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetSummary {
+    /// Number of records written for the target, across all of its log files
+    pub rows:         u64,
+    /// Number of bytes written to the target's log files (including those of
+    /// the other targets in its group, if any)
+    pub bytes:        u64,
+    /// Time since collection of the target started
+    pub elapsed:      Duration,
+    /// Failed reads of the target's cgroup files, by subsystem
+    pub failed_reads: FailedReads,
+    /// Number of times that the buffer of the target's log files was flushed
+    pub flushes:      u64,
+}
+
+impl fmt::Display for TargetSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows ({}) in {:.1}s, {} buffer flushes, failed reads: {}",
+            self.rows,
+            Byte::from_bytes(u128::from(self.bytes)).get_appropriate_unit(true),
+            self.elapsed.as_secs_f64(),
+            self.flushes,
+            self.failed_reads
+        )
+    }
+}
@@ -0,0 +1,126 @@
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Amount of data (in seconds at the rate limit) that can be held back before
+/// the rate limit is considered saturated
+const SATURATION_SECONDS: u64 = 10;
+
+/// Minimum time between consecutive warnings about saturation
+const WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Token bucket that caps the rate at which log files are written to their
+/// destinations. Writes that exceed the limit are held back by each log
+/// file's `FlushLogger` (instead of blocking collection) and written out on
+/// later ticks as tokens become available.
+pub struct RateLimiter {
+    /// Maximum number of bytes written per second, which is also the size of
+    /// the bucket
+    rate:         u64,
+    bucket:       Mutex<Bucket>,
+    /// Total number of bytes currently held back across all log files
+    backlog:      AtomicU64,
+    last_warning: Mutex<Option<Instant>>,
+}
+
+/// Current state of the token bucket
+struct Bucket {
+    /// Number of bytes that can be written right now. Negative if forced
+    /// writes have exceeded the limit.
+    tokens:      i128,
+    refilled_at: Instant,
+}
+
+thread_local! {
+    /// Whether the current thread is only writing out held-back data (see
+    /// `RateLimiter::pumping`)
+    static PUMPING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens:      i128::from(rate),
+                refilled_at: Instant::now(),
+            }),
+            backlog: AtomicU64::new(0),
+            last_warning: Mutex::new(None),
+        }
+    }
+
+    /// Gets the maximum number of bytes written per second
+    #[must_use]
+    pub const fn rate(&self) -> u64 { self.rate }
+
+    /// Takes up to `wanted` bytes worth of tokens from the bucket, returning
+    /// the number of bytes that can be written now. Forced writes (such as
+    /// when a log file is closed) take all tokens they need, delaying later
+    /// writes instead.
+    pub fn take(&self, wanted: usize, force: bool) -> usize {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled_at).as_nanos() * u128::from(self.rate)
+            / 1_000_000_000;
+        bucket.tokens = (bucket.tokens + i128::try_from(refill).unwrap_or(i128::MAX))
+            .min(i128::from(self.rate));
+        bucket.refilled_at = now;
+
+        let granted = match force {
+            true => wanted,
+            false => usize::try_from(bucket.tokens.max(0))
+                .unwrap_or(usize::MAX)
+                .min(wanted),
+        };
+        bucket.tokens -= i128::try_from(granted).unwrap_or(i128::MAX);
+        granted
+    }
+
+    /// Records that the given number of bytes are being held back
+    pub fn hold(&self, bytes: u64) { self.backlog.fetch_add(bytes, Ordering::Relaxed); }
+
+    /// Records that held-back bytes have been written
+    pub fn release(&self, bytes: u64) { self.backlog.fetch_sub(bytes, Ordering::Relaxed); }
+
+    /// Gets the total number of bytes currently held back
+    #[must_use]
+    pub fn backlog(&self) -> u64 { self.backlog.load(Ordering::Relaxed) }
+
+    /// Runs the given function (which flushes a log file) such that only as
+    /// much held-back data is written as the limit currently allows, instead
+    /// of forcing all of it out
+    pub fn pumping<R>(f: impl FnOnce() -> R) -> R {
+        PUMPING.with(|pumping| pumping.set(true));
+        let result = f();
+        PUMPING.with(|pumping| pumping.set(false));
+        result
+    }
+
+    /// Whether the current flush is only writing out held-back data
+    #[must_use]
+    pub fn is_pumping() -> bool { PUMPING.with(Cell::get) }
+
+    /// Returns the current backlog if so much data is being held back that
+    /// it can't be written out in reasonable time, which means that the
+    /// backlog grows without bound unless the limit is raised. Rate-limited
+    /// to avoid flooding the output.
+    pub fn saturation(&self) -> Option<u64> {
+        let backlog = self.backlog();
+        if backlog <= self.rate.saturating_mul(SATURATION_SECONDS) {
+            return None;
+        }
+
+        let mut last_warning = self.last_warning.lock().unwrap();
+        match *last_warning {
+            Some(last) if last.elapsed() < WARNING_INTERVAL => None,
+            _ => {
+                *last_warning = Some(Instant::now());
+                Some(backlog)
+            },
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::formats::{CpuTopology, NumaNode};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Directory containing a subdirectory for each possible CPU
+const SYS_CPU_ROOT: &str = "/sys/devices/system/cpu";
+
+/// Directory containing a subdirectory for each possible NUMA node
+const SYS_NODE_ROOT: &str = "/sys/devices/system/node";
+
+/// Directories of the performance monitoring units of hybrid x86 CPUs, each
+/// of which lists the CPUs of the corresponding core type
+const HYBRID_PMUS: &[(&str, &str)] = &[
+    ("core", "/sys/devices/cpu_core/cpus"),
+    ("atom", "/sys/devices/cpu_atom/cpus"),
+];
+
+/// Reads the topology of each possible CPU from sysfs, ordered by CPU index.
+/// Returns an empty list if sysfs isn't available.
+#[must_use]
+pub fn detect() -> Vec<CpuTopology> {
+    let mut cpus: Vec<u32> = match fs::read_dir(SYS_CPU_ROOT) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_prefix("cpu")?.parse().ok()
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    cpus.sort_unstable();
+
+    let core_types = HYBRID_PMUS
+        .iter()
+        .filter_map(|(core_type, path)| {
+            let cpus = fs::read_to_string(path).ok()?;
+            Some((*core_type, parse_cpu_list(&cpus)))
+        })
+        .collect::<Vec<_>>();
+
+    cpus.into_iter()
+        .map(|cpu| {
+            let dir = Path::new(SYS_CPU_ROOT).join(format!("cpu{}", cpu));
+            let topology = dir.join("topology");
+            CpuTopology {
+                cpu,
+                // The boot CPU usually can't be taken offline, so it has no
+                // `online` file
+                online: match read(&dir.join("online")) {
+                    Some(online) => online == "1",
+                    None => true,
+                },
+                package: read_id(&topology.join("physical_package_id")),
+                die: read_id(&topology.join("die_id")),
+                cluster: read_id(&topology.join("cluster_id")),
+                core: read_id(&topology.join("core_id")),
+                capacity: read(&dir.join("cpu_capacity")).and_then(|c| c.parse().ok()),
+                core_type: core_types
+                    .iter()
+                    .find(|(_, cpus)| cpus.contains(&cpu))
+                    .map(|(core_type, _)| String::from(*core_type)),
+                midr: read(&dir.join("regs/identification/midr_el1")),
+            }
+        })
+        .collect()
+}
+
+/// Reads the CPUs and memory of each NUMA node from sysfs, ordered by node
+/// id. Returns an empty list if the kernel wasn't built with NUMA support.
+#[must_use]
+pub fn numa_nodes() -> Vec<NumaNode> {
+    let mut nodes: Vec<u32> = match fs::read_dir(SYS_NODE_ROOT) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_prefix("node")?.parse().ok()
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    nodes.sort_unstable();
+
+    nodes
+        .into_iter()
+        .map(|node| {
+            let dir = Path::new(SYS_NODE_ROOT).join(format!("node{}", node));
+            NumaNode {
+                node,
+                cpus: match read(&dir.join("cpulist")) {
+                    Some(cpus) => parse_cpu_list(&cpus).into_iter().collect(),
+                    None => Vec::new(),
+                },
+                memory_total: read_node_memory_total(&dir.join("meminfo")),
+            }
+        })
+        .collect()
+}
+
+/// Reads the total memory (in kilobytes) from a per-node meminfo file, which
+/// contains lines such as `Node 0 MemTotal:  6147400 kB`
+fn read_node_memory_total(path: &Path) -> Option<u64> {
+    let meminfo = fs::read_to_string(path).ok()?;
+    meminfo.lines().find_map(|line| {
+        let (_, rest) = line.split_once("MemTotal:")?;
+        rest.trim().strip_suffix("kB")?.trim().parse().ok()
+    })
+}
+
+/// Reads a single-line sysfs file, trimming the trailing newline
+fn read(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_owned())
+}
+
+/// Reads a topology id, which is -1 if the kernel can't determine it (in
+/// which case it is omitted)
+fn read_id(path: &Path) -> Option<i64> {
+    read(path)
+        .and_then(|id| id.parse().ok())
+        .filter(|&id| id >= 0)
+}
+
+/// Parses a CPU list in the kernel's list format, such as `0-3,8,10-11`
+fn parse_cpu_list(list: &str) -> BTreeSet<u32> {
+    let mut cpus = BTreeSet::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let bounds: Option<(u32, u32)> = match range.split_once('-') {
+            Some((start, end)) => start.parse().ok().zip(end.parse().ok()),
+            None => range.parse().ok().map(|cpu| (cpu, cpu)),
+        };
+        if let Some((start, end)) = bounds {
+            cpus.extend(start..=end);
+        }
+    }
+    cpus
+}
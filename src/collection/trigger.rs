@@ -0,0 +1,230 @@
+//! Anomaly triggers, which switch a target to burst sampling (a faster
+//! collection interval) for a while whenever one of the configured rules
+//! matches its most recent sample. This captures rare incidents at high
+//! resolution without paying for a fast interval constantly.
+
+use crate::cli::ParseFailure;
+use crate::collection::processor::ColumnRegistry;
+use csv::ByteRecord;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Comparison between a column's value and a rule's threshold
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Greater => value > threshold,
+            Self::GreaterOrEqual => value >= threshold,
+            Self::Less => value < threshold,
+            Self::LessOrEqual => value <= threshold,
+        }
+    }
+}
+
+/// Value that a column is compared against
+#[derive(Clone, Debug, PartialEq)]
+pub enum Threshold {
+    Value(f64),
+    /// Fraction of the value of another column in the same sample (such as
+    /// a limit)
+    FractionOf {
+        fraction: f64,
+        column:   String,
+    },
+}
+
+/// Condition on a column that makes a rule match
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Compare {
+        comparison: Comparison,
+        threshold:  Threshold,
+    },
+    /// The value is greater than in the previous sample of the target (such
+    /// as a counter of throttled periods)
+    Increases,
+}
+
+/// Single `--trigger` rule, parsed from `<column> increases`,
+/// `<column> <op> <value>`, or `<column> <op> <percent>% of <column>` (where
+/// `<op>` is one of `>`, `>=`, `<`, or `<=`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriggerRule {
+    pub column:    String,
+    pub condition: Condition,
+    /// Original text of the rule, used when reporting that it matched
+    source:        String,
+}
+
+impl FromStr for TriggerRule {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseFailure::new(String::from("trigger"), s.to_owned());
+        let tokens = s.split_whitespace().collect::<Vec<_>>();
+        let condition = match tokens.as_slice() {
+            [_, "increases"] => Condition::Increases,
+            [_, op, value] => Condition::Compare {
+                comparison: parse_comparison(op).ok_or_else(invalid)?,
+                threshold:  Threshold::Value(value.parse().map_err(|_| invalid())?),
+            },
+            [_, op, percent, "of", column] => {
+                let percent = percent.strip_suffix('%').ok_or_else(invalid)?;
+                let percent = percent.parse::<f64>().map_err(|_| invalid())?;
+                Condition::Compare {
+                    comparison: parse_comparison(op).ok_or_else(invalid)?,
+                    threshold:  Threshold::FractionOf {
+                        fraction: percent / 100.0,
+                        column:   (*column).to_owned(),
+                    },
+                }
+            },
+            _ => return Err(invalid()),
+        };
+
+        Ok(Self {
+            column: tokens[0].to_owned(),
+            condition,
+            source: tokens.join(" "),
+        })
+    }
+}
+
+impl fmt::Display for TriggerRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.source) }
+}
+
+fn parse_comparison(op: &str) -> Option<Comparison> {
+    match op {
+        ">" => Some(Comparison::Greater),
+        ">=" => Some(Comparison::GreaterOrEqual),
+        "<" => Some(Comparison::Less),
+        "<=" => Some(Comparison::LessOrEqual),
+        _ => None,
+    }
+}
+
+/// Rules that start burst sampling, along with how it is done
+#[derive(Clone, Debug, PartialEq)]
+pub struct BurstPolicy {
+    pub rules:    Vec<TriggerRule>,
+    /// Collection interval of targets during bursts
+    pub interval: Duration,
+    /// Duration of burst sampling after the last time a rule matched
+    pub duration: Duration,
+}
+
+/// Burst sampling that was started because a rule matched a target's sample
+#[derive(Clone, Debug, PartialEq)]
+pub struct BurstStart {
+    /// Id of the target
+    pub id:   String,
+    /// Text of the rule that matched
+    pub rule: String,
+}
+
+/// Trigger state of a single target
+#[derive(Debug, Default)]
+pub struct TriggerState {
+    /// Value of each rule's column in the previous sample
+    previous:       Vec<Option<f64>>,
+    /// Time at which the current burst ends, if bursting
+    burst_until:    Option<Instant>,
+    last_collected: Option<Instant>,
+}
+
+impl TriggerState {
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Whether the target is currently being burst sampled
+    #[must_use]
+    pub fn is_bursting(&self) -> bool {
+        matches!(self.burst_until, Some(until) if Instant::now() < until)
+    }
+
+    /// Whether the target should be collected during the current tick. While
+    /// any target is being burst sampled (and the collection interval is
+    /// shortened), the other targets are still only collected once per base
+    /// interval (which is given only while burst sampling).
+    #[must_use]
+    pub fn is_due(&self, base: Option<Duration>, policy: &BurstPolicy) -> bool {
+        let base = match base {
+            Some(base) if !self.is_bursting() => base,
+            _ => return true,
+        };
+
+        // Allow for some jitter in when ticks happen
+        let slack = policy.interval / 2;
+        match self.last_collected {
+            Some(last) => last.elapsed() + slack >= base,
+            None => true,
+        }
+    }
+
+    /// Evaluates each rule against the target's latest sample, starting or
+    /// extending burst sampling if any of them match. Returns the rule that
+    /// started a new burst, if any.
+    pub fn evaluate<'p>(
+        &mut self,
+        record: &ByteRecord,
+        columns: &ColumnRegistry,
+        policy: &'p BurstPolicy,
+    ) -> Option<&'p TriggerRule> {
+        let now = Instant::now();
+        self.last_collected = Some(now);
+        self.previous.resize(policy.rules.len(), None);
+
+        let mut first_match = None;
+        for (rule, previous) in policy.rules.iter().zip(&mut self.previous) {
+            let value = field(record, columns, &rule.column);
+            let matches = match (&rule.condition, value) {
+                (_, None) => false,
+                (Condition::Increases, Some(value)) => matches!(*previous, Some(p) if value > p),
+                (
+                    Condition::Compare {
+                        comparison,
+                        threshold,
+                    },
+                    Some(value),
+                ) => {
+                    let threshold = match threshold {
+                        Threshold::Value(threshold) => Some(*threshold),
+                        Threshold::FractionOf { fraction, column } => {
+                            field(record, columns, column).map(|limit| limit * fraction)
+                        },
+                    };
+                    matches!(threshold, Some(threshold) if comparison.holds(value, threshold))
+                },
+            };
+            *previous = value;
+            if matches && first_match.is_none() {
+                first_match = Some(rule);
+            }
+        }
+
+        let was_bursting = self.is_bursting();
+        if first_match.is_some() {
+            self.burst_until = Some(now + policy.duration);
+        }
+        match was_bursting {
+            true => None,
+            false => first_match,
+        }
+    }
+}
+
+/// Parses the numeric value of a field, if it has one
+fn field(record: &ByteRecord, columns: &ColumnRegistry, name: &str) -> Option<f64> {
+    let field = columns.get(record, name)?;
+    std::str::from_utf8(field).ok()?.parse().ok()
+}
@@ -0,0 +1,128 @@
+use crate::formats::{Column, ColumnType, TableMetadata};
+use csv::ByteRecord;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Number of bytes of an invalid value that are kept as an example
+const EXAMPLE_LENGTH: usize = 32;
+
+/// Invalid values found in a single column across all log files
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnErrors {
+    pub count:   u64,
+    /// First invalid value, truncated
+    pub example: String,
+    /// Name of the declared type of the column
+    pub r#type:  &'static str,
+}
+
+/// Counts the values that did not parse as the declared type of their column,
+/// by column name, for the report printed when collection stops
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    errors: Mutex<BTreeMap<String, ColumnErrors>>,
+}
+
+impl ValidationReport {
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    fn record(&self, column: &str, column_type: &ColumnType, value: &[u8]) {
+        let mut errors = self.errors.lock().unwrap();
+        let entry = errors
+            .entry(column.to_owned())
+            .or_insert_with(|| ColumnErrors {
+                count:   0,
+                example: String::from_utf8_lossy(&value[..value.len().min(EXAMPLE_LENGTH)])
+                    .into_owned(),
+                r#type:  type_name(column_type),
+            });
+        entry.count += 1;
+    }
+
+    /// Gets the errors of each column that had any invalid values, ordered by
+    /// column name
+    #[must_use]
+    pub fn errors(&self) -> BTreeMap<String, ColumnErrors> { self.errors.lock().unwrap().clone() }
+}
+
+/// Checks that each field written by a collector parses as the type declared
+/// for its column in the table metadata. Columns without any declared type
+/// are integers. Empty fields (such as for files that don't exist) are valid.
+pub struct RecordValidator {
+    /// Name and type of each collector column, in record order
+    columns: Vec<(String, Column)>,
+    /// Index of the first collector column in the record
+    offset:  usize,
+}
+
+impl RecordValidator {
+    #[must_use]
+    pub fn new(header: &ByteRecord, metadata: &TableMetadata, offset: usize) -> Self {
+        let columns = header
+            .iter()
+            .map(|name| {
+                let name = String::from_utf8_lossy(name).into_owned();
+                let column = match metadata.columns.get(&name) {
+                    Some(column) => column.clone(),
+                    None => Column::Scalar {
+                        r#type: ColumnType::Int,
+                    },
+                };
+                (name, column)
+            })
+            .collect();
+        Self { columns, offset }
+    }
+
+    /// Validates the collector fields of the record, recording any invalid
+    /// values in the report
+    pub fn validate(&self, record: &ByteRecord, report: &ValidationReport) {
+        for (index, (name, column)) in self.columns.iter().enumerate() {
+            let field = match record.get(self.offset + index) {
+                Some(field) if !field.is_empty() => field,
+                _ => continue,
+            };
+
+            let (valid, column_type) = match column {
+                Column::Scalar { r#type } => (is_valid(field, r#type), r#type),
+                Column::Vector { r#type, .. } => (
+                    field
+                        .split(|&b| b == b' ')
+                        .filter(|element| !element.is_empty())
+                        .all(|element| is_valid(element, r#type)),
+                    r#type,
+                ),
+            };
+            if !valid {
+                report.record(name, column_type, field);
+            }
+        }
+    }
+}
+
+/// Whether the value parses as the given column type
+fn is_valid(value: &[u8], column_type: &ColumnType) -> bool {
+    match column_type {
+        // Limits are `max` if unlimited
+        ColumnType::Int => {
+            value == b"max" || {
+                let digits = value.strip_prefix(b"-").unwrap_or(value);
+                !digits.is_empty() && digits.iter().all(u8::is_ascii_digit)
+            }
+        },
+        ColumnType::Epoch19 => !value.is_empty() && value.iter().all(u8::is_ascii_digit),
+        ColumnType::List => value
+            .split(|&b| b == b',')
+            .flat_map(|range| range.splitn(2, |&b| b == b'-'))
+            .all(|bound| !bound.is_empty() && bound.iter().all(u8::is_ascii_digit)),
+    }
+}
+
+const fn type_name(column_type: &ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Int => "int",
+        ColumnType::Epoch19 => "epoch19",
+        ColumnType::List => "list",
+    }
+}
@@ -0,0 +1,379 @@
+//! Contains the `Daemon` type, which bootstraps the polling and collection
+//! threads and prepares the necessary communication between them. This is
+//! what the rAdvisor binary uses to run collection, and it can be used to
+//! embed rAdvisor in other binaries as well:
+//!
+//! ```no_run
+//! use clap::Clap;
+//! use radvisor::cli::RunCommand;
+//! use radvisor::daemon::{Daemon, DaemonEvent};
+//! use radvisor::shell::Shell;
+//! use std::io;
+//! use std::sync::Arc;
+//!
+//! let opts = RunCommand::parse_from(&["run", "cgroup", "--cgroup", "system.slice/*.service"]);
+//! let shell = Arc::new(Shell::from_write(Box::new(io::stdout()), Box::new(io::stderr())));
+//! let mut daemon = Daemon::new(opts, shell);
+//! daemon.subscribe(|event| {
+//!     if let DaemonEvent::TargetStarted(target) = event {
+//!         println!("collecting {}", target.name);
+//!     }
+//! });
+//!
+//! daemon.start()?;
+//! std::thread::sleep(std::time::Duration::from_secs(10));
+//! daemon.stop();
+//! daemon.wait()?;
+//! # Ok::<(), radvisor::daemon::DaemonError>(())
+//! ```
+
+use crate::channel;
+use crate::cli::RunCommand;
+use crate::collection::{self, Control, RecordProcessor};
+use crate::polling;
+use crate::polling::providers::{InitializationError, Provider};
+use crate::shared::{CollectionEvent, CollectionTarget, Id, IntervalWorkerContext};
+use crate::shell::Shell;
+use crate::util;
+use bus::Bus;
+use gethostname::gethostname;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Lifecycle event emitted by a running daemon to its subscribers
+#[derive(Clone, Debug, PartialEq)]
+pub enum DaemonEvent {
+    /// The provider was initialized, and both worker threads are running
+    Started,
+    /// A new target was discovered and sent to the collection thread
+    TargetStarted(CollectionTarget),
+    /// A target stopped and its removal was sent to the collection thread
+    TargetStopped(Id),
+    /// The worker threads have been signalled to stop (and flush their
+    /// buffers)
+    Stopping,
+    /// Both worker threads have exited. Includes the status code of the
+    /// provider if it finished on its own, or a non-zero status code if
+    /// collection stopped because of an error.
+    Stopped { exit_code: Option<i32> },
+}
+
+impl From<&CollectionEvent> for DaemonEvent {
+    fn from(event: &CollectionEvent) -> Self {
+        match event {
+            CollectionEvent::Start { target, .. } => Self::TargetStarted(target.clone()),
+            CollectionEvent::Stop(id) => Self::TargetStopped(id.clone()),
+        }
+    }
+}
+
+/// Error that can occur while starting or waiting on a daemon
+#[derive(Debug, thiserror::Error)]
+pub enum DaemonError {
+    #[error("the daemon has already been started")]
+    AlreadyStarted,
+    #[error("the daemon has not been started")]
+    NotStarted,
+    #[error(transparent)]
+    Initialization(#[from] InitializationError),
+    #[error("{0} thread resulted in panic")]
+    Panicked(&'static str),
+}
+
+type Callback = Box<dyn Fn(&DaemonEvent) + Send>;
+
+/// Shared set of callbacks that receive daemon events
+#[derive(Clone, Default)]
+pub struct Subscribers {
+    callbacks: Arc<Mutex<Vec<Callback>>>,
+}
+
+impl Subscribers {
+    /// Calls every callback with the given event
+    pub fn notify(&self, event: &DaemonEvent) {
+        for callback in self.callbacks.lock().unwrap().iter() {
+            callback(event);
+        }
+    }
+
+    /// Whether there are no callbacks, in which case events don't need to be
+    /// constructed at all
+    #[must_use]
+    pub fn is_empty(&self) -> bool { self.callbacks.lock().unwrap().is_empty() }
+}
+
+/// Handles to the two worker threads of a started daemon
+struct Workers {
+    polling:    JoinHandle<Option<i32>>,
+    collection: JoinHandle<Option<i32>>,
+}
+
+/// Runs the polling and collection threads for a single provider
+pub struct Daemon {
+    opts:        RunCommand,
+    shell:       Arc<Shell>,
+    subscribers: Subscribers,
+    term_bus:    Arc<Mutex<Bus<()>>>,
+    /// Sends control messages to the collection thread, once it has started
+    control:     Arc<Mutex<Option<mpsc::Sender<Control>>>>,
+    workers:     Option<Workers>,
+    processors:  Vec<Arc<dyn RecordProcessor>>,
+}
+
+impl Daemon {
+    #[must_use]
+    pub fn new(opts: RunCommand, shell: Arc<Shell>) -> Self {
+        Self {
+            opts,
+            shell,
+            subscribers: Subscribers::default(),
+            term_bus: Arc::new(Mutex::new(Bus::new(1))),
+            control: Arc::new(Mutex::new(None)),
+            workers: None,
+            processors: Vec::new(),
+        }
+    }
+
+    /// Registers a callback that receives all future daemon events. Callbacks
+    /// are called from the worker threads, so they should return quickly.
+    pub fn subscribe<F>(&self, callback: F)
+    where
+        F: Fn(&DaemonEvent) + Send + 'static,
+    {
+        self.subscribers
+            .callbacks
+            .lock()
+            .unwrap()
+            .push(Box::new(callback));
+    }
+
+    /// Registers a record processor that is invoked with each record before
+    /// it is written, after any previously registered processors. Must be
+    /// called before the daemon is started.
+    pub fn add_processor<P>(&mut self, processor: P)
+    where
+        P: RecordProcessor + 'static,
+    {
+        self.processors.push(Arc::new(processor));
+    }
+
+    /// Installs a SIGINT/SIGTERM handler that stops the daemon, forcibly
+    /// exiting the process if it could not stop gracefully, and a SIGUSR1
+    /// handler that prints runtime statistics (see `dump`). Since signal
+    /// handlers are process-wide, this can only be done once per process.
+    pub fn handle_signals(&self) -> Result<(), ctrlc::Error> {
+        let control = Arc::clone(&self.control);
+        util::on_usr1(move || send_control(&control, Control::Dump))
+            .map_err(ctrlc::Error::System)?;
+
+        let term_bus = Arc::clone(&self.term_bus);
+        let shell = Arc::clone(&self.shell);
+        let subscribers = self.subscribers.clone();
+        ctrlc::set_handler(move || {
+            subscribers.notify(&DaemonEvent::Stopping);
+            handle_termination(&term_bus, &shell)
+        })
+    }
+
+    /// Initializes the provider and then starts both worker threads. Returns
+    /// once collection has started, or with an error if the provider could
+    /// not be initialized.
+    pub fn start(&mut self) -> Result<(), DaemonError> {
+        if self.workers.is_some() {
+            return Err(DaemonError::AlreadyStarted);
+        }
+
+        let opts = self.opts.clone();
+        let polling_opts = opts.provider.polling().clone();
+        let mut collection_opts = opts.provider.collection().clone();
+        polling::resolve_cgroup_root(&polling_opts, &self.shell);
+
+        // Used to send collection events from the polling thread to the
+        // collection thread, and targets whose cgroups were removed back
+        let (tx, rx) = channel::bounded(polling_opts.event_queue);
+        let (vanished_tx, vanished_rx) = mpsc::channel::<Id>();
+
+        // Create the thread worker contexts using the term bus lock
+        let mut term_bus_handle = self.term_bus.lock().unwrap();
+        let polling_context = IntervalWorkerContext {
+            interval: polling_opts.interval,
+            term_rx:  term_bus_handle.add_rx(),
+            shell:    Arc::clone(&self.shell),
+        };
+        let collection_context = IntervalWorkerContext {
+            interval: collection_opts.interval,
+            term_rx:  term_bus_handle.add_rx(),
+            shell:    Arc::clone(&self.shell),
+        };
+        drop(term_bus_handle);
+
+        // Spawn the polling thread, which initializes the provider first
+        let (init_tx, init_rx) =
+            mpsc::sync_channel::<Result<Option<String>, InitializationError>>(1);
+        let term_bus = Arc::clone(&self.term_bus);
+        let subscribers = self.subscribers.clone();
+        let polling: JoinHandle<Option<i32>> = thread::Builder::new()
+            .name(String::from("poll"))
+            .spawn(move || {
+                collection::schedule_thread(
+                    opts.provider.collection(),
+                    "polling",
+                    &polling_context.shell,
+                );
+
+                // Resolve container metadata provider
+                let mut provider: Box<dyn Provider> = opts.provider.get_impl();
+
+                // Determine if the current process can connect to the provider source
+                let provider_shell = Arc::clone(&polling_context.shell);
+                if let Err(err) = provider.initialize(&opts, provider_shell) {
+                    let _ = init_tx.send(Err(err));
+                    return None;
+                }
+                let _ = init_tx.send(Ok(provider.node()));
+
+                let exit_code = polling::run(
+                    &tx,
+                    &vanished_rx,
+                    polling_context,
+                    provider,
+                    &subscribers,
+                    polling_opts.collect_host,
+                );
+                if exit_code.is_some() {
+                    // The provider finished on its own; stop collection as if the
+                    // process had been terminated
+                    subscribers.notify(&DaemonEvent::Stopping);
+                    term_bus.lock().unwrap().broadcast(());
+                }
+
+                exit_code
+            })
+            .unwrap();
+
+        match init_rx.recv() {
+            Ok(Ok(node)) => {
+                // Default to the node name from the provider, or the hostname
+                let node_id = collection_opts.node_id.take().or(node);
+                collection_opts.node_id = node_id.or_else(|| gethostname().into_string().ok());
+            },
+            Ok(Err(err)) => {
+                let _ = polling.join();
+                return Err(err.into());
+            },
+            Err(_) => {
+                let _ = polling.join();
+                return Err(DaemonError::Panicked("polling"));
+            },
+        }
+
+        let processors = self.processors.clone();
+        let (control_tx, control_rx) = mpsc::channel::<Control>();
+        *self.control.lock().unwrap() = Some(control_tx.clone());
+        let control = (control_tx, control_rx);
+        let term_bus = Arc::clone(&self.term_bus);
+        let subscribers = self.subscribers.clone();
+        let collection: JoinHandle<Option<i32>> = thread::Builder::new()
+            .name(String::from("collect"))
+            .spawn(move || {
+                let exit_code = collection::run(
+                    &rx,
+                    &vanished_tx,
+                    control,
+                    collection_context,
+                    &collection_opts,
+                    processors,
+                );
+                if exit_code.is_some() {
+                    // Collection stopped because of an error; stop polling as
+                    // if the process had been terminated
+                    subscribers.notify(&DaemonEvent::Stopping);
+                    let _ = term_bus.lock().unwrap().try_broadcast(());
+                }
+
+                exit_code
+            })
+            .unwrap();
+
+        self.workers = Some(Workers {
+            polling,
+            collection,
+        });
+        self.subscribers.notify(&DaemonEvent::Started);
+        Ok(())
+    }
+
+    /// Signals both worker threads to stop, flushing all buffers. Does not
+    /// wait for them to exit (see `wait`).
+    pub fn stop(&self) {
+        self.subscribers.notify(&DaemonEvent::Stopping);
+        // If the bus is full, then the daemon is already stopping
+        let _ = self.term_bus.lock().unwrap().try_broadcast(());
+    }
+
+    /// Asks the collection thread to flush the buffers of all log files to
+    /// their destinations without stopping collection. The flush happens at
+    /// the end of the next collection tick.
+    pub fn flush(&self) -> Result<(), DaemonError> {
+        self.workers.as_ref().ok_or(DaemonError::NotStarted)?;
+        // If the collection thread has already exited, then its buffers have
+        // already been flushed
+        send_control(&self.control, Control::Flush);
+        Ok(())
+    }
+
+    /// Asks the collection thread to print runtime statistics (the number of
+    /// active targets, the rows and bytes written and the most recent error
+    /// of each target, and percentiles of the collection tick duration) to
+    /// the shell. The statistics are printed at the end of the next
+    /// collection tick.
+    pub fn dump(&self) -> Result<(), DaemonError> {
+        self.workers.as_ref().ok_or(DaemonError::NotStarted)?;
+        send_control(&self.control, Control::Dump);
+        Ok(())
+    }
+
+    /// Waits for both worker threads to exit, returning the status code of
+    /// the provider if it finished on its own, or a non-zero status code if
+    /// collection stopped because of an error
+    pub fn wait(mut self) -> Result<Option<i32>, DaemonError> {
+        let workers = self.workers.take().ok_or(DaemonError::NotStarted)?;
+
+        // Join the threads, which automatically exit upon termination
+        let collection_result = workers.collection.join();
+        let polling_result = workers.polling.join();
+        let collection_exit_code =
+            collection_result.map_err(|_| DaemonError::Panicked("collection"))?;
+
+        let exit_code = polling_result
+            .map_err(|_| DaemonError::Panicked("polling"))?
+            .or(collection_exit_code);
+        self.subscribers.notify(&DaemonEvent::Stopped { exit_code });
+        Ok(exit_code)
+    }
+}
+
+/// Sends a control message to the collection thread if it has started. If it
+/// has already exited, then there is nothing left to control.
+fn send_control(control: &Mutex<Option<mpsc::Sender<Control>>>, message: Control) {
+    if let Some(control) = control.lock().unwrap().as_ref() {
+        let _ = control.send(message);
+    }
+}
+
+/// Handles program termination by broadcasting an empty message on a special
+/// termination bus that each thread listens to
+fn handle_termination(bus_lock: &Arc<Mutex<Bus<()>>>, shell: &Shell) -> ! {
+    let mut bus = bus_lock.lock().unwrap();
+    bus.broadcast(());
+
+    // Try again to tear down the program
+    thread::sleep(Duration::from_secs(2));
+    shell.warn("Could not shutdown gracefully on the first try. Trying again...");
+    bus.broadcast(());
+    thread::sleep(Duration::from_secs(1));
+    shell.warn("Forcibly closing; buffers may not be flushed.");
+    std::process::exit(2);
+}
@@ -0,0 +1,192 @@
+//! Lets every CLI option be configured using an environment variable, since
+//! container deployments (such as Kubernetes daemon sets) generally prefer
+//! environment variables over long argument lists. Each option `--foo-bar`
+//! can be set using `RADVISOR_FOO_BAR`, and the provider of `radvisor run` or
+//! `radvisor snapshot` can be set using `RADVISOR_PROVIDER`:
+//!
+//! ```sh
+//! RADVISOR_PROVIDER=docker RADVISOR_INTERVAL=100ms RADVISOR_CHECKSUM=true radvisor run
+//! ```
+//!
+//! Options given on the command line take precedence over environment
+//! variables, which take precedence over the defaults. Environment variables
+//! are applied by adding their options to the command line before it is
+//! parsed, so their values are validated the same way:
+//!
+//! - flags are set by `1`, `true`, `yes`, or `on`, and left unset by `0`,
+//!   `false`, `no`, `off`, or an empty value
+//! - options that can be given multiple times (such as `--include`) take a
+//!   comma-separated list of values
+
+use clap::{App, ArgSettings, ErrorKind};
+use std::env;
+use std::ffi::{OsStr, OsString};
+
+/// Prefix of the environment variables for each option
+const PREFIX: &str = "RADVISOR_";
+
+/// Environment variable with the provider subcommand to use
+const PROVIDER_VARIABLE: &str = "RADVISOR_PROVIDER";
+
+/// Subcommands whose own subcommand is the provider
+const PROVIDER_PARENTS: &[&str] = &["run", "snapshot"];
+
+/// Arguments added by clap itself, which aren't configurable
+const RESERVED_ARGS: &[&str] = &["help", "version"];
+
+/// Adds the options set using environment variables to the command line
+/// arguments (including the program name), for the subcommands that are
+/// present on the command line (or given by `RADVISOR_PROVIDER`). Options that
+/// are already present on the command line are left as-is.
+pub fn apply<I, T>(args: I, app: &App<'_>) -> Result<Vec<OsString>, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let mut args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let mut path = subcommand_path(&args, app);
+
+    let last = path.last().map(|&(app, _)| app);
+    if let Some(parent) = last.filter(|app| PROVIDER_PARENTS.contains(&app.get_name())) {
+        if let Some(provider) = env::var_os(PROVIDER_VARIABLE) {
+            let subcommand = parent
+                .get_subcommands()
+                .find(|subcommand| OsStr::new(subcommand.get_name()) == provider)
+                .ok_or_else(|| {
+                    invalid(&format!(
+                        "{} is set to '{}', which isn't a provider",
+                        PROVIDER_VARIABLE,
+                        provider.to_string_lossy()
+                    ))
+                })?;
+            let position = end_of_options(&args);
+            args.insert(position, provider);
+            path.push((subcommand, position + 1));
+        }
+    }
+
+    // Add options right after the subcommand that defines them, starting from
+    // the deepest so that the positions of the others stay the same
+    for &(app, position) in path.iter().rev() {
+        let added = options_from_env(app, &args)?;
+        args.splice(position..position, added);
+    }
+
+    Ok(args)
+}
+
+/// Finds the subcommands that are present on the command line, along with the
+/// position of the argument after each one. The first entry is the root app.
+fn subcommand_path<'a, 'help>(
+    args: &[OsString],
+    app: &'a App<'help>,
+) -> Vec<(&'a App<'help>, usize)> {
+    let mut path = vec![(app, 1)];
+    let mut skip_value = false;
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            break;
+        } else if skip_value {
+            skip_value = false;
+        } else if arg.starts_with('-') {
+            // Skip the value of options that take one as a separate argument
+            skip_value = !arg.contains('=')
+                && path
+                    .iter()
+                    .flat_map(|(app, _)| app.get_arguments())
+                    .any(|a| is_present_as(a, &arg) && a.is_set(ArgSettings::TakesValue));
+        } else {
+            let current = path[path.len() - 1].0;
+            match current.get_subcommands().find(|s| s.get_name() == arg) {
+                Some(subcommand) => path.push((subcommand, i + 1)),
+                // Positional arguments (such as the command of the exec
+                // provider) end the subcommands
+                None if current.get_subcommands().next().is_none() => break,
+                None => {},
+            }
+        }
+    }
+
+    path
+}
+
+/// Gets the position of the `--` separator, or the end of the arguments
+fn end_of_options(args: &[OsString]) -> usize {
+    args.iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len())
+}
+
+/// Gets the arguments for the options of the app that are set using
+/// environment variables and aren't present on the command line
+fn options_from_env(app: &App<'_>, args: &[OsString]) -> Result<Vec<OsString>, clap::Error> {
+    let present = &args[..end_of_options(args)];
+    let mut added = Vec::new();
+    for arg in app.get_arguments() {
+        let long = match arg.get_long() {
+            Some(long) if !RESERVED_ARGS.contains(&arg.get_name()) => long,
+            _ => continue,
+        };
+        let variable = format!("{}{}", PREFIX, long.to_uppercase().replace('-', "_"));
+        let value = match env::var(&variable) {
+            Ok(value) => value,
+            Err(env::VarError::NotPresent) => continue,
+            Err(env::VarError::NotUnicode(_)) => {
+                return Err(invalid(&format!("{} isn't valid unicode", variable)));
+            },
+        };
+        if present
+            .iter()
+            .any(|present| is_present_as(arg, &present.to_string_lossy()))
+        {
+            continue;
+        }
+
+        let repeated = arg.is_set(ArgSettings::MultipleOccurrences)
+            && !arg.is_set(ArgSettings::UseValueDelimiter);
+        match (arg.is_set(ArgSettings::TakesValue), repeated) {
+            (true, true) => added.extend(
+                value
+                    .split(',')
+                    .map(|value| OsString::from(format!("--{}={}", long, value))),
+            ),
+            (true, false) => added.push(OsString::from(format!("--{}={}", long, value))),
+            (false, _) => match value.to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => added.push(OsString::from(format!("--{}", long))),
+                "" | "0" | "false" | "no" | "off" => {},
+                _ => {
+                    return Err(invalid(&format!(
+                        "{} is set to '{}', but it must be either true or false",
+                        variable, value
+                    )));
+                },
+            },
+        }
+    }
+
+    Ok(added)
+}
+
+/// Determines whether the command line argument is the given option, either
+/// by its long name (optionally with an attached value) or by its short name
+fn is_present_as(arg: &clap::Arg<'_>, present: &str) -> bool {
+    let long = arg.get_long().map_or(false, |long| {
+        present
+            .strip_prefix("--")
+            .and_then(|present| present.strip_prefix(long))
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('='))
+    });
+    let short = arg.get_short().map_or(false, |short| {
+        !present.starts_with("--")
+            && present
+                .strip_prefix('-')
+                .map_or(false, |rest| rest.starts_with(short))
+    });
+    long || short
+}
+
+/// Creates an error about an invalid environment variable
+fn invalid(message: &str) -> clap::Error {
+    clap::Error::with_description(format!("{}\n", message), ErrorKind::InvalidValue)
+}
@@ -0,0 +1,195 @@
+use crate::export::ExportError;
+use crate::formats::{self, Body, Column, LogFileHeader, RingHeader};
+use crate::util::MappedFile;
+use csv::{ByteRecord, ByteRecordsIntoIter};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Log file or ring file that records are read from, in the order they were
+/// collected
+pub struct Input {
+    pub path:    PathBuf,
+    pub header:  LogFileHeader,
+    /// Names of the columns in the CSV header row
+    pub columns: Vec<String>,
+    records:     Records,
+}
+
+enum Records {
+    Log(ByteRecordsIntoIter<Body<BufReader<File>>>),
+    Ring(RingRecords),
+}
+
+impl Input {
+    /// Opens a log file or ring file (recognized by its magic bytes), reading
+    /// its header
+    pub fn open(path: &Path) -> Result<Self, ExportError> {
+        let mut file = File::open(path)?;
+        let mut magic = [0_u8; 8];
+        let is_ring = file.read_exact(&mut magic).is_ok() && &magic == formats::RING_MAGIC;
+        match is_ring {
+            true => Self::open_ring(path),
+            false => {
+                let (header, mut body) = formats::read(BufReader::new(File::open(path)?))?;
+                let columns = names(body.byte_headers()?);
+                Ok(Self {
+                    path: path.to_owned(),
+                    header,
+                    columns,
+                    records: Records::Log(body.into_byte_records()),
+                })
+            },
+        }
+    }
+
+    fn open_ring(path: &Path) -> Result<Self, ExportError> {
+        let map = MappedFile::open(path)?;
+        let bytes = map.as_slice();
+        let mut ring = RingHeader::parse(bytes).ok_or(ExportError::UnsupportedRing)?;
+        let (Some(data_offset), Some(record_length)) = (ring.data_offset(), ring.record_length())
+        else {
+            return Err(ExportError::Corrupt);
+        };
+        match ring.file_length() {
+            Some(file_length) if bytes.len() >= file_length => {},
+            _ => return Err(ExportError::Corrupt),
+        }
+        // Only read the records that the writer has finished writing
+        if let Some(written) = map.atomic_u64(RingHeader::WRITTEN_OFFSET) {
+            ring.written = RingHeader::load_written(written);
+        }
+
+        // Parse the log file text at the start of the ring file to find out
+        // the layout of each record
+        let metadata_start = formats::RING_HEADER_LENGTH;
+        let metadata_length = usize::try_from(ring.metadata_length).unwrap_or(usize::MAX);
+        let metadata = &bytes[metadata_start..metadata_start + metadata_length];
+        let (header, mut body) = formats::read(metadata)?;
+        let header_row = body.byte_headers()?;
+        let layout = formats::layout(header_row, &header.perf_table);
+        let columns = names(header_row);
+        let slots = layout
+            .iter()
+            .map(|column| formats::slot_count(column, ring.list_slots))
+            .sum::<usize>();
+        if slots as u64 != ring.slots {
+            return Err(ExportError::Corrupt);
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            header,
+            columns,
+            records: Records::Ring(RingRecords {
+                next: ring.written.saturating_sub(ring.capacity),
+                map,
+                ring,
+                layout,
+                slots: vec![formats::MISSING; slots],
+                field: Vec::new(),
+                data_offset,
+                record_length,
+            }),
+        })
+    }
+
+    /// Gets the name that the log file was created with: the group name for
+    /// grouped log files, or the (escaped) target id, which is the file name
+    /// without the timestamp suffix
+    #[must_use]
+    pub fn log_name(&self) -> String {
+        if let Some(group) = &self.header.group {
+            return group.clone();
+        }
+
+        let stem = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match stem.rsplit_once('_') {
+            Some((name, timestamp)) if timestamp.bytes().all(|b| b.is_ascii_digit()) => {
+                name.to_owned()
+            },
+            _ => stem,
+        }
+    }
+
+    /// Gets the index of the column with the given name
+    #[must_use]
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column == name)
+    }
+}
+
+impl Iterator for Input {
+    type Item = Result<ByteRecord, ExportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.records {
+            Records::Log(records) => records.next().map(|record| record.map_err(Into::into)),
+            Records::Ring(records) => records.next().map(Ok),
+        }
+    }
+}
+
+/// Decodes the records of a ring file that haven't been overwritten, from
+/// oldest to newest. Ring files can be read while they are still being
+/// written to, although records that are overwritten while they are read may
+/// be mixed up.
+struct RingRecords {
+    map:           MappedFile,
+    ring:          RingHeader,
+    layout:        Vec<Column>,
+    /// Number of the next record to decode (counting overwritten records)
+    next:          u64,
+    /// Slots of the record currently being decoded
+    slots:         Vec<i64>,
+    field:         Vec<u8>,
+    data_offset:   usize,
+    record_length: usize,
+}
+
+impl Iterator for RingRecords {
+    type Item = ByteRecord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.ring.written {
+            return None;
+        }
+
+        let position = usize::try_from(self.next % self.ring.capacity).unwrap_or(0);
+        self.next += 1;
+        let start = self.data_offset + position * self.record_length;
+        let bytes = &self.map.as_slice()[start..start + self.record_length];
+        for (slot, chunk) in self
+            .slots
+            .iter_mut()
+            .zip(bytes.chunks_exact(formats::SLOT_LENGTH))
+        {
+            let mut slot_bytes = [0_u8; formats::SLOT_LENGTH];
+            slot_bytes.copy_from_slice(chunk);
+            *slot = i64::from_le_bytes(slot_bytes);
+        }
+
+        let mut record = ByteRecord::new();
+        let mut offset = 0;
+        for column in &self.layout {
+            let count = formats::slot_count(column, self.ring.list_slots);
+            self.field.clear();
+            formats::decode(&self.slots[offset..offset + count], column, &mut self.field);
+            record.push_field(&self.field);
+            offset += count;
+        }
+        Some(record)
+    }
+}
+
+fn names(header_row: &ByteRecord) -> Vec<String> {
+    header_row
+        .iter()
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
@@ -0,0 +1,385 @@
+//! Contains the `radvisor export` command, which converts log files and ring
+//! files (written when using the `ring` sink) into log files or JSON,
+//! optionally filtering their records by time range or selecting a subset of
+//! their columns. Multiple files can also be merged into a single table keyed
+//! by target id, with their records ordered by the time they were collected.
+
+mod input;
+mod output;
+
+pub(crate) use output::to_json;
+pub use output::ExportFormat;
+
+use crate::cli::ExportCommand;
+use crate::export::input::Input;
+use crate::export::output::{Output, Prelude};
+use crate::formats::{self, Column, ColumnType, LogFileHeader};
+use csv::ByteRecord;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the column containing the nanosecond timestamp of each record
+const READ_COLUMN: &str = "read";
+
+/// Name of the column identifying the target of each record in merged tables
+/// (and grouped log files)
+const TARGET_COLUMN: &str = "target.id";
+
+/// Error that can occur when exporting a file
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("could not read or write file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not read or write CSV record: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("could not parse log file: {0}")]
+    Read(#[from] formats::ReadError),
+    #[error("could not write log file header: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("could not write JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("ring file was written by an unsupported version of rAdvisor")]
+    UnsupportedRing,
+    #[error("ring file is truncated or its columns don't match its header")]
+    Corrupt,
+    #[error("unknown column {0}")]
+    UnknownColumn(String),
+    #[error("exporting would overwrite the original file")]
+    WouldOverwrite,
+    #[error("{}: {error}", .path.display())]
+    Input {
+        path:  PathBuf,
+        #[source]
+        error: Box<Self>,
+    },
+}
+
+/// Result of a successful export
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exported {
+    pub destination: PathBuf,
+    /// Number of records that were written
+    pub records:     u64,
+}
+
+/// Gets the path of the file that a log file or ring file is exported to:
+/// the original file name ending in the format's extension, placed in the
+/// given directory (or next to the original file)
+#[must_use]
+pub fn destination(path: &Path, output: Option<&Path>, format: ExportFormat) -> PathBuf {
+    let file_name =
+        Path::new(path.file_name().unwrap_or_default()).with_extension(format.extension());
+    match output {
+        Some(directory) => directory.join(file_name),
+        None => path.with_file_name(file_name),
+    }
+}
+
+/// Converts a single log file or ring file according to the given options,
+/// writing it to its destination (see `destination`)
+pub fn convert(path: &Path, opts: &ExportCommand) -> Result<Exported, ExportError> {
+    let destination = destination(path, opts.output.as_deref(), opts.format);
+    if destination == path {
+        return Err(ExportError::WouldOverwrite);
+    }
+
+    let input = Input::open(path)?;
+    if let Some(directory) = &opts.output {
+        fs::create_dir_all(directory)?;
+    }
+    let columns = match opts.columns.is_empty() {
+        true => input.columns.clone(),
+        false => {
+            for column in &opts.columns {
+                if input.index_of(column).is_none() {
+                    return Err(ExportError::UnknownColumn(column.clone()));
+                }
+            }
+            opts.columns.clone()
+        },
+    };
+
+    // Only keep the definitions of the exported columns
+    let mut header = upgrade(&input.header, &columns);
+    header
+        .perf_table
+        .columns
+        .retain(|name, _| columns.contains(name));
+    let definitions = definitions(&columns, &[&input.header]);
+    let fields = columns
+        .iter()
+        .map(|column| match input.index_of(column) {
+            Some(index) => Field::Column(index),
+            None => Field::Empty,
+        })
+        .collect::<Vec<_>>();
+
+    let filter = TimeFilter::new(opts, &input);
+    let mut output = Output::create(
+        &destination,
+        opts.format,
+        Prelude::Single(&header),
+        definitions,
+    )?;
+    let mut row = ByteRecord::new();
+    let mut records = 0;
+    for record in input {
+        let record = record?;
+        if filter.matches(&record) {
+            project(&record, &fields, &mut row);
+            output.write(&row)?;
+            records += 1;
+        }
+    }
+
+    output.finish()?;
+    Ok(Exported {
+        destination,
+        records,
+    })
+}
+
+/// Merges all of the given log files and ring files into a single table at
+/// the destination, whose first column is the id of each record's target,
+/// followed by the union of all files' columns. Records are ordered by the
+/// time they were collected. Targets in ungrouped log files are identified by
+/// the file name without the timestamp.
+pub fn merge(opts: &ExportCommand, destination: &Path) -> Result<Exported, ExportError> {
+    let mut inputs = Vec::with_capacity(opts.files.len());
+    for path in &opts.files {
+        if path == destination {
+            return Err(ExportError::WouldOverwrite);
+        }
+
+        let input = Input::open(path).map_err(|error| ExportError::Input {
+            path:  path.clone(),
+            error: Box::new(error),
+        })?;
+        inputs.push(input);
+    }
+
+    let mut all_columns = vec![String::from(TARGET_COLUMN)];
+    for input in &inputs {
+        for column in &input.columns {
+            if !all_columns.contains(column) {
+                all_columns.push(column.clone());
+            }
+        }
+    }
+    let columns = match opts.columns.is_empty() {
+        true => all_columns,
+        false => {
+            let mut columns = vec![String::from(TARGET_COLUMN)];
+            for column in opts.columns.iter().filter(|c| c.as_str() != TARGET_COLUMN) {
+                if !all_columns.contains(column) {
+                    return Err(ExportError::UnknownColumn(column.clone()));
+                }
+                columns.push(column.clone());
+            }
+            columns
+        },
+    };
+
+    let headers = inputs
+        .iter()
+        .map(|input| {
+            let name = input
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            (name, upgrade(&input.header, &input.columns))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let definitions = definitions(
+        &columns,
+        &inputs.iter().map(|input| &input.header).collect::<Vec<_>>(),
+    );
+    let mut output = Output::create(
+        destination,
+        opts.format,
+        Prelude::Merged(&headers),
+        definitions,
+    )?;
+
+    let mut sources = inputs
+        .into_iter()
+        .map(|input| MergeSource::new(input, &columns, opts))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut row = ByteRecord::new();
+    let mut records = 0;
+    // Each file is already ordered by time, so the next record is always the
+    // earliest of the current records of each file
+    while let Some((_, index)) = sources
+        .iter()
+        .enumerate()
+        .filter_map(|(i, source)| source.current.as_ref().map(|(read, _)| (*read, i)))
+        .min()
+    {
+        let source = &mut sources[index];
+        if let Some((_, record)) = &source.current {
+            project(record, &source.fields, &mut row);
+            output.write(&row)?;
+            records += 1;
+        }
+        source.advance()?;
+    }
+
+    output.finish()?;
+    Ok(Exported {
+        destination: destination.to_owned(),
+        records,
+    })
+}
+
+/// Source of a single field in an exported record
+enum Field {
+    /// Field copied from the original record
+    Column(usize),
+    /// Field that is the same for every record
+    Constant(Vec<u8>),
+    /// Field that is always empty, for columns missing from the original file
+    Empty,
+}
+
+/// Builds an exported record out of the fields of an original record
+fn project(record: &ByteRecord, fields: &[Field], row: &mut ByteRecord) {
+    row.clear();
+    for field in fields {
+        match field {
+            Field::Column(index) => row.push_field(record.get(*index).unwrap_or_default()),
+            Field::Constant(value) => row.push_field(value),
+            Field::Empty => row.push_field(b""),
+        }
+    }
+}
+
+/// Copies a log file header, describing the given columns in its schema (in
+/// order) and bumping its schema version, so that exported headers always
+/// have a schema even if the original log file was written by an older
+/// version
+fn upgrade(header: &LogFileHeader, columns: &[String]) -> LogFileHeader {
+    let mut header = header.clone();
+    header.perf_table.schema = header.column_schema(columns.iter().map(String::as_bytes));
+    header.schema_version = formats::SCHEMA_VERSION;
+    header
+}
+
+/// Gets the definition of each column from the first header that defines it.
+/// Other columns are integers, except for the target id column, which
+/// contains strings.
+fn definitions(columns: &[String], headers: &[&LogFileHeader]) -> Vec<(String, Option<Column>)> {
+    columns
+        .iter()
+        .map(|name| {
+            let definition = headers
+                .iter()
+                .find_map(|header| header.perf_table.columns.get(name))
+                .cloned();
+            let definition = match (definition, name.as_str()) {
+                (Some(definition), _) => Some(definition),
+                (None, TARGET_COLUMN) => None,
+                (None, _) => Some(Column::Scalar {
+                    r#type: ColumnType::Int,
+                }),
+            };
+            (name.clone(), definition)
+        })
+        .collect()
+}
+
+/// Time range that records are filtered by, using their `read` column
+struct TimeFilter {
+    read:  Option<usize>,
+    since: Option<u128>,
+    until: Option<u128>,
+}
+
+impl TimeFilter {
+    fn new(opts: &ExportCommand, input: &Input) -> Self {
+        Self {
+            read:  input.index_of(READ_COLUMN),
+            since: opts.since,
+            until: opts.until,
+        }
+    }
+
+    /// Gets the nanosecond timestamp of a record, if it has one
+    fn read(&self, record: &ByteRecord) -> Option<u128> {
+        let field = record.get(self.read?)?;
+        std::str::from_utf8(field).ok()?.parse().ok()
+    }
+
+    /// Whether a record is within the time range. Records without a
+    /// timestamp are only kept if there is no time range.
+    fn matches(&self, record: &ByteRecord) -> bool {
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+
+        match self.read(record) {
+            Some(read) => {
+                !matches!(self.since, Some(since) if read < since)
+                    && !matches!(self.until, Some(until) if read >= until)
+            },
+            None => false,
+        }
+    }
+}
+
+/// File that is being merged, along with its earliest record that hasn't
+/// been written yet
+struct MergeSource {
+    input:   Input,
+    fields:  Vec<Field>,
+    filter:  TimeFilter,
+    /// Timestamp and contents of the current record, or None once the file
+    /// has been exhausted
+    current: Option<(u128, ByteRecord)>,
+}
+
+impl MergeSource {
+    fn new(input: Input, columns: &[String], opts: &ExportCommand) -> Result<Self, ExportError> {
+        let target = input.index_of(TARGET_COLUMN);
+        let log_name = input.log_name();
+        let fields = columns
+            .iter()
+            .map(|column| match (input.index_of(column), target) {
+                (Some(index), _) => Field::Column(index),
+                // Ungrouped log files don't have a target id column
+                (None, None) if column == TARGET_COLUMN => {
+                    Field::Constant(log_name.clone().into_bytes())
+                },
+                (None, _) => Field::Empty,
+            })
+            .collect();
+
+        let filter = TimeFilter::new(opts, &input);
+        let mut source = Self {
+            input,
+            fields,
+            filter,
+            current: None,
+        };
+        source.advance()?;
+        Ok(source)
+    }
+
+    /// Reads the next record that is within the time range
+    fn advance(&mut self) -> Result<(), ExportError> {
+        self.current = None;
+        for record in &mut self.input {
+            let record = record?;
+            if self.filter.matches(&record) {
+                // Records without a timestamp are written as soon as possible
+                let read = self.filter.read(&record).unwrap_or_default();
+                self.current = Some((read, record));
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
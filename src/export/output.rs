@@ -0,0 +1,178 @@
+use crate::cli::ParseFailure;
+use crate::export::ExportError;
+use crate::formats::{self, Column, ColumnType, LogFileHeader};
+use csv::{ByteRecord, Writer};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Format that records are exported to, controllable via a CLI flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// Log files (with the YAML header), or a plain CSV table when merging
+    Csv,
+    /// A single JSON object with the log file headers and an array of
+    /// records, each of which is an object keyed by column name
+    Json,
+}
+
+impl ExportFormat {
+    /// Gets the extension of exported files
+    #[must_use]
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "log",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(ParseFailure::new(String::from("format"), s.to_owned())),
+        }
+    }
+}
+
+/// Headers written at the start of an exported file
+#[derive(Clone, Copy)]
+pub enum Prelude<'a> {
+    /// Header of the single log file that is converted
+    Single(&'a LogFileHeader),
+    /// Headers of each merged log file, by file name
+    Merged(&'a BTreeMap<String, LogFileHeader>),
+}
+
+/// Exported file that records are written to
+#[allow(clippy::large_enum_variant)]
+pub enum Output {
+    Csv(Writer<BufWriter<File>>),
+    Json {
+        writer:  BufWriter<File>,
+        /// Names and definitions of each column, where columns without a
+        /// definition contain strings
+        columns: Vec<(String, Option<Column>)>,
+        first:   bool,
+    },
+}
+
+impl Output {
+    /// Creates the exported file, writing the headers and (for CSV) the
+    /// header row
+    pub fn create(
+        path: &Path,
+        format: ExportFormat,
+        prelude: Prelude<'_>,
+        columns: Vec<(String, Option<Column>)>,
+    ) -> Result<Self, ExportError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match format {
+            ExportFormat::Csv => {
+                // Single log files stay log files, while merged tables are
+                // plain CSV so that they can be read by any CSV parser
+                if let Prelude::Single(header) = prelude {
+                    let header_str = serde_yaml::to_string(header)?;
+                    writeln!(writer, "{}", header_str)?;
+                    writeln!(writer, "{}", formats::SECTION_SEPARATOR)?;
+                }
+
+                let mut writer = Writer::from_writer(writer);
+                writer.write_record(columns.iter().map(|(name, _)| name))?;
+                Ok(Self::Csv(writer))
+            },
+            ExportFormat::Json => {
+                match prelude {
+                    Prelude::Single(header) => {
+                        writer.write_all(b"{\"Header\":")?;
+                        serde_json::to_writer(&mut writer, header)?;
+                    },
+                    Prelude::Merged(headers) => {
+                        writer.write_all(b"{\"Headers\":")?;
+                        serde_json::to_writer(&mut writer, headers)?;
+                    },
+                }
+                writer.write_all(b",\"Records\":[")?;
+                Ok(Self::Json {
+                    writer,
+                    columns,
+                    first: true,
+                })
+            },
+        }
+    }
+
+    /// Writes a single record, which has a field for each column
+    pub fn write(&mut self, record: &ByteRecord) -> Result<(), ExportError> {
+        match self {
+            Self::Csv(writer) => writer.write_byte_record(record)?,
+            Self::Json {
+                writer,
+                columns,
+                first,
+            } => {
+                if !*first {
+                    writer.write_all(b",")?;
+                }
+                *first = false;
+
+                let mut object = Map::new();
+                for ((name, column), field) in columns.iter().zip(record) {
+                    object.insert(name.clone(), to_json(field, column.as_ref()));
+                }
+                serde_json::to_writer(writer, &object)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Finishes the exported file, flushing it to disk
+    pub fn finish(self) -> Result<(), ExportError> {
+        match self {
+            Self::Csv(mut writer) => writer.flush()?,
+            Self::Json { mut writer, .. } => {
+                writer.write_all(b"]}\n")?;
+                writer.flush()?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a field to JSON according to its column's definition: integers
+/// become numbers and vectors become arrays, while empty fields become null
+pub fn to_json(field: &[u8], column: Option<&Column>) -> Value {
+    let field = String::from_utf8_lossy(field);
+    match column {
+        _ if field.is_empty() => Value::Null,
+        Some(Column::Scalar { r#type }) => value_to_json(&field, r#type),
+        Some(Column::Vector { r#type, .. }) => Value::Array(
+            field
+                .split_whitespace()
+                .map(|element| value_to_json(element, r#type))
+                .collect(),
+        ),
+        None => Value::String(field.into_owned()),
+    }
+}
+
+fn value_to_json(value: &str, column_type: &ColumnType) -> Value {
+    let number = match column_type {
+        ColumnType::Int | ColumnType::Epoch19 => value
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| value.parse::<u64>().map(Value::from))
+            .ok(),
+        ColumnType::List => None,
+    };
+    number.unwrap_or_else(|| Value::String(value.to_owned()))
+}
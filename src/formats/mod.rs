@@ -0,0 +1,414 @@
+//! Contains the definitions of the log files that rAdvisor produces, along
+//! with functions to read them back. Each log file consists of a YAML header
+//! (see `LogFileHeader`), followed by a `---` separator line and then the CSV
+//! body containing the collected statistics (see `TableMetadata` for the
+//! definitions of its columns). Log files that were closed cleanly (when
+//! their targets stopped, when rAdvisor exited, or by rotation) end with
+//! another `---` separator line followed by a YAML footer (see
+//! `LogFileFooter`), which can be used to detect truncated log files (see
+//! `Integrity`).
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! let file = BufReader::new(File::open("stats.log")?);
+//! let (header, mut body) = radvisor::formats::read(file)?;
+//! println!("collected by {}", header.collector_type);
+//! for record in body.records() {
+//!     println!("{:?}", record?);
+//! }
+//! if let Some(footer) = body.into_inner().read_footer()? {
+//!     println!("continued in {:?}", footer.next_file);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod ring;
+mod schema;
+pub(self) mod system;
+pub(self) mod table;
+
+pub use ring::*;
+pub use schema::*;
+pub use system::*;
+pub use table::*;
+
+use crate::util::Crc32;
+use csv::{Reader, ReaderBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+/// Line that separates the YAML header and footer from the CSV body in log
+/// files
+pub const SECTION_SEPARATOR: &str = "---";
+
+/// Bundles together all information stored in log file headers
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LogFileHeader {
+    /// Version of rAdvisor that produced the log file
+    pub version:            String,
+    /// Version of the header's schema (see `SCHEMA_VERSION`), which is 1 for
+    /// log files written by older versions
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version:     u32,
+    /// Identifier of the node that the log file was collected on, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id:            Option<String>,
+    /// Name of the provider that discovered the target
+    pub provider:           String,
+    /// Provider-specific metadata about the target
+    pub metadata:           Option<serde_yaml::Value>,
+    pub perf_table:         TableMetadata,
+    pub system:             SystemInfo,
+    /// Name of the collector that produced the CSV body
+    pub collector_type:     String,
+    /// Collector-specific metadata about the target
+    pub collector_metadata: Option<serde_yaml::Value>,
+    /// Nanosecond timestamp of when the target was discovered
+    pub polled_at:          u128,
+    /// Nanosecond timestamp of when collection for the target started
+    pub initialized_at:     u128,
+    /// Nanosecond timestamp that the `read.monotonic` column counts from
+    /// (approximately the boot time), as of when the log file was opened.
+    /// Only present if the column was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monotonic_epoch:    Option<u128>,
+    /// Sparse mode settings, if records were only written when counters
+    /// changed. Counters in skipped records are equal (within the epsilon)
+    /// to those in the previous record.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sparse:             Option<SparseMetadata>,
+    /// Aggregation settings, if each record summarizes the samples collected
+    /// during an interval: integer columns are replaced by `.min`, `.mean`,
+    /// and `.max` columns, the remaining columns hold the values of the last
+    /// sample, and the `aggregate.samples` column counts the samples.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate:          Option<AggregateMetadata>,
+    /// Name of the group of targets that share the log file, if any. Records
+    /// in grouped log files start with an additional `target.id` column.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group:              Option<String>,
+    /// Targets in the group when the log file was opened, by id. Targets that
+    /// join the group later are only identified by the `target.id` column.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub members:            BTreeMap<String, GroupMember>,
+    /// Scheduling settings of the collection thread, if any were given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduling:         Option<SchedulingMetadata>,
+}
+
+impl LogFileHeader {
+    /// Gets the description of each column of the given header row,
+    /// describing the columns of log files written before the schema was
+    /// included based on their names (see `TableMetadata::schema`)
+    #[must_use]
+    pub fn column_schema<'a, I>(&self, names: I) -> Vec<ColumnSchema>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        self.perf_table.schema(names, self.aggregate.is_some())
+    }
+}
+
+/// Schema version of log files without the `SchemaVersion` field
+const fn legacy_schema_version() -> u32 { 1 }
+
+/// Information about a single target in a grouped log file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GroupMember {
+    /// Human-readable name of the target
+    pub name:               String,
+    /// Provider-specific metadata about the target
+    pub metadata:           Option<serde_yaml::Value>,
+    /// Collector-specific metadata about the target
+    pub collector_metadata: Option<serde_yaml::Value>,
+    /// Nanosecond timestamp of when the target was discovered
+    pub polled_at:          u128,
+}
+
+/// Settings used to write a log file in sparse mode
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SparseMetadata {
+    /// Minimum absolute change in any counter for a record to be written
+    pub epsilon:   u64,
+    /// Maximum number of nanoseconds between written records
+    pub heartbeat: u128,
+}
+
+/// Scheduling settings that rAdvisor's own threads ran with while the log
+/// file was collected
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SchedulingMetadata {
+    /// CPUs that the threads were pinned to, in the kernel's list format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus:        Option<String>,
+    /// Nice value of the threads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice:        Option<i32>,
+    /// Priority of the threads under the real-time `SCHED_FIFO` policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rt_priority: Option<i32>,
+}
+
+/// Settings used to write a log file in aggregation mode
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AggregateMetadata {
+    /// Number of nanoseconds between aggregated records
+    pub interval: u128,
+}
+
+/// Information written at the end of a log file when it is closed cleanly
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LogFileFooter {
+    /// Nanosecond timestamp of when the log file was closed
+    pub closed_at: u128,
+    /// Name of the log file that collection continued in, if it was closed
+    /// by rotation
+    pub next_file: Option<String>,
+    /// Number of records in the CSV body, excluding the header row (missing
+    /// in log files written by older versions)
+    #[serde(default)]
+    pub rows:      Option<u64>,
+    /// Number of bytes in the CSV body, including the header row (missing in
+    /// log files written by older versions)
+    #[serde(default)]
+    pub bytes:     Option<u64>,
+    /// CRC-32 checksum of the CSV body, if checksums were enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crc32:     Option<u32>,
+}
+
+impl LogFileFooter {
+    /// Checks the CSV body that was read (with the given number of records)
+    /// against the footer
+    #[must_use]
+    pub fn check(&self, rows: u64, body: BodySummary) -> Integrity {
+        let mismatch =
+            |what: &'static str, expected: String, found: String| Integrity::Mismatched {
+                what,
+                expected,
+                found,
+            };
+        match (self.rows, self.bytes, self.crc32) {
+            (Some(expected), ..) if expected != rows => {
+                mismatch("row count", expected.to_string(), rows.to_string())
+            },
+            (_, Some(expected), _) if expected != body.bytes => {
+                mismatch("byte count", expected.to_string(), body.bytes.to_string())
+            },
+            (.., Some(expected)) if expected != body.crc32 => mismatch(
+                "checksum",
+                format!("{:08x}", expected),
+                format!("{:08x}", body.crc32),
+            ),
+            _ => Integrity::Complete,
+        }
+    }
+}
+
+/// Size and checksum of the CSV body of a log file, as it was read
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BodySummary {
+    /// Number of bytes in the CSV body, including the header row
+    pub bytes: u64,
+    /// CRC-32 checksum of the CSV body
+    pub crc32: u32,
+}
+
+/// Whether a log file is complete, according to its footer
+#[derive(Clone, Debug, PartialEq)]
+pub enum Integrity {
+    /// The log file was closed cleanly, and its body matches its footer
+    Complete,
+    /// The log file has no footer, so it wasn't closed cleanly (such as when
+    /// rAdvisor or the machine crashed) and may be missing records at the end
+    Truncated,
+    /// The body of the log file doesn't match its footer, so it was modified
+    /// or corrupted after it was closed
+    Mismatched {
+        what:     &'static str,
+        expected: String,
+        found:    String,
+    },
+}
+
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Complete => write!(f, "complete"),
+            Self::Truncated => write!(f, "truncated (no footer)"),
+            Self::Mismatched {
+                what,
+                expected,
+                found,
+            } => write!(
+                f,
+                "corrupted ({} doesn't match the footer: expected {}, found {})",
+                what, expected, found
+            ),
+        }
+    }
+}
+
+/// Error that can occur when reading a log file
+#[derive(Debug, thiserror::Error)]
+pub enum ReadError {
+    #[error("could not read log file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not parse log file header or footer: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("could not parse log file body: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("log file header is not followed by a '---' separator")]
+    MissingSeparator,
+    #[error("log file has an invalid CSV delimiter: {0:?}")]
+    InvalidDelimiter(String),
+}
+
+/// Reads and parses the YAML header at the start of a log file, leaving the
+/// reader positioned at the start of the CSV body
+pub fn read_header<R: BufRead>(reader: &mut R) -> Result<LogFileHeader, ReadError> {
+    let mut yaml = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(ReadError::MissingSeparator);
+        }
+
+        if line.trim_end() == SECTION_SEPARATOR {
+            // The header itself can start with a YAML document marker
+            if yaml.trim().is_empty() {
+                continue;
+            }
+
+            break;
+        }
+
+        yaml.push_str(&line);
+    }
+
+    Ok(serde_yaml::from_str(&yaml)?)
+}
+
+/// Reads a log file, parsing its header and returning a CSV reader over the
+/// remaining body. The first record of the body contains the column names.
+pub fn read<R: BufRead>(reader: R) -> Result<(LogFileHeader, Reader<Body<R>>), ReadError> {
+    let mut reader = reader;
+    let header = read_header(&mut reader)?;
+    let delimiter = match header.perf_table.delimiter.as_bytes() {
+        [delimiter] => *delimiter,
+        _ => {
+            return Err(ReadError::InvalidDelimiter(
+                header.perf_table.delimiter.clone(),
+            ))
+        },
+    };
+
+    let body = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(Body::new(reader));
+    Ok((header, body))
+}
+
+/// Reader over the CSV body of a log file that ends at the footer separator,
+/// if there is one
+pub struct Body<R> {
+    inner: R,
+    line:  Vec<u8>,
+    pos:   usize,
+    ended: bool,
+    bytes: u64,
+    crc32: Crc32,
+}
+
+impl<R: BufRead> Body<R> {
+    /// Wraps a reader that is positioned at the start of the CSV body
+    pub const fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            pos: 0,
+            ended: false,
+            bytes: 0,
+            crc32: Crc32::new(),
+        }
+    }
+
+    /// Gets the size and checksum of the body lines that have been read so
+    /// far
+    #[must_use]
+    pub const fn summary(&self) -> BodySummary {
+        BodySummary {
+            bytes: self.bytes,
+            crc32: self.crc32.value(),
+        }
+    }
+
+    /// Reads the footer of the log file (if it has one), and checks the body
+    /// against it given the number of records that were read from it. Any
+    /// remaining body lines are skipped, so all records should have been
+    /// read beforehand.
+    pub fn check(mut self, rows: u64) -> Result<Integrity, ReadError> {
+        io::copy(&mut self, &mut io::sink())?;
+        let summary = self.summary();
+        match self.read_footer()? {
+            Some(footer) => Ok(footer.check(rows, summary)),
+            None => Ok(Integrity::Truncated),
+        }
+    }
+
+    /// Reads the footer of the log file, if it has one. Any remaining body
+    /// lines are skipped.
+    pub fn read_footer(mut self) -> Result<Option<LogFileFooter>, ReadError> {
+        io::copy(&mut self, &mut io::sink())?;
+
+        let mut yaml = String::new();
+        self.inner.read_to_string(&mut yaml)?;
+        match yaml.trim().is_empty() {
+            true => Ok(None),
+            false => Ok(Some(serde_yaml::from_str(&yaml)?)),
+        }
+    }
+}
+
+impl<R: BufRead> Read for Body<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.line.len() {
+            if self.ended {
+                return Ok(0);
+            }
+
+            // Buffer the next line so that the separator can be detected
+            self.line.clear();
+            self.pos = 0;
+            let at_end = self.inner.read_until(b'\n', &mut self.line)? == 0
+                || matches!(
+                    std::str::from_utf8(&self.line),
+                    Ok(line) if line.trim_end() == SECTION_SEPARATOR
+                );
+            if at_end {
+                self.line.clear();
+                self.ended = true;
+                return Ok(0);
+            }
+            self.bytes += self.line.len() as u64;
+            self.crc32.update(&self.line);
+        }
+
+        let remaining = &self.line[self.pos..];
+        let count = remaining.len().min(buf.len());
+        buf[..count].copy_from_slice(&remaining[..count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
@@ -0,0 +1,320 @@
+//! Contains the definition of ring files, which are written instead of log
+//! files when using the `ring` sink. A ring file consists of a fixed-size
+//! binary header (see `RingHeader`), followed by the text that a log file
+//! would start with (the YAML header, the `---` separator line, and the CSV
+//! header row), and then a ring of fixed-size binary records. Once the ring is
+//! full, the oldest records are overwritten.
+//!
+//! Each field of a record is stored in one or more little-endian `i64` slots:
+//! integers and timestamps take a single slot, vector columns take one slot
+//! per element, and lists (such as `cpuset.cpus`) are stored as a bitmask of
+//! 63 elements per slot, in as many slots as the header says. Empty fields are
+//! stored as `MISSING`, and `max` as `UNLIMITED`. Ring files can be converted
+//! to log files with `radvisor export`.
+
+use crate::formats::{Column, ColumnType, TableMetadata};
+use std::convert::TryFrom;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Magic bytes at the start of every ring file
+pub const RING_MAGIC: &[u8; 8] = b"RADVRING";
+
+/// Version of the ring file format. Version 1 files have no list slot count in
+/// their header, since lists always took a single slot.
+pub const RING_VERSION: u32 = 2;
+
+/// Length of the fixed-size binary header
+pub const RING_HEADER_LENGTH: usize = 48;
+
+/// Length of a single slot
+pub const SLOT_LENGTH: usize = 8;
+
+/// Slot value of empty fields (and values that could not be encoded)
+pub const MISSING: i64 = i64::MIN;
+
+/// Slot value of `max` (such as for unlimited limits)
+pub const UNLIMITED: i64 = i64::MAX;
+
+/// Number of elements that each slot of a list bitmask can hold, which leaves
+/// the sign bit unused so that bitmasks never equal `MISSING`
+const LIST_SLOT_BITS: usize = 63;
+
+/// Fixed-size binary header at the start of a ring file. All fields are
+/// little-endian.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RingHeader {
+    /// Number of slots in each record
+    pub slots:           u64,
+    /// Number of records that fit in the ring
+    pub capacity:        u64,
+    /// Total number of records written, including the ones that have since
+    /// been overwritten
+    pub written:         u64,
+    /// Length of the text following the binary header
+    pub metadata_length: u64,
+    /// Number of slots that each list column takes
+    pub list_slots:      u32,
+}
+
+impl RingHeader {
+    /// Offset of the `written` field, which is updated after each record
+    pub const WRITTEN_OFFSET: usize = 32;
+
+    /// Parses the binary header at the start of a ring file, returning None if
+    /// the magic bytes or version don't match
+    #[must_use]
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < RING_HEADER_LENGTH || &bytes[..8] != RING_MAGIC {
+            return None;
+        }
+
+        let u32_at = |offset: usize| {
+            <[u8; 4]>::try_from(&bytes[offset..offset + 4])
+                .ok()
+                .map(u32::from_le_bytes)
+        };
+        let u64_at = |offset: usize| {
+            <[u8; 8]>::try_from(&bytes[offset..offset + 8])
+                .ok()
+                .map(u64::from_le_bytes)
+        };
+        let list_slots = match u32_at(8)? {
+            1 => 1,
+            RING_VERSION => u32_at(12).filter(|&list_slots| list_slots > 0)?,
+            _ => return None,
+        };
+
+        Some(Self {
+            slots: u64_at(16)?,
+            capacity: u64_at(24)?,
+            written: u64_at(Self::WRITTEN_OFFSET)?,
+            metadata_length: u64_at(40)?,
+            list_slots,
+        })
+    }
+
+    /// Writes the binary header to the start of the given buffer
+    pub fn write(&self, bytes: &mut [u8]) {
+        let mut header = &mut bytes[..RING_HEADER_LENGTH];
+        // Writing to a slice of the right length can't fail
+        let _ = header.write_all(RING_MAGIC);
+        let _ = header.write_all(&RING_VERSION.to_le_bytes());
+        let _ = header.write_all(&self.list_slots.to_le_bytes());
+        let _ = header.write_all(&self.slots.to_le_bytes());
+        let _ = header.write_all(&self.capacity.to_le_bytes());
+        let _ = header.write_all(&self.written.to_le_bytes());
+        let _ = header.write_all(&self.metadata_length.to_le_bytes());
+    }
+
+    /// Gets the offset of the first record, which is aligned to the slot
+    /// length, or None if it overflows
+    #[must_use]
+    pub fn data_offset(&self) -> Option<usize> {
+        let metadata_length = usize::try_from(self.metadata_length).ok()?;
+        let end = RING_HEADER_LENGTH.checked_add(metadata_length)?;
+        Some(end.checked_add(SLOT_LENGTH - 1)? & !(SLOT_LENGTH - 1))
+    }
+
+    /// Gets the length of a single record, or None if it overflows
+    #[must_use]
+    pub fn record_length(&self) -> Option<usize> {
+        usize::try_from(self.slots).ok()?.checked_mul(SLOT_LENGTH)
+    }
+
+    /// Gets the total length of the ring file, or None if it overflows
+    #[must_use]
+    pub fn file_length(&self) -> Option<usize> {
+        let capacity = usize::try_from(self.capacity).ok()?;
+        let records = self.record_length()?.checked_mul(capacity)?;
+        self.data_offset()?.checked_add(records)
+    }
+
+    /// Publishes the number of records written to readers of the ring file
+    /// (which may be in other processes), once the records themselves have
+    /// been written. `written` has to point to the `written` field of the
+    /// mapped header.
+    pub fn publish_written(written: &AtomicU64, count: u64) {
+        written.store(count.to_le(), Ordering::Release);
+    }
+
+    /// Loads the number of records written that were published by the
+    /// writer of the ring file, after which those records can be read
+    #[must_use]
+    pub fn load_written(written: &AtomicU64) -> u64 {
+        u64::from_le(written.load(Ordering::Acquire))
+    }
+}
+
+/// Gets the number of slots that each list column needs to hold any of the
+/// given number of elements (such as the CPUs of the host)
+#[must_use]
+pub fn list_slots(elements: usize) -> u32 {
+    let slots = (elements.max(1) + LIST_SLOT_BITS - 1) / LIST_SLOT_BITS;
+    u32::try_from(slots).unwrap_or(u32::MAX)
+}
+
+/// Gets the column definition (and so the slot layout) of each column in the
+/// CSV header row. Columns without a definition in the table metadata are
+/// integers.
+#[must_use]
+pub fn layout<'a, I>(names: I, table: &TableMetadata) -> Vec<Column>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    names
+        .into_iter()
+        .map(|name| {
+            let name = String::from_utf8_lossy(name);
+            match table.columns.get(name.as_ref()) {
+                Some(column) => column.clone(),
+                None => Column::Scalar {
+                    r#type: ColumnType::Int,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Gets the number of slots taken up by a column, given the number of slots
+/// that each list column takes
+#[must_use]
+pub const fn slot_count(column: &Column, list_slots: u32) -> usize {
+    match column {
+        Column::Scalar {
+            r#type: ColumnType::List,
+        } => list_slots as usize,
+        Column::Scalar { .. } => 1,
+        Column::Vector { count, .. } => *count,
+    }
+}
+
+/// Encodes a single field into the slots of its column
+pub fn encode(field: &[u8], column: &Column, slots: &mut [i64]) {
+    match column {
+        Column::Scalar {
+            r#type: ColumnType::List,
+        } => encode_list(field, slots),
+        Column::Scalar { r#type } => slots[0] = encode_value(field, r#type),
+        Column::Vector { r#type, .. } => {
+            let mut elements = field
+                .split(|&b| b == b' ')
+                .filter(|element| !element.is_empty());
+            for slot in slots.iter_mut() {
+                *slot = match elements.next() {
+                    Some(element) => encode_value(element, r#type),
+                    None => MISSING,
+                };
+            }
+        },
+    }
+}
+
+fn encode_value(value: &[u8], column_type: &ColumnType) -> i64 {
+    match (value, column_type) {
+        (b"", _) => MISSING,
+        (b"max", ColumnType::Int) => UNLIMITED,
+        (_, ColumnType::Int | ColumnType::Epoch19) => atoi::atoi::<i64>(value).unwrap_or(MISSING),
+        (_, ColumnType::List) => {
+            let mut slot = [0];
+            encode_list(value, &mut slot);
+            slot[0]
+        },
+    }
+}
+
+/// Encodes a list as a bitmask spread over the given slots. Empty or invalid
+/// lists, and lists with elements that don't fit, are stored as `MISSING`.
+fn encode_list(value: &[u8], slots: &mut [i64]) {
+    slots.fill(0);
+    let capacity = slots.len() * LIST_SLOT_BITS;
+    let valid = !value.is_empty()
+        && value.split(|&b| b == b',').all(|range| {
+            let mut bounds = range.splitn(2, |&b| b == b'-');
+            let start = bounds.next().and_then(atoi::atoi::<usize>);
+            let end = match bounds.next() {
+                Some(end) => atoi::atoi::<usize>(end),
+                None => start,
+            };
+            match (start, end) {
+                (Some(start), Some(end)) if start <= end && end < capacity => {
+                    for element in start..=end {
+                        slots[element / LIST_SLOT_BITS] |= 1 << (element % LIST_SLOT_BITS);
+                    }
+                    true
+                },
+                _ => false,
+            }
+        });
+    if !valid {
+        slots.fill(MISSING);
+    }
+}
+
+/// Decodes the slots of a column back into a field, appending it to the given
+/// buffer
+pub fn decode(slots: &[i64], column: &Column, field: &mut Vec<u8>) {
+    match column {
+        Column::Scalar {
+            r#type: ColumnType::List,
+        } => decode_list(slots, field),
+        Column::Scalar { r#type } => decode_value(slots[0], r#type, field),
+        Column::Vector { r#type, .. } => {
+            for (i, slot) in slots.iter().filter(|&&slot| slot != MISSING).enumerate() {
+                if i > 0 {
+                    field.push(b' ');
+                }
+                decode_value(*slot, r#type, field);
+            }
+        },
+    }
+}
+
+fn decode_value(slot: i64, column_type: &ColumnType, field: &mut Vec<u8>) {
+    let mut itoa_buffer = itoa::Buffer::new();
+    match (slot, column_type) {
+        (MISSING, _) => {},
+        (UNLIMITED, ColumnType::Int) => field.extend_from_slice(b"max"),
+        (_, ColumnType::Int | ColumnType::Epoch19) => {
+            field.extend_from_slice(itoa_buffer.format(slot).as_bytes());
+        },
+        (_, ColumnType::List) => decode_list(&[slot], field),
+    }
+}
+
+/// Decodes a list from a bitmask spread over the given slots
+fn decode_list(slots: &[i64], field: &mut Vec<u8>) {
+    if slots.first().map_or(true, |&slot| slot == MISSING) {
+        return;
+    }
+
+    let mut itoa_buffer = itoa::Buffer::new();
+    let capacity = slots.len() * LIST_SLOT_BITS;
+    let contains =
+        |element: usize| slots[element / LIST_SLOT_BITS] & (1 << (element % LIST_SLOT_BITS)) != 0;
+
+    // Collapse consecutive elements into ranges
+    let mut element = 0;
+    let mut first = true;
+    while element < capacity {
+        if !contains(element) {
+            element += 1;
+            continue;
+        }
+
+        let start = element;
+        while element + 1 < capacity && contains(element + 1) {
+            element += 1;
+        }
+        if !first {
+            field.push(b',');
+        }
+        first = false;
+        field.extend_from_slice(itoa_buffer.format(start).as_bytes());
+        if element > start {
+            field.push(b'-');
+            field.extend_from_slice(itoa_buffer.format(element).as_bytes());
+        }
+        element += 1;
+    }
+}
@@ -0,0 +1,281 @@
+use crate::formats::{Column, ColumnType, TableMetadata};
+use serde::{Deserialize, Serialize};
+
+/// Version of the schema of log file headers. Version 2 added the
+/// description of every column (see `ColumnSchema`) to the perf table; log
+/// files without the `SchemaVersion` field use version 1.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Names of the subsystems that columns are grouped under, which are the
+/// first component of their names
+const SUBSYSTEMS: &[&str] = &[
+    "pids", "cpu", "cpuset", "memory", "hugetlb", "blkio", "io", "host", "fds", "sched", "net",
+    "gpu", "perf", "derived",
+];
+
+/// Declaration of a column that rAdvisor produces: its name, along with the
+/// unit and kind of its values
+pub type ColumnDeclaration = (&'static str, Unit, ColumnKind);
+
+/// Machine-readable description of a single column of the CSV body
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ColumnSchema {
+    pub name:      String,
+    pub r#type:    ColumnType,
+    /// Number of elements in each field, if the column is a vector column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count:     Option<usize>,
+    pub unit:      Unit,
+    /// Subsystem (or other source) that the column's values come from, if
+    /// any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subsystem: Option<String>,
+    pub kind:      ColumnKind,
+    /// Statistic of the column's samples that each field contains, if the
+    /// log file is aggregated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statistic: Option<Statistic>,
+}
+
+/// Unit of the values in a column. Units that this version doesn't know
+/// (from log files written by newer versions) are read as `Unknown`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    /// Clock ticks, in units of `USER_HZ`
+    Ticks,
+    Bytes,
+    /// 512-byte sectors
+    Sectors,
+    Pages,
+    Count,
+    /// Set of CPUs
+    Cpus,
+    /// Set of NUMA nodes
+    Nodes,
+    Percent,
+    Permille,
+    /// Thousandths of a CPU
+    Millicores,
+    /// Values without a unit, such as identifiers, codes, and weights
+    Unitless,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Semantic meaning of the values in a column. Kinds that this version
+/// doesn't know are read as `Unknown`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnKind {
+    /// Point in time
+    Timestamp,
+    /// Cumulative value that only increases (until its source is reset), so
+    /// rates are computed from the difference between records
+    Counter,
+    /// Value at the time of the record
+    Gauge,
+    /// Configured limit on a gauge or counter, which is `max` if unlimited
+    Limit,
+    /// Other configured setting
+    Setting,
+    /// Identifier of the record's target
+    Identifier,
+    /// Status code of the record
+    Status,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Statistic of the samples summarized by an aggregated record
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Statistic {
+    Min,
+    Mean,
+    Max,
+}
+
+impl ColumnSchema {
+    /// Describes the column with the given name and definition (where
+    /// columns without one are integers), whose values have the given unit
+    /// and kind
+    #[must_use]
+    pub fn new(name: &str, column: Option<&Column>, unit: Unit, kind: ColumnKind) -> Self {
+        let (r#type, count) = match column {
+            Some(Column::Scalar { r#type }) => (r#type.clone(), None),
+            Some(Column::Vector { r#type, count }) => (r#type.clone(), Some(*count)),
+            None => (ColumnType::Int, None),
+        };
+        let subsystem = name
+            .split(['.', '/'])
+            .next()
+            .filter(|prefix| SUBSYSTEMS.contains(prefix))
+            .map(String::from);
+        Self {
+            name: String::from(name),
+            r#type,
+            count,
+            unit,
+            subsystem,
+            kind,
+            statistic: None,
+        }
+    }
+
+    /// Describes a column of a log file written before the schema was
+    /// included (version 1), inferring its unit and kind from its name as
+    /// rAdvisor produced it. Columns with unfamiliar names (such as those
+    /// added by record processors) have an unknown unit and kind.
+    #[must_use]
+    pub fn infer(name: &str, column: Option<&Column>) -> Self {
+        let (unit, kind) = classify(name);
+        Self::new(name, column, unit, kind)
+    }
+
+    /// Describes a column that summarizes an integer column in aggregated
+    /// log files, which is named after the summarized column followed by
+    /// the statistic's suffix
+    #[must_use]
+    pub fn summary(&self, statistic: Statistic) -> Self {
+        Self {
+            name: format!("{}.{}", self.name, statistic.suffix()),
+            statistic: Some(statistic),
+            ..self.clone()
+        }
+    }
+}
+
+impl Statistic {
+    /// Gets all statistics, in the order of the columns that summarize each
+    /// integer column
+    #[must_use]
+    pub const fn all() -> [Self; 3] { [Self::Min, Self::Mean, Self::Max] }
+
+    /// Gets the suffix (after a `.`) of the columns that contain the
+    /// statistic in aggregated log files
+    #[must_use]
+    pub const fn suffix(self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Mean => "mean",
+            Self::Max => "max",
+        }
+    }
+}
+
+impl TableMetadata {
+    /// Describes the column with the given name (after the columns already
+    /// described), whose definition must already be in the table if it isn't
+    /// an integer column
+    pub fn declare(&mut self, name: &str, unit: Unit, kind: ColumnKind) {
+        let schema = ColumnSchema::new(name, self.columns.get(name), unit, kind);
+        self.schema.push(schema);
+    }
+
+    /// Gets the description of each column of the given header row. Log
+    /// files with a schema (version 2 and later) are described by it, while
+    /// the columns of older log files are inferred from their names (see
+    /// `ColumnSchema::infer`). Integer columns of aggregated log files
+    /// (whose names end in the summarized statistic) are described as
+    /// summaries of the column that they summarize.
+    #[must_use]
+    pub fn schema<'a, I>(&self, names: I, aggregated: bool) -> Vec<ColumnSchema>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        names
+            .into_iter()
+            .map(|name| {
+                let name = String::from_utf8_lossy(name);
+                if let Some(schema) = self.schema.iter().find(|schema| schema.name == name) {
+                    return schema.clone();
+                }
+
+                let column = self.columns.get(name.as_ref());
+                let integer = matches!(
+                    column,
+                    None | Some(Column::Scalar {
+                        r#type: ColumnType::Int,
+                    })
+                );
+                let summary = Statistic::all()
+                    .iter()
+                    .filter(|_| aggregated && integer)
+                    .find_map(|statistic| {
+                        let summarized = name.strip_suffix(statistic.suffix())?;
+                        Some((summarized.strip_suffix('.')?, *statistic))
+                    });
+                match summary {
+                    Some((summarized, statistic)) => {
+                        ColumnSchema::infer(summarized, column).summary(statistic)
+                    },
+                    None => ColumnSchema::infer(&name, column),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Gets the unit and kind of a column of a log file written before the
+/// schema was included, which are inferred from the column's name
+fn classify(name: &str) -> (Unit, ColumnKind) {
+    use ColumnKind::{Counter, Gauge, Identifier, Limit, Setting, Status, Timestamp};
+    use Unit::{Bytes, Count, Cpus, Microseconds, Millicores, Milliseconds, Nanoseconds, Nodes,
+               Pages, Percent, Permille, Sectors, Ticks, Unitless};
+
+    let starts = |prefixes: &[&str]| prefixes.iter().any(|prefix| name.starts_with(prefix));
+    match name {
+        "read" | "read.boottime" => (Nanoseconds, Timestamp),
+        "target.id" => (Unitless, Identifier),
+        "status" => (Unitless, Status),
+        "suspended" | "latency" => (Nanoseconds, Gauge),
+        "aggregate.samples" => (Count, Gauge),
+        "cpuset.cpus" => (Cpus, Setting),
+        "cpuset.mems" => (Nodes, Setting),
+        // From `cpuacct.stat`
+        "cpu.stat.user" | "cpu.stat.system" => (Ticks, Counter),
+        "cpu.throttling.throttled.time" | "sched.wait_time" => (Nanoseconds, Counter),
+        "cpu.max/quota" | "cpu.max.burst" => (Microseconds, Limit),
+        "cpu.max/period" => (Microseconds, Setting),
+        "cpu.weight" => (Unitless, Setting),
+        "pids.max" => (Count, Limit),
+        "memory.high" | "memory.max" | "memory.swap.max" => (Bytes, Limit),
+        "memory.paged.in" | "memory.paged.out" => (Pages, Counter),
+        "memory.stat/pgfault" | "memory.stat/pgmajfault" => (Count, Counter),
+        "blkio.time" => (Milliseconds, Counter),
+        "blkio.sectors" => (Sectors, Counter),
+        "derived.cpu.millicores" => (Millicores, Gauge),
+        _ if name.ends_with(".failcnt") || starts(&["memory.fault.", "memory.events/"]) => {
+            (Count, Counter)
+        },
+        _ if name.contains(".limit.") || name.starts_with("memory.hierarchical_limit.") => {
+            (Bytes, Limit)
+        },
+        _ if starts(&[
+            "memory.",
+            "hugetlb.",
+            "host.memory.",
+            "host.swap.",
+            "gpu.memory.",
+        ]) =>
+        {
+            (Bytes, Gauge)
+        },
+        _ if starts(&["cpu.usage.", "host.cpu."]) => (Nanoseconds, Counter),
+        _ if starts(&["cpu.stat/"]) && name.ends_with("_usec") => (Microseconds, Counter),
+        _ if starts(&["cpu.stat/", "cpu.throttling.", "perf.", "sched."]) => (Count, Counter),
+        _ if starts(&["blkio.service.time.hist.", "blkio.queued."]) => (Count, Gauge),
+        _ if starts(&["blkio.service.time.", "blkio.wait."]) => (Nanoseconds, Counter),
+        _ if starts(&["blkio.", "io.stat/"]) && name.contains("bytes") => (Bytes, Counter),
+        _ if starts(&["blkio.", "io.stat/"]) => (Count, Counter),
+        _ if starts(&["pids.", "fds.", "net.", "gpu.count"]) => (Count, Gauge),
+        _ if starts(&["gpu.utilization."]) => (Percent, Gauge),
+        _ if starts(&["derived."]) && name.ends_with(".permille") => (Permille, Gauge),
+        _ => (Unit::Unknown, ColumnKind::Unknown),
+    }
+}
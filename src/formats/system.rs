@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+/// Represents mostly-static metadata about a system and its network/hardware
+/// configuration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SystemInfo {
+    pub os_type:          Option<String>,
+    pub os_release:       Option<String>,
+    pub distribution:     Option<Distribution>,
+    pub memory_total:     Option<u64>,
+    pub swap_total:       Option<u64>,
+    pub hostname:         Option<String>,
+    pub cpu_count:        u64,
+    pub cpu_online_count: u64,
+    pub cpu_speed:        Option<u64>,
+    /// Kernel-specific behaviors that affect how the collected statistics
+    /// should be interpreted
+    #[serde(default)]
+    pub quirks:           Vec<Quirk>,
+    /// Topology of each possible CPU, in the same order as the per-CPU usage
+    /// vectors. Needed to interpret per-CPU usage on systems with
+    /// heterogeneous cores (such as big.LITTLE ARM servers).
+    #[serde(default)]
+    pub cpu_topology:     Vec<CpuTopology>,
+    /// NUMA nodes of the system, ordered by node id. Together with the
+    /// `CpusetCpus`/`CpusetMems` collector metadata, this allows the collected
+    /// statistics to be related to where each target was placed.
+    #[serde(default)]
+    pub numa_nodes:       Vec<NumaNode>,
+}
+
+/// Represents metadata about a Linux distribution, compliant with
+/// [`os-release`](https://www.freedesktop.org/software/systemd/man/os-release.html)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Distribution {
+    pub id:               Option<String>,
+    pub id_like:          Option<String>,
+    pub name:             Option<String>,
+    pub pretty_name:      Option<String>,
+    pub version:          Option<String>,
+    pub version_id:       Option<String>,
+    pub version_codename: Option<String>,
+    pub cpe_name:         Option<String>,
+    pub build_id:         Option<String>,
+    pub variant:          Option<String>,
+    pub variant_id:       Option<String>,
+}
+
+/// Position of a single logical CPU within the system's topology, read from
+/// `/sys/devices/system/cpu/cpu<n>`. Fields that the kernel doesn't expose on
+/// the current architecture are omitted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CpuTopology {
+    /// Index of the logical CPU
+    pub cpu:       u32,
+    pub online:    bool,
+    /// Physical package (socket) id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package:   Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub die:       Option<i64>,
+    /// Cluster id (such as the cluster of cores sharing an L2 cache on ARM)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster:   Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core:      Option<i64>,
+    /// Relative compute capacity of the CPU (normalized so that the fastest
+    /// CPU has a capacity of 1024), which distinguishes big and little cores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity:  Option<u64>,
+    /// Core type for hybrid x86 CPUs (`core` or `atom`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_type: Option<String>,
+    /// (aarch64) Main ID register, which identifies the implementer and part
+    /// number of the core (such as Cortex-A76 vs Cortex-A55)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub midr:      Option<String>,
+}
+
+/// Single NUMA node, read from `/sys/devices/system/node/node<n>`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NumaNode {
+    pub node:         u32,
+    /// Logical CPUs that belong to the node
+    pub cpus:         Vec<u32>,
+    /// Total memory of the node, in kilobytes (like `MemoryTotal`). Omitted for
+    /// nodes whose memory can't be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_total: Option<u64>,
+}
+
+/// Known kernel behaviors that affect how the collected statistics should be
+/// interpreted. Detected once per log file and recorded in the `System`
+/// section of the header, so that analysis can adjust automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quirk {
+    /// (cgroup v1) `cpuacct.usage_percpu` contains an entry for every
+    /// possible CPU, including ones that are currently offline, so
+    /// `cpu.usage.percpu` has more entries than there are online CPUs
+    CpuacctPercpuIncludesOfflineCpus,
+    /// (cgroup v1) The `blkio.io_*`, `blkio.time`, and `blkio.sectors` files
+    /// are only provided by the legacy CFQ I/O scheduler (removed in Linux
+    /// 5.0), so the corresponding columns are always empty. Only the
+    /// `blkio.throttle.*` columns contain data.
+    BlkioCfqStatsUnavailable,
+    /// (cgroup v2) The `percpu` entry in `memory.stat` was added in Linux 5.9,
+    /// so `memory.stat/percpu` is always 0 on older kernels
+    MemoryStatPercpuUnavailable,
+}
@@ -0,0 +1,40 @@
+use crate::formats::ColumnSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Contains all metadata used for perf table parsing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TableMetadata {
+    pub delimiter: String,
+    /// Definitions of the columns that aren't plain integers
+    pub columns:   BTreeMap<String, Column>,
+    /// Description of every column, in the order of the header row (missing
+    /// in log files written by older versions; see `TableMetadata::schema`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schema:    Vec<ColumnSchema>,
+}
+
+/// Contains the definitions for a single column
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    // Vector is listed first so that it takes precedence when deserializing,
+    // since a vector column would otherwise also match the scalar variant
+    #[serde(rename_all = "PascalCase")]
+    Vector { r#type: ColumnType, count: usize },
+    #[serde(rename_all = "PascalCase")]
+    Scalar { r#type: ColumnType },
+}
+
+/// Enum representing known variants of a column
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    /// Generic integer type
+    Int,
+    /// Nanosecond timestamp
+    Epoch19,
+    /// Set of integers in the kernel's list format (such as `0-3,8`)
+    List,
+}
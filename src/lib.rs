@@ -25,8 +25,14 @@
 #![allow(clippy::redundant_else)]
 
 // Re-export all items
+pub mod channel;
 pub mod cli;
 pub mod collection;
+pub mod daemon;
+pub mod environment;
+pub mod export;
+pub mod formats;
+pub mod logfile;
 pub mod polling;
 pub mod shared;
 pub mod shell;
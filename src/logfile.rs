@@ -0,0 +1,248 @@
+//! Typed API for reading the log files that rAdvisor produces. `LogFile`
+//! parses the YAML header into a `LogFileHeader` and then iterates over the
+//! CSV body, parsing each field according to the column definitions in the
+//! header's `TableMetadata` (see `Value`).
+//!
+//! ```no_run
+//! use radvisor::logfile::{LogFile, Value};
+//!
+//! let mut log = LogFile::open("stats.log")?;
+//! println!("collected by {}", log.header().collector_type);
+//! for row in log.rows() {
+//!     let row = row?;
+//!     if let Some(Value::Int(current)) = row.get("memory.current") {
+//!         println!("{:?}: {}", row.get("read"), current);
+//!     }
+//! }
+//! if let Some(footer) = log.into_footer()? {
+//!     println!("continued in {:?}", footer.next_file);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Log files that weren't closed cleanly (such as when rAdvisor or the
+//! machine crashed) have no footer, which `LogFile::check` reports:
+//!
+//! ```no_run
+//! use radvisor::logfile::{Integrity, LogFile};
+//!
+//! let log = LogFile::open("stats.log")?;
+//! if let Integrity::Truncated = log.check()? {
+//!     println!("stats.log may be missing records at the end");
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::formats::{self, Body, Column, ColumnType};
+use csv::{ByteRecord, ByteRecordsIntoIter};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+pub use crate::formats::{ColumnKind, ColumnSchema, Integrity, LogFileFooter, LogFileHeader,
+                         ReadError, TableMetadata, Unit};
+
+/// Single parsed field of a log file row
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Field was empty (such as when the statistic couldn't be read)
+    Missing,
+    Int(i64),
+    /// `max` in an integer column, meaning that there is no limit
+    Unlimited,
+    /// Nanosecond timestamp
+    Timestamp(u64),
+    /// Set of integers that was written in the kernel's list format (such as
+    /// `0-3,8`)
+    List(Vec<u32>),
+    /// Elements of a vector column (such as per-CPU usage)
+    Vector(Vec<Self>),
+    /// Field that couldn't be parsed as its column's type (such as the
+    /// `target.id` column of group log files)
+    Text(String),
+}
+
+impl Value {
+    fn parse(field: &[u8], column: &Column) -> Self {
+        match column {
+            Column::Scalar { r#type } => Self::parse_scalar(field, r#type),
+            Column::Vector { r#type, .. } => Self::Vector(
+                field
+                    .split(|&b| b == b' ')
+                    .filter(|element| !element.is_empty())
+                    .map(|element| Self::parse_scalar(element, r#type))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn parse_scalar(field: &[u8], column_type: &ColumnType) -> Self {
+        let parsed = match (field, column_type) {
+            (b"", _) => Some(Self::Missing),
+            (b"max", ColumnType::Int) => Some(Self::Unlimited),
+            (_, ColumnType::Int) => atoi::atoi::<i64>(field).map(Self::Int),
+            (_, ColumnType::Epoch19) => atoi::atoi::<u64>(field).map(Self::Timestamp),
+            (_, ColumnType::List) => parse_list(field).map(Self::List),
+        };
+        parsed.unwrap_or_else(|| Self::Text(String::from_utf8_lossy(field).into_owned()))
+    }
+
+    /// Gets the value as an integer, if it is one
+    #[must_use]
+    pub const fn as_int(&self) -> Option<i64> {
+        match self {
+            Self::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a set of integers in the kernel's list format
+fn parse_list(field: &[u8]) -> Option<Vec<u32>> {
+    let mut elements = Vec::new();
+    for range in field.split(|&b| b == b',') {
+        let mut bounds = range.splitn(2, |&b| b == b'-');
+        let start = bounds.next().and_then(atoi::atoi::<u32>)?;
+        let end = match bounds.next() {
+            Some(end) => atoi::atoi::<u32>(end)?,
+            None => start,
+        };
+        elements.extend(start..=end);
+    }
+    Some(elements)
+}
+
+/// Single parsed row of a log file's CSV body
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    columns:    Arc<[String]>,
+    pub values: Vec<Value>,
+}
+
+impl Row {
+    /// Gets the value of the column with the given name, if the log file has
+    /// that column
+    #[must_use]
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        let index = self.columns.iter().position(|name| name == column)?;
+        self.values.get(index)
+    }
+
+    /// Iterates over the column names and values of the row
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.columns
+            .iter()
+            .map(String::as_str)
+            .zip(self.values.iter())
+    }
+}
+
+/// Log file opened for reading, with its header already parsed
+pub struct LogFile<R = BufReader<File>> {
+    header:  LogFileHeader,
+    columns: Arc<[String]>,
+    layout:  Vec<Column>,
+    schema:  Vec<ColumnSchema>,
+    records: ByteRecordsIntoIter<Body<R>>,
+    /// Number of records read so far
+    read:    u64,
+}
+
+impl LogFile {
+    /// Opens the log file at the given path, parsing its header
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ReadError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> LogFile<R> {
+    /// Reads a log file from the given reader, parsing its header
+    pub fn from_reader(reader: R) -> Result<Self, ReadError> {
+        let (header, mut body) = formats::read(reader)?;
+        let header_row = body.byte_headers()?;
+        let layout = formats::layout(header_row, &header.perf_table);
+        let schema = header.column_schema(header_row);
+        let columns = header_row
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        Ok(Self {
+            header,
+            columns,
+            layout,
+            schema,
+            records: body.into_byte_records(),
+            read: 0,
+        })
+    }
+
+    /// Gets the parsed header of the log file
+    #[must_use]
+    pub const fn header(&self) -> &LogFileHeader { &self.header }
+
+    /// Gets the names of the columns in the CSV header row
+    #[must_use]
+    pub fn columns(&self) -> &[String] { &self.columns }
+
+    /// Gets the description of each column (see `ColumnSchema`), which is
+    /// derived from the column names for log files written by older versions
+    #[must_use]
+    pub fn schema(&self) -> &[ColumnSchema] { &self.schema }
+
+    /// Iterates over the remaining rows of the log file, parsing their fields
+    pub fn rows(&mut self) -> Rows<'_, R> { Rows { log: self } }
+
+    /// Reads the footer of the log file, if it has one (such as when it was
+    /// closed by rotation). Any remaining rows are skipped.
+    pub fn into_footer(self) -> Result<Option<LogFileFooter>, ReadError> {
+        self.records.into_reader().into_inner().read_footer()
+    }
+
+    /// Checks whether the log file is complete according to its footer (see
+    /// `Integrity`), reading any remaining rows. Rows with the wrong number of
+    /// fields (such as a partially written last row) are counted, and are
+    /// left to be caught by the footer.
+    pub fn check(mut self) -> Result<Integrity, ReadError> {
+        for record in &mut self.records {
+            if let Err(err) = record {
+                if !matches!(err.kind(), csv::ErrorKind::UnequalLengths { .. }) {
+                    return Err(err.into());
+                }
+            }
+            self.read += 1;
+        }
+        self.records.into_reader().into_inner().check(self.read)
+    }
+
+    fn parse(&self, record: &ByteRecord) -> Row {
+        let values = record
+            .iter()
+            .zip(&self.layout)
+            .map(|(field, column)| Value::parse(field, column))
+            .collect();
+        Row {
+            columns: Arc::clone(&self.columns),
+            values,
+        }
+    }
+}
+
+/// Iterator over the parsed rows of a log file
+pub struct Rows<'a, R> {
+    log: &'a mut LogFile<R>,
+}
+
+impl<R: BufRead> Iterator for Rows<'_, R> {
+    type Item = Result<Row, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.log.records.next()?;
+        self.log.read += 1;
+        Some(
+            record
+                .map(|record| self.log.parse(&record))
+                .map_err(ReadError::from),
+        )
+    }
+}
@@ -1,14 +1,16 @@
-use bus::Bus;
-use radvisor::cli::{self, Command, Opts, RunCommand};
-use radvisor::collection;
-use radvisor::polling;
-use radvisor::polling::providers::Provider;
-use radvisor::shared::{CollectionEvent, IntervalWorkerContext};
+use radvisor::cli::{self, BenchCommand, Command, ExportCommand, Opts, RunCommand, SnapshotCommand};
+use radvisor::collection::{Bench, SinkType, Snapshot, SnapshotError};
+use radvisor::daemon::{Daemon, DaemonError};
+use radvisor::export;
+use radvisor::polling::providers::InitializationError;
 use radvisor::shell::{self, Shell};
-use std::sync::mpsc::{self, Receiver, Sender};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::io;
+use std::sync::Arc;
+
+/// Counts heap allocations, so that `radvisor bench` can report them
+#[cfg(feature = "bench")]
+#[global_allocator]
+static ALLOCATOR: radvisor::util::CountingAllocator = radvisor::util::CountingAllocator;
 
 /// Disable compilation on platforms other than Linux
 #[cfg(not(target_os = "linux"))]
@@ -32,102 +34,146 @@ fn main() {
     // Parse command line arguments
     let opts: Opts = cli::load();
     // Wrap the shell in an Arc so that it can be sent across threads
-    let shell = Arc::new(shell::Shell::new(&opts.shell_options));
+    let mut shell = shell::Shell::new(&opts.shell_options);
+    // Keep stdout free for records when they are streamed or printed to it
+    let stdout_records = match &opts.command {
+        Command::Run(run_opts) => run_opts.provider.collection().sink == SinkType::Stream,
+        Command::Snapshot(_) => true,
+        Command::Export(_) | Command::Bench(_) => false,
+    };
+    if stdout_records {
+        shell = shell.with_stderr_only();
+    }
+    let shell = Arc::new(shell);
 
     match opts.command {
         Command::Run(run_opts) => {
             run(run_opts, shell);
         },
+        Command::Export(export_opts) => {
+            export(&export_opts, &shell);
+        },
+        Command::Snapshot(snapshot_opts) => {
+            snapshot(&snapshot_opts, &shell);
+        },
+        Command::Bench(bench_opts) => {
+            bench(&bench_opts, &shell);
+        },
     }
 }
 
-/// Bootstraps the two worker threads, preparing the necessary communication
-/// between them
+/// Runs the collection daemon until it is terminated, exiting with the
+/// provider's status code if it finished on its own
 fn run(opts: RunCommand, shell: Arc<Shell>) {
-    // Used to send collection events from the polling thread to the collection
-    // thread
-    let (tx, rx): (Sender<CollectionEvent>, Receiver<CollectionEvent>) = mpsc::channel();
-
-    let polling_opts = opts.provider.polling().clone();
-    let collection_opts = opts.provider.collection().clone();
-
-    // Create the thread worker contexts using the term bus lock
-    let term_bus = initialize_term_handler(Arc::clone(&shell));
-    let mut term_bus_handle = term_bus.lock().unwrap();
-    let polling_context = IntervalWorkerContext {
-        interval: polling_opts.interval,
-        term_rx:  term_bus_handle.add_rx(),
-        shell:    Arc::clone(&shell),
-    };
-    let collection_context = IntervalWorkerContext {
-        interval: collection_opts.interval,
-        term_rx:  term_bus_handle.add_rx(),
-        shell:    Arc::clone(&shell),
+    let mut daemon = Daemon::new(opts, Arc::clone(&shell));
+    daemon
+        .handle_signals()
+        .expect("Error: could not create SIGINT handler");
+
+    match daemon.start() {
+        Ok(()) => (),
+        Err(DaemonError::Initialization(err)) => {
+            report_initialization_error(err, &shell);
+            std::process::exit(1);
+        },
+        Err(err) => {
+            shell.error(format!("Error: {}", err));
+            std::process::exit(1);
+        },
+    }
+
+    let exit_code = match daemon.wait() {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            shell.error(format!("Error: {}", err));
+            None
+        },
     };
-    drop(term_bus_handle);
-
-    // Spawn both threads
-    let polling_thread: thread::JoinHandle<()> = thread::Builder::new()
-        .name(String::from("poll"))
-        .spawn(move || {
-            // Resolve container metadata provider
-            let mut provider: Box<dyn Provider> = opts.provider.get_impl();
-
-            // Determine if the current process can connect to the provider source
-            let provider_shell = Arc::clone(&polling_context.shell);
-            if let Err(err) = provider.initialize(&opts, provider_shell) {
-                let mut message = err.suggestion.clone();
-                // Print the reason for the error in verbose mode
-                if let Some(original) = err.original {
-                    polling_context.shell.verbose(|_| {
-                        let formatted = format!("\n\n{}", original);
-                        message.push_str(&formatted);
-                    });
-                }
-                polling_context.shell.error(message);
-                std::process::exit(1);
-            }
-
-            polling::run(&tx, polling_context, provider)
-        })
-        .unwrap();
-    let collection_thread: thread::JoinHandle<()> = thread::Builder::new()
-        .name(String::from("collect"))
-        .spawn(move || collection::run(&rx, collection_context, &collection_opts))
-        .unwrap();
-
-    // Join the threads, which automatically exit upon termination
-    if collection_thread.join().is_err() {
-        shell.error("Error: collection thread resulted in panic");
+    shell.status("Exiting", "rAdvisor");
+
+    if let Some(code) = exit_code {
+        std::process::exit(code);
     }
-    if polling_thread.join().is_err() {
-        shell.error("Error: polling thread resulted in panic");
+}
+
+/// Prints the suggestion of a provider initialization error, along with the
+/// reason for the error in verbose mode
+fn report_initialization_error(err: InitializationError, shell: &Shell) {
+    let mut message = err.suggestion.clone();
+    if let Some(original) = err.original {
+        shell.verbose(|_| {
+            let formatted = format!("\n\n{}", original);
+            message.push_str(&formatted);
+        });
     }
-    shell.status("Exiting", "rAdvisor");
+    shell.error(message);
 }
 
-/// Initializes a bus that handles termination by broadcasting an empty message
-/// to all worker threads
-fn initialize_term_handler(shell: Arc<Shell>) -> Arc<Mutex<Bus<()>>> {
-    let term_bus = Arc::new(Mutex::new(Bus::new(1)));
-    let term_bus_c = Arc::clone(&term_bus);
-    ctrlc::set_handler(move || handle_termination(&term_bus_c, Arc::clone(&shell)))
-        .expect("Error: could not create SIGINT handler");
+/// Takes a snapshot of the current targets and prints it to stdout, exiting
+/// with a non-zero status code if it couldn't be taken
+fn snapshot(opts: &SnapshotCommand, shell: &Arc<Shell>) {
+    let result = Snapshot::take(opts, shell).and_then(|snapshot| {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        snapshot.write(opts.format, &mut out)
+    });
 
-    term_bus
+    match result {
+        Ok(()) => (),
+        Err(SnapshotError::Initialization(err)) => {
+            report_initialization_error(err, shell);
+            std::process::exit(1);
+        },
+        Err(err) => {
+            shell.error(format!("Error: {}", err));
+            std::process::exit(1);
+        },
+    }
 }
 
-/// Handles program termination by broadcasting an empty message on a special
-/// termination bus that each thread listens to
-fn handle_termination(bus_lock: &Arc<Mutex<Bus<()>>>, shell: Arc<Shell>) -> ! {
-    let mut bus = bus_lock.lock().unwrap();
-    bus.broadcast(());
-
-    // Try again to tear down the program
-    thread::sleep(Duration::from_millis(2000));
-    shell.warn("Could not shutdown gracefully on the first try. Trying again...");
-    bus.broadcast(());
-    thread::sleep(Duration::from_millis(1000));
-    shell.warn("Forcibly closing; buffers may not be flushed.");
-    std::process::exit(2);
+/// Runs the benchmark and prints its results to stdout, exiting with a
+/// non-zero status code if it failed (or if the audit found allocations)
+fn bench(opts: &BenchCommand, shell: &Shell) {
+    if let Err(err) = Bench::run(opts) {
+        shell.error(format!("Error: {}", err));
+        std::process::exit(1);
+    }
+}
+
+/// Exports each of the given files (or merges them into a single file),
+/// exiting with a non-zero status code if any of them couldn't be exported
+fn export(opts: &ExportCommand, shell: &Shell) {
+    let report =
+        |result: Result<export::Exported, export::ExportError>, source: String| match result {
+            Ok(exported) => {
+                shell.status(
+                    "Exported",
+                    format!(
+                        "{} records from {} to {}",
+                        exported.records,
+                        source,
+                        exported.destination.display()
+                    ),
+                );
+                true
+            },
+            Err(err) => {
+                shell.error(format!("Could not export {}: {}", source, err));
+                false
+            },
+        };
+
+    let succeeded = match &opts.merge {
+        Some(destination) => report(
+            export::merge(opts, destination),
+            format!("{} files", opts.files.len()),
+        ),
+        None => opts.files.iter().fold(true, |succeeded, path| {
+            report(export::convert(path, opts), path.display().to_string()) && succeeded
+        }),
+    };
+
+    if !succeeded {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,93 @@
+use crate::cli::ParseFailure;
+use crate::util;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Attribute of a target that a filter is matched against
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterField {
+    Name,
+    Image,
+    Label(String),
+}
+
+/// Single `--include`/`--exclude` rule, parsed from one of:
+///   - `<glob>` or `name=<glob>`: matches the target name
+///   - `image=<glob>`: matches the image the target was created from
+///   - `label=<key>=<glob>`: matches the value of the given label
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetFilter {
+    pub field:   FilterField,
+    pub pattern: String,
+}
+
+/// Attributes of a single target that filters can be applied to
+pub struct FilterSubject<'a> {
+    pub names:  &'a [String],
+    pub image:  &'a str,
+    pub labels: &'a HashMap<String, String>,
+}
+
+impl FromStr for TargetFilter {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseFailure::new(String::from("target filter"), s.to_owned());
+        let (field, pattern) = match s.split_once('=') {
+            None => (FilterField::Name, s),
+            Some(("name", pattern)) => (FilterField::Name, pattern),
+            Some(("image", pattern)) => (FilterField::Image, pattern),
+            Some(("label", rest)) => match rest.split_once('=') {
+                Some((key, pattern)) if !key.is_empty() => {
+                    (FilterField::Label(key.to_owned()), pattern)
+                },
+                // A bare label key matches any value
+                None if !rest.is_empty() => (FilterField::Label(rest.to_owned()), "*"),
+                _ => return Err(invalid()),
+            },
+            Some(_) => return Err(invalid()),
+        };
+
+        if pattern.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            field,
+            pattern: pattern.to_owned(),
+        })
+    }
+}
+
+impl TargetFilter {
+    /// Determines whether the filter matches the given target
+    #[must_use]
+    pub fn matches(&self, subject: &FilterSubject<'_>) -> bool {
+        match &self.field {
+            FilterField::Name => subject.names.iter().any(|name| {
+                // Docker container names are prefixed with a slash
+                let name = name.strip_prefix('/').unwrap_or(name);
+                util::glob_match(&self.pattern, name)
+            }),
+            FilterField::Image => util::glob_match(&self.pattern, subject.image),
+            FilterField::Label(key) => matches!(
+                subject.labels.get(key),
+                Some(value) if util::glob_match(&self.pattern, value)
+            ),
+        }
+    }
+}
+
+/// Determines whether a target should be collected given the sets of include
+/// and exclude filters. If any include filters are given, the target must
+/// match at least one of them. A target matching any exclude filter is never
+/// collected.
+#[must_use]
+pub fn should_collect(
+    subject: &FilterSubject<'_>,
+    include: &[TargetFilter],
+    exclude: &[TargetFilter],
+) -> bool {
+    let included = include.is_empty() || include.iter().any(|f| f.matches(subject));
+    included && !exclude.iter().any(|f| f.matches(subject))
+}
@@ -0,0 +1,36 @@
+//! Synthetic target for the host itself (`--collect-host`), which is collected
+//! alongside the provider's targets to give each node a host-level baseline
+
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
+use crate::util::{self, CgroupDriver, CgroupPath, CgroupVersion};
+use std::path::PathBuf;
+
+const PROVIDER_TYPE: &str = "host";
+
+/// Id (and name) of the host target, which determines its log file name
+pub const HOST_TARGET_ID: &str = "host";
+
+/// Creates the start event for the host target, collecting the root cgroup.
+/// Returns None if the version of the cgroup hierarchy couldn't be resolved.
+#[must_use]
+pub fn start_event() -> Option<CollectionEvent> {
+    let version = CgroupVersion::try_resolve()?;
+    let cgroup = CgroupPath {
+        path: PathBuf::new(),
+        driver: CgroupDriver::Cgroupfs,
+        version,
+    };
+
+    Some(CollectionEvent::Start {
+        method: CollectionMethod::Host(cgroup),
+        target: CollectionTarget {
+            provider:  PROVIDER_TYPE,
+            id:        String::from(HOST_TARGET_ID),
+            name:      String::from(HOST_TARGET_ID),
+            metadata:  None,
+            poll_time: util::nano_ts(),
+            group:     None,
+            file_name: None,
+        },
+    })
+}
@@ -1,19 +1,44 @@
+pub mod filter;
+pub mod host;
 pub mod providers;
+pub mod redact;
 
+use crate::channel::{EventSender, SendError};
+use crate::cli::PollingOptions;
+use crate::daemon::{DaemonEvent, Subscribers};
 use crate::polling::providers::Provider;
-use crate::shared::{CollectionEvent, IntervalWorkerContext};
+use crate::shared::{CollectionEvent, Id, IntervalWorkerContext};
+use crate::shell::Shell;
 use crate::timer::{Stoppable, Timer};
+use crate::util;
+use std::collections::HashMap;
+use std::mem;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 use std::thread;
 
-/// Thread function that updates the target list each second by default
+/// Number of polls that the provider has to report a target as stopped after
+/// the collection thread found its cgroup removed, before a warning is printed
+const REMOVAL_CONFIRMATION_POLLS: u32 = 3;
+
+/// Thread function that updates the target list each second by default,
+/// notifying the subscribers of every sent event. Returns the status code
+/// that rAdvisor should exit with if the provider finished generating targets
+/// on its own. If `collect_host` is set, the host target is started along with
+/// the first polled targets. Targets received on `vanished_rx` (whose cgroups
+/// the collection thread found removed) are expected to be reported as
+/// stopped by the provider soon after.
+#[must_use]
 pub fn run(
-    tx: &Sender<CollectionEvent>,
+    tx: &EventSender,
+    vanished_rx: &Receiver<Id>,
     context: IntervalWorkerContext,
     provider: Box<dyn Provider>,
-) {
+    subscribers: &Subscribers,
+    collect_host: bool,
+) -> Option<i32> {
     context.shell.status(
         "Beginning",
         format!(
@@ -40,9 +65,17 @@ pub fn run(
         .unwrap();
     // Move to mutable
     let mut provider = provider;
+    provider.watch(timer.waker());
+    let mut exit_code: Option<i32> = None;
+    let mut host_event = match collect_host {
+        true => host_start_event(&context.shell),
+        false => None,
+    };
+    let mut removals = RemovalTracker::default();
+    let mut deferred: Vec<PendingEvent> = Vec::new();
 
     for _ in timer {
-        let events: Vec<CollectionEvent> = match provider.poll() {
+        let mut events: Vec<CollectionEvent> = match provider.poll() {
             Ok(vec) => vec,
             Err(err) => {
                 context
@@ -51,19 +84,243 @@ pub fn run(
                 Vec::with_capacity(0)
             },
         };
+        if let Some(event) = host_event.take() {
+            events.insert(0, event);
+        }
+        removals.confirm(vanished_rx, &events, &context.shell);
 
         // Make sure the collection hasn't been stopped
         if !has_stopped.load(Ordering::SeqCst) {
-            for event in events {
-                if let Err(err) = tx.send(event) {
-                    // If sending fails, then stop the collection thread
-                    context.shell.error(format!(
-                        "Could not send polled target events to collector thread: {}",
-                        err
+            // Events still waiting from earlier polls mean that the collection
+            // thread is falling behind
+            let backlog = tx.stats().depth;
+            if backlog > 0 && !events.is_empty() {
+                context.shell.verbose(|sh| {
+                    sh.info(format!(
+                        "Event queue depth: {}/{} before sending {} new events",
+                        backlog,
+                        tx.capacity(),
+                        events.len()
+                    ));
+                });
+            }
+
+            send_events(tx, events, &mut deferred, subscribers, &context.shell);
+        }
+
+        exit_code = provider.finished();
+        if exit_code.is_some() {
+            break;
+        }
+    }
+
+    context.shell.verbose(|sh| {
+        let stats = tx.stats();
+        sh.info(format!(
+            "Event queue statistics: {} sent, {} coalesced, {} deferred, {} still queued",
+            stats.sent, stats.coalesced, stats.deferred, stats.depth
+        ));
+    });
+
+    exit_code
+}
+
+/// Sends polled events to the collection thread, notifying the subscribers
+/// of each one that was queued. Start events that don't fit in the queue are
+/// added to `deferred`, to be sent again on the next poll.
+fn send_events(
+    tx: &EventSender,
+    events: Vec<CollectionEvent>,
+    deferred: &mut Vec<PendingEvent>,
+    subscribers: &Subscribers,
+    shell: &Shell,
+) {
+    // Start events deferred by earlier polls are sent first, since the new
+    // events might stop their targets again
+    let earlier = mem::take(deferred);
+    let pending = earlier
+        .into_iter()
+        .chain(events.into_iter().map(|event| PendingEvent {
+            event,
+            notified: false,
+        }));
+    for PendingEvent { event, notified } in pending {
+        if let CollectionEvent::Stop(id) = &event {
+            // A target that stops before its deferred start event was sent
+            // doesn't need to be collected at all
+            if let Some(position) = deferred.iter().position(|d| d.starts(id)) {
+                let start = deferred.remove(position);
+                if start.notified {
+                    subscribers.notify(&DaemonEvent::from(&event));
+                }
+                continue;
+            }
+        }
+
+        let notification = match subscribers.is_empty() || notified {
+            true => None,
+            false => Some(DaemonEvent::from(&event)),
+        };
+        let is_stop = matches!(event, CollectionEvent::Stop(_));
+        match tx.send(event) {
+            Ok(()) => {
+                if let Some(notification) = notification {
+                    subscribers.notify(&notification);
+                }
+            },
+            Err(SendError::Overflow {
+                deferred: start, ..
+            }) => {
+                // A stop event is always queued, and only ever defers a start
+                // event that was queued (and notified) earlier
+                if is_stop {
+                    if let Some(notification) = notification {
+                        subscribers.notify(&notification);
+                    }
+                }
+                deferred.push(PendingEvent {
+                    event:    *start,
+                    notified: is_stop,
+                });
+            },
+            Err(err @ SendError::Disconnected) => {
+                // If sending fails, then stop the collection thread
+                shell.error(format!(
+                    "Could not send polled target events to collector thread: {}",
+                    err
+                ));
+                break;
+            },
+        }
+    }
+
+    if !deferred.is_empty() {
+        shell.warn(format!(
+            "Could not send all polled target events to collector thread: event queue is full, so \
+             the start of {} targets is deferred to the next poll ({} start events deferred so \
+             far)",
+            deferred.len(),
+            tx.stats().deferred
+        ));
+    }
+}
+
+/// Event that is still to be sent to the collection thread: either a start
+/// event that was deferred since the event queue was full, or a newly polled
+/// event
+struct PendingEvent {
+    event:    CollectionEvent,
+    /// Whether subscribers were already notified of the event, which happens
+    /// when it's first queued
+    notified: bool,
+}
+
+impl PendingEvent {
+    /// Whether this is the start event of the given target
+    fn starts(&self, id: &Id) -> bool {
+        matches!(&self.event, CollectionEvent::Start { target, .. } if &target.id == id)
+    }
+}
+
+/// Sets the directory that the cgroup hierarchy is mounted at, which must be
+/// done before the provider is initialized: either the one given by
+/// `--cgroup-root`, or the one detected from the mounted cgroup hierarchies.
+/// Warns if rAdvisor seems to run in a cgroup namespace that hides the rest
+/// of the hierarchy, since the cgroups of targets won't be found then.
+pub fn resolve_cgroup_root(opts: &PollingOptions, shell: &Shell) {
+    let root = match &opts.cgroup_root {
+        Some(root) => root.clone(),
+        None => match util::detect_cgroup_root() {
+            Some(detected) => {
+                if !detected.complete {
+                    shell.warn(format!(
+                        "rAdvisor seems to run in a cgroup namespace, so the cgroup hierarchy \
+                         mounted at {} only includes its own cgroup. To collect other targets, \
+                         mount the host's cgroup hierarchy (such as at /host/sys/fs/cgroup) and \
+                         pass it to --cgroup-root",
+                        detected.path.display()
                     ));
-                    break;
+                }
+                detected.path
+            },
+            None => return,
+        },
+    };
+
+    if root != Path::new(util::STANDARD_CGROUP_MOUNT_ROOT) {
+        shell.status(
+            "Using",
+            format!("cgroup hierarchy mounted at {}", root.display()),
+        );
+    }
+    if !util::set_cgroup_root(&root) {
+        shell.warn(format!(
+            "Could not use the cgroup hierarchy mounted at {}: cgroups are already resolved \
+             relative to {}",
+            root.display(),
+            util::cgroup_root().display()
+        ));
+    }
+}
+
+/// Creates the start event for the host target, warning if it can't be
+/// collected
+fn host_start_event(shell: &Shell) -> Option<CollectionEvent> {
+    let event = host::start_event();
+    if event.is_none() {
+        shell.warn(
+            "Could not collect host statistics: the version of the cgroup hierarchy could not be \
+             resolved",
+        );
+    }
+    event
+}
+
+/// Targets whose cgroups the collection thread found removed, along with the
+/// number of polls since then that haven't reported them as stopped
+#[derive(Default)]
+struct RemovalTracker {
+    pending: HashMap<Id, u32>,
+}
+
+impl RemovalTracker {
+    /// Adds the targets newly received from the collection thread and
+    /// confirms the removal of those stopped by the latest poll, warning
+    /// about any that the provider still hasn't reported as stopped after
+    /// several polls (such as containers that were restarted in between
+    /// polls, which won't be collected again until they stop)
+    fn confirm(&mut self, vanished_rx: &Receiver<Id>, events: &[CollectionEvent], shell: &Shell) {
+        for id in vanished_rx.try_iter() {
+            self.pending.insert(id, 0);
+        }
+        if self.pending.is_empty() {
+            return;
+        }
+
+        for event in events {
+            if let CollectionEvent::Stop(id) = event {
+                if self.pending.remove(id).is_some() {
+                    shell.verbose(|sh| {
+                        sh.info(format!("Confirmed the removal of target {}", id));
+                    });
                 }
             }
         }
+
+        self.pending.retain(|id, polls| {
+            *polls += 1;
+            match *polls < REMOVAL_CONFIRMATION_POLLS {
+                true => true,
+                false => {
+                    shell.warn(format!(
+                        "The cgroup of target {} was removed, but the provider hasn't reported it \
+                         as stopped after {} polls; it won't be collected until it is stopped and \
+                         started again",
+                        id, polls
+                    ));
+                    false
+                },
+            }
+        });
     }
 }
@@ -0,0 +1,298 @@
+use crate::cli::RunCommand;
+use crate::polling::providers::{CgroupOptions, InitializationError, Provider};
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
+use crate::shell::Shell;
+use crate::timer::Waker;
+use crate::util::{self, CgroupDriver, CgroupPath, CgroupVersion, Inotify, ItemPool};
+use anyhow::Error;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+const PROVIDER_TYPE: &str = "cgroup";
+
+/// Events on the watched directories that can mean that a cgroup was created
+/// in or removed from them
+const WATCH_MASK: u32 =
+    util::IN_CREATE | util::IN_DELETE | util::IN_MOVED_FROM | util::IN_MOVED_TO | util::IN_ONLYDIR;
+
+/// Provider that collects arbitrary cgroups (such as systemd services) given
+/// by path or by glob pattern, without needing a container runtime
+pub struct Cgroup {
+    id_pool:  ItemPool<String>,
+    patterns: Vec<String>,
+    /// Whether cgroups matching the same pattern share a log file
+    group:    bool,
+    /// Whether to watch the directories that matching cgroups are created in
+    watch:    bool,
+    /// Inotify instance watching those directories, if watching them
+    watcher:  Option<Arc<Inotify>>,
+    version:  Option<CgroupVersion>,
+    shell:    Option<Arc<Shell>>,
+}
+
+/// Possible errors that can occur during cgroup provider initialization
+#[derive(Debug)]
+enum CgroupInitError {
+    InvalidCgroupMount,
+    VersionDetectionFailed,
+}
+
+impl From<CgroupInitError> for InitializationError {
+    fn from(other: CgroupInitError) -> Self {
+        match other {
+            CgroupInitError::InvalidCgroupMount => Self {
+                original:   None,
+                suggestion: String::from(util::INVALID_CGROUP_MOUNT_MESSAGE),
+            },
+            CgroupInitError::VersionDetectionFailed => Self {
+                original:   None,
+                suggestion: String::from(
+                    "Could not detect the currently running cgroup version. Are cgroups mounted \
+                     in /sys/fs/cgroup (or at --cgroup-root)?",
+                ),
+            },
+        }
+    }
+}
+
+/// Metadata included in the log file header for each cgroup
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct CgroupMetadata<'a> {
+    cgroup:  &'a str,
+    pattern: &'a str,
+}
+
+impl Provider for Cgroup {
+    fn initialize(
+        &mut self,
+        opts: &RunCommand,
+        shell: Arc<Shell>,
+    ) -> Result<(), InitializationError> {
+        self.shell = Some(Arc::clone(&shell));
+        self.shell().status("Initializing", "cgroup provider");
+
+        let inner_opts: CgroupOptions = opts.provider.clone().into_inner_cgroup();
+        self.patterns = inner_opts.cgroups;
+        self.group = inner_opts.group;
+        self.watch = !inner_opts.no_watch;
+
+        match self.try_init() {
+            Ok(()) => Ok(()),
+            Err(init_err) => Err(init_err.into()),
+        }
+    }
+
+    fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error> {
+        let version = self
+            .version
+            .expect("Cgroup version must be initialized: invariant violated");
+
+        // Map each matching cgroup to the first pattern that matched it
+        let mut to_collect: BTreeMap<String, &str> = BTreeMap::new();
+        for pattern in &self.patterns {
+            for path in util::find_cgroups(pattern, version) {
+                let id = format!("/{}", path.display());
+                to_collect.entry(id).or_insert(pattern);
+            }
+        }
+
+        let ids = to_collect.keys().map(String::clone);
+        let (added, removed) = self.id_pool.update(ids);
+
+        let mut events: Vec<CollectionEvent> = Vec::with_capacity(added.len() + removed.len());
+        let (added_len, removed_len) = (added.len(), removed.len());
+        events.extend(removed.into_iter().map(CollectionEvent::Stop));
+        for id in added {
+            let pattern = to_collect.get(&id).copied().unwrap_or_default();
+            match make_start_event(&id, pattern, self.group, version) {
+                Ok(start) => events.push(start),
+                Err(err) => self.shell().warn(format!(
+                    "Could not start collection for cgroup {}: failed to serialize metadata: {}",
+                    id, err
+                )),
+            }
+        }
+
+        if added_len != 0 || removed_len != 0 {
+            self.shell().verbose(|sh| {
+                sh.info(format!(
+                    "Found {} (+{}, -{}) cgroups matching the given paths",
+                    to_collect.len(),
+                    added_len,
+                    removed_len
+                ));
+            });
+        }
+
+        // Directories that matching cgroups are created in might have been
+        // created since the last poll
+        self.add_watches();
+        Ok(events)
+    }
+
+    /// Watches the directories that matching cgroups are created in with
+    /// inotify, polling the provider right away when any subdirectories are
+    /// created in or removed from them. Falls back to only polling if
+    /// inotify isn't available.
+    fn watch(&mut self, waker: Waker) {
+        if !self.watch {
+            return;
+        }
+
+        let watcher = match Inotify::new() {
+            Ok(watcher) => Arc::new(watcher),
+            Err(err) => {
+                self.shell().warn(format!(
+                    "Could not watch the cgroup hierarchy for new and removed cgroups: {}. They \
+                     will only be found when polling",
+                    err
+                ));
+                return;
+            },
+        };
+
+        let watcher_c = Arc::clone(&watcher);
+        let shell_c = Arc::clone(self.shell.as_ref().unwrap());
+        let spawned = thread::Builder::new()
+            .name(String::from("poll-watch"))
+            .spawn(move || loop {
+                match watcher_c.read_events() {
+                    Ok(events) => {
+                        if events
+                            .iter()
+                            .any(util::InotifyEvent::changed_subdirectories)
+                        {
+                            waker.wake();
+                        }
+                    },
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                    Err(err) => {
+                        shell_c.warn(format!(
+                            "Could not read changes to the cgroup hierarchy: {}. New and removed \
+                             cgroups will only be found when polling",
+                            err
+                        ));
+                        break;
+                    },
+                }
+            });
+        if spawned.is_ok() {
+            self.watcher = Some(watcher);
+            self.add_watches();
+        }
+    }
+}
+
+impl Default for Cgroup {
+    fn default() -> Self { Self::new() }
+}
+
+impl Cgroup {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            id_pool:  ItemPool::new(),
+            patterns: Vec::new(),
+            group:    false,
+            watch:    false,
+            watcher:  None,
+            version:  None,
+            shell:    None,
+        }
+    }
+
+    /// Watches every directory that a cgroup matching any pattern could be
+    /// created in: the cgroups matching each leading part of the pattern
+    /// (including the cgroup root), in every hierarchy that they exist in.
+    /// Watches of removed directories are removed by the kernel, and those
+    /// that already exist are left as they are.
+    fn add_watches(&self) {
+        let (Some(watcher), Some(version)) = (&self.watcher, self.version) else {
+            return;
+        };
+
+        for pattern in &self.patterns {
+            let components = pattern
+                .split('/')
+                .filter(|component| !component.is_empty())
+                .collect::<Vec<_>>();
+            for depth in 0..components.len() {
+                let parents = util::find_cgroups(&components[..depth].join("/"), version);
+                for root in util::cgroup_hierarchies(version) {
+                    for parent in &parents {
+                        // Ignore failures: the directory could have been
+                        // removed since it was found
+                        let _ = watcher.add_watch(&root.join(parent), WATCH_MASK);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Attempts to initialize the cgroup provider, failing if the needed
+    /// cgroups aren't mounted properly
+    fn try_init(&mut self) -> Result<(), CgroupInitError> {
+        if !util::cgroups_mounted_properly() {
+            return Err(CgroupInitError::InvalidCgroupMount);
+        }
+
+        let version =
+            CgroupVersion::try_resolve().ok_or(CgroupInitError::VersionDetectionFailed)?;
+        self.shell()
+            .info(format!("Identified {} as cgroup version", version));
+        self.version = Some(version);
+
+        Ok(())
+    }
+
+    /// Gets a reference to the current shell
+    fn shell(&self) -> &Shell {
+        self.shell
+            .as_ref()
+            .expect("Shell must be initialized: invariant violated")
+    }
+}
+
+/// Creates a collection start event for the cgroup with the given id (its
+/// absolute path in the cgroup hierarchy), optionally grouping it by the
+/// pattern it matched
+fn make_start_event(
+    id: &str,
+    pattern: &str,
+    group: bool,
+    version: CgroupVersion,
+) -> Result<CollectionEvent, Error> {
+    let metadata = serde_yaml::to_value(&CgroupMetadata {
+        cgroup: id,
+        pattern,
+    })?;
+
+    let path = PathBuf::from(id.trim_start_matches('/'));
+    let cgroup = CgroupPath {
+        driver: CgroupDriver::infer(&path),
+        path,
+        version,
+    };
+    let method = CollectionMethod::for_cgroup(cgroup);
+
+    Ok(CollectionEvent::Start {
+        method,
+        target: CollectionTarget {
+            provider:  PROVIDER_TYPE,
+            metadata:  Some(metadata),
+            name:      id.to_owned(),
+            poll_time: util::nano_ts(),
+            id:        id.to_owned(),
+            group:     match group {
+                true => Some(pattern.to_owned()),
+                false => None,
+            },
+            file_name: None,
+        },
+    })
+}
@@ -1,24 +1,66 @@
-use crate::cli::RunCommand;
-use crate::polling::providers::{InitializationError, Provider};
+use crate::cli::{ParseFailure, RunCommand};
+use crate::polling::filter::{self, FilterSubject, TargetFilter};
+use crate::polling::providers::{DockerOptions, InitializationError, Provider};
+use crate::polling::redact::{self, RedactRule};
 use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
 use crate::shell::Shell;
 use crate::util::{self, CgroupManager, CgroupPath, CgroupSlices, GetCgroupError, ItemPool};
 use anyhow::Error;
+use serde::Serialize;
 use shiplift::builder::ContainerListOptions;
-use shiplift::rep::Container;
-use std::collections::BTreeMap;
+use shiplift::rep::{Container, ContainerDetails, Mount};
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
+use tokio::task::{self, LocalSet};
 
 const PROVIDER_TYPE: &str = "docker";
 
+/// Maximum number of containers that are inspected at the same time
+const INSPECT_CONCURRENCY: usize = 32;
+
+/// State of containers that are paused, as reported by the Docker API
+const PAUSED_STATE: &str = "paused";
+
 pub struct Docker {
     container_id_pool: ItemPool<String>,
     cgroup_manager:    CgroupManager,
     client:            shiplift::Docker,
     shell:             Option<Arc<Shell>>,
     runtime:           Runtime,
+    include:           Vec<TargetFilter>,
+    exclude:           Vec<TargetFilter>,
+    group_by:          Option<String>,
+    name_by_task:      bool,
+    pause_policy:      PausePolicy,
+    redact_rules:      Vec<RedactRule>,
+}
+
+/// Whether paused containers are collected, controllable via a CLI flag
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PausePolicy {
+    /// Paused containers are collected like running containers
+    Collect,
+    /// Collection of containers is stopped while they are paused, and started
+    /// again (in a new log file) once they are unpaused
+    Stop,
+}
+
+impl FromStr for PausePolicy {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "collect" => Ok(Self::Collect),
+            "stop" => Ok(Self::Stop),
+            _ => Err(ParseFailure::new(
+                String::from("pause policy"),
+                s.to_owned(),
+            )),
+        }
+    }
 }
 
 /// Possible errors that can occur during Docker provider initialization
@@ -46,6 +88,105 @@ impl From<DockerInitError> for InitializationError {
     }
 }
 
+/// Curated subset of a container's inspect output, which includes details
+/// that are missing from its entry in the container list (such as its
+/// resource limits). Added to the container's metadata as `Inspect`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectInfo<'a> {
+    image:         &'a str,
+    /// Id (digest) of the image that the container was created from
+    image_id:      &'a str,
+    entrypoint:    &'a Option<Vec<String>>,
+    cmd:           &'a Option<Vec<String>>,
+    restart_count: u64,
+    port_bindings: &'a Option<HashMap<String, Vec<HashMap<String, String>>>>,
+    mounts:        &'a [Mount],
+    resources:     ResourceLimits<'a>,
+}
+
+/// Resource limits from the container's host config
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResourceLimits<'a> {
+    cpu_shares:  Option<u64>,
+    cpuset_cpus: &'a Option<String>,
+    memory:      Option<u64>,
+    memory_swap: Option<i64>,
+}
+
+impl<'a> InspectInfo<'a> {
+    fn new(details: &'a ContainerDetails) -> Self {
+        let host_config = &details.host_config;
+        Self {
+            image:         &details.config.image,
+            image_id:      &details.image,
+            entrypoint:    &details.config.entrypoint,
+            cmd:           &details.config.cmd,
+            restart_count: details.restart_count,
+            port_bindings: &host_config.port_bindings,
+            mounts:        &details.mounts,
+            resources:     ResourceLimits {
+                cpu_shares:  host_config.cpu_shares,
+                cpuset_cpus: &host_config.cpuset_cpus,
+                memory:      host_config.memory,
+                memory_swap: host_config.memory_swap,
+            },
+        }
+    }
+}
+
+/// Labels that Docker sets on the containers of Swarm service tasks
+const SWARM_SERVICE_NAME_LABEL: &str = "com.docker.swarm.service.name";
+const SWARM_SERVICE_ID_LABEL: &str = "com.docker.swarm.service.id";
+const SWARM_TASK_NAME_LABEL: &str = "com.docker.swarm.task.name";
+const SWARM_TASK_ID_LABEL: &str = "com.docker.swarm.task.id";
+const SWARM_NODE_ID_LABEL: &str = "com.docker.swarm.node.id";
+
+/// Identity of the Swarm service task that a container belongs to, from its
+/// labels. Added to the container's metadata as top-level `Swarm*` fields, so
+/// that records can be aggregated per service without parsing labels.
+#[derive(Debug, Serialize)]
+struct SwarmInfo<'a> {
+    #[serde(rename = "SwarmServiceName")]
+    service_name: &'a str,
+    #[serde(rename = "SwarmServiceId", skip_serializing_if = "Option::is_none")]
+    service_id:   Option<&'a str>,
+    /// Name of the task (`<service>.<slot>.<task id>` for replicated services,
+    /// or `<service>.<node id>.<task id>` for global services)
+    #[serde(rename = "SwarmTaskName", skip_serializing_if = "Option::is_none")]
+    task_name:    Option<&'a str>,
+    #[serde(rename = "SwarmTaskId", skip_serializing_if = "Option::is_none")]
+    task_id:      Option<&'a str>,
+    #[serde(rename = "SwarmNodeId", skip_serializing_if = "Option::is_none")]
+    node_id:      Option<&'a str>,
+}
+
+impl<'a> SwarmInfo<'a> {
+    /// Gets the Swarm identity of the container, if it belongs to a service
+    fn new(container: &'a Container) -> Option<Self> {
+        let label = |key: &str| container.labels.get(key).map(String::as_str);
+        Some(Self {
+            service_name: label(SWARM_SERVICE_NAME_LABEL)?,
+            service_id:   label(SWARM_SERVICE_ID_LABEL),
+            task_name:    label(SWARM_TASK_NAME_LABEL),
+            task_id:      label(SWARM_TASK_ID_LABEL),
+            node_id:      label(SWARM_NODE_ID_LABEL),
+        })
+    }
+
+    /// Gets the name to create the container's log files with, after its
+    /// service and task (using the task name, which already includes the
+    /// service name), if its task is known
+    fn file_name(&self) -> Option<String> {
+        match (self.task_name, self.task_id) {
+            (Some(task_name), _) => Some(task_name.to_owned()),
+            (None, Some(task_id)) => Some(format!("{}.{}", self.service_name, task_id)),
+            (None, None) => None,
+        }
+    }
+}
+
 /// Possible error that can occur during Docker container collection target
 /// initialization
 #[derive(Debug)]
@@ -58,12 +199,21 @@ enum StartCollectionError {
 impl Provider for Docker {
     fn initialize(
         &mut self,
-        _opts: &RunCommand,
+        opts: &RunCommand,
         shell: Arc<Shell>,
     ) -> Result<(), InitializationError> {
         self.shell = Some(Arc::clone(&shell));
         self.shell().status("Initializing", "Docker API provider");
 
+        let inner_opts: DockerOptions = opts.provider.clone().into_inner_docker();
+        self.include = inner_opts.include;
+        self.exclude = inner_opts.exclude;
+        self.group_by = inner_opts.group_by;
+        self.name_by_task = inner_opts.name_by_task;
+        self.pause_policy = inner_opts.paused;
+        self.redact_rules =
+            redact::resolve_rules(&inner_opts.redact, !inner_opts.no_default_redactions);
+
         match self.try_init() {
             Ok(_) => Ok(()),
             Err(init_err) => Err(init_err.into()),
@@ -79,6 +229,7 @@ impl Provider for Docker {
         let original_num = containers.len();
         let to_collect: BTreeMap<String, Container> = containers
             .into_iter()
+            .filter(|c| self.should_collect(c))
             .map(|c| (c.id.clone(), c))
             .collect::<BTreeMap<_, _>>();
 
@@ -92,58 +243,35 @@ impl Provider for Docker {
         events.extend(removed.into_iter().map(CollectionEvent::Stop));
 
         // Add all added Ids as Start events
-        let start_events = added
-            .into_iter()
+        let added = added
+            .iter()
             .filter_map(|id| {
                 // It shouldn't be possible to have an Id that doesn't exist in the map, but
                 // check anyways
-                let container = match to_collect.get(&id) {
-                    Some(container) => container,
-                    None => {
-                        self.shell().error(format!(
-                            "Processed Id from ItemPool added result that was not in fetched \
-                             container list. This is a bug!\nId: {}",
-                            id
-                        ));
-                        return None;
-                    },
-                };
-
-                match self.make_start_event(container) {
-                    Ok(start) => Some(start),
-                    Err(error) => {
-                        let container_display = display(container);
-                        match error {
-                            StartCollectionError::CgroupNotFound(path) => {
-                                self.shell().warn(format!(
-                                    "Could not start collection for container {}: cgroup path \
-                                     '{:?}' does not exist on system",
-                                    container_display, path,
-                                ));
-                            },
-                            StartCollectionError::MetadataSerializationError(cause) => {
-                                self.shell().warn(format!(
-                                    "Could not start collection for container {}: failed to \
-                                     serialize container metadata: {}",
-                                    container_display, cause
-                                ));
-                            },
-                            StartCollectionError::CgroupVersionDetectionFailed => {
-                                self.shell().warn(format!(
-                                    "Could not start collection for container {}: failed to \
-                                     detect the currently running cgroup version (are cgroups \
-                                     mounted in /sys/fs/cgroup?)",
-                                    container_display
-                                ));
-                            },
-                        }
-
-                        // Ignore container and continue initializing the rest
-                        None
-                    },
+                let container = to_collect.get(id);
+                if container.is_none() {
+                    self.shell().error(format!(
+                        "Processed Id from ItemPool added result that was not in fetched \
+                         container list. This is a bug!\nId: {}",
+                        id
+                    ));
                 }
+                container
             })
             .collect::<Vec<_>>();
+        let start_events = self.make_start_events(&added);
+        // Containers whose collection couldn't be started (such as when their
+        // cgroup doesn't exist yet because they are still starting) are only
+        // kept in the pool once a start event was made for them, so that they
+        // are tried again on the next poll
+        for container in &added {
+            let started = start_events.iter().any(
+                |event| matches!(event, CollectionEvent::Start { target, .. } if target.id == container.id),
+            );
+            if !started {
+                self.container_id_pool.remove(&container.id);
+            }
+        }
         let processed_num = start_events.len();
         events.extend(start_events);
 
@@ -184,7 +312,28 @@ impl Docker {
             client: shiplift::Docker::new(),
             shell: None,
             runtime,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            group_by: None,
+            name_by_task: false,
+            pause_policy: PausePolicy::Collect,
+            redact_rules: Vec::new(),
+        }
+    }
+
+    /// Determines whether the container passes the configured include/exclude
+    /// filters, and isn't paused if paused containers aren't collected
+    fn should_collect(&self, container: &Container) -> bool {
+        if self.pause_policy == PausePolicy::Stop && container.state == PAUSED_STATE {
+            return false;
         }
+
+        let subject = FilterSubject {
+            names:  &container.names,
+            image:  &container.image,
+            labels: &container.labels,
+        };
+        filter::should_collect(&subject, &self.include, &self.exclude)
     }
 
     /// Attempts to initialize the Docker provider, failing if the connection
@@ -205,23 +354,50 @@ impl Docker {
         Ok(())
     }
 
-    /// Converts a container to a collection start event, preparing all
-    /// serialization/cgroup checks needed
+    /// Converts newly added containers to collection start events (in the
+    /// same order), printing a warning for each container whose collection
+    /// couldn't be started. The containers are inspected concurrently, and
+    /// their cgroups are resolved and their metadata is serialized in parallel,
+    /// so that polling stays fast on nodes with many containers.
+    fn make_start_events(&mut self, containers: &[&Container]) -> Vec<CollectionEvent> {
+        let details = self.inspect_all(containers);
+        let methods = self.get_collection_methods(containers);
+        let redact_rules = &self.redact_rules;
+        let metadata = util::parallel_map(&details, |(container, details)| {
+            serialize_metadata(container, details.as_ref()).map(|mut metadata| {
+                redact::sanitize(&mut metadata, redact_rules);
+                metadata
+            })
+        });
+
+        containers
+            .iter()
+            .zip(methods.into_iter().zip(metadata))
+            .filter_map(|(container, (method, metadata))| {
+                let metadata = metadata
+                    .map_err(|err| StartCollectionError::MetadataSerializationError(err.into()));
+                match method.and_then(|method| Ok((method, metadata?))) {
+                    Ok((method, metadata)) => {
+                        Some(self.make_start_event(container, method, metadata))
+                    },
+                    Err(error) => {
+                        self.warn_start_failed(container, error);
+                        // Ignore container and continue initializing the rest
+                        None
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Creates a collection start event for a container
     fn make_start_event(
-        &mut self,
+        &self,
         container: &Container,
-    ) -> Result<CollectionEvent, StartCollectionError> {
-        let method = self.get_collection_method(container)?;
-        let metadata = match serde_yaml::to_value(container) {
-            Ok(metadata) => metadata,
-            Err(err) => {
-                return Err(StartCollectionError::MetadataSerializationError(
-                    Error::from(err),
-                ));
-            },
-        };
-
-        Ok(CollectionEvent::Start {
+        method: CollectionMethod,
+        metadata: serde_yaml::Value,
+    ) -> CollectionEvent {
+        CollectionEvent::Start {
             method,
             target: CollectionTarget {
                 provider:  PROVIDER_TYPE,
@@ -229,33 +405,130 @@ impl Docker {
                 name:      container.names.get(0).unwrap_or(&container.id).clone(),
                 poll_time: util::nano_ts(),
                 id:        container.id.clone(),
+                group:     self
+                    .group_by
+                    .as_ref()
+                    .and_then(|key| container.labels.get(key).cloned()),
+                file_name: match self.name_by_task {
+                    true => SwarmInfo::new(container).and_then(|swarm| swarm.file_name()),
+                    false => None,
+                },
             },
-        })
+        }
     }
 
-    /// Gets the collection method struct for the container, resolving the
-    /// proper collection method
-    fn get_collection_method(
-        &mut self,
-        container: &Container,
-    ) -> Result<CollectionMethod, StartCollectionError> {
-        // Only one type of CollectionMethod currently
-        match self.get_cgroup(container) {
-            Ok(cgroup) => match cgroup.version {
-                util::CgroupVersion::V1 => Ok(CollectionMethod::LinuxCgroupV1(cgroup)),
-                util::CgroupVersion::V2 => Ok(CollectionMethod::LinuxCgroupV2(cgroup)),
+    /// Prints a warning about a container whose collection couldn't be started
+    fn warn_start_failed(&self, container: &Container, error: StartCollectionError) {
+        let container_display = display(container);
+        match error {
+            StartCollectionError::CgroupNotFound(path) => {
+                self.shell().warn(format!(
+                    "Could not start collection for container {}: cgroup path '{:?}' does not \
+                     exist on system",
+                    container_display, path,
+                ));
             },
-            Err(GetCgroupError::VersionDetectionFailed) => {
-                Err(StartCollectionError::CgroupVersionDetectionFailed)
+            StartCollectionError::MetadataSerializationError(cause) => {
+                self.shell().warn(format!(
+                    "Could not start collection for container {}: failed to serialize container \
+                     metadata: {}",
+                    container_display, cause
+                ));
+            },
+            StartCollectionError::CgroupVersionDetectionFailed => {
+                self.shell().warn(format!(
+                    "Could not start collection for container {}: failed to detect the currently \
+                     running cgroup version (are cgroups mounted in /sys/fs/cgroup or at \
+                     --cgroup-root?)",
+                    container_display
+                ));
             },
-            Err(GetCgroupError::NotFound(path)) => Err(StartCollectionError::CgroupNotFound(path)),
-            Err(GetCgroupError::CgroupV1NotEnabled) => unreachable!(),
         }
     }
 
-    /// Gets the group path for the given container, printing out a
-    /// message upon the first successful cgroup resolution
-    fn get_cgroup(&mut self, c: &Container) -> Result<CgroupPath, GetCgroupError> {
+    /// Inspects the containers using the Docker API, sending up to
+    /// `INSPECT_CONCURRENCY` requests at a time. Containers that couldn't be
+    /// inspected (such as if they stopped since they were listed) are
+    /// returned without details after printing a warning.
+    fn inspect_all<'a>(
+        &self,
+        containers: &[&'a Container],
+    ) -> Vec<(&'a Container, Option<ContainerDetails>)> {
+        let mut results = Vec::with_capacity(containers.len());
+        for batch in containers.chunks(INSPECT_CONCURRENCY) {
+            let local = LocalSet::new();
+            let details = local.block_on(&self.runtime, async {
+                let handles = batch
+                    .iter()
+                    .map(|container| {
+                        let client = self.client.clone();
+                        let id = container.id.clone();
+                        task::spawn_local(
+                            async move { client.containers().get(&id).inspect().await },
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut details = Vec::with_capacity(handles.len());
+                for handle in handles {
+                    details.push(match handle.await {
+                        Ok(result) => result.map_err(Error::from),
+                        Err(err) => Err(Error::from(err)),
+                    });
+                }
+                details
+            });
+
+            for (container, details) in batch.iter().zip(details) {
+                match details {
+                    Ok(details) => results.push((*container, Some(details))),
+                    Err(err) => {
+                        // The container may have stopped since it was listed, so
+                        // collect it with only its list entry as metadata
+                        self.shell().warn(format!(
+                            "Could not inspect container {}, omitting its inspect metadata: {}",
+                            display(container),
+                            err
+                        ));
+                        results.push((*container, None));
+                    },
+                }
+            }
+        }
+
+        results
+    }
+
+    /// Gets the collection method structs for the containers (in the same
+    /// order), resolving the proper collection method
+    fn get_collection_methods(
+        &mut self,
+        containers: &[&Container],
+    ) -> Vec<Result<CollectionMethod, StartCollectionError>> {
+        // Only one type of CollectionMethod currently
+        self.get_cgroups(containers)
+            .into_iter()
+            .map(|result| match result {
+                Ok(cgroup) => Ok(CollectionMethod::for_cgroup(cgroup)),
+                Err(GetCgroupError::VersionDetectionFailed) => {
+                    Err(StartCollectionError::CgroupVersionDetectionFailed)
+                },
+                Err(GetCgroupError::NotFound(path)) => {
+                    Err(StartCollectionError::CgroupNotFound(path))
+                },
+                Err(GetCgroupError::CgroupV1NotEnabled | GetCgroupError::DelegationRequiresV2) => {
+                    unreachable!()
+                },
+            })
+            .collect()
+    }
+
+    /// Gets the group paths for the given containers (in the same order),
+    /// printing out a message upon the first successful cgroup resolution
+    fn get_cgroups(
+        &mut self,
+        containers: &[&Container],
+    ) -> Vec<Result<CgroupPath, GetCgroupError>> {
         // Determine if the manager had a resolved version or driver beforehand
         let had_driver = self.cgroup_manager.driver().is_some();
         let had_version = self.cgroup_manager.version().is_some();
@@ -266,12 +539,27 @@ impl Docker {
         // According to https://docs.docker.com/engine/reference/commandline/dockerd/#default-cgroup-parent ,
         // "[container cgroups are mounted at] `/docker` for fs cgroup driver and
         // `system.slice` for systemd cgroup driver."
-        let result = self.cgroup_manager.get_cgroup(CgroupSlices {
-            systemd:  &["system.slice", &format!("docker-{}.scope", &c.id)],
-            cgroupfs: &["docker", &c.id],
-        });
+        let paths = containers
+            .iter()
+            .map(|c| {
+                (
+                    ["system.slice".to_owned(), format!("docker-{}.scope", &c.id)],
+                    ["docker", c.id.as_str()],
+                )
+            })
+            .collect::<Vec<_>>();
+        let slices = paths
+            .iter()
+            .map(|(systemd, cgroupfs)| CgroupSlices { cgroupfs, systemd })
+            .collect::<Vec<_>>();
+        let results = self.cgroup_manager.get_cgroups(&slices);
 
-        if !had_driver {
+        if let Some((previous, driver)) = self.cgroup_manager.take_driver_change() {
+            self.shell().warn(format!(
+                "cgroup driver changed from {} to {} after repeated cgroup resolution failures",
+                previous, driver
+            ));
+        } else if !had_driver {
             if let Some(driver) = self.cgroup_manager.driver() {
                 self.shell()
                     .info(format!("Identified {} as cgroup driver", driver));
@@ -285,7 +573,7 @@ impl Docker {
             }
         }
 
-        result
+        results
     }
 
     /// Gets a reference to the current shell
@@ -296,6 +584,31 @@ impl Docker {
     }
 }
 
+/// Serializes the container's list entry, along with its Swarm identity (if it
+/// belongs to a service) and the curated subset of its inspect output (if it
+/// could be inspected) under `Inspect`
+fn serialize_metadata(
+    container: &Container,
+    details: Option<&ContainerDetails>,
+) -> Result<serde_yaml::Value, serde_yaml::Error> {
+    let mut metadata = serde_yaml::to_value(container)?;
+    if let serde_yaml::Value::Mapping(mapping) = &mut metadata {
+        if let Some(serde_yaml::Value::Mapping(swarm)) = SwarmInfo::new(container)
+            .map(serde_yaml::to_value)
+            .transpose()?
+        {
+            mapping.extend(swarm);
+        }
+        if let Some(details) = details {
+            mapping.insert(
+                serde_yaml::Value::from("Inspect"),
+                serde_yaml::to_value(InspectInfo::new(details))?,
+            );
+        }
+    }
+    Ok(metadata)
+}
+
 /// Gets a human-readable representation of the container, attempting to use the
 /// name before using the Id as a fallback
 fn display(container: &Container) -> &str { container.names.get(0).unwrap_or(&container.id) }
@@ -0,0 +1,301 @@
+use crate::cli::RunCommand;
+use crate::polling::providers::{ExecOptions, InitializationError, Provider};
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
+use crate::shell::Shell;
+use crate::util::{self, CgroupDriver, CgroupPath, CgroupVersion};
+use anyhow::Error;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::PathBuf;
+use std::process::{self, Child, Command, ExitStatus};
+use std::sync::Arc;
+
+const PROVIDER_TYPE: &str = "exec";
+
+/// Provider that launches a command in a fresh cgroup and collects it until
+/// it exits, after which rAdvisor exits with the command's status code
+pub struct Exec {
+    command:   Vec<String>,
+    /// Cgroup the command runs in, relative to the cgroup root
+    cgroup:    PathBuf,
+    /// Absolute paths of the created cgroup directories, removed on drop
+    created:   Vec<PathBuf>,
+    version:   Option<CgroupVersion>,
+    child:     Option<Child>,
+    started:   bool,
+    exit_code: Option<i32>,
+    shell:     Option<Arc<Shell>>,
+}
+
+/// Possible errors that can occur during exec provider initialization
+#[derive(Debug)]
+enum ExecInitError {
+    InvalidCgroupMount,
+    VersionDetectionFailed,
+    CgroupCreationFailed(io::Error),
+    SpawnFailed(io::Error),
+}
+
+impl From<ExecInitError> for InitializationError {
+    fn from(other: ExecInitError) -> Self {
+        match other {
+            ExecInitError::InvalidCgroupMount => Self {
+                original:   None,
+                suggestion: String::from(util::INVALID_CGROUP_MOUNT_MESSAGE),
+            },
+            ExecInitError::VersionDetectionFailed => Self {
+                original:   None,
+                suggestion: String::from(
+                    "Could not detect the currently running cgroup version. Are cgroups mounted \
+                     in /sys/fs/cgroup (or at --cgroup-root)?",
+                ),
+            },
+            ExecInitError::CgroupCreationFailed(error) => Self {
+                original:   Some(error.into()),
+                suggestion: String::from(
+                    "Could not create a cgroup for the command. Are you running rAdvisor as root?",
+                ),
+            },
+            ExecInitError::SpawnFailed(error) => Self {
+                original:   Some(error.into()),
+                suggestion: String::from(
+                    "Could not launch the command. Make sure it exists and is executable.",
+                ),
+            },
+        }
+    }
+}
+
+/// Metadata included in the log file header for the launched command
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ExecMetadata<'a> {
+    command: &'a [String],
+    pid:     u32,
+}
+
+impl Provider for Exec {
+    fn initialize(
+        &mut self,
+        opts: &RunCommand,
+        shell: Arc<Shell>,
+    ) -> Result<(), InitializationError> {
+        self.shell = Some(Arc::clone(&shell));
+        self.shell().status("Initializing", "exec provider");
+
+        let inner_opts: ExecOptions = opts.provider.clone().into_inner_exec();
+        self.command = inner_opts.command;
+
+        match self.try_init() {
+            Ok(()) => Ok(()),
+            Err(init_err) => Err(init_err.into()),
+        }
+    }
+
+    fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error> {
+        let child = self
+            .child
+            .as_mut()
+            .expect("Child must be spawned: invariant violated");
+
+        if let Some(status) = child.try_wait()? {
+            let code = exit_code(status);
+            self.shell().status(
+                "Finished",
+                format!("command exited with status code {}", code),
+            );
+            self.exit_code = Some(code);
+            return Ok(Vec::new());
+        }
+
+        if self.started {
+            return Ok(Vec::new());
+        }
+
+        self.started = true;
+        Ok(vec![self.make_start_event()?])
+    }
+
+    fn finished(&mut self) -> Option<i32> { self.exit_code }
+}
+
+impl Default for Exec {
+    fn default() -> Self { Self::new() }
+}
+
+impl Exec {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            command:   Vec::new(),
+            cgroup:    PathBuf::from(format!("radvisor-exec-{}", process::id())),
+            created:   Vec::new(),
+            version:   None,
+            child:     None,
+            started:   false,
+            exit_code: None,
+            shell:     None,
+        }
+    }
+
+    /// Attempts to initialize the exec provider, creating the cgroup and then
+    /// launching the command inside of it
+    fn try_init(&mut self) -> Result<(), ExecInitError> {
+        if !util::cgroups_mounted_properly() {
+            return Err(ExecInitError::InvalidCgroupMount);
+        }
+
+        let version = CgroupVersion::try_resolve().ok_or(ExecInitError::VersionDetectionFailed)?;
+        self.version = Some(version);
+
+        let procs_files = match self.create_cgroups(version) {
+            Ok(procs_files) => procs_files,
+            Err(err) => {
+                self.remove_cgroups();
+                return Err(ExecInitError::CgroupCreationFailed(err));
+            },
+        };
+        let procs_fds: Vec<RawFd> = procs_files.iter().map(AsRawFd::as_raw_fd).collect();
+
+        let mut command = Command::new(&self.command[0]);
+        command.args(&self.command[1..]);
+        unsafe {
+            // Move the child into the cgroup before it executes the command, so
+            // that none of its resource utilization escapes collection.
+            // Writing 0 to cgroup.procs moves the writing process.
+            command.pre_exec(move || {
+                for fd in &procs_fds {
+                    if libc::write(*fd, b"0".as_ptr().cast(), 1) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        self.shell()
+            .status("Launching", format!("'{}'", self.command.join(" ")));
+        match command.spawn() {
+            Ok(child) => {
+                self.child = Some(child);
+                Ok(())
+            },
+            Err(err) => {
+                // rAdvisor exits right after failed initialization,
+                // so clean up the cgroups now
+                self.remove_cgroups();
+                Err(ExecInitError::SpawnFailed(err))
+            },
+        }
+    }
+
+    /// Creates the cgroup for the command (in each mounted subsystem for cgroup
+    /// v1), returning the opened `cgroup.procs` files for each
+    fn create_cgroups(&mut self, version: CgroupVersion) -> Result<Vec<File>, io::Error> {
+        let root = util::cgroup_root();
+        let parents: Vec<PathBuf> = match version {
+            CgroupVersion::V1 => util::CGROUP_V1_SUBSYSTEMS
+                .iter()
+                .map(|subsystem| root.join(subsystem))
+                .filter(|path| path.exists())
+                .collect(),
+            CgroupVersion::V2 => vec![root.to_path_buf()],
+        };
+
+        let mut procs_files = Vec::with_capacity(parents.len());
+        for parent in parents {
+            let path = parent.join(&self.cgroup);
+            fs::create_dir(&path)?;
+            self.created.push(path.clone());
+
+            // New cpuset cgroups start out without any CPUs or memory nodes,
+            // which prevents processes from joining them
+            if parent.ends_with("cpuset") {
+                for file in &["cpuset.cpus", "cpuset.mems"] {
+                    fs::write(path.join(file), fs::read(parent.join(file))?)?;
+                }
+            }
+
+            let procs_file = OpenOptions::new()
+                .write(true)
+                .open(path.join("cgroup.procs"))?;
+            procs_files.push(procs_file);
+        }
+
+        Ok(procs_files)
+    }
+
+    /// Creates the collection start event for the launched command
+    fn make_start_event(&self) -> Result<CollectionEvent, Error> {
+        let version = self
+            .version
+            .expect("Cgroup version must be initialized: invariant violated");
+        let pid = self.child.as_ref().map_or(0, Child::id);
+        let metadata = serde_yaml::to_value(&ExecMetadata {
+            command: &self.command,
+            pid,
+        })?;
+
+        let cgroup = CgroupPath {
+            path: self.cgroup.clone(),
+            driver: CgroupDriver::Cgroupfs,
+            version,
+        };
+        let method = CollectionMethod::for_cgroup(cgroup);
+
+        Ok(CollectionEvent::Start {
+            method,
+            target: CollectionTarget {
+                provider:  PROVIDER_TYPE,
+                metadata:  Some(metadata),
+                name:      self.command.join(" "),
+                poll_time: util::nano_ts(),
+                id:        self.cgroup.to_string_lossy().into_owned(),
+                group:     None,
+                file_name: None,
+            },
+        })
+    }
+
+    /// Gets a reference to the current shell
+    fn shell(&self) -> &Shell {
+        self.shell
+            .as_ref()
+            .expect("Shell must be initialized: invariant violated")
+    }
+}
+
+impl Drop for Exec {
+    fn drop(&mut self) { self.remove_cgroups(); }
+}
+
+impl Exec {
+    /// Removes all created cgroups. Cgroups can only be removed once all
+    /// processes in them have exited.
+    fn remove_cgroups(&mut self) {
+        for path in self.created.drain(..) {
+            if let Err(err) = fs::remove_dir(&path) {
+                if let Some(shell) = &self.shell {
+                    shell.warn(format!(
+                        "Could not remove cgroup {}: {}",
+                        path.display(),
+                        err
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Converts the exit status of the command to a status code, following the
+/// shell convention of 128 + the signal number if it was killed by a signal
+fn exit_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
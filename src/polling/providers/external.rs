@@ -0,0 +1,269 @@
+use crate::cli::RunCommand;
+use crate::polling::providers::{ExternalOptions, InitializationError, Provider};
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
+use crate::shell::Shell;
+use crate::util::{self, CgroupDriver, CgroupPath, CgroupVersion, ItemPool};
+use anyhow::Error;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const PROVIDER_TYPE: &str = "external";
+
+/// Provider that gets the current targets from a user-supplied plugin
+/// executable, which is run on each poll and prints the targets to stdout as
+/// a JSON array (see `PluginTarget`). Lets custom schedulers and
+/// orchestrators be integrated without modifying rAdvisor.
+pub struct External {
+    id_pool: ItemPool<String>,
+    command: Vec<String>,
+    timeout: Duration,
+    version: Option<CgroupVersion>,
+    shell:   Option<Arc<Shell>>,
+}
+
+/// Possible errors that can occur during external provider initialization
+#[derive(Debug)]
+enum ExternalInitError {
+    InvalidCgroupMount,
+    VersionDetectionFailed,
+    PluginFailed(PluginError),
+}
+
+impl From<ExternalInitError> for InitializationError {
+    fn from(other: ExternalInitError) -> Self {
+        match other {
+            ExternalInitError::InvalidCgroupMount => Self {
+                original:   None,
+                suggestion: String::from(util::INVALID_CGROUP_MOUNT_MESSAGE),
+            },
+            ExternalInitError::VersionDetectionFailed => Self {
+                original:   None,
+                suggestion: String::from(
+                    "Could not detect the currently running cgroup version. Are cgroups mounted \
+                     in /sys/fs/cgroup (or at --cgroup-root)?",
+                ),
+            },
+            ExternalInitError::PluginFailed(error) => Self {
+                original:   Some(error.into()),
+                suggestion: String::from(
+                    "Could not get the list of targets from the plugin. It should print a JSON \
+                     array of targets (each with an `id` and a `cgroup`) to stdout and exit \
+                     successfully.",
+                ),
+            },
+        }
+    }
+}
+
+/// Error that can occur when running the plugin
+#[derive(Debug, thiserror::Error)]
+enum PluginError {
+    #[error("could not run plugin: {0}")]
+    Spawn(#[source] io::Error),
+    #[error("plugin did not finish within {}", humantime::Duration::from(*.0))]
+    Timeout(Duration),
+    #[error("plugin exited with {0}")]
+    Failed(std::process::ExitStatus),
+    #[error("could not parse plugin output: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Single target in the JSON array printed by the plugin
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct PluginTarget {
+    /// Unique id of the target, used to detect when it starts and stops
+    id:       String,
+    /// Cgroup of the target, relative to the cgroup root (such as
+    /// `slurm/uid_1000/job_42`)
+    cgroup:   String,
+    /// Human-readable name of the target (defaults to its id)
+    #[serde(default)]
+    name:     Option<String>,
+    /// Name of the group of targets that share a log file, if any
+    #[serde(default)]
+    group:    Option<String>,
+    /// Arbitrary metadata included in the log file header
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+}
+
+impl Provider for External {
+    fn initialize(
+        &mut self,
+        opts: &RunCommand,
+        shell: Arc<Shell>,
+    ) -> Result<(), InitializationError> {
+        self.shell = Some(Arc::clone(&shell));
+        self.shell().status("Initializing", "external provider");
+
+        let inner_opts: ExternalOptions = opts.provider.clone().into_inner_external();
+        self.command = inner_opts.plugin;
+        self.timeout = inner_opts.plugin_timeout;
+
+        match self.try_init() {
+            Ok(()) => Ok(()),
+            Err(init_err) => Err(init_err.into()),
+        }
+    }
+
+    fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error> {
+        let version = self
+            .version
+            .expect("Cgroup version must be initialized: invariant violated");
+
+        let to_collect: BTreeMap<String, PluginTarget> = run_plugin(&self.command, self.timeout)?
+            .into_iter()
+            .map(|target| (target.id.clone(), target))
+            .collect();
+
+        let ids = to_collect.keys().map(String::clone);
+        let (added, removed) = self.id_pool.update(ids);
+
+        let mut events: Vec<CollectionEvent> = Vec::with_capacity(added.len() + removed.len());
+        let (added_len, removed_len) = (added.len(), removed.len());
+        events.extend(removed.into_iter().map(CollectionEvent::Stop));
+        for target in added.iter().filter_map(|id| to_collect.get(id)) {
+            match make_start_event(target, version) {
+                Ok(start) => events.push(start),
+                Err(err) => self.shell().warn(format!(
+                    "Could not start collection for target {}: failed to serialize metadata: {}",
+                    target.id, err
+                )),
+            }
+        }
+
+        if added_len != 0 || removed_len != 0 {
+            self.shell().verbose(|sh| {
+                sh.info(format!(
+                    "Received {} (+{}, -{}) targets from the plugin",
+                    to_collect.len(),
+                    added_len,
+                    removed_len
+                ));
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+impl Default for External {
+    fn default() -> Self { Self::new() }
+}
+
+impl External {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            id_pool: ItemPool::new(),
+            command: Vec::new(),
+            timeout: Duration::default(),
+            version: None,
+            shell:   None,
+        }
+    }
+
+    /// Attempts to initialize the external provider, failing if the needed
+    /// cgroups aren't mounted properly or if the plugin can't be run
+    fn try_init(&mut self) -> Result<(), ExternalInitError> {
+        if !util::cgroups_mounted_properly() {
+            return Err(ExternalInitError::InvalidCgroupMount);
+        }
+
+        let version =
+            CgroupVersion::try_resolve().ok_or(ExternalInitError::VersionDetectionFailed)?;
+        self.shell()
+            .info(format!("Identified {} as cgroup version", version));
+        self.version = Some(version);
+
+        // Run the plugin once to make sure that it works
+        run_plugin(&self.command, self.timeout).map_err(ExternalInitError::PluginFailed)?;
+
+        Ok(())
+    }
+
+    /// Gets a reference to the current shell
+    fn shell(&self) -> &Shell {
+        self.shell
+            .as_ref()
+            .expect("Shell must be initialized: invariant violated")
+    }
+}
+
+/// Runs the plugin and parses the targets that it prints to stdout, killing
+/// it if it doesn't finish within the timeout
+fn run_plugin(command: &[String], timeout: Duration) -> Result<Vec<PluginTarget>, PluginError> {
+    let (program, args) = command
+        .split_first()
+        .expect("Plugin command must be given: invariant violated");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(PluginError::Spawn)?;
+
+    // Read the output on a separate thread so that a plugin that never
+    // closes stdout can't block polling
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (output_tx, output_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let result = stdout.read_to_end(&mut output).map(|_| output);
+        let _ = output_tx.send(result);
+    });
+
+    let output = match output_rx.recv_timeout(timeout) {
+        Ok(result) => result.map_err(PluginError::Spawn)?,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(PluginError::Timeout(timeout));
+        },
+    };
+    let status = child.wait().map_err(PluginError::Spawn)?;
+    if !status.success() {
+        return Err(PluginError::Failed(status));
+    }
+
+    Ok(serde_json::from_slice(&output)?)
+}
+
+/// Creates a collection start event for a target printed by the plugin
+fn make_start_event(
+    target: &PluginTarget,
+    version: CgroupVersion,
+) -> Result<CollectionEvent, Error> {
+    let metadata = match &target.metadata {
+        Some(metadata) => Some(serde_yaml::to_value(metadata)?),
+        None => None,
+    };
+
+    let path = PathBuf::from(target.cgroup.trim_start_matches('/'));
+    let cgroup = CgroupPath {
+        driver: CgroupDriver::infer(&path),
+        path,
+        version,
+    };
+    let method = CollectionMethod::for_cgroup(cgroup);
+
+    Ok(CollectionEvent::Start {
+        method,
+        target: CollectionTarget {
+            provider: PROVIDER_TYPE,
+            metadata,
+            name: target.name.clone().unwrap_or_else(|| target.id.clone()),
+            poll_time: util::nano_ts(),
+            id: target.id.clone(),
+            group: target.group.clone(),
+            file_name: None,
+        },
+    })
+}
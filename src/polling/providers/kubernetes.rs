@@ -1,23 +1,28 @@
-use crate::cli::RunCommand;
+use crate::cli::{ParseFailure, RunCommand};
 use crate::polling::providers::{InitializationError, KubernetesOptions, Provider};
 use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
 use crate::shell::Shell;
 use crate::util::{self, CgroupManager, CgroupPath, CgroupSlices, GetCgroupError, ItemPool};
 use anyhow::Error;
+use futures::stream::{Stream, StreamExt};
 use gethostname::gethostname;
-use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::api::core::v1::{Container, ContainerStatus, Node, Pod};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use kube::api::{Api, ListParams};
 use kube::client::Client;
 use kube::config;
 use kube::Resource as _;
+use kube_runtime::watcher;
 use serde::Serialize;
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use strum_macros::{EnumString, IntoStaticStr};
 use tokio::runtime::Runtime;
 
@@ -29,15 +34,116 @@ const ROOT_CGROUP: &str = "kubepods";
 
 const PROVIDER_TYPE: &str = "kubernetes";
 
+/// Pod annotation that opts a pod into (`"true"`) or out of (`"false"`)
+/// collection, overriding the scrape policy
+const SCRAPE_ANNOTATION: &str = "radvisor.io/scrape";
+
+/// Maximum amount of time to wait for the next event from the pod watch before
+/// considering all currently available events as received
+const WATCH_DRAIN_TIMEOUT: Duration = Duration::from_millis(5);
+
 pub struct Kubernetes {
     cgroup_manager: CgroupManager,
-    pod_uid_pool:   ItemPool<String>,
+    target_id_pool: ItemPool<String>,
     runtime:        Runtime,
     pod_client:     Option<Api<Pod>>,
     node_client:    Option<Api<Node>>,
     shell:          Option<Arc<Shell>>,
     hostname:       Option<String>,
     node_name:      Option<String>,
+    label_selector: Option<String>,
+    field_selector: Option<String>,
+    group_by:       Option<String>,
+    per_container:  bool,
+    scrape_policy:  ScrapePolicy,
+    watch:          Option<PodWatch>,
+}
+
+/// Whether pods without the scrape annotation are collected, controllable via
+/// a CLI flag
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScrapePolicy {
+    /// All pods are collected unless they opt out with `radvisor.io/scrape:
+    /// "false"`
+    Allow,
+    /// Only pods that opt in with `radvisor.io/scrape: "true"` are collected
+    Deny,
+}
+
+impl FromStr for ScrapePolicy {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            _ => Err(ParseFailure::new(
+                String::from("scrape policy"),
+                s.to_owned(),
+            )),
+        }
+    }
+}
+
+/// Single collection target within a pod: either the entire pod, or one of its
+/// running containers (when collecting per-container)
+#[derive(Clone, Copy)]
+struct PodTarget<'a> {
+    pod:       &'a Pod,
+    container: Option<&'a ContainerStatus>,
+}
+
+/// Stream of pod changes from the API server
+type PodStream = Pin<Box<dyn Stream<Item = watcher::Result<watcher::Event<Pod>>> + Send>>;
+
+/// State used when polling in watch mode, where pod changes are streamed from
+/// the API server instead of being re-listed on every poll
+struct PodWatch {
+    stream: PodStream,
+    /// Current set of pods, keyed by their Uid
+    pods:   BTreeMap<String, Pod>,
+    /// Whether the pod set needs to be re-built from a full list, either
+    /// because no list has happened yet or because the watch failed
+    stale:  bool,
+}
+
+impl PodWatch {
+    /// Applies a single event from the watch stream to the current pod set
+    fn apply(&mut self, event: watcher::Event<Pod>) {
+        match event {
+            watcher::Event::Applied(pod) => {
+                if let Some(uid) = pod.meta().uid.clone() {
+                    self.pods.insert(uid, pod);
+                }
+            },
+            watcher::Event::Deleted(pod) => {
+                if let Some(uid) = &pod.meta().uid {
+                    self.pods.remove(uid);
+                }
+            },
+            watcher::Event::Restarted(pods) => {
+                self.pods = pods
+                    .into_iter()
+                    .filter_map(|p| p.meta().uid.clone().map(|id| (id, p)))
+                    .collect::<BTreeMap<_, _>>();
+            },
+        }
+    }
+
+    /// Applies all events that are currently available on the watch stream,
+    /// returning the first error encountered (if any)
+    fn drain(&mut self, runtime: &Runtime) -> Option<watcher::Error> {
+        runtime.block_on(async {
+            loop {
+                match tokio::time::timeout(WATCH_DRAIN_TIMEOUT, self.stream.next()).await {
+                    // No more events are immediately available, or the stream ended
+                    Err(_) | Ok(None) => return None,
+                    Ok(Some(Ok(event))) => self.apply(event),
+                    Ok(Some(Err(err))) => return Some(err),
+                }
+            }
+        })
+    }
 }
 
 /// Possible errors that can occur during Kubernetes provider initialization
@@ -122,6 +228,7 @@ impl QualityOfService {
 enum StartCollectionError {
     MetadataSerializationError(Error),
     MissingPodUid,
+    MissingContainerId,
     FailedQosParse,
     CgroupNotFound(PathBuf),
     CgroupVersionDetectionFailed,
@@ -151,6 +258,10 @@ impl StartCollectionError {
                  shouldn't happen",
                 pod_display
             ),
+            Self::MissingContainerId => format!(
+                "Could not start collection for a container in pod {}: container has no id",
+                pod_display
+            ),
             Self::FailedQosParse => format!(
                 "Could not start collection for pod {}: could not parse quality of service class \
                  (invalid value '{}')",
@@ -162,7 +273,7 @@ impl StartCollectionError {
             ),
             Self::CgroupVersionDetectionFailed => format!(
                 "Could not start collection for pod {}: failed to detect the currently running \
-                 cgroup version (are cgroups mounted in /sys/fs/cgroup?)",
+                 cgroup version (are cgroups mounted in /sys/fs/cgroup or at --cgroup-root?)",
                 pod_display
             ),
             Self::CgroupV1NotEnabled => format!(
@@ -185,24 +296,42 @@ impl Provider for Kubernetes {
             .status("Initializing", "Kubernetes API provider");
 
         let inner_opts: KubernetesOptions = opts.provider.clone().into_inner_kubernetes();
-        match self.try_init(inner_opts.kube_config) {
-            Ok(_) => Ok(()),
-            Err(init_err) => Err(init_err.into()),
+        self.label_selector = inner_opts.label_selector;
+        self.field_selector = inner_opts.field_selector;
+        self.group_by = inner_opts.group_by;
+        self.per_container = inner_opts.per_container;
+        self.scrape_policy = inner_opts.scrape_policy;
+        self.try_init(inner_opts.kube_config, inner_opts.namespace)?;
+
+        if inner_opts.watch {
+            self.shell()
+                .info("Using the watch API to receive pod changes");
+            let stream = watcher(self.pod_client().clone(), self.pod_list_params());
+            self.watch = Some(PodWatch {
+                stream: Box::pin(stream),
+                pods:   BTreeMap::new(),
+                stale:  true,
+            });
         }
+
+        Ok(())
     }
 
     fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error> {
-        let pods = self.get_pods()?;
+        let pods = match self.watch.is_some() {
+            true => self.get_watched_pods()?,
+            false => self.get_pods()?,
+        };
 
         let original_num = pods.len();
-        let pods_map: BTreeMap<String, Pod> = pods
-            .into_iter()
-            .filter_map(|p| p.meta().uid.clone().map(|id| (id, p)))
+        let targets: BTreeMap<String, PodTarget<'_>> = pods
+            .iter()
+            .flat_map(|pod| self.pod_targets(pod))
             .collect::<BTreeMap<_, _>>();
 
-        let uids = pods_map.keys().map(String::clone);
+        let ids = targets.keys().map(String::clone);
         let mut events: Vec<CollectionEvent> = Vec::new();
-        let (added, removed) = self.pod_uid_pool.update(uids);
+        let (added, removed) = self.target_id_pool.update(ids);
 
         let removed_len = removed.len();
         events.reserve_exact(added.len() + removed.len());
@@ -212,25 +341,25 @@ impl Provider for Kubernetes {
         // Add all added Ids as Start events
         let start_events = added
             .into_iter()
-            .filter_map(|uid| {
-                // It shouldn't be possible to have a Uid that doesn't exist in the map, but
+            .filter_map(|id| {
+                // It shouldn't be possible to have an Id that doesn't exist in the map, but
                 // check anyways
-                let pod: &Pod = match pods_map.get(uid.as_str()) {
-                    Some(pod) => pod,
+                let target: PodTarget<'_> = match targets.get(id.as_str()) {
+                    Some(target) => *target,
                     None => {
                         self.shell().error(format!(
-                            "Processed Uid from ItemPool added result that was not in fetched pod \
-                             list. This is a bug!\nUid: {}",
-                            uid
+                            "Processed Id from ItemPool added result that was not in fetched pod \
+                             list. This is a bug!\nId: {}",
+                            id
                         ));
                         return None;
                     },
                 };
 
-                match self.make_start_event(pod) {
+                match self.make_start_event(target) {
                     Ok(start) => Some(start),
                     Err(error) => {
-                        self.shell().warn(error.display(pod));
+                        self.shell().warn(error.display(target.pod));
                         // Ignore container and continue initializing the rest
                         None
                     },
@@ -245,7 +374,7 @@ impl Provider for Kubernetes {
                 sh.info(format!(
                     "Received {} -> {} (+{}, -{}) containers from the Kubernetes API",
                     original_num,
-                    pods_map.len(),
+                    targets.len(),
                     processed_num,
                     removed_len
                 ));
@@ -254,6 +383,8 @@ impl Provider for Kubernetes {
 
         Ok(events)
     }
+
+    fn node(&self) -> Option<String> { self.node_name.clone() }
 }
 
 impl Default for Kubernetes {
@@ -273,12 +404,18 @@ impl Kubernetes {
             .unwrap();
         Self {
             cgroup_manager: CgroupManager::new(),
-            pod_uid_pool: ItemPool::new(),
+            target_id_pool: ItemPool::new(),
             runtime,
             pod_client: None,
             node_client: None,
             hostname: None,
             node_name: None,
+            label_selector: None,
+            field_selector: None,
+            group_by: None,
+            per_container: false,
+            scrape_policy: ScrapePolicy::Allow,
+            watch: None,
             shell: None,
         }
     }
@@ -289,7 +426,11 @@ impl Kubernetes {
     ///   2. Can't load Kubernetes config from filesystem
     ///   3. Cgroups mounted unexpectedly/improperly
     ///   4. API server/Node can't be communicated with
-    fn try_init(&mut self, kube_config: Option<PathBuf>) -> Result<(), KubernetesInitError> {
+    fn try_init(
+        &mut self,
+        kube_config: Option<PathBuf>,
+        namespace: Option<String>,
+    ) -> Result<(), KubernetesInitError> {
         if !util::cgroups_mounted_properly() {
             return Err(KubernetesInitError::InvalidCgroupMount);
         }
@@ -312,7 +453,10 @@ impl Kubernetes {
         // Initialize the API clients
         let client = Client::try_from(config)
             .map_err(|err| KubernetesInitError::ConfigLoadError(Error::from(err)))?;
-        self.pod_client = Some(Api::all(client.clone()));
+        self.pod_client = Some(match namespace {
+            Some(namespace) => Api::namespaced(client.clone(), &namespace),
+            None => Api::all(client.clone()),
+        });
         self.node_client = Some(Api::all(client));
 
         // Load the hostname of the machine
@@ -350,7 +494,7 @@ impl Kubernetes {
     /// Tries to get all pods that are running on the current node, polling the
     /// Kubernetes API backend to get a fresh list
     fn get_pods(&self) -> Result<Vec<Pod>, Error> {
-        let lp = ListParams::default().fields(&format!("spec.nodeName={}", self.node_name()));
+        let lp = self.pod_list_params();
         let future = self.pod_client().list(&lp);
         let pods = self
             .runtime
@@ -360,16 +504,135 @@ impl Kubernetes {
         Ok(pods)
     }
 
-    /// Converts a pod to a collection start event, preparing all
+    /// Gets all pods that are running on the current node using the pod watch,
+    /// applying any changes streamed from the API server since the last poll.
+    /// If the watch failed, falls back to listing all pods until it recovers
+    fn get_watched_pods(&mut self) -> Result<Vec<Pod>, Error> {
+        let watch = self
+            .watch
+            .as_mut()
+            .expect("Pod watch must be initialized: invariant violated");
+        if let Some(err) = watch.drain(&self.runtime) {
+            watch.stale = true;
+            self.shell().warn(format!(
+                "Kubernetes pod watch failed; falling back to listing pods: {}",
+                err
+            ));
+        }
+
+        if matches!(&self.watch, Some(watch) if watch.stale) {
+            let pods = self.get_pods()?;
+            let watch = self.watch.as_mut().unwrap();
+            watch.apply(watcher::Event::Restarted(pods));
+            watch.stale = false;
+        }
+
+        let watch = self.watch.as_ref().unwrap();
+        Ok(watch.pods.values().cloned().collect::<Vec<_>>())
+    }
+
+    /// Builds the list parameters used to fetch pods, restricting them to the
+    /// current node and any user-supplied selectors
+    fn pod_list_params(&self) -> ListParams {
+        let mut fields = format!("spec.nodeName={}", self.node_name());
+        if let Some(field_selector) = &self.field_selector {
+            fields.push(',');
+            fields.push_str(field_selector);
+        }
+
+        let lp = ListParams::default().fields(&fields);
+        match &self.label_selector {
+            Some(label_selector) => lp.labels(label_selector),
+            None => lp,
+        }
+    }
+
+    /// Gets the collection targets for a pod, keyed by their Id. This is
+    /// either the pod itself (keyed by its Uid), or each of its running
+    /// containers (keyed by their container Id) when collecting per-container.
+    fn pod_targets<'a>(&self, pod: &'a Pod) -> Vec<(String, PodTarget<'a>)> {
+        if !self.is_scraped(pod) {
+            return Vec::new();
+        }
+
+        if !self.per_container {
+            return match &pod.meta().uid {
+                Some(uid) => vec![(uid.clone(), PodTarget {
+                    pod,
+                    container: None,
+                })],
+                None => Vec::new(),
+            };
+        }
+
+        // Containers only have an Id once they have been started
+        pod.status
+            .iter()
+            .flat_map(|status| status.container_statuses.iter().flatten())
+            .filter_map(|container| {
+                let id = container.container_id.as_deref().map(strip_runtime)?;
+                Some((id.to_owned(), PodTarget {
+                    pod,
+                    container: Some(container),
+                }))
+            })
+            .collect()
+    }
+
+    /// Whether the pod is collected, based on its scrape annotation (if it has
+    /// one) or else the scrape policy. Since this is checked on every poll,
+    /// changing the annotation of a running pod starts or stops its
+    /// collection.
+    fn is_scraped(&self, pod: &Pod) -> bool {
+        let annotation = pod
+            .meta()
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(SCRAPE_ANNOTATION));
+        match annotation
+            .map(|value| value.trim().to_lowercase())
+            .as_deref()
+        {
+            Some("true") => true,
+            Some("false") => false,
+            _ => self.scrape_policy == ScrapePolicy::Allow,
+        }
+    }
+
+    /// Converts a pod target to a collection start event, preparing all
     /// serialization/cgroup checks needed
-    fn make_start_event(&mut self, pod: &Pod) -> Result<CollectionEvent, StartCollectionError> {
+    fn make_start_event(
+        &mut self,
+        target: PodTarget<'_>,
+    ) -> Result<CollectionEvent, StartCollectionError> {
+        let pod = target.pod;
         let uid: &str = pod
             .meta()
             .uid
             .as_deref()
             .ok_or(StartCollectionError::MissingPodUid)?;
-        let method = self.get_collection_method(pod, uid)?;
-        let metadata = match serialize_pod_info(pod) {
+        let pod_name = pod
+            .meta()
+            .name
+            .clone()
+            .unwrap_or_else(|| String::from(NONE_STR));
+        let (id, name) = match target.container {
+            None => (uid.to_owned(), pod_name),
+            Some(container) => {
+                let container_id = container
+                    .container_id
+                    .as_deref()
+                    .map(strip_runtime)
+                    .ok_or(StartCollectionError::MissingContainerId)?;
+                (
+                    container_id.to_owned(),
+                    format!("{}/{}", pod_name, container.name),
+                )
+            },
+        };
+
+        let method = self.get_collection_method(pod, uid, target.container.map(|_| id.as_str()))?;
+        let metadata = match serialize_pod_info(pod, target.container) {
             Ok(metadata) => metadata,
             Err(err) => {
                 return Err(StartCollectionError::MetadataSerializationError(err));
@@ -379,25 +642,31 @@ impl Kubernetes {
         Ok(CollectionEvent::Start {
             method,
             target: CollectionTarget {
-                provider:  PROVIDER_TYPE,
-                metadata:  Some(metadata),
-                name:      pod
-                    .meta()
-                    .name
-                    .clone()
-                    .unwrap_or_else(|| String::from(NONE_STR)),
+                provider: PROVIDER_TYPE,
+                metadata: Some(metadata),
+                name,
                 poll_time: util::nano_ts(),
-                id:        uid.to_owned(),
+                id,
+                group: self.group_by.as_ref().and_then(|key| {
+                    pod.meta()
+                        .labels
+                        .as_ref()
+                        .and_then(|labels| labels.get(key))
+                        .cloned()
+                }),
+                file_name: None,
             },
         })
     }
 
-    /// Gets the collection method struct for the pod, resolving the
-    /// proper collection method
+    /// Gets the collection method struct for the pod (or for one of its
+    /// containers, if a container Id is given), resolving the proper
+    /// collection method
     fn get_collection_method(
         &mut self,
         pod: &Pod,
         uid: &str,
+        container_id: Option<&str>,
     ) -> Result<CollectionMethod, StartCollectionError> {
         // Only one type of CollectionMethod currently
         let qos_class: QualityOfService =
@@ -405,11 +674,14 @@ impl Kubernetes {
 
         // Construct the cgroup path from the UID and QoS class
         // from the metadata, and make sure it exists/is mounted
-        match self.get_cgroup(uid, qos_class) {
-            Ok(cgroup) => match cgroup.version {
-                util::CgroupVersion::V1 => Ok(CollectionMethod::LinuxCgroupV1(cgroup)),
-                util::CgroupVersion::V2 => Ok(CollectionMethod::LinuxCgroupV2(cgroup)),
-            },
+        let cgroup = self
+            .get_cgroup(uid, qos_class)
+            .and_then(|cgroup| match container_id {
+                Some(container_id) => find_container_cgroup(&cgroup, container_id),
+                None => Ok(cgroup),
+            });
+        match cgroup {
+            Ok(cgroup) => Ok(CollectionMethod::for_cgroup(cgroup)),
             Err(GetCgroupError::VersionDetectionFailed) => {
                 Err(StartCollectionError::CgroupVersionDetectionFailed)
             },
@@ -417,6 +689,7 @@ impl Kubernetes {
             Err(GetCgroupError::CgroupV1NotEnabled) => {
                 Err(StartCollectionError::CgroupV1NotEnabled)
             },
+            Err(GetCgroupError::DelegationRequiresV2) => unreachable!(),
         }
     }
 
@@ -440,7 +713,12 @@ impl Kubernetes {
             systemd:  &util::build_systemd_cgroup_hierarchy(base_cgroup_slices),
         });
 
-        if !had_driver {
+        if let Some((previous, driver)) = self.cgroup_manager.take_driver_change() {
+            self.shell().warn(format!(
+                "cgroup driver changed from {} to {} after repeated cgroup resolution failures",
+                previous, driver
+            ));
+        } else if !had_driver {
             if let Some(driver) = self.cgroup_manager.driver() {
                 self.shell()
                     .info(format!("Identified {} as cgroup driver", driver));
@@ -493,6 +771,34 @@ impl Kubernetes {
     }
 }
 
+/// Finds the child cgroup of a pod that belongs to the given container. Its
+/// name depends on both the cgroup driver and the container runtime (such as
+/// `<id>` for cgroupfs or `cri-containerd-<id>.scope` for systemd), but
+/// always contains the container Id.
+fn find_container_cgroup(
+    pod: &CgroupPath,
+    container_id: &str,
+) -> Result<CgroupPath, GetCgroupError> {
+    let pattern = format!("{}/*{}*", pod.path.to_string_lossy(), container_id);
+    match util::find_cgroups(&pattern, pod.version).into_iter().next() {
+        Some(path) => Ok(CgroupPath {
+            path,
+            driver: pod.driver,
+            version: pod.version,
+        }),
+        None => Err(GetCgroupError::NotFound(pod.path.join(container_id))),
+    }
+}
+
+/// Strips the runtime prefix from a container Id reported in a pod's status
+/// (such as `containerd://<id>`)
+fn strip_runtime(container_id: &str) -> &str {
+    match container_id.split_once("://") {
+        Some((_, id)) => id,
+        None => container_id,
+    }
+}
+
 /// Pod info struct that gets included with each log file
 #[derive(Clone, Debug, PartialEq, Serialize)]
 #[serde(rename_all = "PascalCase")]
@@ -507,6 +813,8 @@ struct PodInfo<'a> {
     phase:      &'a Option<String>,
     qos_class:  &'a Option<String>,
     started_at: &'a Option<Time>,
+    /// Resources and runtime state of each container in the pod's spec
+    containers: Vec<ContainerInfo<'a>>,
 }
 
 impl<'a> PodInfo<'a> {
@@ -537,6 +845,21 @@ impl<'a> PodInfo<'a> {
             ),
         };
 
+        let statuses = p
+            .status
+            .as_ref()
+            .and_then(|status| status.container_statuses.as_deref())
+            .unwrap_or_default();
+        let containers = p
+            .spec
+            .iter()
+            .flat_map(|spec| &spec.containers)
+            .map(|container| {
+                let status = statuses.iter().find(|s| s.name == container.name);
+                ContainerInfo::new(&container.name, Some(container), status)
+            })
+            .collect();
+
         PodInfo {
             uid,
             name,
@@ -548,13 +871,71 @@ impl<'a> PodInfo<'a> {
             phase,
             qos_class,
             started_at,
+            containers,
         }
     }
 }
 
-/// Attempts to format pod info, potentially failing to do so
-fn serialize_pod_info(pod: &Pod) -> Result<serde_yaml::Value, Error> {
+/// Container info struct that gets included with the info of its pod, and
+/// separately with each log file when collecting per-container. Combines the
+/// container's spec (for resource requests and limits) with its status (for
+/// runtime state), either of which may be missing.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerInfo<'a> {
+    name:          &'a str,
+    /// Id of the container in its runtime (such as `containerd://<id>`)
+    id:            &'a Option<String>,
+    image:         Option<&'a str>,
+    /// Image (including its digest) that the runtime resolved the image to
+    image_id:      Option<&'a str>,
+    ready:         Option<bool>,
+    restart_count: Option<i32>,
+    requests:      Option<&'a BTreeMap<String, Quantity>>,
+    limits:        Option<&'a BTreeMap<String, Quantity>>,
+}
+
+impl<'a> ContainerInfo<'a> {
+    fn new(
+        name: &'a str,
+        spec: Option<&'a Container>,
+        status: Option<&'a ContainerStatus>,
+    ) -> Self {
+        let resources = spec.and_then(|c| c.resources.as_ref());
+        ContainerInfo {
+            name,
+            id: status.map_or(&None, |s| &s.container_id),
+            image: status
+                .map(|s| s.image.as_str())
+                .or_else(|| spec.and_then(|c| c.image.as_deref())),
+            image_id: status
+                .map(|s| s.image_id.as_str())
+                .filter(|image_id| !image_id.is_empty()),
+            ready: status.map(|s| s.ready),
+            restart_count: status.map(|s| s.restart_count),
+            requests: resources.and_then(|r| r.requests.as_ref()),
+            limits: resources.and_then(|r| r.limits.as_ref()),
+        }
+    }
+}
+
+/// Attempts to format pod info (and container info, if given), potentially
+/// failing to do so
+fn serialize_pod_info(
+    pod: &Pod,
+    container: Option<&ContainerStatus>,
+) -> Result<serde_yaml::Value, Error> {
     let pod_info = PodInfo::new(pod);
-    let serde_output = serde_yaml::to_value(&pod_info)?;
+    let mut serde_output = serde_yaml::to_value(&pod_info)?;
+    if let (Some(container), serde_yaml::Value::Mapping(mapping)) = (container, &mut serde_output) {
+        let spec = pod
+            .spec
+            .iter()
+            .flat_map(|spec| &spec.containers)
+            .find(|c| c.name == container.name);
+        let container_info =
+            serde_yaml::to_value(ContainerInfo::new(&container.name, spec, Some(container)))?;
+        mapping.insert(serde_yaml::Value::from("Container"), container_info);
+    }
     Ok(serde_output)
 }
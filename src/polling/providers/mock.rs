@@ -0,0 +1,207 @@
+use crate::cli::RunCommand;
+use crate::polling::providers::{InitializationError, MockOptions, Provider};
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
+use crate::shell::Shell;
+use crate::util::{self, CgroupDriver, CgroupPath, CgroupVersion, ItemPool};
+use anyhow::Error;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const PROVIDER_TYPE: &str = "mock";
+
+/// File that only exists in cgroup v2 directories, used to tell which
+/// collector reads a fixture
+const CGROUP_V2_MARKER: &str = "cgroup.controllers";
+
+/// Multiplier used to spread the seeds of synthetic targets (from splitmix64)
+const SEED_MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Provider that collects targets that don't need a container runtime or real
+/// cgroups: fixture directories (each mimicking a cgroup directory in
+/// cgroupfs) and synthetic targets whose statistics are generated. Used to
+/// run rAdvisor end-to-end for demos, and to test downstream tooling.
+pub struct Mock {
+    id_pool:   ItemPool<String>,
+    fixtures:  Option<PathBuf>,
+    synthetic: u32,
+    shell:     Option<Arc<Shell>>,
+}
+
+/// Source of a mock target's statistics
+#[derive(Clone, Debug, PartialEq)]
+enum Source {
+    /// Fixture directory, read by the collector of its cgroup version
+    Fixture(PathBuf, CgroupVersion),
+    /// Synthetic target with the given seed
+    Synthetic(u64),
+}
+
+/// Metadata included in the log file header for each mock target
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MockMetadata<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fixture: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed:    Option<u64>,
+}
+
+impl Provider for Mock {
+    fn initialize(
+        &mut self,
+        opts: &RunCommand,
+        shell: Arc<Shell>,
+    ) -> Result<(), InitializationError> {
+        self.shell = Some(Arc::clone(&shell));
+        self.shell().status("Initializing", "mock provider");
+
+        let inner_opts: MockOptions = opts.provider.clone().into_inner_mock();
+        self.synthetic = inner_opts.synthetic.unwrap_or(0);
+        if let Some(fixtures) = inner_opts.fixtures {
+            // Resolve the directory now, so that fixture paths are absolute
+            let fixtures = fs::canonicalize(&fixtures).map_err(|err| InitializationError {
+                original:   Some(err.into()),
+                suggestion: format!(
+                    "Could not open the fixture directory {}. Does it exist?",
+                    fixtures.display()
+                ),
+            })?;
+            self.fixtures = Some(fixtures);
+        }
+
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error> {
+        let mut to_collect: BTreeMap<String, Source> = BTreeMap::new();
+        if let Some(fixtures) = &self.fixtures {
+            for (name, path) in list_fixtures(fixtures)? {
+                let version = match path.join(CGROUP_V2_MARKER).exists() {
+                    true => CgroupVersion::V2,
+                    false => CgroupVersion::V1,
+                };
+                to_collect.insert(format!("fixture-{}", name), Source::Fixture(path, version));
+            }
+        }
+        for i in 0..u64::from(self.synthetic) {
+            let seed = (i + 1).wrapping_mul(SEED_MULTIPLIER);
+            to_collect.insert(format!("synthetic-{}", i), Source::Synthetic(seed));
+        }
+
+        let total = to_collect.len();
+        let ids = to_collect.keys().map(String::clone);
+        let (added, removed) = self.id_pool.update(ids);
+
+        let mut events: Vec<CollectionEvent> = Vec::with_capacity(added.len() + removed.len());
+        let (added_len, removed_len) = (added.len(), removed.len());
+        events.extend(removed.into_iter().map(CollectionEvent::Stop));
+        for id in added {
+            if let Some(source) = to_collect.remove(&id) {
+                match make_start_event(id, source) {
+                    Ok(start) => events.push(start),
+                    Err(err) => self.shell().warn(format!(
+                        "Could not start collection for a mock target: failed to serialize \
+                         metadata: {}",
+                        err
+                    )),
+                }
+            }
+        }
+
+        if added_len != 0 || removed_len != 0 {
+            self.shell().verbose(|sh| {
+                sh.info(format!(
+                    "Found {} (+{}, -{}) mock targets",
+                    total, added_len, removed_len
+                ));
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+impl Default for Mock {
+    fn default() -> Self { Self::new() }
+}
+
+impl Mock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            id_pool:   ItemPool::new(),
+            fixtures:  None,
+            synthetic: 0,
+            shell:     None,
+        }
+    }
+
+    /// Gets a reference to the current shell
+    fn shell(&self) -> &Shell {
+        self.shell
+            .as_ref()
+            .expect("Shell must be initialized: invariant violated")
+    }
+}
+
+/// Lists the fixtures in the fixture directory (each of its subdirectories),
+/// by name
+fn list_fixtures(fixtures: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(fixtures)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                found.push((name.to_owned(), path.clone()));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Creates a collection start event for the mock target with the given id
+fn make_start_event(id: String, source: Source) -> Result<CollectionEvent, Error> {
+    let (metadata, method) = match source {
+        Source::Fixture(path, version) => {
+            let metadata = serde_yaml::to_value(&MockMetadata {
+                fixture: Some(&path),
+                seed:    None,
+            })?;
+            let cgroup = CgroupPath {
+                path,
+                driver: CgroupDriver::Cgroupfs,
+                version,
+            };
+            let method = match version {
+                CgroupVersion::V1 => CollectionMethod::LinuxCgroupV1(cgroup),
+                CgroupVersion::V2 => CollectionMethod::LinuxCgroupV2(cgroup),
+            };
+            (metadata, method)
+        },
+        Source::Synthetic(seed) => {
+            let metadata = serde_yaml::to_value(&MockMetadata {
+                fixture: None,
+                seed:    Some(seed),
+            })?;
+            (metadata, CollectionMethod::Synthetic(seed))
+        },
+    };
+
+    Ok(CollectionEvent::Start {
+        method,
+        target: CollectionTarget {
+            provider: PROVIDER_TYPE,
+            metadata: Some(metadata),
+            name: id.clone(),
+            poll_time: util::nano_ts(),
+            id,
+            group: None,
+            file_name: None,
+        },
+    })
+}
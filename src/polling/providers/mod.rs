@@ -1,15 +1,28 @@
+pub mod cgroup;
 #[cfg(feature = "docker")]
 pub mod docker;
+pub mod exec;
+pub mod external;
 #[cfg(feature = "kubernetes")]
 pub mod kubernetes;
+pub mod mock;
+pub mod multi;
+pub mod systemd;
 
-use crate::cli::{CollectionOptions, PollingOptions, RunCommand};
+use crate::cli::{parse_duration, CollectionOptions, PollingOptions, RunCommand};
+#[cfg(feature = "docker")]
+use crate::polling::filter::TargetFilter;
+#[cfg(feature = "docker")]
+use crate::polling::redact::RedactRule;
 use crate::shared::CollectionEvent;
 use crate::shell::Shell;
+use crate::timer::Waker;
 use anyhow::Error;
 use clap::Clap;
 use serde::{Serialize, Serializer};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// An error that occurred during provider initialization/connection check,
 /// including a suggestion message printed to stdout
@@ -34,6 +47,17 @@ pub trait Provider {
     /// Attempts to poll the provider for a list of collection events (new/old
     /// targets), returning an Error if it failed
     fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error>;
+    /// Whether the provider has finished generating targets, in which case
+    /// rAdvisor stops collection and exits with the returned status code
+    fn finished(&mut self) -> Option<i32> { None }
+    /// Name of the node that the provider runs on, if it knows one (such as
+    /// the Kubernetes node name), which is used as the default node id
+    fn node(&self) -> Option<String> { None }
+    /// Gives the provider a handle to poll it right away, which providers
+    /// that are notified of new and removed targets (instead of only finding
+    /// them when polled) can use to report them without waiting for the next
+    /// poll. Called once after initialization.
+    fn watch(&mut self, _waker: Waker) {}
 }
 
 pub use provider_type::ProviderType;
@@ -68,6 +92,46 @@ mod provider_type {
             each pod"
         )]
         Kubernetes(super::KubernetesOptions),
+
+        #[clap(
+            version = VERSION.unwrap_or("unknown"),
+            author = AUTHORS.as_deref().unwrap_or("contributors"),
+            about = "Runs collection using cgroup paths as the target backend; collecting stats \
+            for each matching cgroup"
+        )]
+        Cgroup(super::CgroupOptions),
+
+        #[clap(
+            version = VERSION.unwrap_or("unknown"),
+            author = AUTHORS.as_deref().unwrap_or("contributors"),
+            about = "Runs collection for a launched command until it exits; collecting stats for \
+            the cgroup it is launched in"
+        )]
+        Exec(super::ExecOptions),
+
+        #[clap(
+            version = VERSION.unwrap_or("unknown"),
+            author = AUTHORS.as_deref().unwrap_or("contributors"),
+            about = "Runs collection using a plugin executable as the target backend; collecting \
+            stats for each target that it prints"
+        )]
+        External(super::ExternalOptions),
+
+        #[clap(
+            version = VERSION.unwrap_or("unknown"),
+            author = AUTHORS.as_deref().unwrap_or("contributors"),
+            about = "Runs collection using systemd as the target backend; collecting stats for \
+            each active unit that matches a pattern"
+        )]
+        Systemd(super::SystemdOptions),
+
+        #[clap(
+            version = VERSION.unwrap_or("unknown"),
+            author = AUTHORS.as_deref().unwrap_or("contributors"),
+            about = "Runs collection using fixture directories and synthetic targets as the \
+            target backend; collecting stats without containers or real cgroups"
+        )]
+        Mock(super::MockOptions),
     }
 }
 
@@ -90,6 +154,11 @@ impl ProviderType {
             #[cfg(feature = "docker")]
             Self::Docker(_) => panic!("Cannot unwrap Docker provider to Kubernetes options"),
             Self::Kubernetes(opts) => opts,
+            Self::Cgroup(_) => panic!("Cannot unwrap cgroup provider to Kubernetes options"),
+            Self::Exec(_) => panic!("Cannot unwrap exec provider to Kubernetes options"),
+            Self::External(_) => panic!("Cannot unwrap external provider to Kubernetes options"),
+            Self::Systemd(_) => panic!("Cannot unwrap systemd provider to Kubernetes options"),
+            Self::Mock(_) => panic!("Cannot unwrap mock provider to Kubernetes options"),
         }
     }
 
@@ -101,17 +170,118 @@ impl ProviderType {
             Self::Docker(opts) => opts,
             #[cfg(feature = "kubernetes")]
             Self::Kubernetes(_) => panic!("Cannot unwrap Kubernetes provider to Docker options"),
+            Self::Cgroup(_) => panic!("Cannot unwrap cgroup provider to Docker options"),
+            Self::Exec(_) => panic!("Cannot unwrap exec provider to Docker options"),
+            Self::External(_) => panic!("Cannot unwrap external provider to Docker options"),
+            Self::Systemd(_) => panic!("Cannot unwrap systemd provider to Docker options"),
+            Self::Mock(_) => panic!("Cannot unwrap mock provider to Docker options"),
         }
     }
 
-    /// Gets the corresponding provider for the CLI polling mode
+    /// Gets the inner options struct for the cgroup provider
+    #[must_use]
+    pub fn into_inner_cgroup(self) -> CgroupOptions {
+        match self {
+            #[cfg(feature = "docker")]
+            Self::Docker(_) => panic!("Cannot unwrap Docker provider to cgroup options"),
+            #[cfg(feature = "kubernetes")]
+            Self::Kubernetes(_) => panic!("Cannot unwrap Kubernetes provider to cgroup options"),
+            Self::Cgroup(opts) => opts,
+            Self::Exec(_) => panic!("Cannot unwrap exec provider to cgroup options"),
+            Self::External(_) => panic!("Cannot unwrap external provider to cgroup options"),
+            Self::Systemd(_) => panic!("Cannot unwrap systemd provider to cgroup options"),
+            Self::Mock(_) => panic!("Cannot unwrap mock provider to cgroup options"),
+        }
+    }
+
+    /// Gets the inner options struct for the exec provider
+    #[must_use]
+    pub fn into_inner_exec(self) -> ExecOptions {
+        match self {
+            #[cfg(feature = "docker")]
+            Self::Docker(_) => panic!("Cannot unwrap Docker provider to exec options"),
+            #[cfg(feature = "kubernetes")]
+            Self::Kubernetes(_) => panic!("Cannot unwrap Kubernetes provider to exec options"),
+            Self::Cgroup(_) => panic!("Cannot unwrap cgroup provider to exec options"),
+            Self::Exec(opts) => opts,
+            Self::External(_) => panic!("Cannot unwrap external provider to exec options"),
+            Self::Systemd(_) => panic!("Cannot unwrap systemd provider to exec options"),
+            Self::Mock(_) => panic!("Cannot unwrap mock provider to exec options"),
+        }
+    }
+
+    /// Gets the inner options struct for the external provider
+    #[must_use]
+    pub fn into_inner_external(self) -> ExternalOptions {
+        match self {
+            #[cfg(feature = "docker")]
+            Self::Docker(_) => panic!("Cannot unwrap Docker provider to external options"),
+            #[cfg(feature = "kubernetes")]
+            Self::Kubernetes(_) => panic!("Cannot unwrap Kubernetes provider to external options"),
+            Self::Cgroup(_) => panic!("Cannot unwrap cgroup provider to external options"),
+            Self::Exec(_) => panic!("Cannot unwrap exec provider to external options"),
+            Self::External(opts) => opts,
+            Self::Systemd(_) => panic!("Cannot unwrap systemd provider to external options"),
+            Self::Mock(_) => panic!("Cannot unwrap mock provider to external options"),
+        }
+    }
+
+    /// Gets the inner options struct for the systemd provider
+    #[must_use]
+    pub fn into_inner_systemd(self) -> SystemdOptions {
+        match self {
+            #[cfg(feature = "docker")]
+            Self::Docker(_) => panic!("Cannot unwrap Docker provider to systemd options"),
+            #[cfg(feature = "kubernetes")]
+            Self::Kubernetes(_) => panic!("Cannot unwrap Kubernetes provider to systemd options"),
+            Self::Cgroup(_) => panic!("Cannot unwrap cgroup provider to systemd options"),
+            Self::Exec(_) => panic!("Cannot unwrap exec provider to systemd options"),
+            Self::External(_) => panic!("Cannot unwrap external provider to systemd options"),
+            Self::Systemd(opts) => opts,
+            Self::Mock(_) => panic!("Cannot unwrap mock provider to systemd options"),
+        }
+    }
+
+    /// Gets the inner options struct for the mock provider
+    #[must_use]
+    pub fn into_inner_mock(self) -> MockOptions {
+        match self {
+            #[cfg(feature = "docker")]
+            Self::Docker(_) => panic!("Cannot unwrap Docker provider to mock options"),
+            #[cfg(feature = "kubernetes")]
+            Self::Kubernetes(_) => panic!("Cannot unwrap Kubernetes provider to mock options"),
+            Self::Cgroup(_) => panic!("Cannot unwrap cgroup provider to mock options"),
+            Self::Exec(_) => panic!("Cannot unwrap exec provider to mock options"),
+            Self::External(_) => panic!("Cannot unwrap external provider to mock options"),
+            Self::Systemd(_) => panic!("Cannot unwrap systemd provider to mock options"),
+            Self::Mock(opts) => opts,
+        }
+    }
+
+    /// Gets the corresponding provider for the CLI polling mode, merged with
+    /// any additional providers given with `--also`
     #[must_use]
     pub fn get_impl(&self) -> Box<dyn Provider> {
+        let also = &self.polling().also;
+        match also.is_empty() {
+            true => self.get_single_impl(),
+            false => Box::new(multi::Multi::new(self, also)),
+        }
+    }
+
+    /// Gets the provider for the CLI polling mode alone
+    #[must_use]
+    pub fn get_single_impl(&self) -> Box<dyn Provider> {
         match self {
             #[cfg(feature = "docker")]
             Self::Docker(_) => Box::new(docker::Docker::new()),
             #[cfg(feature = "kubernetes")]
             Self::Kubernetes(_) => Box::new(kubernetes::Kubernetes::new()),
+            Self::Cgroup(_) => Box::new(cgroup::Cgroup::new()),
+            Self::Exec(_) => Box::new(exec::Exec::new()),
+            Self::External(_) => Box::new(external::External::new()),
+            Self::Systemd(_) => Box::new(systemd::Systemd::new()),
+            Self::Mock(_) => Box::new(mock::Mock::new()),
         }
     }
 
@@ -123,7 +293,51 @@ impl ProviderType {
             Self::Docker(opts) => &opts.collection,
             #[cfg(feature = "kubernetes")]
             Self::Kubernetes(opts) => &opts.collection,
+            Self::Cgroup(opts) => &opts.collection,
+            Self::Exec(opts) => &opts.collection,
+            Self::External(opts) => &opts.collection,
+            Self::Systemd(opts) => &opts.collection,
+            Self::Mock(opts) => &opts.collection,
+        }
+    }
+
+    /// Replaces the polling and collection options, such as to share the
+    /// options of the subcommand with an additional provider
+    #[must_use]
+    pub fn with_options(mut self, polling: PollingOptions, collection: CollectionOptions) -> Self {
+        match &mut self {
+            #[cfg(feature = "docker")]
+            Self::Docker(opts) => {
+                opts.polling = polling;
+                opts.collection = collection;
+            },
+            #[cfg(feature = "kubernetes")]
+            Self::Kubernetes(opts) => {
+                opts.polling = polling;
+                opts.collection = collection;
+            },
+            Self::Cgroup(opts) => {
+                opts.polling = polling;
+                opts.collection = collection;
+            },
+            Self::Exec(opts) => {
+                opts.polling = polling;
+                opts.collection = collection;
+            },
+            Self::External(opts) => {
+                opts.polling = polling;
+                opts.collection = collection;
+            },
+            Self::Systemd(opts) => {
+                opts.polling = polling;
+                opts.collection = collection;
+            },
+            Self::Mock(opts) => {
+                opts.polling = polling;
+                opts.collection = collection;
+            },
         }
+        self
     }
 
     /// Gets the polling options
@@ -134,6 +348,11 @@ impl ProviderType {
             Self::Docker(opts) => &opts.polling,
             #[cfg(feature = "kubernetes")]
             Self::Kubernetes(opts) => &opts.polling,
+            Self::Cgroup(opts) => &opts.polling,
+            Self::Exec(opts) => &opts.polling,
+            Self::External(opts) => &opts.polling,
+            Self::Systemd(opts) => &opts.polling,
+            Self::Mock(opts) => &opts.polling,
         }
     }
 }
@@ -143,6 +362,66 @@ impl ProviderType {
 #[cfg(feature = "docker")]
 #[derive(Clap, Clone, Debug, PartialEq)]
 pub struct DockerOptions {
+    /// Only collect containers matching at least one of these filters
+    /// (`<glob>`, `name=<glob>`, `image=<glob>`, or `label=<key>=<glob>`)
+    #[clap(
+        long = "include",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub include: Vec<TargetFilter>,
+
+    /// Never collect containers matching any of these filters (same syntax as
+    /// `--include`)
+    #[clap(
+        long = "exclude",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub exclude: Vec<TargetFilter>,
+
+    /// (optional) Label whose value groups containers into a shared log file
+    /// (such as `com.docker.compose.project` or `io.kubernetes.pod.uid`).
+    /// Containers without the label are written to their own log files
+    #[clap(long = "group-by", value_hint = ::clap::ValueHint::Other)]
+    pub group_by: Option<String>,
+
+    /// Hashes or omits container metadata fields before they are written to
+    /// log file headers (`hash=<glob>`, `omit=<glob>`, or `keep=<glob>`,
+    /// matched against dotted field paths such as `Labels.<key>`). Takes
+    /// precedence over the default rules, which hash the command and omit
+    /// environment variables and secret-like labels
+    #[clap(
+        long = "redact",
+        number_of_values = 1,
+        multiple_occurrences = true,
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub redact: Vec<RedactRule>,
+
+    /// Disables the default redaction rules, writing the container command
+    /// and all labels to log file headers unless `--redact` is given
+    #[clap(long = "no-default-redactions")]
+    pub no_default_redactions: bool,
+
+    /// Whether to name the log files of containers that belong to a Swarm
+    /// service after their service and task (such as `web.1.<task id>`)
+    /// instead of their container id
+    #[clap(long = "name-by-task")]
+    pub name_by_task: bool,
+
+    /// How paused containers are handled: `collect` to keep collecting them
+    /// (so their statistics stay flat while paused), or `stop` to stop
+    /// collecting them while paused, starting a new log file once unpaused
+    #[clap(
+        long = "paused",
+        default_value = "collect",
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub paused: docker::PausePolicy,
+
     // Polling-related options
     #[clap(flatten)]
     pub polling: PollingOptions,
@@ -164,6 +443,182 @@ pub struct KubernetesOptions {
     )]
     pub kube_config: Option<std::path::PathBuf>,
 
+    /// (optional) Namespace to restrict pod collection to. If not given, pods
+    /// in all namespaces are collected
+    #[clap(short = 'n', long = "namespace", value_hint = ::clap::ValueHint::Other)]
+    pub namespace: Option<String>,
+
+    /// (optional) Label selector used to restrict the pods that are collected
+    /// (such as `app=web,tier!=cache`)
+    #[clap(short = 'l', long = "label-selector", value_hint = ::clap::ValueHint::Other)]
+    pub label_selector: Option<String>,
+
+    /// (optional) Field selector used to restrict the pods that are collected
+    /// (such as `status.phase=Running`), in addition to the current node
+    #[clap(long = "field-selector", value_hint = ::clap::ValueHint::Other)]
+    pub field_selector: Option<String>,
+
+    /// Whether to receive pod changes from a watch on the API server instead
+    /// of re-listing all pods on every poll
+    #[clap(short = 'w', long = "watch")]
+    pub watch: bool,
+
+    /// (optional) Label whose value groups pods into a shared log file (such
+    /// as `app`). Pods without the label are written to their own log files
+    #[clap(long = "group-by", value_hint = ::clap::ValueHint::Other)]
+    pub group_by: Option<String>,
+
+    /// Whether to collect each container in a pod separately (from its child
+    /// cgroup beneath the pod's cgroup) instead of the pod as a whole
+    #[clap(long = "per-container")]
+    pub per_container: bool,
+
+    /// Whether pods without a `radvisor.io/scrape` annotation are collected:
+    /// `allow` to collect them (so pods opt out with `"false"`), or `deny` to
+    /// skip them (so pods opt in with `"true"`)
+    #[clap(
+        long = "scrape-policy",
+        default_value = "allow",
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub scrape_policy: kubernetes::ScrapePolicy,
+
+    // Polling-related options
+    #[clap(flatten)]
+    pub polling: PollingOptions,
+
+    // Collection-related options
+    #[clap(flatten)]
+    pub collection: CollectionOptions,
+}
+
+#[derive(Clap, Clone, Debug, PartialEq)]
+pub struct CgroupOptions {
+    /// Cgroup to collect, relative to the cgroup root (such as
+    /// `system.slice/nginx.service`). Each path component can be a glob (such
+    /// as `system.slice/*.service`), and new matching cgroups are picked up
+    /// while running
+    #[clap(
+        long = "cgroup",
+        required = true,
+        number_of_values = 1,
+        multiple_occurrences = true,
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub cgroups: Vec<String>,
+
+    /// Whether to write all cgroups matching the same `--cgroup` pattern to a
+    /// shared log file
+    #[clap(long = "group")]
+    pub group: bool,
+
+    /// Whether to only find new and removed cgroups when polling, instead of
+    /// also watching the directories that they are created in with inotify
+    /// (which picks them up right away)
+    #[clap(long = "no-watch")]
+    pub no_watch: bool,
+
+    // Polling-related options
+    #[clap(flatten)]
+    pub polling: PollingOptions,
+
+    // Collection-related options
+    #[clap(flatten)]
+    pub collection: CollectionOptions,
+}
+
+#[derive(Clap, Clone, Debug, PartialEq)]
+pub struct ExecOptions {
+    /// Command to launch and collect statistics for, along with its arguments
+    #[clap(required = true, last = true)]
+    pub command: Vec<String>,
+
+    // Polling-related options
+    #[clap(flatten)]
+    pub polling: PollingOptions,
+
+    // Collection-related options
+    #[clap(flatten)]
+    pub collection: CollectionOptions,
+}
+
+#[derive(Clap, Clone, Debug, PartialEq)]
+pub struct ExternalOptions {
+    /// Plugin executable to run on each poll, along with its arguments. It
+    /// should print the current targets to stdout as a JSON array of objects
+    /// with `id` and `cgroup` (relative to the cgroup root) fields, and
+    /// optionally `name`, `group`, and `metadata` fields
+    #[clap(required = true, last = true)]
+    pub plugin: Vec<String>,
+
+    /// Maximum duration that the plugin can run for on each poll before it is
+    /// killed and the poll fails
+    #[clap(
+        parse(try_from_str = parse_duration),
+        long = "plugin-timeout",
+        default_value = "5s",
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub plugin_timeout: Duration,
+
+    // Polling-related options
+    #[clap(flatten)]
+    pub polling: PollingOptions,
+
+    // Collection-related options
+    #[clap(flatten)]
+    pub collection: CollectionOptions,
+}
+
+#[derive(Clap, Clone, Debug, PartialEq)]
+pub struct SystemdOptions {
+    /// Pattern of the units to collect (such as `myapp-*.service`), which can
+    /// be given multiple times. Units that become active while running are
+    /// picked up, and units that become inactive are stopped
+    #[clap(
+        long = "unit",
+        required = true,
+        number_of_values = 1,
+        multiple_occurrences = true,
+        value_hint = ::clap::ValueHint::Other
+    )]
+    pub units: Vec<String>,
+
+    /// Whether to collect the units of the calling user's service manager
+    /// instead of the system service manager
+    #[clap(long = "user")]
+    pub user: bool,
+
+    // Polling-related options
+    #[clap(flatten)]
+    pub polling: PollingOptions,
+
+    // Collection-related options
+    #[clap(flatten)]
+    pub collection: CollectionOptions,
+}
+
+#[derive(Clap, Clone, Debug, PartialEq)]
+pub struct MockOptions {
+    /// (optional) Directory of fixtures to collect, where each subdirectory is
+    /// a target that mimics a cgroup directory in cgroupfs. Subdirectories
+    /// with a `cgroup.controllers` file are read as cgroup v2 directories,
+    /// and the others as cgroup v1 directories containing the files of every
+    /// subsystem. Subdirectories that are added or removed while running are
+    /// picked up
+    #[clap(
+        parse(from_os_str),
+        long = "fixtures",
+        required_unless_present = "synthetic",
+        value_hint = ::clap::ValueHint::DirPath
+    )]
+    pub fixtures: Option<PathBuf>,
+
+    /// (optional) Number of synthetic targets to collect, whose statistics are
+    /// generated instead of read from any files
+    #[clap(long = "synthetic", value_hint = ::clap::ValueHint::Other)]
+    pub synthetic: Option<u32>,
+
     // Polling-related options
     #[clap(flatten)]
     pub polling: PollingOptions,
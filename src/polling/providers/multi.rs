@@ -0,0 +1,185 @@
+use crate::cli::RunCommand;
+use crate::polling::providers::{InitializationError, Provider, ProviderType};
+use crate::shared::{CollectionEvent, Id};
+use crate::shell::Shell;
+use crate::timer::Waker;
+use anyhow::Error;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Provider that merges the targets of several providers (the provider of the
+/// subcommand, along with those given with `--also`) into a single stream of
+/// collection events. Each target is only collected once: if several
+/// providers report a target with the same id, it is collected from the
+/// provider that reported it first.
+pub struct Multi {
+    providers: Vec<Member>,
+    /// Index of the provider that reported each active target
+    owners:    HashMap<Id, usize>,
+    shell:     Option<Arc<Shell>>,
+}
+
+/// Single provider whose targets are merged
+struct Member {
+    name:     &'static str,
+    provider: Box<dyn Provider>,
+    /// Subcommand and options of the provider, or None for the provider of
+    /// the subcommand itself
+    options:  Option<ProviderType>,
+}
+
+impl Provider for Multi {
+    fn initialize(
+        &mut self,
+        opts: &RunCommand,
+        shell: Arc<Shell>,
+    ) -> Result<(), InitializationError> {
+        self.shell = Some(Arc::clone(&shell));
+
+        // Additional providers share the polling and collection options of
+        // the subcommand
+        let mut polling = opts.provider.polling().clone();
+        polling.also.clear();
+        let collection = opts.provider.collection();
+        for member in &mut self.providers {
+            let member_opts = match &member.options {
+                None => opts.clone(),
+                Some(options) => RunCommand {
+                    provider: options
+                        .clone()
+                        .with_options(polling.clone(), collection.clone()),
+                },
+            };
+            member
+                .provider
+                .initialize(&member_opts, Arc::clone(&shell))
+                .map_err(|err| InitializationError {
+                    suggestion: format!("{} (from the {} provider)", err.suggestion, member.name),
+                    original:   err.original,
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error> {
+        let mut events = Vec::new();
+        let mut last_error = None;
+        for index in 0..self.providers.len() {
+            let member = &mut self.providers[index];
+            match member.provider.poll() {
+                Ok(polled) => {
+                    let name = member.name;
+                    events.extend(
+                        polled
+                            .into_iter()
+                            .filter(|event| self.claim(index, name, event)),
+                    );
+                },
+                Err(err) => {
+                    let err = err.context(format!("{} provider", member.name));
+                    if let Some(previous) = last_error.replace(err) {
+                        self.shell()
+                            .error(format!("Could not poll target provider: {:#}", previous));
+                    }
+                },
+            }
+        }
+
+        // Only fail if no provider could be polled, since the events of the
+        // other providers would be lost otherwise
+        match (last_error, events.is_empty()) {
+            (Some(err), true) => Err(err),
+            (Some(err), false) => {
+                self.shell()
+                    .error(format!("Could not poll target provider: {:#}", err));
+                Ok(events)
+            },
+            (None, _) => Ok(events),
+        }
+    }
+
+    /// Finishes once any provider finishes (such as the exec provider once
+    /// its command exits)
+    fn finished(&mut self) -> Option<i32> {
+        self.providers
+            .iter_mut()
+            .find_map(|member| member.provider.finished())
+    }
+
+    fn watch(&mut self, waker: Waker) {
+        for member in &mut self.providers {
+            member.provider.watch(waker.clone());
+        }
+    }
+
+    fn node(&self) -> Option<String> {
+        self.providers
+            .iter()
+            .find_map(|member| member.provider.node())
+    }
+}
+
+impl Multi {
+    /// Merges the provider of the subcommand with the additional providers
+    #[must_use]
+    pub fn new(primary: &ProviderType, also: &[ProviderType]) -> Self {
+        let mut providers = vec![Member {
+            name:     primary.into(),
+            provider: primary.get_single_impl(),
+            options:  None,
+        }];
+        for options in also {
+            providers.push(Member {
+                name:     options.into(),
+                provider: options.get_single_impl(),
+                options:  Some(options.clone()),
+            });
+        }
+
+        Self {
+            providers,
+            owners: HashMap::new(),
+            shell: None,
+        }
+    }
+
+    /// Gets a reference to the current shell
+    fn shell(&self) -> &Shell {
+        self.shell
+            .as_ref()
+            .expect("Shell must be initialized: invariant violated")
+    }
+
+    /// Determines whether an event of the provider at the given index should
+    /// be sent, recording which provider reported each target. Targets that
+    /// another provider already reported are skipped.
+    fn claim(&mut self, index: usize, name: &str, event: &CollectionEvent) -> bool {
+        match event {
+            CollectionEvent::Start { target, .. } => match self.owners.get(&target.id) {
+                Some(&owner) if owner != index => {
+                    let owner = self.providers[owner].name;
+                    self.shell().verbose(|sh| {
+                        sh.info(format!(
+                            "Skipping target {} from the {} provider since the {} provider \
+                             already collects it",
+                            target.id, name, owner
+                        ));
+                    });
+                    false
+                },
+                _ => {
+                    self.owners.insert(target.id.clone(), index);
+                    true
+                },
+            },
+            CollectionEvent::Stop(id) => match self.owners.get(id) {
+                Some(&owner) if owner == index => {
+                    self.owners.remove(id);
+                    true
+                },
+                _ => false,
+            },
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use crate::cli::RunCommand;
+use crate::polling::providers::{InitializationError, Provider, SystemdOptions};
+use crate::shared::{CollectionEvent, CollectionMethod, CollectionTarget};
+use crate::shell::Shell;
+use crate::util::{self, CgroupDriver, CgroupPath, CgroupVersion, ItemPool};
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+const PROVIDER_TYPE: &str = "systemd";
+
+/// Bus name and object path of the systemd manager
+const SYSTEMD_DESTINATION: &str = "org.freedesktop.systemd1";
+const MANAGER_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+
+/// Maximum number of seconds that each D-Bus call can take
+const BUS_TIMEOUT_SECS: u32 = 5;
+
+/// Provider that collects the systemd units matching a set of patterns (such
+/// as `myapp-*.service`), using the systemd D-Bus API to list the active units
+/// and resolve their cgroups. The D-Bus calls are made using `busctl`, which
+/// ships with systemd.
+pub struct Systemd {
+    id_pool:  ItemPool<String>,
+    patterns: Vec<String>,
+    user:     bool,
+    version:  Option<CgroupVersion>,
+    shell:    Option<Arc<Shell>>,
+}
+
+/// Possible errors that can occur during systemd provider initialization
+#[derive(Debug)]
+enum SystemdInitError {
+    InvalidCgroupMount,
+    VersionDetectionFailed,
+    BusFailed(BusError),
+}
+
+impl From<SystemdInitError> for InitializationError {
+    fn from(other: SystemdInitError) -> Self {
+        match other {
+            SystemdInitError::InvalidCgroupMount => Self {
+                original:   None,
+                suggestion: String::from(util::INVALID_CGROUP_MOUNT_MESSAGE),
+            },
+            SystemdInitError::VersionDetectionFailed => Self {
+                original:   None,
+                suggestion: String::from(
+                    "Could not detect the currently running cgroup version. Are cgroups mounted \
+                     in /sys/fs/cgroup (or at --cgroup-root)?",
+                ),
+            },
+            SystemdInitError::BusFailed(error) => Self {
+                original:   Some(error.into()),
+                suggestion: String::from(
+                    "Could not list units from systemd over D-Bus. Is systemd running, and is \
+                     busctl (which ships with systemd) installed?",
+                ),
+            },
+        }
+    }
+}
+
+/// Error that can occur when calling the systemd D-Bus API
+#[derive(Debug, thiserror::Error)]
+enum BusError {
+    #[error("could not run busctl: {0}")]
+    Spawn(#[source] io::Error),
+    #[error("busctl failed: {0}")]
+    Failed(String),
+    #[error("could not parse busctl output: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Reply to a D-Bus call, as printed by `busctl --json`
+#[derive(Debug, Deserialize)]
+struct BusReply<T> {
+    data: T,
+}
+
+/// Single unit returned by `ListUnitsByPatterns`: its name, description, load
+/// state, active state, sub state, followed unit, object path, queued job id,
+/// job type, and job object path
+type UnitTuple = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    u32,
+    String,
+    String,
+);
+
+/// Active unit that matches one of the patterns
+#[derive(Clone, Debug, PartialEq)]
+struct Unit {
+    name:        String,
+    description: String,
+    object_path: String,
+}
+
+/// Metadata about a unit included in the log file header
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct UnitMetadata<'a> {
+    description: &'a str,
+    object_path: &'a str,
+}
+
+impl Provider for Systemd {
+    fn initialize(
+        &mut self,
+        opts: &RunCommand,
+        shell: Arc<Shell>,
+    ) -> Result<(), InitializationError> {
+        self.shell = Some(Arc::clone(&shell));
+        self.shell().status("Initializing", "systemd provider");
+
+        let inner_opts: SystemdOptions = opts.provider.clone().into_inner_systemd();
+        self.patterns = inner_opts.units;
+        self.user = inner_opts.user;
+
+        match self.try_init() {
+            Ok(()) => Ok(()),
+            Err(init_err) => Err(init_err.into()),
+        }
+    }
+
+    fn poll(&mut self) -> Result<Vec<CollectionEvent>, Error> {
+        let version = self
+            .version
+            .expect("Cgroup version must be initialized: invariant violated");
+
+        let units: BTreeMap<String, Unit> = self
+            .list_units()?
+            .into_iter()
+            .map(|unit| (unit.name.clone(), unit))
+            .collect();
+
+        // Units are only pooled once their start event is created (or once
+        // it's known that they don't have a cgroup), so that units whose
+        // cgroup couldn't be read are retried on the next poll
+        let mut starts: Vec<CollectionEvent> = Vec::new();
+        let mut ids: Vec<String> = Vec::with_capacity(units.len());
+        for (name, unit) in &units {
+            if self.id_pool.contains(name) {
+                ids.push(name.clone());
+                continue;
+            }
+
+            match self.try_start(unit, version) {
+                Ok(Some(start)) => starts.push(start),
+                Ok(None) => self.shell().verbose(|sh| {
+                    sh.info(format!(
+                        "Skipping unit {} since it doesn't have a cgroup",
+                        unit.name
+                    ));
+                }),
+                Err(err) => {
+                    self.shell().warn(format!(
+                        "Could not start collection for unit {}: {}",
+                        unit.name, err
+                    ));
+                    continue;
+                },
+            }
+            ids.push(name.clone());
+        }
+        let (added, removed) = self.id_pool.update(ids);
+
+        let (added_len, removed_len) = (added.len(), removed.len());
+        let mut events: Vec<CollectionEvent> = Vec::with_capacity(starts.len() + removed_len);
+        events.extend(removed.into_iter().map(CollectionEvent::Stop));
+        events.extend(starts);
+
+        if added_len != 0 || removed_len != 0 {
+            self.shell().verbose(|sh| {
+                sh.info(format!(
+                    "Received {} (+{}, -{}) units from systemd",
+                    units.len(),
+                    added_len,
+                    removed_len
+                ));
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+impl Default for Systemd {
+    fn default() -> Self { Self::new() }
+}
+
+impl Systemd {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            id_pool:  ItemPool::new(),
+            patterns: Vec::new(),
+            user:     false,
+            version:  None,
+            shell:    None,
+        }
+    }
+
+    /// Attempts to initialize the systemd provider, failing if the needed
+    /// cgroups aren't mounted properly or if systemd can't be reached
+    fn try_init(&mut self) -> Result<(), SystemdInitError> {
+        if !util::cgroups_mounted_properly() {
+            return Err(SystemdInitError::InvalidCgroupMount);
+        }
+
+        let version =
+            CgroupVersion::try_resolve().ok_or(SystemdInitError::VersionDetectionFailed)?;
+        self.shell()
+            .info(format!("Identified {} as cgroup version", version));
+        self.version = Some(version);
+
+        // List the units once to make sure that systemd can be reached
+        self.list_units().map_err(SystemdInitError::BusFailed)?;
+
+        Ok(())
+    }
+
+    /// Lists the active units that match any of the patterns
+    fn list_units(&self) -> Result<Vec<Unit>, BusError> {
+        let mut args = vec![
+            String::from("call"),
+            String::from(SYSTEMD_DESTINATION),
+            String::from(MANAGER_PATH),
+            String::from(MANAGER_INTERFACE),
+            String::from("ListUnitsByPatterns"),
+            String::from("asas"),
+            String::from("1"),
+            String::from("active"),
+            self.patterns.len().to_string(),
+        ];
+        args.extend(self.patterns.iter().cloned());
+
+        let reply: BusReply<(Vec<UnitTuple>,)> = self.call(&args)?;
+        let (units,) = reply.data;
+        Ok(units
+            .into_iter()
+            .map(|(name, description, _, _, _, _, object_path, ..)| Unit {
+                name,
+                description,
+                object_path,
+            })
+            .collect())
+    }
+
+    /// Gets the cgroup of the unit (relative to the cgroup root), if it has
+    /// one
+    fn get_cgroup(&self, unit: &Unit) -> Result<Option<PathBuf>, BusError> {
+        // Units of other types (such as targets) don't have cgroups
+        let interface = cgroup_interface(&unit.name);
+        let cgroup: String = match interface {
+            Some(interface) => {
+                let args = [
+                    "get-property",
+                    SYSTEMD_DESTINATION,
+                    &unit.object_path,
+                    interface,
+                    "ControlGroup",
+                ];
+                self.call::<BusReply<String>, _>(&args)?.data
+            },
+            None => String::new(),
+        };
+        match cgroup.trim_start_matches('/') {
+            "" => Ok(None),
+            cgroup => Ok(Some(PathBuf::from(cgroup))),
+        }
+    }
+
+    /// Calls the D-Bus API of the system (or user) service manager using
+    /// `busctl`, parsing its JSON output
+    fn call<T, A>(&self, args: &[A]) -> Result<T, BusError>
+    where
+        T: for<'de> Deserialize<'de>,
+        A: AsRef<std::ffi::OsStr>,
+    {
+        let mut command = Command::new("busctl");
+        if self.user {
+            command.arg("--user");
+        }
+        let output = command
+            .arg("--json=short")
+            .arg(format!("--timeout={}", BUS_TIMEOUT_SECS))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(BusError::Spawn)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(BusError::Failed(stderr.trim().to_owned()));
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    /// Creates a collection start event for a unit, if it has a cgroup
+    fn try_start(
+        &self,
+        unit: &Unit,
+        version: CgroupVersion,
+    ) -> Result<Option<CollectionEvent>, Error> {
+        match self.get_cgroup(unit)? {
+            Some(path) => Ok(Some(make_start_event(unit, path, version)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets a reference to the current shell
+    fn shell(&self) -> &Shell {
+        self.shell
+            .as_ref()
+            .expect("Shell must be initialized: invariant violated")
+    }
+}
+
+/// Creates a collection start event for a unit in the given cgroup
+fn make_start_event(
+    unit: &Unit,
+    path: PathBuf,
+    version: CgroupVersion,
+) -> Result<CollectionEvent, Error> {
+    let metadata = serde_yaml::to_value(UnitMetadata {
+        description: &unit.description,
+        object_path: &unit.object_path,
+    })?;
+    let cgroup = CgroupPath {
+        driver: CgroupDriver::infer(&path),
+        path,
+        version,
+    };
+    let method = CollectionMethod::for_cgroup(cgroup);
+
+    Ok(CollectionEvent::Start {
+        method,
+        target: CollectionTarget {
+            provider:  PROVIDER_TYPE,
+            metadata:  Some(metadata),
+            name:      unit.name.clone(),
+            poll_time: util::nano_ts(),
+            id:        unit.name.clone(),
+            group:     None,
+            file_name: None,
+        },
+    })
+}
+
+/// Gets the D-Bus interface with the `ControlGroup` property of a unit, based
+/// on its type, if units of that type have cgroups
+fn cgroup_interface(unit: &str) -> Option<&'static str> {
+    match unit.rsplit('.').next()? {
+        "service" => Some("org.freedesktop.systemd1.Service"),
+        "scope" => Some("org.freedesktop.systemd1.Scope"),
+        "slice" => Some("org.freedesktop.systemd1.Slice"),
+        "socket" => Some("org.freedesktop.systemd1.Socket"),
+        "mount" => Some("org.freedesktop.systemd1.Mount"),
+        "swap" => Some("org.freedesktop.systemd1.Swap"),
+        _ => None,
+    }
+}
@@ -0,0 +1,172 @@
+use crate::cli::ParseFailure;
+use crate::util;
+use serde_yaml::{Mapping, Value};
+use std::str::FromStr;
+
+/// Rules that are applied after any `--redact` rules, unless
+/// `--no-default-redactions` is given. These cover the fields of container
+/// metadata that commonly contain secrets passed in by the user.
+pub const DEFAULT_RULES: &[&str] = &[
+    "hash=Command",
+    "hash=*.Cmd",
+    "hash=*.Args",
+    "omit=*Env",
+    "omit=Labels.*password*",
+    "omit=Labels.*secret*",
+    "omit=Labels.*token*",
+];
+
+/// Prefix of the values that replace hashed fields
+const HASH_PREFIX: &str = "fnv1a:";
+
+/// Action taken on the metadata fields that a rule matches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RedactAction {
+    /// Replaces the field with a hash of its serialized value, so that equal
+    /// values can still be recognized across log files
+    Hash,
+    /// Removes the field entirely
+    Omit,
+    /// Keeps the field as-is (used to override later rules)
+    Keep,
+}
+
+/// Single `--redact` rule, parsed from `hash=<glob>`, `omit=<glob>`, or
+/// `keep=<glob>`. The glob is matched (case-insensitively) against the dotted
+/// path of each field in the metadata, such as `Command` or
+/// `Labels.com.example.password`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedactRule {
+    pub action:  RedactAction,
+    pub pattern: String,
+}
+
+impl FromStr for RedactRule {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseFailure::new(String::from("redaction rule"), s.to_owned());
+        let (action, pattern) = match s.split_once('=') {
+            Some(("hash", pattern)) => (RedactAction::Hash, pattern),
+            Some(("omit", pattern)) => (RedactAction::Omit, pattern),
+            Some(("keep", pattern)) => (RedactAction::Keep, pattern),
+            _ => return Err(invalid()),
+        };
+
+        if pattern.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            action,
+            pattern: pattern.to_lowercase(),
+        })
+    }
+}
+
+impl RedactRule {
+    /// Determines whether the rule matches the (lowercased) field path
+    #[must_use]
+    pub fn matches(&self, path: &str) -> bool { util::glob_match(&self.pattern, path) }
+}
+
+/// Resolves the full list of rules to apply, in order of precedence
+#[must_use]
+pub fn resolve_rules(rules: &[RedactRule], defaults: bool) -> Vec<RedactRule> {
+    let mut resolved = rules.to_vec();
+    if defaults {
+        resolved.extend(
+            DEFAULT_RULES
+                .iter()
+                .map(|rule| rule.parse().expect("default redaction rule is valid")),
+        );
+    }
+    resolved
+}
+
+/// Sanitizes serialized metadata in place, applying the first rule that
+/// matches each field. Fields within a redacted field are not visited.
+pub fn sanitize(metadata: &mut Value, rules: &[RedactRule]) {
+    if rules.is_empty() {
+        return;
+    }
+
+    if let Value::Mapping(mapping) = metadata {
+        sanitize_mapping(mapping, "", rules);
+    }
+}
+
+fn sanitize_mapping(mapping: &mut Mapping, prefix: &str, rules: &[RedactRule]) {
+    let keys: Vec<Value> = mapping.iter().map(|(key, _)| key.clone()).collect();
+    for key in keys {
+        let name = match &key {
+            Value::String(name) => name.to_lowercase(),
+            _ => continue,
+        };
+        let path = match prefix {
+            "" => name,
+            _ => format!("{}.{}", prefix, name),
+        };
+
+        match rules
+            .iter()
+            .find(|rule| rule.matches(&path))
+            .map(|r| r.action)
+        {
+            Some(RedactAction::Omit) => {
+                mapping.remove(&key);
+            },
+            Some(RedactAction::Hash) => {
+                if let Some(value) = mapping.get_mut(&key) {
+                    *value = hash_value(value);
+                }
+            },
+            Some(RedactAction::Keep) => {},
+            None => {
+                if let Some(value) = mapping.get_mut(&key) {
+                    sanitize_value(value, &path, rules);
+                }
+            },
+        }
+    }
+}
+
+fn sanitize_value(value: &mut Value, path: &str, rules: &[RedactRule]) {
+    match value {
+        Value::Mapping(mapping) => sanitize_mapping(mapping, path, rules),
+        // Elements of a sequence share the path of the sequence itself
+        Value::Sequence(elements) => {
+            for element in elements {
+                sanitize_value(element, path, rules);
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Replaces a value with a hash of its serialized form. Null values are left
+/// as-is, since they don't contain anything to redact.
+fn hash_value(value: &Value) -> Value {
+    if let Value::Null = value {
+        return Value::Null;
+    }
+
+    let serialized = serde_yaml::to_string(value).unwrap_or_default();
+    Value::String(format!(
+        "{}{:016x}",
+        HASH_PREFIX,
+        fnv1a(serialized.as_bytes())
+    ))
+}
+
+/// 64-bit FNV-1a hash, which (unlike the standard library's hasher) is stable
+/// across Rust versions and so can be compared between runs. Note that this
+/// is not a cryptographic hash; it only prevents values from being read off
+/// of the log file directly.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
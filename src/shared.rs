@@ -1,5 +1,5 @@
 use crate::shell::Shell;
-use crate::util::CgroupPath;
+use crate::util::{CgroupPath, CgroupVersion};
 use bus::BusReader;
 use serde::Serialize;
 use std::sync::Arc;
@@ -17,7 +17,9 @@ pub struct IntervalWorkerContext {
 pub type Id = String;
 
 /// Type of event pushed to common channel between the polling and collection
-/// threads, used to update the state of the collection
+/// threads, used to update the state of the collection. Start events are only
+/// sent when targets appear, so they aren't boxed despite their size.
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum CollectionEvent {
     Stop(Id),
@@ -32,6 +34,28 @@ pub enum CollectionEvent {
 pub enum CollectionMethod {
     LinuxCgroupV1(CgroupPath),
     LinuxCgroupV2(CgroupPath),
+    /// Cgroup v1 cgroup in the hybrid layout, whose statistics are read from
+    /// the cgroup v2 hierarchy for the controllers that are only enabled there
+    LinuxCgroupHybrid(CgroupPath),
+    /// Host-wide statistics, collected from the root cgroup (of either
+    /// version) along with `/proc/stat` and `/proc/meminfo`
+    Host(CgroupPath),
+    /// Generated statistics that don't come from any cgroup, determined by
+    /// the given seed
+    Synthetic(u64),
+}
+
+impl CollectionMethod {
+    /// Gets the method that collects the given cgroup, which depends on its
+    /// version (and on whether it is in the hybrid layout, for cgroup v1)
+    #[must_use]
+    pub fn for_cgroup(cgroup: CgroupPath) -> Self {
+        match cgroup.version {
+            CgroupVersion::V1 if cgroup.hybrid().is_some() => Self::LinuxCgroupHybrid(cgroup),
+            CgroupVersion::V1 => Self::LinuxCgroupV1(cgroup),
+            CgroupVersion::V2 => Self::LinuxCgroupV2(cgroup),
+        }
+    }
 }
 
 /// Single container/pod/process/other entity that represents a single target
@@ -49,4 +73,18 @@ pub struct CollectionTarget {
     pub metadata:  Option<serde_yaml::Value>,
     /// Time of polling
     pub poll_time: u128,
+    /// Name of the group of targets to share a log file with, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group:     Option<String>,
+    /// Name to create the target's log files with instead of its id, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+}
+
+impl CollectionTarget {
+    /// Gets the name that the target's own log files are created with (unless
+    /// it shares the log file of its group): the file name given by its
+    /// provider, or its id
+    #[must_use]
+    pub fn log_name(&self) -> &str { self.file_name.as_deref().unwrap_or(&self.id) }
 }
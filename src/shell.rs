@@ -1,9 +1,11 @@
 use crate::cli::ParseFailure;
 use crate::util;
 use clap::Clap;
+use serde::Serialize;
 use std::fmt;
 use std::io::{self, Write};
 use std::sync::Mutex;
+use std::thread;
 use termcolor::{self, Color, ColorSpec, StandardStream, WriteColor};
 
 /// Inspiration/partial implementations taken from the Cargo source at
@@ -34,6 +36,16 @@ pub struct Options {
     /// Color display mode for stdout/stderr output
     #[clap(short = 'c', long = "color", default_value = "auto", global = true)]
     pub color_mode: ColorMode,
+
+    /// Format of stdout/stderr output (human, json, or journald)
+    #[clap(long = "log-format", default_value = "human", global = true)]
+    pub log_format: LogFormat,
+
+    /// Whether to print all output to stderr as JSON objects (one per line)
+    /// that include the level, timestamp, and thread name of each message,
+    /// for parsing by supervisors (overrides the log format)
+    #[clap(long = "shell-json", global = true)]
+    pub shell_json: bool,
 }
 
 impl Verbosity {
@@ -71,6 +83,74 @@ impl std::str::FromStr for ColorMode {
     }
 }
 
+/// Format of the process's output, controllable via a CLI flag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Colored, justified text meant to be read in a terminal
+    Human,
+    /// One JSON object per line
+    Json,
+    /// Plain text lines with syslog priority prefixes (such as `<4>`), which
+    /// journald parses when rAdvisor is run as a systemd service
+    Journald,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "journald" => Ok(Self::Journald),
+            _ => Err(ParseFailure::new(String::from("log format"), s.to_owned())),
+        }
+    }
+}
+
+/// Severity of a single message, used for structured output formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Error,
+    Warning,
+    Notice,
+    Info,
+}
+
+impl Level {
+    /// Gets the corresponding syslog priority
+    /// (see <https://www.freedesktop.org/software/systemd/man/sd-daemon.html>)
+    const fn priority(self) -> u8 {
+        match self {
+            Self::Error => 3,
+            Self::Warning => 4,
+            Self::Notice => 5,
+            Self::Info => 6,
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Notice => "notice",
+            Self::Info => "info",
+        }
+    }
+}
+
+/// Single message printed in the JSON output format
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: u128,
+    level:     &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread:    Option<&'a str>,
+    status:    &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message:   Option<String>,
+}
+
 impl ColorMode {
     fn into_termcolor(self, stream: atty::Stream) -> termcolor::ColorChoice {
         match self {
@@ -90,6 +170,9 @@ impl ColorMode {
 /// Thread-safe handle to formatted stderr/stdout output (implements `Sync`)
 pub struct Shell {
     pub verbosity: Verbosity,
+    format:        LogFormat,
+    /// Whether all messages are printed to stderr, rather than only errors
+    stderr_only:   bool,
     out:           Mutex<OutSink>,
     err:           Mutex<OutSink>,
 }
@@ -101,8 +184,13 @@ impl Shell {
     #[must_use]
     pub fn new(opts: &Options) -> Self {
         Self {
-            verbosity: Verbosity::from_opts(opts),
-            out:       Mutex::new(OutSink::Stream {
+            verbosity:   Verbosity::from_opts(opts),
+            format:      match opts.shell_json {
+                true => LogFormat::Json,
+                false => opts.log_format,
+            },
+            stderr_only: opts.shell_json,
+            out:         Mutex::new(OutSink::Stream {
                 color_mode:  opts.color_mode,
                 is_tty:      atty::is(atty::Stream::Stdout),
                 stream_type: atty::Stream::Stdout,
@@ -110,7 +198,7 @@ impl Shell {
                     opts.color_mode.into_termcolor(atty::Stream::Stdout),
                 ),
             }),
-            err:       Mutex::new(OutSink::Stream {
+            err:         Mutex::new(OutSink::Stream {
                 color_mode:  opts.color_mode,
                 is_tty:      atty::is(atty::Stream::Stderr),
                 stream_type: atty::Stream::Stderr,
@@ -126,32 +214,50 @@ impl Shell {
     #[must_use]
     pub fn from_write(stdout: Box<dyn Write + Send>, stderr: Box<dyn Write + Send>) -> Self {
         Self {
-            out:       Mutex::new(OutSink::Write(stdout)),
-            err:       Mutex::new(OutSink::Write(stderr)),
-            verbosity: Verbosity::Verbose,
+            out:         Mutex::new(OutSink::Write(stdout)),
+            err:         Mutex::new(OutSink::Write(stderr)),
+            verbosity:   Verbosity::Verbose,
+            format:      LogFormat::Human,
+            stderr_only: false,
         }
     }
 
+    /// Prints all messages to stderr, such as when stdout is used for output
+    #[must_use]
+    pub const fn with_stderr_only(mut self) -> Self {
+        self.stderr_only = true;
+        self
+    }
+
     /// Shortcut to right-align and color green a status message.
     pub fn status<T, U>(&self, status: T, message: U)
     where
         T: fmt::Display,
         U: fmt::Display,
     {
-        self.print(&status, Some(&message), Color::Green, None, true);
+        self.print(
+            Level::Notice,
+            &status,
+            Some(&message),
+            Color::Green,
+            None,
+            true,
+        );
     }
 
     pub fn status_header<T>(&self, status: T)
     where
         T: fmt::Display,
     {
-        self.print(&status, None, Color::Cyan, None, true);
+        self.print(Level::Notice, &status, None, Color::Cyan, None, true);
     }
 
     /// Prints a message, where the status will have `color` color, and can be
     /// justified. The messages follows without color.
+    /// Structured output formats ignore the colors and justification.
     fn print(
         &self,
+        level: Level,
         status: &dyn fmt::Display,
         message: Option<&dyn fmt::Display>,
         status_color: Color,
@@ -159,11 +265,17 @@ impl Shell {
         justified: bool,
     ) {
         if self.verbosity != Verbosity::Quiet {
-            let mut out = self
-                .out
+            let sink = match self.stderr_only {
+                true => &self.err,
+                false => &self.out,
+            };
+            let mut out = sink
                 .lock()
-                .expect("Could not unwrap stdout lock: mutex poisoned");
-            let _result = out.print(status, message, status_color, text_color, justified);
+                .expect("Could not unwrap output lock: mutex poisoned");
+            let _result = match self.format {
+                LogFormat::Human => out.print(status, message, status_color, text_color, justified),
+                format => out.print_structured(format, level, status, message),
+            };
         }
     }
 
@@ -173,26 +285,43 @@ impl Shell {
             .err
             .lock()
             .expect("Could not unwrap stderr lock: mutex poisoned");
-        let _result = err.print(
-            &"(error)",
-            Some(&message),
-            Color::Red,
-            Some(Color::Red),
-            true,
-        );
+        let _result = match self.format {
+            LogFormat::Human => err.print(
+                &"(error)",
+                Some(&message),
+                Color::Red,
+                Some(Color::Red),
+                true,
+            ),
+            format => err.print_structured(format, Level::Error, &"error", Some(&message)),
+        };
     }
 
     /// Prints an amber 'warning' message.
     pub fn warn<T: fmt::Display>(&self, message: T) {
         match self.verbosity {
             Verbosity::Quiet => (),
-            _ => self.print(&"(warning)", Some(&message), Color::Yellow, None, true),
+            _ => self.print(
+                Level::Warning,
+                &"(warning)",
+                Some(&message),
+                Color::Yellow,
+                None,
+                true,
+            ),
         };
     }
 
     /// Prints a cyan 'info' message.
     pub fn info<T: fmt::Display>(&self, message: T) {
-        self.print(&"(info)", Some(&message), Color::Cyan, None, true);
+        self.print(
+            Level::Info,
+            &"(info)",
+            Some(&message),
+            Color::Cyan,
+            None,
+            true,
+        );
     }
 
     /// Gets the current color mode.
@@ -324,6 +453,52 @@ impl OutSink {
         Ok(())
     }
 
+    /// Prints out a message with a status as a single structured record,
+    /// without any colors
+    fn print_structured(
+        &mut self,
+        format: LogFormat,
+        level: Level,
+        status: &dyn fmt::Display,
+        message: Option<&dyn fmt::Display>,
+    ) -> io::Result<()> {
+        let status = status.to_string();
+        // Strip the parentheses used to set apart levels in human output
+        let status = status.trim_start_matches('(').trim_end_matches(')');
+        let message = message.map(ToString::to_string);
+        let w: &mut dyn Write = match self {
+            Self::Stream { stream, .. } => stream,
+            Self::Write(w) => w,
+        };
+
+        match format {
+            LogFormat::Json => {
+                let current = thread::current();
+                let record = JsonRecord {
+                    timestamp: util::nano_ts(),
+                    level: level.name(),
+                    thread: current.name(),
+                    status,
+                    message,
+                };
+                serde_json::to_writer(&mut *w, &record)?;
+                writeln!(w)
+            },
+            _ => {
+                // Journald treats each line as a separate entry,
+                // so every line needs its own priority prefix
+                let text = match message {
+                    Some(message) => format!("{}: {}", status, message),
+                    None => String::from(status),
+                };
+                for line in text.lines() {
+                    writeln!(w, "<{}>{}", level.priority(), line)?;
+                }
+                Ok(())
+            },
+        }
+    }
+
     /// Gets width of terminal, if applicable
     #[must_use]
     fn width(&self) -> Option<usize> {
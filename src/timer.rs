@@ -1,11 +1,10 @@
-// Allow using Mutex<bool> to support Mutex/Condvar pattern
-#![allow(clippy::mutex_atomic)]
-
-use std::sync::atomic::{AtomicBool, Ordering};
+use crate::util;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Represents a timer that can be iterated on and will block until either
 /// stopped or signalled by its worker thread to emit another tick. The specific
@@ -14,6 +13,8 @@ use std::time::Duration;
 /// timing mechanism) due to a separate thread doing the waiting. This means
 /// that this timer thread can signal and then immediately wait for the next
 /// interval without being slowed by the processing time for the previous tick.
+/// Aligned timers (see `Timer::aligned`) instead tick on multiples of the
+/// interval on the wall clock.
 pub struct Timer {
     pub duration: Duration,
     shared:       Arc<SharedTimerState>,
@@ -24,10 +25,64 @@ pub struct Stopper {
     shared: Arc<SharedTimerState>,
 }
 
+/// Represents a cloneable handle to change the interval of a running timer
+pub struct IntervalHandle {
+    shared: Arc<SharedTimerState>,
+}
+
+/// Represents a cloneable handle to make a timer tick early, such as when
+/// something happened that the thread iterating on it should handle right
+/// away
+pub struct Waker {
+    shared: Arc<SharedTimerState>,
+}
+
+/// Single tick of a timer, yielded when iterating on it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tick {
+    /// Time between when the tick was signalled by the timer thread and when
+    /// it was received by the listening thread
+    pub latency: Duration,
+    /// Number of ticks that were signalled while the listening thread was
+    /// still busy with the previous tick, and so were skipped
+    pub missed:  u64,
+}
+
+/// Tick that has been signalled but not yet received
+#[derive(Clone, Copy, Debug)]
+struct PendingTick {
+    signalled_at: Instant,
+    missed:       u64,
+}
+
+impl PendingTick {
+    /// Creates a new pending tick, taking into account the previous pending
+    /// tick if it was never received
+    fn after(previous: Option<Self>) -> Self {
+        match previous {
+            // Keep the time of the earliest skipped tick, since the listening
+            // thread has been behind since then
+            Some(previous) => Self {
+                signalled_at: previous.signalled_at,
+                missed:       previous.missed + 1,
+            },
+            None => Self {
+                signalled_at: Instant::now(),
+                missed:       0,
+            },
+        }
+    }
+}
+
 /// Shared concurrency control data structures used to synchronize a timer
 struct SharedTimerState {
     stopping:    AtomicBool,
-    lock:        Mutex<bool>,
+    /// Current interval between ticks, in nanoseconds
+    interval:    AtomicU64,
+    /// Whether ticks are aligned to multiples of the interval on the wall
+    /// clock
+    aligned:     bool,
+    lock:        Mutex<Option<PendingTick>>,
     signal_tick: Condvar,
     tx_stop:     Mutex<Sender<()>>,
 }
@@ -40,31 +95,62 @@ pub trait Stoppable {
 impl Timer {
     #[must_use]
     pub fn new<A: AsRef<str>>(dur: Duration, name: A) -> (Self, Stopper) {
+        Self::start(dur, name.as_ref(), false)
+    }
+
+    /// Creates a timer whose ticks are aligned to multiples of the interval
+    /// on the wall clock (since the Unix epoch), such as every 100ms on the
+    /// 100ms mark, so that the ticks of timers in different processes (or on
+    /// different machines with synchronized clocks) line up. Since the time
+    /// until the next tick is computed from the wall clock each time, the
+    /// ticks don't drift away from the multiples.
+    #[must_use]
+    pub fn aligned<A: AsRef<str>>(dur: Duration, name: A) -> (Self, Stopper) {
+        Self::start(dur, name.as_ref(), true)
+    }
+
+    fn start(dur: Duration, name: &str, aligned: bool) -> (Self, Stopper) {
         let (tx_stop, rx_stop): (Sender<()>, Receiver<()>) = mpsc::channel();
         let shared = Arc::new(SharedTimerState {
-            stopping:    AtomicBool::new(false),
-            lock:        Mutex::new(false),
+            stopping: AtomicBool::new(false),
+            interval: AtomicU64::new(duration_to_nanos(dur)),
+            aligned,
+            lock: Mutex::new(None),
             signal_tick: Condvar::new(),
-            tx_stop:     Mutex::new(tx_stop),
+            tx_stop: Mutex::new(tx_stop),
         });
 
         // Spawn the timer thread
         let shared_c = Arc::clone(&shared);
         thread::Builder::new()
-            .name(format!("timer-{}", name.as_ref()))
+            .name(format!("timer-{}", name))
             .spawn(move || {
+                // Aligned timers wait until the first multiple of the interval
+                // before the first tick
+                if aligned {
+                    let interval = shared_c.interval.load(Ordering::Relaxed);
+                    if rx_stop.recv_timeout(until_aligned(interval, false)).is_ok() {
+                        return;
+                    }
+                }
+
                 loop {
                     // Signal the receiving thread to wake up and perform the timer
                     // action (without stopping)
                     let mut signal = shared_c.lock.lock().unwrap();
-                    *signal = true;
+                    *signal = Some(PendingTick::after(signal.take()));
                     shared_c.signal_tick.notify_one();
                     // Drop the mutex to prevent deadlock
                     drop(signal);
 
                     // Use recv_timeout as the sleep mechanism to allow for early
                     // waking
-                    let recv_result = rx_stop.recv_timeout(dur);
+                    let interval = shared_c.interval.load(Ordering::Relaxed);
+                    let wait = match shared_c.aligned {
+                        true => until_aligned(interval, true),
+                        false => Duration::from_nanos(interval),
+                    };
+                    let recv_result = rx_stop.recv_timeout(wait);
                     if recv_result.is_ok() {
                         // An empty message was sent on rx_stop, so stop the timer
                         // immediately
@@ -83,6 +169,68 @@ impl Timer {
             Stopper { shared: shared_c },
         )
     }
+
+    /// Gets a handle that can change the interval of the timer while it is
+    /// being iterated on
+    #[must_use]
+    pub fn interval_handle(&self) -> IntervalHandle {
+        IntervalHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Gets a handle that can make the timer tick early
+    #[must_use]
+    pub fn waker(&self) -> Waker {
+        Waker {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Waker {
+    /// Signals a tick right away, unless one is already pending. The timer
+    /// keeps ticking on its usual schedule afterwards.
+    pub fn wake(&self) {
+        let mut signal = self.shared.lock.lock().unwrap();
+        if signal.is_none() {
+            *signal = Some(PendingTick::after(None));
+            self.shared.signal_tick.notify_one();
+        }
+    }
+}
+
+impl IntervalHandle {
+    /// Gets the current interval between ticks
+    #[must_use]
+    pub fn get(&self) -> Duration {
+        Duration::from_nanos(self.shared.interval.load(Ordering::Relaxed))
+    }
+
+    /// Changes the interval between ticks, starting after the next tick
+    pub fn set(&self, dur: Duration) {
+        self.shared
+            .interval
+            .store(duration_to_nanos(dur), Ordering::Relaxed);
+    }
+}
+
+/// Converts a duration to nanoseconds, saturating at the maximum
+fn duration_to_nanos(dur: Duration) -> u64 { u64::try_from(dur.as_nanos()).unwrap_or(u64::MAX) }
+
+/// Gets the time until the wall clock reaches the next multiple of the
+/// interval. If a tick was just signalled, it is taken to be the tick of the
+/// closest multiple, so that a tick signalled slightly before its multiple
+/// (such as after the wall clock was slewed forwards) isn't followed by
+/// another tick right away.
+fn until_aligned(interval: u64, ticked: bool) -> Duration {
+    let interval = u128::from(interval.max(1));
+    let remaining = interval - util::nano_ts() % interval;
+    let remaining = match ticked && remaining < interval / 2 {
+        true => remaining + interval,
+        false => remaining,
+    };
+    Duration::from_nanos(u64::try_from(remaining).unwrap_or(u64::MAX))
 }
 
 /// Performs the internal logic to stop and then signal an update to the
@@ -90,7 +238,7 @@ impl Timer {
 fn stop_timer(shared: &SharedTimerState) {
     shared.stopping.store(true, Ordering::SeqCst);
     let mut signal = shared.lock.lock().unwrap();
-    *signal = true;
+    *signal = Some(PendingTick::after(signal.take()));
     drop(signal);
 
     let tx_stop = shared.tx_stop.lock().unwrap();
@@ -126,23 +274,42 @@ impl Drop for Timer {
 }
 
 impl Iterator for Timer {
-    type Item = ();
+    type Item = Tick;
 
     /// Blocks the current thread until the next timer action, or returns None
     /// if the timer has stopped. Called by the listening thread
     fn next(&mut self) -> Option<Self::Item> {
         let mut next_tick = self.shared.lock.lock().unwrap();
-        while !*next_tick {
+        while next_tick.is_none() {
             next_tick = self.shared.signal_tick.wait(next_tick).unwrap();
         }
-        *next_tick = false;
+        let pending = next_tick.take().unwrap();
 
-        // If stopping was flagged, then stop. Else, return an empty option to
-        // yield to the caller and let them process the next tick
+        // If stopping was flagged, then stop. Else, yield to the caller and
+        // let them process the next tick
         if self.shared.stopping.load(Ordering::SeqCst) {
             None
         } else {
-            Some(())
+            Some(Tick {
+                latency: pending.signalled_at.elapsed(),
+                missed:  pending.missed,
+            })
+        }
+    }
+}
+
+impl Clone for IntervalHandle {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Clone for Waker {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
         }
     }
 }
@@ -0,0 +1,143 @@
+use crate::util::remap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ptr;
+
+/// Number of CPUs that a CPU set can hold, which bounds the CPUs in a list
+const MAX_CPUS: usize = libc::CPU_SETSIZE as usize;
+
+/// Set of CPUs, given in the kernel's list format (such as `0-3,8,10-11`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuList(BTreeSet<usize>);
+
+impl CpuList {
+    /// Parses a CPU list in the kernel's list format, returning None if it is
+    /// malformed, empty, or includes a CPU beyond `CPU_SETSIZE`
+    #[must_use]
+    pub fn parse(list: &str) -> Option<Self> {
+        let mut cpus = BTreeSet::new();
+        for range in list.trim().split(',') {
+            let (start, end): (usize, usize) = match range.split_once('-') {
+                Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+                None => {
+                    let cpu = range.trim().parse().ok()?;
+                    (cpu, cpu)
+                },
+            };
+            if start > end || end >= MAX_CPUS {
+                return None;
+            }
+            cpus.extend(start..=end);
+        }
+
+        Some(Self(cpus)).filter(|list| !list.0.is_empty())
+    }
+
+    /// Iterates over the CPUs in the list, in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ { self.0.iter().copied() }
+}
+
+impl fmt::Display for CpuList {
+    /// Formats the list in the kernel's list format, collapsing consecutive
+    /// CPUs into ranges
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for cpu in self.iter() {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == cpu => *end = cpu,
+                _ => ranges.push((cpu, cpu)),
+            }
+        }
+
+        let ranges = ranges
+            .iter()
+            .map(|&(start, end)| match start == end {
+                true => start.to_string(),
+                false => format!("{}-{}", start, end),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+/// Scheduling settings of rAdvisor's own threads, which can be used to keep
+/// them from interfering with the workloads being measured
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThreadScheduling {
+    /// CPUs that the thread is pinned to
+    pub cpus:        Option<CpuList>,
+    /// Nice value of the thread, from -20 (highest priority) to 19 (lowest)
+    pub nice:        Option<i32>,
+    /// Priority of the thread under the real-time `SCHED_FIFO` policy, from 1
+    /// to 99
+    pub rt_priority: Option<i32>,
+}
+
+impl ThreadScheduling {
+    /// Whether no settings were given, in which case the thread keeps the
+    /// scheduling settings it inherited
+    #[must_use]
+    pub const fn is_default(&self) -> bool {
+        self.cpus.is_none() && self.nice.is_none() && self.rt_priority.is_none()
+    }
+
+    /// Applies the settings to the calling thread (on Linux, each of these
+    /// settings is per-thread)
+    pub fn apply(&self) -> io::Result<()> {
+        if let Some(cpus) = &self.cpus {
+            pin_current_thread(cpus)?;
+        }
+        if let Some(nice) = self.nice {
+            set_current_thread_nice(nice)?;
+        }
+        if let Some(priority) = self.rt_priority {
+            set_current_thread_rt_priority(priority)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets the CPU affinity of the calling thread to the CPUs in the list
+fn pin_current_thread(cpus: &CpuList) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        // Parsing bounds the CPUs in the list by the size of the set
+        for cpu in cpus.iter() {
+            libc::CPU_SET(cpu, &mut set);
+        }
+
+        // A pid of 0 refers to the calling thread
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), ptr::addr_of!(set)) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Sets the nice value of the calling thread. `setpriority` with
+/// `PRIO_PROCESS` changes a single thread when given its thread id.
+fn set_current_thread_nice(nice: i32) -> io::Result<()> {
+    unsafe {
+        let tid = libc::syscall(libc::SYS_gettid);
+        if libc::setpriority(libc::PRIO_PROCESS, remap(tid), nice) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Switches the calling thread to the real-time `SCHED_FIFO` policy with the
+/// given priority
+fn set_current_thread_rt_priority(priority: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    // A pid of 0 refers to the calling thread
+    if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, ptr::addr_of!(param)) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of heap allocations (including reallocations) made through
+/// `CountingAllocator`
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of bytes requested by the heap allocations made through
+/// `CountingAllocator`
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Global allocator that wraps the system allocator, counting each allocation
+/// so that the allocations made by a piece of code can be measured (see
+/// `allocations`). Counting only adds a pair of relaxed atomic increments to
+/// each allocation.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) { System.dealloc(ptr, layout) }
+}
+
+fn count(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+}
+
+/// Running totals of the heap allocations made so far
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Allocations {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+impl Allocations {
+    /// Gets the number of allocations made since the earlier totals were taken
+    #[must_use]
+    pub const fn since(self, earlier: Self) -> Self {
+        Self {
+            count: self.count.wrapping_sub(earlier.count),
+            bytes: self.bytes.wrapping_sub(earlier.bytes),
+        }
+    }
+}
+
+/// Whether heap allocations are counted, which `CountingAllocator` only does
+/// when it's the global allocator (as it is for the `radvisor` binary when
+/// built with the `bench` feature)
+#[must_use]
+pub const fn counts_allocations() -> bool { cfg!(feature = "bench") }
+
+/// Gets the totals of the heap allocations made so far by all threads. These
+/// are always 0 unless `CountingAllocator` is the global allocator (see
+/// `counts_allocations`).
+#[must_use]
+pub fn allocations() -> Allocations {
+    Allocations {
+        count: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    }
+}
@@ -1,6 +1,12 @@
+use crate::util::glob_match;
 use serde::Serialize;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
 use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 /// Docker cgroup driver used to orchestrate
 /// moving containers in and out of cgroups
@@ -11,6 +17,32 @@ pub enum CgroupDriver {
     Cgroupfs,
 }
 
+impl CgroupDriver {
+    /// Infers the driver that manages an arbitrary cgroup from its path:
+    /// cgroups managed by systemd are nested in units such as `*.slice`,
+    /// `*.scope`, or `*.service`
+    #[must_use]
+    pub fn infer<P: AsRef<Path>>(path: P) -> Self {
+        let systemd = path.as_ref().iter().any(|component| {
+            let extension = Path::new(component).extension().and_then(OsStr::to_str);
+            matches!(extension, Some("slice" | "scope" | "service"))
+        });
+        match systemd {
+            true => Self::Systemd,
+            false => Self::Cgroupfs,
+        }
+    }
+
+    /// Gets the other driver, which the cached driver might have changed to
+    #[must_use]
+    pub const fn other(self) -> Self {
+        match self {
+            Self::Systemd => Self::Cgroupfs,
+            Self::Cgroupfs => Self::Systemd,
+        }
+    }
+}
+
 impl fmt::Display for CgroupDriver {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -37,11 +69,46 @@ impl fmt::Display for CgroupVersion {
     }
 }
 
-pub const CGROUP_V2_CHECK_PATH: &str = "/sys/fs/cgroup/cgroup.controllers";
+/// File that only exists at the root of a cgroup v2 hierarchy
+pub const CGROUP_V2_CHECK_FILE: &str = "cgroup.controllers";
+
+/// Directory (relative to the cgroup root) that the cgroup v2 hierarchy is
+/// mounted at in the hybrid layout, where the cgroup v1 hierarchies are
+/// mounted at the cgroup root as usual, but controllers that aren't bound to
+/// any of them are available on the cgroup v2 hierarchy instead
+pub const CGROUP_V2_HYBRID_DIRECTORY: &str = "unified";
+
+/// Controllers whose statistics are collected from cgroup v2 hierarchies (the
+/// others, such as `hugetlb`, are only collected on cgroup v1)
+const CGROUP_V2_COLLECTED_CONTROLLERS: &[&str] = &["pids", "cpu", "memory", "io"];
+
+lazy_static::lazy_static! {
+    /// Controllers that are enabled on the cgroup v2 hierarchy of the hybrid
+    /// layout and collected from it, if there is one
+    static ref HYBRID_CONTROLLERS: Vec<String> = {
+        let path = cgroup_root()
+            .join(CGROUP_V2_HYBRID_DIRECTORY)
+            .join(CGROUP_V2_CHECK_FILE);
+        fs::read_to_string(path)
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter(|controller| CGROUP_V2_COLLECTED_CONTROLLERS.contains(controller))
+            .map(String::from)
+            .collect()
+    };
+}
+
+/// Gets the controllers that are enabled on the cgroup v2 hierarchy of the
+/// hybrid layout (from its `cgroup.controllers` file) and whose statistics
+/// are collected, which is empty if cgroups don't use the hybrid layout.
+/// Since a controller can only be bound to a single hierarchy, none of these
+/// are available on cgroup v1.
+#[must_use]
+pub fn hybrid_controllers() -> &'static [String] { &HYBRID_CONTROLLERS }
 
 impl CgroupVersion {
-    fn try_resolve() -> Option<Self> {
-        if Path::new(CGROUP_V2_CHECK_PATH).exists() {
+    pub(crate) fn try_resolve() -> Option<Self> {
+        if cgroup_root().join(CGROUP_V2_CHECK_FILE).exists() {
             return Some(Self::V2);
         }
 
@@ -62,6 +129,23 @@ where
     pub systemd:  &'s [S],
 }
 
+// Implemented manually since the slices can be copied regardless of whether
+// their items can
+impl<C, S> Clone for CgroupSlices<'_, '_, C, S>
+where
+    C: AsRef<str>,
+    S: AsRef<str>,
+{
+    fn clone(&self) -> Self { *self }
+}
+
+impl<C, S> Copy for CgroupSlices<'_, '_, C, S>
+where
+    C: AsRef<str>,
+    S: AsRef<str>,
+{
+}
+
 impl<C, S> CgroupSlices<'_, '_, C, S>
 where
     C: AsRef<str>,
@@ -88,10 +172,27 @@ fn join_slices(s: &[impl AsRef<str>]) -> PathBuf {
 /// Encapsulated behavior for lazy-resolution of Docker cgroup driver (systemd
 /// or cgroupfs). Works for cgroup v1 and v2
 pub struct CgroupManager {
-    driver:  Option<CgroupDriver>,
-    version: Option<CgroupVersion>,
+    /// User whose delegated cgroup v2 hierarchy the cgroups are resolved in
+    /// (see `CgroupManager::for_user`), if any
+    user:          Option<u32>,
+    driver:        Option<CgroupDriver>,
+    version:       Option<CgroupVersion>,
+    /// Number of consecutive resolutions that failed using the cached driver
+    /// while the cgroup existed using the other driver
+    failures:      u32,
+    /// Driver that was cached before it was last invalidated
+    invalidated:   Option<CgroupDriver>,
+    /// Driver change detected after invalidation that hasn't been reported
+    driver_change: Option<(CgroupDriver, CgroupDriver)>,
 }
 
+/// Number of consecutive cgroup resolution failures with the cached driver
+/// (for cgroups that exist using the other driver) after which the driver is
+/// detected again from scratch. This handles the container runtime being
+/// reconfigured to use a different driver mid-run, without re-detecting it
+/// just because the cgroups of a few targets are already gone.
+const DRIVER_INVALIDATION_THRESHOLD: u32 = 5;
+
 /// Resolved and existing cgroup path constructed from the construction methods
 /// on `CgroupManager`
 #[derive(Clone, Debug, PartialEq, Serialize)]
@@ -101,12 +202,81 @@ pub struct CgroupPath {
     pub version: CgroupVersion,
 }
 
+impl CgroupPath {
+    /// Gets the ids of all processes in the cgroup and its descendants (such
+    /// as the containers of a pod, whose own cgroup has no processes), from
+    /// their `cgroup.procs`, in ascending order. For cgroup v1, this uses the
+    /// first subsystem that the cgroup exists in.
+    #[must_use]
+    pub fn processes(&self) -> Vec<u32> {
+        let roots = match self.version {
+            CgroupVersion::V1 => CGROUP_V1_SUBSYSTEMS
+                .iter()
+                .map(|subsystem| cgroup_root().join(subsystem))
+                .collect(),
+            CgroupVersion::V2 => vec![cgroup_root().to_path_buf()],
+        };
+        let directory = match roots
+            .into_iter()
+            .map(|root| root.join(&self.path))
+            .find(|directory| directory.is_dir())
+        {
+            Some(directory) => directory,
+            None => return Vec::new(),
+        };
+
+        // Descendants that are removed while they are walked are skipped
+        let mut processes = BTreeSet::new();
+        let mut pending = vec![directory];
+        while let Some(directory) = pending.pop() {
+            if let Ok(procs) = fs::read_to_string(directory.join("cgroup.procs")) {
+                processes.extend(procs.lines().filter_map(|pid| pid.parse::<u32>().ok()));
+            }
+            let children = fs::read_dir(&directory).into_iter().flatten().flatten();
+            pending.extend(
+                children
+                    .filter(|child| {
+                        child
+                            .file_type()
+                            .map_or(false, |file_type| file_type.is_dir())
+                    })
+                    .map(|child| child.path()),
+            );
+        }
+
+        processes.into_iter().collect()
+    }
+
+    /// Gets the same cgroup in the cgroup v2 hierarchy of the hybrid layout,
+    /// if this is a cgroup v1 cgroup that also exists there and any
+    /// controllers are enabled on it (see `hybrid_controllers`)
+    #[must_use]
+    pub fn hybrid(&self) -> Option<Self> {
+        if self.version != CgroupVersion::V1 || hybrid_controllers().is_empty() {
+            return None;
+        }
+
+        let path = Path::new(CGROUP_V2_HYBRID_DIRECTORY).join(&self.path);
+        match cgroup_root().join(&path).is_dir() {
+            true => Some(Self {
+                path,
+                driver: self.driver,
+                version: CgroupVersion::V2,
+            }),
+            false => None,
+        }
+    }
+}
+
 impl Default for CgroupManager {
     fn default() -> Self { Self::new() }
 }
 
 pub enum GetCgroupError {
     CgroupV1NotEnabled,
+    /// The manager resolves cgroups in a user's delegated hierarchy, which
+    /// only exists on cgroup v2
+    DelegationRequiresV2,
     VersionDetectionFailed,
     NotFound(PathBuf),
 }
@@ -116,11 +286,73 @@ impl CgroupManager {
     #[must_use]
     pub const fn new() -> Self {
         Self {
-            driver:  None,
-            version: None,
+            user:          None,
+            driver:        None,
+            version:       None,
+            failures:      0,
+            invalidated:   None,
+            driver_change: None,
+        }
+    }
+
+    /// Creates a new cgroup manager that resolves cgroups in the hierarchy
+    /// that systemd delegates to the given user's session (see
+    /// `user_session_cgroup`), where rootless container runtimes (such as
+    /// rootless Podman) create the cgroups of their containers. The slices
+    /// given to it are relative to the user's session instead of the cgroup
+    /// root.
+    #[must_use]
+    pub const fn for_user(uid: u32) -> Self {
+        Self {
+            user: Some(uid),
+            ..Self::new()
         }
     }
 
+    /// Constructs the path (relative to the cgroup root) of the hierarchy
+    /// that systemd delegates to the given user's session, which is the
+    /// cgroup of their service manager:
+    /// `user.slice/user-<uid>.slice/user@<uid>.service`
+    #[must_use]
+    pub fn user_session_cgroup(uid: u32) -> PathBuf {
+        [
+            String::from(USER_SLICE),
+            format!("user-{}.slice", uid),
+            format!("user@{}.service", uid),
+        ]
+        .iter()
+        .collect()
+    }
+
+    /// Detects the hierarchy delegated to the given user's session, which
+    /// only exists on cgroup v2 while the user is logged in (or lingering).
+    /// The session's cgroup is only considered delegated if it is owned by
+    /// the user, since the user can't create cgroups in it otherwise.
+    #[must_use]
+    pub fn detect_user_session(uid: u32) -> Option<CgroupPath> {
+        if CgroupVersion::try_resolve()? != CgroupVersion::V2 {
+            return None;
+        }
+
+        let path = Self::user_session_cgroup(uid);
+        let metadata = fs::metadata(cgroup_root().join(&path)).ok()?;
+        match metadata.is_dir() && metadata.uid() == uid {
+            true => Some(CgroupPath {
+                path,
+                driver: CgroupDriver::Systemd,
+                version: CgroupVersion::V2,
+            }),
+            false => None,
+        }
+    }
+
+    /// Gets the directory (relative to the cgroup root) that cgroups are
+    /// resolved in: the user's delegated hierarchy, or the cgroup root itself
+    fn root(&self) -> PathBuf {
+        self.user
+            .map_or_else(PathBuf::new, Self::user_session_cgroup)
+    }
+
     /// Joins together the given slices to make a target cgroup,
     /// selecting the appropriate list of slices depending on the driver.
     /// Ensures that the cgroup path exists before returning it.
@@ -128,7 +360,7 @@ impl CgroupManager {
     /// then this function also tries to detect them.
     ///
     /// Only works if cgroups are enabled,
-    /// and mounted in the filesystem at `LINUX_CGROUP_ROOT`;
+    /// and mounted in the filesystem at the cgroup root;
     /// otherwise returns `Err`.
     #[allow(clippy::needless_pass_by_value)]
     pub fn get_cgroup<C, S>(
@@ -143,48 +375,137 @@ impl CgroupManager {
             .get_version_or_resolve()
             .ok_or(GetCgroupError::VersionDetectionFailed)?;
 
-        match self.driver {
-            Some(driver) => {
-                // Pick the appropriate list of slices for the driver,
-                // and join them together to make the path.
-                let path: PathBuf = slices.pick_and_join(driver);
+        if self.user.is_some() && version != CgroupVersion::V2 {
+            return Err(GetCgroupError::DelegationRequiresV2);
+        }
+
+        if let Some(driver) = self.driver {
+            // Pick the appropriate list of slices for the driver,
+            // and join them together to make the path.
+            let path: PathBuf = self.root().join(slices.pick_and_join(driver));
+
+            // Make sure the cgroup exists before returning it
+            if cgroup_exists(Some(&path), version) {
+                self.failures = 0;
+                return Ok(CgroupPath {
+                    path,
+                    driver,
+                    version,
+                });
+            }
+
+            // Only count the failure if the cgroup exists using the other
+            // driver: otherwise, it's just gone (such as for a container
+            // that already exited), which says nothing about the driver
+            let other = self.root().join(slices.pick_and_join(driver.other()));
+            if !cgroup_exists(Some(&other), version) {
+                return Err(GetCgroupError::NotFound(path));
+            }
+
+            self.failures += 1;
+            if self.failures < DRIVER_INVALIDATION_THRESHOLD {
+                return Err(GetCgroupError::NotFound(path));
+            }
+
+            // The driver might have changed since it was detected,
+            // so try detecting it again from scratch
+            self.failures = 0;
+            self.driver = None;
+            self.invalidated = Some(driver);
+        }
+
+        self.detect_driver(&slices, version)
+    }
 
-                // Make sure the cgroup exists before returning it
+    /// Resolves the cgroups of many targets at once, returning the results in
+    /// the same order as the slices. Once the driver and version are known
+    /// (detecting them with the first target if needed), whether each cgroup
+    /// exists is checked in parallel; cgroups that couldn't be found are then
+    /// resolved again one at a time using `get_cgroup`, so that repeated
+    /// failures still cause the driver to be detected again.
+    pub fn get_cgroups<C, S>(
+        &mut self,
+        slices: &[CgroupSlices<'_, '_, C, S>],
+    ) -> Vec<Result<CgroupPath, GetCgroupError>>
+    where
+        C: AsRef<str> + Sync,
+        S: AsRef<str> + Sync,
+    {
+        let mut results = Vec::with_capacity(slices.len());
+        let mut remaining = slices;
+        while let (None, Some((first, rest))) = (self.driver, remaining.split_first()) {
+            results.push(self.get_cgroup(*first));
+            remaining = rest;
+        }
+
+        // Otherwise, every target was already resolved while detecting the driver
+        if let (Some(driver), Some(version)) = (self.driver, self.version) {
+            let root = self.root();
+            let found = super::parallel_map(remaining, |slices| {
+                let path = root.join(slices.pick_and_join(driver));
                 match cgroup_exists(Some(&path), version) {
-                    true => Ok(CgroupPath {
-                        path,
-                        driver,
-                        version,
-                    }),
-                    false => Err(GetCgroupError::NotFound(path)),
-                }
-            },
-            None => {
-                // Try to see if the systemd cgroup exists
-                let systemd_cgroup = join_slices(slices.systemd);
-                if cgroup_exists(Some(&systemd_cgroup), version) {
-                    self.driver = Some(CgroupDriver::Systemd);
-                    return Ok(CgroupPath {
-                        path: systemd_cgroup,
-                        driver: CgroupDriver::Systemd,
-                        version,
-                    });
+                    true => Some(path),
+                    false => None,
                 }
+            });
+            for (slices, found) in remaining.iter().zip(found) {
+                results.push(match found {
+                    Some(path) => {
+                        self.failures = 0;
+                        Ok(CgroupPath {
+                            path,
+                            driver,
+                            version,
+                        })
+                    },
+                    None => self.get_cgroup(*slices),
+                });
+            }
+        }
 
-                // Otherwise, try to see if the cgroupfs cgroup exists
-                let cgroupfs_cgroup = join_slices(slices.cgroupfs);
-                if cgroup_exists(Some(&cgroupfs_cgroup), version) {
-                    self.driver = Some(CgroupDriver::Cgroupfs);
-                    return Ok(CgroupPath {
-                        path: cgroupfs_cgroup,
-                        driver: CgroupDriver::Cgroupfs,
-                        version,
-                    });
+        results
+    }
+
+    /// Attempts to detect the driver by checking whether the cgroup exists
+    /// under each driver's list of slices, caching it if found
+    fn detect_driver<C, S>(
+        &mut self,
+        slices: &CgroupSlices<'_, '_, C, S>,
+        version: CgroupVersion,
+    ) -> Result<CgroupPath, GetCgroupError>
+    where
+        C: AsRef<str>,
+        S: AsRef<str>,
+    {
+        // Try to see if the systemd cgroup exists, and otherwise, try to see if
+        // the cgroupfs cgroup exists
+        let root = self.root();
+        let systemd_cgroup = root.join(join_slices(slices.systemd));
+        let candidates = [
+            (CgroupDriver::Systemd, systemd_cgroup.clone()),
+            (
+                CgroupDriver::Cgroupfs,
+                root.join(join_slices(slices.cgroupfs)),
+            ),
+        ];
+        for (driver, path) in candidates {
+            if cgroup_exists(Some(&path), version) {
+                self.driver = Some(driver);
+                if let Some(previous) = self.invalidated.take() {
+                    if previous != driver {
+                        self.driver_change = Some((previous, driver));
+                    }
                 }
 
-                Err(GetCgroupError::NotFound(systemd_cgroup))
-            },
+                return Ok(CgroupPath {
+                    path,
+                    driver,
+                    version,
+                });
+            }
         }
+
+        Err(GetCgroupError::NotFound(systemd_cgroup))
     }
 
     /// Joins together the given slices to make a target cgroup,
@@ -194,7 +515,7 @@ impl CgroupManager {
     /// then this function also tries to detect them.
     ///
     /// Only works if cgroup v1 is enabled,
-    /// and mounted in the filesystem at `LINUX_CGROUP_ROOT`;
+    /// and mounted in the filesystem at the cgroup root;
     /// otherwise returns `Err`.
     pub fn get_cgroup_v1<C, S>(
         &mut self,
@@ -227,6 +548,11 @@ impl CgroupManager {
         }
     }
 
+    /// Gets the user whose delegated hierarchy the manager resolves cgroups
+    /// in, if any
+    #[must_use]
+    pub const fn user(&self) -> Option<u32> { self.user }
+
     /// Gets the current resolved driver for the manager
     #[must_use]
     pub const fn driver(&self) -> Option<CgroupDriver> { self.driver }
@@ -234,6 +560,14 @@ impl CgroupManager {
     /// Gets the current resolved cgroup version for the manager
     #[must_use]
     pub const fn version(&self) -> Option<CgroupVersion> { self.version }
+
+    /// Gets the (previous, new) driver pair if the driver changed when it was
+    /// detected again after repeated resolution failures. Only returns each
+    /// change once.
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn take_driver_change(&mut self) -> Option<(CgroupDriver, CgroupDriver)> {
+        self.driver_change.take()
+    }
 }
 
 /// Converts a vec of slice names such as:
@@ -279,17 +613,56 @@ pub fn build_systemd_cgroup_hierarchy(slices: &[impl AsRef<str>]) -> Vec<String>
 #[must_use]
 fn escape_systemd(slice: &str) -> String { slice.replace("-", "_") }
 
-pub const INVALID_CGROUP_MOUNT_MESSAGE: &str =
-    "rAdvisor expects cgroups to be enabled and mounted in /sys/fs/cgroup.";
+pub const INVALID_CGROUP_MOUNT_MESSAGE: &str = "rAdvisor expects cgroups to be enabled and \
+                                                mounted in /sys/fs/cgroup (or at the path given \
+                                                by --cgroup-root).";
 
-/// Checks if cgroups are mounted in /sys/fs/cgroup
+/// Checks if cgroups are mounted at the cgroup root
 /// (for both cgroup v1 and v2)
 #[must_use]
-pub fn cgroups_mounted_properly() -> bool { Path::new(STANDARD_CGROUP_MOUNT_ROOT).exists() }
+pub fn cgroups_mounted_properly() -> bool { cgroup_root().exists() }
 
 // From https://man7.org/linux/man-pages/man7/cgroups.7.html
 pub const STANDARD_CGROUP_MOUNT_ROOT: &str = "/sys/fs/cgroup";
 
+lazy_static::lazy_static! {
+    /// Directory that the cgroup hierarchy is mounted at, if it was set to
+    /// something other than the standard mount point (leaked once set, so that
+    /// it can be borrowed for the rest of the program)
+    static ref CGROUP_ROOT: Mutex<Option<&'static Path>> = Mutex::new(None);
+}
+
+/// Sets the directory that the cgroup hierarchy is mounted at (such as
+/// `/host/sys/fs/cgroup` when rAdvisor runs in a container with the host's
+/// cgroupfs mounted into it), which all cgroup paths are relative to. Can only
+/// be set once, before any cgroups are resolved; returns false if it was
+/// already set to a different directory.
+pub fn set_cgroup_root<P: Into<PathBuf>>(root: P) -> bool {
+    let root = root.into();
+    let mut current = CGROUP_ROOT.lock().unwrap();
+    match *current {
+        Some(current) => current == root,
+        None => {
+            *current = Some(Box::leak(root.into_boxed_path()));
+            true
+        },
+    }
+}
+
+/// Gets the directory that the cgroup hierarchy is mounted at: the standard
+/// mount point (`/sys/fs/cgroup`) unless it was set with `set_cgroup_root`
+#[must_use]
+pub fn cgroup_root() -> &'static Path {
+    CGROUP_ROOT
+        .lock()
+        .unwrap()
+        .unwrap_or_else(|| Path::new(STANDARD_CGROUP_MOUNT_ROOT))
+}
+
+/// Slice (relative to the cgroup root) that systemd places the sessions of
+/// all users in
+pub const USER_SLICE: &str = "user.slice";
+
 // From https://man7.org/linux/man-pages/man7/cgroups.7.html
 pub const CGROUP_V1_SUBSYSTEMS: &[&str] = &[
     // Place the cpuacct subsystem first,
@@ -311,16 +684,14 @@ pub const CGROUP_V1_SUBSYSTEMS: &[&str] = &[
 ];
 
 /// Determines whether the given (absolute) cgroup
-/// exists in the virtual filesystem at the standard mount point.
+/// exists in the virtual filesystem at the cgroup root.
 #[must_use]
 fn cgroup_exists<C: AsRef<Path>>(path: Option<C>, version: CgroupVersion) -> bool {
     match version {
         CgroupVersion::V1 => {
             // See if any of the cgroup v1 subsystems are mounted
             for subsystem in CGROUP_V1_SUBSYSTEMS {
-                let mut full_path = PathBuf::new();
-                full_path.push(STANDARD_CGROUP_MOUNT_ROOT);
-                full_path.push(subsystem);
+                let mut full_path = cgroup_root().join(subsystem);
                 if let Some(p) = &path {
                     full_path.push(p.as_ref());
                 }
@@ -331,8 +702,7 @@ fn cgroup_exists<C: AsRef<Path>>(path: Option<C>, version: CgroupVersion) -> boo
             }
         },
         CgroupVersion::V2 => {
-            let mut full_path = PathBuf::new();
-            full_path.push(STANDARD_CGROUP_MOUNT_ROOT);
+            let mut full_path = cgroup_root().to_path_buf();
             if let Some(p) = path {
                 full_path.push(p.as_ref());
             }
@@ -343,3 +713,66 @@ fn cgroup_exists<C: AsRef<Path>>(path: Option<C>, version: CgroupVersion) -> boo
 
     false
 }
+
+/// Gets the directories that the hierarchies of the given cgroup version are
+/// mounted at: one for each subsystem for cgroup v1, and the cgroup root for
+/// cgroup v2. Some of them might not exist.
+#[must_use]
+pub fn cgroup_hierarchies(version: CgroupVersion) -> Vec<PathBuf> {
+    match version {
+        CgroupVersion::V1 => CGROUP_V1_SUBSYSTEMS
+            .iter()
+            .map(|subsystem| cgroup_root().join(subsystem))
+            .collect(),
+        CgroupVersion::V2 => vec![cgroup_root().to_path_buf()],
+    }
+}
+
+/// Finds all existing cgroups matching the given pattern, which is a path
+/// relative to the cgroup root where each component can be a glob (such as
+/// `system.slice/*.service`). For cgroup v1, the cgroups can exist in any
+/// subsystem.
+#[must_use]
+pub fn find_cgroups(pattern: &str, version: CgroupVersion) -> BTreeSet<PathBuf> {
+    let roots = cgroup_hierarchies(version);
+
+    let components = pattern
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>();
+    let mut found = BTreeSet::new();
+    for root in roots.iter().filter(|root| root.exists()) {
+        // Walk the hierarchy one component at a time,
+        // tracking all matching cgroups (relative to the root)
+        let mut current = vec![PathBuf::new()];
+        for component in &components {
+            let mut next = Vec::new();
+            for relative in &current {
+                if !component.contains(&['*', '?'][..]) {
+                    let candidate = relative.join(component);
+                    if root.join(&candidate).is_dir() {
+                        next.push(candidate);
+                    }
+                    continue;
+                }
+
+                if let Ok(entries) = fs::read_dir(root.join(relative)) {
+                    for entry in entries.flatten() {
+                        let is_dir =
+                            matches!(entry.file_type(), Ok(file_type) if file_type.is_dir());
+                        let name = entry.file_name();
+                        if let Some(name) = name.to_str() {
+                            if is_dir && glob_match(component, name) {
+                                next.push(relative.join(name));
+                            }
+                        }
+                    }
+                }
+            }
+            current = next;
+        }
+        found.extend(current);
+    }
+
+    found
+}
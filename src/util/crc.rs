@@ -0,0 +1,57 @@
+/// Polynomial of the CRC-32 used by zlib, gzip, and PNG (in reversed form)
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// Lookup table with the CRC of each byte value
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0_u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ POLYNOMIAL,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Running CRC-32 checksum of a stream of bytes, which is updated as more of
+/// the stream is seen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self { Self::new() }
+}
+
+impl Crc32 {
+    #[must_use]
+    pub const fn new() -> Self { Self { state: !0 } }
+
+    /// Continues a checksum with the given value
+    #[must_use]
+    pub const fn resume(value: u32) -> Self { Self { state: !value } }
+
+    /// Adds the given bytes to the checksum
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let index = (self.state ^ u32::from(byte)) & 0xFF;
+            self.state = (self.state >> 8) ^ TABLE[index as usize];
+        }
+    }
+
+    /// Gets the checksum of all bytes seen so far
+    #[must_use]
+    pub const fn value(&self) -> u32 { !self.state }
+}
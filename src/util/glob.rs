@@ -0,0 +1,34 @@
+//! Minimal shell-style glob matching, supporting `*` (any sequence of
+//! characters, including none) and `?` (any single character)
+
+/// Determines whether the entire text matches the given glob pattern
+#[must_use]
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen in the pattern, and the position in the
+    // text that it was matched against (used for backtracking)
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last star absorb one more character and retry
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    // Any remaining pattern characters must all be stars
+    pattern[p..].iter().all(|&c| c == b'*')
+}
@@ -0,0 +1,100 @@
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+
+pub const IN_MOVED_FROM: u32 = libc::IN_MOVED_FROM;
+pub const IN_MOVED_TO: u32 = libc::IN_MOVED_TO;
+pub const IN_CREATE: u32 = libc::IN_CREATE;
+pub const IN_DELETE: u32 = libc::IN_DELETE;
+pub const IN_ISDIR: u32 = libc::IN_ISDIR;
+pub const IN_ONLYDIR: u32 = libc::IN_ONLYDIR;
+/// Set when events were dropped since the event queue overflowed
+pub const IN_Q_OVERFLOW: u32 = libc::IN_Q_OVERFLOW;
+
+/// Size of the buffer that events are read into, which fits many events with
+/// names up to `NAME_MAX`
+const EVENT_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Inotify instance, whose events can be read while watches are added from
+/// other threads
+pub struct Inotify {
+    file: File,
+}
+
+/// Single inotify event on a watched directory
+#[derive(Clone, Debug, PartialEq)]
+pub struct InotifyEvent {
+    pub wd:   i32,
+    pub mask: u32,
+}
+
+impl Inotify {
+    /// Creates a new inotify instance, whose reads block until any events
+    /// are available
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        match fd {
+            -1 => Err(io::Error::last_os_error()),
+            fd => Ok(Self {
+                file: unsafe { File::from_raw_fd(fd) },
+            }),
+        }
+    }
+
+    /// Watches the given path for the events in the mask, returning the
+    /// watch descriptor. Watching a path that is already watched replaces
+    /// the mask of its existing watch.
+    pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<i32> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let wd = unsafe { libc::inotify_add_watch(self.file.as_raw_fd(), path.as_ptr(), mask) };
+        match wd {
+            -1 => Err(io::Error::last_os_error()),
+            wd => Ok(wd),
+        }
+    }
+
+    /// Blocks until any events are available, returning all of them. Names of
+    /// the affected directory entries are skipped, since only the kind of
+    /// each event is needed.
+    pub fn read_events(&self) -> io::Result<Vec<InotifyEvent>> {
+        let mut buffer = vec![0_u8; EVENT_BUFFER_SIZE];
+        let read = (&self.file).read(&mut buffer)?;
+
+        let header = mem::size_of::<libc::inotify_event>();
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset + header <= read {
+            let event = unsafe {
+                buffer
+                    .as_ptr()
+                    .add(offset)
+                    .cast::<libc::inotify_event>()
+                    .read_unaligned()
+            };
+            events.push(InotifyEvent {
+                wd:   event.wd,
+                mask: event.mask,
+            });
+            offset += header + usize::try_from(event.len).unwrap_or_default();
+        }
+
+        Ok(events)
+    }
+}
+
+impl InotifyEvent {
+    /// Whether the event is for a subdirectory that was created in, removed
+    /// from, or moved in or out of the watched directory, or reports that
+    /// events were lost (so any of those could have happened)
+    #[must_use]
+    pub const fn changed_subdirectories(&self) -> bool {
+        let changed = IN_CREATE | IN_DELETE | IN_MOVED_FROM | IN_MOVED_TO;
+        self.mask & IN_Q_OVERFLOW != 0 || (self.mask & IN_ISDIR != 0 && self.mask & changed != 0)
+    }
+}
@@ -0,0 +1,119 @@
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::AtomicU64;
+
+/// File that is mapped into memory in its entirety (using `mmap`), such that
+/// writes to the mapping end up in the file without any system calls. The
+/// mapping is removed when dropped.
+pub struct MappedFile {
+    /// Kept open for as long as the file is mapped
+    _file:  File,
+    ptr:    *mut u8,
+    length: usize,
+}
+
+// The mapping is exclusively owned, so it can be moved between threads like
+// any other buffer
+unsafe impl Send for MappedFile {}
+
+impl MappedFile {
+    /// Creates a new file of the given length (truncating any existing file)
+    /// and maps it into memory for writing
+    pub fn create(path: &Path, length: usize) -> IoResult<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(length as u64)?;
+        Self::map(file, length, libc::PROT_READ | libc::PROT_WRITE)
+    }
+
+    /// Maps an existing file into memory for reading
+    pub fn open(path: &Path) -> IoResult<Self> {
+        let file = File::open(path)?;
+        let length = usize::try_from(file.metadata()?.len())
+            .map_err(|_| IoError::new(ErrorKind::InvalidData, "file is too large to map"))?;
+        Self::map(file, length, libc::PROT_READ)
+    }
+
+    fn map(file: File, length: usize, protection: libc::c_int) -> IoResult<Self> {
+        // Zero-length mappings are invalid, so they're represented by a
+        // dangling pointer instead
+        if length == 0 {
+            return Ok(Self {
+                _file: file,
+                ptr: ptr::NonNull::dangling().as_ptr(),
+                length,
+            });
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                length,
+                protection,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(IoError::last_os_error());
+        }
+
+        Ok(Self {
+            _file: file,
+            ptr: ptr.cast(),
+            length,
+        })
+    }
+
+    /// Gets the contents of the mapped file
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn as_slice(&self) -> &[u8] { unsafe { slice::from_raw_parts(self.ptr, self.length) } }
+
+    /// Gets the contents of the mapped file for writing. Only valid for files
+    /// mapped using `create`.
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.length) }
+    }
+
+    /// Gets the 8 bytes at the given offset as an atomic integer, through
+    /// which updates can be published to other mappings of the same file.
+    /// Returns None if they are out of bounds or unaligned.
+    #[must_use]
+    // The alignment is checked before casting
+    #[allow(clippy::cast_ptr_alignment)]
+    pub fn atomic_u64(&self, offset: usize) -> Option<&AtomicU64> {
+        let end = offset.checked_add(mem::size_of::<AtomicU64>())?;
+        if end > self.length {
+            return None;
+        }
+
+        let ptr = unsafe { self.ptr.add(offset) };
+        match ptr.align_offset(mem::align_of::<AtomicU64>()) {
+            0 => Some(unsafe { &*ptr.cast::<AtomicU64>() }),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.length > 0 {
+            unsafe {
+                libc::munmap(self.ptr.cast(), self.length);
+            }
+        }
+    }
+}
@@ -6,18 +6,40 @@
 //! Contains utility methods for processing various data structures, such as
 //! bytes, buffers, or system-specific calls
 
+mod affinity;
+mod alloc;
 pub(self) mod buffer;
 pub(self) mod byte;
 pub(self) mod cgroup;
+mod crc;
+pub(self) mod glob;
+mod inotify;
 pub(self) mod lazy_quantity;
+mod mmap;
+mod mountinfo;
+mod parallel;
+mod perf_event;
 pub(self) mod pool;
+mod rlimit;
+mod signal;
 pub(self) mod system;
 
+pub use affinity::*;
+pub use alloc::*;
 pub use buffer::*;
 pub use byte::*;
 pub use cgroup::*;
+pub use crc::*;
+pub use glob::*;
+pub use inotify::*;
 pub use lazy_quantity::*;
+pub use mmap::*;
+pub use mountinfo::*;
+pub use parallel::*;
+pub use perf_event::*;
 pub use pool::*;
+pub use rlimit::*;
+pub use signal::*;
 pub use system::*;
 
 /// Represents an anonymous slice, lacking any memory ownership semantics
@@ -0,0 +1,203 @@
+use crate::util::{CgroupVersion, CGROUP_V1_SUBSYSTEMS, STANDARD_CGROUP_MOUNT_ROOT};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+const PROC_CGROUP_PATH: &str = "/proc/self/cgroup";
+const CGROUP_NAMESPACE_PATH: &str = "/proc/self/ns/cgroup";
+
+/// Link target of the namespace file of the initial cgroup namespace, whose
+/// inode number is fixed by the kernel (`PROC_CGROUP_INIT_INO`)
+const INITIAL_CGROUP_NAMESPACE: &str = "cgroup:[4026531835]";
+
+/// Mounted cgroup hierarchy, from `/proc/self/mountinfo`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CgroupMount {
+    /// Cgroup that the mount exposes, relative to the root of the cgroup
+    /// namespace that rAdvisor runs in. Cgroups above the namespace root (such
+    /// as the host's root cgroup, when the host's cgroupfs is mounted into a
+    /// container) start with `/..`.
+    pub root:        String,
+    /// Directory that the hierarchy is mounted at
+    pub mount_point: PathBuf,
+    pub version:     CgroupVersion,
+}
+
+impl CgroupMount {
+    /// Gets the number of levels that the exposed cgroup is above the root of
+    /// the cgroup namespace, or None if it is a cgroup below the namespace
+    /// root (in which case the mount only exposes part of the hierarchy)
+    #[must_use]
+    pub fn height(&self) -> Option<usize> {
+        let components = self
+            .root
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect::<Vec<_>>();
+        match components.iter().all(|component| *component == "..") {
+            true => Some(components.len()),
+            false => None,
+        }
+    }
+
+    /// Gets the directory that cgroup paths are relative to: the mount point
+    /// itself for cgroup v2, and the directory containing the subsystem mount
+    /// points for cgroup v1
+    #[must_use]
+    pub fn hierarchy_root(&self) -> Option<&Path> {
+        match self.version {
+            CgroupVersion::V1 => self.mount_point.parent(),
+            CgroupVersion::V2 => Some(&self.mount_point),
+        }
+    }
+}
+
+/// Cgroup root detected from the cgroup hierarchies mounted in rAdvisor's
+/// mount namespace
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectedCgroupRoot {
+    pub path:       PathBuf,
+    pub version:    CgroupVersion,
+    /// Whether rAdvisor runs in a cgroup namespace other than the initial one
+    /// (such as in a container)
+    pub namespaced: bool,
+    /// Whether the mount exposes the whole hierarchy (as far as can be told),
+    /// instead of only the cgroup namespace that rAdvisor runs in
+    pub complete:   bool,
+}
+
+/// Gets the cgroup hierarchies mounted in rAdvisor's mount namespace. For
+/// cgroup v1, only the mounts of known subsystems are included.
+#[must_use]
+pub fn cgroup_mounts() -> Vec<CgroupMount> {
+    match fs::read_to_string(MOUNTINFO_PATH) {
+        Ok(mountinfo) => mountinfo.lines().filter_map(parse_mount).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Detects the directory that the cgroup hierarchy is mounted at. Prefers the
+/// mount that exposes the highest cgroup relative to rAdvisor's cgroup
+/// namespace (such as the host's cgroupfs mounted into a container at
+/// `/host/sys/fs/cgroup`), then the standard mount point. Returns None if no
+/// cgroup hierarchies are mounted.
+#[must_use]
+pub fn detect_cgroup_root() -> Option<DetectedCgroupRoot> {
+    let mounts = cgroup_mounts();
+    // With both versions mounted (a hybrid hierarchy), the cgroup v1 mounts
+    // hold the controllers
+    let version = match mounts.iter().any(|m| m.version == CgroupVersion::V1) {
+        true => CgroupVersion::V1,
+        false => CgroupVersion::V2,
+    };
+    let best = mounts
+        .iter()
+        .filter(|mount| mount.version == version)
+        .filter_map(|mount| Some((mount.height()?, mount.hierarchy_root()?)))
+        .max_by_key(|(height, root)| (*height, *root == Path::new(STANDARD_CGROUP_MOUNT_ROOT)));
+
+    let namespaced = in_cgroup_namespace();
+    best.map(|(height, root)| DetectedCgroupRoot {
+        path: root.to_path_buf(),
+        version,
+        namespaced,
+        complete: height > 0 || !namespaced,
+    })
+}
+
+/// Determines whether rAdvisor runs in a cgroup namespace other than the
+/// initial one. If its namespace can't be read, it is assumed to run in one if
+/// its own cgroup is the root of its namespace (since processes are rarely
+/// placed in the root cgroup otherwise).
+fn in_cgroup_namespace() -> bool {
+    match fs::read_link(CGROUP_NAMESPACE_PATH) {
+        Ok(namespace) => namespace != Path::new(INITIAL_CGROUP_NAMESPACE),
+        Err(_) => own_cgroup().as_deref() == Some("/"),
+    }
+}
+
+/// Gets the cgroup that rAdvisor runs in, relative to the root of its cgroup
+/// namespace, from `/proc/self/cgroup`. Uses the cgroup v2 entry if there is
+/// one, and otherwise the entry of the first cgroup v1 subsystem.
+fn own_cgroup() -> Option<String> {
+    let cgroups = fs::read_to_string(PROC_CGROUP_PATH).ok()?;
+    let entries = cgroups
+        .lines()
+        .filter_map(|line| {
+            // Entries look like `0::/system.slice/radvisor.service` for cgroup
+            // v2, and `4:cpu,cpuacct:/docker/<id>` for cgroup v1
+            let mut parts = line.splitn(3, ':');
+            let id = parts.next()?;
+            let controllers = parts.next()?;
+            Some((id, controllers, parts.next()?))
+        })
+        .collect::<Vec<_>>();
+    entries
+        .iter()
+        .find(|(id, controllers, _)| *id == "0" && controllers.is_empty())
+        .or_else(|| {
+            entries
+                .iter()
+                .find(|(_, controllers, _)| !controllers.is_empty())
+        })
+        .map(|(_, _, path)| (*path).to_owned())
+}
+
+/// Parses a line of `/proc/self/mountinfo`, returning the mount if it is a
+/// cgroup hierarchy. Lines look like:
+/// `30 24 0:26 / /sys/fs/cgroup rw,nosuid - cgroup2 cgroup2 rw`, where the 4th
+/// field is the root of the mount and the 5th is the mount point. The
+/// filesystem type follows the `-` separator, after a variable number of
+/// optional fields.
+fn parse_mount(line: &str) -> Option<CgroupMount> {
+    let (fields, filesystem) = line.split_once(" - ")?;
+    let mut fields = fields.split(' ');
+    let root = unescape(fields.nth(3)?);
+    let mount_point = PathBuf::from(unescape(fields.next()?));
+    let mut filesystem = filesystem.split(' ');
+    let version = match filesystem.next()? {
+        "cgroup2" => CgroupVersion::V2,
+        "cgroup" => {
+            // Skip named hierarchies without controllers (such as
+            // `name=systemd`)
+            let options = filesystem.nth(1)?;
+            let known = options
+                .split(',')
+                .any(|option| CGROUP_V1_SUBSYSTEMS.contains(&option));
+            if !known {
+                return None;
+            }
+            CgroupVersion::V1
+        },
+        _ => return None,
+    };
+
+    Some(CgroupMount {
+        root,
+        mount_point,
+        version,
+    })
+}
+
+/// Replaces the octal escapes in a mountinfo field (such as `\040` for a
+/// space) with the characters they represent
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+        let escape = rest.get(index + 1..index + 4);
+        match escape.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                unescaped.push(char::from(byte));
+                rest = &rest[index + 4..];
+            },
+            None => {
+                unescaped.push('\\');
+                rest = &rest[index + 1..];
+            },
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
@@ -0,0 +1,46 @@
+use crate::util;
+use crossbeam_utils::thread;
+use std::panic;
+
+/// Maximum number of threads that work is split across
+const MAX_THREADS: usize = 8;
+
+/// Minimum number of items given to each thread, below which spawning more
+/// threads costs more than it saves
+const MIN_ITEMS_PER_THREAD: usize = 32;
+
+/// Applies the function to each item, splitting the items into contiguous
+/// chunks that are processed on a small number of scoped threads (bounded by
+/// the available parallelism). The results are in the same order as the
+/// items. Small inputs are processed on the current thread.
+pub fn parallel_map<T, U, F>(items: &[T], f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    let threads = util::remap::<_, usize>(util::num_available_cores())
+        .min(MAX_THREADS)
+        .min(items.len() / MIN_ITEMS_PER_THREAD)
+        .max(1);
+    if threads == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = (items.len() + threads - 1) / threads;
+    let f = &f;
+    thread::scope(|scope| {
+        let handles = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move |_| chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| match handle.join() {
+                Ok(results) => results,
+                Err(payload) => panic::resume_unwind(payload),
+            })
+            .collect()
+    })
+    .unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
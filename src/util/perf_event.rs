@@ -0,0 +1,105 @@
+use crate::util::CpuList;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::ptr;
+
+pub const PERF_TYPE_HARDWARE: u32 = 0;
+pub const PERF_TYPE_SOFTWARE: u32 = 1;
+pub const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+pub const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+pub const PERF_FORMAT_GROUP: u64 = 1 << 3;
+/// `exclude_hv` bit of the flags in `perf_event_attr`
+pub const PERF_ATTR_EXCLUDE_HV: u64 = 1 << 6;
+pub const PERF_FLAG_PID_CGROUP: libc::c_ulong = 1 << 2;
+pub const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+/// List of the online CPUs, in the kernel's list format
+const ONLINE_CPUS_PATH: &str = "/sys/devices/system/cpu/online";
+/// List of the CPUs that can ever be online, in the kernel's list format
+const POSSIBLE_CPUS_PATH: &str = "/sys/devices/system/cpu/possible";
+
+/// `struct perf_event_attr` from `linux/perf_event.h`, up to
+/// `PERF_ATTR_SIZE_VER5`. The bitfield of flags is represented as a single
+/// integer.
+#[repr(C)]
+#[derive(Default)]
+pub struct PerfEventAttr {
+    pub r#type:             u32,
+    pub size:               u32,
+    pub config:             u64,
+    pub sample_period:      u64,
+    pub sample_type:        u64,
+    pub read_format:        u64,
+    pub flags:              u64,
+    pub wakeup_events:      u32,
+    pub bp_type:            u32,
+    pub config1:            u64,
+    pub config2:            u64,
+    pub branch_sample_type: u64,
+    pub sample_regs_user:   u64,
+    pub sample_stack_user:  u32,
+    pub clockid:            i32,
+    pub sample_regs_intr:   u64,
+    pub aux_watermark:      u32,
+    pub sample_max_stack:   u16,
+    pub reserved:           u16,
+}
+
+impl PerfEventAttr {
+    /// Creates the attributes of an event with the given type and config,
+    /// with the size of the struct filled in
+    #[must_use]
+    pub fn new(r#type: u32, config: u64) -> Self {
+        Self {
+            r#type,
+            size: u32::try_from(mem::size_of::<Self>()).unwrap_or_default(),
+            config,
+            ..Self::default()
+        }
+    }
+}
+
+/// Opens a counter for an event with `perf_event_open`, as part of the
+/// leader's group if one is given. With `PERF_FLAG_PID_CGROUP`, `pid` is a
+/// file descriptor of the cgroup's directory instead.
+pub fn perf_event_open(
+    attr: &PerfEventAttr,
+    pid: RawFd,
+    cpu: i32,
+    leader: Option<&File>,
+    flags: libc::c_ulong,
+) -> io::Result<File> {
+    let group_fd = leader.map_or(-1, AsRawFd::as_raw_fd);
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            ptr::addr_of!(*attr),
+            pid,
+            cpu,
+            group_fd,
+            flags,
+        )
+    };
+    match RawFd::try_from(fd) {
+        Ok(fd) if fd >= 0 => Ok(unsafe { File::from_raw_fd(fd) }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Gets the CPUs that are currently online
+pub fn online_cpus() -> io::Result<CpuList> {
+    let online = fs::read_to_string(ONLINE_CPUS_PATH)?;
+    CpuList::parse(&online)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid online CPU list"))
+}
+
+/// Gets the CPUs that can ever be online, including those that could be
+/// hotplugged later
+pub fn possible_cpus() -> io::Result<CpuList> {
+    let possible = fs::read_to_string(POSSIBLE_CPUS_PATH)?;
+    CpuList::parse(&possible)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid possible CPU list"))
+}
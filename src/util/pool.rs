@@ -23,6 +23,14 @@ impl<T: Ord + Clone> ItemPool<T> {
         }
     }
 
+    /// Whether the item is in the pool
+    #[must_use]
+    pub fn contains(&self, item: &T) -> bool { self.items.contains(item) }
+
+    /// Removes the item from the pool, so that the next update adds it again
+    /// if it is still present
+    pub fn remove(&mut self, item: &T) { self.items.remove(item); }
+
     /// Updates the internal pool map, returning two vectors of items `(added,
     /// removed)` that represent all new items that were added (items that
     /// appear in the given iterator and not in the previous internal pool)
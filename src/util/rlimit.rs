@@ -0,0 +1,62 @@
+use std::fs;
+use std::io;
+use std::ptr;
+
+/// Maximum number of file descriptors that a process can open, which caps
+/// the open file limit if its hard limit is unlimited
+const NR_OPEN_PATH: &str = "/proc/sys/fs/nr_open";
+
+/// Default value of `fs.nr_open`, used if it couldn't be read
+const DEFAULT_NR_OPEN: u64 = 1024 * 1024;
+
+/// Soft and hard limits on the number of open file descriptors
+/// (`RLIMIT_NOFILE`)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FdLimit {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// Gets the current limits on the number of open file descriptors
+pub fn fd_limit() -> io::Result<FdLimit> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    match unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, ptr::addr_of_mut!(limit)) } {
+        0 => Ok(FdLimit {
+            soft: limit.rlim_cur,
+            hard: limit.rlim_max,
+        }),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Raises the soft limit on the number of open file descriptors to the hard
+/// limit (or to `fs.nr_open` if the hard limit is unlimited), returning the
+/// limits from before and after
+pub fn raise_fd_limit() -> io::Result<(FdLimit, FdLimit)> {
+    let previous = fd_limit()?;
+    let target = match previous.hard {
+        libc::RLIM_INFINITY => fs::read_to_string(NR_OPEN_PATH)
+            .ok()
+            .and_then(|nr_open| nr_open.trim().parse().ok())
+            .unwrap_or(DEFAULT_NR_OPEN),
+        hard => hard,
+    };
+    if previous.soft >= target {
+        return Ok((previous, previous));
+    }
+
+    let limit = libc::rlimit {
+        rlim_cur: target,
+        rlim_max: previous.hard,
+    };
+    match unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, ptr::addr_of!(limit)) } {
+        0 => Ok((previous, FdLimit {
+            soft: target,
+            ..previous
+        })),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::{Error as IoError, Read, Result as IoResult};
+use std::os::unix::io::FromRawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+/// Write end of the pipe that the SIGUSR1 handler writes to, or -1 if the
+/// handler isn't installed
+static USR1_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// Signal handler for SIGUSR1, which only wakes up the listening thread since
+/// almost nothing else is safe to do inside a signal handler
+extern "C" fn handle_usr1(_: libc::c_int) {
+    let fd = USR1_PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        let byte = 1_u8;
+        unsafe {
+            libc::write(fd, ptr::addr_of!(byte).cast(), 1);
+        }
+    }
+}
+
+/// Installs a SIGUSR1 handler that calls the callback on a background thread
+/// each time the process receives the signal. Since signal handlers are
+/// process-wide, this can only be done once per process.
+pub fn on_usr1<F>(callback: F) -> IoResult<()>
+where
+    F: Fn() + Send + 'static,
+{
+    let mut fds = [0 as libc::c_int; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    let mut read_end = unsafe { File::from_raw_fd(fds[0]) };
+    USR1_PIPE.store(fds[1], Ordering::Relaxed);
+    let handler = handle_usr1 as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGUSR1, handler) } == libc::SIG_ERR {
+        return Err(IoError::last_os_error());
+    }
+
+    thread::Builder::new()
+        .name(String::from("usr1"))
+        .spawn(move || {
+            let mut buf = [0_u8; 16];
+            while let Ok(read) = read_end.read(&mut buf) {
+                if read == 0 {
+                    break;
+                }
+                callback();
+            }
+        })?;
+    Ok(())
+}
@@ -1,6 +1,7 @@
 //! Function interfaces that sit in front of system-specific implementations
 
 use std::convert::TryFrom;
+use std::time::Duration;
 
 /// Gets the nanosecond unix timestamp for a stat read
 #[must_use]
@@ -10,6 +11,59 @@ pub fn nano_ts() -> u128 { time::nano_ts() }
 #[must_use]
 pub fn second_ts() -> u64 { time::second_ts() }
 
+/// Gets the nanoseconds since boot, including any time the system spent
+/// suspended
+#[must_use]
+pub fn boottime_ts() -> u128 { time::boottime_ts() }
+
+/// Gets the nanoseconds since boot from `CLOCK_MONOTONIC_RAW`, which is
+/// neither stepped nor slewed by NTP (and doesn't advance while the system is
+/// suspended)
+#[must_use]
+pub fn monotonic_raw_ts() -> u128 { time::monotonic_raw_ts() }
+
+/// Detects system suspends by comparing `CLOCK_BOOTTIME` (which advances while
+/// the system is suspended) against `CLOCK_MONOTONIC` (which doesn't)
+pub struct SuspendDetector {
+    /// Difference between the two clocks as of the last check
+    offset: u128,
+}
+
+impl SuspendDetector {
+    /// Minimum growth of the clock difference that is considered a suspend,
+    /// to ignore the jitter between reading the two clocks
+    const THRESHOLD: Duration = Duration::from_millis(100);
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            offset: time::suspended_ts(),
+        }
+    }
+
+    /// Returns how long the system was suspended since the last check, if it
+    /// was suspended at all
+    pub fn check(&mut self) -> Option<Duration> {
+        let offset = time::suspended_ts();
+        let gap = offset.saturating_sub(self.offset);
+        self.offset = offset;
+
+        let gap = Duration::from_nanos(remap::<_, u64>(gap));
+        match gap >= Self::THRESHOLD {
+            true => Some(gap),
+            false => None,
+        }
+    }
+}
+
+impl Default for SuspendDetector {
+    fn default() -> Self { Self::new() }
+}
+
+/// Gets the total CPU time consumed by all threads of the current process
+#[must_use]
+pub fn process_cpu_time() -> Duration { time::process_cpu_time() }
+
 /// Gets the total number of cores on the system. On Linux, this includes
 /// disabled ones
 ///
@@ -26,6 +80,11 @@ pub fn num_cores() -> u64 { cpu::num_cores() }
 #[must_use]
 pub fn num_available_cores() -> u64 { cpu::num_available_cores() }
 
+/// Gets the number of clock ticks per second (`USER_HZ`) that the times in
+/// `/proc/stat` are counted in
+#[must_use]
+pub fn clock_ticks_per_second() -> u64 { cpu::clock_ticks_per_second() }
+
 /// Attempts to get the width of the given terminal type (in characters),
 /// returning None if no applicable width can be found
 #[must_use]
@@ -43,30 +102,52 @@ pub fn remap<S, D: TryFrom<S> + From<bool>>(num: S) -> D {
 #[cfg(target_os = "linux")]
 mod time {
     use super::remap;
-    use libc::{clock_gettime, timespec, CLOCK_REALTIME};
+    use libc::{clock_gettime, clockid_t, timespec, CLOCK_BOOTTIME, CLOCK_MONOTONIC,
+               CLOCK_MONOTONIC_RAW, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME};
     use std::mem;
+    use std::time::Duration;
 
     /// Invokes `clock_gettime` from time.h in libc to get a `timespec` struct
-    fn get_time() -> timespec {
+    fn get_time() -> timespec { get_clock(CLOCK_REALTIME) }
+
+    /// Invokes `clock_gettime` for the given clock
+    fn get_clock(clock: clockid_t) -> timespec {
         let mut tp: timespec = unsafe { mem::zeroed() };
         unsafe {
-            clock_gettime(CLOCK_REALTIME, &mut tp);
+            clock_gettime(clock, &mut tp);
         }
         tp
     }
 
-    pub fn nano_ts() -> u128 {
-        let tp = get_time();
+    pub fn nano_ts() -> u128 { to_nanos(&get_time()) }
+
+    pub fn boottime_ts() -> u128 { to_nanos(&get_clock(CLOCK_BOOTTIME)) }
+
+    pub fn monotonic_raw_ts() -> u128 { to_nanos(&get_clock(CLOCK_MONOTONIC_RAW)) }
+
+    /// Gets the total nanoseconds that the system has spent suspended since
+    /// boot, as the difference between the boot time and monotonic clocks
+    pub fn suspended_ts() -> u128 {
+        let monotonic = to_nanos(&get_clock(CLOCK_MONOTONIC));
+        boottime_ts().saturating_sub(monotonic)
+    }
+
+    fn to_nanos(tp: &timespec) -> u128 {
         remap::<_, u128>(tp.tv_nsec) + (remap::<_, u128>(tp.tv_sec) * 1_000_000_000)
     }
 
     pub fn second_ts() -> u64 { remap::<_, u64>(get_time().tv_sec) }
+
+    pub fn process_cpu_time() -> Duration {
+        let tp = get_clock(CLOCK_PROCESS_CPUTIME_ID);
+        Duration::new(remap::<_, u64>(tp.tv_sec), remap::<_, u32>(tp.tv_nsec))
+    }
 }
 
 #[cfg(target_os = "linux")]
 mod cpu {
     use super::remap;
-    use libc::{c_long, sysconf, _SC_NPROCESSORS_CONF, _SC_NPROCESSORS_ONLN};
+    use libc::{c_long, sysconf, _SC_CLK_TCK, _SC_NPROCESSORS_CONF, _SC_NPROCESSORS_ONLN};
 
     pub fn num_cores() -> u64 {
         let count: c_long = unsafe { sysconf(_SC_NPROCESSORS_CONF) };
@@ -77,6 +158,11 @@ mod cpu {
         let count: c_long = unsafe { sysconf(_SC_NPROCESSORS_ONLN) };
         remap::<_, u64>(count)
     }
+
+    pub fn clock_ticks_per_second() -> u64 {
+        let ticks: c_long = unsafe { sysconf(_SC_CLK_TCK) };
+        remap::<_, u64>(ticks)
+    }
 }
 
 #[cfg(target_os = "linux")]